@@ -1,6 +1,10 @@
 use bytes::Bytes;
 use clap::{Parser, ValueHint};
-use shiva::core::{Document, DocumentType, ImageData, ImageDimension};
+use shiva::core::{
+    Document, DocumentType, GeneratorOptions, ImageData, ImageDimension, ImageEmbedding,
+};
+use shiva::png::PngOptions;
+use shiva::registry::FormatRegistry;
 use std::path::Path;
 
 #[derive(Parser, Debug)]
@@ -16,7 +20,7 @@ struct Args {
         value_name = "INPUT_FILE",
         help = &format!(
             "Input file (possible formats: {})",
-            DocumentType::supported_extensions().join(", ")
+            FormatRegistry::global().supported_extensions().join(", ")
         ),
         value_hint = ValueHint::FilePath,
         required_unless_present = "image_to_base64"
@@ -27,7 +31,7 @@ struct Args {
         value_name = "OUTPUT_FILE",
         help = &format!(
             "Output file (possible formats: {})",
-            DocumentType::supported_extensions().join(", ")
+            FormatRegistry::global().supported_extensions().join(", ")
         ),
         value_hint = ValueHint::FilePath,
         required_unless_present = "image_to_base64"
@@ -55,6 +59,20 @@ struct Args {
         required_if_eq("image_to_base64", "true")
     )]
     base64_output: Option<String>,
+
+    #[arg(
+        long = "to",
+        value_name = "FORMAT",
+        help = "Output format, overrides the output file extension (e.g. `--to ansi` for /dev/stdout)"
+    )]
+    to: Option<String>,
+
+    #[arg(
+        long = "dpi",
+        value_name = "DPI",
+        help = "Resolution of PNG output (default 144)"
+    )]
+    dpi: Option<f32>,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -79,7 +97,8 @@ fn main() -> anyhow::Result<()> {
     let input_path = Path::new(input_file);
     let output_path = Path::new(output_file);
 
-    let supported_formats = DocumentType::supported_extensions();
+    // Formats registered by the crates shiva is built with are supported as the built-in ones
+    let supported_formats = FormatRegistry::global().supported_extensions();
 
     let input_format = match input_path.extension() {
         Some(ext) => ext.to_str().ok_or_else(|| {
@@ -96,56 +115,70 @@ fn main() -> anyhow::Result<()> {
         }
     };
 
-    let output_format = match output_path.extension() {
-        Some(ext) => ext.to_str().ok_or_else(|| {
-            anyhow::anyhow!(
+    let output_format = match args.to.as_deref().or_else(|| output_path.extension()?.to_str()) {
+        Some(format) => format,
+        None if output_path.extension().is_some() => {
+            return Err(anyhow::anyhow!(
                 "Invalid output file extension. Supported formats are: {}",
                 supported_formats.join(", ")
-            )
-        })?,
+            ))
+        }
         None => {
             return Err(anyhow::anyhow!(
-                "The output file has no extension. Supported formats are: {}",
+                "The output file has no extension, use --to to choose the format. Supported formats are: {}",
                 supported_formats.join(", ")
             ))
         }
     };
 
-    let input_doc_type = DocumentType::from_extension(input_format).ok_or_else(|| {
-        anyhow::anyhow!(
+    let is_supported = |format: &str| supported_formats.contains(&format.to_ascii_lowercase());
+    if !is_supported(input_format) {
+        return Err(anyhow::anyhow!(
             "Unsupported input file format '{}'. Supported formats are: {}",
             input_format,
             supported_formats.join(", ")
-        )
-    })?;
-
-    let output_doc_type = DocumentType::from_extension(output_format).ok_or_else(|| {
-        anyhow::anyhow!(
+        ));
+    }
+    if !is_supported(output_format) {
+        return Err(anyhow::anyhow!(
             "Unsupported output file format '{}'. Supported formats are: {}",
             output_format,
             supported_formats.join(", ")
-        )
-    })?;
+        ));
+    }
 
     let input_vec = std::fs::read(input_file)?;
     let input_bytes = Bytes::from(input_vec);
 
-    let document = Document::parse(&input_bytes, input_doc_type)?;
-    let output = if args.base64_images && output_format == "md" {
-        // Use custom image saver to trigger Base64 embedding
-        document.generate_with_saver(output_doc_type, |_, marker| {
-            if marker == "__base64__" {
-                Ok(()) // Signal to embed as Base64
-            } else {
-                // Default: save image
-                std::fs::write(marker, &[])?;
-                Ok(())
-            }
-        })?
-    } else {
-        document.generate(output_doc_type)?
+    let document = Document::parse_by_extension(&input_bytes, input_format)?;
+    let options = GeneratorOptions {
+        images: if args.base64_images {
+            ImageEmbedding::Embed
+        } else {
+            ImageEmbedding::Default
+        },
+        ..GeneratorOptions::default()
+    };
+    let output_doc_type = DocumentType::from_extension(&output_format.to_ascii_lowercase());
+    // A format registered for the extension writes it in place of the built-in PNG output
+    let registered = FormatRegistry::global().by_extension(output_format);
+    let output = match (args.dpi, output_doc_type) {
+        (Some(_), Some(DocumentType::PNG)) if registered.is_some() => {
+            return Err(anyhow::anyhow!(
+                "--dpi only applies to the built-in PNG output, '{}' files are written by a registered format",
+                output_format
+            ))
+        }
+        (Some(dpi), Some(DocumentType::PNG)) => {
+            shiva::png::Transformer::generate_with_options(&document, &PngOptions::with_dpi(dpi))?
+        }
+        _ => document.generate_by_extension(output_format, &options)?,
     };
     std::fs::write(output_file, output)?;
+    // Keep the output clean when it goes to the terminal
+    if output_path == Path::new("/dev/stdout") {
+        return Ok(());
+    }
     if args.base64_images && output_format == "md" {
         println!("Document converted with Base64 images embedded in markdown.");
     } else {
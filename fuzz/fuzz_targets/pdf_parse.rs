@@ -0,0 +1,9 @@
+#![no_main]
+
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+use shiva::core::TransformerTrait;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = shiva::pdf::Transformer::parse(&Bytes::copy_from_slice(data));
+});
@@ -0,0 +1,50 @@
+//! Text generation of large tables.
+//!
+//! Compare against another revision with criterion baselines:
+//! `cargo bench --bench text_table -- --save-baseline before` on the old revision, then
+//! `cargo bench --bench text_table -- --baseline before` on the new one.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use shiva::core::{
+    Document, Element, FontSpec, TableCell, TableHeader, TableRow, TransformerTrait,
+};
+
+fn table_document(row_count: usize) -> Document {
+    let text = |text: String| Element::Text {
+        text,
+        font: FontSpec::from(8),
+    };
+    let headers = ["Id", "Name", "Description", "Price"]
+        .iter()
+        .map(|header| TableHeader {
+            element: text(header.to_string()),
+            width: 30.0,
+        })
+        .collect();
+    let rows = (0..row_count)
+        .map(|i| TableRow {
+            cells: vec![
+                TableCell::new(text(i.to_string())),
+                TableCell::new(text(format!("Item {}", i))),
+                TableCell::new(text("x".repeat(i % 40))),
+                TableCell::new(text(format!("{}.{:02}", i % 1000, i % 100))),
+            ],
+        })
+        .collect();
+    Document::new(vec![Element::Table {
+        headers,
+        rows,
+        alignments: vec![],
+        caption: vec![],
+    }])
+}
+
+fn bench_text_table(c: &mut Criterion) {
+    let document = table_document(10_000);
+    c.bench_function("text generate 10k row table", |b| {
+        b.iter(|| shiva::text::Transformer::generate(black_box(&document)).unwrap())
+    });
+}
+
+criterion_group!(benches, bench_text_table);
+criterion_main!(benches);
@@ -0,0 +1,627 @@
+use crate::core::*;
+use bytes::Bytes;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(_document: &Bytes) -> anyhow::Result<Document> {
+        Err(anyhow::anyhow!("ANSI text parsing is not supported"))
+    }
+
+    /// Produces text for a terminal: headers are bold, the top levels also underlined,
+    /// hyperlinks are colored and clickable through OSC 8, paragraphs and lists are wrapped
+    /// to 80 columns and tables are drawn with box-drawing characters.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        // A table of contents is written as the list of its headers
+        let document = &document.expand_toc();
+        let mut blocks = Vec::new();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                let block = block(element);
+                if !block.is_empty() {
+                    blocks.push(block);
+                }
+            }
+        }
+        let mut text = blocks.join("\n\n");
+        text.push('\n');
+        Ok(Bytes::from(text))
+    }
+}
+
+const WIDTH: usize = 80;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BOLD_UNDERLINE: &str = "\x1b[1;4m";
+const DIM: &str = "\x1b[2m";
+const ITALIC: &str = "\x1b[3m";
+const UNDERLINE: &str = "\x1b[4m";
+const STRIKETHROUGH: &str = "\x1b[9m";
+const LINK: &str = "\x1b[34;4m";
+
+/// Text with one style, links also carry their target
+struct Span {
+    text: String,
+    style: &'static str,
+    url: Option<String>,
+}
+
+/// A run of characters without whitespace, it may mix styles
+#[derive(Default)]
+struct Word {
+    pieces: Vec<(String, &'static str, Option<String>)>,
+    width: usize,
+}
+
+impl Word {
+    fn render(&self) -> String {
+        let mut text = String::new();
+        for (piece, style, url) in &self.pieces {
+            if let Some(url) = url {
+                text.push_str(&format!("\x1b]8;;{}\x1b\\", url));
+            }
+            if style.is_empty() {
+                text.push_str(piece);
+            } else {
+                text.push_str(&format!("{}{}{}", style, piece, RESET));
+            }
+            if url.is_some() {
+                text.push_str("\x1b]8;;\x1b\\");
+            }
+        }
+        text
+    }
+}
+
+enum Token {
+    Word(Word),
+    Break,
+}
+
+/// Control characters in the content would be taken for escape sequences
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control() || *c == '\n')
+        .collect()
+}
+
+fn spans(element: &Element, style: &'static str, spans: &mut Vec<Span>) {
+    match element {
+        Element::Text { text, .. } | Element::Header { text, .. } => spans.push(Span {
+            text: sanitize(text),
+            style,
+            url: None,
+        }),
+        Element::Paragraph { elements }
+        | Element::Blockquote { elements, .. }
+        | Element::Section {
+            children: elements, ..
+        } => {
+            for child in elements {
+                self::spans(child, style, spans);
+            }
+        }
+        // A span carries a single escape sequence, the strongest style wins
+        Element::Styled {
+            style: text_style,
+            elements,
+        } => {
+            let style = match text_style {
+                TextStyle { bold: true, .. } => BOLD,
+                TextStyle { italic: true, .. } => ITALIC,
+                TextStyle {
+                    underline: true, ..
+                } => UNDERLINE,
+                TextStyle {
+                    strikethrough: true,
+                    ..
+                } => STRIKETHROUGH,
+                _ => style,
+            };
+            for child in elements {
+                self::spans(child, style, spans);
+            }
+        }
+        Element::Hyperlink { title, url, .. } => {
+            let title = if title.is_empty() { url } else { title };
+            spans.push(Span {
+                text: sanitize(title),
+                style: LINK,
+                url: Some(sanitize(url).replace('\n', "")),
+            });
+        }
+        Element::Image(image) => spans.push(image_span(image)),
+        // The caption follows its image
+        Element::Figure { image, caption } => {
+            spans.push(image_span(image));
+            for child in caption {
+                spans.push(Span {
+                    text: " ".to_string(),
+                    style,
+                    url: None,
+                });
+                self::spans(child, style, spans);
+            }
+        }
+        Element::LineBreak | Element::HorizontalRule => spans.push(Span {
+            text: "\n".to_string(),
+            style,
+            url: None,
+        }),
+        Element::List { elements, .. } => {
+            for item in elements {
+                self::spans(&item.element, style, spans);
+                spans.push(Span {
+                    text: " ".to_string(),
+                    style,
+                    url: None,
+                });
+            }
+        }
+        Element::DefinitionList { items } => {
+            for item in items {
+                for child in item.term.iter().chain(&item.definitions) {
+                    self::spans(child, style, spans);
+                    spans.push(Span {
+                        text: " ".to_string(),
+                        style,
+                        url: None,
+                    });
+                }
+            }
+        }
+        Element::CodeBlock { code, .. } => spans.push(Span {
+            text: sanitize(code),
+            style,
+            url: None,
+        }),
+        Element::Math { tex, .. } => spans.push(Span {
+            text: sanitize(tex),
+            style,
+            url: None,
+        }),
+        Element::FootnoteReference { id } => spans.push(Span {
+            text: format!("[{}]", sanitize(id)),
+            style: DIM,
+            url: None,
+        }),
+        Element::Footnote { id, content } => {
+            spans.push(Span {
+                text: format!("[{}] ", sanitize(id)),
+                style: DIM,
+                url: None,
+            });
+            for child in content {
+                self::spans(child, style, spans);
+            }
+        }
+        Element::Media { .. } => self::spans(&element.media_fallback(), style, spans),
+        Element::Ruby { .. } => self::spans(&element.ruby_fallback(), style, spans),
+        Element::Table { .. }
+        | Element::Chart { .. }
+        | Element::Comment { .. }
+        | Element::PageBreak
+        | Element::TableOfContents { .. }
+        | Element::Custom { .. } => {}
+    }
+}
+
+fn image_span(image: &ImageData) -> Span {
+    let text = [image.alt(), image.title()]
+        .into_iter()
+        .find(|text| !text.is_empty())
+        .unwrap_or("image");
+    Span {
+        text: format!("[image: {}]", sanitize(text)),
+        style: DIM,
+        url: None,
+    }
+}
+
+fn tokens(spans: &[Span]) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = Word::default();
+    let finish = |word: &mut Word, tokens: &mut Vec<Token>| {
+        if word.width > 0 {
+            tokens.push(Token::Word(std::mem::take(word)));
+        }
+    };
+    for span in spans {
+        for c in span.text.chars() {
+            if c == '\n' {
+                finish(&mut word, &mut tokens);
+                tokens.push(Token::Break);
+            } else if c.is_whitespace() {
+                finish(&mut word, &mut tokens);
+            } else {
+                match word.pieces.last_mut() {
+                    Some((piece, style, url)) if *style == span.style && *url == span.url => {
+                        piece.push(c)
+                    }
+                    _ => word
+                        .pieces
+                        .push((c.to_string(), span.style, span.url.clone())),
+                }
+                word.width += 1;
+            }
+        }
+    }
+    finish(&mut word, &mut tokens);
+    tokens
+}
+
+/// Lines with their visible width, words longer than `width` get a line of their own
+fn wrap(tokens: &[Token], width: usize) -> Vec<(String, usize)> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut line_width = 0;
+    for token in tokens {
+        match token {
+            Token::Break => {
+                lines.push((std::mem::take(&mut line), line_width));
+                line_width = 0;
+            }
+            Token::Word(word) => {
+                if line_width > 0 && line_width + 1 + word.width > width {
+                    lines.push((std::mem::take(&mut line), line_width));
+                    line_width = 0;
+                }
+                if line_width > 0 {
+                    line.push(' ');
+                    line_width += 1;
+                }
+                line.push_str(&word.render());
+                line_width += word.width;
+            }
+        }
+    }
+    if line_width > 0 || lines.is_empty() {
+        lines.push((line, line_width));
+    }
+    lines
+}
+
+fn inline_lines(element: &Element, style: &'static str, width: usize) -> Vec<(String, usize)> {
+    let mut inline = Vec::new();
+    spans(element, style, &mut inline);
+    wrap(&tokens(&inline), width)
+}
+
+fn block(element: &Element) -> String {
+    match element {
+        Element::Header { level, .. } => {
+            let style = if *level <= 2 { BOLD_UNDERLINE } else { BOLD };
+            join(inline_lines(element, style, WIDTH))
+        }
+        // Multi-line text keeps its layout, indented
+        Element::Text { text, .. } if text.contains('\n') => indented(text),
+        Element::CodeBlock { code, .. } => indented(code),
+        Element::List { elements, numbered } => {
+            let mut lines = Vec::new();
+            list(&mut lines, elements, *numbered, 0);
+            lines.join("\n")
+        }
+        Element::Chart { .. } => block(&element.chart_fallback()),
+        Element::Table { headers, rows, .. } => table(headers, rows),
+        Element::HorizontalRule => format!("{}{}{}", DIM, "─".repeat(WIDTH), RESET),
+        // Bold terms, each definition wraps indented below its term
+        Element::DefinitionList { items } => {
+            let mut lines = Vec::new();
+            for item in items {
+                let mut term = Vec::new();
+                for child in &item.term {
+                    spans(child, BOLD, &mut term);
+                }
+                lines.extend(
+                    wrap(&tokens(&term), WIDTH)
+                        .into_iter()
+                        .map(|(line, _)| line),
+                );
+                for definition in &item.definitions {
+                    for (line, _) in inline_lines(definition, "", WIDTH - 4) {
+                        lines.push(format!("    {}", line));
+                    }
+                }
+            }
+            lines.join("\n")
+        }
+        Element::Section { children, .. } => children
+            .iter()
+            .map(block)
+            .filter(|block| !block.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n\n"),
+        // Quoted blocks are set off by a dim bar
+        Element::Blockquote { elements, .. } => elements
+            .iter()
+            .map(block)
+            .filter(|block| !block.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n\n")
+            .lines()
+            .map(|line| format!("{}│{} {}", DIM, RESET, line))
+            .collect::<Vec<String>>()
+            .join("\n"),
+        // A terminal has no pages
+        Element::Comment { .. } | Element::PageBreak | Element::TableOfContents { .. } => {
+            String::new()
+        }
+        element => {
+            let lines = inline_lines(element, "", WIDTH);
+            if lines.iter().all(|(_, width)| *width == 0) {
+                return String::new();
+            }
+            join(lines)
+        }
+    }
+}
+
+fn indented(text: &str) -> String {
+    sanitize(text.trim_end_matches('\n'))
+        .lines()
+        .map(|line| format!("    {}", line))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn join(lines: Vec<(String, usize)>) -> String {
+    lines
+        .into_iter()
+        .map(|(line, _)| line)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Items wrap with a hanging indent, nested lists are indented below their parent item
+fn list(lines: &mut Vec<String>, items: &[ListItem], numbered: bool, depth: usize) {
+    let indent = " ".repeat(depth * 3);
+    let mut number = 0;
+    for item in items {
+        if let Element::List { elements, numbered } = &item.element {
+            list(lines, elements, *numbered, depth + 1);
+            continue;
+        }
+        number += 1;
+        let marker = if numbered {
+            format!("{}. ", number)
+        } else {
+            "• ".to_string()
+        };
+        let marker_width = marker.chars().count();
+        let width = WIDTH.saturating_sub(indent.len() + marker_width).max(20);
+        for (index, (line, _)) in inline_lines(&item.element, "", width)
+            .into_iter()
+            .enumerate()
+        {
+            if index == 0 {
+                lines.push(format!("{}{}{}", indent, marker, line));
+            } else {
+                lines.push(format!("{}{}{}", indent, " ".repeat(marker_width), line));
+            }
+        }
+    }
+}
+
+fn table(headers: &[TableHeader], rows: &[TableRow]) -> String {
+    let mut cells: Vec<Vec<Vec<Token>>> = Vec::new();
+    let cell_tokens = |element: &Element, style: &'static str| {
+        let mut inline = Vec::new();
+        spans(element, style, &mut inline);
+        tokens(&inline)
+    };
+    if !headers.is_empty() {
+        cells.push(
+            headers
+                .iter()
+                .map(|header| cell_tokens(&header.element, BOLD))
+                .collect(),
+        );
+    }
+    for row in rows {
+        cells.push(
+            row.cells
+                .iter()
+                .map(|cell| cell_tokens(&cell.element(), ""))
+                .collect(),
+        );
+    }
+    let columns = cells.iter().map(Vec::len).max().unwrap_or(0);
+    if columns == 0 {
+        return String::new();
+    }
+
+    // Natural widths keep every cell on one line, the longest word is the least a column takes
+    let measure = |column: usize, single_line: bool| {
+        cells
+            .iter()
+            .filter_map(|row| row.get(column))
+            .map(|tokens| {
+                let words = tokens.iter().filter_map(|token| match token {
+                    Token::Word(word) => Some(word.width),
+                    Token::Break => None,
+                });
+                if single_line {
+                    let (count, total) =
+                        words.fold((0, 0), |(count, total), width| (count + 1, total + width));
+                    total + count.max(1) - 1
+                } else {
+                    words.max().unwrap_or(0)
+                }
+            })
+            .max()
+            .unwrap_or(0)
+            .max(1)
+    };
+    let mut widths: Vec<usize> = (0..columns).map(|column| measure(column, true)).collect();
+    let minimums: Vec<usize> = (0..columns).map(|column| measure(column, false)).collect();
+    let available = WIDTH.saturating_sub(3 * columns + 1);
+    while widths.iter().sum::<usize>() > available {
+        let Some(column) = (0..columns)
+            .filter(|column| widths[*column] > minimums[*column])
+            .max_by_key(|column| widths[*column])
+        else {
+            break;
+        };
+        widths[column] -= 1;
+    }
+
+    let border = |left: &str, middle: &str, right: &str| {
+        let segments: Vec<String> = widths.iter().map(|width| "─".repeat(width + 2)).collect();
+        format!("{}{}{}", left, segments.join(middle), right)
+    };
+    let mut lines = vec![border("┌", "┬", "┐")];
+    for (index, row) in cells.iter().enumerate() {
+        let wrapped: Vec<Vec<(String, usize)>> = (0..columns)
+            .map(|column| match row.get(column) {
+                Some(tokens) => wrap(tokens, widths[column]),
+                None => Vec::new(),
+            })
+            .collect();
+        let height = wrapped.iter().map(Vec::len).max().unwrap_or(0).max(1);
+        for line in 0..height {
+            let mut text = String::from("│");
+            for (column, cell) in wrapped.iter().enumerate() {
+                let (content, width) = cell
+                    .get(line)
+                    .map_or((String::new(), 0), |(content, width)| {
+                        (content.clone(), *width)
+                    });
+                let padding = widths[column].saturating_sub(width);
+                text.push_str(&format!(" {}{} │", content, " ".repeat(padding)));
+            }
+            lines.push(text);
+        }
+        if index == 0 && !headers.is_empty() && cells.len() > 1 {
+            lines.push(border("├", "┼", "┤"));
+        }
+    }
+    lines.push(border("└", "┴", "┘"));
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ansi::*;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Report".to_string(),
+                anchor: None,
+            },
+            Element::Paragraph {
+                elements: vec![
+                    text("See "),
+                    Element::Hyperlink {
+                        title: "the docs".to_string(),
+                        url: "https://example.com".to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    },
+                    text(".\x1b[31m"),
+                ],
+            },
+            Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("One"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: Element::List {
+                            elements: vec![ListItem {
+                                element: text("Nested"),
+                                checked: None,
+                            }],
+                            numbered: true,
+                        },
+                        checked: None,
+                    },
+                ],
+                numbered: false,
+            },
+            Element::Table {
+                headers: vec![TableHeader {
+                    element: text("Name"),
+                    width: 30.0,
+                }],
+                rows: vec![TableRow {
+                    cells: vec![TableCell::new(text("shiva"))],
+                }],
+                alignments: vec![],
+                caption: vec![],
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let link = |word: &str| {
+            format!(
+                "\x1b]8;;https://example.com\x1b\\{}{}{}\x1b]8;;\x1b\\",
+                LINK, word, RESET
+            )
+        };
+        assert_eq!(
+            String::from_utf8(generated.to_vec())?,
+            format!(
+                "{}Report{}\n\nSee {} {}.[31m\n\n• One\n   1. Nested\n\n{}\n",
+                BOLD_UNDERLINE,
+                RESET,
+                link("the"),
+                link("docs"),
+                [
+                    "┌───────┐".to_string(),
+                    format!("│ {}Name{}  │", BOLD, RESET),
+                    "├───────┤".to_string(),
+                    "│ shiva │".to_string(),
+                    "└───────┘".to_string(),
+                ]
+                .join("\n")
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_wrap() -> anyhow::Result<()> {
+        let words = "lorem ipsum ".repeat(20);
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: words.clone(),
+                    font: FontSpec::from(8),
+                }],
+            },
+            Element::Table {
+                headers: vec![],
+                rows: vec![TableRow {
+                    cells: vec![
+                        TableCell::new(Element::Text {
+                            text: words.clone(),
+                            font: FontSpec::from(8),
+                        }),
+                        TableCell::new(Element::Text {
+                            text: words,
+                            font: FontSpec::from(8),
+                        }),
+                    ],
+                }],
+                alignments: vec![],
+                caption: vec![],
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let text = String::from_utf8(generated.to_vec())?;
+        assert!(text.lines().count() > 4);
+        assert!(text.lines().all(|line| line.chars().count() <= WIDTH));
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+use crate::core::*;
+use crate::tables::{sheets, ColumnType, Sheet};
+use ::arrow::array::{ArrayRef, Float64Array, Int64Array, StringArray};
+use ::arrow::datatypes::{DataType, Field, Schema};
+use ::arrow::record_batch::RecordBatch;
+use ::parquet::arrow::ArrowWriter;
+use bytes::Bytes;
+use std::sync::Arc;
+
+/// Column that names the source table when several tables share one Parquet file.
+/// Column names derived from headers never start with an underscore followed by a letter.
+const TABLE_COLUMN: &str = "_table";
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(_document: &Bytes) -> anyhow::Result<Document> {
+        Err(anyhow::anyhow!("Parquet parsing is not supported"))
+    }
+
+    /// Writes the tables of the document into a Parquet file. A single table keeps its
+    /// columns, several tables are stacked into one schema with the union of their columns
+    /// and a leading `_table` column holding the table name. Names and column types follow
+    /// the SQLite export, a column with different types in different tables becomes `Utf8`.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        let mut sheets = sheets(document);
+        let sheet = match sheets.len() {
+            0 => return Err(anyhow::anyhow!("The document has no tables to export")),
+            1 => sheets.remove(0),
+            _ => stack(&sheets),
+        };
+        let batch = record_batch(&sheet)?;
+        let mut buffer = Vec::new();
+        let mut writer = ArrowWriter::try_new(&mut buffer, batch.schema(), None)?;
+        writer.write(&batch)?;
+        writer.close()?;
+        Ok(Bytes::from(buffer))
+    }
+}
+
+/// One record batch per table of the document, with the table name, for pipelines that
+/// consume Arrow data directly.
+pub fn record_batches(document: &Document) -> anyhow::Result<Vec<(String, RecordBatch)>> {
+    sheets(document)
+        .iter()
+        .map(|sheet| Ok((sheet.name.clone(), record_batch(sheet)?)))
+        .collect()
+}
+
+fn record_batch(sheet: &Sheet) -> anyhow::Result<RecordBatch> {
+    let fields: Vec<Field> = sheet
+        .columns
+        .iter()
+        .zip(&sheet.types)
+        .map(|(column, column_type)| {
+            let data_type = match column_type {
+                ColumnType::Integer => DataType::Int64,
+                ColumnType::Real => DataType::Float64,
+                ColumnType::Text => DataType::Utf8,
+            };
+            Field::new(column, data_type, true)
+        })
+        .collect();
+    let arrays: Vec<ArrayRef> = sheet
+        .types
+        .iter()
+        .enumerate()
+        .map(|(index, column_type)| {
+            let cells = sheet
+                .rows
+                .iter()
+                .map(|row| Some(row[index].as_str()).filter(|cell| !cell.is_empty()));
+            let array: ArrayRef = match column_type {
+                ColumnType::Integer => Arc::new(
+                    cells
+                        .map(|cell| cell.and_then(|cell| cell.parse().ok()))
+                        .collect::<Int64Array>(),
+                ),
+                ColumnType::Real => Arc::new(
+                    cells
+                        .map(|cell| cell.and_then(|cell| cell.parse().ok()))
+                        .collect::<Float64Array>(),
+                ),
+                ColumnType::Text => Arc::new(cells.collect::<StringArray>()),
+            };
+            array
+        })
+        .collect();
+    Ok(RecordBatch::try_new(Arc::new(Schema::new(fields)), arrays)?)
+}
+
+/// Rows of all tables under the union of their columns, missing cells stay empty
+fn stack(sheets: &[Sheet]) -> Sheet {
+    let mut columns = vec![TABLE_COLUMN.to_string()];
+    let mut types = vec![ColumnType::Text];
+    for sheet in sheets {
+        for (column, column_type) in sheet.columns.iter().zip(&sheet.types) {
+            match columns.iter().position(|name| name == column) {
+                Some(index) => types[index] = merge(types[index], *column_type),
+                None => {
+                    columns.push(column.clone());
+                    types.push(*column_type);
+                }
+            }
+        }
+    }
+
+    let mut rows = Vec::new();
+    for sheet in sheets {
+        let positions: Vec<usize> = sheet
+            .columns
+            .iter()
+            .filter_map(|column| columns.iter().position(|name| name == column))
+            .collect();
+        for cells in &sheet.rows {
+            let mut row = vec![String::new(); columns.len()];
+            row[0] = sheet.name.clone();
+            for (position, cell) in positions.iter().zip(cells) {
+                row[*position] = cell.clone();
+            }
+            rows.push(row);
+        }
+    }
+    Sheet {
+        name: TABLE_COLUMN.to_string(),
+        columns,
+        types,
+        rows,
+    }
+}
+
+fn merge(left: ColumnType, right: ColumnType) -> ColumnType {
+    match (left, right) {
+        (left, right) if left == right => left,
+        (ColumnType::Integer, ColumnType::Real) | (ColumnType::Real, ColumnType::Integer) => {
+            ColumnType::Real
+        }
+        _ => ColumnType::Text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::arrow::*;
+    use ::arrow::array::Array;
+    use ::parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    fn table(headers: &[&str], rows: &[&[&str]]) -> Element {
+        let cell = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        Element::Table {
+            headers: headers
+                .iter()
+                .map(|header| TableHeader {
+                    element: cell(header),
+                    width: 30.0,
+                })
+                .collect(),
+            rows: rows
+                .iter()
+                .map(|cells| TableRow {
+                    cells: cells
+                        .iter()
+                        .map(|text| TableCell::new(cell(text)))
+                        .collect(),
+                })
+                .collect(),
+            alignments: vec![],
+            caption: vec![],
+        }
+    }
+
+    fn read(bytes: Bytes) -> anyhow::Result<RecordBatch> {
+        let mut reader = ParquetRecordBatchReaderBuilder::try_new(bytes)?.build()?;
+        Ok(reader.next().ok_or_else(|| anyhow::anyhow!("no rows"))??)
+    }
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Header {
+                level: 2,
+                text: "Sales".to_string(),
+                anchor: None,
+            },
+            table(
+                &["Region", "Units", "Share"],
+                &[&["North", "12", "0.4"], &["South", "18"]],
+            ),
+        ]);
+        let batch = read(Transformer::generate(&document)?)?;
+        let schema = batch.schema();
+        let fields: Vec<(&str, &DataType)> = schema
+            .fields()
+            .iter()
+            .map(|field| (field.name().as_str(), field.data_type()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("region", &DataType::Utf8),
+                ("units", &DataType::Int64),
+                ("share", &DataType::Float64)
+            ]
+        );
+        let units = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Int64Array>()
+            .ok_or_else(|| anyhow::anyhow!("units are not integers"))?;
+        assert_eq!(units.values(), &[12, 18]);
+        assert!(batch.column(2).is_null(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_several_tables() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            table(&["Name", "Score"], &[&["a", "1"]]),
+            table(&["Name", "Score", "Note"], &[&["b", "2.5", "late"]]),
+        ]);
+        let batch = read(Transformer::generate(&document)?)?;
+        let schema = batch.schema();
+        let names: Vec<&str> = schema
+            .fields()
+            .iter()
+            .map(|field| field.name().as_str())
+            .collect();
+        assert_eq!(names, vec!["_table", "name", "score", "note"]);
+        assert_eq!(schema.field(2).data_type(), &DataType::Float64);
+        let tables = batch
+            .column(0)
+            .as_any()
+            .downcast_ref::<StringArray>()
+            .ok_or_else(|| anyhow::anyhow!("table names are not strings"))?;
+        assert_eq!(tables.value(0), "table_1");
+        assert_eq!(tables.value(1), "table_2");
+        assert!(batch.column(3).is_null(0));
+        assert_eq!(record_batches(&document)?.len(), 2);
+        Ok(())
+    }
+}
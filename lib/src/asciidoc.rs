@@ -0,0 +1,1225 @@
+use crate::core::*;
+use bytes::Bytes;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Parses titles, sections, lists, `|===` tables, admonitions, links and image macros.
+    /// Inline formatting marks are dropped, the text is kept.
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let source = String::from_utf8(document.to_vec())?;
+        let mut parser = Parser {
+            lines: source.lines().collect(),
+            index: 0,
+            image_loader: &image_loader,
+        };
+        parser.skip_document_header();
+        let elements = parser.parse_blocks(None)?;
+        Ok(Document::new(elements))
+    }
+
+    /// Images are written through `image_saver` and referenced from `image::` macros.
+    /// Page headers and footers have no AsciiDoc equivalent and are skipped.
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let mut generator = Generator {
+            image_saver,
+            image_num: 0,
+            after_list: false,
+        };
+        let mut asciidoc = String::new();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                generator.block(&mut asciidoc, element)?;
+            }
+        }
+        // The macro only places the table of contents, the document header has to enable it
+        let toc = document
+            .get_all_elements()
+            .into_iter()
+            .any(|element| matches!(element, Element::TableOfContents { .. }));
+        if toc {
+            match asciidoc
+                .strip_prefix("= ")
+                .and_then(|_| asciidoc.find('\n'))
+            {
+                Some(end) => asciidoc.insert_str(end + 1, ":toc: macro\n"),
+                None => asciidoc.insert_str(0, ":toc: macro\n\n"),
+            }
+        }
+        Ok(Bytes::from(asciidoc))
+    }
+}
+
+const ADMONITIONS: [&str; 5] = ["NOTE", "TIP", "IMPORTANT", "WARNING", "CAUTION"];
+
+/// Delimiters of blocks whose content is regular AsciiDoc (example, sidebar, quote, open)
+const COMPOUND_DELIMITERS: [&str; 4] = ["====", "****", "____", "--"];
+
+/// Delimiters of blocks whose content is taken verbatim (listing, literal, passthrough)
+const VERBATIM_DELIMITERS: [&str; 3] = ["----", "....", "++++"];
+
+struct Parser<'a, F> {
+    lines: Vec<&'a str>,
+    index: usize,
+    image_loader: &'a F,
+}
+
+impl<'a, F> Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    fn next_line(&self) -> Option<&'a str> {
+        self.lines.get(self.index).copied()
+    }
+
+    /// The header is the document title followed by author, revision and attribute lines
+    fn skip_document_header(&mut self) {
+        while self
+            .next_line()
+            .is_some_and(|line| line.trim().is_empty() || line.starts_with("//"))
+        {
+            self.index += 1;
+        }
+        if !self.next_line().is_some_and(|line| line.starts_with("= ")) {
+            return;
+        }
+        // Keep the title line itself, skip what follows it up to the first blank line
+        let mut end = self.index + 1;
+        while self
+            .lines
+            .get(end)
+            .is_some_and(|line| !line.trim().is_empty())
+        {
+            end += 1;
+        }
+        self.lines.drain(self.index + 1..end);
+    }
+
+    fn parse_blocks(&mut self, delimiter: Option<&str>) -> anyhow::Result<Vec<Element>> {
+        let mut elements = Vec::new();
+        let mut block_attributes: Option<&str> = None;
+        while let Some(line) = self.next_line() {
+            let trimmed = line.trim_end();
+            if Some(trimmed) == delimiter {
+                self.index += 1;
+                break;
+            }
+            if trimmed.is_empty() {
+                self.index += 1;
+                continue;
+            }
+
+            if trimmed.starts_with("////") {
+                self.index += 1;
+                let comment = self.take_until(trimmed);
+                elements.push(Element::Comment {
+                    text: comment.join("\n"),
+                    author: None,
+                    date: None,
+                    range: None,
+                });
+                continue;
+            }
+            if let Some(comment) = trimmed.strip_prefix("//") {
+                self.index += 1;
+                // `//-` only separates adjacent lists
+                if comment == "-" {
+                    continue;
+                }
+                elements.push(Element::Comment {
+                    text: comment.trim().to_string(),
+                    author: None,
+                    date: None,
+                    range: None,
+                });
+                continue;
+            }
+            if trimmed == "<<<" {
+                elements.push(Element::PageBreak);
+                self.index += 1;
+                continue;
+            }
+            if let Some(attributes) = trimmed
+                .strip_prefix("toc::[")
+                .and_then(|rest| rest.strip_suffix(']'))
+            {
+                elements.push(Element::TableOfContents {
+                    max_level: toc_level(attributes),
+                });
+                self.index += 1;
+                continue;
+            }
+            if is_attribute_entry(trimmed) || trimmed == "'''" {
+                self.index += 1;
+                continue;
+            }
+            if trimmed.starts_with('[') && trimmed.ends_with(']') && !trimmed.starts_with("[[") {
+                block_attributes = Some(&trimmed[1..trimmed.len() - 1]);
+                self.index += 1;
+                continue;
+            }
+            if let Some(level) = section_level(trimmed) {
+                elements.push(Element::Header {
+                    level,
+                    text: strip_formatting(trimmed[level as usize..].trim()),
+                    anchor: None,
+                });
+                self.index += 1;
+                block_attributes = None;
+                continue;
+            }
+
+            let attributes = block_attributes.take();
+            if trimmed == "|===" {
+                self.index += 1;
+                let lines = self.take_until("|===");
+                elements.push(self.parse_table(&lines, attributes)?);
+            } else if VERBATIM_DELIMITERS.contains(&trimmed) {
+                self.index += 1;
+                let lines = self.take_until(trimmed);
+                elements.push(Element::Text {
+                    text: lines.join("\n"),
+                    font: FontSpec::from(8),
+                });
+            } else if COMPOUND_DELIMITERS.contains(&trimmed) {
+                self.index += 1;
+                let mut content = self.parse_blocks(Some(trimmed))?;
+                let admonition =
+                    attributes.filter(|attributes| ADMONITIONS.contains(&attributes.trim()));
+                if let Some(label) = admonition {
+                    prefix_admonition(&mut content, label.trim());
+                }
+                elements.append(&mut content);
+            } else if let Some(target) = trimmed.strip_prefix("image::") {
+                self.index += 1;
+                elements.push(self.parse_image(target)?);
+            } else if list_marker(trimmed).is_some() {
+                elements.push(self.parse_list()?);
+            } else if trimmed.starts_with('.')
+                && trimmed.len() > 1
+                && !trimmed[1..].starts_with(['.', ' '])
+            {
+                // Block title, kept as plain text so it is not lost
+                self.index += 1;
+                elements.push(Element::Text {
+                    text: strip_formatting(&trimmed[1..]),
+                    font: FontSpec::from(8),
+                });
+            } else {
+                elements.push(self.parse_paragraph(delimiter)?);
+            }
+        }
+        Ok(elements)
+    }
+
+    /// Lines up to the closing delimiter, which is consumed
+    fn take_until(&mut self, delimiter: &str) -> Vec<&'a str> {
+        let mut lines = Vec::new();
+        while let Some(line) = self.next_line() {
+            self.index += 1;
+            if line.trim_end() == delimiter {
+                break;
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    fn parse_paragraph(&mut self, delimiter: Option<&str>) -> anyhow::Result<Element> {
+        let mut lines = Vec::new();
+        while let Some(line) = self.next_line() {
+            let trimmed = line.trim_end();
+            if trimmed.is_empty()
+                || Some(trimmed) == delimiter
+                || (!lines.is_empty() && starts_block(trimmed))
+            {
+                break;
+            }
+            lines.push(trimmed);
+            self.index += 1;
+        }
+
+        let mut elements = Vec::new();
+        let mut admonition = None;
+        for (index, line) in lines.iter().enumerate() {
+            let mut line = *line;
+            if index == 0 {
+                if let Some((label, rest)) = line.split_once(": ") {
+                    if ADMONITIONS.contains(&label) {
+                        admonition = Some(label);
+                        line = rest;
+                    }
+                }
+            }
+            if index > 0 && !matches!(elements.last(), Some(Element::LineBreak)) {
+                push_text(&mut elements, " ");
+            }
+            let (line, hard_break) = match line.strip_suffix(" +") {
+                Some(line) => (line, true),
+                None => (line, false),
+            };
+            self.parse_inline(line, &mut elements)?;
+            if hard_break {
+                elements.push(Element::LineBreak);
+            }
+        }
+        if let Some(label) = admonition {
+            prefix_text(&mut elements, label);
+        }
+        Ok(Element::Paragraph { elements })
+    }
+
+    fn parse_list(&mut self) -> anyhow::Result<Element> {
+        // Each open level: its marker, whether it is numbered and the items so far
+        let mut stack: Vec<(String, bool, Vec<ListItem>)> = Vec::new();
+        while let Some(line) = self.next_line() {
+            let trimmed = line.trim();
+            let Some((marker, numbered, text)) = list_marker(trimmed) else {
+                // Continuation lines belong to the previous item
+                if trimmed.is_empty() || starts_block(trimmed) || stack.is_empty() {
+                    break;
+                }
+                self.index += 1;
+                if let Some(item) = stack.last_mut().and_then(|(_, _, items)| items.last_mut()) {
+                    let mut continuation = Vec::new();
+                    self.parse_inline(trimmed, &mut continuation)?;
+                    item.element = append_inline(item.element.clone(), continuation);
+                }
+                continue;
+            };
+            self.index += 1;
+
+            if let Some(position) = stack.iter().position(|(open, _, _)| *open == marker) {
+                while stack.len() > position + 1 {
+                    fold_level(&mut stack);
+                }
+            } else {
+                stack.push((marker, numbered, Vec::new()));
+            }
+            let mut inline = Vec::new();
+            self.parse_inline(text, &mut inline)?;
+            let element = match inline.len() {
+                1 => inline.remove(0),
+                _ => Element::Paragraph { elements: inline },
+            };
+            if let Some((_, _, items)) = stack.last_mut() {
+                items.push(ListItem {
+                    element,
+                    checked: None,
+                });
+            }
+        }
+        while stack.len() > 1 {
+            fold_level(&mut stack);
+        }
+        let (_, numbered, elements) = stack.pop().unwrap_or_default();
+        Ok(Element::List { elements, numbered })
+    }
+
+    fn parse_table(&mut self, lines: &[&str], attributes: Option<&str>) -> anyhow::Result<Element> {
+        let attributes = attributes.unwrap_or_default();
+        let no_header = attributes.contains("noheader");
+        let mut header = !no_header && attributes.contains("header");
+        let mut columns = column_count(attributes);
+
+        let mut cells: Vec<String> = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut parts = split_cells(line);
+            // Text before the first separator continues the previous cell
+            let first = parts.remove(0);
+            if !first.trim().is_empty() {
+                if let Some(cell) = cells.last_mut() {
+                    cell.push(' ');
+                    cell.push_str(first.trim());
+                }
+            }
+            if columns.is_none() && !parts.is_empty() {
+                columns = Some(parts.len());
+                // A first row followed by a blank line is an implicit header
+                header |= !no_header
+                    && lines
+                        .get(index + 1)
+                        .is_some_and(|next| next.trim().is_empty());
+            }
+            cells.extend(parts.into_iter().map(|cell| cell.trim().to_string()));
+        }
+
+        let columns = columns.unwrap_or(1).max(1);
+        let mut rows = Vec::new();
+        for chunk in cells.chunks(columns) {
+            let mut row = Vec::new();
+            for cell in chunk {
+                let mut inline = Vec::new();
+                self.parse_inline(cell, &mut inline)?;
+                row.push(match inline.len() {
+                    0 => Element::Text {
+                        text: String::new(),
+                        font: FontSpec::from(8),
+                    },
+                    1 => inline.remove(0),
+                    _ => Element::Paragraph { elements: inline },
+                });
+            }
+            rows.push(row);
+        }
+
+        let headers = if header && !rows.is_empty() {
+            rows.remove(0)
+                .into_iter()
+                .map(|element| TableHeader {
+                    element,
+                    width: 30.0,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let rows = rows
+            .into_iter()
+            .map(|cells| TableRow {
+                cells: cells.into_iter().map(TableCell::new).collect(),
+            })
+            .collect();
+        Ok(Element::Table {
+            headers,
+            rows,
+            alignments: vec![],
+            caption: vec![],
+        })
+    }
+
+    fn parse_image(&self, macro_body: &str) -> anyhow::Result<Element> {
+        let (target, attributes) = match macro_body.split_once('[') {
+            Some((target, attributes)) => (target, attributes.trim_end_matches(']')),
+            None => (macro_body, ""),
+        };
+        let mut alt = String::new();
+        let mut title = String::new();
+        let mut width = None;
+        let mut height = None;
+        for (index, attribute) in attributes.split(',').map(str::trim).enumerate() {
+            match attribute.split_once('=') {
+                Some(("alt", value)) => alt = value.trim_matches('"').to_string(),
+                Some(("title", value)) => title = value.trim_matches('"').to_string(),
+                Some(("width", value)) => width = Some(value.trim_matches('"').to_string()),
+                Some(("height", value)) => height = Some(value.trim_matches('"').to_string()),
+                Some(_) => {}
+                None if attribute.is_empty() => {}
+                None => match index {
+                    0 => alt = attribute.trim_matches('"').to_string(),
+                    1 => width = Some(attribute.to_string()),
+                    2 => height = Some(attribute.to_string()),
+                    _ => {}
+                },
+            }
+        }
+        let bytes = (self.image_loader)(target)?;
+        Ok(Element::Image(ImageData::new(
+            bytes,
+            title,
+            alt,
+            target.to_string(),
+            String::new(),
+            ImageDimension { width, height },
+        )))
+    }
+
+    /// Splits a line into text, hyperlinks and inline images
+    fn parse_inline(&self, line: &str, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        let mut rest = line;
+        while !rest.is_empty() {
+            let next = ["https://", "http://", "mailto:", "link:", "image:", "<<"]
+                .iter()
+                .filter_map(|prefix| rest.find(prefix).map(|index| (index, *prefix)))
+                .filter(|(index, _)| {
+                    *index == 0 || !rest[..*index].ends_with(char::is_alphanumeric)
+                })
+                .min_by_key(|(index, _)| *index);
+            let Some((start, prefix)) = next else {
+                push_text(elements, &strip_formatting(rest));
+                break;
+            };
+            push_text(elements, &strip_formatting(&rest[..start]));
+            let target_start = start
+                + if prefix == "link:" || prefix == "image:" {
+                    prefix.len()
+                } else {
+                    0
+                };
+
+            if prefix == "<<" {
+                // Cross reference, only the text survives
+                let end = rest[start..].find(">>").map(|end| start + end);
+                let Some(end) = end else {
+                    push_text(elements, "<<");
+                    rest = &rest[start + 2..];
+                    continue;
+                };
+                let reference = &rest[start + 2..end];
+                let text = reference
+                    .split_once(',')
+                    .map(|(_, text)| text)
+                    .unwrap_or(reference);
+                push_text(elements, text.trim());
+                rest = &rest[end + 2..];
+                continue;
+            }
+
+            let target_end = rest[target_start..]
+                .find(|c: char| c.is_whitespace() || c == '[')
+                .map(|end| target_start + end)
+                .unwrap_or(rest.len());
+            let target = &rest[target_start..target_end];
+            let (text, end) = if rest[target_end..].starts_with('[') {
+                match rest[target_end..].find(']') {
+                    Some(close) => (
+                        Some(&rest[target_end + 1..target_end + close]),
+                        target_end + close + 1,
+                    ),
+                    None => (None, target_end),
+                }
+            } else {
+                (None, target_end)
+            };
+
+            if prefix == "image:" {
+                elements.push(self.parse_image(&rest[target_start..end])?);
+            } else {
+                // Trailing punctuation of a bare URL is not part of it
+                let (target, end) = match text {
+                    None => {
+                        let trimmed = target.trim_end_matches(['.', ',', ';', ':', ')']);
+                        (trimmed, target_start + trimmed.len())
+                    }
+                    Some(_) => (target, end),
+                };
+                let title = text
+                    .map(|text| text.trim_end_matches('^').trim())
+                    .filter(|text| !text.is_empty())
+                    .unwrap_or(target);
+                elements.push(Element::Hyperlink {
+                    title: strip_formatting(title),
+                    url: target.to_string(),
+                    alt: String::new(),
+                    size: 8,
+                });
+                rest = &rest[end..];
+                continue;
+            }
+            rest = &rest[end..];
+        }
+        Ok(())
+    }
+}
+
+fn is_attribute_entry(line: &str) -> bool {
+    line.starts_with(':') && line[1..].contains(':') && !line.starts_with("::")
+}
+
+/// The deepest header level in a table of contents. `levels` counts the sections below the
+/// title, two of them by default
+fn toc_level(attributes: &str) -> u8 {
+    let levels = attributes
+        .split(',')
+        .find_map(|attribute| attribute.trim().strip_prefix("levels="))
+        .and_then(|levels| levels.trim_matches('"').parse::<u8>().ok())
+        .unwrap_or(2);
+    (levels + 1).clamp(1, 6)
+}
+
+/// `= Title` is level 1, `== Section` level 2 and so on
+fn section_level(line: &str) -> Option<u8> {
+    let level = line.chars().take_while(|c| *c == '=').count();
+    if (1..=6).contains(&level) && line[level..].starts_with(' ') {
+        Some(level as u8)
+    } else {
+        None
+    }
+}
+
+/// Returns the marker, whether the list is ordered and the item text
+fn list_marker(line: &str) -> Option<(String, bool, &str)> {
+    let marker_end = line.find(' ')?;
+    let (marker, text) = (&line[..marker_end], line[marker_end..].trim_start());
+    if text.is_empty() {
+        return None;
+    }
+    if marker == "-" || (!marker.is_empty() && marker.chars().all(|c| c == '*')) {
+        return Some((marker.to_string(), false, text));
+    }
+    if !marker.is_empty() && marker.chars().all(|c| c == '.') {
+        return Some((marker.to_string(), true, text));
+    }
+    // Explicit numbering (1. 2. ...) is one level
+    if marker.ends_with('.')
+        && marker[..marker.len() - 1]
+            .chars()
+            .all(|c| c.is_ascii_digit())
+    {
+        return Some((".".to_string(), true, text));
+    }
+    None
+}
+
+fn starts_block(line: &str) -> bool {
+    section_level(line).is_some()
+        || list_marker(line).is_some()
+        || line.starts_with("image::")
+        || line == "|==="
+        || line.starts_with("//")
+        || VERBATIM_DELIMITERS.contains(&line)
+        || COMPOUND_DELIMITERS.contains(&line)
+}
+
+fn fold_level(stack: &mut Vec<(String, bool, Vec<ListItem>)>) {
+    if let Some((_, numbered, elements)) = stack.pop() {
+        if let Some((_, _, items)) = stack.last_mut() {
+            items.push(ListItem {
+                element: Element::List { elements, numbered },
+                checked: None,
+            });
+        }
+    }
+}
+
+fn append_inline(element: Element, mut continuation: Vec<Element>) -> Element {
+    let mut elements = match element {
+        Element::Paragraph { elements } => elements,
+        element => vec![element],
+    };
+    push_text(&mut elements, " ");
+    for element in continuation.drain(..) {
+        match element {
+            Element::Text { text, .. } => push_text(&mut elements, &text),
+            element => elements.push(element),
+        }
+    }
+    match elements.len() {
+        1 => elements.remove(0),
+        _ => Element::Paragraph { elements },
+    }
+}
+
+fn column_count(attributes: &str) -> Option<usize> {
+    let start = attributes.find("cols=")? + "cols=".len();
+    let value = attributes[start..].trim_start_matches('"');
+    let value = &value[..value.find('"').unwrap_or(value.len())];
+    // cols="3" is a column count, cols="3*" repeats one specifier
+    if let Ok(count) = value.trim().parse() {
+        return Some(count);
+    }
+    if let Some((count, _)) = value.split_once('*') {
+        if let Ok(count) = count.trim().parse() {
+            return Some(count);
+        }
+    }
+    Some(value.split(',').count())
+}
+
+/// Splits a table line on unescaped `|`, the first part is the text before the first separator
+fn split_cells(line: &str) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                parts.last_mut().unwrap().push('|');
+                chars.next();
+            }
+            '|' => {
+                // Cell specifiers such as `2+` or `a` directly precede the separator
+                let first = parts.len() == 1;
+                let last = parts.last_mut().unwrap();
+                let trimmed = last.trim_end();
+                if let Some(space) = trimmed.rfind(' ') {
+                    if is_cell_specifier(&trimmed[space + 1..]) {
+                        last.truncate(space);
+                    }
+                } else if first && is_cell_specifier(trimmed) {
+                    last.clear();
+                }
+                parts.push(String::new());
+            }
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+fn is_cell_specifier(text: &str) -> bool {
+    !text.is_empty()
+        && text
+            .chars()
+            .any(|c| c == '+' || c == '*' || c.is_ascii_digit())
+        && text
+            .chars()
+            .all(|c| c.is_ascii_digit() || "+*.<^>adehlmsv".contains(c))
+}
+
+/// Drops constrained and unconstrained formatting marks (`*bold*`, `_emphasis_`,
+/// `` `code` ``, `#highlight#`) while keeping the marked text
+fn strip_formatting(text: &str) -> String {
+    const MARKS: [char; 4] = ['*', '_', '`', '#'];
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::with_capacity(text.len());
+    for (index, c) in chars.iter().enumerate() {
+        if MARKS.contains(c) {
+            let before = index.checked_sub(1).map(|index| chars[index]);
+            let after = chars.get(index + 1).copied();
+            let boundary =
+                |c: Option<char>| c.is_none_or(|c| c.is_whitespace() || c.is_ascii_punctuation());
+            let word = |c: Option<char>| c.is_some_and(|c| !c.is_whitespace());
+            if (boundary(before) && word(after)) || (word(before) && boundary(after)) {
+                continue;
+            }
+        }
+        result.push(*c);
+    }
+    result
+}
+
+fn push_text(elements: &mut Vec<Element>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(Element::Text { text: last, .. }) = elements.last_mut() {
+        last.push_str(text);
+    } else {
+        elements.push(Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        });
+    }
+}
+
+/// Admonitions have no element of their own, the label leads the text
+fn prefix_text(elements: &mut Vec<Element>, label: &str) {
+    match elements.first_mut() {
+        Some(Element::Text { text, .. }) => *text = format!("{}: {}", label, text),
+        _ => elements.insert(
+            0,
+            Element::Text {
+                text: format!("{}: ", label),
+                font: FontSpec::from(8),
+            },
+        ),
+    }
+}
+
+fn prefix_admonition(content: &mut Vec<Element>, label: &str) {
+    match content.first_mut() {
+        Some(Element::Paragraph { elements }) => prefix_text(elements, label),
+        _ => content.insert(
+            0,
+            Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: format!("{}:", label),
+                    font: FontSpec::from(8),
+                }],
+            },
+        ),
+    }
+}
+
+struct Generator<F> {
+    image_saver: F,
+    image_num: usize,
+    after_list: bool,
+}
+
+impl<F> Generator<F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn block(&mut self, asciidoc: &mut String, element: &Element) -> anyhow::Result<()> {
+        let list = matches!(
+            element,
+            Element::List { .. } | Element::DefinitionList { .. }
+        );
+        if list && self.after_list {
+            // Adjacent lists would otherwise be merged into one
+            asciidoc.push_str("//-\n\n");
+        }
+        self.after_list = list;
+        match element {
+            Element::Header { level, text, .. } => {
+                let level = (*level).clamp(1, 6) as usize;
+                asciidoc.push_str(&format!("{} {}\n\n", "=".repeat(level), text));
+            }
+            Element::HorizontalRule => asciidoc.push_str("'''\n\n"),
+            Element::PageBreak => asciidoc.push_str("<<<\n\n"),
+            Element::TableOfContents { max_level } => asciidoc.push_str(&format!(
+                "toc::[levels={}]\n\n",
+                max_level.saturating_sub(1).max(1)
+            )),
+            Element::Math { tex, display: true } => {
+                asciidoc.push_str(&format!("[latexmath]\n++++\n{}\n++++\n\n", tex));
+            }
+            Element::List { elements, numbered } => {
+                self.list(asciidoc, elements, *numbered, 1)?;
+                asciidoc.push('\n');
+            }
+            // Further definitions are attached to the entry with list continuations
+            Element::DefinitionList { items } => {
+                for item in items {
+                    let mut term = String::new();
+                    for child in &item.term {
+                        self.inline(&mut term, child)?;
+                    }
+                    asciidoc.push_str(&format!("{}::", term.replace(" +\n", " ").trim()));
+                    for (index, definition) in item.definitions.iter().enumerate() {
+                        let mut text = String::new();
+                        self.inline(&mut text, definition)?;
+                        let text = text.replace(" +\n", " ");
+                        if index == 0 {
+                            asciidoc.push_str(&format!(" {}", text.trim()));
+                        } else {
+                            asciidoc.push_str(&format!("\n+\n{}", text.trim()));
+                        }
+                    }
+                    asciidoc.push('\n');
+                }
+                asciidoc.push('\n');
+            }
+            Element::Chart { .. } => self.block(asciidoc, &element.chart_fallback())?,
+            Element::Table { headers, rows, .. } => {
+                let columns = rows
+                    .iter()
+                    .map(|row| row.cells.len())
+                    .chain(std::iter::once(headers.len()))
+                    .max()
+                    .unwrap_or(0)
+                    .max(1);
+                if headers.is_empty() {
+                    asciidoc.push_str(&format!("[cols=\"{}\"]\n|===\n", columns));
+                } else {
+                    asciidoc.push_str(&format!("[%header,cols=\"{}\"]\n|===\n", columns));
+                    let mut line = String::new();
+                    for header in headers {
+                        line.push_str(&self.cell(&header.element)?);
+                    }
+                    asciidoc.push_str(line.trim_end());
+                    asciidoc.push_str("\n\n");
+                }
+                for row in rows {
+                    let mut line = String::new();
+                    for cell in &row.cells {
+                        line.push_str(&self.cell(&cell.element())?);
+                    }
+                    asciidoc.push_str(line.trim_end());
+                    asciidoc.push('\n');
+                }
+                asciidoc.push_str("|===\n\n");
+            }
+            Element::Image(image) => {
+                if let Some(target) = self.save_image(image)? {
+                    asciidoc.push_str(&format!(
+                        "image::{}[{}]\n\n",
+                        target,
+                        image_attributes(image)
+                    ));
+                }
+            }
+            // The caption is the block title of the image
+            Element::Figure { image, caption } => {
+                if let Some(target) = self.save_image(image)? {
+                    let mut title = String::new();
+                    for child in caption {
+                        self.inline(&mut title, child)?;
+                    }
+                    let title = title.replace(" +\n", " ");
+                    if !title.trim().is_empty() {
+                        asciidoc.push_str(&format!(".{}\n", title.trim()));
+                    }
+                    asciidoc.push_str(&format!(
+                        "image::{}[{}]\n\n",
+                        target,
+                        image_attributes(image)
+                    ));
+                }
+            }
+            Element::Comment { text, .. } => {
+                for line in text.lines() {
+                    asciidoc.push_str(&format!("// {}\n", line));
+                }
+                asciidoc.push('\n');
+            }
+            Element::Blockquote { elements, .. } => {
+                asciidoc.push_str("____\n");
+                for child in elements {
+                    self.block(asciidoc, child)?;
+                }
+                asciidoc.push_str("____\n\n");
+            }
+            // The headers give the nesting
+            Element::Section { children, .. } => {
+                for child in children {
+                    self.block(asciidoc, child)?;
+                }
+            }
+            Element::CodeBlock { language, code } => {
+                match language {
+                    Some(language) => asciidoc.push_str(&format!("[source,{}]\n", language)),
+                    None => asciidoc.push_str("[source]\n"),
+                }
+                asciidoc.push_str(&format!("----\n{}\n----\n\n", code));
+            }
+            element => {
+                let mut paragraph = String::new();
+                self.inline(&mut paragraph, element)?;
+                let paragraph = paragraph.trim_end_matches(" +\n");
+                if !paragraph.trim().is_empty() {
+                    asciidoc.push_str(paragraph);
+                    asciidoc.push_str("\n\n");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn list(
+        &mut self,
+        asciidoc: &mut String,
+        items: &[ListItem],
+        numbered: bool,
+        depth: usize,
+    ) -> anyhow::Result<()> {
+        let marker = if numbered { "." } else { "*" }.repeat(depth);
+        for item in items {
+            match &item.element {
+                Element::List { elements, numbered } => {
+                    self.list(asciidoc, elements, *numbered, depth + 1)?
+                }
+                element => {
+                    let mut text = String::new();
+                    self.inline(&mut text, element)?;
+                    // A list item is a single line, hard breaks would end the list
+                    let text = text.replace(" +\n", " ");
+                    asciidoc.push_str(&format!("{} {}\n", marker, text.trim()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn cell(&mut self, element: &Element) -> anyhow::Result<String> {
+        let mut text = String::new();
+        self.inline(&mut text, element)?;
+        let text = text.replace(" +\n", " ").replace('|', "\\|");
+        Ok(format!("|{} ", text.trim()))
+    }
+
+    fn inline(&mut self, asciidoc: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => asciidoc.push_str(text),
+            Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => {
+                for child in elements {
+                    self.inline(asciidoc, child)?;
+                }
+            }
+            Element::Paragraph { elements } => {
+                for (index, child) in elements.iter().enumerate() {
+                    let separate = index > 0
+                        && !asciidoc.ends_with(char::is_whitespace)
+                        && matches!(child, Element::Text { .. } | Element::Hyperlink { .. });
+                    if separate {
+                        asciidoc.push(' ');
+                    }
+                    self.inline(asciidoc, child)?;
+                }
+            }
+            Element::Hyperlink { title, url, .. } => {
+                let scheme = ["http://", "https://", "mailto:"]
+                    .iter()
+                    .any(|scheme| url.starts_with(scheme));
+                let prefix = if scheme { "" } else { "link:" };
+                if title.is_empty() || title == url {
+                    asciidoc.push_str(&format!("{}{}[]", prefix, url));
+                } else {
+                    asciidoc.push_str(&format!("{}{}[{}]", prefix, url, title.replace(']', "\\]")));
+                }
+            }
+            Element::Image(image) => {
+                if let Some(target) = self.save_image(image)? {
+                    asciidoc.push_str(&format!("image:{}[{}]", target, image_attributes(image)));
+                }
+            }
+            Element::Figure { image, caption } => {
+                if let Some(target) = self.save_image(image)? {
+                    asciidoc.push_str(&format!("image:{}[{}]", target, image_attributes(image)));
+                }
+                for child in caption {
+                    asciidoc.push(' ');
+                    self.inline(asciidoc, child)?;
+                }
+            }
+            Element::LineBreak | Element::HorizontalRule => asciidoc.push_str(" +\n"),
+            Element::Media { .. } => self.inline(asciidoc, &element.media_fallback())?,
+            Element::Ruby { .. } => self.inline(asciidoc, &element.ruby_fallback())?,
+            Element::PageBreak | Element::TableOfContents { .. } | Element::Custom { .. } => {}
+            Element::List { elements, .. } => {
+                for item in elements {
+                    self.inline(asciidoc, &item.element)?;
+                    asciidoc.push(' ');
+                }
+            }
+            Element::DefinitionList { items } => {
+                for item in items {
+                    for child in item.term.iter().chain(&item.definitions) {
+                        self.inline(asciidoc, child)?;
+                        asciidoc.push(' ');
+                    }
+                }
+            }
+            Element::CodeBlock { code, .. } => asciidoc.push_str(&format!("`+{}+`", code)),
+            // Unconstrained marks, so the style may start or end inside a word
+            Element::Styled { style, elements } => {
+                let mut text = String::new();
+                for child in elements {
+                    self.inline(&mut text, child)?;
+                }
+                if style.strikethrough {
+                    text = format!("[.line-through]#{}#", text);
+                }
+                if style.underline {
+                    text = format!("[.underline]#{}#", text);
+                }
+                if style.italic {
+                    text = format!("__{}__", text);
+                }
+                if style.bold {
+                    text = format!("**{}**", text);
+                }
+                asciidoc.push_str(&text);
+            }
+            Element::Math { tex, .. } => {
+                asciidoc.push_str(&format!("latexmath:[{}]", tex.replace(']', "\\]")))
+            }
+            Element::FootnoteReference { id } => asciidoc.push_str(&format!("[{}]", id)),
+            Element::Footnote { id, content } => {
+                asciidoc.push_str(&format!("[{}] ", id));
+                for child in content {
+                    self.inline(asciidoc, child)?;
+                    asciidoc.push(' ');
+                }
+            }
+            Element::Table { .. } | Element::Chart { .. } | Element::Comment { .. } => {}
+        }
+        Ok(())
+    }
+
+    fn save_image(&mut self, image: &ImageData) -> anyhow::Result<Option<String>> {
+        if image.bytes().is_empty() {
+            return Ok(None);
+        }
+        let target = format!(
+            "image{}{}",
+            self.image_num,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &target)?;
+        self.image_num += 1;
+        Ok(Some(target))
+    }
+}
+
+fn image_attributes(image: &ImageData) -> String {
+    let mut attributes = vec![image.alt().replace([',', ']'], " ")];
+    if let Some(width) = &image.size().width {
+        attributes.push(width.clone());
+        if let Some(height) = &image.size().height {
+            attributes.push(height.clone());
+        }
+    }
+    if !image.title().is_empty() {
+        attributes.push(format!("title=\"{}\"", image.title().replace('"', "'")));
+    }
+    attributes.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::asciidoc::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let source = r#"= Guide
+Jane Doe
+:toc:
+
+== Setup
+
+Install the *cli* first,
+see https://example.com/docs[the docs] for details.
+
+NOTE: Requires Rust 1.75.
+
+* One
+** Nested
+* Two
+
+. First
+. Second
+
+[%header,cols="2"]
+|===
+|Name |Value
+|a |1
+|b |2
+|===
+
+image::logo.png[Logo, 200]
+"#;
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |_| {
+            Ok(Bytes::from_static(b"\x89PNG"))
+        })?;
+        let elements = document.get_all_elements();
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 1,
+                text: "Guide".to_string(),
+                anchor: None,
+            }
+        );
+        assert_eq!(
+            elements[1],
+            &Element::Header {
+                level: 2,
+                text: "Setup".to_string(),
+                anchor: None,
+            }
+        );
+        assert_eq!(
+            elements[2],
+            &Element::Paragraph {
+                elements: vec![
+                    text("Install the cli first, see "),
+                    Element::Hyperlink {
+                        title: "the docs".to_string(),
+                        url: "https://example.com/docs".to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    },
+                    text(" for details."),
+                ]
+            }
+        );
+        assert_eq!(
+            elements[3],
+            &Element::Paragraph {
+                elements: vec![text("NOTE: Requires Rust 1.75.")]
+            }
+        );
+        assert_eq!(
+            elements[4],
+            &Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("One"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: Element::List {
+                            elements: vec![ListItem {
+                                element: text("Nested"),
+                                checked: None,
+                            }],
+                            numbered: false,
+                        },
+                        checked: None,
+                    },
+                    ListItem {
+                        element: text("Two"),
+                        checked: None,
+                    },
+                ],
+                numbered: false,
+            }
+        );
+        assert!(matches!(
+            elements[5],
+            Element::List { numbered: true, elements } if elements.len() == 2
+        ));
+        let Element::Table { headers, rows, .. } = elements[6] else {
+            panic!("expected a table, got {:?}", elements[6]);
+        };
+        assert_eq!(headers[0].element, text("Name"));
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].cells[1].elements, vec![text("2")]);
+        let Element::Image(image) = elements[7] else {
+            panic!("expected an image, got {:?}", elements[7]);
+        };
+        assert_eq!(image.alt(), "Logo");
+        assert_eq!(image.size().width.as_deref(), Some("200"));
+        assert_eq!(elements.len(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let images = std::cell::RefCell::new(std::collections::HashMap::new());
+        let generated = Transformer::generate_with_saver(&parsed, |bytes, name| {
+            images.borrow_mut().insert(name.to_string(), bytes.clone());
+            Ok(())
+        })?;
+        let asciidoc = String::from_utf8(generated.to_vec())?;
+        assert!(asciidoc.starts_with("= First header\n\n"));
+        assert!(asciidoc.contains("\n== Second header\n"));
+        assert!(asciidoc.contains("\n. List item 1\n"));
+        assert!(asciidoc.contains("\n.. List item 3\n... List item secode level 1\n"));
+        assert!(asciidoc.contains("\n//-\n\n** List item one\n"));
+        assert!(asciidoc.contains("\n[%header,cols=\"8\"]\n|===\n|Column 1 |Column 2"));
+        assert!(asciidoc.contains("* https://link-url-here.org[Link text Here]"));
+        assert!(asciidoc.contains("\nimage::image0.png[Picture alt1,title=\"Picture title1\"]\n"));
+
+        let reparsed = Transformer::parse_with_loader(&generated, |name| {
+            Ok(images.borrow().get(name).cloned().unwrap_or_default())
+        })?;
+        let original = parsed.get_all_elements();
+        let elements = reparsed.get_all_elements();
+        assert_eq!(original.len(), elements.len());
+        for (original, element) in original.iter().zip(elements.iter()) {
+            assert_eq!(
+                std::mem::discriminant(*original),
+                std::mem::discriminant(*element)
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+//! Conversions for async services, run on the blocking threads of the tokio runtime
+//!
+//! Every transformer implements [`AsyncTransformerTrait`], and those with image loaders
+//! [`AsyncTransformerWithImageLoaderSaverTrait`], so a handler awaits a conversion instead of
+//! wrapping it in `spawn_blocking` itself. Inputs are taken by value to move them to the
+//! blocking thread, and must be awaited inside a tokio runtime.
+use std::future::Future;
+use std::pin::Pin;
+
+use bytes::Bytes;
+use tokio::runtime::Handle;
+use tokio::task;
+
+use crate::core::{Document, TransformerTrait, TransformerWithImageLoaderSaverTrait};
+
+pub trait AsyncTransformerTrait {
+    fn parse(document: Bytes) -> impl Future<Output = anyhow::Result<Document>> + Send;
+    fn generate(document: Document) -> impl Future<Output = anyhow::Result<Bytes>> + Send;
+}
+
+/// Images are loaded and saved by futures, awaited from the blocking thread one at a time as the
+/// parser or generator reaches them
+pub trait AsyncTransformerWithImageLoaderSaverTrait {
+    fn parse_with_loader<F, Fut>(
+        document: Bytes,
+        image_loader: F,
+    ) -> impl Future<Output = anyhow::Result<Document>> + Send
+    where
+        F: Fn(String) -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<Bytes>>;
+    fn generate_with_saver<F, Fut>(
+        document: Document,
+        image_saver: F,
+    ) -> impl Future<Output = anyhow::Result<Bytes>> + Send
+    where
+        F: Fn(Bytes, String) -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<()>>;
+}
+
+impl<T: TransformerTrait + 'static> AsyncTransformerTrait for T {
+    async fn parse(document: Bytes) -> anyhow::Result<Document> {
+        task::spawn_blocking(move || T::parse(&document)).await?
+    }
+
+    async fn generate(document: Document) -> anyhow::Result<Bytes> {
+        task::spawn_blocking(move || T::generate(&document)).await?
+    }
+}
+
+impl<T: TransformerWithImageLoaderSaverTrait + 'static> AsyncTransformerWithImageLoaderSaverTrait
+    for T
+{
+    async fn parse_with_loader<F, Fut>(document: Bytes, image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(String) -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<Bytes>>,
+    {
+        let handle = Handle::current();
+        task::spawn_blocking(move || {
+            T::parse_with_loader(&document, |image| {
+                handle.block_on(image_loader(image.to_string()))
+            })
+        })
+        .await?
+    }
+
+    async fn generate_with_saver<F, Fut>(
+        document: Document,
+        image_saver: F,
+    ) -> anyhow::Result<Bytes>
+    where
+        F: Fn(Bytes, String) -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<()>>,
+    {
+        let handle = Handle::current();
+        task::spawn_blocking(move || {
+            T::generate_with_saver(&document, |bytes, image| {
+                handle.block_on(image_saver(bytes.clone(), image.to_string()))
+            })
+        })
+        .await?
+    }
+}
+
+/// What the image loaders and savers of this module return
+pub type ImageFuture<T> = Pin<Box<dyn Future<Output = anyhow::Result<T>> + Send>>;
+
+/// Loads images from files under `path`, like [`crate::core::disk_image_loader`]
+pub fn disk_image_loader(path: &str) -> impl Fn(String) -> ImageFuture<Bytes> + Send + 'static {
+    let path = path.to_string();
+    move |image| {
+        let image_path = format!("{}/{}", path, image);
+        Box::pin(async move { Ok(Bytes::from(tokio::fs::read(image_path).await?)) })
+    }
+}
+
+/// Saves images to files under `path`, like [`crate::core::disk_image_saver`]
+pub fn disk_image_saver(path: &str) -> impl Fn(Bytes, String) -> ImageFuture<()> + Send + 'static {
+    let path = path.to_string();
+    move |bytes, image| {
+        let image_path = format!("{}/{}", path, image);
+        Box::pin(async move { Ok(tokio::fs::write(image_path, bytes).await?) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        disk_image_loader, disk_image_saver, AsyncTransformerTrait,
+        AsyncTransformerWithImageLoaderSaverTrait,
+    };
+    use crate::core::{DocumentBuilder, Element, TransformerTrait};
+    use bytes::Bytes;
+
+    #[tokio::test]
+    async fn test_parse_generate() -> anyhow::Result<()> {
+        let document = <crate::text::Transformer as AsyncTransformerTrait>::parse(Bytes::from(
+            "First line\nSecond line\n",
+        ))
+        .await?;
+        assert_eq!(
+            document,
+            <crate::text::Transformer as TransformerTrait>::parse(&Bytes::from(
+                "First line\nSecond line\n"
+            ))?
+        );
+
+        let document = DocumentBuilder::new().header(1, "Title").build();
+        let generated =
+            <crate::text::Transformer as AsyncTransformerTrait>::generate(document).await?;
+        assert!(String::from_utf8(generated.to_vec())?.contains("Title"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "markdown")]
+    async fn test_image_loader() -> anyhow::Result<()> {
+        use crate::markdown::Transformer;
+
+        let markdown = Bytes::from("![Picture](picture.png)\n");
+        let document = Transformer::parse_with_loader(markdown.clone(), |image| async move {
+            tokio::task::yield_now().await;
+            Ok(Bytes::from(image.into_bytes()))
+        })
+        .await?;
+        assert!(matches!(
+            document.get_all_elements()[..],
+            [Element::Image(image)] if image.bytes() == "picture.png"
+        ));
+
+        let loaded =
+            Transformer::parse_with_loader(markdown, disk_image_loader("test/data")).await?;
+        let directory = std::env::temp_dir().join("shiva_async_images");
+        std::fs::create_dir_all(&directory)?;
+        let saver = disk_image_saver(&directory.to_string_lossy());
+        let generated = Transformer::generate_with_saver(loaded, saver).await?;
+        assert!(!generated.is_empty());
+        assert!(std::fs::read_dir(&directory)?.next().is_some());
+        Ok(())
+    }
+}
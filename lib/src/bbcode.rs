@@ -0,0 +1,1032 @@
+use crate::core::*;
+use bytes::Bytes;
+use std::collections::HashMap;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Blank lines separate paragraphs. `[list]`, `[quote]`, `[code]` and `[table]` are
+    /// blocks, text formatting tags are dropped and their text is kept. A paragraph that is
+    /// only `[size=N][b]...[/b][/size]` is read back as a header. Unknown tags stay text.
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let source = String::from_utf8(document.to_vec())?.replace("\r\n", "\n");
+        let nodes = parse_nodes(&source);
+        let parser = Parser {
+            image_loader: &image_loader,
+        };
+        let mut elements = Vec::new();
+        parser.parse_blocks(&nodes, &mut elements)?;
+        Ok(Document::new(elements))
+    }
+
+    /// Headers are written as bold text in a larger size, tables use the `[table]`, `[tr]`,
+    /// `[th]` and `[td]` tags most forums support. Images are written through `image_saver`.
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        // A table of contents is written as the list of its headers
+        let document = &document.expand_toc();
+        let mut generator = Generator {
+            image_saver,
+            image_num: 0,
+        };
+        let mut bbcode = String::new();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                generator.block(&mut bbcode, element)?;
+            }
+        }
+        Ok(Bytes::from(bbcode.trim_end().to_string() + "\n"))
+    }
+}
+
+/// Tags that are understood, anything else in brackets is text
+const TAGS: [&str; 20] = [
+    "b", "i", "u", "s", "url", "img", "list", "*", "quote", "code", "size", "color", "font",
+    "center", "left", "right", "table", "tr", "th", "td",
+];
+
+/// Tags that start a block of their own
+const BLOCK_TAGS: [&str; 4] = ["list", "quote", "code", "table"];
+
+/// `[size=N]` of headers by level, in percent
+const HEADER_SIZES: [u32; 6] = [200, 170, 150, 130, 115, 100];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Tag {
+        name: String,
+        argument: Option<String>,
+        attributes: HashMap<String, String>,
+        children: Vec<Node>,
+    },
+}
+
+struct Tag {
+    name: String,
+    argument: Option<String>,
+    attributes: HashMap<String, String>,
+}
+
+/// `[name]`, `[name=argument]` and `[name key=value ...]`, the bool is true for closing tags
+fn parse_tag(inner: &str) -> Option<(bool, Tag)> {
+    if let Some(name) = inner.strip_prefix('/') {
+        let name = name.trim().to_lowercase();
+        return TAGS.contains(&name.as_str()).then(|| {
+            (
+                true,
+                Tag {
+                    name,
+                    argument: None,
+                    attributes: HashMap::new(),
+                },
+            )
+        });
+    }
+    let end = inner.find(['=', ' ']).unwrap_or(inner.len());
+    let name = inner[..end].to_lowercase();
+    if !TAGS.contains(&name.as_str()) {
+        return None;
+    }
+    let rest = &inner[end..];
+    let mut argument = None;
+    let mut attributes = HashMap::new();
+    if let Some(value) = rest.strip_prefix('=') {
+        argument = Some(unquote(value).to_string());
+    } else {
+        attributes = parse_attributes(rest);
+    }
+    Some((
+        false,
+        Tag {
+            name,
+            argument,
+            attributes,
+        },
+    ))
+}
+
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// ` width=100 alt="Some text"` attributes
+fn parse_attributes(text: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = text.trim_start();
+    while let Some(equals) = rest.find('=') {
+        let key = rest[..equals].trim().to_lowercase();
+        let value = &rest[equals + 1..];
+        let (value, next) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or_default())
+            }
+            None => {
+                let end = value.find(' ').unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+        };
+        attributes.insert(key, value.to_string());
+        rest = next.trim_start();
+    }
+    attributes
+}
+
+/// Builds the tag tree. Unclosed tags end with their parent, `[code]` content is not parsed.
+fn parse_nodes(source: &str) -> Vec<Node> {
+    let mut stack: Vec<(Tag, Vec<Node>)> = vec![(
+        Tag {
+            name: String::new(),
+            argument: None,
+            attributes: HashMap::new(),
+        },
+        Vec::new(),
+    )];
+    let mut rest = source;
+    while let Some(start) = rest.find('[') {
+        push_node_text(&mut stack, &rest[..start]);
+        rest = &rest[start..];
+        let tag = rest
+            .find(']')
+            .and_then(|end| parse_tag(&rest[1..end]).map(|(closing, tag)| (end + 1, closing, tag)));
+        let Some((length, closing, tag)) = tag else {
+            push_node_text(&mut stack, "[");
+            rest = &rest[1..];
+            continue;
+        };
+        let source_tag = &rest[..length];
+        rest = &rest[length..];
+
+        if closing {
+            let open = stack
+                .iter()
+                .skip(1)
+                .rposition(|(open, _)| open.name == tag.name);
+            match open {
+                Some(index) => {
+                    while stack.len() > index + 1 {
+                        close_node(&mut stack);
+                    }
+                }
+                // `[/*]` closes list items, which close on their own
+                None if tag.name == "*" => {}
+                None => push_node_text(&mut stack, source_tag),
+            }
+            continue;
+        }
+        match tag.name.as_str() {
+            "code" => {
+                let end = rest.to_ascii_lowercase().find("[/code]");
+                let content = &rest[..end.unwrap_or(rest.len())];
+                rest = &rest[end.map(|end| end + "[/code]".len()).unwrap_or(rest.len())..];
+                push_child(
+                    &mut stack,
+                    Node::Tag {
+                        name: tag.name,
+                        argument: tag.argument,
+                        attributes: tag.attributes,
+                        children: vec![Node::Text(content.to_string())],
+                    },
+                );
+            }
+            // List item markers are leaves, items run up to the next marker
+            "*" => push_child(
+                &mut stack,
+                Node::Tag {
+                    name: tag.name,
+                    argument: None,
+                    attributes: HashMap::new(),
+                    children: Vec::new(),
+                },
+            ),
+            _ => stack.push((tag, Vec::new())),
+        }
+    }
+    push_node_text(&mut stack, rest);
+    while stack.len() > 1 {
+        close_node(&mut stack);
+    }
+    stack.pop().map(|(_, nodes)| nodes).unwrap_or_default()
+}
+
+fn push_child(stack: &mut [(Tag, Vec<Node>)], node: Node) {
+    if let Some((_, children)) = stack.last_mut() {
+        children.push(node);
+    }
+}
+
+fn push_node_text(stack: &mut [(Tag, Vec<Node>)], text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some((_, children)) = stack.last_mut() {
+        match children.last_mut() {
+            Some(Node::Text(last)) => last.push_str(text),
+            _ => children.push(Node::Text(text.to_string())),
+        }
+    }
+}
+
+fn close_node(stack: &mut Vec<(Tag, Vec<Node>)>) {
+    if let Some((tag, children)) = stack.pop() {
+        push_child(
+            stack,
+            Node::Tag {
+                name: tag.name,
+                argument: tag.argument,
+                attributes: tag.attributes,
+                children,
+            },
+        );
+    }
+}
+
+fn plain_text(nodes: &[Node]) -> String {
+    let mut text = String::new();
+    for node in nodes {
+        match node {
+            Node::Text(value) => text.push_str(value),
+            Node::Tag { children, .. } => text.push_str(&plain_text(children)),
+        }
+    }
+    text
+}
+
+fn is_blank(node: &Node) -> bool {
+    matches!(node, Node::Text(text) if text.trim().is_empty())
+}
+
+/// Strips whitespace around the first and last text nodes and drops blank ones
+fn trim_nodes(mut nodes: Vec<Node>) -> Vec<Node> {
+    while nodes.first().is_some_and(is_blank) {
+        nodes.remove(0);
+    }
+    while nodes.last().is_some_and(is_blank) {
+        nodes.pop();
+    }
+    if let Some(Node::Text(text)) = nodes.first_mut() {
+        *text = text.trim_start().to_string();
+    }
+    if let Some(Node::Text(text)) = nodes.last_mut() {
+        *text = text.trim_end().to_string();
+    }
+    nodes
+}
+
+/// `[size=N][b]Title[/b][/size]` as written for headers
+fn header(nodes: &[Node]) -> Option<Element> {
+    let [Node::Tag {
+        name,
+        argument: Some(size),
+        children,
+        ..
+    }] = nodes
+    else {
+        return None;
+    };
+    let size: u32 = size.trim().parse().ok()?;
+    if name != "size" || size < HEADER_SIZES[HEADER_SIZES.len() - 1] {
+        return None;
+    }
+    let children: Vec<&Node> = children.iter().filter(|node| !is_blank(node)).collect();
+    let [Node::Tag { name, children, .. }] = children.as_slice() else {
+        return None;
+    };
+    if name != "b" {
+        return None;
+    }
+    let level = HEADER_SIZES
+        .iter()
+        .position(|header_size| size >= *header_size)
+        .unwrap_or(HEADER_SIZES.len() - 1);
+    Some(Element::Header {
+        level: level as u8 + 1,
+        text: plain_text(children).trim().to_string(),
+        anchor: None,
+    })
+}
+
+/// Image type of an image address, unknown extensions fall back to PNG
+fn image_type(source: &str) -> &'static str {
+    let path = source.split(['?', '#']).next().unwrap_or_default();
+    let extension = path.rsplit('.').next().unwrap_or_default().to_lowercase();
+    match extension.as_str() {
+        "jpg" | "jpeg" => "jpeg",
+        "gif" => "gif",
+        "svg" => "svg",
+        _ => "png",
+    }
+}
+
+fn push_text(elements: &mut Vec<Element>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(Element::Text { text: last, .. }) = elements.last_mut() {
+        last.push_str(text);
+    } else {
+        elements.push(Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        });
+    }
+}
+
+/// A single element stays as it is, several are wrapped in a paragraph
+fn single(mut elements: Vec<Element>) -> Element {
+    match elements.len() {
+        0 => Element::Text {
+            text: String::new(),
+            font: FontSpec::from(8),
+        },
+        1 => elements.remove(0),
+        _ => Element::Paragraph { elements },
+    }
+}
+
+struct Parser<'a, F> {
+    image_loader: &'a F,
+}
+
+impl<'a, F> Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    fn parse_blocks(&self, nodes: &[Node], elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        let mut paragraph = Vec::new();
+        for node in nodes {
+            match node {
+                Node::Text(text) => {
+                    for (index, part) in text.split("\n\n").enumerate() {
+                        if index > 0 {
+                            self.flush_paragraph(&mut paragraph, elements)?;
+                        }
+                        if !part.is_empty() {
+                            paragraph.push(Node::Text(part.to_string()));
+                        }
+                    }
+                }
+                Node::Tag { name, .. } if BLOCK_TAGS.contains(&name.as_str()) => {
+                    self.flush_paragraph(&mut paragraph, elements)?;
+                    self.parse_block(node, elements)?;
+                }
+                node => paragraph.push(node.clone()),
+            }
+        }
+        self.flush_paragraph(&mut paragraph, elements)
+    }
+
+    fn flush_paragraph(
+        &self,
+        paragraph: &mut Vec<Node>,
+        elements: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        let nodes = trim_nodes(std::mem::take(paragraph));
+        if nodes.is_empty() {
+            return Ok(());
+        }
+        if let Some(header) = header(&nodes) {
+            elements.push(header);
+            return Ok(());
+        }
+        let mut inline = Vec::new();
+        for node in &nodes {
+            self.parse_inline(node, &mut inline)?;
+        }
+        match inline.as_slice() {
+            [] => {}
+            // A paragraph with only an image is a standalone figure
+            [Element::Image(_)] => elements.append(&mut inline),
+            _ => elements.push(Element::Paragraph { elements: inline }),
+        }
+        Ok(())
+    }
+
+    fn parse_block(&self, node: &Node, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        let Node::Tag {
+            name,
+            argument,
+            children,
+            ..
+        } = node
+        else {
+            return Ok(());
+        };
+        match name.as_str() {
+            "list" => {
+                // `[list=1]`, `[list=a]` and the like are numbered
+                let numbered = argument.is_some();
+                elements.push(self.parse_list(children, numbered)?);
+            }
+            "code" => {
+                let text = plain_text(children);
+                let text = text.strip_prefix('\n').unwrap_or(&text);
+                elements.push(Element::Text {
+                    text: text.trim_end().to_string(),
+                    font: FontSpec::from(8),
+                });
+            }
+            "table" => elements.push(self.parse_table(children)?),
+            // Quotes have no element of their own, their content is kept
+            _ => self.parse_blocks(children, elements)?,
+        }
+        Ok(())
+    }
+
+    /// Items run from one `[*]` to the next, nested lists follow their parent item
+    fn parse_list(&self, nodes: &[Node], numbered: bool) -> anyhow::Result<Element> {
+        let mut items: Vec<Vec<Node>> = Vec::new();
+        for node in nodes {
+            match (node, items.last_mut()) {
+                (Node::Tag { name, .. }, _) if name == "*" => items.push(Vec::new()),
+                (node, Some(item)) => item.push(node.clone()),
+                // Text before the first marker
+                (_, None) => {}
+            }
+        }
+
+        let mut elements = Vec::new();
+        for item in items {
+            let mut inline = Vec::new();
+            let mut nested = Vec::new();
+            for node in trim_nodes(item) {
+                match &node {
+                    Node::Tag {
+                        name,
+                        argument,
+                        children,
+                        ..
+                    } if name == "list" => {
+                        nested.push(self.parse_list(children, argument.is_some())?)
+                    }
+                    node => self.parse_inline(node, &mut inline)?,
+                }
+            }
+            while let Some(Element::LineBreak) = inline.last() {
+                inline.pop();
+            }
+            if let Some(Element::Text { text, .. }) = inline.last_mut() {
+                *text = text.trim_end().to_string();
+            }
+            elements.push(ListItem {
+                element: single(inline),
+                checked: None,
+            });
+            elements.extend(nested.into_iter().map(|element| ListItem {
+                element,
+                checked: None,
+            }));
+        }
+        Ok(Element::List { elements, numbered })
+    }
+
+    /// `[th]` cells in the first row are the header
+    fn parse_table(&self, nodes: &[Node]) -> anyhow::Result<Element> {
+        let mut headers = Vec::new();
+        let mut rows = Vec::new();
+        for node in nodes {
+            let Node::Tag { name, children, .. } = node else {
+                continue;
+            };
+            if name != "tr" {
+                continue;
+            }
+            let mut cells = Vec::new();
+            let mut header_row = true;
+            for cell in children {
+                let Node::Tag { name, children, .. } = cell else {
+                    continue;
+                };
+                if name != "th" && name != "td" {
+                    continue;
+                }
+                header_row &= name == "th";
+                let mut inline = Vec::new();
+                for node in trim_nodes(children.clone()) {
+                    self.parse_inline(&node, &mut inline)?;
+                }
+                cells.push(single(inline));
+            }
+            if header_row && headers.is_empty() && rows.is_empty() && !cells.is_empty() {
+                headers = cells
+                    .into_iter()
+                    .map(|element| TableHeader {
+                        element,
+                        width: 30.0,
+                    })
+                    .collect();
+            } else {
+                rows.push(TableRow {
+                    cells: cells.into_iter().map(TableCell::new).collect(),
+                });
+            }
+        }
+        Ok(Element::Table {
+            headers,
+            rows,
+            alignments: vec![],
+            caption: vec![],
+        })
+    }
+
+    fn parse_inline(&self, node: &Node, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        let (name, argument, attributes, children) = match node {
+            Node::Text(text) => {
+                for (index, line) in text.split('\n').enumerate() {
+                    if index > 0 {
+                        elements.push(Element::LineBreak);
+                    }
+                    push_text(elements, line);
+                }
+                return Ok(());
+            }
+            Node::Tag {
+                name,
+                argument,
+                attributes,
+                children,
+            } => (name, argument, attributes, children),
+        };
+        match name.as_str() {
+            "url" => {
+                let title = plain_text(children).trim().to_string();
+                let url = argument.clone().unwrap_or_else(|| title.clone());
+                elements.push(Element::Hyperlink {
+                    title,
+                    url,
+                    alt: String::new(),
+                    size: 8,
+                });
+            }
+            "img" => {
+                let source = plain_text(children).trim().to_string();
+                let mut size = ImageDimension::default();
+                // `[img=100x50]` or `[img width=100 height=50]`
+                let (width, height) = match argument {
+                    Some(argument) => argument
+                        .split_once('x')
+                        .map(|(width, height)| (Some(width), Some(height)))
+                        .unwrap_or((Some(argument.as_str()), None)),
+                    None => (
+                        attributes.get("width").map(String::as_str),
+                        attributes.get("height").map(String::as_str),
+                    ),
+                };
+                let pixels = |value: &str| format!("{}px", value.trim().trim_end_matches("px"));
+                size.width = width.filter(|width| !width.is_empty()).map(pixels);
+                size.height = height.filter(|height| !height.is_empty()).map(pixels);
+                let bytes = (self.image_loader)(&source)?;
+                elements.push(Element::Image(ImageData::new(
+                    bytes,
+                    attributes.get("title").cloned().unwrap_or_default(),
+                    attributes.get("alt").cloned().unwrap_or_default(),
+                    image_type(&source).to_string(),
+                    String::new(),
+                    size,
+                )));
+            }
+            "code" => push_text(elements, &plain_text(children)),
+            // Lists, quotes and tables inside items and cells are flattened to text
+            "list" | "quote" | "table" | "tr" => {
+                let mut blocks = Vec::new();
+                self.parse_blocks(children, &mut blocks)?;
+                for block in blocks {
+                    match block {
+                        Element::Paragraph { elements: inline } => elements.extend(inline),
+                        Element::List {
+                            elements: items, ..
+                        } => {
+                            for item in items {
+                                elements.push(item.element);
+                            }
+                        }
+                        block => elements.push(block),
+                    }
+                }
+            }
+            _ => {
+                for child in children {
+                    self.parse_inline(child, elements)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Generator<F> {
+    image_saver: F,
+    image_num: usize,
+}
+
+impl<F> Generator<F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn block(&mut self, bbcode: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Header { level, text, .. } => {
+                let size = HEADER_SIZES[(*level).clamp(1, 6) as usize - 1];
+                bbcode.push_str(&format!(
+                    "[size={}][b]{}[/b][/size]\n\n",
+                    size,
+                    text.replace('\n', " ")
+                ));
+            }
+            // Multi-line text keeps its layout in a code block
+            Element::Text { text, .. } if text.contains('\n') => {
+                bbcode.push_str(&format!("[code]{}[/code]\n\n", text));
+            }
+            Element::List { elements, numbered } => {
+                self.list(bbcode, elements, *numbered)?;
+                bbcode.push('\n');
+            }
+            Element::Chart { .. } => self.block(bbcode, &element.chart_fallback())?,
+            Element::Table { headers, rows, .. } => {
+                self.table(bbcode, headers, rows)?;
+                bbcode.push('\n');
+            }
+            Element::HorizontalRule => bbcode.push_str("[hr]\n\n"),
+            // BBCode has no definition lists, bold terms have their definitions on the lines below
+            Element::DefinitionList { items } => {
+                for item in items {
+                    let mut term = String::new();
+                    for child in &item.term {
+                        term.push_str(&self.inline(child)?);
+                    }
+                    bbcode.push_str(&format!("[b]{}[/b]\n", term.trim()));
+                    for definition in &item.definitions {
+                        bbcode.push_str(self.inline(definition)?.trim());
+                        bbcode.push('\n');
+                    }
+                }
+                bbcode.push('\n');
+            }
+            Element::CodeBlock { language, code } => match language {
+                Some(language) => {
+                    bbcode.push_str(&format!("[code={}]{}[/code]\n\n", language, code))
+                }
+                None => bbcode.push_str(&format!("[code]{}[/code]\n\n", code)),
+            },
+            Element::Blockquote { elements, .. } => {
+                let mut quote = String::new();
+                for child in elements {
+                    self.block(&mut quote, child)?;
+                }
+                bbcode.push_str(&format!("[quote]{}[/quote]\n\n", quote.trim_end()));
+            }
+            Element::Section { children, .. } => {
+                for child in children {
+                    self.block(bbcode, child)?;
+                }
+            }
+            // BBCode has no comments or pages
+            Element::Comment { .. } | Element::PageBreak | Element::TableOfContents { .. } => {}
+            element => {
+                let text = self.inline(element)?;
+                if !text.trim().is_empty() {
+                    bbcode.push_str(text.trim());
+                    bbcode.push_str("\n\n");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn list(
+        &mut self,
+        bbcode: &mut String,
+        items: &[ListItem],
+        numbered: bool,
+    ) -> anyhow::Result<()> {
+        bbcode.push_str(if numbered { "[list=1]\n" } else { "[list]\n" });
+        for item in items {
+            match &item.element {
+                Element::List { elements, numbered } => self.list(bbcode, elements, *numbered)?,
+                element => {
+                    let text = self.inline(element)?;
+                    bbcode.push_str(&format!("[*]{}\n", text.trim()));
+                }
+            }
+        }
+        bbcode.push_str("[/list]\n");
+        Ok(())
+    }
+
+    fn table(
+        &mut self,
+        bbcode: &mut String,
+        headers: &[TableHeader],
+        rows: &[TableRow],
+    ) -> anyhow::Result<()> {
+        bbcode.push_str("[table]\n");
+        if !headers.is_empty() {
+            bbcode.push_str("[tr]");
+            for header in headers {
+                let text = self.inline(&header.element)?;
+                bbcode.push_str(&format!("[th]{}[/th]", text.trim()));
+            }
+            bbcode.push_str("[/tr]\n");
+        }
+        for row in rows {
+            bbcode.push_str("[tr]");
+            for cell in &row.cells {
+                let text = self.inline(&cell.element())?;
+                bbcode.push_str(&format!("[td]{}[/td]", text.trim()));
+            }
+            bbcode.push_str("[/tr]\n");
+        }
+        bbcode.push_str("[/table]\n");
+        Ok(())
+    }
+
+    fn inline(&mut self, element: &Element) -> anyhow::Result<String> {
+        Ok(match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => text.clone(),
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => {
+                let mut text = String::new();
+                for child in elements {
+                    text.push_str(&self.inline(child)?);
+                }
+                text
+            }
+            Element::Hyperlink { title, url, .. } => {
+                if title.is_empty() || title == url {
+                    format!("[url]{}[/url]", url)
+                } else {
+                    format!("[url={}]{}[/url]", url, title)
+                }
+            }
+            Element::Image(image) => self.image(image)?.unwrap_or_default(),
+            // BBCode has no figures, the caption is set in italics below the image
+            Element::Figure { image, caption } => {
+                let mut text = self.image(image)?.unwrap_or_default();
+                let mut caption_text = String::new();
+                for child in caption {
+                    caption_text.push_str(&self.inline(child)?);
+                }
+                if !caption_text.trim().is_empty() {
+                    if !text.is_empty() {
+                        text.push('\n');
+                    }
+                    text.push_str(&format!("[i]{}[/i]", caption_text.trim()));
+                }
+                text
+            }
+            Element::LineBreak => "\n".to_string(),
+            Element::HorizontalRule => "\n[hr]\n".to_string(),
+            Element::List { elements, .. } => {
+                let mut items = Vec::new();
+                for item in elements {
+                    items.push(self.inline(&item.element)?);
+                }
+                items.join(" ")
+            }
+            Element::DefinitionList { items } => {
+                let mut parts = Vec::new();
+                for item in items {
+                    for child in item.term.iter().chain(&item.definitions) {
+                        parts.push(self.inline(child)?);
+                    }
+                }
+                parts.join(" ")
+            }
+            Element::CodeBlock { code, .. } => format!("[code]{}[/code]", code),
+            Element::Styled { style, elements } => {
+                let mut text = String::new();
+                for child in elements {
+                    text.push_str(&self.inline(child)?);
+                }
+                for (enabled, tag) in [
+                    (style.strikethrough, "s"),
+                    (style.underline, "u"),
+                    (style.italic, "i"),
+                    (style.bold, "b"),
+                ] {
+                    if enabled {
+                        text = format!("[{}]{}[/{}]", tag, text, tag);
+                    }
+                }
+                text
+            }
+            // BBCode has no formulas, the TeX source is kept
+            Element::Math { tex, .. } => tex.clone(),
+            Element::FootnoteReference { id } => format!("[sup]{}[/sup]", id),
+            Element::Footnote { id, content } => {
+                let mut text = format!("[sup]{}[/sup] ", id);
+                for child in content {
+                    text.push_str(&self.inline(child)?);
+                }
+                text
+            }
+            Element::Media { .. } => self.inline(&element.media_fallback())?,
+            Element::Ruby { .. } => self.inline(&element.ruby_fallback())?,
+            Element::Table { .. }
+            | Element::Chart { .. }
+            | Element::Comment { .. }
+            | Element::PageBreak
+            | Element::TableOfContents { .. }
+            | Element::Custom { .. } => String::new(),
+        })
+    }
+
+    /// Saves the image and returns the `[img]` tag for it
+    fn image(&mut self, image: &ImageData) -> anyhow::Result<Option<String>> {
+        if image.bytes().is_empty() {
+            return Ok(None);
+        }
+        let target = format!(
+            "image{}{}",
+            self.image_num,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &target)?;
+        self.image_num += 1;
+
+        let mut tag = String::from("[img");
+        let size = image.size();
+        for (attribute, value) in [("width", &size.width), ("height", &size.height)] {
+            let pixels = value
+                .as_deref()
+                .map(|value| value.trim_end_matches("px"))
+                .filter(|value| !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()));
+            if let Some(pixels) = pixels {
+                tag.push_str(&format!(" {}={}", attribute, pixels));
+            }
+        }
+        for (attribute, value) in [("alt", image.alt()), ("title", image.title())] {
+            if !value.is_empty() {
+                tag.push_str(&format!(
+                    " {}=\"{}\"",
+                    attribute,
+                    value.replace(['"', ']'], "")
+                ));
+            }
+        }
+        tag.push_str(&format!("]{}[/img]", target));
+        Ok(Some(tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bbcode::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let source = r#"[size=150][b]Patch notes[/b][/size]
+
+Hello [b]all[/b], see [url=https://example.com/forum]the [i]forum[/i][/url] or [url]https://example.com[/url].
+Second line [unknown]kept[/unknown]
+
+[list]
+[*]First
+[*]Second
+[list=1][*]Nested[/list]
+[/list]
+[quote=admin]Quoted text[/quote]
+[code]
+fn main() {}
+[/code]
+[img width=100 alt="Chart"]https://example.com/chart.jpg?v=2[/img]
+[table][tr][th]Name[/th][th]Value[/th][/tr][tr][td]a[/td][td]1[/td][/tr][/table]
+"#;
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |_| {
+            Ok(Bytes::from_static(b"\xff\xd8\xff"))
+        })?;
+        let elements = document.get_all_elements();
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let link = |title: &str, url: &str| Element::Hyperlink {
+            title: title.to_string(),
+            url: url.to_string(),
+            alt: String::new(),
+            size: 8,
+        };
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 3,
+                text: "Patch notes".to_string(),
+                anchor: None,
+            }
+        );
+        assert_eq!(
+            elements[1],
+            &Element::Paragraph {
+                elements: vec![
+                    text("Hello all, see "),
+                    link("the forum", "https://example.com/forum"),
+                    text(" or "),
+                    link("https://example.com", "https://example.com"),
+                    text("."),
+                    Element::LineBreak,
+                    text("Second line [unknown]kept[/unknown]"),
+                ]
+            }
+        );
+        assert_eq!(
+            elements[2],
+            &Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("First"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: text("Second"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: Element::List {
+                            elements: vec![ListItem {
+                                element: text("Nested"),
+                                checked: None,
+                            }],
+                            numbered: true,
+                        },
+                        checked: None,
+                    },
+                ],
+                numbered: false,
+            }
+        );
+        assert_eq!(
+            elements[3],
+            &Element::Paragraph {
+                elements: vec![text("Quoted text")]
+            }
+        );
+        assert_eq!(elements[4], &text("fn main() {}"));
+        let Element::Image(image) = elements[5] else {
+            panic!("expected an image, got {:?}", elements[5]);
+        };
+        assert_eq!(image.image_type(), &ImageType::Jpeg);
+        assert_eq!(image.alt(), "Chart");
+        assert_eq!(image.size().width.as_deref(), Some("100px"));
+        let Element::Table { headers, rows, .. } = elements[6] else {
+            panic!("expected a table, got {:?}", elements[6]);
+        };
+        assert_eq!(headers[1].element, text("Value"));
+        assert_eq!(rows[0].cells[1].elements, vec![text("1")]);
+        assert_eq!(elements.len(), 7);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let images = RefCell::new(HashMap::new());
+        let generated = Transformer::generate_with_saver(&parsed, |bytes, name| {
+            images.borrow_mut().insert(name.to_string(), bytes.clone());
+            Ok(())
+        })?;
+        let reparsed = Transformer::parse_with_loader(&generated, |name| {
+            Ok(images.borrow().get(name).cloned().unwrap_or_default())
+        })?;
+        let original = parsed.get_all_elements();
+        let elements = reparsed.get_all_elements();
+        assert_eq!(original.len(), elements.len());
+        for (original, element) in original.iter().zip(elements.iter()) {
+            assert_eq!(
+                std::mem::discriminant(*original),
+                std::mem::discriminant(*element)
+            );
+        }
+        Ok(())
+    }
+}
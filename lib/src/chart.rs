@@ -0,0 +1,352 @@
+//! Charts drawn as SVG for the formats that show them as pictures, and read from the DrawingML
+//! chart parts of office packages
+use crate::core::*;
+
+#[cfg(any(feature = "html", feature = "typst"))]
+const WIDTH: f64 = 480.0;
+#[cfg(any(feature = "html", feature = "typst"))]
+const HEIGHT: f64 = 300.0;
+/// Room for the legend above the plot and the category labels below it
+#[cfg(any(feature = "html", feature = "typst"))]
+const TOP: f64 = 30.0;
+#[cfg(any(feature = "html", feature = "typst"))]
+const BOTTOM: f64 = HEIGHT - 30.0;
+#[cfg(any(feature = "html", feature = "typst"))]
+const LEFT: f64 = 50.0;
+#[cfg(any(feature = "html", feature = "typst"))]
+const RIGHT: f64 = WIDTH - 20.0;
+/// Series colors, repeated when there are more series
+#[cfg(any(feature = "html", feature = "typst"))]
+const COLORS: [&str; 6] = [
+    "#4472c4", "#ed7d31", "#a5a5a5", "#ffc000", "#5b9bd5", "#70ad47",
+];
+
+#[cfg(any(feature = "html", feature = "typst"))]
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(any(feature = "html", feature = "typst"))]
+fn color(index: usize) -> &'static str {
+    COLORS[index % COLORS.len()]
+}
+
+/// The chart as a standalone SVG image
+#[cfg(any(feature = "html", feature = "typst"))]
+pub(crate) fn svg(chart_type: ChartType, series: &[ChartSeries], categories: &[String]) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+         viewBox=\"0 0 {0} {1}\" font-family=\"sans-serif\" font-size=\"10\">",
+        WIDTH, HEIGHT
+    );
+    match chart_type {
+        ChartType::Pie => {
+            legend(&mut svg, categories.iter().map(String::as_str));
+            if let Some(series) = series.first() {
+                pie(&mut svg, &series.values);
+            }
+        }
+        _ => {
+            legend(&mut svg, series.iter().map(|series| series.name.as_str()));
+            plot(&mut svg, chart_type, series, categories);
+        }
+    }
+    svg.push_str("</svg>");
+    svg
+}
+
+/// A colored mark with every name, in a row above the plot
+#[cfg(any(feature = "html", feature = "typst"))]
+fn legend<'a>(svg: &mut String, names: impl Iterator<Item = &'a str>) {
+    for (index, name) in names.enumerate() {
+        let x = LEFT + index as f64 * 80.0;
+        svg.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"8\" width=\"10\" height=\"10\" fill=\"{}\"/>\
+             <text x=\"{:.1}\" y=\"17\">{}</text>",
+            x,
+            color(index),
+            x + 14.0,
+            escape(name)
+        ));
+    }
+}
+
+/// A slice for every positive value, clockwise from the top
+#[cfg(any(feature = "html", feature = "typst"))]
+fn pie(svg: &mut String, values: &[f64]) {
+    let (cx, cy, radius) = (
+        WIDTH / 2.0,
+        (TOP + HEIGHT) / 2.0,
+        (HEIGHT - TOP) / 2.0 - 10.0,
+    );
+    let total: f64 = values.iter().filter(|value| **value > 0.0).sum();
+    if total <= 0.0 {
+        return;
+    }
+    let point = |angle: f64| (cx + radius * angle.sin(), cy - radius * angle.cos());
+    let mut start = 0.0;
+    for (index, value) in values.iter().enumerate() {
+        if *value <= 0.0 {
+            continue;
+        }
+        let sweep = value / total * std::f64::consts::TAU;
+        if sweep >= std::f64::consts::TAU - f64::EPSILON {
+            svg.push_str(&format!(
+                "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\"/>",
+                cx,
+                cy,
+                radius,
+                color(index)
+            ));
+            return;
+        }
+        let (x1, y1) = point(start);
+        let (x2, y2) = point(start + sweep);
+        svg.push_str(&format!(
+            "<path d=\"M {:.1} {:.1} L {:.1} {:.1} A {:.1} {:.1} 0 {} 1 {:.1} {:.1} Z\" \
+             fill=\"{}\"/>",
+            cx,
+            cy,
+            x1,
+            y1,
+            radius,
+            radius,
+            u8::from(sweep > std::f64::consts::PI),
+            x2,
+            y2,
+            color(index)
+        ));
+        start += sweep;
+    }
+}
+
+/// Bars, lines and areas over a value axis that includes zero
+#[cfg(any(feature = "html", feature = "typst"))]
+fn plot(svg: &mut String, chart_type: ChartType, series: &[ChartSeries], categories: &[String]) {
+    let values = series
+        .iter()
+        .flat_map(|series| series.values.iter().copied());
+    let (min, max) = values.fold((0.0f64, 0.0f64), |(min, max), value| {
+        (min.min(value), max.max(value))
+    });
+    let max = if max > min { max } else { min + 1.0 };
+    let y = |value: f64| BOTTOM - (value - min) / (max - min) * (BOTTOM - TOP);
+    let points = series
+        .iter()
+        .map(|series| series.values.len())
+        .chain([categories.len()])
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let band = (RIGHT - LEFT) / points as f64;
+    let center = |index: usize| LEFT + band * (index as f64 + 0.5);
+
+    // The axes, with the bounds of the values and a label under every category
+    svg.push_str(&format!(
+        "<line x1=\"{0}\" y1=\"{1}\" x2=\"{0}\" y2=\"{2}\" stroke=\"#000\"/>\
+         <line x1=\"{0}\" y1=\"{3:.1}\" x2=\"{4}\" y2=\"{3:.1}\" stroke=\"#000\"/>",
+        LEFT,
+        TOP,
+        BOTTOM,
+        y(0.0),
+        RIGHT
+    ));
+    for value in [min, max] {
+        svg.push_str(&format!(
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\">{}</text>",
+            LEFT - 4.0,
+            y(value) + 3.0,
+            value
+        ));
+    }
+    for (index, category) in categories.iter().enumerate() {
+        svg.push_str(&format!(
+            "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\">{}</text>",
+            center(index),
+            BOTTOM + 14.0,
+            escape(category)
+        ));
+    }
+
+    // The bars of a category share 80% of its band
+    let width = band * 0.8 / series.len().max(1) as f64;
+    for (number, series) in series.iter().enumerate() {
+        let line: Vec<String> = series
+            .values
+            .iter()
+            .enumerate()
+            .map(|(index, value)| format!("{:.1},{:.1}", center(index), y(*value)))
+            .collect();
+        match chart_type {
+            ChartType::Bar => {
+                for (index, value) in series.values.iter().enumerate() {
+                    let x = LEFT + band * (index as f64 + 0.1) + width * number as f64;
+                    let (top, bottom) = (y(value.max(0.0)), y(value.min(0.0)));
+                    svg.push_str(&format!(
+                        "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" \
+                         fill=\"{}\"/>",
+                        x,
+                        top,
+                        width,
+                        bottom - top,
+                        color(number)
+                    ));
+                }
+            }
+            // The area is closed along the zero line
+            ChartType::Area if !line.is_empty() => {
+                let last = center(line.len() - 1);
+                svg.push_str(&format!(
+                    "<polygon points=\"{first:.1},{zero:.1} {line} {last:.1},{zero:.1}\" \
+                     fill=\"{color}\" fill-opacity=\"0.5\" stroke=\"{color}\"/>",
+                    first = center(0),
+                    zero = y(0.0),
+                    line = line.join(" "),
+                    last = last,
+                    color = color(number)
+                ));
+            }
+            _ => svg.push_str(&format!(
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>",
+                line.join(" "),
+                color(number)
+            )),
+        }
+    }
+}
+
+/// The chart of a DrawingML chart part, `None` when it has no series. Only the first plot of
+/// the part is read, its values come from the caches the part keeps of its data
+#[cfg(any(feature = "docx", feature = "xlsx"))]
+pub(crate) fn parse(xml: &[u8]) -> anyhow::Result<Option<Element>> {
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    #[derive(PartialEq)]
+    enum Part {
+        Name,
+        Category,
+        Value,
+    }
+
+    let mut reader = Reader::from_reader(xml);
+    let mut chart_type = None;
+    let mut series: Vec<ChartSeries> = Vec::new();
+    let mut categories: Vec<String> = Vec::new();
+    let mut part = None;
+    let mut index = 0;
+    let mut in_value = false;
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => match start.local_name().as_ref() {
+                b"ser" => series.push(ChartSeries {
+                    name: String::new(),
+                    values: Vec::new(),
+                }),
+                b"tx" if !series.is_empty() => part = Some(Part::Name),
+                b"cat" | b"xVal" if !series.is_empty() => part = Some(Part::Category),
+                b"val" | b"yVal" if !series.is_empty() => part = Some(Part::Value),
+                b"pt" => {
+                    index = start
+                        .attributes()
+                        .flatten()
+                        .find(|attribute| attribute.key.as_ref() == b"idx")
+                        .and_then(|attribute| {
+                            std::str::from_utf8(&attribute.value).ok()?.parse().ok()
+                        })
+                        .unwrap_or(0);
+                }
+                b"v" => in_value = true,
+                name => {
+                    if chart_type.is_none() {
+                        chart_type = plot_type(name);
+                    }
+                }
+            },
+            Event::Text(text) if in_value => {
+                let text = text.unescape()?.to_string();
+                let first = series.len() == 1;
+                let Some(current) = series.last_mut() else {
+                    continue;
+                };
+                match part {
+                    Some(Part::Name) => current.name = text,
+                    // The categories are shared, the first series has them all
+                    Some(Part::Category) if first => {
+                        if categories.len() <= index {
+                            categories.resize(index + 1, String::new());
+                        }
+                        categories[index] = text;
+                    }
+                    Some(Part::Value) => {
+                        if current.values.len() <= index {
+                            current.values.resize(index + 1, 0.0);
+                        }
+                        current.values[index] = text.trim().parse().unwrap_or(0.0);
+                    }
+                    _ => {}
+                }
+            }
+            Event::End(end) => match end.local_name().as_ref() {
+                b"v" => in_value = false,
+                b"tx" | b"cat" | b"xVal" | b"val" | b"yVal" => part = None,
+                // The series of later plots in the part are left out
+                name if chart_type.is_some() && plot_type(name).is_some() => break,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(match chart_type {
+        Some(chart_type) if !series.is_empty() => Some(Element::Chart {
+            chart_type,
+            series,
+            categories,
+        }),
+        _ => None,
+    })
+}
+
+/// The chart type of a DrawingML plot element, other elements have none
+#[cfg(any(feature = "docx", feature = "xlsx"))]
+fn plot_type(name: &[u8]) -> Option<ChartType> {
+    match name {
+        b"barChart" | b"bar3DChart" => Some(ChartType::Bar),
+        b"lineChart" | b"line3DChart" | b"scatterChart" | b"radarChart" | b"stockChart" => {
+            Some(ChartType::Line)
+        }
+        b"areaChart" | b"area3DChart" => Some(ChartType::Area),
+        b"pieChart" | b"pie3DChart" | b"doughnutChart" | b"ofPieChart" => Some(ChartType::Pie),
+        _ => None,
+    }
+}
+
+/// The charts kept in a directory of an office package, in the order of their numbers
+#[cfg(any(feature = "docx", feature = "xlsx"))]
+pub(crate) fn package_charts(package: &[u8], directory: &str) -> anyhow::Result<Vec<Element>> {
+    use std::io::Read;
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(package))?;
+    let mut parts: Vec<(usize, String)> = archive
+        .file_names()
+        .filter_map(|name| {
+            let number = name
+                .strip_prefix(directory)?
+                .strip_prefix("chart")?
+                .strip_suffix(".xml")?;
+            Some((number.parse().ok()?, name.to_string()))
+        })
+        .collect();
+    parts.sort();
+    let mut charts = Vec::new();
+    for (_, name) in parts {
+        let mut xml = Vec::new();
+        archive.by_name(&name)?.read_to_end(&mut xml)?;
+        charts.extend(parse(&xml)?);
+    }
+    Ok(charts)
+}
@@ -0,0 +1,468 @@
+use crate::core::*;
+use bytes::Bytes;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    fn parse_with_loader<F>(_document: &Bytes, _image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Err(anyhow::anyhow!("Confluence parsing is not supported"))
+    }
+
+    /// Produces the XHTML body of a page in Confluence storage format. Images are written
+    /// through `image_saver` and referenced as page attachments, so they have to be uploaded
+    /// next to the page. Multi-line text becomes a code macro.
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let mut generator = Generator {
+            image_saver,
+            image_num: 0,
+        };
+        let mut xml = String::new();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                generator.block(&mut xml, element)?;
+            }
+        }
+        Ok(Bytes::from(xml))
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `]]>` would end the CDATA section, it is split across two sections
+fn cdata(text: &str) -> String {
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+struct Generator<F> {
+    image_saver: F,
+    image_num: usize,
+}
+
+impl<F> Generator<F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn block(&mut self, xml: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Header { level, text, .. } => {
+                let level = (*level).clamp(1, 6);
+                xml.push_str(&format!("<h{}>{}</h{}>\n", level, escape(text), level));
+            }
+            Element::Text { text, .. } if text.contains('\n') => {
+                xml.push_str(&format!(
+                    "<ac:structured-macro ac:name=\"code\" ac:schema-version=\"1\"><ac:plain-text-body>{}</ac:plain-text-body></ac:structured-macro>\n",
+                    cdata(text)
+                ));
+            }
+            Element::List { elements, numbered } => self.list(xml, elements, *numbered)?,
+            Element::Chart { .. } => self.block(xml, &element.chart_fallback())?,
+            Element::Table { headers, rows, .. } => self.table(xml, headers, rows)?,
+            Element::HorizontalRule => xml.push_str("<hr />\n"),
+            Element::Image(image) => {
+                if self.image(xml, image)? {
+                    xml.push('\n');
+                }
+            }
+            // The editor has no figures, the caption is an italic paragraph below the image
+            Element::Figure { image, caption } => {
+                if self.image(xml, image)? {
+                    xml.push('\n');
+                }
+                if !caption.is_empty() {
+                    xml.push_str("<p><em>");
+                    for child in caption {
+                        self.inline(xml, child)?;
+                    }
+                    xml.push_str("</em></p>\n");
+                }
+            }
+            Element::CodeBlock { language, code } => {
+                xml.push_str("<ac:structured-macro ac:name=\"code\" ac:schema-version=\"1\">");
+                if let Some(language) = language {
+                    xml.push_str(&format!(
+                        "<ac:parameter ac:name=\"language\">{}</ac:parameter>",
+                        escape(language)
+                    ));
+                }
+                xml.push_str(&format!(
+                    "<ac:plain-text-body>{}</ac:plain-text-body></ac:structured-macro>\n",
+                    cdata(code)
+                ));
+            }
+            Element::Blockquote { elements, .. } => {
+                xml.push_str("<blockquote>\n");
+                for child in elements {
+                    self.block(xml, child)?;
+                }
+                xml.push_str("</blockquote>\n");
+            }
+            Element::Section { children, .. } => {
+                for child in children {
+                    self.block(xml, child)?;
+                }
+            }
+            // The editor has no definition lists, definitions are indented below the bold term
+            Element::DefinitionList { items } => {
+                for item in items {
+                    xml.push_str("<p><strong>");
+                    for child in &item.term {
+                        self.inline(xml, child)?;
+                    }
+                    xml.push_str("</strong></p>\n");
+                    for definition in &item.definitions {
+                        xml.push_str("<p style=\"margin-left: 30.0px;\">");
+                        self.inline(xml, definition)?;
+                        xml.push_str("</p>\n");
+                    }
+                }
+            }
+            // Confluence lists the headings of the page itself
+            Element::TableOfContents { max_level } => {
+                xml.push_str("<ac:structured-macro ac:name=\"toc\" ac:schema-version=\"1\">");
+                xml.push_str(&format!(
+                    "<ac:parameter ac:name=\"maxLevel\">{}</ac:parameter>",
+                    max_level
+                ));
+                xml.push_str("</ac:structured-macro>\n");
+            }
+            // Confluence drops XML comments from stored pages, and pages are not paged
+            Element::Comment { .. } | Element::PageBreak => {}
+            element => {
+                xml.push_str("<p>");
+                self.inline(xml, element)?;
+                xml.push_str("</p>\n");
+            }
+        }
+        Ok(())
+    }
+
+    fn list(&mut self, xml: &mut String, items: &[ListItem], numbered: bool) -> anyhow::Result<()> {
+        let tag = if numbered { "ol" } else { "ul" };
+        xml.push_str(&format!("<{}>\n", tag));
+        let mut open = false;
+        for item in items {
+            match &item.element {
+                // Nested lists belong to the preceding item
+                Element::List { elements, numbered } if open => {
+                    xml.truncate(xml.len() - "</li>\n".len());
+                    self.list(xml, elements, *numbered)?;
+                }
+                Element::List { elements, numbered } => {
+                    xml.push_str("<li>");
+                    self.list(xml, elements, *numbered)?;
+                }
+                element => {
+                    xml.push_str("<li>");
+                    self.inline(xml, element)?;
+                }
+            }
+            xml.push_str("</li>\n");
+            open = true;
+        }
+        xml.push_str(&format!("</{}>\n", tag));
+        Ok(())
+    }
+
+    /// Confluence keeps header cells in the body, there is no `thead`
+    fn table(
+        &mut self,
+        xml: &mut String,
+        headers: &[TableHeader],
+        rows: &[TableRow],
+    ) -> anyhow::Result<()> {
+        xml.push_str("<table>\n<tbody>\n");
+        if !headers.is_empty() {
+            xml.push_str("<tr>");
+            for header in headers {
+                xml.push_str("<th>");
+                self.cell(xml, &header.element)?;
+                xml.push_str("</th>");
+            }
+            xml.push_str("</tr>\n");
+        }
+        for row in rows {
+            xml.push_str("<tr>");
+            for cell in &row.cells {
+                xml.push_str("<td>");
+                self.cell(xml, &cell.element())?;
+                xml.push_str("</td>");
+            }
+            xml.push_str("</tr>\n");
+        }
+        xml.push_str("</tbody>\n</table>\n");
+        Ok(())
+    }
+
+    fn cell(&mut self, xml: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::List { .. } | Element::Table { .. } => {
+                self.block(xml, element)?;
+                // The trailing line break would end up in the cell text
+                xml.pop();
+            }
+            element => self.inline(xml, element)?,
+        }
+        Ok(())
+    }
+
+    fn inline(&mut self, xml: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => {
+                xml.push_str(&escape(text).replace('\n', "<br />"))
+            }
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => {
+                for child in elements {
+                    self.inline(xml, child)?;
+                }
+            }
+            Element::Styled { style, elements } => {
+                let tags = [
+                    (style.bold, "strong"),
+                    (style.italic, "em"),
+                    (style.underline, "u"),
+                    (style.strikethrough, "s"),
+                ];
+                for (_, tag) in tags.iter().filter(|(enabled, _)| *enabled) {
+                    xml.push_str(&format!("<{}>", tag));
+                }
+                for child in elements {
+                    self.inline(xml, child)?;
+                }
+                for (_, tag) in tags.iter().rev().filter(|(enabled, _)| *enabled) {
+                    xml.push_str(&format!("</{}>", tag));
+                }
+            }
+            Element::Hyperlink {
+                title, url, alt, ..
+            } => {
+                xml.push_str(&format!("<a href=\"{}\"", escape(url)));
+                if !alt.is_empty() {
+                    xml.push_str(&format!(" title=\"{}\"", escape(alt)));
+                }
+                let title = if title.is_empty() { url } else { title };
+                xml.push_str(&format!(">{}</a>", escape(title)));
+            }
+            Element::Image(image) => {
+                self.image(xml, image)?;
+            }
+            Element::Figure { image, caption } => {
+                self.image(xml, image)?;
+                for child in caption {
+                    xml.push(' ');
+                    self.inline(xml, child)?;
+                }
+            }
+            Element::LineBreak | Element::HorizontalRule => xml.push_str("<br />"),
+            Element::CodeBlock { code, .. } => {
+                xml.push_str(&format!("<code>{}</code>", escape(code)))
+            }
+            Element::Media { .. } => self.inline(xml, &element.media_fallback())?,
+            Element::Ruby { .. } => self.inline(xml, &element.ruby_fallback())?,
+            Element::Comment { .. }
+            | Element::PageBreak
+            | Element::TableOfContents { .. }
+            | Element::Custom { .. } => {}
+            Element::List { elements, .. } => {
+                for (index, item) in elements.iter().enumerate() {
+                    if index > 0 {
+                        xml.push(' ');
+                    }
+                    self.inline(xml, &item.element)?;
+                }
+            }
+            Element::DefinitionList { items } => {
+                let children = items
+                    .iter()
+                    .flat_map(|item| item.term.iter().chain(&item.definitions));
+                for (index, child) in children.enumerate() {
+                    if index > 0 {
+                        xml.push(' ');
+                    }
+                    self.inline(xml, child)?;
+                }
+            }
+            // Formulas need a marketplace macro, the TeX source is set as code
+            Element::Math { tex, .. } => xml.push_str(&format!("<code>{}</code>", escape(tex))),
+            Element::FootnoteReference { id } => {
+                xml.push_str(&format!("<sup>{}</sup>", escape(id)))
+            }
+            Element::Footnote { id, content } => {
+                xml.push_str(&format!("<sup>{}</sup> ", escape(id)));
+                for child in content {
+                    self.inline(xml, child)?;
+                }
+            }
+            Element::Table { .. } | Element::Chart { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Saves the image and references it as an attachment, returns false for images
+    /// without content
+    fn image(&mut self, xml: &mut String, image: &ImageData) -> anyhow::Result<bool> {
+        if image.bytes().is_empty() {
+            return Ok(false);
+        }
+        let target = format!(
+            "image{}{}",
+            self.image_num,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &target)?;
+        self.image_num += 1;
+
+        xml.push_str("<ac:image");
+        let align = match image.align() {
+            ImageAlignment::Left => Some("left"),
+            ImageAlignment::Center => Some("center"),
+            ImageAlignment::Right => Some("right"),
+            ImageAlignment::None => None,
+        };
+        if let Some(align) = align {
+            xml.push_str(&format!(" ac:align=\"{}\"", align));
+        }
+        // Only pixel sizes are understood, the unit is implied
+        let size = image.size();
+        for (attribute, value) in [("ac:width", &size.width), ("ac:height", &size.height)] {
+            let pixels = value
+                .as_deref()
+                .map(|value| value.trim_end_matches("px"))
+                .filter(|value| !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()));
+            if let Some(pixels) = pixels {
+                xml.push_str(&format!(" {}=\"{}\"", attribute, pixels));
+            }
+        }
+        if !image.alt().is_empty() {
+            xml.push_str(&format!(" ac:alt=\"{}\"", escape(image.alt())));
+        }
+        if !image.title().is_empty() {
+            xml.push_str(&format!(" ac:title=\"{}\"", escape(image.title())));
+        }
+        xml.push_str(&format!(
+            "><ri:attachment ri:filename=\"{}\" /></ac:image>",
+            escape(&target)
+        ));
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::confluence::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 2,
+                text: "Install & run".to_string(),
+                anchor: None,
+            },
+            text("cargo build\ncargo run -- \"]]>\""),
+            Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("One"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: Element::List {
+                            elements: vec![ListItem {
+                                element: text("Nested"),
+                                checked: None,
+                            }],
+                            numbered: true,
+                        },
+                        checked: None,
+                    },
+                ],
+                numbered: false,
+            },
+            Element::Table {
+                headers: vec![TableHeader {
+                    element: text("Name"),
+                    width: 30.0,
+                }],
+                rows: vec![TableRow {
+                    cells: vec![TableCell::new(Element::Hyperlink {
+                        title: "Docs".to_string(),
+                        url: "https://example.com/?a=1&b=2".to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    })],
+                }],
+                alignments: vec![],
+                caption: vec![],
+            },
+            Element::Image(ImageData::new(
+                Bytes::from_static(b"\x89PNG"),
+                "Logo".to_string(),
+                "The logo".to_string(),
+                "png".to_string(),
+                "center".to_string(),
+                ImageDimension {
+                    width: Some("200px".to_string()),
+                    height: Some("50%".to_string()),
+                },
+            )),
+        ]);
+        let saved = RefCell::new(Vec::new());
+        let generated = Transformer::generate_with_saver(&document, |bytes, name| {
+            saved.borrow_mut().push((name.to_string(), bytes.clone()));
+            Ok(())
+        })?;
+        let xml = String::from_utf8(generated.to_vec())?;
+
+        assert!(xml.contains("<h2>Install &amp; run</h2>"));
+        assert!(xml.contains(
+            "<ac:plain-text-body><![CDATA[cargo build\ncargo run -- \"]]]]><![CDATA[>\"]]></ac:plain-text-body>"
+        ));
+        assert!(xml.contains("<ul>\n<li>One<ol>\n<li>Nested</li>\n</ol>\n</li>\n</ul>"));
+        assert!(xml.contains("<tr><th>Name</th></tr>"));
+        assert!(xml.contains("<td><a href=\"https://example.com/?a=1&amp;b=2\">Docs</a></td>"));
+        assert!(xml.contains(concat!(
+            "<ac:image ac:align=\"center\" ac:width=\"200\" ac:alt=\"The logo\" ac:title=\"Logo\">",
+            "<ri:attachment ri:filename=\"image0.png\" /></ac:image>"
+        )));
+        assert_eq!(saved.borrow()[0].0, "image0.png");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_is_not_supported() {
+        assert!(Transformer::parse(&Bytes::from("<p>Text</p>")).is_err());
+    }
+}
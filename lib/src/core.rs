@@ -3,38 +3,119 @@ use log::info;
 #[cfg(feature = "json")]
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
-use std::{collections::HashMap, fmt::Debug};
-use strum::{Display, EnumCount, EnumString, IntoStaticStr, VariantArray};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::{Range, RangeInclusive};
+use std::fmt::Debug;
+use std::io::Read;
+use strum::{Display, EnumCount, EnumDiscriminants, EnumString, IntoStaticStr, VariantArray};
 use thiserror::Error;
 use wasm_bindgen::prelude::wasm_bindgen;
 
 #[cfg(feature = "json")]
 use anyhow;
 
+#[cfg(feature = "ansi")]
+use crate::ansi;
+#[cfg(feature = "arrow")]
+use crate::arrow;
+#[cfg(feature = "asciidoc")]
+use crate::asciidoc;
+#[cfg(feature = "bbcode")]
+use crate::bbcode;
+#[cfg(feature = "confluence")]
+use crate::confluence;
 #[cfg(feature = "csv")]
 use crate::csv;
+#[cfg(feature = "daisy")]
+use crate::daisy;
+#[cfg(feature = "djvu")]
+use crate::djvu;
+#[cfg(feature = "docbook")]
+use crate::docbook;
 #[cfg(feature = "docx")]
 use crate::docx;
+use crate::emoji;
+#[cfg(feature = "epub")]
+use crate::epub;
+#[cfg(feature = "fb2")]
+use crate::fb2;
+#[cfg(feature = "gemtext")]
+use crate::gemtext;
 #[cfg(feature = "html")]
 use crate::html;
+#[cfg(feature = "ipynb")]
+use crate::ipynb;
+#[cfg(feature = "jira")]
+use crate::jira;
 #[cfg(feature = "json")]
 use crate::json;
+#[cfg(feature = "latex")]
+use crate::latex;
+#[cfg(feature = "man")]
+use crate::man;
 #[cfg(feature = "markdown")]
 use crate::markdown;
 #[cfg(feature = "ods")]
 use crate::ods;
+#[cfg(feature = "mediawiki")]
+use crate::mediawiki;
+#[cfg(feature = "mhtml")]
+use crate::mhtml;
+#[cfg(feature = "mobi")]
+use crate::mobi;
+#[cfg(feature = "ndjson")]
+use crate::ndjson;
+#[cfg(feature = "odp")]
+use crate::odp;
+#[cfg(feature = "odt")]
+use crate::odt;
+#[cfg(feature = "opml")]
+use crate::opml;
+#[cfg(feature = "org")]
+use crate::org;
 #[cfg(feature = "pdf")]
 use crate::pdf;
+#[cfg(feature = "png")]
+use crate::png;
+#[cfg(feature = "postscript")]
+use crate::postscript;
+#[cfg(feature = "pptx")]
+use crate::pptx;
+#[cfg(feature = "prosemirror")]
+use crate::prosemirror;
+#[cfg(feature = "quill")]
+use crate::quill;
+#[cfg(feature = "rst")]
+use crate::rst;
 #[cfg(feature = "rtf")]
 use crate::rtf;
+#[cfg(feature = "slack")]
+use crate::slack;
+#[cfg(feature = "sqlite")]
+use crate::sqlite;
+#[cfg(feature = "svg")]
+use crate::svg;
 #[cfg(feature = "text")]
 use crate::text;
+#[cfg(feature = "textile")]
+use crate::textile;
+#[cfg(feature = "tsv")]
+use crate::tsv;
+#[cfg(feature = "typst")]
+use crate::typst;
 #[cfg(feature = "xls")]
 use crate::xls;
 #[cfg(feature = "xlsx")]
 use crate::xlsx;
 #[cfg(feature = "xml")]
 use crate::xml;
+#[cfg(feature = "yaml")]
+use crate::yaml;
+use crate::registry::FormatRegistry;
+use crate::telemetry;
 
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
@@ -109,6 +190,44 @@ pub enum PageOrientation {
     Landscape,
 }
 
+/// Page margins in millimeters of the formats typst lays out: PDF, PNG, SVG and PostScript.
+///
+/// When `mirror` is set, `left` is used as the inner (binding) margin and `right` as the outer
+/// one, so the gutter alternates between odd and even pages as in double-sided printing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Margins {
+    pub top: f32,
+    pub bottom: f32,
+    pub left: f32,
+    pub right: f32,
+    pub mirror: bool,
+}
+
+impl Default for Margins {
+    fn default() -> Self {
+        Margins {
+            top: 25.0,
+            bottom: 25.0,
+            left: 25.0,
+            right: 25.0,
+            mirror: false,
+        }
+    }
+}
+
+impl Margins {
+    /// Mirrored margins for book-style output: `inside` is the margin at the binding edge.
+    pub fn mirrored(inside: f32, outside: f32, top: f32, bottom: f32) -> Margins {
+        Margins {
+            top,
+            bottom,
+            left: inside,
+            right: outside,
+            mirror: true,
+        }
+    }
+}
+
 /// Band is a section of a document(Title, PageHeader, ColumnHeader, Detail, ColumnFooter, PageFooter, Summary).
 ///
 /// Each band contains a list of elements (Text, Table, List, Image, Hyperlink...).
@@ -167,10 +286,38 @@ impl Band {
             Band::Custom(_, e) => e,
         }
     }
+
+    pub fn elements_mut(&mut self) -> &mut Vec<Element> {
+        match self {
+            Band::Title(e) => e,
+            Band::PageHeader(e) => e,
+            Band::ColumnHeader(e) => e,
+            Band::Detail(e) => e,
+            Band::ColumnFooter(e) => e,
+            Band::PageFooter(e) => e,
+            Band::Summary(e) => e,
+            Band::Custom(_, e) => e,
+        }
+    }
+
+    /// A band of the same kind holding other elements
+    fn with_elements(&self, elements: Vec<Element>) -> Band {
+        match self {
+            Band::Title(_) => Band::Title(elements),
+            Band::PageHeader(_) => Band::PageHeader(elements),
+            Band::ColumnHeader(_) => Band::ColumnHeader(elements),
+            Band::Detail(_) => Band::Detail(elements),
+            Band::ColumnFooter(_) => Band::ColumnFooter(elements),
+            Band::PageFooter(_) => Band::PageFooter(elements),
+            Band::Summary(_) => Band::Summary(elements),
+            Band::Custom(name, _) => Band::Custom(name.clone(), elements),
+        }
+    }
 }
 
-#[derive(Debug, PartialEq)]
-#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+/// Serialized with the version of its schema, see [`crate::schema`]
+#[derive(Debug)]
+#[cfg_attr(feature = "json", derive(Deserialize))]
 pub struct Document {
     /// Bands are the different sections of a document(Title, PageHeader, ColumnHeader, Detail, ColumnFooter, PageFooter, Summary)
     /// Each band contains a list of elements (Text, Table, List, Image, Hyperlink...)
@@ -181,6 +328,30 @@ pub struct Document {
 
     /// Page orientation (Portrait, Landscape) Default is Portrait.
     pub orientation: PageOrientation,
+
+    /// Text direction (Ltr, Rtl) Default is left to right.
+    #[cfg_attr(feature = "json", serde(default))]
+    pub direction: TextDirection,
+
+    /// Title, authors, dates and other properties of the document as a whole
+    #[cfg_attr(feature = "json", serde(default))]
+    pub metadata: Metadata,
+
+    /// Where parsers found the elements in the input, by the path of the element as
+    /// [`Document::source_span`] takes it. Not serialized, and not part of equality: the same
+    /// content parsed from two inputs is the same document
+    #[cfg_attr(feature = "json", serde(skip))]
+    pub source_spans: BTreeMap<Vec<usize>, SourceSpan>,
+}
+
+impl PartialEq for Document {
+    fn eq(&self, other: &Self) -> bool {
+        self.bands == other.bands
+            && self.page_format == other.page_format
+            && self.orientation == other.orientation
+            && self.direction == other.direction
+            && self.metadata == other.metadata
+    }
 }
 
 impl Document {
@@ -189,6 +360,9 @@ impl Document {
             bands: vec![Band::Detail(elements)],
             page_format: PageFormat::default(),
             orientation: PageOrientation::default(),
+            direction: TextDirection::default(),
+            metadata: Metadata::default(),
+            source_spans: BTreeMap::new(),
         }
     }
 
@@ -206,136 +380,1237 @@ impl Document {
             ],
             page_format,
             orientation: PageOrientation::default(),
+            direction: TextDirection::default(),
+            metadata: Metadata::default(),
+            source_spans: BTreeMap::new(),
         }
     }
 
     pub fn parse(input_bytes: &Bytes, document_type: DocumentType) -> anyhow::Result<Document> {
-        let document = match document_type {
+        Document::parse_with_options(input_bytes, document_type, &ParserOptions::default())
+    }
+
+    /// Parses a document of any format the same way, see [`ParserOptions`]
+    pub fn parse_with_options(
+        input_bytes: &Bytes,
+        document_type: DocumentType,
+        options: &ParserOptions,
+    ) -> anyhow::Result<Document> {
+        let operation = telemetry::Operation::parse(document_type, input_bytes.len());
+        let result = options
+            .check_cancelled()
+            .and_then(|_| options.decode(input_bytes, document_type))
+            .and_then(|input_bytes| Document::parse_by_type(&input_bytes, document_type, options))
+            .and_then(|document| options.check(document));
+        operation.finish_parse(&result);
+        result
+    }
+
+    fn parse_by_type(
+        input_bytes: &Bytes,
+        document_type: DocumentType,
+        options: &ParserOptions,
+    ) -> anyhow::Result<Document> {
+        if let Some(format) = FormatRegistry::global().by_type(document_type) {
+            return format.parse(input_bytes);
+        }
+        // Unused when none of the formats that refer to images by path is enabled
+        #[allow(unused_variables)]
+        let image_loader = |image: &str| options.load_image(image);
+        match document_type {
             #[cfg(feature = "markdown")]
-            DocumentType::Markdown => markdown::Transformer::parse(input_bytes)?,
+            DocumentType::Markdown => markdown::Transformer::parse_with_loader(input_bytes, image_loader),
             #[cfg(not(feature = "markdown"))]
             DocumentType::Markdown => {
-                return Err(anyhow::anyhow!("Markdown feature is not enabled"))
+                Err(anyhow::anyhow!("Markdown feature is not enabled"))
             }
             #[cfg(feature = "html")]
-            DocumentType::HTML => html::Transformer::parse(input_bytes)?,
+            DocumentType::HTML => html::Transformer::parse_with_loader(input_bytes, image_loader),
             #[cfg(not(feature = "html"))]
-            DocumentType::HTML => return Err(anyhow::anyhow!("HTML feature is not enabled")),
+            DocumentType::HTML => Err(anyhow::anyhow!("HTML feature is not enabled")),
             #[cfg(feature = "text")]
-            DocumentType::Text => text::Transformer::parse(input_bytes)?,
+            DocumentType::Text => text::Transformer::parse(input_bytes),
             #[cfg(not(feature = "text"))]
-            DocumentType::Text => return Err(anyhow::anyhow!("Text feature is not enabled")),
+            DocumentType::Text => Err(anyhow::anyhow!("Text feature is not enabled")),
             #[cfg(feature = "pdf")]
-            DocumentType::PDF => pdf::Transformer::parse(input_bytes)?,
+            DocumentType::PDF => {
+                let pdf_options = pdf::ParseOptions {
+                    cancellation: options.cancellation.clone(),
+                    pages: options.pages.clone(),
+                };
+                pdf::Transformer::parse_with_options(input_bytes, &pdf_options)
+            }
             #[cfg(not(feature = "pdf"))]
-            DocumentType::PDF => return Err(anyhow::anyhow!("PDF feature is not enabled")),
+            DocumentType::PDF => Err(anyhow::anyhow!("PDF feature is not enabled")),
             #[cfg(feature = "json")]
-            DocumentType::Json => json::Transformer::parse(input_bytes)?,
+            DocumentType::Json => json::Transformer::parse(input_bytes),
             #[cfg(not(feature = "json"))]
-            DocumentType::Json => return Err(anyhow::anyhow!("Json feature is not enabled")),
+            DocumentType::Json => Err(anyhow::anyhow!("Json feature is not enabled")),
             #[cfg(feature = "csv")]
-            DocumentType::CSV => csv::Transformer::parse(input_bytes)?,
+            DocumentType::CSV => csv::Transformer::parse(input_bytes),
             #[cfg(not(feature = "csv"))]
-            DocumentType::CSV => return Err(anyhow::anyhow!("CSV feature is not enabled")),
+            DocumentType::CSV => Err(anyhow::anyhow!("CSV feature is not enabled")),
             #[cfg(feature = "rtf")]
-            DocumentType::RTF => rtf::Transformer::parse(input_bytes)?,
+            DocumentType::RTF => rtf::Transformer::parse(input_bytes),
             #[cfg(not(feature = "rtf"))]
-            DocumentType::RTF => return Err(anyhow::anyhow!("RTF feature is not enabled")),
+            DocumentType::RTF => Err(anyhow::anyhow!("RTF feature is not enabled")),
             #[cfg(feature = "docx")]
-            DocumentType::DOCX => docx::Transformer::parse(input_bytes)?,
+            DocumentType::DOCX => docx::Transformer::parse(input_bytes),
             #[cfg(not(feature = "docx"))]
-            DocumentType::DOCX => return Err(anyhow::anyhow!("DOCX feature is not enabled")),
+            DocumentType::DOCX => Err(anyhow::anyhow!("DOCX feature is not enabled")),
             #[cfg(feature = "xml")]
-            DocumentType::XML => xml::Transformer::parse(input_bytes)?,
+            DocumentType::XML => xml::Transformer::parse(input_bytes),
             #[cfg(not(feature = "xml"))]
-            DocumentType::XML => return Err(anyhow::anyhow!("XML feature is not enabled")),
+            DocumentType::XML => Err(anyhow::anyhow!("XML feature is not enabled")),
             #[cfg(feature = "xls")]
-            DocumentType::XLS => xls::Transformer::parse(input_bytes)?,
+            DocumentType::XLS => xls::Transformer::parse(input_bytes),
             #[cfg(not(feature = "xls"))]
-            DocumentType::XLS => return Err(anyhow::anyhow!("XLS feature is not enabled")),
+            DocumentType::XLS => Err(anyhow::anyhow!("XLS feature is not enabled")),
             #[cfg(feature = "xlsx")]
-            DocumentType::XLSX => xlsx::Transformer::parse(input_bytes)?,
+            DocumentType::XLSX => xlsx::Transformer::parse(input_bytes),
             #[cfg(not(feature = "xlsx"))]
-            DocumentType::XLSX => return Err(anyhow::anyhow!("XLSX feature is not enabled")),
+            DocumentType::XLSX => Err(anyhow::anyhow!("XLSX feature is not enabled")),
             #[cfg(feature = "ods")]
-            DocumentType::ODS => ods::Transformer::parse(input_bytes)?,
+            DocumentType::ODS => ods::Transformer::parse(input_bytes),
             #[cfg(not(feature = "ods"))]
-            DocumentType::ODS => return Err(anyhow::anyhow!("ODS feature is not enabled")),
-        };
-        Ok(document)
+            DocumentType::ODS => Err(anyhow::anyhow!("ODS feature is not enabled")),
+            #[cfg(feature = "epub")]
+            DocumentType::EPUB => epub::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "epub"))]
+            DocumentType::EPUB => Err(anyhow::anyhow!("EPUB feature is not enabled")),
+            #[cfg(feature = "odt")]
+            DocumentType::ODT => odt::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "odt"))]
+            DocumentType::ODT => Err(anyhow::anyhow!("ODT feature is not enabled")),
+            #[cfg(feature = "latex")]
+            DocumentType::LaTeX => latex::Transformer::parse_with_loader(input_bytes, image_loader),
+            #[cfg(not(feature = "latex"))]
+            DocumentType::LaTeX => Err(anyhow::anyhow!("LaTeX feature is not enabled")),
+            #[cfg(feature = "asciidoc")]
+            DocumentType::AsciiDoc => asciidoc::Transformer::parse_with_loader(input_bytes, image_loader),
+            #[cfg(not(feature = "asciidoc"))]
+            DocumentType::AsciiDoc => {
+                Err(anyhow::anyhow!("AsciiDoc feature is not enabled"))
+            }
+            #[cfg(feature = "rst")]
+            DocumentType::RST => rst::Transformer::parse_with_loader(input_bytes, image_loader),
+            #[cfg(not(feature = "rst"))]
+            DocumentType::RST => Err(anyhow::anyhow!("RST feature is not enabled")),
+            #[cfg(feature = "org")]
+            DocumentType::Org => org::Transformer::parse_with_loader(input_bytes, image_loader),
+            #[cfg(not(feature = "org"))]
+            DocumentType::Org => Err(anyhow::anyhow!("Org feature is not enabled")),
+            #[cfg(feature = "docbook")]
+            DocumentType::DocBook => docbook::Transformer::parse_with_loader(input_bytes, image_loader),
+            #[cfg(not(feature = "docbook"))]
+            DocumentType::DocBook => {
+                Err(anyhow::anyhow!("DocBook feature is not enabled"))
+            }
+            #[cfg(feature = "fb2")]
+            DocumentType::FB2 => fb2::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "fb2"))]
+            DocumentType::FB2 => Err(anyhow::anyhow!("FB2 feature is not enabled")),
+            #[cfg(feature = "pptx")]
+            DocumentType::PPTX => pptx::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "pptx"))]
+            DocumentType::PPTX => Err(anyhow::anyhow!("PPTX feature is not enabled")),
+            #[cfg(feature = "odp")]
+            DocumentType::ODP => odp::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "odp"))]
+            DocumentType::ODP => Err(anyhow::anyhow!("ODP feature is not enabled")),
+            #[cfg(feature = "ipynb")]
+            DocumentType::Ipynb => ipynb::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "ipynb"))]
+            DocumentType::Ipynb => {
+                Err(anyhow::anyhow!("Jupyter notebook feature is not enabled"))
+            }
+            #[cfg(feature = "mhtml")]
+            DocumentType::MHTML => mhtml::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "mhtml"))]
+            DocumentType::MHTML => Err(anyhow::anyhow!("MHTML feature is not enabled")),
+            #[cfg(feature = "mediawiki")]
+            DocumentType::MediaWiki => mediawiki::Transformer::parse_with_loader(input_bytes, image_loader),
+            #[cfg(not(feature = "mediawiki"))]
+            DocumentType::MediaWiki => {
+                Err(anyhow::anyhow!("MediaWiki feature is not enabled"))
+            }
+            #[cfg(feature = "confluence")]
+            DocumentType::Confluence => confluence::Transformer::parse_with_loader(input_bytes, image_loader),
+            #[cfg(not(feature = "confluence"))]
+            DocumentType::Confluence => {
+                Err(anyhow::anyhow!("Confluence feature is not enabled"))
+            }
+            #[cfg(feature = "jira")]
+            DocumentType::Jira => jira::Transformer::parse_with_loader(input_bytes, image_loader),
+            #[cfg(not(feature = "jira"))]
+            DocumentType::Jira => Err(anyhow::anyhow!("Jira feature is not enabled")),
+            #[cfg(feature = "bbcode")]
+            DocumentType::BBCode => bbcode::Transformer::parse_with_loader(input_bytes, image_loader),
+            #[cfg(not(feature = "bbcode"))]
+            DocumentType::BBCode => Err(anyhow::anyhow!("BBCode feature is not enabled")),
+            #[cfg(feature = "textile")]
+            DocumentType::Textile => textile::Transformer::parse_with_loader(input_bytes, image_loader),
+            #[cfg(not(feature = "textile"))]
+            DocumentType::Textile => Err(anyhow::anyhow!("Textile feature is not enabled")),
+            #[cfg(feature = "man")]
+            DocumentType::Man => man::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "man"))]
+            DocumentType::Man => Err(anyhow::anyhow!("Man page feature is not enabled")),
+            #[cfg(feature = "gemtext")]
+            DocumentType::Gemtext => gemtext::Transformer::parse_with_loader(input_bytes, image_loader),
+            #[cfg(not(feature = "gemtext"))]
+            DocumentType::Gemtext => Err(anyhow::anyhow!("Gemtext feature is not enabled")),
+            #[cfg(feature = "ansi")]
+            DocumentType::Ansi => ansi::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "ansi"))]
+            DocumentType::Ansi => Err(anyhow::anyhow!("ANSI feature is not enabled")),
+            #[cfg(feature = "opml")]
+            DocumentType::Opml => opml::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "opml"))]
+            DocumentType::Opml => Err(anyhow::anyhow!("OPML feature is not enabled")),
+            #[cfg(feature = "quill")]
+            DocumentType::Quill => quill::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "quill"))]
+            DocumentType::Quill => Err(anyhow::anyhow!("Quill feature is not enabled")),
+            #[cfg(feature = "prosemirror")]
+            DocumentType::ProseMirror => prosemirror::Transformer::parse_with_loader(input_bytes, image_loader),
+            #[cfg(not(feature = "prosemirror"))]
+            DocumentType::ProseMirror => {
+                Err(anyhow::anyhow!("ProseMirror feature is not enabled"))
+            }
+            #[cfg(feature = "slack")]
+            DocumentType::Slack => slack::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "slack"))]
+            DocumentType::Slack => Err(anyhow::anyhow!("Slack feature is not enabled")),
+            #[cfg(feature = "tsv")]
+            DocumentType::TSV => tsv::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "tsv"))]
+            DocumentType::TSV => Err(anyhow::anyhow!("TSV feature is not enabled")),
+            #[cfg(feature = "ndjson")]
+            DocumentType::NDJSON => ndjson::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "ndjson"))]
+            DocumentType::NDJSON => Err(anyhow::anyhow!("NDJSON feature is not enabled")),
+            #[cfg(feature = "yaml")]
+            DocumentType::YAML => yaml::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "yaml"))]
+            DocumentType::YAML => Err(anyhow::anyhow!("YAML feature is not enabled")),
+            #[cfg(feature = "sqlite")]
+            DocumentType::SQLite => sqlite::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "sqlite"))]
+            DocumentType::SQLite => Err(anyhow::anyhow!("SQLite feature is not enabled")),
+            #[cfg(feature = "arrow")]
+            DocumentType::Parquet => arrow::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "arrow"))]
+            DocumentType::Parquet => Err(anyhow::anyhow!("Parquet feature is not enabled")),
+            #[cfg(feature = "svg")]
+            DocumentType::SVG => svg::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "svg"))]
+            DocumentType::SVG => Err(anyhow::anyhow!("SVG feature is not enabled")),
+            #[cfg(feature = "png")]
+            DocumentType::PNG => png::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "png"))]
+            DocumentType::PNG => Err(anyhow::anyhow!("PNG feature is not enabled")),
+            #[cfg(feature = "postscript")]
+            DocumentType::PostScript => postscript::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "postscript"))]
+            DocumentType::PostScript => {
+                Err(anyhow::anyhow!("PostScript feature is not enabled"))
+            }
+            #[cfg(feature = "djvu")]
+            DocumentType::DjVu => djvu::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "djvu"))]
+            DocumentType::DjVu => Err(anyhow::anyhow!("DjVu feature is not enabled")),
+            #[cfg(feature = "mobi")]
+            DocumentType::MOBI => mobi::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "mobi"))]
+            DocumentType::MOBI => Err(anyhow::anyhow!("MOBI feature is not enabled")),
+            #[cfg(feature = "daisy")]
+            DocumentType::DAISY => daisy::Transformer::parse(input_bytes),
+            #[cfg(not(feature = "daisy"))]
+            DocumentType::DAISY => Err(anyhow::anyhow!("DAISY feature is not enabled")),
+            #[cfg(feature = "typst")]
+            DocumentType::Typst => typst::Transformer::parse_with_loader(input_bytes, image_loader),
+            #[cfg(not(feature = "typst"))]
+            DocumentType::Typst => Err(anyhow::anyhow!("Typst feature is not enabled")),
+        }
     }
 
     pub fn generate(&self, document_type: DocumentType) -> anyhow::Result<Bytes> {
-        let output = match document_type {
+        self.generate_with_options(document_type, &GeneratorOptions::default())
+    }
+
+    /// Generates a document in any format the same way, see [`GeneratorOptions`]
+    pub fn generate_with_options(
+        &self,
+        document_type: DocumentType,
+        options: &GeneratorOptions,
+    ) -> anyhow::Result<Bytes> {
+        let prepared = options.prepare(self);
+        let document = prepared.as_ref().unwrap_or(self);
+        let operation =
+            telemetry::Operation::generate(document_type, document.get_all_elements().len());
+        let result = match &options.images {
+            _ if options.cancellation.as_ref().is_some_and(|token| token.is_cancelled()) => {
+                Err(GeneratorError::Cancelled.into())
+            }
+            ImageEmbedding::Directory(directory) => document
+                .generate_by_type_with_saver(document_type, disk_image_saver(directory))
+                .unwrap_or_else(|| document.generate_by_type(document_type, options)),
+            _ => document.generate_by_type(document_type, options),
+        };
+        operation.finish_generate(&result);
+        result
+    }
+
+    #[cfg_attr(
+        not(any(
+            feature = "text",
+            feature = "pdf",
+            feature = "svg",
+            feature = "png",
+            feature = "postscript"
+        )),
+        allow(unused_variables)
+    )]
+    fn generate_by_type(
+        &self,
+        document_type: DocumentType,
+        options: &GeneratorOptions,
+    ) -> anyhow::Result<Bytes> {
+        if let Some(format) = FormatRegistry::global().by_type(document_type) {
+            return format.generate(self);
+        }
+        match document_type {
             #[cfg(feature = "markdown")]
-            DocumentType::Markdown => markdown::Transformer::generate(self)?,
+            DocumentType::Markdown => markdown::Transformer::generate(self),
             #[cfg(not(feature = "markdown"))]
             DocumentType::Markdown => {
-                return Err(anyhow::anyhow!("Markdown feature is not enabled"))
+                Err(anyhow::anyhow!("Markdown feature is not enabled"))
             }
             #[cfg(feature = "html")]
-            DocumentType::HTML => html::Transformer::generate(self)?,
+            DocumentType::HTML => html::Transformer::generate(self),
             #[cfg(not(feature = "html"))]
-            DocumentType::HTML => return Err(anyhow::anyhow!("HTML feature is not enabled")),
+            DocumentType::HTML => Err(anyhow::anyhow!("HTML feature is not enabled")),
             #[cfg(feature = "text")]
-            DocumentType::Text => text::Transformer::generate(self)?,
+            DocumentType::Text => match options.line_width {
+                Some(width) => text::Transformer::generate_with_width(self, width),
+                None => text::Transformer::generate(self),
+            },
             #[cfg(not(feature = "text"))]
-            DocumentType::Text => return Err(anyhow::anyhow!("Text feature is not enabled")),
+            DocumentType::Text => Err(anyhow::anyhow!("Text feature is not enabled")),
             #[cfg(feature = "pdf")]
-            DocumentType::PDF => pdf::Transformer::generate(self)?,
+            DocumentType::PDF => pdf::Transformer::generate_with_options(self, &options.layout()),
             #[cfg(not(feature = "pdf"))]
-            DocumentType::PDF => return Err(anyhow::anyhow!("PDF feature is not enabled")),
+            DocumentType::PDF => Err(anyhow::anyhow!("PDF feature is not enabled")),
             #[cfg(feature = "json")]
-            DocumentType::Json => json::Transformer::generate(self)?,
+            DocumentType::Json => json::Transformer::generate(self),
             #[cfg(not(feature = "json"))]
-            DocumentType::Json => return Err(anyhow::anyhow!("Json feature is not enabled")),
+            DocumentType::Json => Err(anyhow::anyhow!("Json feature is not enabled")),
             #[cfg(feature = "csv")]
-            DocumentType::CSV => csv::Transformer::generate(self)?,
+            DocumentType::CSV => csv::Transformer::generate(self),
             #[cfg(not(feature = "csv"))]
-            DocumentType::CSV => return Err(anyhow::anyhow!("CSV feature is not enabled")),
+            DocumentType::CSV => Err(anyhow::anyhow!("CSV feature is not enabled")),
             #[cfg(feature = "rtf")]
-            DocumentType::RTF => rtf::Transformer::generate(self)?,
+            DocumentType::RTF => rtf::Transformer::generate(self),
             #[cfg(not(feature = "rtf"))]
-            DocumentType::RTF => return Err(anyhow::anyhow!("RTF feature is not enabled")),
+            DocumentType::RTF => Err(anyhow::anyhow!("RTF feature is not enabled")),
             #[cfg(feature = "docx")]
-            DocumentType::DOCX => docx::Transformer::generate(self)?,
+            DocumentType::DOCX => docx::Transformer::generate(self),
             #[cfg(not(feature = "docx"))]
-            DocumentType::DOCX => return Err(anyhow::anyhow!("DOCX feature is not enabled")),
+            DocumentType::DOCX => Err(anyhow::anyhow!("DOCX feature is not enabled")),
             #[cfg(feature = "xml")]
-            DocumentType::XML => xml::Transformer::generate(self)?,
+            DocumentType::XML => xml::Transformer::generate(self),
             #[cfg(not(feature = "xml"))]
-            DocumentType::XML => return Err(anyhow::anyhow!("XML feature is not enabled")),
+            DocumentType::XML => Err(anyhow::anyhow!("XML feature is not enabled")),
             #[cfg(feature = "xls")]
-            DocumentType::XLS => xls::Transformer::generate(self)?,
+            DocumentType::XLS => xls::Transformer::generate(self),
             #[cfg(not(feature = "xls"))]
-            DocumentType::XLS => return Err(anyhow::anyhow!("XLS feature is not enabled")),
+            DocumentType::XLS => Err(anyhow::anyhow!("XLS feature is not enabled")),
             #[cfg(feature = "xlsx")]
-            DocumentType::XLSX => xlsx::Transformer::generate(self)?,
+            DocumentType::XLSX => xlsx::Transformer::generate(self),
             #[cfg(not(feature = "xlsx"))]
-            DocumentType::XLSX => return Err(anyhow::anyhow!("XLSX feature is not enabled")),
+            DocumentType::XLSX => Err(anyhow::anyhow!("XLSX feature is not enabled")),
             #[cfg(feature = "ods")]
-            DocumentType::ODS => ods::Transformer::generate(self)?,
+            DocumentType::ODS => ods::Transformer::generate(self),
             #[cfg(not(feature = "ods"))]
-            DocumentType::ODS => return Err(anyhow::anyhow!("ODS feature is not enabled")),
-        };
-        Ok(output)
+            DocumentType::ODS => Err(anyhow::anyhow!("ODS feature is not enabled")),
+            #[cfg(feature = "epub")]
+            DocumentType::EPUB => epub::Transformer::generate(self),
+            #[cfg(not(feature = "epub"))]
+            DocumentType::EPUB => Err(anyhow::anyhow!("EPUB feature is not enabled")),
+            #[cfg(feature = "odt")]
+            DocumentType::ODT => odt::Transformer::generate(self),
+            #[cfg(not(feature = "odt"))]
+            DocumentType::ODT => Err(anyhow::anyhow!("ODT feature is not enabled")),
+            #[cfg(feature = "latex")]
+            DocumentType::LaTeX => latex::Transformer::generate(self),
+            #[cfg(not(feature = "latex"))]
+            DocumentType::LaTeX => Err(anyhow::anyhow!("LaTeX feature is not enabled")),
+            #[cfg(feature = "asciidoc")]
+            DocumentType::AsciiDoc => asciidoc::Transformer::generate(self),
+            #[cfg(not(feature = "asciidoc"))]
+            DocumentType::AsciiDoc => {
+                Err(anyhow::anyhow!("AsciiDoc feature is not enabled"))
+            }
+            #[cfg(feature = "rst")]
+            DocumentType::RST => rst::Transformer::generate(self),
+            #[cfg(not(feature = "rst"))]
+            DocumentType::RST => Err(anyhow::anyhow!("RST feature is not enabled")),
+            #[cfg(feature = "org")]
+            DocumentType::Org => org::Transformer::generate(self),
+            #[cfg(not(feature = "org"))]
+            DocumentType::Org => Err(anyhow::anyhow!("Org feature is not enabled")),
+            #[cfg(feature = "docbook")]
+            DocumentType::DocBook => docbook::Transformer::generate(self),
+            #[cfg(not(feature = "docbook"))]
+            DocumentType::DocBook => {
+                Err(anyhow::anyhow!("DocBook feature is not enabled"))
+            }
+            #[cfg(feature = "fb2")]
+            DocumentType::FB2 => fb2::Transformer::generate(self),
+            #[cfg(not(feature = "fb2"))]
+            DocumentType::FB2 => Err(anyhow::anyhow!("FB2 feature is not enabled")),
+            #[cfg(feature = "pptx")]
+            DocumentType::PPTX => pptx::Transformer::generate(self),
+            #[cfg(not(feature = "pptx"))]
+            DocumentType::PPTX => Err(anyhow::anyhow!("PPTX feature is not enabled")),
+            #[cfg(feature = "odp")]
+            DocumentType::ODP => odp::Transformer::generate(self),
+            #[cfg(not(feature = "odp"))]
+            DocumentType::ODP => Err(anyhow::anyhow!("ODP feature is not enabled")),
+            #[cfg(feature = "ipynb")]
+            DocumentType::Ipynb => ipynb::Transformer::generate(self),
+            #[cfg(not(feature = "ipynb"))]
+            DocumentType::Ipynb => {
+                Err(anyhow::anyhow!("Jupyter notebook feature is not enabled"))
+            }
+            #[cfg(feature = "mhtml")]
+            DocumentType::MHTML => mhtml::Transformer::generate(self),
+            #[cfg(not(feature = "mhtml"))]
+            DocumentType::MHTML => Err(anyhow::anyhow!("MHTML feature is not enabled")),
+            #[cfg(feature = "mediawiki")]
+            DocumentType::MediaWiki => mediawiki::Transformer::generate(self),
+            #[cfg(not(feature = "mediawiki"))]
+            DocumentType::MediaWiki => {
+                Err(anyhow::anyhow!("MediaWiki feature is not enabled"))
+            }
+            #[cfg(feature = "confluence")]
+            DocumentType::Confluence => confluence::Transformer::generate(self),
+            #[cfg(not(feature = "confluence"))]
+            DocumentType::Confluence => {
+                Err(anyhow::anyhow!("Confluence feature is not enabled"))
+            }
+            #[cfg(feature = "jira")]
+            DocumentType::Jira => jira::Transformer::generate(self),
+            #[cfg(not(feature = "jira"))]
+            DocumentType::Jira => Err(anyhow::anyhow!("Jira feature is not enabled")),
+            #[cfg(feature = "bbcode")]
+            DocumentType::BBCode => bbcode::Transformer::generate(self),
+            #[cfg(not(feature = "bbcode"))]
+            DocumentType::BBCode => Err(anyhow::anyhow!("BBCode feature is not enabled")),
+            #[cfg(feature = "textile")]
+            DocumentType::Textile => textile::Transformer::generate(self),
+            #[cfg(not(feature = "textile"))]
+            DocumentType::Textile => Err(anyhow::anyhow!("Textile feature is not enabled")),
+            #[cfg(feature = "man")]
+            DocumentType::Man => man::Transformer::generate(self),
+            #[cfg(not(feature = "man"))]
+            DocumentType::Man => Err(anyhow::anyhow!("Man page feature is not enabled")),
+            #[cfg(feature = "gemtext")]
+            DocumentType::Gemtext => gemtext::Transformer::generate(self),
+            #[cfg(not(feature = "gemtext"))]
+            DocumentType::Gemtext => Err(anyhow::anyhow!("Gemtext feature is not enabled")),
+            #[cfg(feature = "ansi")]
+            DocumentType::Ansi => ansi::Transformer::generate(self),
+            #[cfg(not(feature = "ansi"))]
+            DocumentType::Ansi => Err(anyhow::anyhow!("ANSI feature is not enabled")),
+            #[cfg(feature = "opml")]
+            DocumentType::Opml => opml::Transformer::generate(self),
+            #[cfg(not(feature = "opml"))]
+            DocumentType::Opml => Err(anyhow::anyhow!("OPML feature is not enabled")),
+            #[cfg(feature = "quill")]
+            DocumentType::Quill => quill::Transformer::generate(self),
+            #[cfg(not(feature = "quill"))]
+            DocumentType::Quill => Err(anyhow::anyhow!("Quill feature is not enabled")),
+            #[cfg(feature = "prosemirror")]
+            DocumentType::ProseMirror => prosemirror::Transformer::generate(self),
+            #[cfg(not(feature = "prosemirror"))]
+            DocumentType::ProseMirror => {
+                Err(anyhow::anyhow!("ProseMirror feature is not enabled"))
+            }
+            #[cfg(feature = "slack")]
+            DocumentType::Slack => slack::Transformer::generate(self),
+            #[cfg(not(feature = "slack"))]
+            DocumentType::Slack => Err(anyhow::anyhow!("Slack feature is not enabled")),
+            #[cfg(feature = "tsv")]
+            DocumentType::TSV => tsv::Transformer::generate(self),
+            #[cfg(not(feature = "tsv"))]
+            DocumentType::TSV => Err(anyhow::anyhow!("TSV feature is not enabled")),
+            #[cfg(feature = "ndjson")]
+            DocumentType::NDJSON => ndjson::Transformer::generate(self),
+            #[cfg(not(feature = "ndjson"))]
+            DocumentType::NDJSON => Err(anyhow::anyhow!("NDJSON feature is not enabled")),
+            #[cfg(feature = "yaml")]
+            DocumentType::YAML => yaml::Transformer::generate(self),
+            #[cfg(not(feature = "yaml"))]
+            DocumentType::YAML => Err(anyhow::anyhow!("YAML feature is not enabled")),
+            #[cfg(feature = "sqlite")]
+            DocumentType::SQLite => sqlite::Transformer::generate(self),
+            #[cfg(not(feature = "sqlite"))]
+            DocumentType::SQLite => Err(anyhow::anyhow!("SQLite feature is not enabled")),
+            #[cfg(feature = "arrow")]
+            DocumentType::Parquet => arrow::Transformer::generate(self),
+            #[cfg(not(feature = "arrow"))]
+            DocumentType::Parquet => Err(anyhow::anyhow!("Parquet feature is not enabled")),
+            #[cfg(feature = "svg")]
+            DocumentType::SVG => svg::Transformer::generate_with_options(self, &options.layout()),
+            #[cfg(not(feature = "svg"))]
+            DocumentType::SVG => Err(anyhow::anyhow!("SVG feature is not enabled")),
+            #[cfg(feature = "png")]
+            DocumentType::PNG => {
+                let png_options = png::PngOptions {
+                    layout: options.layout(),
+                    ..png::PngOptions::default()
+                };
+                png::Transformer::generate_with_options(self, &png_options)
+            }
+            #[cfg(not(feature = "png"))]
+            DocumentType::PNG => Err(anyhow::anyhow!("PNG feature is not enabled")),
+            #[cfg(feature = "postscript")]
+            DocumentType::PostScript => {
+                let postscript_options = postscript::PostScriptOptions {
+                    layout: options.layout(),
+                    ..postscript::PostScriptOptions::default()
+                };
+                postscript::Transformer::generate_with_options(self, &postscript_options)
+            }
+            #[cfg(not(feature = "postscript"))]
+            DocumentType::PostScript => {
+                Err(anyhow::anyhow!("PostScript feature is not enabled"))
+            }
+            #[cfg(feature = "djvu")]
+            DocumentType::DjVu => djvu::Transformer::generate(self),
+            #[cfg(not(feature = "djvu"))]
+            DocumentType::DjVu => Err(anyhow::anyhow!("DjVu feature is not enabled")),
+            #[cfg(feature = "mobi")]
+            DocumentType::MOBI => mobi::Transformer::generate(self),
+            #[cfg(not(feature = "mobi"))]
+            DocumentType::MOBI => Err(anyhow::anyhow!("MOBI feature is not enabled")),
+            #[cfg(feature = "daisy")]
+            DocumentType::DAISY => daisy::Transformer::generate(self),
+            #[cfg(not(feature = "daisy"))]
+            DocumentType::DAISY => Err(anyhow::anyhow!("DAISY feature is not enabled")),
+            #[cfg(feature = "typst")]
+            DocumentType::Typst => typst::Transformer::generate(self),
+            #[cfg(not(feature = "typst"))]
+            DocumentType::Typst => Err(anyhow::anyhow!("Typst feature is not enabled")),
+        }
     }
 
-    /// Generate output with a custom image saver (for markdown with Base64 images)
+    /// Generates the document saving images with `image_saver`, for the formats that refer to
+    /// images by name. Other formats are generated as by [`Document::generate`]
     pub fn generate_with_saver<F>(&self, document_type: DocumentType, image_saver: F) -> anyhow::Result<Bytes>
     where
         F: Fn(&Bytes, &str) -> anyhow::Result<()> {
+        let operation =
+            telemetry::Operation::generate(document_type, self.get_all_elements().len());
+        let result = self
+            .generate_by_type_with_saver(document_type, image_saver)
+            .unwrap_or_else(|| self.generate_by_type(document_type, &GeneratorOptions::default()));
+        operation.finish_generate(&result);
+        result
+    }
+
+    /// `None` for the formats without an image saver and those registered in place of a
+    /// built-in one
+    fn generate_by_type_with_saver<F>(
+        &self,
+        document_type: DocumentType,
+        // Unused when none of the formats with an image saver is enabled
+        #[allow(unused_variables)] image_saver: F,
+    ) -> Option<anyhow::Result<Bytes>>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        if FormatRegistry::global().by_type(document_type).is_some() {
+            return None;
+        }
         match document_type {
             #[cfg(feature = "markdown")]
             DocumentType::Markdown => {
-                crate::markdown::Transformer::generate_with_saver(self, image_saver)
+                Some(crate::markdown::Transformer::generate_with_saver(self, image_saver))
             }
             #[cfg(feature = "html")]
             DocumentType::HTML => {
-                crate::html::Transformer::generate_with_saver(self, image_saver)
+                Some(crate::html::Transformer::generate_with_saver(self, image_saver))
+            }
+            #[cfg(feature = "latex")]
+            DocumentType::LaTeX => {
+                Some(crate::latex::Transformer::generate_with_saver(self, image_saver))
+            }
+            #[cfg(feature = "asciidoc")]
+            DocumentType::AsciiDoc => {
+                Some(crate::asciidoc::Transformer::generate_with_saver(self, image_saver))
+            }
+            #[cfg(feature = "rst")]
+            DocumentType::RST => {
+                Some(crate::rst::Transformer::generate_with_saver(self, image_saver))
+            }
+            #[cfg(feature = "org")]
+            DocumentType::Org => {
+                Some(crate::org::Transformer::generate_with_saver(self, image_saver))
+            }
+            #[cfg(feature = "docbook")]
+            DocumentType::DocBook => {
+                Some(crate::docbook::Transformer::generate_with_saver(self, image_saver))
+            }
+            #[cfg(feature = "mediawiki")]
+            DocumentType::MediaWiki => {
+                Some(crate::mediawiki::Transformer::generate_with_saver(self, image_saver))
+            }
+            #[cfg(feature = "confluence")]
+            DocumentType::Confluence => {
+                Some(crate::confluence::Transformer::generate_with_saver(self, image_saver))
+            }
+            #[cfg(feature = "jira")]
+            DocumentType::Jira => {
+                Some(crate::jira::Transformer::generate_with_saver(self, image_saver))
+            }
+            #[cfg(feature = "bbcode")]
+            DocumentType::BBCode => {
+                Some(crate::bbcode::Transformer::generate_with_saver(self, image_saver))
+            }
+            #[cfg(feature = "textile")]
+            DocumentType::Textile => {
+                Some(crate::textile::Transformer::generate_with_saver(self, image_saver))
+            }
+            #[cfg(feature = "gemtext")]
+            DocumentType::Gemtext => {
+                Some(crate::gemtext::Transformer::generate_with_saver(self, image_saver))
+            }
+            #[cfg(feature = "prosemirror")]
+            DocumentType::ProseMirror => {
+                Some(crate::prosemirror::Transformer::generate_with_saver(self, image_saver))
+            }
+            #[cfg(feature = "typst")]
+            DocumentType::Typst => {
+                Some(crate::typst::Transformer::generate_with_saver(self, image_saver))
+            }
+            _ => None,
+        }
+    }
+
+    /// Removes comments from all bands, including comments nested in paragraphs, blockquotes,
+    /// footnotes, styled spans, lists and definition lists
+    pub fn strip_comments(&mut self) {
+        fn strip(elements: &mut Vec<Element>) {
+            elements.retain(|element| !matches!(element, Element::Comment { .. }));
+            elements.iter_mut().for_each(strip_nested);
+        }
+        fn strip_nested(element: &mut Element) {
+            match element {
+                Element::Paragraph { elements }
+                | Element::Blockquote { elements, .. }
+                | Element::Styled { elements, .. }
+                | Element::Section {
+                    children: elements, ..
+                }
+                | Element::Footnote {
+                    content: elements, ..
+                } => strip(elements),
+                Element::List { elements, .. } => {
+                    elements.retain(|item| !matches!(item.element, Element::Comment { .. }));
+                    for item in elements.iter_mut() {
+                        strip_nested(&mut item.element);
+                    }
+                }
+                Element::DefinitionList { items } => {
+                    for item in items.iter_mut() {
+                        strip(&mut item.term);
+                        strip(&mut item.definitions);
+                    }
+                }
+                _ => {}
+            }
+        }
+        for band in &mut self.bands {
+            strip(band.elements_mut());
+        }
+    }
+
+    /// Replaces `:name:` shortcodes in the text of the document with their emoji, for sources
+    /// such as chat exports that write emoji as shortcodes. Code, formulas and comments are left
+    /// as they are, as are shortcodes with no known emoji.
+    pub fn replace_emoji_shortcodes(&mut self) {
+        for band in &mut self.bands {
+            map_text(band.elements_mut(), &emoji::shortcodes_to_emoji);
+        }
+    }
+
+    /// Replaces emoji in the text of the document with their shortcodes, for targets such as
+    /// Slack and Jira that expect emoji written as `:name:`
+    pub fn replace_emoji_with_shortcodes(&mut self) {
+        for band in &mut self.bands {
+            map_text(band.elements_mut(), &emoji::emoji_to_shortcodes);
+        }
+    }
+
+    /// Replaces each match of `pattern` in the text of the document, headers, hyperlink titles and
+    /// the page header and footer included, and returns the number of matches replaced. With a
+    /// regex pattern `replacement` may refer to its groups as `$1` or `$name`. Matches are found
+    /// within a single run of text, code, formulas and comments are left as they are.
+    pub fn replace_text(&mut self, pattern: impl Into<TextPattern>, replacement: &str) -> usize {
+        let pattern = pattern.into();
+        let count = std::cell::Cell::new(0);
+        let replace = |text: &str| -> String {
+            match &pattern {
+                TextPattern::Plain(plain) if !plain.is_empty() => {
+                    count.set(count.get() + text.matches(plain.as_str()).count());
+                    text.replace(plain.as_str(), replacement)
+                }
+                TextPattern::Plain(_) => text.to_string(),
+                #[cfg(feature = "regex")]
+                TextPattern::Regex(regex) => {
+                    count.set(count.get() + regex.find_iter(text).count());
+                    regex.replace_all(text, replacement).into_owned()
+                }
+            }
+        };
+        for band in &mut self.bands {
+            map_text(band.elements_mut(), &replace);
+        }
+        count.get()
+    }
+
+    /// Returns a copy of the document containing only the elements matching `predicate`.
+    ///
+    /// Paragraphs, blockquotes, sections, footnotes, styled spans, lists and definition lists are
+    /// filtered recursively and removed when the filter leaves them empty. Table cells and
+    /// captions are filtered too, a cell left empty stays so that the columns line up, a header
+    /// left empty holds an empty paragraph. Bands, page format and orientation are preserved.
+    pub fn filter(&self, predicate: impl Fn(&Element) -> bool) -> Document {
+        fn filter_elements(
+            elements: &[Element],
+            predicate: &dyn Fn(&Element) -> bool,
+        ) -> Vec<Element> {
+            elements
+                .iter()
+                .filter_map(|element| filter_element(element, predicate))
+                .collect()
+        }
+        fn filter_element(
+            element: &Element,
+            predicate: &dyn Fn(&Element) -> bool,
+        ) -> Option<Element> {
+            if !predicate(element) {
+                return None;
+            }
+            match element {
+                Element::Paragraph { elements } => {
+                    let filtered = filter_elements(elements, predicate);
+                    if filtered.is_empty() && !elements.is_empty() {
+                        return None;
+                    }
+                    Some(Element::Paragraph { elements: filtered })
+                }
+                Element::Blockquote {
+                    elements,
+                    attribution,
+                } => {
+                    let filtered = filter_elements(elements, predicate);
+                    if filtered.is_empty() && !elements.is_empty() {
+                        return None;
+                    }
+                    Some(Element::Blockquote {
+                        elements: filtered,
+                        attribution: attribution.clone(),
+                    })
+                }
+                Element::Styled { style, elements } => {
+                    let filtered = filter_elements(elements, predicate);
+                    if filtered.is_empty() && !elements.is_empty() {
+                        return None;
+                    }
+                    Some(Element::Styled {
+                        style: *style,
+                        elements: filtered,
+                    })
+                }
+                Element::Section { level, children } => {
+                    let filtered = filter_elements(children, predicate);
+                    if filtered.is_empty() && !children.is_empty() {
+                        return None;
+                    }
+                    Some(Element::Section {
+                        level: *level,
+                        children: filtered,
+                    })
+                }
+                Element::Footnote { id, content } => {
+                    let filtered = filter_elements(content, predicate);
+                    if filtered.is_empty() && !content.is_empty() {
+                        return None;
+                    }
+                    Some(Element::Footnote {
+                        id: id.clone(),
+                        content: filtered,
+                    })
+                }
+                Element::List { elements, numbered } => {
+                    let filtered: Vec<ListItem> = elements
+                        .iter()
+                        .filter_map(|item| {
+                            filter_element(&item.element, predicate).map(|element| ListItem {
+                                element,
+                                checked: item.checked,
+                            })
+                        })
+                        .collect();
+                    if filtered.is_empty() && !elements.is_empty() {
+                        return None;
+                    }
+                    Some(Element::List {
+                        elements: filtered,
+                        numbered: *numbered,
+                    })
+                }
+                Element::DefinitionList { items } => {
+                    // An item goes with its term, its definitions are filtered
+                    let filtered: Vec<DefinitionItem> = items
+                        .iter()
+                        .filter(|item| item.term.iter().all(predicate))
+                        .map(|item| DefinitionItem {
+                            term: item.term.clone(),
+                            definitions: filter_elements(&item.definitions, predicate),
+                        })
+                        .collect();
+                    if filtered.is_empty() && !items.is_empty() {
+                        return None;
+                    }
+                    Some(Element::DefinitionList { items: filtered })
+                }
+                Element::Table {
+                    headers,
+                    rows,
+                    alignments,
+                    caption,
+                } => Some(Element::Table {
+                    headers: headers
+                        .iter()
+                        .map(|header| TableHeader {
+                            element: filter_element(&header.element, predicate).unwrap_or(
+                                Element::Paragraph { elements: vec![] },
+                            ),
+                            width: header.width,
+                        })
+                        .collect(),
+                    rows: rows
+                        .iter()
+                        .map(|row| TableRow {
+                            cells: row
+                                .cells
+                                .iter()
+                                .map(|cell| TableCell {
+                                    elements: filter_elements(&cell.elements, predicate),
+                                    ..cell.clone()
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                    alignments: alignments.clone(),
+                    caption: filter_elements(caption, predicate),
+                }),
+                _ => Some(element.clone()),
+            }
+        }
+
+        let bands = self
+            .bands
+            .iter()
+            .map(|band| band.with_elements(filter_elements(band.elements(), &predicate)))
+            .collect();
+        Document {
+            bands,
+            page_format: self.page_format.clone(),
+            orientation: self.orientation.clone(),
+            direction: self.direction,
+            metadata: self.metadata.clone(),
+            source_spans: BTreeMap::new(),
+        }
+    }
+
+    /// Returns a copy of the document with each table of contents replaced by the nested list of
+    /// its entries, for formats without a table of contents of their own
+    pub fn expand_toc(&self) -> Document {
+        fn expand(document: &Document, elements: &[Element]) -> Vec<Element> {
+            elements
+                .iter()
+                .map(|element| match element {
+                    Element::TableOfContents { max_level } => {
+                        TocEntry::list(&document.toc_entries(*max_level))
+                    }
+                    Element::Section { level, children } => Element::Section {
+                        level: *level,
+                        children: expand(document, children),
+                    },
+                    element => element.clone(),
+                })
+                .collect()
+        }
+        let bands = self
+            .bands
+            .iter()
+            .map(|band| band.with_elements(expand(self, band.elements())))
+            .collect();
+        Document {
+            bands,
+            page_format: self.page_format.clone(),
+            orientation: self.orientation.clone(),
+            direction: self.direction,
+            metadata: self.metadata.clone(),
+            source_spans: BTreeMap::new(),
+        }
+    }
+
+    /// Returns a copy of the document with the elements of each band nested in sections, one for
+    /// each header holding what follows it up to the next header of the same or a higher level.
+    /// Sections already in the document are flattened first.
+    pub fn nest_sections(&self) -> Document {
+        fn close(open: &mut Vec<(u8, Vec<Element>)>, root: &mut Vec<Element>) {
+            if let Some((level, children)) = open.pop() {
+                let section = Element::Section { level, children };
+                match open.last_mut() {
+                    Some((_, parent)) => parent.push(section),
+                    None => root.push(section),
+                }
+            }
+        }
+        let flat = self.flatten_sections();
+        let bands = flat
+            .bands
+            .iter()
+            .map(|band| {
+                let mut root = Vec::new();
+                let mut open: Vec<(u8, Vec<Element>)> = Vec::new();
+                for element in band.elements() {
+                    if let Element::Header { level, .. } = element {
+                        while open.last().is_some_and(|(open, _)| open >= level) {
+                            close(&mut open, &mut root);
+                        }
+                        open.push((*level, vec![element.clone()]));
+                    } else if let Some((_, children)) = open.last_mut() {
+                        children.push(element.clone());
+                    } else {
+                        root.push(element.clone());
+                    }
+                }
+                while !open.is_empty() {
+                    close(&mut open, &mut root);
+                }
+                band.with_elements(root)
+            })
+            .collect();
+        Document { bands, ..flat }
+    }
+
+    /// Returns a copy of the document with each section replaced by its children, the flat form
+    /// parsers produce
+    pub fn flatten_sections(&self) -> Document {
+        let bands = self
+            .bands
+            .iter()
+            .map(|band| {
+                band.with_elements(
+                    unnest_sections(band.elements())
+                        .into_iter()
+                        .cloned()
+                        .collect(),
+                )
+            })
+            .collect();
+        Document {
+            bands,
+            page_format: self.page_format.clone(),
+            orientation: self.orientation.clone(),
+            direction: self.direction,
+            metadata: self.metadata.clone(),
+            source_spans: BTreeMap::new(),
+        }
+    }
+
+    /// Returns a copy of the document with each custom element replaced by the elements `render`
+    /// returns for its kind, data and children. Custom elements `render` returns `None` for are
+    /// left out, custom elements nested in blocks and spans are replaced as well.
+    pub fn resolve_custom(
+        &self,
+        render: impl Fn(&str, &serde_json::Value, &[Element]) -> Option<Vec<Element>>,
+    ) -> Document {
+        type Render<'a> = &'a dyn Fn(&str, &serde_json::Value, &[Element]) -> Option<Vec<Element>>;
+        fn resolve_elements(elements: &[Element], render: Render) -> Vec<Element> {
+            elements
+                .iter()
+                .flat_map(|element| resolve_element(element, render))
+                .collect()
+        }
+        fn resolve_element(element: &Element, render: Render) -> Vec<Element> {
+            let resolved = match element {
+                Element::Custom {
+                    kind,
+                    data,
+                    children,
+                } => {
+                    let rendered = render(kind, data, children).unwrap_or_default();
+                    return resolve_elements(&rendered, render);
+                }
+                Element::Paragraph { elements } => Element::Paragraph {
+                    elements: resolve_elements(elements, render),
+                },
+                Element::Blockquote {
+                    elements,
+                    attribution,
+                } => Element::Blockquote {
+                    elements: resolve_elements(elements, render),
+                    attribution: resolve_elements(attribution, render),
+                },
+                Element::Styled { style, elements } => Element::Styled {
+                    style: *style,
+                    elements: resolve_elements(elements, render),
+                },
+                Element::Section { level, children } => Element::Section {
+                    level: *level,
+                    children: resolve_elements(children, render),
+                },
+                Element::Footnote { id, content } => Element::Footnote {
+                    id: id.clone(),
+                    content: resolve_elements(content, render),
+                },
+                Element::List { elements, numbered } => Element::List {
+                    elements: elements
+                        .iter()
+                        .flat_map(|item| {
+                            resolve_element(&item.element, render)
+                                .into_iter()
+                                .map(|element| ListItem {
+                                    element,
+                                    checked: item.checked,
+                                })
+                        })
+                        .collect(),
+                    numbered: *numbered,
+                },
+                Element::DefinitionList { items } => Element::DefinitionList {
+                    items: items
+                        .iter()
+                        .map(|item| DefinitionItem {
+                            term: resolve_elements(&item.term, render),
+                            definitions: resolve_elements(&item.definitions, render),
+                        })
+                        .collect(),
+                },
+                Element::Table {
+                    headers,
+                    rows,
+                    alignments,
+                    caption,
+                } => Element::Table {
+                    headers: headers.clone(),
+                    rows: rows
+                        .iter()
+                        .map(|row| TableRow {
+                            cells: row
+                                .cells
+                                .iter()
+                                .map(|cell| TableCell {
+                                    elements: resolve_elements(&cell.elements, render),
+                                    ..cell.clone()
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                    alignments: alignments.clone(),
+                    caption: resolve_elements(caption, render),
+                },
+                element => element.clone(),
+            };
+            vec![resolved]
+        }
+
+        let bands = self
+            .bands
+            .iter()
+            .map(|band| band.with_elements(resolve_elements(band.elements(), &render)))
+            .collect();
+        Document {
+            bands,
+            page_format: self.page_format.clone(),
+            orientation: self.orientation.clone(),
+            direction: self.direction,
+            metadata: self.metadata.clone(),
+            source_spans: BTreeMap::new(),
+        }
+    }
+
+    /// Generates the document after resolving its custom elements with `render`, see
+    /// [`Document::resolve_custom`]
+    pub fn generate_with_custom(
+        &self,
+        document_type: DocumentType,
+        render: impl Fn(&str, &serde_json::Value, &[Element]) -> Option<Vec<Element>>,
+    ) -> anyhow::Result<Bytes> {
+        self.resolve_custom(render).generate(document_type)
+    }
+
+    /// Visits every band and, depth first, every element of the document, the elements of
+    /// paragraphs, lists, tables and the other containers included
+    pub fn walk(&self, visitor: &mut impl ElementVisitor) {
+        for band in &self.bands {
+            visitor.visit_band(band);
+            walk_elements(band.elements(), visitor);
+        }
+    }
+
+    /// Visits every band and element as [`Document::walk`] does, allowing the visitor to change
+    /// them. An element is walked into after it is visited, so the elements a visitor puts in
+    /// its place are walked as well
+    pub fn walk_mut(&mut self, visitor: &mut impl ElementVisitorMut) {
+        for band in &mut self.bands {
+            visitor.visit_band(band);
+            walk_elements_mut(band.elements_mut(), visitor);
+        }
+    }
+
+    /// Every element of the document, depth first and in document order as
+    /// [`Document::walk`] visits them, each with the band and element it is in
+    pub fn iter_elements(&self) -> ElementIter<'_> {
+        ElementIter {
+            bands: self.bands.iter(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// The elements of a kind, the nested ones included, in document order
+    pub fn select(&self, kind: ElementKind) -> Vec<&Element> {
+        self.select_where(|element| element.kind() == kind)
+    }
+
+    /// Copies of the elements of a kind, for keeping them once the document is dropped
+    pub fn select_cloned(&self, kind: ElementKind) -> Vec<Element> {
+        self.select(kind).into_iter().cloned().collect()
+    }
+
+    /// The headers of a level, the ones nested in sections included
+    pub fn select_headers(&self, level: u8) -> Vec<&Element> {
+        self.select_where(|element| match element {
+            Element::Header {
+                level: header_level,
+                ..
+            } => *header_level == level,
+            _ => false,
+        })
+    }
+
+    /// The elements `predicate` holds for, the nested ones included, in document order
+    pub fn select_where(&self, predicate: impl Fn(&Element) -> bool) -> Vec<&Element> {
+        self.iter_elements()
+            .map(|item| item.element)
+            .filter(|element| predicate(element))
+            .collect()
+    }
+
+    /// The text of the document without markup, each block and band separated by a blank line,
+    /// see [`Element::plain_text`]. Unlike the text generator it writes no list markers, table
+    /// borders or other markdown-like decoration.
+    pub fn to_plain_text(&self) -> String {
+        let texts = self.bands.iter().map(|band| blocks_text(band.elements(), "\n\n"));
+        join_non_empty(texts, "\n\n")
+    }
+
+    /// Counts of the words, characters, elements, images and tables of the document, with an
+    /// estimate of the pages it fills, to tell its size before converting it
+    pub fn stats(&self) -> DocumentStats {
+        let text = self.to_plain_text();
+        let mut stats = DocumentStats {
+            words: text.split_whitespace().count(),
+            characters: text.chars().count(),
+            characters_without_spaces: text.chars().filter(|c| !c.is_whitespace()).count(),
+            estimated_pages: self.estimate_pages(),
+            ..DocumentStats::default()
+        };
+        for item in self.iter_elements() {
+            *stats.elements.entry(item.element.kind()).or_default() += 1;
+            let image = match item.element {
+                Element::Image(image) | Element::Figure { image, .. } => Some(image),
+                Element::Media { poster, .. } => poster.as_ref(),
+                _ => None,
+            };
+            if let Some(image) = image {
+                stats.images += 1;
+                stats.image_bytes += image.bytes().len();
+            }
+            if let Element::Table { headers, rows, .. } = item.element {
+                let columns = rows
+                    .iter()
+                    .map(|row| row.cells.iter().map(|cell| cell.colspan.max(1)).sum())
+                    .chain([headers.len()])
+                    .max()
+                    .unwrap_or(0);
+                stats.tables.push(TableStats {
+                    rows: rows.len(),
+                    columns,
+                });
+            }
+        }
+        stats
+    }
+
+    /// Pages the detail band fills at the page format and orientation, taking a character as
+    /// 2.2 mm wide, a line as 6 mm high and an image as a third of a page
+    fn estimate_pages(&self) -> usize {
+        let dimensions = self.page_format.dimensions();
+        let (width, height) = match self.orientation {
+            PageOrientation::Portrait => (dimensions.page_width, dimensions.page_height),
+            PageOrientation::Landscape => (dimensions.page_height, dimensions.page_width),
+        };
+        let width = width - dimensions.page_margin_left - dimensions.page_margin_right;
+        let height = height - dimensions.page_margin_top - dimensions.page_margin_bottom;
+        let characters_per_line = ((width / 2.2) as usize).max(1);
+        let lines_per_page = ((height / 6.0) as usize).max(1);
+
+        let mut pages = 0;
+        let mut lines: usize = 0;
+        for element in unnest_sections(self.get_detail()) {
+            if let Element::PageBreak = element {
+                pages += lines.div_ceil(lines_per_page).max(1);
+                lines = 0;
+                continue;
+            }
+            let mut stack = vec![element];
+            while let Some(element) = stack.pop() {
+                if let Element::Image(_) | Element::Figure { .. } = element {
+                    lines += lines_per_page / 3;
+                }
+                stack.extend(child_elements(element));
+            }
+            let text = element.plain_text();
+            if !text.is_empty() {
+                let wrapped = text.lines().map(|line| {
+                    line.chars().count().div_ceil(characters_per_line).max(1)
+                });
+                // The blank line after the block
+                lines += wrapped.sum::<usize>() + 1;
             }
-            _ => self.generate(document_type),
         }
+        pages + lines.div_ceil(lines_per_page).max(1)
+    }
+
+    /// Where the element at `path` was found in the input, or the closest element holding it
+    /// that parsers recorded. The path is the index of the band, then of the element in the band
+    /// and of each nested element, as [`crate::validate::Issue`] gives it. Paths change when the
+    /// content is edited, copies made by filtering, nesting and splitting have no spans.
+    pub fn source_span(&self, path: &[usize]) -> Option<&SourceSpan> {
+        (1..=path.len())
+            .rev()
+            .find_map(|length| self.source_spans.get(&path[..length]))
     }
 
     /// Returns all elements from all bands
@@ -347,11 +1622,114 @@ impl Document {
         elements
     }
 
+    /// Footnote contents by id, for generators that place the note at its reference
+    pub fn footnotes(&self) -> HashMap<&str, &[Element]> {
+        unnest_sections(self.get_all_elements())
+            .into_iter()
+            .filter_map(|element| match element {
+                Element::Footnote { id, content } => Some((id.as_str(), content.as_slice())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Headers of all bands down to `max_level` as a tree, the entries of a table of contents
+    pub fn toc_entries(&self, max_level: u8) -> Vec<TocEntry> {
+        fn insert(entries: &mut Vec<TocEntry>, entry: TocEntry) {
+            match entries.last_mut() {
+                Some(last) if last.level < entry.level => insert(&mut last.children, entry),
+                _ => entries.push(entry),
+            }
+        }
+        let mut entries = Vec::new();
+        for element in unnest_sections(self.get_all_elements()) {
+            if let Element::Header {
+                level,
+                text,
+                anchor,
+            } = element
+            {
+                if *level <= max_level {
+                    let entry = TocEntry {
+                        level: *level,
+                        text: text.clone(),
+                        anchor: anchor.clone(),
+                        children: vec![],
+                    };
+                    insert(&mut entries, entry);
+                }
+            }
+        }
+        entries
+    }
+
+    /// Inserts a table of contents at the start of the detail band, after a level 1 title.
+    /// Headers without an anchor get one made from their text so the entries can link to them
+    pub fn insert_toc(&mut self, max_level: u8) {
+        fn add_anchors(elements: &mut [Element], anchors: &mut Vec<String>) {
+            for element in elements {
+                match element {
+                    Element::Header {
+                        text,
+                        anchor: anchor @ None,
+                        ..
+                    } => {
+                        let slug = slug(text);
+                        let mut unique = slug.clone();
+                        let mut count = 1;
+                        while anchors.contains(&unique) {
+                            count += 1;
+                            unique = format!("{}-{}", slug, count);
+                        }
+                        anchors.push(unique.clone());
+                        *anchor = Some(unique);
+                    }
+                    Element::Section { children, .. } => add_anchors(children, anchors),
+                    _ => {}
+                }
+            }
+        }
+        let mut anchors: Vec<String> = unnest_sections(self.get_all_elements())
+            .into_iter()
+            .filter_map(|element| match element {
+                Element::Header {
+                    anchor: Some(anchor),
+                    ..
+                } => Some(anchor.clone()),
+                _ => None,
+            })
+            .collect();
+        for band in &mut self.bands {
+            add_anchors(band.elements_mut(), &mut anchors);
+        }
+        let toc = Element::TableOfContents { max_level };
+        match self
+            .bands
+            .iter_mut()
+            .find(|band| matches!(band, Band::Detail(_)))
+        {
+            Some(band) => {
+                let elements = band.elements_mut();
+                let position = match elements.first() {
+                    Some(Element::Header { level: 1, .. }) => 1,
+                    _ => 0,
+                };
+                elements.insert(position, toc);
+            }
+            None => self.bands.push(Band::Detail(vec![toc])),
+        }
+    }
+
     /// Returns all elements from a specific band
     pub fn get_elements_by_band(&self, band: &Band) -> Vec<&Element> {
         let mut elements = Vec::new();
         for b in &self.bands {
-            if b == band {
+            // Bands of a kind match whatever their elements, custom bands by name
+            let matches = match (b, band) {
+                (Band::Custom(name, _), Band::Custom(other, _)) => name == other,
+                _ => std::mem::discriminant(b) == std::mem::discriminant(band),
+            };
+            if matches {
                 elements.extend(b.elements());
             }
         }
@@ -517,66 +1895,1988 @@ impl Document {
     pub fn remove_all_bands(&mut self) {
         self.bands.clear();
     }
-}
 
-pub trait TransformerTrait {
-    fn parse(document: &Bytes) -> anyhow::Result<Document>;
-    fn generate(document: &Document) -> anyhow::Result<Bytes>;
-}
+    /// Adds the content of `other` after the content of the document, see
+    /// [`Document::append_with_options`]
+    pub fn append(&mut self, other: Document) {
+        self.append_with_options(other, &MergeOptions::default());
+    }
 
-pub trait TransformerWithImageLoaderSaverTrait {
-    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
-    where
-        F: Fn(&str) -> anyhow::Result<Bytes>;
-    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
-    where
-        F: Fn(&Bytes, &str) -> anyhow::Result<()>;
-}
-#[derive(Error, Debug)]
-pub enum ParserError {
-    #[error("Parser error")]
-    Common,
-}
-#[derive(Error, Debug)]
-pub enum GeneratorError {
-    #[error("Generator error")]
-    Common,
-}
-#[derive(Debug, Clone, PartialEq)]
-#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
-pub enum Element {
-    Text {
-        text: String,
-        size: u8,
-    },
-    Header {
-        level: u8,
-        text: String,
-    },
-    Paragraph {
-        elements: Vec<Element>,
-    },
-    Table {
-        headers: Vec<TableHeader>,
-        rows: Vec<TableRow>,
+    /// Adds the content of `other` after the content of the document. Bands of the same kind are
+    /// joined, except the page header and footer: repeated on every page, the document keeps its
+    /// own and takes those of `other` only where it has none. Footnote ids and header anchors of
+    /// `other` the document has already are renumbered, with the links to them. Images have
+    /// nothing to renumber, generators number them as they write them.
+    pub fn append_with_options(&mut self, mut other: Document, options: &MergeOptions) {
+        other.renumber_ids(self);
+        let has_detail = self
+            .bands
+            .iter()
+            .any(|band| matches!(band, Band::Detail(elements) if !elements.is_empty()));
+        for mut band in other.bands {
+            if options.page_breaks && has_detail {
+                if let Band::Detail(elements) = &mut band {
+                    if !elements.is_empty() {
+                        elements.insert(0, Element::PageBreak);
+                    }
+                }
+            }
+            let same = self
+                .bands
+                .iter_mut()
+                .find(|existing| match (&**existing, &band) {
+                    (Band::Custom(name, _), Band::Custom(other_name, _)) => name == other_name,
+                    (existing, band) => {
+                        std::mem::discriminant(existing) == std::mem::discriminant(band)
+                    }
+                });
+            match (same, band) {
+                (None, band) => self.bands.push(band),
+                (Some(existing), Band::PageHeader(elements) | Band::PageFooter(elements)) => {
+                    if existing.elements().is_empty() {
+                        *existing.elements_mut() = elements;
+                    }
+                }
+                (Some(existing), band) => existing.elements_mut().extend(band.elements().clone()),
+            }
+        }
+        self.metadata.fill_from(other.metadata);
+    }
+
+    /// One document with the content of all of them in order, see [`Document::append`]
+    pub fn merge(documents: Vec<Document>) -> Document {
+        Document::merge_with_options(documents, &MergeOptions::default())
+    }
+
+    /// One document with the content of all of them in order, the first sets the page format,
+    /// orientation and direction
+    pub fn merge_with_options(documents: Vec<Document>, options: &MergeOptions) -> Document {
+        let mut documents = documents.into_iter();
+        let Some(mut merged) = documents.next() else {
+            return Document::new(vec![]);
+        };
+        for document in documents {
+            merged.append_with_options(document, options);
+        }
+        merged
+    }
+
+    /// Splits the document before each header of `level` or a higher level, what comes before the
+    /// first one is a part of its own. The parts share the bands as in
+    /// [`Document::split_by_page_breaks`].
+    pub fn split_by_heading(&self, level: u8) -> Vec<Document> {
+        self.split_detail(
+            |element| matches!(element, Element::Header { level: l, .. } if *l <= level),
+            true,
+        )
+    }
+
+    /// Splits the document at the page breaks of the detail band, which are left out. Each part
+    /// repeats the page and column headers and footers and keeps the page setup and metadata, the
+    /// title and custom bands go with the first part and the summary with the last. Footnotes are
+    /// moved to the part referencing them.
+    pub fn split_by_page_breaks(&self) -> Vec<Document> {
+        self.split_detail(|element| matches!(element, Element::PageBreak), false)
+    }
+
+    /// Splits the detail band before each element `starts` is true for, which is kept at the start
+    /// of the new part with `keep`
+    fn split_detail(&self, starts: impl Fn(&Element) -> bool, keep: bool) -> Vec<Document> {
+        fn references(elements: &[Element]) -> HashSet<String> {
+            let mut references = HashSet::new();
+            let mut stack: Vec<&Element> = elements.iter().collect();
+            while let Some(element) = stack.pop() {
+                if let Element::FootnoteReference { id } = element {
+                    references.insert(id.clone());
+                }
+                stack.extend(child_elements(element));
+            }
+            references
+        }
+
+        let flat = self.flatten_sections();
+        let mut parts: Vec<Vec<Element>> = vec![Vec::new()];
+        for element in flat.get_detail() {
+            if starts(element) {
+                parts.push(Vec::new());
+                if !keep {
+                    continue;
+                }
+            }
+            if let Some(part) = parts.last_mut() {
+                part.push(element.clone());
+            }
+        }
+
+        let references: Vec<HashSet<String>> = parts.iter().map(|part| references(part)).collect();
+        let mut moved: Vec<Vec<Element>> = vec![Vec::new(); parts.len()];
+        for (index, part) in parts.iter_mut().enumerate() {
+            part.retain(|element| match element {
+                Element::Footnote { id, .. } if !references[index].contains(id) => {
+                    match references.iter().position(|part| part.contains(id)) {
+                        Some(target) => {
+                            moved[target].push(element.clone());
+                            false
+                        }
+                        None => true,
+                    }
+                }
+                _ => true,
+            });
+        }
+        for (part, moved) in parts.iter_mut().zip(moved) {
+            part.extend(moved);
+        }
+        parts.retain(|part| !part.is_empty());
+        if parts.is_empty() {
+            parts.push(Vec::new());
+        }
+
+        let last = parts.len() - 1;
+        parts
+            .into_iter()
+            .enumerate()
+            .map(|(index, elements)| {
+                let mut detail = Some(elements);
+                let bands = flat
+                    .bands
+                    .iter()
+                    .filter_map(|band| match band {
+                        Band::Detail(_) => detail.take().map(Band::Detail),
+                        Band::Title(_) | Band::Custom(..) if index > 0 => None,
+                        Band::Summary(_) if index < last => None,
+                        band => Some(band.clone()),
+                    })
+                    .collect();
+                Document {
+                    bands,
+                    page_format: flat.page_format.clone(),
+                    orientation: flat.orientation.clone(),
+                    direction: flat.direction,
+                    metadata: flat.metadata.clone(),
+                    source_spans: BTreeMap::new(),
+                }
+            })
+            .collect()
+    }
+
+    /// Gives the footnotes and header anchors `other` has as well new ids, links to the anchors
+    /// are changed along with them
+    fn renumber_ids(&mut self, other: &Document) {
+        fn ids(document: &Document) -> (HashSet<String>, HashSet<String>) {
+            let mut footnotes = HashSet::new();
+            let mut anchors = HashSet::new();
+            for item in document.iter_elements() {
+                match item.element {
+                    Element::Footnote { id, .. } | Element::FootnoteReference { id } => {
+                        footnotes.insert(id.clone());
+                    }
+                    Element::Header {
+                        anchor: Some(anchor),
+                        ..
+                    } => {
+                        anchors.insert(anchor.clone());
+                    }
+                    _ => {}
+                }
+            }
+            (footnotes, anchors)
+        }
+        /// New ids for the ids in `taken`, numbers are continued after the highest one
+        fn renames(ids: HashSet<String>, mut taken: HashSet<String>) -> HashMap<String, String> {
+            let mut renames = HashMap::new();
+            let mut clashing: Vec<String> = ids
+                .iter()
+                .filter(|id| taken.contains(*id))
+                .cloned()
+                .collect();
+            clashing.sort();
+            taken.extend(ids);
+            for id in clashing {
+                let mut count = 1;
+                let unique = match id.parse::<u64>() {
+                    Ok(_) => {
+                        let highest = taken.iter().filter_map(|id| id.parse::<u64>().ok()).max();
+                        (highest.unwrap_or(0) + 1).to_string()
+                    }
+                    Err(_) => loop {
+                        count += 1;
+                        let unique = format!("{}-{}", id, count);
+                        if !taken.contains(&unique) {
+                            break unique;
+                        }
+                    },
+                };
+                taken.insert(unique.clone());
+                renames.insert(id, unique);
+            }
+            renames
+        }
+        struct Renumber {
+            footnotes: HashMap<String, String>,
+            anchors: HashMap<String, String>,
+        }
+        impl ElementVisitorMut for Renumber {
+            fn visit_element(&mut self, element: &mut Element) {
+                match element {
+                    Element::Footnote { id, .. } | Element::FootnoteReference { id } => {
+                        if let Some(renamed) = self.footnotes.get(id) {
+                            *id = renamed.clone();
+                        }
+                    }
+                    Element::Header {
+                        anchor: Some(anchor),
+                        ..
+                    } => {
+                        if let Some(renamed) = self.anchors.get(anchor) {
+                            *anchor = renamed.clone();
+                        }
+                    }
+                    Element::Hyperlink { url, .. } => {
+                        let renamed = url
+                            .strip_prefix('#')
+                            .and_then(|anchor| self.anchors.get(anchor));
+                        if let Some(renamed) = renamed {
+                            *url = format!("#{}", renamed);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let (footnotes, anchors) = ids(self);
+        let (taken_footnotes, taken_anchors) = ids(other);
+        self.walk_mut(&mut Renumber {
+            footnotes: renames(footnotes, taken_footnotes),
+            anchors: renames(anchors, taken_anchors),
+        });
+    }
+}
+
+/// Builds a document block by block, without writing out the nested elements by hand
+///
+/// ```
+/// use shiva::core::DocumentBuilder;
+///
+/// let document = DocumentBuilder::new()
+///     .page_header("Quarterly report")
+///     .header(1, "Revenue")
+///     .paragraph(|p| p.text("Revenue grew by ").bold("12%").text(" this quarter."))
+///     .table(["Region", "Revenue"], [["North", "1200"], ["South", "900"]])
+///     .build();
+/// assert_eq!(document.get_detail().len(), 3);
+/// ```
+#[derive(Debug, Default)]
+pub struct DocumentBuilder {
+    page_header: Vec<Element>,
+    elements: Vec<Element>,
+    page_footer: Vec<Element>,
+    page_format: PageFormat,
+    orientation: PageOrientation,
+    direction: TextDirection,
+    metadata: Metadata,
+}
+
+impl DocumentBuilder {
+    pub fn new() -> DocumentBuilder {
+        DocumentBuilder::default()
+    }
+
+    pub fn header(mut self, level: u8, text: impl Into<String>) -> DocumentBuilder {
+        self.elements.push(Element::Header {
+            level,
+            text: text.into(),
+            anchor: None,
+        });
+        self
+    }
+
+    /// A paragraph of the inline content `build` adds
+    pub fn paragraph(
+        mut self,
+        build: impl FnOnce(ParagraphBuilder) -> ParagraphBuilder,
+    ) -> DocumentBuilder {
+        self.elements.push(Element::Paragraph {
+            elements: build(ParagraphBuilder::default()).elements,
+        });
+        self
+    }
+
+    /// A table of plain text cells under a row of plain text headers
+    pub fn table<H, R, C>(mut self, headers: H, rows: R) -> DocumentBuilder
+    where
+        H: IntoIterator,
+        H::Item: Into<String>,
+        R: IntoIterator<Item = C>,
+        C: IntoIterator,
+        C::Item: Into<String>,
+    {
+        let headers = headers
+            .into_iter()
+            .map(|header| TableHeader {
+                element: plain_text(header),
+                width: 30.0,
+            })
+            .collect();
+        let rows = rows
+            .into_iter()
+            .map(|row| TableRow {
+                cells: row
+                    .into_iter()
+                    .map(|cell| TableCell::new(plain_text(cell)))
+                    .collect(),
+            })
+            .collect();
+        self.elements.push(Element::Table {
+            headers,
+            rows,
+            alignments: Vec::new(),
+            caption: vec![],
+        });
+        self
+    }
+
+    /// A list of plain text items
+    pub fn list<I>(mut self, items: I, numbered: bool) -> DocumentBuilder
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        let elements = items
+            .into_iter()
+            .map(|item| ListItem {
+                element: plain_text(item),
+                checked: None,
+            })
+            .collect();
+        self.elements.push(Element::List { elements, numbered });
+        self
+    }
+
+    pub fn image(mut self, image: ImageData) -> DocumentBuilder {
+        self.elements.push(Element::Image(image));
+        self
+    }
+
+    pub fn code_block(
+        mut self,
+        language: Option<&str>,
+        code: impl Into<String>,
+    ) -> DocumentBuilder {
+        self.elements.push(Element::CodeBlock {
+            language: language.map(str::to_string),
+            code: code.into(),
+        });
+        self
+    }
+
+    pub fn page_break(mut self) -> DocumentBuilder {
+        self.elements.push(Element::PageBreak);
+        self
+    }
+
+    /// Any other element, added as is
+    pub fn element(mut self, element: Element) -> DocumentBuilder {
+        self.elements.push(element);
+        self
+    }
+
+    /// A line of text repeated at the top of every page
+    pub fn page_header(mut self, text: impl Into<String>) -> DocumentBuilder {
+        self.page_header.push(plain_text(text));
+        self
+    }
+
+    /// A line of text repeated at the bottom of every page
+    pub fn page_footer(mut self, text: impl Into<String>) -> DocumentBuilder {
+        self.page_footer.push(plain_text(text));
+        self
+    }
+
+    pub fn page_format(mut self, page_format: PageFormat) -> DocumentBuilder {
+        self.page_format = page_format;
+        self
+    }
+
+    pub fn orientation(mut self, orientation: PageOrientation) -> DocumentBuilder {
+        self.orientation = orientation;
+        self
+    }
+
+    pub fn direction(mut self, direction: TextDirection) -> DocumentBuilder {
+        self.direction = direction;
+        self
+    }
+
+    pub fn metadata(mut self, metadata: Metadata) -> DocumentBuilder {
+        self.metadata = metadata;
+        self
+    }
+
+    /// The document, with page header and footer bands only when any were added
+    pub fn build(self) -> Document {
+        let mut document = if self.page_header.is_empty() && self.page_footer.is_empty() {
+            Document::new(self.elements)
+        } else {
+            Document::new_with_dimensions(
+                self.page_header,
+                self.elements,
+                self.page_footer,
+                PageFormat::default(),
+            )
+        };
+        document.page_format = self.page_format;
+        document.orientation = self.orientation;
+        document.direction = self.direction;
+        document.metadata = self.metadata;
+        document
+    }
+}
+
+/// Builds the inline content of a paragraph for [`DocumentBuilder::paragraph`]
+#[derive(Debug, Default)]
+pub struct ParagraphBuilder {
+    elements: Vec<Element>,
+}
+
+impl ParagraphBuilder {
+    pub fn text(mut self, text: impl Into<String>) -> ParagraphBuilder {
+        self.elements.push(plain_text(text));
+        self
+    }
+
+    pub fn bold(self, text: impl Into<String>) -> ParagraphBuilder {
+        self.styled(TextStyle::BOLD, text)
+    }
+
+    pub fn italic(self, text: impl Into<String>) -> ParagraphBuilder {
+        self.styled(TextStyle::ITALIC, text)
+    }
+
+    pub fn styled(mut self, style: TextStyle, text: impl Into<String>) -> ParagraphBuilder {
+        self.elements.push(Element::Styled {
+            style,
+            elements: vec![plain_text(text)],
+        });
+        self
+    }
+
+    pub fn link(mut self, title: impl Into<String>, url: impl Into<String>) -> ParagraphBuilder {
+        self.elements.push(Element::Hyperlink {
+            title: title.into(),
+            url: url.into(),
+            alt: String::new(),
+            size: 8,
+        });
+        self
+    }
+
+    pub fn line_break(mut self) -> ParagraphBuilder {
+        self.elements.push(Element::LineBreak);
+        self
+    }
+
+    /// Any other inline element, added as is
+    pub fn element(mut self, element: Element) -> ParagraphBuilder {
+        self.elements.push(element);
+        self
+    }
+}
+
+fn plain_text(text: impl Into<String>) -> Element {
+    Element::Text {
+        text: text.into(),
+        font: FontSpec::from(8),
+    }
+}
+
+pub trait TransformerTrait {
+    fn parse(document: &Bytes) -> anyhow::Result<Document>;
+    fn generate(document: &Document) -> anyhow::Result<Bytes>;
+
+    /// Parses the document `reader` gives. This reads all of it before parsing, formats that can
+    /// be parsed as they are read override it so that large inputs are not held in memory twice
+    fn parse_reader(mut reader: impl Read) -> anyhow::Result<Document>
+    where
+        Self: Sized,
+    {
+        let mut document = Vec::new();
+        reader.read_to_end(&mut document)?;
+        Self::parse(&Bytes::from(document))
+    }
+}
+
+pub trait TransformerWithImageLoaderSaverTrait {
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>;
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>;
+}
+#[derive(Error, Debug)]
+pub enum ParserError {
+    #[error("Parser error")]
+    Common,
+    #[error("Malformed input: {0}")]
+    Malformed(String),
+    #[error("Parsing was cancelled")]
+    Cancelled,
+    #[error("Document has more than {0} elements")]
+    TooManyElements(usize),
+}
+#[derive(Error, Debug)]
+pub enum GeneratorError {
+    #[error("Generator error")]
+    Common,
+    #[error("Typst compilation failed: {0}")]
+    Typst(String),
+    #[error("Generation was cancelled")]
+    Cancelled,
+    #[error("Invalid document: {0}")]
+    Invalid(String),
+}
+
+/// Cooperative cancellation for long running conversions.
+///
+/// Parsers and generators that accept a token check it at page/element boundaries.
+/// Clones share the same cancellation flag, so a token can be cancelled from another thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Token which is tripped once `timeout` has elapsed.
+    pub fn with_timeout(timeout: Duration) -> CancellationToken {
+        CancellationToken::with_deadline(Instant::now() + timeout)
+    }
+
+    /// Token which is tripped once `deadline` has passed.
+    pub fn with_deadline(deadline: Instant) -> CancellationToken {
+        CancellationToken {
+            cancelled: Arc::new(AtomicBool::new(false)),
+            deadline: Some(deadline),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.cancelled, &other.cancelled) && self.deadline == other.deadline
+    }
+}
+/// [`ElementKind`] names the variants without their fields, as [`Document::select`] takes them
+#[derive(Debug, Clone, PartialEq, EnumDiscriminants)]
+#[strum_discriminants(name(ElementKind), derive(Hash, EnumString, Display, VariantArray))]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub enum Element {
+    Text {
+        text: String,
+        /// Earlier versions had a bare `size`
+        #[cfg_attr(feature = "json", serde(alias = "size"))]
+        font: FontSpec,
+    },
+    Header {
+        level: u8,
+        text: String,
+        /// Identifier internal links point to, as the `id` of an HTML heading
+        #[cfg_attr(feature = "json", serde(default))]
+        anchor: Option<String>,
+    },
+    Paragraph {
+        elements: Vec<Element>,
+    },
+    Table {
+        headers: Vec<TableHeader>,
+        rows: Vec<TableRow>,
+        /// Alignment of each column, columns past the end have no alignment
+        alignments: Vec<ColumnAlignment>,
+        /// Inline content of the caption, as "Table 3: Quarterly results", empty without one
+        #[cfg_attr(feature = "json", serde(default))]
+        caption: Vec<Element>,
     },
     List {
         elements: Vec<ListItem>,
         numbered: bool,
     },
     Image(ImageData),
+    /// A link to another resource, or to a header of the same document when the url is
+    /// `#` followed by the header's anchor
     Hyperlink {
         title: String,
         url: String,
         alt: String,
         size: u8,
     },
+    LineBreak,
+    /// A note about the document rather than part of it, as source comments and the review
+    /// comments of word processors are
+    Comment {
+        /// Who wrote it, review comments and annotations usually record this
+        #[cfg_attr(feature = "json", serde(default))]
+        author: Option<String>,
+        /// When it was written, in ISO 8601 where the format records it
+        #[cfg_attr(feature = "json", serde(default))]
+        date: Option<String>,
+        text: String,
+        /// The text of the document the comment is attached to
+        #[cfg_attr(feature = "json", serde(default))]
+        range: Option<String>,
+    },
+    CodeBlock {
+        language: Option<String>,
+        code: String,
+    },
+    Blockquote {
+        elements: Vec<Element>,
+        /// Inline content naming the source of the quote, empty without one
+        #[cfg_attr(feature = "json", serde(default))]
+        attribution: Vec<Element>,
+    },
+    /// The content of a footnote, placed where the source defines it
+    Footnote {
+        id: String,
+        content: Vec<Element>,
+    },
+    /// Inline mark pointing at the footnote with the same `id`
+    FootnoteReference {
+        id: String,
+    },
+    /// Inline content with emphasis, nested spans add their styles to the outer ones
+    Styled {
+        style: TextStyle,
+        elements: Vec<Element>,
+    },
+    /// A formula in TeX notation, display formulas are set apart from the text around them
+    Math {
+        tex: String,
+        display: bool,
+    },
+    /// A thematic break between sections
+    HorizontalRule,
+    /// The content after it starts on a new page
+    PageBreak,
+    /// Terms with their definitions, as in a glossary
+    DefinitionList {
+        items: Vec<DefinitionItem>,
+    },
+    /// An image set apart with its caption, numbered where the format numbers figures
+    Figure {
+        image: ImageData,
+        caption: Vec<Element>,
+    },
+    /// A table of contents listing the headers down to `max_level`, generators expand it from
+    /// the headers of the document
+    TableOfContents {
+        max_level: u8,
+    },
+    /// Embedded video or audio, formats without a player show the poster and a link instead
+    Media {
+        kind: MediaKind,
+        source: MediaSource,
+        /// Shown before playback starts
+        #[cfg_attr(feature = "json", serde(default))]
+        poster: Option<ImageData>,
+    },
+    /// Numeric series plotted over shared categories, formats without charts show the data
+    /// as a table
+    Chart {
+        chart_type: ChartType,
+        series: Vec<ChartSeries>,
+        /// Labels along the category axis, or of the slices of a pie
+        categories: Vec<String>,
+    },
+    /// Text with its reading set above it, as furigana over kanji. Formats without ruby write
+    /// the reading in parentheses after the text
+    Ruby {
+        base: String,
+        /// The reading of the base, or another short note on it
+        annotation: String,
+    },
+    /// A header with the content under it, up to the next header of the same or a higher level.
+    /// Parsers keep documents flat, [`Document::nest_sections`] builds sections from the headers
+    /// for formats that write them nested, as HTML `<section>` and DocBook do
+    Section {
+        level: u8,
+        /// The header of the section first, when it has one
+        children: Vec<Element>,
+    },
+    /// A block of an application built on shiva, carried through parsing and generation as is.
+    /// Only JSON and XML keep it, other generators skip it unless it is resolved with
+    /// [`Document::resolve_custom`] first.
+    Custom {
+        /// Names the block for the application, as `"callout"` or `"acme.chart"`
+        kind: String,
+        data: serde_json::Value,
+        children: Vec<Element>,
+    },
+}
+
+impl Element {
+    pub fn kind(&self) -> ElementKind {
+        ElementKind::from(self)
+    }
+
+    /// The direction of a paragraph all in one style that sets it, other elements have none
+    pub fn paragraph_direction(&self) -> Option<TextDirection> {
+        match self {
+            Element::Paragraph { elements } => match elements.as_slice() {
+                [Element::Styled { style, .. }] => style.direction,
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The text of a `Ruby` element with its reading in parentheses, for formats without ruby.
+    /// Other elements are returned unchanged
+    pub fn ruby_fallback(&self) -> Element {
+        let Element::Ruby { base, annotation } = self else {
+            return self.clone();
+        };
+        Element::Text {
+            text: format!("{}({})", base, annotation),
+            font: FontSpec::from(8),
+        }
+    }
+
+    /// What formats that cannot play media show in place of a `Media` element, a paragraph of
+    /// the poster and a link to the source. Other elements are returned unchanged
+    pub fn media_fallback(&self) -> Element {
+        let Element::Media {
+            kind,
+            source,
+            poster,
+        } = self
+        else {
+            return self.clone();
+        };
+        let mut elements = Vec::new();
+        if let Some(poster) = poster {
+            elements.push(Element::Image(poster.clone()));
+            elements.push(Element::LineBreak);
+        }
+        let link = match source {
+            MediaSource::Url(url) => Element::Hyperlink {
+                title: url.clone(),
+                url: url.clone(),
+                alt: kind.to_string(),
+                size: 8,
+            },
+            MediaSource::Embedded { .. } => Element::Text {
+                text: format!("[{}]", kind),
+                font: FontSpec::from(8),
+            },
+        };
+        elements.push(link);
+        Element::Paragraph { elements }
+    }
+
+    /// What formats without charts show in place of a `Chart` element, a table with a row
+    /// for every category and a column for every series. Other elements are returned unchanged
+    pub fn chart_fallback(&self) -> Element {
+        let Element::Chart {
+            series, categories, ..
+        } = self
+        else {
+            return self.clone();
+        };
+        let text = |text: String| Element::Text {
+            text,
+            font: FontSpec::from(8),
+        };
+        let mut headers = vec![TableHeader {
+            element: text(String::new()),
+            width: 30.0,
+        }];
+        for series in series {
+            headers.push(TableHeader {
+                element: text(series.name.clone()),
+                width: 30.0,
+            });
+        }
+        let points = series.iter().map(|series| series.values.len());
+        let rows = (0..points.chain([categories.len()]).max().unwrap_or(0))
+            .map(|index| {
+                let mut cells = vec![TableCell::new(text(
+                    categories.get(index).cloned().unwrap_or_default(),
+                ))];
+                for series in series {
+                    let value = series.values.get(index).map(f64::to_string);
+                    cells.push(TableCell::new(text(value.unwrap_or_default())));
+                }
+                TableRow { cells }
+            })
+            .collect();
+        Element::Table {
+            headers,
+            rows,
+            alignments: Vec::new(),
+            caption: vec![],
+        }
+    }
+
+    /// The text of the element without markup, for indexing and search. Inline content is run
+    /// together with its spaces collapsed, blocks are set apart by a blank line, list items and
+    /// table rows by a line break and table cells by a tab. Comments, images without alt text and
+    /// other elements with no text give an empty string.
+    pub fn plain_text(&self) -> String {
+        blocks_text(std::slice::from_ref(self), "\n\n")
+    }
+
+    /// The plain text with the spaces of inline elements kept as they are, for joining them up
+    fn raw_text(&self) -> String {
+        match self {
+            Element::Text { text, .. } => text.clone(),
+            Element::Hyperlink { title, .. } => title.clone(),
+            Element::Header { text, .. } => collapse_spaces(text),
+            Element::Ruby { base, .. } => base.clone(),
+            Element::Math { tex, .. } => tex.clone(),
+            Element::CodeBlock { code, .. } => code.trim_end().to_string(),
+            Element::Image(image) => image.alt().to_string(),
+            Element::LineBreak => "\n".to_string(),
+            Element::Styled { elements, .. } => elements.iter().map(Element::raw_text).collect(),
+            Element::Paragraph { elements } => inline_text(elements),
+            Element::Figure { image, caption } if caption.is_empty() => {
+                collapse_spaces(image.alt())
+            }
+            Element::Figure { caption, .. } => inline_text(caption),
+            Element::Blockquote {
+                elements,
+                attribution,
+            } => join_non_empty(
+                [blocks_text(elements, "\n\n"), inline_text(attribution)],
+                "\n",
+            ),
+            Element::Section { children, .. } | Element::Custom { children, .. } => {
+                blocks_text(children, "\n\n")
+            }
+            Element::Footnote { content, .. } => blocks_text(content, "\n"),
+            Element::List { elements, .. } => join_non_empty(
+                elements.iter().map(|item| item.element.plain_text()),
+                "\n",
+            ),
+            Element::DefinitionList { items } => join_non_empty(
+                items.iter().flat_map(|item| {
+                    [inline_text(&item.term), blocks_text(&item.definitions, "\n")]
+                }),
+                "\n",
+            ),
+            Element::Table {
+                headers,
+                rows,
+                caption,
+                ..
+            } => {
+                let headers = headers
+                    .iter()
+                    .map(|header| header.element.plain_text())
+                    .collect::<Vec<_>>()
+                    .join("\t");
+                let rows = rows.iter().map(|row| {
+                    row.cells
+                        .iter()
+                        .map(|cell| blocks_text(&cell.elements, " "))
+                        .collect::<Vec<_>>()
+                        .join("\t")
+                });
+                let lines = [inline_text(caption), headers].into_iter().chain(rows);
+                join_non_empty(lines.filter(|line| !line.trim().is_empty()), "\n")
+            }
+            Element::Media { .. } => self.media_fallback().raw_text(),
+            Element::Chart { .. } => self.chart_fallback().raw_text(),
+            _ => String::new(),
+        }
+    }
+
+    /// Whether the element is part of the text around it rather than a block of its own
+    pub(crate) fn is_inline(&self) -> bool {
+        matches!(
+            self,
+            Element::Text { .. }
+                | Element::Hyperlink { .. }
+                | Element::Styled { .. }
+                | Element::LineBreak
+                | Element::Ruby { .. }
+                | Element::Image(_)
+                | Element::FootnoteReference { .. }
+                | Element::Math { display: false, .. }
+        )
+    }
+}
+
+/// What [`Document::replace_text`] looks for, a string or a `&str` converts to a plain pattern
+#[derive(Debug, Clone)]
+pub enum TextPattern {
+    /// The text as it is, an empty one matches nothing
+    Plain(String),
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl From<&str> for TextPattern {
+    fn from(text: &str) -> TextPattern {
+        TextPattern::Plain(text.to_string())
+    }
+}
+
+impl From<String> for TextPattern {
+    fn from(text: String) -> TextPattern {
+        TextPattern::Plain(text)
+    }
+}
+
+#[cfg(feature = "regex")]
+impl From<regex::Regex> for TextPattern {
+    fn from(regex: regex::Regex) -> TextPattern {
+        TextPattern::Regex(regex)
+    }
+}
+
+/// Size of a document, as [`Document::stats`] counts it
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DocumentStats {
+    /// Words of the text of all bands, see [`Document::to_plain_text`]
+    pub words: usize,
+    /// Characters of the text of all bands, spaces and line breaks included
+    pub characters: usize,
+    pub characters_without_spaces: usize,
+    /// Elements of each kind, those nested in others included
+    pub elements: HashMap<ElementKind, usize>,
+    /// Images, figures and media posters
+    pub images: usize,
+    /// Size of the data of the images
+    pub image_bytes: usize,
+    /// Each table of the document in order
+    pub tables: Vec<TableStats>,
+    /// Pages the detail band fills, a rough estimate from its text, images and page breaks
+    pub estimated_pages: usize,
+}
+
+/// Dimensions of a table, as [`Document::stats`] counts them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableStats {
+    /// Rows below the header
+    pub rows: usize,
+    /// Columns of the widest row, cells spanning columns counting for each
+    pub columns: usize,
+}
+
+/// A place in the input of a parser, text formats give the bytes, line and column and formats
+/// laid out in pages the page
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SourceSpan {
+    pub byte_range: Option<Range<usize>>,
+    /// Line of the start, from 1
+    pub line: Option<usize>,
+    /// Column of the start in characters, from 1
+    pub column: Option<usize>,
+    /// Page, from 1
+    pub page: Option<usize>,
+}
+
+impl SourceSpan {
+    /// The span of `byte_range` in a text input, with the line and column it starts at
+    pub fn in_text(input: &str, byte_range: Range<usize>) -> SourceSpan {
+        let before = &input[..byte_range.start.min(input.len())];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        SourceSpan {
+            line: Some(before.matches('\n').count() + 1),
+            column: Some(before[line_start..].chars().count() + 1),
+            byte_range: Some(byte_range),
+            page: None,
+        }
+    }
+
+    pub fn on_page(page: usize) -> SourceSpan {
+        SourceSpan {
+            page: Some(page),
+            ..SourceSpan::default()
+        }
+    }
+}
+
+impl std::fmt::Display for SourceSpan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(page) = self.page {
+            parts.push(format!("page {}", page));
+        }
+        if let Some(line) = self.line {
+            parts.push(format!("line {}", line));
+        }
+        if let Some(column) = self.column {
+            parts.push(format!("column {}", column));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// How [`Document::append_with_options`] and [`Document::merge_with_options`] join documents
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    /// Starts the content of each document after the first on a new page
+    pub page_breaks: bool,
+}
+
+/// How [`Document::parse_with_options`] reads a document, whatever its format
+#[derive(Debug, Clone, Default)]
+pub struct ParserOptions {
+    /// Encoding of text formats as a label like `windows-1252` or `utf-16le`, overriding what
+    /// the input declares. Binary formats like PDF and DOCX ignore it
+    pub encoding: Option<String>,
+    /// Where images the document refers to by path are loaded from
+    pub images: ImagePolicy,
+    /// Fails with [`ParserError::TooManyElements`] on documents with more elements, nested ones
+    /// included. Only checked once the document is parsed, so it does not bound the time and
+    /// memory parsing takes, `cancellation` does
+    pub max_elements: Option<usize>,
+    /// Fails with [`ParserError::Malformed`] on input that does not decode in `encoding` and on
+    /// documents with the issues [`Document::validate`] reports, instead of keeping them
+    pub strict: bool,
+    /// Pages to parse, from 1, for formats laid out in pages as PDF. Other formats are parsed
+    /// whole
+    pub pages: Option<RangeInclusive<usize>>,
+    /// Fails with [`ParserError::Cancelled`] once tripped. PDF checks it before each page, the
+    /// other formats before and after parsing
+    pub cancellation: Option<CancellationToken>,
+}
+
+/// How [`ParserOptions`] loads the images a document refers to by path
+#[derive(Clone)]
+pub enum ImagePolicy {
+    /// From files relative to this directory, as [`disk_image_loader`] does
+    Directory(String),
+    /// Images are left without data, for input that must not read local files
+    Skip,
+    Loader(ImageLoaderFn),
+}
+
+/// Loads an image by the path the document refers to it with
+pub type ImageLoaderFn = Arc<dyn Fn(&str) -> anyhow::Result<Bytes> + Send + Sync>;
+
+impl Default for ImagePolicy {
+    /// The working directory, where [`Document::parse`] loads images from
+    fn default() -> ImagePolicy {
+        ImagePolicy::Directory(".".to_string())
+    }
+}
+
+impl Debug for ImagePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImagePolicy::Directory(directory) => {
+                f.debug_tuple("Directory").field(directory).finish()
+            }
+            ImagePolicy::Skip => write!(f, "Skip"),
+            ImagePolicy::Loader(_) => write!(f, "Loader(..)"),
+        }
+    }
+}
+
+impl ParserOptions {
+    /// Loads an image the document refers to, as `images` says
+    pub fn load_image(&self, image: &str) -> anyhow::Result<Bytes> {
+        match &self.images {
+            ImagePolicy::Directory(directory) => disk_image_loader(directory)(image),
+            ImagePolicy::Skip => Ok(Bytes::new()),
+            ImagePolicy::Loader(loader) => loader(image),
+        }
+    }
+
+    /// The input as UTF-8 when it is text in another encoding
+    fn decode(&self, input: &Bytes, document_type: DocumentType) -> anyhow::Result<Bytes> {
+        let Some(label) = &self.encoding else {
+            return Ok(input.clone());
+        };
+        if !document_type.is_text() {
+            return Ok(input.clone());
+        }
+        let encoding = encoding_rs::Encoding::for_label(label.trim().as_bytes())
+            .ok_or_else(|| anyhow::anyhow!("Unknown encoding: {}", label))?;
+        let (text, malformed) = encoding.decode_without_bom_handling(input);
+        if malformed && self.strict {
+            return Err(ParserError::Malformed(format!("Input is not valid {}", label)).into());
+        }
+        Ok(Bytes::from(text.into_owned()))
+    }
+
+    fn check_cancelled(&self) -> anyhow::Result<()> {
+        match &self.cancellation {
+            Some(token) if token.is_cancelled() => Err(ParserError::Cancelled.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Applies the limits to a parsed document
+    fn check(&self, document: Document) -> anyhow::Result<Document> {
+        self.check_cancelled()?;
+        if let Some(max_elements) = self.max_elements {
+            if document.iter_elements().nth(max_elements).is_some() {
+                return Err(ParserError::TooManyElements(max_elements).into());
+            }
+        }
+        if self.strict {
+            if let Some(issue) = document.validate().into_iter().next() {
+                return Err(ParserError::Malformed(issue.to_string()).into());
+            }
+        }
+        Ok(document)
+    }
+}
+
+/// How [`Document::generate_with_options`] writes a document, whatever its format. Formats
+/// ignore the options they have no use for
+#[derive(Debug, Clone, Default)]
+pub struct GeneratorOptions {
+    /// Page size and the margins of formats that take them from it, as ODT and LaTeX, instead of
+    /// the document's own
+    pub page_format: Option<PageFormat>,
+    pub orientation: Option<PageOrientation>,
+    /// Margins of the formats typst lays out, which keep the typst defaults without them
+    pub margins: Option<Margins>,
+    /// Font family of the text that does not set one, for formats that name fonts as DOCX and
+    /// HTML
+    pub font: Option<String>,
+    pub images: ImageEmbedding,
+    /// Inserts a table of contents of the headers down to this level, unless the document has
+    /// one, see [`Document::insert_toc`]
+    pub table_of_contents: Option<u8>,
+    /// Wraps plain text output at this many characters
+    pub line_width: Option<usize>,
+    /// Fails with [`GeneratorError::Cancelled`] once tripped. The formats typst lays out check
+    /// it before each element and page, the other formats before generating
+    pub cancellation: Option<CancellationToken>,
+}
+
+/// Where [`GeneratorOptions`] puts the images of a document
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ImageEmbedding {
+    /// As the format does: markdown embeds them, the other formats that refer to images by name
+    /// save them to the working directory
+    #[default]
+    Default,
+    /// In the output as data, markdown as `data:` urls. Formats that can only refer to images
+    /// save them as by default
+    Embed,
+    /// Saved to files in this directory, for formats that refer to images by name
+    Directory(String),
+    /// Left out, figures with their caption
+    Skip,
+}
+
+impl GeneratorOptions {
+    /// A copy of the document with the changes the options make to it, `None` when they make
+    /// none
+    pub(crate) fn prepare(&self, document: &Document) -> Option<Document> {
+        let changed = self.page_format.is_some()
+            || self.orientation.is_some()
+            || self.font.is_some()
+            || self.images == ImageEmbedding::Skip
+            || self.table_of_contents.is_some();
+        if !changed {
+            return None;
+        }
+        let mut document = match self.images {
+            ImageEmbedding::Skip => document
+                .filter(|element| !matches!(element, Element::Image(_) | Element::Figure { .. })),
+            _ => document.filter(|_| true),
+        };
+        if let Some(page_format) = &self.page_format {
+            document.page_format = page_format.clone();
+        }
+        if let Some(orientation) = &self.orientation {
+            document.orientation = orientation.clone();
+        }
+        if let Some(family) = &self.font {
+            struct SetFont<'a>(&'a str);
+            impl ElementVisitorMut for SetFont<'_> {
+                fn visit_element(&mut self, element: &mut Element) {
+                    if let Element::Text { font, .. } = element {
+                        font.family.get_or_insert_with(|| self.0.to_string());
+                    }
+                }
+            }
+            document.walk_mut(&mut SetFont(family));
+        }
+        if let Some(max_level) = self.table_of_contents {
+            let has_toc = document
+                .iter_elements()
+                .any(|item| matches!(item.element, Element::TableOfContents { .. }));
+            if !has_toc {
+                document.insert_toc(max_level);
+            }
+        }
+        Some(document)
+    }
+
+    #[cfg(any(feature = "pdf", feature = "svg", feature = "png", feature = "postscript"))]
+    fn layout(&self) -> crate::typst::PdfOptions {
+        crate::typst::PdfOptions {
+            margins: self.margins.clone(),
+            cancellation: self.cancellation.clone(),
+            ..crate::typst::PdfOptions::default()
+        }
+    }
+}
+
+/// Properties of a document as a whole. Parsers fill what the format records and generators
+/// write what the format has a place for, formats without one leave it out
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Metadata {
+    #[cfg_attr(feature = "json", serde(default))]
+    pub title: Option<String>,
+    #[cfg_attr(feature = "json", serde(default))]
+    pub authors: Vec<String>,
+    #[cfg_attr(feature = "json", serde(default))]
+    pub subject: Option<String>,
+    #[cfg_attr(feature = "json", serde(default))]
+    pub keywords: Vec<String>,
+    /// Language tag, as `en` or `pt-BR`
+    #[cfg_attr(feature = "json", serde(default))]
+    pub language: Option<String>,
+    /// Dates as the format wrote them, ISO 8601 where it has a date type of its own
+    #[cfg_attr(feature = "json", serde(default))]
+    pub created: Option<String>,
+    #[cfg_attr(feature = "json", serde(default))]
+    pub modified: Option<String>,
+    /// Any other named properties
+    #[cfg_attr(feature = "json", serde(default))]
+    pub custom: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Metadata::default()
+    }
+
+    /// Fills the properties the metadata lacks from `other`, the authors, keywords and custom
+    /// properties it does not have are added
+    fn fill_from(&mut self, other: Metadata) {
+        for (property, value) in [
+            (&mut self.title, other.title),
+            (&mut self.subject, other.subject),
+            (&mut self.language, other.language),
+            (&mut self.created, other.created),
+            (&mut self.modified, other.modified),
+        ] {
+            if property.is_none() {
+                *property = value;
+            }
+        }
+        for (list, values) in [
+            (&mut self.authors, other.authors),
+            (&mut self.keywords, other.keywords),
+        ] {
+            for value in values {
+                if !list.contains(&value) {
+                    list.push(value);
+                }
+            }
+        }
+        for (name, value) in other.custom {
+            self.custom.entry(name).or_insert(value);
+        }
+    }
+
+    /// Keywords are written in one comma separated list by most formats
+    #[cfg(any(
+        feature = "html",
+        feature = "latex",
+        feature = "fb2",
+        feature = "docx",
+        feature = "pptx",
+        feature = "xlsx"
+    ))]
+    pub(crate) fn keywords_text(&self) -> Option<String> {
+        (!self.keywords.is_empty()).then(|| self.keywords.join(", "))
+    }
+
+    /// Splits a comma or semicolon separated list of keywords
+    #[cfg(any(
+        feature = "html",
+        feature = "latex",
+        feature = "fb2",
+        feature = "docx",
+        feature = "pptx",
+        feature = "xlsx",
+        feature = "markdown",
+        feature = "pdf"
+    ))]
+    pub(crate) fn split_keywords(text: &str) -> Vec<String> {
+        text.split([',', ';'])
+            .map(str::trim)
+            .filter(|keyword| !keyword.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// Direction text is written in, the characters of each script keep their own order within it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString, Display, VariantArray)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "lowercase")]
+pub enum TextDirection {
+    #[default]
+    Ltr,
+    /// Right to left, as Arabic and Hebrew are written
+    Rtl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString, Display, VariantArray)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "lowercase")]
+pub enum ChartType {
+    /// Vertical bars, the series of a category side by side
+    #[default]
+    Bar,
+    Line,
+    /// Lines with the space below them filled
+    Area,
+    /// Slices of the first series only
+    Pie,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct ChartSeries {
+    pub name: String,
+    /// A value for each category, in the same order
+    pub values: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString, Display, VariantArray)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "lowercase")]
+pub enum MediaKind {
+    #[default]
+    Video,
+    Audio,
+}
+
+/// Where the media is played from
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub enum MediaSource {
+    Url(String),
+    /// Media carried in the document, as the `data:` urls of HTML
+    Embedded {
+        mime: String,
+        bytes: Bytes,
+    },
+}
+
+impl MediaSource {
+    /// The source from the `src` of an HTML media element, `data:` urls are decoded
+    pub fn from_src(src: &str) -> MediaSource {
+        #[cfg(feature = "base64")]
+        if let Some((mime, data)) = src
+            .strip_prefix("data:")
+            .and_then(|rest| rest.split_once(";base64,"))
+        {
+            use base64::{engine::general_purpose, Engine as _};
+            if let Ok(bytes) = general_purpose::STANDARD.decode(data) {
+                return MediaSource::Embedded {
+                    mime: mime.to_string(),
+                    bytes: Bytes::from(bytes),
+                };
+            }
+        }
+        MediaSource::Url(src.to_string())
+    }
+
+    /// The source as the `src` of an HTML media element, embedded media as a `data:` url
+    pub fn to_src(&self) -> String {
+        match self {
+            MediaSource::Url(url) => url.clone(),
+            #[cfg(feature = "base64")]
+            MediaSource::Embedded { mime, bytes } => {
+                use base64::{engine::general_purpose, Engine as _};
+                let data = general_purpose::STANDARD.encode(bytes);
+                format!("data:{};base64,{}", mime, data)
+            }
+            #[cfg(not(feature = "base64"))]
+            MediaSource::Embedded { .. } => String::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct TextStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strikethrough: bool,
+    /// Raised above the baseline, as exponents and ordinals are
+    #[cfg_attr(feature = "json", serde(default))]
+    pub superscript: bool,
+    /// Lowered below the baseline, as the indices of chemical formulas are
+    #[cfg_attr(feature = "json", serde(default))]
+    pub subscript: bool,
+    /// Marked with a background color, as with a highlighter pen
+    #[cfg_attr(feature = "json", serde(default))]
+    pub highlight: Option<HighlightColor>,
+    /// Color of the text itself
+    #[cfg_attr(feature = "json", serde(default))]
+    pub color: Option<Color>,
+    /// Any color behind the text, where `highlight` is one of the common highlighter colors
+    #[cfg_attr(feature = "json", serde(default))]
+    pub background: Option<Color>,
+    /// Direction of the text where it differs from the text around it, a style around all of
+    /// a paragraph sets the direction of the paragraph
+    #[cfg_attr(feature = "json", serde(default))]
+    pub direction: Option<TextDirection>,
+}
+
+impl TextStyle {
+    pub const BOLD: TextStyle = TextStyle {
+        bold: true,
+        italic: false,
+        underline: false,
+        strikethrough: false,
+        superscript: false,
+        subscript: false,
+        highlight: None,
+        color: None,
+        background: None,
+        direction: None,
+    };
+    pub const ITALIC: TextStyle = TextStyle {
+        bold: false,
+        italic: true,
+        underline: false,
+        strikethrough: false,
+        superscript: false,
+        subscript: false,
+        highlight: None,
+        color: None,
+        background: None,
+        direction: None,
+    };
+    pub const UNDERLINE: TextStyle = TextStyle {
+        bold: false,
+        italic: false,
+        underline: true,
+        strikethrough: false,
+        superscript: false,
+        subscript: false,
+        highlight: None,
+        color: None,
+        background: None,
+        direction: None,
+    };
+    pub const STRIKETHROUGH: TextStyle = TextStyle {
+        bold: false,
+        italic: false,
+        underline: false,
+        strikethrough: true,
+        superscript: false,
+        subscript: false,
+        highlight: None,
+        color: None,
+        background: None,
+        direction: None,
+    };
+    pub const SUPERSCRIPT: TextStyle = TextStyle {
+        bold: false,
+        italic: false,
+        underline: false,
+        strikethrough: false,
+        superscript: true,
+        subscript: false,
+        highlight: None,
+        color: None,
+        background: None,
+        direction: None,
+    };
+    pub const SUBSCRIPT: TextStyle = TextStyle {
+        bold: false,
+        italic: false,
+        underline: false,
+        strikethrough: false,
+        superscript: false,
+        subscript: true,
+        highlight: None,
+        color: None,
+        background: None,
+        direction: None,
+    };
+    pub const RTL: TextStyle = TextStyle {
+        bold: false,
+        italic: false,
+        underline: false,
+        strikethrough: false,
+        superscript: false,
+        subscript: false,
+        highlight: None,
+        color: None,
+        background: None,
+        direction: Some(TextDirection::Rtl),
+    };
+    pub const HIGHLIGHT: TextStyle = TextStyle {
+        bold: false,
+        italic: false,
+        underline: false,
+        strikethrough: false,
+        superscript: false,
+        subscript: false,
+        highlight: Some(HighlightColor::Yellow),
+        color: None,
+        background: None,
+        direction: None,
+    };
+
+    /// The styles of both, for formats that apply all of them to a single run. The highlight,
+    /// colors and direction of `other` are kept when both have one, as it is the inner span
+    pub fn combine(self, other: TextStyle) -> TextStyle {
+        TextStyle {
+            bold: self.bold || other.bold,
+            italic: self.italic || other.italic,
+            underline: self.underline || other.underline,
+            strikethrough: self.strikethrough || other.strikethrough,
+            superscript: self.superscript || other.superscript,
+            subscript: self.subscript || other.subscript,
+            highlight: other.highlight.or(self.highlight),
+            color: other.color.or(self.color),
+            background: other.background.or(self.background),
+            direction: other.direction.or(self.direction),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct ListItem {
     pub element: Element,
+    /// Task list items are checked or unchecked, other items have no checkbox
+    #[cfg_attr(feature = "json", serde(default))]
+    pub checked: Option<bool>,
+}
+/// A header in a table of contents, with the headers of lower levels below it
+#[derive(Debug, Clone, PartialEq)]
+pub struct TocEntry {
+    pub level: u8,
+    pub text: String,
+    pub anchor: Option<String>,
+    pub children: Vec<TocEntry>,
+}
+
+impl TocEntry {
+    /// The entries as a nested bullet list, entries with an anchor link to their header
+    pub fn list(entries: &[TocEntry]) -> Element {
+        let mut items = Vec::new();
+        for entry in entries {
+            let element = match &entry.anchor {
+                Some(anchor) => Element::Hyperlink {
+                    title: entry.text.clone(),
+                    url: format!("#{}", anchor),
+                    alt: String::new(),
+                    size: 8,
+                },
+                None => Element::Text {
+                    text: entry.text.clone(),
+                    font: FontSpec::from(8),
+                },
+            };
+            items.push(ListItem {
+                element,
+                checked: None,
+            });
+            if !entry.children.is_empty() {
+                items.push(ListItem {
+                    element: TocEntry::list(&entry.children),
+                    checked: None,
+                });
+            }
+        }
+        Element::List {
+            elements: items,
+            numbered: false,
+        }
+    }
+}
+
+/// Callbacks for [`Document::walk`]. Every method does nothing by default, visitors implement
+/// the ones they need
+pub trait ElementVisitor {
+    /// Called before the elements of the band
+    fn visit_band(&mut self, _band: &Band) {}
+
+    /// Called before the elements nested in the element
+    fn visit_element(&mut self, _element: &Element) {}
+
+    /// Called after the elements nested in the element
+    fn leave_element(&mut self, _element: &Element) {}
+
+    /// Called before the element of the item
+    fn visit_list_item(&mut self, _item: &ListItem) {}
+
+    /// Called before the elements of the cell, header cells are visited as elements only
+    fn visit_table_cell(&mut self, _cell: &TableCell) {}
+}
+
+/// Callbacks for [`Document::walk_mut`], as [`ElementVisitor`] with mutable access
+pub trait ElementVisitorMut {
+    fn visit_band(&mut self, _band: &mut Band) {}
+
+    fn visit_element(&mut self, _element: &mut Element) {}
+
+    fn leave_element(&mut self, _element: &mut Element) {}
+
+    fn visit_list_item(&mut self, _item: &mut ListItem) {}
+
+    fn visit_table_cell(&mut self, _cell: &mut TableCell) {}
+}
+
+fn walk_elements(elements: &[Element], visitor: &mut impl ElementVisitor) {
+    for element in elements {
+        visitor.visit_element(element);
+        match element {
+            Element::Paragraph { elements }
+            | Element::Styled { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            }
+            | Element::Custom {
+                children: elements, ..
+            }
+            | Element::Footnote {
+                content: elements, ..
+            }
+            | Element::Figure {
+                caption: elements, ..
+            } => walk_elements(elements, visitor),
+            Element::Blockquote {
+                elements,
+                attribution,
+            } => {
+                walk_elements(elements, visitor);
+                walk_elements(attribution, visitor);
+            }
+            Element::List { elements, .. } => {
+                for item in elements {
+                    visitor.visit_list_item(item);
+                    walk_elements(std::slice::from_ref(&item.element), visitor);
+                }
+            }
+            Element::DefinitionList { items } => {
+                for item in items {
+                    walk_elements(&item.term, visitor);
+                    walk_elements(&item.definitions, visitor);
+                }
+            }
+            Element::Table {
+                headers,
+                rows,
+                caption,
+                ..
+            } => {
+                for header in headers {
+                    walk_elements(std::slice::from_ref(&header.element), visitor);
+                }
+                for row in rows {
+                    for cell in &row.cells {
+                        visitor.visit_table_cell(cell);
+                        walk_elements(&cell.elements, visitor);
+                    }
+                }
+                walk_elements(caption, visitor);
+            }
+            _ => {}
+        }
+        visitor.leave_element(element);
+    }
+}
+
+fn walk_elements_mut(elements: &mut [Element], visitor: &mut impl ElementVisitorMut) {
+    for element in elements {
+        visitor.visit_element(element);
+        match element {
+            Element::Paragraph { elements }
+            | Element::Styled { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            }
+            | Element::Custom {
+                children: elements, ..
+            }
+            | Element::Footnote {
+                content: elements, ..
+            }
+            | Element::Figure {
+                caption: elements, ..
+            } => walk_elements_mut(elements, visitor),
+            Element::Blockquote {
+                elements,
+                attribution,
+            } => {
+                walk_elements_mut(elements, visitor);
+                walk_elements_mut(attribution, visitor);
+            }
+            Element::List { elements, .. } => {
+                for item in elements {
+                    visitor.visit_list_item(item);
+                    walk_elements_mut(std::slice::from_mut(&mut item.element), visitor);
+                }
+            }
+            Element::DefinitionList { items } => {
+                for item in items {
+                    walk_elements_mut(&mut item.term, visitor);
+                    walk_elements_mut(&mut item.definitions, visitor);
+                }
+            }
+            Element::Table {
+                headers,
+                rows,
+                caption,
+                ..
+            } => {
+                for header in headers {
+                    walk_elements_mut(std::slice::from_mut(&mut header.element), visitor);
+                }
+                for row in rows {
+                    for cell in &mut row.cells {
+                        visitor.visit_table_cell(cell);
+                        walk_elements_mut(&mut cell.elements, visitor);
+                    }
+                }
+                walk_elements_mut(caption, visitor);
+            }
+            _ => {}
+        }
+        visitor.leave_element(element);
+    }
+}
+
+/// An element of a document with where it is, as [`Document::iter_elements`] yields them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ElementRef<'a> {
+    pub element: &'a Element,
+    pub band: &'a Band,
+    /// The element it is nested in, elements of the band itself have none
+    pub parent: Option<&'a Element>,
+    /// Elements of the band itself are at depth 0, the elements nested in them at 1...
+    pub depth: usize,
+}
+
+/// Depth first iterator over the elements of a document, see [`Document::iter_elements`]
+#[derive(Debug, Clone)]
+pub struct ElementIter<'a> {
+    bands: std::slice::Iter<'a, Band>,
+    /// Elements still to be yielded, the next one last
+    pending: Vec<ElementRef<'a>>,
+}
+
+impl<'a> Iterator for ElementIter<'a> {
+    type Item = ElementRef<'a>;
+
+    fn next(&mut self) -> Option<ElementRef<'a>> {
+        loop {
+            if let Some(next) = self.pending.pop() {
+                let children = child_elements(next.element);
+                self.pending
+                    .extend(children.into_iter().rev().map(|element| ElementRef {
+                        element,
+                        band: next.band,
+                        parent: Some(next.element),
+                        depth: next.depth + 1,
+                    }));
+                return Some(next);
+            }
+            let band = self.bands.next()?;
+            self.pending
+                .extend(band.elements().iter().rev().map(|element| ElementRef {
+                    element,
+                    band,
+                    parent: None,
+                    depth: 0,
+                }));
+        }
+    }
+}
+
+/// The elements nested directly in an element, in document order
+pub(crate) fn child_elements(element: &Element) -> Vec<&Element> {
+    match element {
+        Element::Paragraph { elements }
+        | Element::Styled { elements, .. }
+        | Element::Section {
+            children: elements, ..
+        }
+        | Element::Custom {
+            children: elements, ..
+        }
+        | Element::Footnote {
+            content: elements, ..
+        }
+        | Element::Figure {
+            caption: elements, ..
+        } => elements.iter().collect(),
+        Element::Blockquote {
+            elements,
+            attribution,
+        } => elements.iter().chain(attribution).collect(),
+        Element::List { elements, .. } => elements.iter().map(|item| &item.element).collect(),
+        Element::DefinitionList { items } => items
+            .iter()
+            .flat_map(|item| item.term.iter().chain(&item.definitions))
+            .collect(),
+        Element::Table {
+            headers,
+            rows,
+            caption,
+            ..
+        } => headers
+            .iter()
+            .map(|header| &header.element)
+            .chain(rows.iter().flat_map(|row| &row.cells).flat_map(|cell| &cell.elements))
+            .chain(caption)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The plain text of the blocks in `elements` joined with `separator`, runs of inline elements
+/// between them make up a block each
+fn blocks_text(elements: &[Element], separator: &str) -> String {
+    let mut texts = Vec::new();
+    let mut start = 0;
+    for (index, element) in elements.iter().enumerate() {
+        if !element.is_inline() {
+            texts.push(inline_text(&elements[start..index]));
+            texts.push(element.raw_text());
+            start = index + 1;
+        }
+    }
+    texts.push(inline_text(&elements[start..]));
+    join_non_empty(texts, separator)
+}
+
+/// The text of inline elements run together, with the spaces of each line collapsed
+fn inline_text(elements: &[Element]) -> String {
+    let text: String = elements.iter().map(Element::raw_text).collect();
+    let lines: Vec<String> = text.lines().map(collapse_spaces).collect();
+    join_non_empty(lines, "\n")
+}
+
+fn join_non_empty(texts: impl IntoIterator<Item = String>, separator: &str) -> String {
+    texts
+        .into_iter()
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Turns each run of spaces, tabs and line breaks into a single space, trimmed at both ends
+fn collapse_spaces(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Rewrites the text of `elements` and of the elements nested in them with `f`, the text of code,
+/// formulas and comments is not changed
+fn map_text(elements: &mut [Element], f: &dyn Fn(&str) -> String) {
+    for element in elements {
+        match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => *text = f(text),
+            Element::Hyperlink { title, .. } => *title = f(title),
+            Element::Paragraph { elements }
+            | Element::Styled { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            }
+            | Element::Footnote {
+                content: elements, ..
+            }
+            | Element::Figure {
+                caption: elements, ..
+            } => map_text(elements, f),
+            Element::Blockquote {
+                elements,
+                attribution,
+            } => {
+                map_text(elements, f);
+                map_text(attribution, f);
+            }
+            Element::List { elements, .. } => {
+                for item in elements {
+                    map_text(std::slice::from_mut(&mut item.element), f);
+                }
+            }
+            Element::DefinitionList { items } => {
+                for item in items {
+                    map_text(&mut item.term, f);
+                    map_text(&mut item.definitions, f);
+                }
+            }
+            Element::Table {
+                headers,
+                rows,
+                caption,
+                ..
+            } => {
+                for header in headers {
+                    map_text(std::slice::from_mut(&mut header.element), f);
+                }
+                for row in rows {
+                    for cell in &mut row.cells {
+                        map_text(&mut cell.elements, f);
+                    }
+                }
+                map_text(caption, f);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The elements with each section replaced by its children, in document order
+fn unnest_sections<'a>(elements: impl IntoIterator<Item = &'a Element>) -> Vec<&'a Element> {
+    let mut unnested = Vec::new();
+    for element in elements {
+        match element {
+            Element::Section { children, .. } => unnested.extend(unnest_sections(children)),
+            element => unnested.push(element),
+        }
+    }
+    unnested
+}
+
+/// An anchor made from the text of a header, lowercase words joined by hyphens
+fn slug(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct DefinitionItem {
+    /// Inline content of the term
+    pub term: Vec<Element>,
+    /// Each element is a separate definition of the term
+    pub definitions: Vec<Element>,
 }
 #[derive(Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
@@ -590,16 +3890,57 @@ pub struct TableRow {
     pub cells: Vec<TableCell>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
-pub struct TableCell {
-    pub element: Element,
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct TableCell {
+    /// Block content of the cell, a single element for plain text cells
+    pub elements: Vec<Element>,
+    /// Columns the cell spans, the cells it covers are left out of the row
+    #[cfg_attr(feature = "json", serde(default = "single_span"))]
+    pub colspan: usize,
+    /// Rows the cell spans, the rows below leave out the cells it covers
+    #[cfg_attr(feature = "json", serde(default = "single_span"))]
+    pub rowspan: usize,
+}
+
+#[cfg(feature = "json")]
+fn single_span() -> usize {
+    1
+}
+
+impl TableCell {
+    /// A cell spanning a single column and row
+    pub fn new(element: Element) -> TableCell {
+        TableCell::with_elements(vec![element])
+    }
+
+    /// A cell spanning a single column and row holding several blocks
+    pub fn with_elements(elements: Vec<Element>) -> TableCell {
+        TableCell {
+            elements,
+            colspan: 1,
+            rowspan: 1,
+        }
+    }
+
+    /// The cell content as one element, several blocks are wrapped in a paragraph
+    ///
+    /// For formats whose cells only hold a line of inline content.
+    pub fn element(&self) -> Element {
+        match &self.elements[..] {
+            [element] => element.clone(),
+            elements => Element::Paragraph {
+                elements: elements.to_vec(),
+            },
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Default)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct ImageData {
-    #[cfg_attr(feature = "json", serde(skip))]
+    /// Base64 when serialized, documents from before the schema was versioned have none
+    #[cfg_attr(feature = "json", serde(with = "crate::schema::base64_bytes", default))]
     bytes: Bytes,
     title: String,
     alt: String,
@@ -665,7 +4006,7 @@ impl ImageData {
     /// assert_eq!(image.title(), "Small PNG");
     /// assert_eq!(image.bytes().len(), 70);
     /// ```
-    #[cfg(feature = "json")]
+    #[cfg(any(feature = "json", feature = "fb2"))]
     pub fn from_base64(
         base64_data: &str,
         title: String,
@@ -702,7 +4043,7 @@ impl ImageData {
     /// let base64_string = image_data.to_base64();
     /// assert_eq!(base64_string, "dGVzdA=="); // "test" in base64
     /// ```
-    #[cfg(feature = "json")]
+    #[cfg(any(feature = "json", feature = "fb2"))]
     pub fn to_base64(&self) -> String {
         use base64::{engine::general_purpose, Engine as _};
         general_purpose::STANDARD.encode(&self.bytes)
@@ -920,6 +4261,185 @@ pub enum ImageAlignment {
     None,
 }
 
+/// Background of highlighted text, the colors every format with highlighting has in common
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString, Display, VariantArray)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "lowercase")]
+pub enum HighlightColor {
+    #[default]
+    Yellow,
+    Green,
+    Cyan,
+    Magenta,
+    Blue,
+    Red,
+    Gray,
+}
+
+/// The font text is set in. Parsers that find no font in the source give text a nominal size
+/// only, the family and weight are then left to the format the text is written to
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "json", serde(from = "SerializedFont"))]
+pub struct FontSpec {
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub family: Option<String>,
+    pub size_pt: f32,
+    /// CSS weight, 400 is normal and 700 bold
+    #[cfg_attr(feature = "json", serde(skip_serializing_if = "Option::is_none"))]
+    pub weight: Option<u16>,
+}
+
+/// A font as it is serialized, or the bare size of earlier versions
+#[cfg(feature = "json")]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SerializedFont {
+    Size(f32),
+    Font {
+        #[serde(default)]
+        family: Option<String>,
+        size_pt: f32,
+        #[serde(default)]
+        weight: Option<u16>,
+    },
+}
+
+#[cfg(feature = "json")]
+impl From<SerializedFont> for FontSpec {
+    fn from(font: SerializedFont) -> FontSpec {
+        match font {
+            SerializedFont::Size(size_pt) => FontSpec::new(size_pt),
+            SerializedFont::Font {
+                family,
+                size_pt,
+                weight,
+            } => FontSpec {
+                family,
+                size_pt,
+                weight,
+            },
+        }
+    }
+}
+
+impl FontSpec {
+    pub fn new(size_pt: f32) -> FontSpec {
+        FontSpec {
+            family: None,
+            size_pt,
+            weight: None,
+        }
+    }
+
+    /// Whether the font was read from the source rather than being a nominal size
+    pub fn is_explicit(&self) -> bool {
+        self.family.is_some() || self.weight.is_some()
+    }
+
+    /// The size rounded to whole points, as most formats write it
+    pub fn size(&self) -> u8 {
+        self.size_pt.round().clamp(0.0, u8::MAX as f32) as u8
+    }
+}
+
+impl From<u8> for FontSpec {
+    fn from(size: u8) -> FontSpec {
+        FontSpec::new(size as f32)
+    }
+}
+
+/// A color of text or its background
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+pub struct Color {
+    pub red: u8,
+    pub green: u8,
+    pub blue: u8,
+}
+
+impl Color {
+    pub const fn new(red: u8, green: u8, blue: u8) -> Color {
+        Color { red, green, blue }
+    }
+}
+
+impl FromStr for Color {
+    type Err = anyhow::Error;
+
+    /// Reads the color notations of CSS: `#rgb`, `#rrggbb`, `rgb(r, g, b)` and the basic
+    /// color keywords. The `#` may be left out, as Word writes its colors without it
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim().to_ascii_lowercase();
+        if let Some(components) = value
+            .strip_prefix("rgb(")
+            .and_then(|value| value.strip_suffix(')'))
+        {
+            let components = components
+                .split(',')
+                .map(|component| component.trim().parse::<u8>())
+                .collect::<Result<Vec<u8>, _>>()?;
+            let [red, green, blue] = components[..] else {
+                anyhow::bail!("Invalid color: {}", value);
+            };
+            return Ok(Color::new(red, green, blue));
+        }
+        let named = match value.as_str() {
+            "black" => Some(Color::new(0, 0, 0)),
+            "silver" => Some(Color::new(192, 192, 192)),
+            "gray" | "grey" => Some(Color::new(128, 128, 128)),
+            "white" => Some(Color::new(255, 255, 255)),
+            "maroon" => Some(Color::new(128, 0, 0)),
+            "red" => Some(Color::new(255, 0, 0)),
+            "purple" => Some(Color::new(128, 0, 128)),
+            "fuchsia" | "magenta" => Some(Color::new(255, 0, 255)),
+            "green" => Some(Color::new(0, 128, 0)),
+            "lime" => Some(Color::new(0, 255, 0)),
+            "olive" => Some(Color::new(128, 128, 0)),
+            "yellow" => Some(Color::new(255, 255, 0)),
+            "navy" => Some(Color::new(0, 0, 128)),
+            "blue" => Some(Color::new(0, 0, 255)),
+            "teal" => Some(Color::new(0, 128, 128)),
+            "aqua" | "cyan" => Some(Color::new(0, 255, 255)),
+            "orange" => Some(Color::new(255, 165, 0)),
+            _ => None,
+        };
+        if let Some(color) = named {
+            return Ok(color);
+        }
+        let hex = value.strip_prefix('#').unwrap_or(&value);
+        if !hex.chars().all(|digit| digit.is_ascii_hexdigit()) {
+            anyhow::bail!("Invalid color: {}", value);
+        }
+        let digits = match hex.len() {
+            3 => hex.chars().flat_map(|digit| [digit, digit]).collect(),
+            6 => hex.to_string(),
+            _ => anyhow::bail!("Invalid color: {}", value),
+        };
+        let component = |index: usize| u8::from_str_radix(&digits[index..index + 2], 16);
+        Ok(Color::new(component(0)?, component(2)?, component(4)?))
+    }
+}
+
+impl std::fmt::Display for Color {
+    /// The `#rrggbb` notation
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.red, self.green, self.blue)
+    }
+}
+
+/// Horizontal alignment of the cells of a table column
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString, Display, VariantArray)]
+#[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
+#[strum(serialize_all = "lowercase")]
+pub enum ColumnAlignment {
+    Left,
+    Center,
+    Right,
+    #[default]
+    None,
+}
+
 #[derive(Debug, Clone, PartialEq, Default)]
 #[cfg_attr(feature = "json", derive(Serialize, Deserialize))]
 pub struct ImageDimension {
@@ -1033,6 +4553,42 @@ pub enum DocumentType {
     XLS = 9,
     XLSX = 10,
     ODS = 11,
+    EPUB = 12,
+    ODT = 13,
+    LaTeX = 14,
+    AsciiDoc = 15,
+    RST = 16,
+    Org = 17,
+    DocBook = 18,
+    FB2 = 19,
+    PPTX = 20,
+    ODP = 21,
+    Ipynb = 22,
+    MHTML = 23,
+    MediaWiki = 24,
+    Confluence = 25,
+    Jira = 26,
+    BBCode = 27,
+    Textile = 28,
+    Man = 29,
+    Gemtext = 30,
+    Ansi = 31,
+    Opml = 32,
+    Quill = 33,
+    ProseMirror = 34,
+    Slack = 35,
+    TSV = 36,
+    NDJSON = 37,
+    YAML = 38,
+    SQLite = 39,
+    Parquet = 40,
+    SVG = 41,
+    PNG = 42,
+    PostScript = 43,
+    DjVu = 44,
+    MOBI = 45,
+    DAISY = 46,
+    Typst = 47,
 }
 
 impl DocumentType {
@@ -1055,6 +4611,54 @@ impl DocumentType {
         map.insert("xls", DocumentType::XLS);
         map.insert("xlsx", DocumentType::XLSX);
         map.insert("ods", DocumentType::ODS);
+        map.insert("epub", DocumentType::EPUB);
+        map.insert("odt", DocumentType::ODT);
+        map.insert("tex", DocumentType::LaTeX);
+        map.insert("adoc", DocumentType::AsciiDoc);
+        map.insert("asciidoc", DocumentType::AsciiDoc);
+        map.insert("rst", DocumentType::RST);
+        map.insert("org", DocumentType::Org);
+        map.insert("dbk", DocumentType::DocBook);
+        map.insert("docbook", DocumentType::DocBook);
+        map.insert("fb2", DocumentType::FB2);
+        map.insert("pptx", DocumentType::PPTX);
+        map.insert("odp", DocumentType::ODP);
+        map.insert("ipynb", DocumentType::Ipynb);
+        map.insert("mhtml", DocumentType::MHTML);
+        map.insert("mht", DocumentType::MHTML);
+        map.insert("mediawiki", DocumentType::MediaWiki);
+        map.insert("wiki", DocumentType::MediaWiki);
+        map.insert("confluence", DocumentType::Confluence);
+        map.insert("jira", DocumentType::Jira);
+        map.insert("bbcode", DocumentType::BBCode);
+        map.insert("textile", DocumentType::Textile);
+        map.insert("man", DocumentType::Man);
+        map.insert("roff", DocumentType::Man);
+        map.insert("gmi", DocumentType::Gemtext);
+        map.insert("ansi", DocumentType::Ansi);
+        map.insert("opml", DocumentType::Opml);
+        map.insert("quill", DocumentType::Quill);
+        map.insert("delta", DocumentType::Quill);
+        map.insert("prosemirror", DocumentType::ProseMirror);
+        map.insert("tiptap", DocumentType::ProseMirror);
+        map.insert("slack", DocumentType::Slack);
+        map.insert("tsv", DocumentType::TSV);
+        map.insert("ndjson", DocumentType::NDJSON);
+        map.insert("jsonl", DocumentType::NDJSON);
+        map.insert("yaml", DocumentType::YAML);
+        map.insert("yml", DocumentType::YAML);
+        map.insert("sqlite", DocumentType::SQLite);
+        map.insert("db", DocumentType::SQLite);
+        map.insert("parquet", DocumentType::Parquet);
+        map.insert("svg", DocumentType::SVG);
+        map.insert("png", DocumentType::PNG);
+        map.insert("ps", DocumentType::PostScript);
+        map.insert("djvu", DocumentType::DjVu);
+        map.insert("djv", DocumentType::DjVu);
+        map.insert("mobi", DocumentType::MOBI);
+        map.insert("azw", DocumentType::MOBI);
+        map.insert("daisy", DocumentType::DAISY);
+        map.insert("typ", DocumentType::Typst);
         map
     }
 
@@ -1065,6 +4669,32 @@ impl DocumentType {
     pub fn supported_extensions() -> Vec<&'static str> {
         Self::extension_map().keys().cloned().collect()
     }
+
+    /// Whether the format is text, which may be in another encoding than UTF-8. RTF and MHTML
+    /// declare theirs in the input
+    fn is_text(self) -> bool {
+        !matches!(
+            self,
+            DocumentType::PDF
+                | DocumentType::RTF
+                | DocumentType::DOCX
+                | DocumentType::XLS
+                | DocumentType::XLSX
+                | DocumentType::ODS
+                | DocumentType::EPUB
+                | DocumentType::ODT
+                | DocumentType::PPTX
+                | DocumentType::ODP
+                | DocumentType::MHTML
+                | DocumentType::SQLite
+                | DocumentType::Parquet
+                | DocumentType::PNG
+                | DocumentType::PostScript
+                | DocumentType::DjVu
+                | DocumentType::MOBI
+                | DocumentType::DAISY
+        )
+    }
 }
 
 #[cfg(test)]
@@ -1097,6 +4727,42 @@ pub mod tests {
         DocumentType::XLS,
         DocumentType::XLSX,
         DocumentType::ODS,
+        DocumentType::EPUB,
+        DocumentType::ODT,
+        DocumentType::LaTeX,
+        DocumentType::AsciiDoc,
+        DocumentType::RST,
+        DocumentType::Org,
+        DocumentType::DocBook,
+        DocumentType::FB2,
+        DocumentType::PPTX,
+        DocumentType::ODP,
+        DocumentType::Ipynb,
+        DocumentType::MHTML,
+        DocumentType::MediaWiki,
+        DocumentType::Confluence,
+        DocumentType::Jira,
+        DocumentType::BBCode,
+        DocumentType::Textile,
+        DocumentType::Man,
+        DocumentType::Gemtext,
+        DocumentType::Ansi,
+        DocumentType::Opml,
+        DocumentType::Quill,
+        DocumentType::ProseMirror,
+        DocumentType::Slack,
+        DocumentType::TSV,
+        DocumentType::NDJSON,
+        DocumentType::YAML,
+        DocumentType::SQLite,
+        DocumentType::Parquet,
+        DocumentType::SVG,
+        DocumentType::PNG,
+        DocumentType::PostScript,
+        DocumentType::DjVu,
+        DocumentType::MOBI,
+        DocumentType::DAISY,
+        DocumentType::Typst,
     ];
 
     #[test]
@@ -1149,6 +4815,105 @@ pub mod tests {
         assert_eq!(DocumentType::XLSX as u8, 10);
     }
 
+    #[test]
+    fn test_parse_and_generate() -> anyhow::Result<()> {
+        let document = Document::parse(&Bytes::from("# Title\n"), DocumentType::Markdown)?;
+        assert_eq!(document.get_all_elements().len(), 1);
+        let output = document.generate(DocumentType::Markdown)?;
+        assert_eq!(output, Bytes::from("# Title\n"));
+        assert!(Document::parse(&Bytes::from("{"), DocumentType::Json).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter() {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let image = Element::Image(ImageData::default());
+        let table = Element::Table {
+            headers: vec![],
+            rows: vec![],
+            alignments: vec![],
+            caption: vec![],
+        };
+        let document = Document::new_with_dimensions(
+            vec![text("header")],
+            vec![
+                Element::Paragraph {
+                    elements: vec![text("a"), image.clone()],
+                },
+                Element::Paragraph {
+                    elements: vec![image.clone()],
+                },
+                Element::List {
+                    elements: vec![ListItem {
+                        element: image.clone(),
+                        checked: None,
+                    }],
+                    numbered: false,
+                },
+                table.clone(),
+            ],
+            vec![],
+            PageFormat::Letter,
+        );
+
+        let without_images = document.filter(|element| !matches!(element, Element::Image(_)));
+        assert_eq!(without_images.bands.len(), 3);
+        assert_eq!(without_images.page_format, PageFormat::Letter);
+        assert_eq!(
+            without_images.bands[1],
+            Band::Detail(vec![
+                Element::Paragraph {
+                    elements: vec![text("a")]
+                },
+                table.clone()
+            ])
+        );
+
+        let tables = document.filter(|element| matches!(element, Element::Table { .. }));
+        assert_eq!(tables.get_all_elements(), vec![&table]);
+    }
+
+    #[test]
+    fn test_filter_table() {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let image = Element::Image(ImageData::default());
+        let table = |header: Element, cells: Vec<Vec<Element>>| Element::Table {
+            headers: vec![
+                TableHeader {
+                    element: text("Name"),
+                    width: 30.0,
+                },
+                TableHeader {
+                    element: header,
+                    width: 30.0,
+                },
+            ],
+            rows: vec![TableRow {
+                cells: cells.into_iter().map(TableCell::with_elements).collect(),
+            }],
+            alignments: vec![],
+            caption: vec![],
+        };
+        let document = Document::new(vec![table(
+            image.clone(),
+            vec![vec![image.clone(), text("Logo")], vec![image]],
+        )]);
+
+        let without_images = document.filter(|element| !matches!(element, Element::Image(_)));
+        let empty = Element::Paragraph { elements: vec![] };
+        assert_eq!(
+            without_images.get_detail(),
+            vec![&table(empty, vec![vec![text("Logo")], vec![]])]
+        );
+    }
+
     #[test]
     fn test_image_type_from_str() {
         assert_eq!(ImageType::from_str("png").unwrap(), ImageType::Png);
@@ -1239,6 +5004,816 @@ pub mod tests {
         let encoded = image.to_base64();
         assert_eq!(encoded, base64_data);
     }
+
+    #[test]
+    fn test_color() -> anyhow::Result<()> {
+        assert_eq!(Color::from_str("#C00")?, Color::new(204, 0, 0));
+        assert_eq!(Color::from_str("1f4e79")?, Color::new(31, 78, 121));
+        assert_eq!(
+            Color::from_str("rgb(0, 128, 255)")?,
+            Color::new(0, 128, 255)
+        );
+        assert_eq!(Color::from_str("Navy")?, Color::new(0, 0, 128));
+        assert!(Color::from_str("auto").is_err());
+        assert_eq!(Color::new(31, 78, 121).to_string(), "#1f4e79");
+        Ok(())
+    }
+
+    #[test]
+    fn test_media_fallback() {
+        let src = "data:audio/mpeg;base64,SUQz";
+        let source = MediaSource::from_src(src);
+        assert_eq!(
+            source,
+            MediaSource::Embedded {
+                mime: "audio/mpeg".to_string(),
+                bytes: Bytes::from_static(b"ID3"),
+            }
+        );
+        assert_eq!(source.to_src(), src);
+
+        let media = Element::Media {
+            kind: MediaKind::Video,
+            source: MediaSource::from_src("clip.mp4"),
+            poster: None,
+        };
+        assert_eq!(
+            media.media_fallback(),
+            Element::Paragraph {
+                elements: vec![Element::Hyperlink {
+                    title: "clip.mp4".to_string(),
+                    url: "clip.mp4".to_string(),
+                    alt: "video".to_string(),
+                    size: 8,
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn test_chart_fallback() {
+        let chart = Element::Chart {
+            chart_type: ChartType::Line,
+            series: vec![ChartSeries {
+                name: "Sales".to_string(),
+                values: vec![1.5, 3.0, 2.0],
+            }],
+            categories: vec!["Q1".to_string(), "Q2".to_string()],
+        };
+        let Element::Table { headers, rows, .. } = chart.chart_fallback() else {
+            panic!("expected a table");
+        };
+        let text = |element: &Element| match element {
+            Element::Text { text, .. } => text.clone(),
+            other => panic!("expected text, got {:?}", other),
+        };
+        let headers: Vec<String> = headers.iter().map(|header| text(&header.element)).collect();
+        assert_eq!(headers, ["", "Sales"]);
+        let rows: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| row.cells.iter().map(|cell| text(&cell.element())).collect())
+            .collect();
+        assert_eq!(rows, [["Q1", "1.5"], ["Q2", "3"], ["", "2"]]);
+    }
+
+    #[test]
+    fn test_emoji_shortcodes() {
+        let mut document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: "Shipped :rocket: at 10:30:00 :+1::not_an_emoji:".to_string(),
+                    font: FontSpec::from(8),
+                }],
+            },
+            Element::CodeBlock {
+                language: None,
+                code: "a :tada: b".to_string(),
+            },
+        ]);
+        document.replace_emoji_shortcodes();
+        let elements = document.get_all_elements();
+        assert_eq!(
+            elements[0],
+            &Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: "Shipped 🚀 at 10:30:00 👍:not_an_emoji:".to_string(),
+                    font: FontSpec::from(8),
+                }],
+            }
+        );
+        assert!(matches!(elements[1], Element::CodeBlock { code, .. } if code == "a :tada: b"));
+
+        let mut document = Document::new(vec![Element::Header {
+            level: 1,
+            text: "Love ❤ and ❤\u{fe0f} 👍".to_string(),
+            anchor: None,
+        }]);
+        document.replace_emoji_with_shortcodes();
+        assert!(matches!(
+            document.get_all_elements()[0],
+            Element::Header { text, .. } if text == "Love :heart: and :heart: :+1:"
+        ));
+    }
+
+    #[test]
+    fn test_resolve_custom() {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let callout = |kind: &str| Element::Custom {
+            kind: kind.to_string(),
+            data: serde_json::json!({ "title": "Note" }),
+            children: vec![text("Back up first")],
+        };
+        let document = Document::new(vec![
+            callout("callout"),
+            Element::Blockquote {
+                elements: vec![callout("unknown")],
+                attribution: vec![],
+            },
+        ]);
+        let resolved = document.resolve_custom(|kind, data, children| {
+            (kind == "callout").then(|| {
+                let mut elements = vec![Element::Header {
+                    level: 3,
+                    text: data["title"].as_str().unwrap_or_default().to_string(),
+                    anchor: None,
+                }];
+                elements.extend_from_slice(children);
+                elements
+            })
+        });
+        assert_eq!(
+            resolved.get_all_elements(),
+            vec![
+                &Element::Header {
+                    level: 3,
+                    text: "Note".to_string(),
+                    anchor: None,
+                },
+                &text("Back up first"),
+                &Element::Blockquote {
+                    elements: vec![],
+                    attribution: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nest_sections() {
+        let header = |level: u8, text: &str| Element::Header {
+            level,
+            text: text.to_string(),
+            anchor: None,
+        };
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![
+            text("Preface"),
+            header(1, "One"),
+            text("First"),
+            header(2, "One.One"),
+            text("Second"),
+            header(1, "Two"),
+        ]);
+        let nested = document.nest_sections();
+        assert_eq!(
+            nested.get_all_elements(),
+            vec![
+                &text("Preface"),
+                &Element::Section {
+                    level: 1,
+                    children: vec![
+                        header(1, "One"),
+                        text("First"),
+                        Element::Section {
+                            level: 2,
+                            children: vec![header(2, "One.One"), text("Second")],
+                        },
+                    ],
+                },
+                &Element::Section {
+                    level: 1,
+                    children: vec![header(1, "Two")],
+                },
+            ]
+        );
+        assert_eq!(nested.flatten_sections(), document);
+        assert_eq!(nested.toc_entries(6), document.toc_entries(6));
+        assert_eq!(nested.nest_sections(), nested);
+    }
+
+    #[test]
+    fn test_insert_toc() {
+        let header = |level: u8, text: &str| Element::Header {
+            level,
+            text: text.to_string(),
+            anchor: None,
+        };
+        let mut document = Document::new(vec![
+            header(1, "Guide"),
+            header(2, "Setup"),
+            header(3, "Linux"),
+            header(2, "Setup"),
+        ]);
+        document.insert_toc(2);
+        let elements = document.get_all_elements();
+        assert_eq!(elements[1], &Element::TableOfContents { max_level: 2 });
+        assert!(matches!(
+            elements[4],
+            Element::Header { anchor: Some(anchor), .. } if anchor == "setup-2"
+        ));
+
+        let entries = document.toc_entries(2);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].anchor.as_deref(), Some("guide"));
+        let children: Vec<&str> = entries[0]
+            .children
+            .iter()
+            .map(|entry| entry.text.as_str())
+            .collect();
+        assert_eq!(children, vec!["Setup", "Setup"]);
+        assert!(entries[0].children[0].children.is_empty());
+    }
+
+    #[test]
+    fn test_document_builder() {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = DocumentBuilder::new()
+            .page_header("Report")
+            .header(1, "Revenue")
+            .paragraph(|p| p.text("Grew by ").bold("12%"))
+            .table(["Region", "Revenue"], [["North", "1200"]])
+            .list(["One", "Two"], true)
+            .page_format(PageFormat::Letter)
+            .build();
+
+        let mut expected = Document::new_with_dimensions(
+            vec![text("Report")],
+            vec![
+                Element::Header {
+                    level: 1,
+                    text: "Revenue".to_string(),
+                    anchor: None,
+                },
+                Element::Paragraph {
+                    elements: vec![
+                        text("Grew by "),
+                        Element::Styled {
+                            style: TextStyle::BOLD,
+                            elements: vec![text("12%")],
+                        },
+                    ],
+                },
+                Element::Table {
+                    headers: vec![
+                        TableHeader {
+                            element: text("Region"),
+                            width: 30.0,
+                        },
+                        TableHeader {
+                            element: text("Revenue"),
+                            width: 30.0,
+                        },
+                    ],
+                    rows: vec![TableRow {
+                        cells: vec![TableCell::new(text("North")), TableCell::new(text("1200"))],
+                    }],
+                    alignments: Vec::new(),
+                    caption: vec![],
+                },
+                Element::List {
+                    elements: vec![
+                        ListItem {
+                            element: text("One"),
+                            checked: None,
+                        },
+                        ListItem {
+                            element: text("Two"),
+                            checked: None,
+                        },
+                    ],
+                    numbered: true,
+                },
+            ],
+            vec![],
+            PageFormat::Letter,
+        );
+        assert_eq!(document, expected);
+
+        // Without page bands the document has the detail band only
+        expected = Document::new(vec![text("Body")]);
+        assert_eq!(DocumentBuilder::new().element(text("Body")).build(), expected);
+    }
+
+    #[test]
+    fn test_walk() {
+        #[derive(Default)]
+        struct Texts {
+            texts: Vec<String>,
+            cells: usize,
+        }
+        impl ElementVisitor for Texts {
+            fn visit_element(&mut self, element: &Element) {
+                if let Element::Text { text, .. } = element {
+                    self.texts.push(text.clone());
+                }
+            }
+
+            fn visit_table_cell(&mut self, _cell: &TableCell) {
+                self.cells += 1;
+            }
+        }
+
+        struct Uppercase;
+        impl ElementVisitorMut for Uppercase {
+            fn visit_element(&mut self, element: &mut Element) {
+                if let Element::Text { text, .. } = element {
+                    *text = text.to_uppercase();
+                }
+            }
+        }
+
+        let mut document = DocumentBuilder::new()
+            .page_header("Report")
+            .paragraph(|p| p.text("Grew by ").bold("12%"))
+            .table(["Region"], [["North"], ["South"]])
+            .list(["One"], false)
+            .build();
+        let mut visitor = Texts::default();
+        document.walk(&mut visitor);
+        assert_eq!(
+            visitor.texts,
+            vec!["Report", "Grew by ", "12%", "Region", "North", "South", "One"]
+        );
+        assert_eq!(visitor.cells, 2);
+
+        document.walk_mut(&mut Uppercase);
+        let mut visitor = Texts::default();
+        document.walk(&mut visitor);
+        assert_eq!(
+            visitor.texts,
+            vec!["REPORT", "GREW BY ", "12%", "REGION", "NORTH", "SOUTH", "ONE"]
+        );
+    }
+
+    #[test]
+    fn test_iter_elements() {
+        let document = DocumentBuilder::new()
+            .page_header("Report")
+            .paragraph(|p| p.text("See ").link("the site", "https://example.com"))
+            .table(["Links"], [["none"]])
+            .build();
+
+        let urls: Vec<&str> = document
+            .iter_elements()
+            .filter_map(|item| match item.element {
+                Element::Hyperlink { url, .. } => Some(url.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(urls, vec!["https://example.com"]);
+
+        let texts: Vec<(&str, usize, bool)> = document
+            .iter_elements()
+            .filter_map(|item| match item.element {
+                Element::Text { text, .. } => Some((
+                    text.as_str(),
+                    item.depth,
+                    matches!(item.band, Band::PageHeader(_)),
+                )),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                ("Report", 0, true),
+                ("See ", 1, false),
+                ("Links", 1, false),
+                ("none", 1, false)
+            ]
+        );
+        let cell = document
+            .iter_elements()
+            .find(|item| matches!(item.element, Element::Text { text, .. } if text == "none"))
+            .unwrap();
+        assert!(matches!(cell.parent, Some(Element::Table { .. })));
+    }
+
+    #[test]
+    fn test_select() {
+        let document = Document::new(vec![
+            Element::Section {
+                level: 1,
+                children: vec![
+                    Element::Header {
+                        level: 1,
+                        text: "Results".to_string(),
+                        anchor: None,
+                    },
+                    Element::Header {
+                        level: 2,
+                        text: "Revenue".to_string(),
+                        anchor: None,
+                    },
+                ],
+            },
+            DocumentBuilder::new()
+                .table(["Region"], [["North"]])
+                .build()
+                .get_all_elements()[0]
+                .clone(),
+        ]);
+
+        let tables = document.select(ElementKind::Table);
+        assert_eq!(tables.len(), 1);
+        assert_eq!(document.select_cloned(ElementKind::Table), vec![tables[0].clone()]);
+        assert_eq!(
+            document.select_headers(2),
+            vec![&Element::Header {
+                level: 2,
+                text: "Revenue".to_string(),
+                anchor: None,
+            }]
+        );
+        let texts = document.select_where(|element| element.kind() == ElementKind::Text);
+        assert_eq!(texts.len(), 2);
+        assert_eq!(ElementKind::from_str("Table"), Ok(ElementKind::Table));
+    }
+
+    #[test]
+    fn test_merge() {
+        let footnote = |id: &str, text: &str| {
+            vec![
+                Element::Paragraph {
+                    elements: vec![Element::FootnoteReference { id: id.to_string() }],
+                },
+                Element::Footnote {
+                    id: id.to_string(),
+                    content: vec![Element::Text {
+                        text: text.to_string(),
+                        font: FontSpec::from(8),
+                    }],
+                },
+            ]
+        };
+        let mut first = DocumentBuilder::new()
+            .page_header("First")
+            .element(Element::Header {
+                level: 1,
+                text: "Intro".to_string(),
+                anchor: Some("intro".to_string()),
+            })
+            .build();
+        first.add_detail(footnote("1", "One")[0].clone());
+        first.add_detail(footnote("1", "One")[1].clone());
+        first.metadata.title = Some("Manual".to_string());
+        let mut second = DocumentBuilder::new()
+            .page_header("Second")
+            .page_footer("Page")
+            .element(Element::Header {
+                level: 1,
+                text: "Intro".to_string(),
+                anchor: Some("intro".to_string()),
+            })
+            .paragraph(|p| p.link("Back", "#intro"))
+            .build();
+        second.add_detail(footnote("1", "Other")[0].clone());
+        second.add_detail(footnote("1", "Other")[1].clone());
+        second.metadata.title = Some("Appendix".to_string());
+        second.metadata.authors = vec!["Ann".to_string()];
+
+        let merged = Document::merge_with_options(
+            vec![first, second],
+            &MergeOptions { page_breaks: true },
+        );
+        assert_eq!(merged.get_page_header().len(), 1);
+        assert_eq!(merged.get_page_footer().len(), 1);
+        let detail = merged.get_detail();
+        assert_eq!(detail.len(), 8);
+        assert_eq!(detail[3], &Element::PageBreak);
+        assert!(matches!(
+            detail[4],
+            Element::Header { anchor: Some(anchor), .. } if anchor == "intro-2"
+        ));
+        assert!(matches!(
+            detail[5],
+            Element::Paragraph { elements } if matches!(
+                &elements[0],
+                Element::Hyperlink { url, .. } if url == "#intro-2"
+            )
+        ));
+        assert_eq!(detail[7], &footnote("2", "Other")[1]);
+        assert_eq!(merged.metadata.title.as_deref(), Some("Manual"));
+        assert_eq!(merged.metadata.authors, vec!["Ann".to_string()]);
+    }
+
+    #[test]
+    fn test_split() {
+        let mut document = DocumentBuilder::new()
+            .page_header("Manual")
+            .page_footer("Page")
+            .header(1, "Install")
+            .element(Element::Paragraph {
+                elements: vec![Element::FootnoteReference {
+                    id: "1".to_string(),
+                }],
+            })
+            .header(2, "Requirements")
+            .paragraph(|p| p.text("A recent compiler."))
+            .page_break()
+            .header(1, "Usage")
+            .paragraph(|p| p.text("Run it."))
+            .element(Element::Footnote {
+                id: "1".to_string(),
+                content: vec![plain_text("See the website.")],
+            })
+            .build();
+        document.set_summary(vec![plain_text("The end")]);
+
+        let chapters = document.split_by_heading(1);
+        assert_eq!(chapters.len(), 2);
+        for chapter in &chapters {
+            assert_eq!(chapter.get_page_header().len(), 1);
+            assert_eq!(chapter.get_page_footer().len(), 1);
+        }
+        assert_eq!(chapters[0].get_detail().len(), 6);
+        assert!(matches!(
+            chapters[0].get_detail()[5],
+            Element::Footnote { id, .. } if id == "1"
+        ));
+        assert!(chapters[0].get_summary().is_empty());
+        assert_eq!(chapters[1].get_detail().len(), 2);
+        assert_eq!(chapters[1].get_summary().len(), 1);
+        assert_eq!(document.split_by_heading(2).len(), 3);
+
+        let pages = document.split_by_page_breaks();
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[1].get_detail().len(), 2);
+        assert_eq!(pages[0].get_detail().len(), 5);
+        assert!(!pages[0].get_detail().contains(&&Element::PageBreak));
+    }
+
+    #[test]
+    fn test_replace_text() {
+        let mut document = DocumentBuilder::new()
+            .page_footer("ACME Corp, 2023")
+            .header(1, "About ACME")
+            .paragraph(|p| p.text("ACME ships in ").link("ACME store", "https://acme.test"))
+            .table(["Name"], [["ACME 2023"]])
+            .list(["acme", "ACME"], false)
+            .code_block(None, "ACME")
+            .build();
+
+        assert_eq!(document.replace_text("ACME", "Initech"), 6);
+        assert_eq!(document.replace_text("ACME", "Initech"), 0);
+        assert_eq!(document.replace_text("", "Initech"), 0);
+        assert!(matches!(
+            document.get_page_footer()[0],
+            Element::Text { text, .. } if text == "Initech Corp, 2023"
+        ));
+        let links = document.select(ElementKind::Hyperlink);
+        assert!(matches!(links[0], Element::Hyperlink { title, .. } if title == "Initech store"));
+        assert!(matches!(
+            document.get_detail()[4],
+            Element::CodeBlock { code, .. } if code == "ACME"
+        ));
+
+        let year = regex::Regex::new(r"(\d{4})").unwrap();
+        assert_eq!(document.replace_text(year, "FY$1"), 2);
+        let cells = document.select_where(|element| {
+            matches!(element, Element::Text { text, .. } if text == "Initech FY2023")
+        });
+        assert_eq!(cells.len(), 1);
+    }
+
+    #[test]
+    fn test_plain_text() {
+        let mut document = DocumentBuilder::new()
+            .page_header("Manual")
+            .header(1, "Getting   started")
+            .paragraph(|p| {
+                p.text("Install  the ")
+                    .bold("latest")
+                    .text(" release from ")
+                    .link("the website", "https://example.com")
+                    .line_break()
+                    .text("  then run it.")
+            })
+            .list(["One", "Two"], true)
+            .table(["Name", "Size"], [["a.txt", "1 KB"]])
+            .code_block(Some("sh"), "shiva convert\n")
+            .build();
+        document.add_detail(Element::Comment {
+            author: None,
+            date: None,
+            text: "Check this".to_string(),
+            range: None,
+        });
+
+        assert_eq!(
+            document.to_plain_text(),
+            "Manual\n\nGetting started\n\n\
+             Install the latest release from the website\nthen run it.\n\n\
+             One\nTwo\n\nName\tSize\na.txt\t1 KB\n\nshiva convert"
+        );
+        let quote = Element::Blockquote {
+            elements: vec![plain_text(" Be  brief. ")],
+            attribution: vec![plain_text("Anon")],
+        };
+        assert_eq!(quote.plain_text(), "Be brief.\nAnon");
+        assert_eq!(Element::HorizontalRule.plain_text(), "");
+    }
+
+    #[test]
+    fn test_stats() {
+        let image = ImageData::new(
+            Bytes::from_static(&[0; 100]),
+            "Logo".to_string(),
+            "Logo".to_string(),
+            "png".to_string(),
+            "center".to_string(),
+            ImageDimension::default(),
+        );
+        let document = DocumentBuilder::new()
+            .page_header("Annual report")
+            .header(1, "Results")
+            .paragraph(|p| p.text("Sales grew ").bold("twice").text(" as fast."))
+            .table(["Region", "Sales"], [["North", "12"], ["South", "9"]])
+            .page_break()
+            .image(image)
+            .paragraph(|p| p.text("word ".repeat(2000)))
+            .build();
+
+        let stats = document.stats();
+        assert_eq!(stats.words, 2 + 1 + 5 + 6 + 1 + 2000);
+        assert_eq!(stats.characters_without_spaces, 12 + 7 + 21 + 24 + 4 + 8000);
+        assert_eq!(stats.elements[&ElementKind::Paragraph], 2);
+        assert_eq!(stats.elements[&ElementKind::Text], 1 + 3 + 6 + 1);
+        assert_eq!(stats.elements.get(&ElementKind::List), None);
+        assert_eq!((stats.images, stats.image_bytes), (1, 100));
+        assert_eq!(stats.tables, vec![TableStats { rows: 2, columns: 2 }]);
+        assert_eq!(stats.estimated_pages, 1 + 3);
+
+        let mut landscape = DocumentBuilder::new().build();
+        landscape.set_orientation(PageOrientation::Landscape);
+        assert_eq!(landscape.stats().estimated_pages, 1);
+    }
+
+    #[test]
+    #[cfg(all(feature = "text", feature = "markdown"))]
+    fn test_parse_with_options() -> anyhow::Result<()> {
+        let latin1 = Bytes::from_static(b"Caf\xe9 cr\xe8me");
+        assert!(Document::parse(&latin1, DocumentType::Text).is_err());
+        let options = ParserOptions {
+            encoding: Some("windows-1252".to_string()),
+            ..ParserOptions::default()
+        };
+        let document = Document::parse_with_options(&latin1, DocumentType::Text, &options)?;
+        assert_eq!(document.to_plain_text(), "Café crème");
+
+        let markdown = Bytes::from("# Menu\n\n![Logo](missing.png)\n\n- Tea\n- Coffee\n");
+        assert!(Document::parse(&markdown, DocumentType::Markdown).is_err());
+        let mut options = ParserOptions {
+            images: ImagePolicy::Skip,
+            ..ParserOptions::default()
+        };
+        let document = Document::parse_with_options(&markdown, DocumentType::Markdown, &options)?;
+        assert!(matches!(
+            document.get_detail()[1],
+            Element::Image(image) if image.bytes().is_empty()
+        ));
+        options.images = ImagePolicy::Loader(Arc::new(|image: &str| {
+            Ok(Bytes::from(image.to_string()))
+        }));
+        let document = Document::parse_with_options(&markdown, DocumentType::Markdown, &options)?;
+        assert!(matches!(
+            document.get_detail()[1],
+            Element::Image(image) if image.bytes() == "missing.png"
+        ));
+
+        // Header, image, list and its two items
+        options.max_elements = Some(4);
+        let error = Document::parse_with_options(&markdown, DocumentType::Markdown, &options)
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ParserError>(),
+            Some(ParserError::TooManyElements(4))
+        ));
+        options.max_elements = Some(5);
+        assert!(Document::parse_with_options(&markdown, DocumentType::Markdown, &options).is_ok());
+
+        options.images = ImagePolicy::Skip;
+        options.strict = true;
+        let error = Document::parse_with_options(&markdown, DocumentType::Markdown, &options)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Malformed input: image without data at 0.1 (line 3, column 1)"
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "text", feature = "markdown"))]
+    fn test_generate_with_options() -> anyhow::Result<()> {
+        let image = ImageData::new(
+            Bytes::from_static(b"\x89PNG"),
+            "Logo".to_string(),
+            "Logo".to_string(),
+            "png".to_string(),
+            "center".to_string(),
+            ImageDimension::default(),
+        );
+        let document = DocumentBuilder::new()
+            .header(1, "Menu")
+            .image(image)
+            .header(2, "Drinks")
+            .paragraph(|p| p.text("Tea, coffee and juice are served until the kitchen closes."))
+            .build();
+
+        let markdown = String::from_utf8(document.generate(DocumentType::Markdown)?.to_vec())?;
+        assert!(markdown.contains("](data:image/png;base64,iVBORw=="));
+        let directory = std::env::temp_dir().join("shiva_generator_options");
+        std::fs::create_dir_all(&directory)?;
+        let mut options = GeneratorOptions {
+            images: ImageEmbedding::Directory(directory.to_string_lossy().to_string()),
+            ..GeneratorOptions::default()
+        };
+        let markdown = document.generate_with_options(DocumentType::Markdown, &options)?;
+        assert!(String::from_utf8(markdown.to_vec())?.contains("![](image1.png"));
+        assert_eq!(std::fs::read(directory.join("image1.png"))?, b"\x89PNG");
+        options.images = ImageEmbedding::Skip;
+        let markdown = document.generate_with_options(DocumentType::Markdown, &options)?;
+        assert!(!String::from_utf8(markdown.to_vec())?.contains("!["));
+
+        options.line_width = Some(20);
+        let text = document.generate_with_options(DocumentType::Text, &options)?;
+        let text = String::from_utf8(text.to_vec())?;
+        assert!(text.contains("Tea, coffee and\njuice are served\n"));
+        assert!(text.lines().all(|line| line.chars().count() <= 20));
+
+        options.font = Some("Georgia".to_string());
+        options.table_of_contents = Some(2);
+        options.page_format = Some(PageFormat::Letter);
+        let prepared = options.prepare(&document).unwrap();
+        assert_eq!(prepared.page_format, PageFormat::Letter);
+        let detail = prepared.get_detail();
+        assert_eq!(detail[1], &Element::TableOfContents { max_level: 2 });
+        assert!(matches!(
+            detail.last().unwrap(),
+            Element::Paragraph { elements } if matches!(
+                &elements[0],
+                Element::Text { font, .. } if font.family.as_deref() == Some("Georgia")
+            )
+        ));
+        assert!(GeneratorOptions::default().prepare(&document).is_none());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "pdf", feature = "markdown"))]
+    fn test_cancellation_options() -> anyhow::Result<()> {
+        let document = DocumentBuilder::new().header(1, "Menu").build();
+        let pdf = document.generate(DocumentType::PDF)?;
+        let token = CancellationToken::new();
+        let parser_options = ParserOptions {
+            cancellation: Some(token.clone()),
+            ..ParserOptions::default()
+        };
+        let generator_options = GeneratorOptions {
+            cancellation: Some(token.clone()),
+            ..GeneratorOptions::default()
+        };
+        assert!(Document::parse_with_options(&pdf, DocumentType::PDF, &parser_options).is_ok());
+        assert!(generator_options.layout().cancellation.is_some());
+
+        token.cancel();
+        let error = Document::parse_with_options(&pdf, DocumentType::PDF, &parser_options)
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ParserError>(),
+            Some(ParserError::Cancelled)
+        ));
+        for document_type in [DocumentType::PDF, DocumentType::Markdown] {
+            let error = document
+                .generate_with_options(document_type, &generator_options)
+                .unwrap_err();
+            assert!(matches!(
+                error.downcast_ref::<GeneratorError>(),
+                Some(GeneratorError::Cancelled)
+            ));
+        }
+        Ok(())
+    }
 }
 
 pub use bytes;
@@ -1,10 +1,11 @@
 use crate::core::{
     Document,
     Element::{Table, Text},
-    TableCell, TableHeader, TableRow, TransformerTrait,
+    FontSpec, TableCell, TableHeader, TableRow, TransformerTrait,
 };
 use bytes::Bytes;
 use log::info;
+use std::io::Read;
 pub struct Transformer;
 
 #[allow(unused)]
@@ -20,103 +21,149 @@ impl TransformerTrait for Transformer {
     /// # Returns
     /// A result containing a `Document` if successful, or an `anyhow::Error` in case of failure.
     fn parse(document: &Bytes) -> anyhow::Result<Document> {
-        // Deserialize the CSV data into a nested Vec structure.
-        let document = serialize_csv(document)?;
+        parse_dialect(document, &CSV)
+    }
 
-        // Check if the document is empty and return an empty `Document` if so.
-        if document.is_empty() {
-            return Ok(Document::new(Vec::new()));
-        }
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        generate_dialect(document, &CSV)
+    }
 
-        // Create an iterator over the rows of the CSV data.
-        let mut document_iter = document.iter();
-
-        // Extract the first row, which contains the headers.
-        let headings: &Vec<String> = document_iter.next().unwrap();
-        let mut headers: Vec<TableHeader> = Vec::new();
-
-        // Process each header name to create `TableHeader` elements.
-        for name in headings {
-            headers.push(TableHeader {
-                element: Text {
-                    text: name.clone(),
-                    size: 8, // Default font size
-                },
-                width: 10.0, // Default width, can be adjusted as needed
-            });
-        }
+    /// Parses the records as they are read, without holding the whole input
+    fn parse_reader(reader: impl Read) -> anyhow::Result<Document> {
+        parse_dialect_reader(reader, &CSV)
+    }
+}
 
-        // Prepare to collect the table rows.
-        let mut rows: Vec<TableRow> = Vec::with_capacity(document.len() - 1);
-
-        // Process each subsequent row in the CSV data.
-        for lines in document_iter {
-            let mut curr_row: Vec<TableCell> = Vec::with_capacity(headers.len());
-
-            // Create a `TableCell` for each cell in the row.
-            for cell in lines {
-                curr_row.push(TableCell {
-                    element: Text {
-                        text: cell.clone(),
-                        size: 8, // Default font size
-                    },
-                });
-            }
+/// Delimiter and quoting rules of a delimiter-separated values format
+pub(crate) struct Dialect {
+    delimiter: u8,
+    /// Without quoting, delimiters and line breaks inside cells are replaced by spaces
+    quoting: bool,
+}
 
-            // Add the completed row to the rows collection.
-            rows.push(TableRow { cells: curr_row });
+pub(crate) const CSV: Dialect = Dialect {
+    delimiter: b',',
+    quoting: true,
+};
+
+#[cfg(feature = "tsv")]
+pub(crate) const TSV: Dialect = Dialect {
+    delimiter: b'\t',
+    quoting: false,
+};
+
+/// Reads the first row as the table headers and the remaining rows as the table rows.
+pub(crate) fn parse_dialect(document: &Bytes, dialect: &Dialect) -> anyhow::Result<Document> {
+    // Invalid UTF-8 fails here rather than as a CSV error on the record holding it
+    std::str::from_utf8(document)?;
+    parse_dialect_reader(document.as_ref(), dialect)
+}
+
+/// Like [`parse_dialect`], a record at a time as they are read.
+pub(crate) fn parse_dialect_reader(reader: impl Read, dialect: &Dialect) -> anyhow::Result<Document> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false) // We consider that we have no headers so that they'll be preserved
+        .delimiter(dialect.delimiter)
+        .quoting(dialect.quoting)
+        .from_reader(reader);
+    let mut records = rdr.records();
+
+    // Extract the first row, which contains the headers. Without one the document is empty.
+    let Some(headings) = records.next() else {
+        return Ok(Document::new(Vec::new()));
+    };
+    let mut headers: Vec<TableHeader> = Vec::new();
+
+    // Process each header name to create `TableHeader` elements.
+    for name in headings?.iter() {
+        headers.push(TableHeader {
+            element: Text {
+                text: name.to_string(),
+                font: FontSpec::from(8), // Default font size
+            },
+            width: 10.0, // Default width, can be adjusted as needed
+        });
+    }
+
+    // Prepare to collect the table rows.
+    let mut rows: Vec<TableRow> = Vec::new();
+
+    // Process each subsequent row in the CSV data.
+    for record in records {
+        let record = record?;
+        let mut curr_row: Vec<TableCell> = Vec::with_capacity(headers.len());
+
+        // Create a `TableCell` for each cell in the row.
+        for cell in record.iter() {
+            curr_row.push(TableCell::new(Text {
+                text: cell.to_string(),
+                font: FontSpec::from(8), // Default font size
+            }));
         }
 
-        // Construct the `Document` with the table created from the CSV data.
-        Ok(Document::new(vec![Table { headers, rows }]))
+        // Add the completed row to the rows collection.
+        rows.push(TableRow { cells: curr_row });
     }
 
-    fn generate(document: &Document) -> anyhow::Result<Bytes> {
-        let elements = document.get_all_elements();
+    // Construct the `Document` with the table created from the CSV data.
+    Ok(Document::new(vec![Table {
+        headers,
+        rows,
+        alignments: vec![],
+        caption: vec![],
+    }]))
+}
 
-        let mut data: Vec<Vec<String>> = Vec::new();
+/// Writes the headers and rows of every table in the document.
+pub(crate) fn generate_dialect(document: &Document, dialect: &Dialect) -> anyhow::Result<Bytes> {
+    let document = &document.flatten_sections();
+    let elements = document.get_all_elements();
 
-        for element in elements {
-            if let Table { headers, rows } = element {
-                // Create a new vector for the header row
-                let mut header_line = Vec::new();
-                for header in headers {
-                    if let Text { text, size: _ } = &header.element {
-                        header_line.push(text.clone())
-                    }
+    let mut data: Vec<Vec<String>> = Vec::new();
+
+    for element in elements {
+        if let Table { headers, rows, .. } = element {
+            // Create a new vector for the header row
+            let mut header_line = Vec::new();
+            for header in headers {
+                if let Text { text, font: _ } = &header.element {
+                    header_line.push(text.clone())
                 }
-                // Push header row to data
-                data.push(header_line);
-
-                // Iterate over each row
-                for row in rows {
-                    let mut curr_line = Vec::new(); // This must be inside the loop
-                    for cell in &row.cells {
-                        if let Text { text, size: _ } = &cell.element {
-                            curr_line.push(text.clone())
-                        }
+            }
+            // Push header row to data
+            data.push(header_line);
+
+            // Iterate over each row
+            for row in rows {
+                let mut curr_line = Vec::new(); // This must be inside the loop
+                for cell in &row.cells {
+                    if let Text { text, font: _ } = &cell.element() {
+                        curr_line.push(text.clone())
                     }
-                    // Push each row to data
-                    data.push(curr_line);
                 }
+                // Push each row to data
+                data.push(curr_line);
             }
         }
+    }
 
-        // Serialize the data into CSV format and convert it to Bytes
-        let csv_bytes = deserialize_csv(&data)?;
+    // Serialize the data into CSV format and convert it to Bytes
+    let csv_bytes = deserialize_csv(&data, dialect)?;
 
-        // Return Bytes and an empty HashMap for images or additional data
-        Ok(csv_bytes)
-    }
+    // Return Bytes and an empty HashMap for images or additional data
+    Ok(csv_bytes)
 }
 
-fn serialize_csv(csv_data: &Bytes) -> anyhow::Result<Vec<Vec<String>>> {
+#[cfg(test)]
+fn serialize_csv(csv_data: &Bytes, dialect: &Dialect) -> anyhow::Result<Vec<Vec<String>>> {
     // Convert Bytes to a UTF-8 string slice
     let data_str = std::str::from_utf8(csv_data)?;
 
     // Create a CSV reader from a string slice
     let mut rdr = csv::ReaderBuilder::new()
         .has_headers(false) // We consider that we have no headers so that they'll be preserved
+        .delimiter(dialect.delimiter)
+        .quoting(dialect.quoting)
         .from_reader(data_str.as_bytes());
 
     let mut data = Vec::new();
@@ -131,13 +178,26 @@ fn serialize_csv(csv_data: &Bytes) -> anyhow::Result<Vec<Vec<String>>> {
     Ok(data)
 }
 
-fn deserialize_csv(data: &Vec<Vec<String>>) -> anyhow::Result<Bytes> {
+fn deserialize_csv(data: &Vec<Vec<String>>, dialect: &Dialect) -> anyhow::Result<Bytes> {
     // Create a CSV writer that writes into a string
-    let mut wtr = csv::WriterBuilder::new().from_writer(vec![]);
+    let mut builder = csv::WriterBuilder::new();
+    builder.delimiter(dialect.delimiter);
+    if !dialect.quoting {
+        builder.quote_style(csv::QuoteStyle::Never);
+    }
+    let mut wtr = builder.from_writer(vec![]);
 
     // Iterate over the data and write each row to the CSV writer
     for row in data {
-        wtr.write_record(row)?;
+        if dialect.quoting {
+            wtr.write_record(row)?;
+        } else {
+            let delimiter = char::from(dialect.delimiter);
+            wtr.write_record(
+                row.iter()
+                    .map(|cell| cell.replace([delimiter, '\r', '\n'], " ")),
+            )?;
+        }
     }
 
     // After writing all data, we consume the writer to get the underlying string
@@ -151,7 +211,7 @@ mod tests {
     use log::info;
 
     use crate::core::{tests::init_logger, TransformerTrait};
-    use crate::csv::{self, deserialize_csv, serialize_csv};
+    use crate::csv::{self, deserialize_csv, serialize_csv, CSV};
     use crate::markdown;
 
     #[test]
@@ -180,9 +240,25 @@ mod tests {
             generated_string,
             // this assures that new changes made to test string won't
             // affect the result of tests if the implementation is correct
-            deserialize_csv(&serialize_csv(&bytes::Bytes::from(document))?)?
+            deserialize_csv(&serialize_csv(&bytes::Bytes::from(document), &CSV)?, &CSV)?
         );
 
         Ok(())
     }
+
+    #[test]
+    fn test_parse_reader() -> anyhow::Result<()> {
+        let document = "Name,Size\n\"a,b.txt\",1 KB\n\"multi\nline\",2 KB\n";
+        // Reads a few bytes at a time, splitting records and quoted cells
+        let reader = std::io::BufReader::with_capacity(4, document.as_bytes());
+        let parsed = csv::Transformer::parse_reader(reader)?;
+        assert_eq!(parsed, csv::Transformer::parse(&document.as_bytes().into())?);
+        let [crate::core::Element::Table { rows, .. }] = parsed.get_all_elements()[..] else {
+            panic!("expected a table, got {:?}", parsed.get_all_elements());
+        };
+        assert_eq!(rows.len(), 2);
+
+        assert!(csv::Transformer::parse_reader(&b""[..])?.get_all_elements().is_empty());
+        Ok(())
+    }
 }
@@ -0,0 +1,645 @@
+use crate::core::*;
+use crate::epub::{escape, media_type, modified_timestamp};
+use bytes::Bytes;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Write};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+const DTBOOK_FILE: &str = "book.xml";
+const SMIL_FILE: &str = "book.smil";
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(_document: &Bytes) -> anyhow::Result<Document> {
+        Err(anyhow::anyhow!("DAISY parsing is not supported"))
+    }
+
+    /// Writes a zipped DAISY 3 text-only book: the dtbook content, its package file, a SMIL
+    /// reading order and the NCX navigation. Headers open nested `level1`..`level6`
+    /// sections, a header that skips levels is moved up to the next level, and every header
+    /// is an entry of the navigation. Page headers and footers and comments are left out.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        let mut book = Book::default();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                book.element(element);
+            }
+        }
+        while book.depth > 0 {
+            book.body.push_str(&format!("</level{}>\n", book.depth));
+            book.depth -= 1;
+        }
+
+        let title = match &document.metadata.title {
+            Some(title) => title.clone(),
+            None => book
+                .headings
+                .first()
+                .map_or("Document", |heading| heading.text.as_str())
+                .to_string(),
+        };
+        let language = document.metadata.language.as_deref().unwrap_or("en");
+        let mut hasher = DefaultHasher::new();
+        book.body.hash(&mut hasher);
+        let uid = format!("shiva-{:016x}", hasher.finish());
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        zip.start_file("book.opf", deflated)?;
+        zip.write_all(package(&uid, &title, &document.metadata, &book.images).as_bytes())?;
+        zip.start_file("book.ncx", deflated)?;
+        zip.write_all(ncx(&uid, &title, &book.headings).as_bytes())?;
+        zip.start_file(DTBOOK_FILE, deflated)?;
+        zip.write_all(dtbook(&uid, &title, language, &book.body).as_bytes())?;
+        zip.start_file(SMIL_FILE, deflated)?;
+        zip.write_all(smil(&uid, &book.headings).as_bytes())?;
+        for (path, image) in &book.images {
+            zip.start_file(path.as_str(), stored)?;
+            zip.write_all(image.bytes())?;
+        }
+        Ok(Bytes::from(zip.finish()?.into_inner()))
+    }
+}
+
+struct Heading {
+    depth: usize,
+    text: String,
+}
+
+#[derive(Default)]
+struct Book<'a> {
+    body: String,
+    /// Number of open `level` sections
+    depth: usize,
+    headings: Vec<Heading>,
+    images: Vec<(String, &'a ImageData)>,
+}
+
+impl<'a> Book<'a> {
+    fn element(&mut self, element: &'a Element) {
+        if let Element::Header { level, text, .. } = element {
+            let depth = (*level as usize).clamp(1, self.depth + 1).min(6);
+            while self.depth >= depth {
+                self.body.push_str(&format!("</level{}>\n", self.depth));
+                self.depth -= 1;
+            }
+            self.depth = depth;
+            self.headings.push(Heading {
+                depth,
+                text: text.clone(),
+            });
+            let id = self.headings.len();
+            self.body.push_str(&format!(
+                "<level{depth}>\n<h{depth} id=\"h{id}\" smilref=\"{SMIL_FILE}#p{id}\">{}</h{depth}>\n",
+                escape(text)
+            ));
+            return;
+        }
+        // Content before the first header still needs a section
+        if self.depth == 0 && !matches!(element, Element::Comment { .. } | Element::PageBreak) {
+            self.body.push_str("<level1>\n");
+            self.depth = 1;
+        }
+        self.block(element);
+    }
+
+    fn block(&mut self, element: &'a Element) {
+        match element {
+            Element::Text { .. }
+            | Element::Paragraph { .. }
+            | Element::Hyperlink { .. }
+            | Element::Styled { .. }
+            | Element::Math { .. }
+            | Element::Ruby { .. } => {
+                self.body.push_str("<p>");
+                self.inline(element);
+                self.body.push_str("</p>\n");
+            }
+            Element::Header { .. } => {
+                self.body.push_str("<p><strong>");
+                self.inline(element);
+                self.body.push_str("</strong></p>\n");
+            }
+            Element::List { elements, numbered } => {
+                let kind = if *numbered { "ol" } else { "ul" };
+                self.body.push_str(&format!("<list type=\"{}\">\n", kind));
+                for item in elements {
+                    self.body.push_str("<li>");
+                    match &item.element {
+                        Element::List { .. } | Element::Table { .. } => {
+                            self.body.push('\n');
+                            self.block(&item.element);
+                        }
+                        element => self.inline(element),
+                    }
+                    self.body.push_str("</li>\n");
+                }
+                self.body.push_str("</list>\n");
+            }
+            Element::Table { headers, rows, .. } => {
+                self.body.push_str("<table>\n");
+                if !headers.is_empty() {
+                    self.body.push_str("<tr>");
+                    for header in headers {
+                        self.body.push_str("<th>");
+                        self.inline(&header.element);
+                        self.body.push_str("</th>");
+                    }
+                    self.body.push_str("</tr>\n");
+                }
+                for row in rows {
+                    self.body.push_str("<tr>");
+                    for cell in &row.cells {
+                        self.body.push_str("<td>");
+                        match &cell.elements[..] {
+                            [element] => self.inline(element),
+                            elements => {
+                                self.body.push('\n');
+                                for element in elements {
+                                    self.block(element);
+                                }
+                            }
+                        }
+                        self.body.push_str("</td>");
+                    }
+                    self.body.push_str("</tr>\n");
+                }
+                self.body.push_str("</table>\n");
+            }
+            Element::Image(image) if image.bytes().is_empty() => {
+                self.body.push_str(&format!("<p>{}</p>\n", escape(image.alt())));
+            }
+            Element::Image(_) => {
+                self.body.push_str("<imggroup>");
+                self.inline(element);
+                self.body.push_str("</imggroup>\n");
+            }
+            Element::Figure { image, .. } if image.bytes().is_empty() => {
+                self.body.push_str("<p>");
+                self.inline(element);
+                self.body.push_str("</p>\n");
+            }
+            Element::Figure { image, caption } => {
+                self.body.push_str("<imggroup>");
+                self.image(image);
+                if !caption.is_empty() {
+                    self.body.push_str("<caption>");
+                    for child in caption {
+                        self.inline(child);
+                    }
+                    self.body.push_str("</caption>");
+                }
+                self.body.push_str("</imggroup>\n");
+            }
+            Element::CodeBlock { .. } => {
+                self.body.push_str("<p>");
+                self.inline(element);
+                self.body.push_str("</p>\n");
+            }
+            Element::Blockquote { elements, .. } => {
+                self.body.push_str("<blockquote>\n");
+                for child in elements {
+                    self.block(child);
+                }
+                self.body.push_str("</blockquote>\n");
+            }
+            Element::Section { children, .. } => {
+                for child in children {
+                    self.block(child);
+                }
+            }
+            Element::FootnoteReference { .. } => {
+                self.body.push_str("<p>");
+                self.inline(element);
+                self.body.push_str("</p>\n");
+            }
+            Element::Footnote { id, content } => {
+                self.body
+                    .push_str(&format!("<note id=\"fn-{}\">\n", escape(id)));
+                for child in content {
+                    self.block(child);
+                }
+                self.body.push_str("</note>\n");
+            }
+            Element::HorizontalRule => self.body.push_str("<hr/>\n"),
+            Element::DefinitionList { items } => {
+                self.body.push_str("<dl>\n");
+                for item in items {
+                    self.body.push_str("<dt>");
+                    for child in &item.term {
+                        self.inline(child);
+                    }
+                    self.body.push_str("</dt>\n");
+                    for definition in &item.definitions {
+                        self.body.push_str("<dd>");
+                        self.inline(definition);
+                        self.body.push_str("</dd>\n");
+                    }
+                }
+                self.body.push_str("</dl>\n");
+            }
+            // Talking books have no print pages to break, no player for embedded media, no
+            // charts to look at and the navigation control file is their table of contents
+            Element::LineBreak
+            | Element::Comment { .. }
+            | Element::PageBreak
+            | Element::TableOfContents { .. }
+            | Element::Custom { .. }
+            | Element::Media { .. }
+            | Element::Chart { .. } => {}
+        }
+    }
+
+    fn inline(&mut self, element: &'a Element) {
+        match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => {
+                self.body.push_str(&escape(text))
+            }
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => {
+                for child in elements {
+                    self.inline(child);
+                }
+            }
+            // DTBook has no underline or strikethrough, those spans keep only the text
+            Element::Styled { style, elements } => {
+                let tags = [(style.bold, "strong"), (style.italic, "em")];
+                for (_, tag) in tags.iter().filter(|(enabled, _)| *enabled) {
+                    self.body.push_str(&format!("<{}>", tag));
+                }
+                for child in elements {
+                    self.inline(child);
+                }
+                for (_, tag) in tags.iter().rev().filter(|(enabled, _)| *enabled) {
+                    self.body.push_str(&format!("</{}>", tag));
+                }
+            }
+            Element::Hyperlink { title, url, .. } => {
+                let title = if title.is_empty() { url } else { title };
+                self.body.push_str(&format!(
+                    "<a href=\"{}\" external=\"true\">{}</a>",
+                    escape(url),
+                    escape(title)
+                ));
+            }
+            Element::Image(image) => self.image(image),
+            Element::Figure { image, caption } => {
+                self.image(image);
+                for child in caption {
+                    self.body.push(' ');
+                    self.inline(child);
+                }
+            }
+            Element::LineBreak | Element::HorizontalRule => self.body.push_str("<br/>"),
+            Element::CodeBlock { code, .. } => {
+                let lines: Vec<String> = code.lines().map(escape).collect();
+                self.body
+                    .push_str(&format!("<code>{}</code>", lines.join("<br/>")));
+            }
+            Element::List { elements, .. } => {
+                for (index, item) in elements.iter().enumerate() {
+                    if index > 0 {
+                        self.body.push(' ');
+                    }
+                    self.inline(&item.element);
+                }
+            }
+            Element::DefinitionList { items } => {
+                let children = items
+                    .iter()
+                    .flat_map(|item| item.term.iter().chain(&item.definitions));
+                for (index, child) in children.enumerate() {
+                    if index > 0 {
+                        self.body.push(' ');
+                    }
+                    self.inline(child);
+                }
+            }
+            Element::Math { tex, .. } => self.body.push_str(&escape(tex)),
+            Element::FootnoteReference { id } => {
+                let id = escape(id);
+                self.body
+                    .push_str(&format!("<noteref idref=\"#fn-{}\">{}</noteref>", id, id));
+            }
+            Element::Footnote { id, content } => {
+                self.body.push_str(&format!("{} ", escape(id)));
+                for child in content {
+                    self.inline(child);
+                }
+            }
+            Element::Ruby { .. } => {
+                if let Element::Text { text, .. } = element.ruby_fallback() {
+                    self.body.push_str(&escape(&text));
+                }
+            }
+            Element::Table { .. }
+            | Element::Chart { .. }
+            | Element::Comment { .. }
+            | Element::PageBreak
+            | Element::TableOfContents { .. }
+            | Element::Custom { .. }
+            | Element::Media { .. } => {}
+        }
+    }
+
+    /// An image with its path in the book, images without content leave their alternative text
+    fn image(&mut self, image: &'a ImageData) {
+        if image.bytes().is_empty() {
+            self.body.push_str(&escape(image.alt()));
+            return;
+        }
+        let path = format!(
+            "images/image{}{}",
+            self.images.len() + 1,
+            image.image_type().to_extension()
+        );
+        self.body.push_str(&format!(
+            "<img src=\"{}\" alt=\"{}\"/>",
+            path,
+            escape(image.alt())
+        ));
+        self.images.push((path, image));
+    }
+}
+
+fn package(
+    uid: &str,
+    title: &str,
+    metadata: &Metadata,
+    images: &[(String, &ImageData)],
+) -> String {
+    let mut properties = String::new();
+    for author in &metadata.authors {
+        properties.push_str(&format!("      <dc:Creator>{}</dc:Creator>\n", escape(author)));
+    }
+    if let Some(subject) = &metadata.subject {
+        properties.push_str(&format!("      <dc:Subject>{}</dc:Subject>\n", escape(subject)));
+    }
+    let mut manifest = String::new();
+    for (index, (path, image)) in images.iter().enumerate() {
+        manifest.push_str(&format!(
+            "    <item id=\"image{}\" href=\"{}\" media-type=\"{}\"/>\n",
+            index + 1,
+            path,
+            media_type(image.image_type())
+        ));
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE package PUBLIC "+//ISBN 0-9673008-1-9//DTD OEB 1.2 Package//EN" "http://openebook.org/dtds/oeb-1.2/oebpkg12.dtd">
+<package xmlns="http://openebook.org/namespaces/oeb-package/1.0/" unique-identifier="uid">
+  <metadata>
+    <dc-metadata xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:oebpackage="http://openebook.org/namespaces/oeb-package/1.0/">
+      <dc:Format>ANSI/NISO Z39.86-2005</dc:Format>
+      <dc:Identifier id="uid">{uid}</dc:Identifier>
+      <dc:Title>{title}</dc:Title>
+{properties}      <dc:Language>{language}</dc:Language>
+      <dc:Date>{date}</dc:Date>
+      <dc:Publisher>shiva</dc:Publisher>
+    </dc-metadata>
+    <x-metadata>
+      <meta name="dtb:multimediaType" content="textNCX"/>
+      <meta name="dtb:multimediaContent" content="text"/>
+      <meta name="dtb:totalTime" content="0:00:00"/>
+    </x-metadata>
+  </metadata>
+  <manifest>
+    <item id="opf" href="book.opf" media-type="text/xml"/>
+    <item id="ncx" href="book.ncx" media-type="application/x-dtbncx+xml"/>
+    <item id="dtbook" href="{DTBOOK_FILE}" media-type="application/x-dtbook+xml"/>
+    <item id="smil" href="{SMIL_FILE}" media-type="application/smil"/>
+{manifest}  </manifest>
+  <spine>
+    <itemref idref="smil"/>
+  </spine>
+</package>
+"#,
+        uid = uid,
+        title = escape(title),
+        properties = properties,
+        language = escape(metadata.language.as_deref().unwrap_or("en")),
+        date = escape(metadata.created.as_deref().unwrap_or(&modified_timestamp()[..10])),
+        manifest = manifest,
+    )
+}
+
+fn dtbook(uid: &str, title: &str, language: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE dtbook PUBLIC "-//NISO//DTD dtbook 2005-3//EN" "http://www.daisy.org/z3986/2005/dtbook-2005-3.dtd">
+<dtbook xmlns="http://www.daisy.org/z3986/2005/dtbook/" version="2005-3" xml:lang="{language}">
+<head>
+<meta name="dtb:uid" content="{uid}"/>
+<meta name="dc:Title" content="{title}"/>
+</head>
+<book>
+<frontmatter>
+<doctitle id="doctitle" smilref="{SMIL_FILE}#p0">{title}</doctitle>
+</frontmatter>
+<bodymatter>
+{body}</bodymatter>
+</book>
+</dtbook>
+"#,
+        uid = uid,
+        title = escape(title),
+        language = escape(language),
+        body = body,
+    )
+}
+
+/// The reading order: the title, then every header
+fn smil(uid: &str, headings: &[Heading]) -> String {
+    let mut pars = format!(
+        "<par id=\"p0\"><text src=\"{}#doctitle\"/></par>\n",
+        DTBOOK_FILE
+    );
+    for id in 1..=headings.len() {
+        pars.push_str(&format!(
+            "<par id=\"p{id}\"><text src=\"{DTBOOK_FILE}#h{id}\"/></par>\n"
+        ));
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE smil PUBLIC "-//NISO//DTD dtbsmil 2005-2//EN" "http://www.daisy.org/z3986/2005/dtbsmil-2005-2.dtd">
+<smil xmlns="http://www.w3.org/2001/SMIL20/">
+<head>
+<meta name="dtb:uid" content="{uid}"/>
+<meta name="dtb:totalElapsedTime" content="0:00:00"/>
+<meta name="dtb:generator" content="shiva"/>
+</head>
+<body>
+<seq id="mseq" fill="remove">
+{pars}</seq>
+</body>
+</smil>
+"#
+    )
+}
+
+/// Navigation points nested like the header levels, the title when there are no headers
+fn ncx(uid: &str, title: &str, headings: &[Heading]) -> String {
+    let mut nav_map = String::new();
+    let mut open: Vec<usize> = Vec::new();
+    for (index, heading) in headings.iter().enumerate() {
+        while open.last().is_some_and(|depth| *depth >= heading.depth) {
+            open.pop();
+            nav_map.push_str("</navPoint>\n");
+        }
+        let id = index + 1;
+        nav_map.push_str(&format!(
+            "<navPoint id=\"nav{id}\" class=\"level{}\" playOrder=\"{id}\">\
+             <navLabel><text>{}</text></navLabel><content src=\"{SMIL_FILE}#p{id}\"/>\n",
+            heading.depth,
+            escape(&heading.text)
+        ));
+        open.push(heading.depth);
+    }
+    for _ in open {
+        nav_map.push_str("</navPoint>\n");
+    }
+    if headings.is_empty() {
+        nav_map = format!(
+            "<navPoint id=\"nav0\" class=\"title\" playOrder=\"1\">\
+             <navLabel><text>{}</text></navLabel><content src=\"{SMIL_FILE}#p0\"/></navPoint>\n",
+            escape(title)
+        );
+    }
+    let depth = headings
+        .iter()
+        .map(|heading| heading.depth)
+        .max()
+        .unwrap_or(1);
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE ncx PUBLIC "-//NISO//DTD ncx 2005-1//EN" "http://www.daisy.org/z3986/2005/ncx-2005-1.dtd">
+<ncx xmlns="http://www.daisy.org/z3986/2005/ncx/" version="2005-1" xml:lang="en">
+<head>
+<meta name="dtb:uid" content="{uid}"/>
+<meta name="dtb:depth" content="{depth}"/>
+<meta name="dtb:generator" content="shiva"/>
+<meta name="dtb:totalPageCount" content="0"/>
+<meta name="dtb:maxPageNumber" content="0"/>
+</head>
+<docTitle><text>{title}</text></docTitle>
+<navMap>
+{nav_map}</navMap>
+</ncx>
+"#,
+        title = escape(title),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::daisy::*;
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    fn read_bytes(generated: &Bytes, name: &str) -> anyhow::Result<Vec<u8>> {
+        let mut archive = ZipArchive::new(Cursor::new(generated.to_vec()))?;
+        let mut content = Vec::new();
+        archive.by_name(name)?.read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    fn read(generated: &Bytes, name: &str) -> anyhow::Result<String> {
+        Ok(String::from_utf8(read_bytes(generated, name)?)?)
+    }
+
+    fn header(level: u8, text: &str) -> Element {
+        Element::Header {
+            level,
+            text: text.to_string(),
+            anchor: None,
+        }
+    }
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Text {
+                text: "Preface".to_string(),
+                font: FontSpec::from(8),
+            },
+            header(1, "Guide"),
+            header(3, "Install"),
+            Element::List {
+                elements: vec![ListItem {
+                    element: Element::Text {
+                        text: "Run & wait".to_string(),
+                        font: FontSpec::from(8),
+                    },
+                    checked: None,
+                }],
+                numbered: true,
+            },
+            header(1, "Usage"),
+        ]);
+        let generated = Transformer::generate(&document)?;
+
+        let dtbook = read(&generated, "book.xml")?;
+        let body = &dtbook[dtbook.find("<bodymatter>").unwrap()..];
+        assert_eq!(
+            body,
+            concat!(
+                "<bodymatter>\n",
+                "<level1>\n<p>Preface</p>\n</level1>\n",
+                "<level1>\n<h1 id=\"h1\" smilref=\"book.smil#p1\">Guide</h1>\n",
+                "<level2>\n<h2 id=\"h2\" smilref=\"book.smil#p2\">Install</h2>\n",
+                "<list type=\"ol\">\n<li>Run &amp; wait</li>\n</list>\n",
+                "</level2>\n</level1>\n",
+                "<level1>\n<h1 id=\"h3\" smilref=\"book.smil#p3\">Usage</h1>\n",
+                "</level1>\n",
+                "</bodymatter>\n</book>\n</dtbook>\n",
+            )
+        );
+
+        let ncx = read(&generated, "book.ncx")?;
+        let guide = ncx.find("<text>Guide</text>").unwrap();
+        let install = ncx.find("<text>Install</text>").unwrap();
+        let usage = ncx.find("<text>Usage</text>").unwrap();
+        // Install is nested in Guide, whose navigation point closes before Usage
+        let closed = &ncx[install..usage];
+        assert!(guide < install && closed.matches("</navPoint>").count() == 2);
+        assert!(ncx.contains("<meta name=\"dtb:depth\" content=\"2\"/>"));
+
+        let smil = read(&generated, "book.smil")?;
+        assert!(smil.contains("<par id=\"p3\"><text src=\"book.xml#h3\"/></par>"));
+        let package = read(&generated, "book.opf")?;
+        assert!(package.contains("<dc:Title>Guide</dc:Title>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_without_headers() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Image(ImageData::new(
+            Bytes::from_static(b"\x89PNG"),
+            "Chart".to_string(),
+            "Sales chart".to_string(),
+            "png".to_string(),
+            "center".to_string(),
+            ImageDimension::default(),
+        ))]);
+        let generated = Transformer::generate(&document)?;
+        let dtbook = read(&generated, "book.xml")?;
+        assert!(dtbook
+            .contains("<imggroup><img src=\"images/image1.png\" alt=\"Sales chart\"/></imggroup>"));
+        assert_eq!(read_bytes(&generated, "images/image1.png")?, b"\x89PNG");
+        let ncx = read(&generated, "book.ncx")?;
+        assert!(ncx.contains("<content src=\"book.smil#p0\"/>"));
+        let package = read(&generated, "book.opf")?;
+        assert!(package.contains("href=\"images/image1.png\" media-type=\"image/png\""));
+        Ok(())
+    }
+}
@@ -0,0 +1,423 @@
+//! Structural differences between two documents, as between two versions of a contract
+//!
+//! The blocks of both documents are matched by their longest common subsequence. A block removed
+//! where one of the same kind is inserted is a change, its text is compared word by word.
+use crate::core::{Document, Element};
+
+/// The blocks of the new document with the blocks of the old one that are gone, in document
+/// order
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentDiff {
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Unchanged(Element),
+    Inserted(Element),
+    Removed(Element),
+    /// A block replaced by one of the same kind, `hunks` take the text of one to the other
+    Changed {
+        old: Box<Element>,
+        new: Box<Element>,
+        hunks: Vec<TextHunk>,
+    },
+}
+
+/// A run of words of a changed block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextHunk {
+    Equal(String),
+    Inserted(String),
+    Removed(String),
+}
+
+/// The differences between the blocks of all bands of two documents
+pub fn diff(old: &Document, new: &Document) -> DocumentDiff {
+    let old_elements = old.get_all_elements();
+    let new_elements = new.get_all_elements();
+    let mut changes = Vec::new();
+    // Removed and inserted blocks since the last unchanged one
+    let mut removed: Vec<&Element> = Vec::new();
+    let mut inserted: Vec<&Element> = Vec::new();
+    for operation in diff_sequences(&old_elements, &new_elements) {
+        match operation {
+            Operation::Equal(index) => {
+                pair_changes(&mut changes, &mut removed, &mut inserted);
+                changes.push(Change::Unchanged(old_elements[index].clone()));
+            }
+            Operation::Removed(index) => removed.push(old_elements[index]),
+            Operation::Inserted(index) => inserted.push(new_elements[index]),
+        }
+    }
+    pair_changes(&mut changes, &mut removed, &mut inserted);
+    DocumentDiff { changes }
+}
+
+impl DocumentDiff {
+    /// Whether the documents have the same blocks
+    pub fn is_empty(&self) -> bool {
+        self.changes
+            .iter()
+            .all(|change| matches!(change, Change::Unchanged(_)))
+    }
+
+    /// The new document as markdown with the changes marked in CriticMarkup, `{++inserted++}`
+    /// and `{--removed--}` text
+    pub fn to_markdown(&self) -> String {
+        let inserted = |text: &str| format!("{{++{}++}}", text);
+        let removed = |text: &str| format!("{{--{}--}}", text);
+        let blocks: Vec<String> = self
+            .changes
+            .iter()
+            .map(|change| {
+                let (element, text) = match change {
+                    Change::Unchanged(element) => (element, element_text(element)),
+                    Change::Inserted(element) => (element, inserted(&element_text(element))),
+                    Change::Removed(element) => (element, removed(&element_text(element))),
+                    Change::Changed { new, hunks, .. } => {
+                        let text = hunks
+                            .iter()
+                            .map(|hunk| match hunk {
+                                TextHunk::Equal(text) => text.clone(),
+                                TextHunk::Inserted(text) => inserted(text),
+                                TextHunk::Removed(text) => removed(text),
+                            })
+                            .collect();
+                        (new.as_ref(), text)
+                    }
+                };
+                match element {
+                    Element::Header { level, .. } => {
+                        format!("{} {}", "#".repeat(*level as usize), text)
+                    }
+                    _ => text,
+                }
+            })
+            .collect();
+        let mut markdown = blocks.join("\n\n");
+        markdown.push('\n');
+        markdown
+    }
+
+    /// The new document as HTML with the changes in `<ins>` and `<del>` elements
+    pub fn to_html(&self) -> String {
+        let text = |text: &str| escape(text).replace('\n', "<br>");
+        let inserted = |inserted: &str| format!("<ins>{}</ins>", text(inserted));
+        let removed = |removed: &str| format!("<del>{}</del>", text(removed));
+        let mut html = String::new();
+        for change in &self.changes {
+            let (element, content) = match change {
+                Change::Unchanged(element) => (element, text(&element_text(element))),
+                Change::Inserted(element) => (element, inserted(&element_text(element))),
+                Change::Removed(element) => (element, removed(&element_text(element))),
+                Change::Changed { new, hunks, .. } => {
+                    let content = hunks
+                        .iter()
+                        .map(|hunk| match hunk {
+                            TextHunk::Equal(equal) => text(equal),
+                            TextHunk::Inserted(text) => inserted(text),
+                            TextHunk::Removed(text) => removed(text),
+                        })
+                        .collect();
+                    (new.as_ref(), content)
+                }
+            };
+            let tag = match element {
+                Element::Header { level, .. } => format!("h{}", level.clamp(&1, &6)),
+                Element::CodeBlock { .. } => "pre".to_string(),
+                _ => "p".to_string(),
+            };
+            html.push_str(&format!("<{tag}>{content}</{tag}>\n"));
+        }
+        html
+    }
+}
+
+/// Pairs the removed blocks with the inserted ones in order, blocks of the same kind are
+/// changes of one another
+fn pair_changes(
+    changes: &mut Vec<Change>,
+    removed: &mut Vec<&Element>,
+    inserted: &mut Vec<&Element>,
+) {
+    let paired = removed.len().min(inserted.len());
+    for (old, new) in removed.iter().zip(inserted.iter()) {
+        if old.kind() == new.kind() {
+            changes.push(Change::Changed {
+                old: Box::new((*old).clone()),
+                new: Box::new((*new).clone()),
+                hunks: diff_text(&element_text(old), &element_text(new)),
+            });
+        } else {
+            changes.push(Change::Removed((*old).clone()));
+            changes.push(Change::Inserted((*new).clone()));
+        }
+    }
+    changes.extend(
+        removed[paired..]
+            .iter()
+            .map(|old| Change::Removed((*old).clone())),
+    );
+    changes.extend(
+        inserted[paired..]
+            .iter()
+            .map(|new| Change::Inserted((*new).clone())),
+    );
+    removed.clear();
+    inserted.clear();
+}
+
+/// The hunks taking one text to the other, words and the spaces between them are compared
+fn diff_text(old: &str, new: &str) -> Vec<TextHunk> {
+    let old_words = words(old);
+    let new_words = words(new);
+    let mut hunks: Vec<TextHunk> = Vec::new();
+    for operation in diff_sequences(&old_words, &new_words) {
+        let hunk = match operation {
+            Operation::Equal(index) => TextHunk::Equal(old_words[index].to_string()),
+            Operation::Removed(index) => TextHunk::Removed(old_words[index].to_string()),
+            Operation::Inserted(index) => TextHunk::Inserted(new_words[index].to_string()),
+        };
+        // Runs of words of the same kind are one hunk
+        match (hunks.last_mut(), hunk) {
+            (Some(TextHunk::Equal(last)), TextHunk::Equal(word))
+            | (Some(TextHunk::Removed(last)), TextHunk::Removed(word))
+            | (Some(TextHunk::Inserted(last)), TextHunk::Inserted(word)) => last.push_str(&word),
+            (_, hunk) => hunks.push(hunk),
+        }
+    }
+    hunks
+}
+
+/// The words of a text with the runs of whitespace between them
+fn words(text: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = 0;
+    let mut previous_space = None;
+    for (index, c) in text.char_indices() {
+        let space = c.is_whitespace();
+        if previous_space.is_some_and(|previous| previous != space) {
+            words.push(&text[start..index]);
+            start = index;
+        }
+        previous_space = Some(space);
+    }
+    if start < text.len() {
+        words.push(&text[start..]);
+    }
+    words
+}
+
+enum Operation {
+    /// Index of the item in the old sequence
+    Equal(usize),
+    Removed(usize),
+    Inserted(usize),
+}
+
+/// The longest common subsequence of two sequences as the operations taking one to the other
+fn diff_sequences<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Operation> {
+    // The common prefix and suffix are left out of the table
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    // lengths[i][j] is the length of the common subsequence of old_middle[i..] and new_middle[j..]
+    let columns = new_middle.len() + 1;
+    let mut lengths = vec![0u32; (old_middle.len() + 1) * columns];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            lengths[i * columns + j] = if old_middle[i] == new_middle[j] {
+                lengths[(i + 1) * columns + j + 1] + 1
+            } else {
+                lengths[(i + 1) * columns + j].max(lengths[i * columns + j + 1])
+            };
+        }
+    }
+
+    let mut operations: Vec<Operation> = (0..prefix).map(Operation::Equal).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() || j < new_middle.len() {
+        if i < old_middle.len() && j < new_middle.len() && old_middle[i] == new_middle[j] {
+            operations.push(Operation::Equal(prefix + i));
+            i += 1;
+            j += 1;
+        } else if i < old_middle.len()
+            && (j == new_middle.len()
+                || lengths[(i + 1) * columns + j] >= lengths[i * columns + j + 1])
+        {
+            // Removals go first where both keep the subsequence as long
+            operations.push(Operation::Removed(prefix + i));
+            i += 1;
+        } else {
+            operations.push(Operation::Inserted(prefix + j));
+            j += 1;
+        }
+    }
+    operations.extend((old.len() - suffix..old.len()).map(Operation::Equal));
+    operations
+}
+
+/// The text of a block, list items and table rows on lines of their own
+fn element_text(element: &Element) -> String {
+    let join = |elements: &[Element], separator: &str| {
+        elements
+            .iter()
+            .map(element_text)
+            .collect::<Vec<_>>()
+            .join(separator)
+    };
+    match element {
+        Element::Text { text, .. } | Element::Header { text, .. } => text.clone(),
+        Element::Hyperlink { title, .. } => title.clone(),
+        Element::CodeBlock { code, .. } => code.clone(),
+        Element::Math { tex, .. } => tex.clone(),
+        Element::Ruby { base, .. } => base.clone(),
+        Element::Image(image) => image.alt().to_string(),
+        Element::LineBreak => "\n".to_string(),
+        Element::Paragraph { elements } | Element::Styled { elements, .. } => join(elements, ""),
+        Element::Figure { caption, .. } => join(caption, ""),
+        Element::Blockquote {
+            elements,
+            attribution,
+        } => {
+            let mut text = join(elements, "\n");
+            if !attribution.is_empty() {
+                text.push('\n');
+                text.push_str(&join(attribution, ""));
+            }
+            text
+        }
+        Element::Section { children, .. } | Element::Custom { children, .. } => {
+            join(children, "\n")
+        }
+        Element::Footnote { content, .. } => join(content, "\n"),
+        Element::List { elements, .. } => elements
+            .iter()
+            .map(|item| element_text(&item.element))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Element::DefinitionList { items } => items
+            .iter()
+            .map(|item| {
+                format!(
+                    "{}: {}",
+                    join(&item.term, ""),
+                    join(&item.definitions, "; ")
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Element::Table {
+            headers,
+            rows,
+            caption,
+            ..
+        } => {
+            let mut lines = Vec::new();
+            if !caption.is_empty() {
+                lines.push(join(caption, ""));
+            }
+            if !headers.is_empty() {
+                let headers: Vec<String> = headers
+                    .iter()
+                    .map(|header| element_text(&header.element))
+                    .collect();
+                lines.push(headers.join(" | "));
+            }
+            for row in rows {
+                let cells: Vec<String> = row
+                    .cells
+                    .iter()
+                    .map(|cell| join(&cell.elements, " "))
+                    .collect();
+                lines.push(cells.join(" | "));
+            }
+            lines.join("\n")
+        }
+        _ => String::new(),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::DocumentBuilder;
+
+    #[test]
+    fn test_diff() {
+        let old = DocumentBuilder::new()
+            .header(1, "Contract")
+            .paragraph(|p| p.text("The fee is 100 EUR per month."))
+            .paragraph(|p| p.text("Either party may terminate."))
+            .build();
+        let new = DocumentBuilder::new()
+            .header(1, "Contract")
+            .paragraph(|p| p.text("The fee is 120 EUR per month."))
+            .list(["Payment is due monthly"], false)
+            .build();
+        let diff = diff(&old, &new);
+        assert!(!diff.is_empty());
+        assert_eq!(diff.changes.len(), 4);
+        assert!(matches!(diff.changes[0], Change::Unchanged(_)));
+        let Change::Changed { hunks, .. } = &diff.changes[1] else {
+            panic!("expected a change, got {:?}", diff.changes[1]);
+        };
+        assert_eq!(
+            hunks,
+            &vec![
+                TextHunk::Equal("The fee is ".to_string()),
+                TextHunk::Removed("100".to_string()),
+                TextHunk::Inserted("120".to_string()),
+                TextHunk::Equal(" EUR per month.".to_string()),
+            ]
+        );
+        assert!(matches!(
+            diff.changes[2],
+            Change::Removed(Element::Paragraph { .. })
+        ));
+        assert!(matches!(
+            diff.changes[3],
+            Change::Inserted(Element::List { .. })
+        ));
+
+        assert_eq!(
+            diff.to_markdown(),
+            "# Contract\n\n\
+             The fee is {--100--}{++120++} EUR per month.\n\n\
+             {--Either party may terminate.--}\n\n\
+             {++Payment is due monthly++}\n"
+        );
+        assert!(diff
+            .to_html()
+            .contains("<p>The fee is <del>100</del><ins>120</ins> EUR per month.</p>"));
+    }
+
+    #[test]
+    fn test_diff_equal() {
+        let document = DocumentBuilder::new()
+            .header(2, "Terms")
+            .table(["Item", "Price"], [["Support", "10"]])
+            .build();
+        let diff = diff(&document, &document);
+        assert!(diff.is_empty());
+        assert_eq!(
+            diff.to_markdown(),
+            "## Terms\n\nItem | Price\nSupport | 10\n"
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use crate::core::*;
+use bytes::Bytes;
+use log::warn;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    /// Reads single page and bundled multi-page DjVu files. Every page gives the paragraphs
+    /// of its text layer followed by its JPEG coded layers as images.
+    ///
+    /// Only the uncompressed `TXTa` text layer is read. `TXTz` text layers, JB2 masks and IW44
+    /// images use the ZP arithmetic coder, which is not implemented, and are skipped with a
+    /// warning. Indirect documents, whose pages are separate files, are not supported.
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        let data = document
+            .strip_prefix(b"AT&T")
+            .ok_or_else(|| malformed("missing AT&T magic"))?;
+        let chunks = chunks(data)?;
+        let [root] = chunks.as_slice() else {
+            return Err(malformed("expected a single FORM chunk").into());
+        };
+        let mut elements = Vec::new();
+        match form(root)? {
+            (b"DJVU", children) => page(&children, 1, &mut elements),
+            (b"DJVM", children) => {
+                let bundled = children
+                    .iter()
+                    .find(|chunk| &chunk.id == b"DIRM")
+                    .and_then(|dirm| dirm.data.first())
+                    .is_some_and(|flags| flags & 0x80 != 0);
+                if !bundled {
+                    return Err(anyhow::anyhow!(
+                        "Indirect DjVu documents are not supported, bundle them first"
+                    ));
+                }
+                let mut number = 0;
+                for child in &children {
+                    if &child.id != b"FORM" {
+                        continue;
+                    }
+                    // Shared dictionaries (DJVI) and thumbnails (THUM) are no pages
+                    if let (b"DJVU", children) = form(child)? {
+                        number += 1;
+                        page(&children, number, &mut elements);
+                    }
+                }
+            }
+            (form_type, _) => {
+                return Err(malformed(&format!(
+                    "unknown form type {}",
+                    String::from_utf8_lossy(form_type)
+                ))
+                .into())
+            }
+        }
+        Ok(Document::new(elements))
+    }
+
+    fn generate(_document: &Document) -> anyhow::Result<Bytes> {
+        Err(anyhow::anyhow!("DjVu generation is not supported"))
+    }
+}
+
+struct Chunk<'a> {
+    id: [u8; 4],
+    data: &'a [u8],
+}
+
+fn malformed(message: &str) -> ParserError {
+    ParserError::Malformed(format!("DjVu: {}", message))
+}
+
+/// IFF chunks, each one starts at an even offset
+fn chunks(mut data: &[u8]) -> anyhow::Result<Vec<Chunk<'_>>> {
+    let mut chunks = Vec::new();
+    while data.len() >= 8 {
+        let id: [u8; 4] = data[..4].try_into()?;
+        let size = u32::from_be_bytes(data[4..8].try_into()?) as usize;
+        let rest = &data[8..];
+        if size > rest.len() {
+            return Err(malformed("truncated chunk").into());
+        }
+        chunks.push(Chunk {
+            id,
+            data: &rest[..size],
+        });
+        let next = (size + 1) & !1;
+        data = rest.get(next..).unwrap_or_default();
+    }
+    Ok(chunks)
+}
+
+/// The type and the children of a FORM chunk
+fn form<'a>(chunk: &Chunk<'a>) -> anyhow::Result<(&'a [u8], Vec<Chunk<'a>>)> {
+    if &chunk.id != b"FORM" || chunk.data.len() < 4 {
+        return Err(malformed("expected a FORM chunk").into());
+    }
+    Ok((&chunk.data[..4], chunks(&chunk.data[4..])?))
+}
+
+fn page(chunks: &[Chunk], number: usize, elements: &mut Vec<Element>) {
+    let info = chunks.iter().find(|chunk| &chunk.id == b"INFO");
+    for chunk in chunks {
+        match &chunk.id {
+            b"TXTa" => match text(chunk.data) {
+                Some(text) => paragraphs(&text, elements),
+                None => warn!("DjVu page {}: malformed text layer", number),
+            },
+            b"BGjp" | b"FGjp" => {
+                let dimension = match (&chunk.id, info.and_then(|info| info.data.get(..4))) {
+                    (b"BGjp", Some(size)) => ImageDimension {
+                        width: Some(u16::from_be_bytes([size[0], size[1]]).to_string()),
+                        height: Some(u16::from_be_bytes([size[2], size[3]]).to_string()),
+                    },
+                    _ => ImageDimension::default(),
+                };
+                elements.push(Element::Image(ImageData::new(
+                    Bytes::copy_from_slice(chunk.data),
+                    format!("Page {}", number),
+                    format!("DjVu page {}", number),
+                    "jpeg".to_string(),
+                    "center".to_string(),
+                    dimension,
+                )));
+            }
+            b"TXTz" | b"Sjbz" | b"BG44" | b"FG44" => warn!(
+                "DjVu page {}: compressed {} chunk is not supported",
+                number,
+                String::from_utf8_lossy(&chunk.id)
+            ),
+            _ => {}
+        }
+    }
+}
+
+/// Text of a `TXTa` chunk, a 24 bit length followed by UTF-8 text and the zone tree
+fn text(data: &[u8]) -> Option<String> {
+    let length = u32::from_be_bytes([0, *data.first()?, *data.get(1)?, *data.get(2)?]) as usize;
+    let text = data.get(3..3 + length)?;
+    Some(String::from_utf8_lossy(text).to_string())
+}
+
+/// Columns, regions and paragraphs end with control characters, lines with a line feed
+fn paragraphs(text: &str, elements: &mut Vec<Element>) {
+    for paragraph in text.split(['\x0b', '\x1d', '\x1f']) {
+        let lines: Vec<&str> = paragraph
+            .split('\n')
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        if lines.is_empty() {
+            continue;
+        }
+        elements.push(Element::Paragraph {
+            elements: vec![Element::Text {
+                text: lines.join(" "),
+                font: FontSpec::from(8),
+            }],
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::djvu::*;
+
+    fn chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn form(form_type: &[u8], children: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = form_type.to_vec();
+        for child in children {
+            data.extend_from_slice(child);
+        }
+        chunk(b"FORM", &data)
+    }
+
+    fn page(text: &str) -> Vec<u8> {
+        let mut txta = (text.len() as u32).to_be_bytes()[1..].to_vec();
+        txta.extend_from_slice(text.as_bytes());
+        form(
+            b"DJVU",
+            &[
+                chunk(b"INFO", &[0x09, 0xb0, 0x0d, 0xac, 26, 0, 0x2c, 0x01, 22, 1]),
+                chunk(b"Sjbz", &[0; 7]),
+                chunk(b"TXTa", &txta),
+            ],
+        )
+    }
+
+    fn file(root: Vec<u8>) -> Bytes {
+        let mut file = b"AT&T".to_vec();
+        file.extend(root);
+        Bytes::from(file)
+    }
+
+    fn texts(document: &Document) -> Vec<String> {
+        document
+            .get_all_elements()
+            .into_iter()
+            .filter_map(|element| match element {
+                Element::Paragraph { elements } => match elements.as_slice() {
+                    [Element::Text { text, .. }] => Some(text.clone()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_page() -> anyhow::Result<()> {
+        let mut djvu = page("Scanned\nletter\x1fSecond paragraph\n");
+        // A JPEG background, appended to the page form
+        let background = chunk(b"BGjp", b"\xff\xd8\xff\xd9");
+        let size = u32::from_be_bytes(djvu[4..8].try_into()?) + background.len() as u32;
+        djvu[4..8].copy_from_slice(&size.to_be_bytes());
+        djvu.extend(background);
+
+        let document = Transformer::parse(&file(djvu))?;
+        assert_eq!(texts(&document), vec!["Scanned letter", "Second paragraph"]);
+        let images: Vec<&ImageData> = document
+            .get_all_elements()
+            .into_iter()
+            .filter_map(|element| match element {
+                Element::Image(image) => Some(image),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].bytes().as_ref(), b"\xff\xd8\xff\xd9");
+        assert_eq!(images[0].size().width.as_deref(), Some("2480"));
+
+        assert!(Transformer::parse(&Bytes::from("%PDF-1.7")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_bundled() -> anyhow::Result<()> {
+        let dirm = chunk(b"DIRM", &[0x81, 0, 2]);
+        let djvu = form(b"DJVM", &[dirm, page("First page"), page("Second page")]);
+        let document = Transformer::parse(&file(djvu))?;
+        assert_eq!(texts(&document), vec!["First page", "Second page"]);
+
+        let indirect = form(b"DJVM", &[chunk(b"DIRM", &[0x01, 0, 0])]);
+        assert!(Transformer::parse(&file(indirect)).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,1120 @@
+use crate::core::*;
+use crate::parse_util::{collapse_whitespace, parse_xml, unwrap_paragraph, Names, XmlChild, XmlNode};
+use bytes::Bytes;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Parses DocBook 5 (and namespace-less DocBook 4) books, articles, chapters and sections.
+    /// Section nesting gives the header level, the root title is level 1.
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let root = parse_xml(document, Names::Local(&[]))?;
+        let parser = Parser {
+            image_loader: &image_loader,
+        };
+        let mut elements = Vec::new();
+        let title_level = if root_title(&root).is_some() { 1 } else { 0 };
+        parser.parse_division(&root, title_level, &mut elements)?;
+        let mut parsed = Document::new(elements);
+        parsed.metadata = root_metadata(&root);
+        Ok(parsed)
+    }
+
+    /// Sections, nested from the headers when the document has none, become `section` elements
+    /// inside an `article`, the metadata its `info`.
+    /// Images are written through `image_saver` and referenced from `imagedata`.
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let mut generator = Generator {
+            image_saver,
+            image_num: 0,
+        };
+        let metadata = &document.metadata;
+        let language = metadata
+            .language
+            .as_deref()
+            .map(|language| format!(" xml:lang=\"{}\"", escape(language)))
+            .unwrap_or_default();
+        let mut xml = format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<article xmlns=\"http://docbook.org/ns/docbook\" ",
+                "xmlns:xlink=\"http://www.w3.org/1999/xlink\" version=\"5.0\"{}>\n"
+            ),
+            language
+        );
+        xml.push_str(&info(metadata));
+        for band in &document.nest_sections().bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                generator.block(&mut xml, element)?;
+            }
+        }
+        xml.push_str("</article>\n");
+        Ok(Bytes::from(xml))
+    }
+}
+
+/// Elements that divide the document and carry a title
+const DIVISIONS: [&str; 12] = [
+    "book", "part", "chapter", "appendix", "preface", "article", "section", "sect1", "sect2",
+    "sect3", "sect4", "sect5",
+];
+
+const ADMONITIONS: [&str; 5] = ["note", "tip", "important", "warning", "caution"];
+
+/// Elements whose content is taken verbatim
+const VERBATIM: [&str; 4] = ["programlisting", "screen", "literallayout", "synopsis"];
+
+/// `<?linebreak?>` processing instruction
+const LINE_BREAK: &str = "linebreak";
+/// `<?hard-pagebreak?>` processing instruction of the DocBook XSL stylesheets
+const PAGE_BREAK: &str = "hard-pagebreak";
+
+/// Text content with line breaks, without remarks
+fn text_content(node: &XmlNode) -> String {
+    node.text_with(&|child| match child {
+        XmlChild::Node(node) if node.name == "remark" => Some(String::new()),
+        XmlChild::Instruction(instruction) if instruction.starts_with(LINE_BREAK) => {
+            Some("\n".to_string())
+        }
+        _ => None,
+    })
+}
+
+/// Title of a division, either a direct child or inside `info`
+fn root_title(node: &XmlNode) -> Option<&XmlNode> {
+    node.find("title").or_else(|| {
+        [
+            "info",
+            "articleinfo",
+            "bookinfo",
+            "chapterinfo",
+            "sectioninfo",
+        ]
+        .iter()
+        .find_map(|info| node.find(info))
+        .and_then(|info| info.find("title"))
+    })
+}
+
+/// The metadata of the `info` of the root and its language
+fn root_metadata(root: &XmlNode) -> Metadata {
+    let mut metadata = Metadata {
+        title: root_title(root)
+            .map(|title| collapse_whitespace(&text_content(title)).trim().to_string()),
+        language: root.attribute("lang").map(str::to_string),
+        ..Metadata::default()
+    };
+    let Some(info) = ["info", "articleinfo", "bookinfo"]
+        .iter()
+        .find_map(|info| root.find(info))
+    else {
+        return metadata;
+    };
+    // The parts of a name are separate elements
+    let name = |node: &XmlNode| {
+        let node = node.find("personname").unwrap_or(node);
+        let parts: Vec<String> = node
+            .nodes()
+            .map(|part| text_content(part).trim().to_string())
+            .filter(|part| !part.is_empty())
+            .collect();
+        match parts.is_empty() {
+            true => text_content(node).trim().to_string(),
+            false => parts.join(" "),
+        }
+    };
+    for node in info.nodes() {
+        match node.name.as_str() {
+            "author" => metadata.authors.push(name(node)),
+            "authorgroup" => metadata.authors.extend(node.nodes().map(name)),
+            "subjectset" => {
+                metadata.subject = node
+                    .descendant("subjectterm")
+                    .map(|term| text_content(term).trim().to_string())
+            }
+            "keywordset" => {
+                metadata.keywords = node
+                    .nodes()
+                    .map(|keyword| text_content(keyword).trim().to_string())
+                    .collect()
+            }
+            "pubdate" | "date" => metadata.created = Some(text_content(node).trim().to_string()),
+            _ => {}
+        }
+    }
+    metadata
+}
+
+/// The `info` of the article, documents without metadata have none
+fn info(metadata: &Metadata) -> String {
+    let mut info = String::new();
+    if let Some(title) = &metadata.title {
+        info.push_str(&format!("<title>{}</title>\n", escape(title)));
+    }
+    for author in &metadata.authors {
+        info.push_str(&format!(
+            "<author><personname>{}</personname></author>\n",
+            escape(author)
+        ));
+    }
+    if let Some(subject) = &metadata.subject {
+        info.push_str(&format!(
+            "<subjectset><subject><subjectterm>{}</subjectterm></subject></subjectset>\n",
+            escape(subject)
+        ));
+    }
+    if !metadata.keywords.is_empty() {
+        info.push_str("<keywordset>\n");
+        for keyword in &metadata.keywords {
+            info.push_str(&format!("<keyword>{}</keyword>\n", escape(keyword)));
+        }
+        info.push_str("</keywordset>\n");
+    }
+    if let Some(created) = &metadata.created {
+        info.push_str(&format!("<pubdate>{}</pubdate>\n", escape(created)));
+    }
+    match info.is_empty() {
+        true => info,
+        false => format!("<info>\n{}</info>\n", info),
+    }
+}
+
+struct Parser<'a, F> {
+    image_loader: &'a F,
+}
+
+impl<'a, F> Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    /// Emits the division title at `level` and its content, nested divisions one level deeper
+    fn parse_division(
+        &self,
+        node: &XmlNode,
+        level: u8,
+        elements: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        if let Some(title) = root_title(node) {
+            elements.push(Element::Header {
+                level: level.clamp(1, 6),
+                text: collapse_whitespace(&text_content(title)).trim().to_string(),
+                anchor: None,
+            });
+        }
+        for child in node.nodes() {
+            if DIVISIONS.contains(&child.name.as_str()) {
+                self.parse_division(child, level + 1, elements)?;
+            } else {
+                self.parse_block(child, elements)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_blocks(&self, node: &XmlNode, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        for child in &node.children {
+            match child {
+                XmlChild::Node(child) => self.parse_block(child, elements)?,
+                XmlChild::Instruction(instruction) if instruction.starts_with(PAGE_BREAK) => {
+                    elements.push(Element::PageBreak)
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_block(&self, node: &XmlNode, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        match node.name.as_str() {
+            "para" | "simpara" => self.parse_paragraph(node, elements)?,
+            "toc" => elements.push(Element::TableOfContents { max_level: 6 }),
+            "formalpara" => {
+                if let Some(title) = node.find("title") {
+                    elements.push(Element::Paragraph {
+                        elements: vec![Element::Text {
+                            text: collapse_whitespace(&text_content(title)).trim().to_string(),
+                            font: FontSpec::from(8),
+                        }],
+                    });
+                }
+                if let Some(para) = node.find("para") {
+                    self.parse_paragraph(para, elements)?;
+                }
+            }
+            "itemizedlist" => elements.push(self.parse_list(node, false)?),
+            "orderedlist" => elements.push(self.parse_list(node, true)?),
+            "variablelist" => elements.push(self.parse_variable_list(node)?),
+            "table" | "informaltable" => elements.push(self.parse_table(node)?),
+            "figure" | "informalfigure" | "mediaobject" => {
+                let title = node
+                    .find("title")
+                    .map(|title| collapse_whitespace(&text_content(title)).trim().to_string())
+                    .unwrap_or_default();
+                let media = if node.name == "mediaobject" {
+                    Some(node)
+                } else {
+                    node.descendant("mediaobject")
+                };
+                if let Some(image) = media.map(|media| self.parse_image(media, title)) {
+                    elements.extend(image?);
+                }
+            }
+            name if VERBATIM.contains(&name) => elements.push(Element::Text {
+                text: text_content(node).trim_matches('\n').to_string(),
+                font: FontSpec::from(8),
+            }),
+            name if ADMONITIONS.contains(&name) => {
+                let mut content = Vec::new();
+                self.parse_blocks(node, &mut content)?;
+                let label = format!("{}: ", name.to_uppercase());
+                match content.first_mut() {
+                    Some(Element::Paragraph { elements }) => match elements.first_mut() {
+                        Some(Element::Text { text, .. }) => text.insert_str(0, &label),
+                        _ => elements.insert(
+                            0,
+                            Element::Text {
+                                text: label,
+                                font: FontSpec::from(8),
+                            },
+                        ),
+                    },
+                    _ => content.insert(
+                        0,
+                        Element::Paragraph {
+                            elements: vec![Element::Text {
+                                text: label.trim_end().to_string(),
+                                font: FontSpec::from(8),
+                            }],
+                        },
+                    ),
+                }
+                elements.append(&mut content);
+            }
+            "remark" => elements.push(Element::Comment {
+                text: text_content(node).trim().to_string(),
+                author: None,
+                date: None,
+                range: None,
+            }),
+            "blockquote" => {
+                let mut quote = Vec::new();
+                self.parse_blocks(node, &mut quote)?;
+                elements.push(Element::Blockquote {
+                    elements: quote,
+                    attribution: vec![],
+                });
+            }
+            "sidebar" | "example" | "informalexample" | "abstract" => {
+                self.parse_blocks(node, elements)?
+            }
+            // Titles are handled by the parent, metadata is not document content
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// A paragraph may contain block elements such as lists, these end up next to it
+    fn parse_paragraph(&self, node: &XmlNode, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        let mut inline = Vec::new();
+        for child in &node.children {
+            match child {
+                XmlChild::Node(child) if is_block(&child.name) => {
+                    flush_paragraph(&mut inline, elements);
+                    self.parse_block(child, elements)?;
+                }
+                child => self.parse_inline(child, &mut inline)?,
+            }
+        }
+        flush_paragraph(&mut inline, elements);
+        Ok(())
+    }
+
+    fn parse_list(&self, node: &XmlNode, numbered: bool) -> anyhow::Result<Element> {
+        let mut items = Vec::new();
+        for item in node.nodes().filter(|item| item.name == "listitem") {
+            let mut content = Vec::new();
+            self.parse_blocks(item, &mut content)?;
+            // Nested lists follow their parent item in the model
+            let nested = content
+                .iter()
+                .position(|element| matches!(element, Element::List { .. }))
+                .map(|position| content.split_off(position))
+                .unwrap_or_default();
+            match content.len() {
+                0 => {}
+                1 => items.push(ListItem {
+                    element: unwrap_paragraph(content.remove(0)),
+                    checked: None,
+                }),
+                _ => items.push(ListItem {
+                    element: Element::Paragraph { elements: content },
+                    checked: None,
+                }),
+            }
+            items.extend(nested.into_iter().map(|element| ListItem {
+                element,
+                checked: None,
+            }));
+        }
+        Ok(Element::List {
+            elements: items,
+            numbered,
+        })
+    }
+
+    /// Every term of an entry is an item, the blocks of the entry's `listitem` are the
+    /// definitions of its last term
+    fn parse_variable_list(&self, node: &XmlNode) -> anyhow::Result<Element> {
+        let mut items = Vec::new();
+        for entry in node.nodes().filter(|entry| entry.name == "varlistentry") {
+            for term in entry.nodes().filter(|term| term.name == "term") {
+                let mut inline = Vec::new();
+                for child in &term.children {
+                    self.parse_inline(child, &mut inline)?;
+                }
+                let mut paragraph = Vec::new();
+                flush_paragraph(&mut inline, &mut paragraph);
+                let term = paragraph
+                    .into_iter()
+                    .flat_map(|element| match element {
+                        Element::Paragraph { elements } => elements,
+                        element => vec![element],
+                    })
+                    .collect();
+                items.push(DefinitionItem {
+                    term,
+                    definitions: vec![],
+                });
+            }
+            if let (Some(item), Some(listitem)) = (items.last_mut(), entry.find("listitem")) {
+                self.parse_blocks(listitem, &mut item.definitions)?;
+            }
+        }
+        Ok(Element::DefinitionList { items })
+    }
+
+    /// CALS tables (`tgroup`/`row`/`entry`) and HTML tables (`tr`/`th`/`td`)
+    fn parse_table(&self, node: &XmlNode) -> anyhow::Result<Element> {
+        let mut headers = Vec::new();
+        let mut rows = Vec::new();
+        let group = node.find("tgroup").unwrap_or(node);
+        for section in group.nodes() {
+            let section_rows: Vec<&XmlNode> = match section.name.as_str() {
+                "thead" | "tbody" | "tfoot" => section
+                    .nodes()
+                    .filter(|row| row.name == "row" || row.name == "tr")
+                    .collect(),
+                "row" | "tr" => vec![section],
+                _ => continue,
+            };
+            for row in section_rows {
+                let mut cells = Vec::new();
+                let mut header_cells = section.name == "thead";
+                for cell in row.nodes() {
+                    if !matches!(cell.name.as_str(), "entry" | "td" | "th") {
+                        continue;
+                    }
+                    header_cells |= cell.name == "th";
+                    cells.push(self.parse_cell(cell)?);
+                }
+                if header_cells && headers.is_empty() && rows.is_empty() {
+                    headers = cells
+                        .into_iter()
+                        .map(|element| TableHeader {
+                            element,
+                            width: 30.0,
+                        })
+                        .collect();
+                } else {
+                    rows.push(TableRow {
+                        cells: cells.into_iter().map(TableCell::new).collect(),
+                    });
+                }
+            }
+        }
+        Ok(Element::Table {
+            headers,
+            rows,
+            alignments: vec![],
+            caption: vec![],
+        })
+    }
+
+    fn parse_cell(&self, cell: &XmlNode) -> anyhow::Result<Element> {
+        let mut content = Vec::new();
+        if cell.nodes().any(|child| is_block(&child.name)) {
+            self.parse_blocks(cell, &mut content)?;
+        } else {
+            self.parse_paragraph(cell, &mut content)?;
+        }
+        Ok(match content.len() {
+            0 => Element::Text {
+                text: String::new(),
+                font: FontSpec::from(8),
+            },
+            1 => unwrap_paragraph(content.remove(0)),
+            _ => Element::Paragraph { elements: content },
+        })
+    }
+
+    /// Uses the first `imageobject`, `textobject` provides the alternative text
+    fn parse_image(&self, media: &XmlNode, title: String) -> anyhow::Result<Option<Element>> {
+        let Some(data) = media
+            .nodes()
+            .filter(|object| object.name == "imageobject")
+            .find_map(|object| object.find("imagedata"))
+        else {
+            return Ok(None);
+        };
+        let Some(fileref) = data.attribute("fileref") else {
+            return Ok(None);
+        };
+        let alt = media
+            .find("textobject")
+            .map(|text| collapse_whitespace(&text_content(text)).trim().to_string())
+            .or_else(|| media.find("alt").map(|alt| text_content(alt).trim().to_string()))
+            .unwrap_or_default();
+        let bytes = (self.image_loader)(fileref)?;
+        Ok(Some(Element::Image(ImageData::new(
+            bytes,
+            title,
+            alt,
+            fileref.to_string(),
+            data.attribute("align").unwrap_or_default().to_string(),
+            ImageDimension {
+                width: data
+                    .attribute("contentwidth")
+                    .or(data.attribute("width"))
+                    .map(str::to_string),
+                height: data
+                    .attribute("contentdepth")
+                    .or(data.attribute("depth"))
+                    .map(str::to_string),
+            },
+        ))))
+    }
+
+    fn parse_inline(&self, child: &XmlChild, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        match child {
+            XmlChild::Text(text) => push_text(elements, &collapse_whitespace(text)),
+            XmlChild::Instruction(instruction) if instruction.starts_with(LINE_BREAK) => {
+                elements.push(Element::LineBreak)
+            }
+            XmlChild::Comment(_) | XmlChild::Instruction(_) => {}
+            XmlChild::Node(node) => match node.name.as_str() {
+                "link" | "ulink" => {
+                    let url = node
+                        .attribute("href")
+                        .or(node.attribute("url"))
+                        .unwrap_or_default()
+                        .to_string();
+                    let title = collapse_whitespace(&text_content(node)).trim().to_string();
+                    elements.push(Element::Hyperlink {
+                        title: if title.is_empty() { url.clone() } else { title },
+                        url,
+                        alt: node.attribute("title").unwrap_or_default().to_string(),
+                        size: 8,
+                    });
+                }
+                "inlinemediaobject" => {
+                    if let Some(image) = self.parse_image(node, String::new())? {
+                        elements.push(image);
+                    }
+                }
+                "remark" => elements.push(Element::Comment {
+                    text: text_content(node).trim().to_string(),
+                    author: None,
+                    date: None,
+                    range: None,
+                }),
+                "footnote" | "indexterm" => {}
+                _ => {
+                    for child in &node.children {
+                        self.parse_inline(child, elements)?;
+                    }
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+fn is_block(name: &str) -> bool {
+    matches!(
+        name,
+        "para"
+            | "simpara"
+            | "itemizedlist"
+            | "orderedlist"
+            | "variablelist"
+            | "table"
+            | "informaltable"
+            | "figure"
+            | "informalfigure"
+            | "mediaobject"
+            | "blockquote"
+    ) || VERBATIM.contains(&name)
+        || ADMONITIONS.contains(&name)
+}
+
+fn push_text(elements: &mut Vec<Element>, text: &str) {
+    if let Some(Element::Text { text: last, .. }) = elements.last_mut() {
+        last.push_str(text);
+    } else {
+        elements.push(Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        });
+    }
+}
+
+/// Trims the collected inline elements and pushes them as a paragraph
+fn flush_paragraph(inline: &mut Vec<Element>, elements: &mut Vec<Element>) {
+    let last = inline.len().saturating_sub(1);
+    for (index, element) in inline.iter_mut().enumerate() {
+        if let Element::Text { text, .. } = element {
+            if index == 0 {
+                *text = text.trim_start().to_string();
+            }
+            if index == last {
+                *text = text.trim_end().to_string();
+            }
+        }
+    }
+    inline.retain(|element| !matches!(element, Element::Text { text, .. } if text.is_empty()));
+    match inline.as_slice() {
+        [] => {}
+        // A paragraph holding only an image or a remark is that element
+        [Element::Image(_)] | [Element::Comment { .. }] => elements.append(inline),
+        _ => elements.push(Element::Paragraph {
+            elements: std::mem::take(inline),
+        }),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+struct Generator<F> {
+    image_saver: F,
+    image_num: usize,
+}
+
+impl<F> Generator<F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn block(&mut self, xml: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            // Headers inside lists and cells have no section to open
+            Element::Header { text, .. } => {
+                xml.push_str(&format!("<para>{}</para>\n", escape(text)));
+            }
+            Element::Text { text, .. } if text.contains('\n') => {
+                xml.push_str(&format!(
+                    "<literallayout>{}</literallayout>\n",
+                    escape(text)
+                ));
+            }
+            Element::List { elements, numbered } => self.list(xml, elements, *numbered)?,
+            Element::Chart { .. } => self.block(xml, &element.chart_fallback())?,
+            Element::Table { headers, rows, .. } => self.table(xml, headers, rows)?,
+            Element::Image(image) => self.image(xml, image, false)?,
+            Element::Figure { image, caption } => self.figure(xml, image, caption)?,
+            Element::Comment { text, .. } => {
+                xml.push_str(&format!("<remark>{}</remark>\n", escape(text)));
+            }
+            Element::Blockquote { elements, .. } => {
+                xml.push_str("<blockquote>\n");
+                for child in elements {
+                    self.block(xml, child)?;
+                }
+                xml.push_str("</blockquote>\n");
+            }
+            // The header of the section is its title, which DocBook requires
+            Element::Section { children, .. } => {
+                let (title, content) = match children.split_first() {
+                    Some((Element::Header { text, .. }, content)) => (text.as_str(), content),
+                    _ => ("", &children[..]),
+                };
+                xml.push_str(&format!("<section>\n<title>{}</title>\n", escape(title)));
+                for child in content {
+                    self.block(xml, child)?;
+                }
+                xml.push_str("</section>\n");
+            }
+            Element::CodeBlock { language, code } => {
+                xml.push_str("<programlisting");
+                if let Some(language) = language {
+                    xml.push_str(&format!(" language=\"{}\"", escape(language)));
+                }
+                xml.push_str(&format!(">{}</programlisting>\n", escape(code)));
+            }
+            // DocBook has no rule, the sections already separate the content
+            Element::HorizontalRule => {}
+            Element::PageBreak => xml.push_str("<?hard-pagebreak?>\n"),
+            // The stylesheets generate the entries, their depth is a stylesheet parameter
+            Element::TableOfContents { .. } => xml.push_str("<toc/>\n"),
+            // Terms without definitions share the entry of the next term
+            Element::DefinitionList { items } => {
+                xml.push_str("<variablelist>\n");
+                let mut open = false;
+                for item in items {
+                    if !open {
+                        xml.push_str("<varlistentry>\n");
+                        open = true;
+                    }
+                    xml.push_str("<term>");
+                    for child in &item.term {
+                        self.inline(xml, child)?;
+                    }
+                    xml.push_str("</term>\n");
+                    if !item.definitions.is_empty() {
+                        xml.push_str("<listitem>\n");
+                        for definition in &item.definitions {
+                            self.block(xml, definition)?;
+                        }
+                        xml.push_str("</listitem>\n</varlistentry>\n");
+                        open = false;
+                    }
+                }
+                if open {
+                    xml.push_str("<listitem><para/></listitem>\n</varlistentry>\n");
+                }
+                xml.push_str("</variablelist>\n");
+            }
+            Element::Math { tex, display: true } => xml.push_str(&format!(
+                "<informalequation><mathphrase>{}</mathphrase></informalequation>\n",
+                escape(tex)
+            )),
+            element => {
+                xml.push_str("<para>");
+                self.inline(xml, element)?;
+                xml.push_str("</para>\n");
+            }
+        }
+        Ok(())
+    }
+
+    fn list(&mut self, xml: &mut String, items: &[ListItem], numbered: bool) -> anyhow::Result<()> {
+        let tag = if numbered {
+            "orderedlist"
+        } else {
+            "itemizedlist"
+        };
+        xml.push_str(&format!("<{}>\n", tag));
+        let mut open = false;
+        for item in items {
+            match &item.element {
+                // Nested lists belong to the preceding item
+                Element::List { .. } if open => {
+                    xml.truncate(xml.len() - "</listitem>\n".len());
+                    self.block(xml, &item.element)?;
+                }
+                element => {
+                    xml.push_str("<listitem>");
+                    self.block(xml, element)?;
+                }
+            }
+            xml.push_str("</listitem>\n");
+            open = true;
+        }
+        xml.push_str(&format!("</{}>\n", tag));
+        Ok(())
+    }
+
+    fn table(
+        &mut self,
+        xml: &mut String,
+        headers: &[TableHeader],
+        rows: &[TableRow],
+    ) -> anyhow::Result<()> {
+        let columns = rows
+            .iter()
+            .map(|row| row.cells.len())
+            .chain(std::iter::once(headers.len()))
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        xml.push_str(&format!("<informaltable>\n<tgroup cols=\"{}\">\n", columns));
+        if !headers.is_empty() {
+            xml.push_str("<thead>\n<row>");
+            for header in headers {
+                self.cell(xml, &header.element)?;
+            }
+            xml.push_str("</row>\n</thead>\n");
+        }
+        xml.push_str("<tbody>\n");
+        for row in rows {
+            xml.push_str("<row>");
+            for cell in &row.cells {
+                self.cell(xml, &cell.element())?;
+            }
+            xml.push_str("</row>\n");
+        }
+        xml.push_str("</tbody>\n</tgroup>\n</informaltable>\n");
+        Ok(())
+    }
+
+    fn cell(&mut self, xml: &mut String, element: &Element) -> anyhow::Result<()> {
+        xml.push_str("<entry>");
+        match element {
+            Element::List { .. }
+            | Element::Table { .. }
+            | Element::Chart { .. }
+            | Element::Image(_)
+            | Element::Figure { .. } => self.block(xml, element)?,
+            element => self.inline(xml, element)?,
+        }
+        xml.push_str("</entry>");
+        Ok(())
+    }
+
+    fn inline(&mut self, xml: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => {
+                xml.push_str(&escape(text).replace('\n', "<?linebreak?>"))
+            }
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => {
+                for child in elements {
+                    self.inline(xml, child)?;
+                }
+            }
+            Element::Styled { style, elements } => {
+                let roles = [
+                    (style.bold, " role=\"bold\""),
+                    (style.italic, ""),
+                    (style.underline, " role=\"underline\""),
+                    (style.strikethrough, " role=\"strikethrough\""),
+                ];
+                let roles: Vec<&str> = roles
+                    .into_iter()
+                    .filter_map(|(enabled, role)| enabled.then_some(role))
+                    .collect();
+                for role in &roles {
+                    xml.push_str(&format!("<emphasis{}>", role));
+                }
+                for child in elements {
+                    self.inline(xml, child)?;
+                }
+                xml.push_str(&"</emphasis>".repeat(roles.len()));
+            }
+            Element::Hyperlink {
+                title, url, alt, ..
+            } => {
+                xml.push_str(&format!("<link xlink:href=\"{}\"", escape(url)));
+                if !alt.is_empty() {
+                    xml.push_str(&format!(" xlink:title=\"{}\"", escape(alt)));
+                }
+                xml.push_str(&format!(">{}</link>", escape(title)));
+            }
+            Element::Image(image) => self.image(xml, image, true)?,
+            Element::Figure { image, caption } => {
+                self.image(xml, image, true)?;
+                for child in caption {
+                    xml.push(' ');
+                    self.inline(xml, child)?;
+                }
+            }
+            Element::LineBreak | Element::HorizontalRule => xml.push_str("<?linebreak?>"),
+            Element::Media { .. } => self.inline(xml, &element.media_fallback())?,
+            Element::Ruby { .. } => self.inline(xml, &element.ruby_fallback())?,
+            Element::PageBreak | Element::TableOfContents { .. } | Element::Custom { .. } => {}
+            Element::Comment { text, .. } => {
+                xml.push_str(&format!("<remark>{}</remark>", escape(text)))
+            }
+            Element::List { elements, .. } => {
+                for (index, item) in elements.iter().enumerate() {
+                    if index > 0 {
+                        xml.push(' ');
+                    }
+                    self.inline(xml, &item.element)?;
+                }
+            }
+            Element::DefinitionList { items } => {
+                let children = items
+                    .iter()
+                    .flat_map(|item| item.term.iter().chain(&item.definitions));
+                for (index, child) in children.enumerate() {
+                    if index > 0 {
+                        xml.push(' ');
+                    }
+                    self.inline(xml, child)?;
+                }
+            }
+            Element::CodeBlock { code, .. } => {
+                xml.push_str(&format!("<code>{}</code>", escape(code)))
+            }
+            Element::Math { tex, .. } => xml.push_str(&format!(
+                "<inlineequation><mathphrase>{}</mathphrase></inlineequation>",
+                escape(tex)
+            )),
+            Element::FootnoteReference { id } => {
+                xml.push_str(&format!("<superscript>{}</superscript>", escape(id)))
+            }
+            Element::Footnote { id, content } => {
+                xml.push_str(&format!("<superscript>{}</superscript> ", escape(id)));
+                for child in content {
+                    self.inline(xml, child)?;
+                }
+            }
+            Element::Table { .. } | Element::Chart { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Block images with a title become figures
+    fn image(&mut self, xml: &mut String, image: &ImageData, inline: bool) -> anyhow::Result<()> {
+        let Some(media) = self.media(image, inline)? else {
+            return Ok(());
+        };
+        if inline || image.title().is_empty() {
+            xml.push_str(&media);
+        } else {
+            xml.push_str(&format!(
+                "<figure>\n<title>{}</title>\n{}</figure>\n",
+                escape(image.title()),
+                media
+            ));
+        }
+        Ok(())
+    }
+
+    /// The caption is the title of the figure
+    fn figure(
+        &mut self,
+        xml: &mut String,
+        image: &ImageData,
+        caption: &[Element],
+    ) -> anyhow::Result<()> {
+        let Some(media) = self.media(image, false)? else {
+            return Ok(());
+        };
+        let mut title = String::new();
+        for child in caption {
+            self.inline(&mut title, child)?;
+        }
+        xml.push_str(&format!(
+            "<figure>\n<title>{}</title>\n{}</figure>\n",
+            title, media
+        ));
+        Ok(())
+    }
+
+    /// Saves the image and returns its media object, images without content have none
+    fn media(&mut self, image: &ImageData, inline: bool) -> anyhow::Result<Option<String>> {
+        if image.bytes().is_empty() {
+            return Ok(None);
+        }
+        let target = format!(
+            "image{}{}",
+            self.image_num,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &target)?;
+        self.image_num += 1;
+
+        let mut data = format!("<imagedata fileref=\"{}\"", escape(&target));
+        if let Some(width) = &image.size().width {
+            data.push_str(&format!(" contentwidth=\"{}\"", escape(width)));
+        }
+        if let Some(height) = &image.size().height {
+            data.push_str(&format!(" contentdepth=\"{}\"", escape(height)));
+        }
+        data.push_str("/>");
+        let text = if image.alt().is_empty() {
+            String::new()
+        } else {
+            format!(
+                "<textobject><phrase>{}</phrase></textobject>",
+                escape(image.alt())
+            )
+        };
+
+        Ok(Some(if inline {
+            format!(
+                "<inlinemediaobject><imageobject>{}</imageobject>{}</inlinemediaobject>",
+                data, text
+            )
+        } else {
+            format!(
+                "<mediaobject><imageobject>{}</imageobject>{}</mediaobject>\n",
+                data, text
+            )
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::docbook::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+<article xmlns="http://docbook.org/ns/docbook" xmlns:xlink="http://www.w3.org/1999/xlink" version="5.0">
+  <info><title>Guide</title></info>
+  <section>
+    <title>Setup</title>
+    <para>Install the <emphasis>cli</emphasis> first,
+      see <link xlink:href="https://example.com/docs">the docs</link>.</para>
+    <itemizedlist>
+      <listitem><para>One</para>
+        <orderedlist><listitem><para>Nested</para></listitem></orderedlist>
+      </listitem>
+      <listitem><para>Two</para></listitem>
+    </itemizedlist>
+    <informaltable>
+      <tgroup cols="2">
+        <thead><row><entry>Name</entry><entry>Value</entry></row></thead>
+        <tbody><row><entry>a</entry><entry>1</entry></row></tbody>
+      </tgroup>
+    </informaltable>
+    <figure>
+      <title>The logo</title>
+      <mediaobject>
+        <imageobject><imagedata fileref="logo.png" contentwidth="200px"/></imageobject>
+        <textobject><phrase>Logo</phrase></textobject>
+      </mediaobject>
+    </figure>
+    <programlisting>let x = 1;</programlisting>
+    <section>
+      <title>Details</title>
+      <note><para>Requires Rust.</para></note>
+    </section>
+  </section>
+</article>"#;
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |_| {
+            Ok(Bytes::from_static(b"\x89PNG"))
+        })?;
+        let elements = document.get_all_elements();
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let header = |level: u8, text: &str| Element::Header {
+            level,
+            text: text.to_string(),
+            anchor: None,
+        };
+        assert_eq!(elements[0], &header(1, "Guide"));
+        assert_eq!(elements[1], &header(2, "Setup"));
+        assert_eq!(
+            elements[2],
+            &Element::Paragraph {
+                elements: vec![
+                    text("Install the cli first, see "),
+                    Element::Hyperlink {
+                        title: "the docs".to_string(),
+                        url: "https://example.com/docs".to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    },
+                    text("."),
+                ]
+            }
+        );
+        assert_eq!(
+            elements[3],
+            &Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("One"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: Element::List {
+                            elements: vec![ListItem {
+                                element: text("Nested"),
+                                checked: None,
+                            }],
+                            numbered: true,
+                        },
+                        checked: None,
+                    },
+                    ListItem {
+                        element: text("Two"),
+                        checked: None,
+                    },
+                ],
+                numbered: false,
+            }
+        );
+        let Element::Table { headers, rows, .. } = elements[4] else {
+            panic!("expected a table, got {:?}", elements[4]);
+        };
+        assert_eq!(headers[1].element, text("Value"));
+        assert_eq!(rows[0].cells[0].elements, vec![text("a")]);
+        let Element::Image(image) = elements[5] else {
+            panic!("expected an image, got {:?}", elements[5]);
+        };
+        assert_eq!(image.title(), "The logo");
+        assert_eq!(image.alt(), "Logo");
+        assert_eq!(image.size().width.as_deref(), Some("200px"));
+        assert_eq!(elements[6], &text("let x = 1;"));
+        assert_eq!(elements[7], &header(3, "Details"));
+        assert_eq!(
+            elements[8],
+            &Element::Paragraph {
+                elements: vec![text("NOTE: Requires Rust.")]
+            }
+        );
+        assert_eq!(elements.len(), 9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let images = std::cell::RefCell::new(HashMap::new());
+        let generated = Transformer::generate_with_saver(&parsed, |bytes, name| {
+            images.borrow_mut().insert(name.to_string(), bytes.clone());
+            Ok(())
+        })?;
+        let reparsed = Transformer::parse_with_loader(&generated, |name| {
+            Ok(images.borrow().get(name).cloned().unwrap_or_default())
+        })?;
+        let original = parsed.get_all_elements();
+        let elements = reparsed.get_all_elements();
+        assert_eq!(original.len(), elements.len());
+        for (original, element) in original.iter().zip(elements.iter()) {
+            assert_eq!(
+                std::mem::discriminant(*original),
+                std::mem::discriminant(*element)
+            );
+        }
+        Ok(())
+    }
+}
@@ -1,18 +1,867 @@
 use crate::core::{
-    Document, Element, ImageDimension, ListItem, TableCell, TableRow, TransformerTrait,
+    Color, ColumnAlignment, DefinitionItem, Document, Element, FontSpec, HighlightColor, ImageData,
+    ImageDimension, ImageType, ListItem, MediaSource, TableCell, TableHeader, TableRow,
+    TextDirection, TextStyle, TocEntry, TransformerTrait,
 };
 
 use bytes::Bytes;
 use docx_rs::{
-    read_docx, AbstractNumbering, Docx, Hyperlink, HyperlinkType, IndentLevel, Level, LevelJc,
-    LevelText, NumberFormat, Numbering, NumberingId, Paragraph, ParagraphStyle, Pic, Run, RunChild,
-    SpecialIndentType, Start, TableRowChild,
+    read_docx, AbstractNumbering, AlignmentType, BreakType, CommentRangeEnd, CommentRangeStart,
+    DocumentChild, Docx, DrawingData, Footnote, Hyperlink, HyperlinkType, IndentLevel, InstrToC,
+    Level, LevelJc, LevelText, NumberFormat, Numbering, NumberingId, Paragraph, ParagraphBorder,
+    ParagraphBorderPosition, ParagraphChild, ParagraphStyle, Pic, Run, RunChild, RunFonts,
+    RunProperty, Shading, SpecialIndentType, Start, StructuredDataTag, StructuredDataTagChild,
+    Style, StyleType, TableOfContentsItem, TableRowChild, VMergeType, VertAlignType,
 };
 use log::{error, info, warn};
 use std::io::Cursor;
+use std::str::FromStr;
 
 pub struct Transformer;
 
+/// Paragraph style of code blocks
+const CODE: &str = "Code";
+
+/// Paragraph style of blockquotes, the name of the built-in Word style
+const QUOTE: &str = "Quote";
+
+/// Paragraph style of definition list entries, with a hanging indent
+const DEFINITION: &str = "Definition";
+
+/// Paragraph style of figure captions, the name of the built-in Word style
+const CAPTION: &str = "Caption";
+
+/// Character style of footnote references, `docx-rs` sets it on the reference runs
+const FOOTNOTE_REFERENCE: &str = "FootnoteReference";
+
+/// A code block is a single paragraph in a monospace font, its lines are separated by breaks
+fn code_paragraph(code: &str) -> Paragraph {
+    let mut run = Run::new().fonts(RunFonts::new().ascii("Courier New").hi_ansi("Courier New"));
+    for (index, line) in code.split('\n').enumerate() {
+        if index > 0 {
+            run = run.add_break(BreakType::TextWrapping);
+        }
+        run = run.add_text(line);
+    }
+    Paragraph::new().style(CODE).add_run(run)
+}
+
+/// A horizontal rule is an empty paragraph with a bottom border
+fn rule_paragraph() -> Paragraph {
+    let mut paragraph = Paragraph::new();
+    paragraph.property = paragraph
+        .property
+        .set_border(ParagraphBorder::new(ParagraphBorderPosition::Bottom).size(6));
+    paragraph
+}
+
+/// The text of a code paragraph with its line breaks and tabs
+fn extract_code(paragraph: &docx_rs::Paragraph) -> String {
+    let mut code = String::new();
+    for child in &paragraph.children {
+        if let docx_rs::ParagraphChild::Run(run) = child {
+            for run_child in &run.children {
+                match run_child {
+                    RunChild::Text(text) => code.push_str(&text.text),
+                    RunChild::Break(_) => code.push('\n'),
+                    RunChild::Tab(_) => code.push('\t'),
+                    _ => {}
+                }
+            }
+        }
+    }
+    code
+}
+
+/// The text of a paragraph split where runs are raised, lowered, highlighted, colored or in a
+/// font of their own or link to a bookmark, `None` when all of it is plain. `docx-rs` keeps the
+/// run properties private so they are read from the serialized properties
+fn formatted_runs(paragraph: &docx_rs::Paragraph) -> Option<Vec<Element>> {
+    let mut elements: Vec<Element> = Vec::new();
+    let mut formatted = false;
+    for child in &paragraph.children {
+        let run = match child {
+            docx_rs::ParagraphChild::Run(run) => run,
+            // Links to bookmarks are the ones of a table of contents
+            docx_rs::ParagraphChild::Hyperlink(link) => {
+                if let docx_rs::HyperlinkData::Anchor { anchor } = &link.link {
+                    let title: String = link
+                        .children
+                        .iter()
+                        .filter_map(|child| match child {
+                            ParagraphChild::Run(run) => Some(run_text(run)),
+                            _ => None,
+                        })
+                        .collect();
+                    formatted = true;
+                    elements.push(Element::Hyperlink {
+                        title,
+                        url: format!("#{}", anchor),
+                        alt: String::new(),
+                        size: 16,
+                    });
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        let property = serde_json::to_value(&run.run_property).unwrap_or_default();
+        let style = TextStyle {
+            superscript: property["vertAlign"] == "superscript",
+            subscript: property["vertAlign"] == "subscript",
+            highlight: property["highlight"].as_str().and_then(highlight_color),
+            // The `auto` color of Word is no color of its own and is left out
+            color: property["color"]
+                .as_str()
+                .and_then(|color| color.parse().ok()),
+            background: property["shading"]["fill"]
+                .as_str()
+                .and_then(|color| color.parse().ok()),
+            ..Default::default()
+        };
+        let font = run_font(&property);
+        formatted |= font.is_explicit();
+        for (index, text) in run_lines(run).into_iter().enumerate() {
+            if index > 0 {
+                formatted = true;
+                elements.push(Element::LineBreak);
+            }
+            if text.is_empty() {
+                continue;
+            }
+            if style == TextStyle::default() {
+                match elements.last_mut() {
+                    Some(Element::Text {
+                        text: last,
+                        font: last_font,
+                    }) if *last_font == font => last.push_str(&text),
+                    _ => elements.push(Element::Text {
+                        text,
+                        font: font.clone(),
+                    }),
+                }
+                continue;
+            }
+            formatted = true;
+            elements.push(Element::Styled {
+                style,
+                elements: vec![Element::Text {
+                    text,
+                    font: font.clone(),
+                }],
+            });
+        }
+    }
+    formatted.then_some(elements)
+}
+
+/// The bookmark of a header, internal links point to it. Word keeps a `_GoBack` bookmark at the
+/// last edit which is no anchor
+fn bookmark(paragraph: &docx_rs::Paragraph) -> Option<String> {
+    paragraph.children.iter().find_map(|child| match child {
+        ParagraphChild::BookmarkStart(bookmark) if bookmark.name != "_GoBack" => {
+            Some(bookmark.name.clone())
+        }
+        _ => None,
+    })
+}
+
+/// A hyperlink to a url, or to the bookmark of a header when the url is `#` and its anchor
+fn hyperlink(url: &str) -> Hyperlink {
+    match url.strip_prefix('#') {
+        Some(anchor) => Hyperlink::new(anchor, HyperlinkType::Anchor),
+        None => Hyperlink::new(url, HyperlinkType::External),
+    }
+}
+
+/// Entries of a table of contents with the headers below them, linking to their bookmarks
+fn toc_items(entries: &[TocEntry], toc: &mut docx_rs::TableOfContents) {
+    for entry in entries {
+        toc.items.push(
+            TableOfContentsItem::new()
+                .text(&entry.text)
+                .level(entry.level as usize)
+                .toc_key(entry.anchor.as_deref().unwrap_or_default()),
+        );
+        toc_items(&entry.children, toc);
+    }
+}
+
+/// The deepest header level listed by a table of contents field in a content control. A
+/// field without a level range lists all headers
+fn toc_level(tag: &StructuredDataTag) -> Option<u8> {
+    let paragraphs = tag.children.iter().filter_map(|child| match child {
+        StructuredDataTagChild::Paragraph(paragraph) => Some(paragraph),
+        _ => None,
+    });
+    let runs = paragraphs.flat_map(|paragraph| {
+        paragraph.children.iter().filter_map(|child| match child {
+            ParagraphChild::Run(run) => Some(run),
+            _ => None,
+        })
+    });
+    let instr = runs
+        .flat_map(|run| run.children.iter())
+        .find_map(|run_child| match run_child {
+            RunChild::InstrTextString(instr) if instr.trim_start().starts_with("TOC") => {
+                InstrToC::from_str(instr.trim()).ok()
+            }
+            _ => None,
+        })?;
+    Some(
+        instr
+            .heading_styles_range
+            .map_or(6, |(_, end)| end.clamp(1, 6) as u8),
+    )
+}
+
+/// Whether a run of the paragraph breaks the page. `docx-rs` keeps the type of breaks private
+/// so it is read from the serialized break
+fn has_page_break(paragraph: &docx_rs::Paragraph) -> bool {
+    paragraph.children.iter().any(|child| match child {
+        ParagraphChild::Run(run) => run.children.iter().any(|run_child| match run_child {
+            RunChild::Break(page_break) => {
+                serde_json::to_value(page_break).unwrap_or_default()["breakType"] == "page"
+            }
+            _ => false,
+        }),
+        _ => false,
+    })
+}
+
+/// Whether each paragraph of the body is right to left. `docx-rs` does not read the `bidi`
+/// property so it is read from the document part, the paragraphs are in the order of its
+/// children
+fn bidi_paragraphs(package: &[u8]) -> anyhow::Result<Vec<bool>> {
+    use quick_xml::events::Event;
+    use std::io::Read;
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(package))?;
+    let mut xml = Vec::new();
+    archive
+        .by_name("word/document.xml")?
+        .read_to_end(&mut xml)?;
+    let mut reader = quick_xml::Reader::from_reader(xml.as_slice());
+    let mut paragraphs = Vec::new();
+    // Depth of the open elements, the paragraphs of the body are at the third level
+    let mut depth = 0;
+    let mut in_property = false;
+    loop {
+        let event = reader.read_event()?;
+        let (start, empty) = match &event {
+            Event::Start(start) => (Some(start), false),
+            Event::Empty(start) => (Some(start), true),
+            Event::End(end) => {
+                if end.local_name().as_ref() == b"pPr" {
+                    in_property = false;
+                }
+                depth -= 1;
+                continue;
+            }
+            Event::Eof => break,
+            _ => continue,
+        };
+        if let Some(start) = start {
+            match start.local_name().as_ref() {
+                b"p" if depth == 2 => paragraphs.push(false),
+                b"pPr" if depth == 3 => in_property = !empty,
+                b"bidi" if in_property && depth == 4 => {
+                    let off = start.attributes().flatten().any(|attribute| {
+                        attribute.key.local_name().as_ref() == b"val"
+                            && matches!(attribute.value.as_ref(), b"0" | b"false" | b"off")
+                    });
+                    if let Some(bidi) = paragraphs.last_mut() {
+                        *bidi = !off;
+                    }
+                }
+                _ => {}
+            }
+            if !empty {
+                depth += 1;
+            }
+        }
+    }
+    Ok(paragraphs)
+}
+
+/// A paragraph right to left of its own
+fn rtl_paragraph(elements: Vec<Element>) -> Element {
+    Element::Paragraph {
+        elements: vec![Element::Styled {
+            style: TextStyle::RTL,
+            elements,
+        }],
+    }
+}
+
+/// The text of a run without its breaks and drawings
+fn run_text(run: &Run) -> String {
+    run.children
+        .iter()
+        .filter_map(|run_child| match run_child {
+            RunChild::Text(text) => Some(text.text.as_str()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The text of a run split at its line breaks, `<w:br/>` without a type breaks the line
+fn run_lines(run: &Run) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    for run_child in &run.children {
+        match run_child {
+            RunChild::Text(text) => {
+                if let Some(line) = lines.last_mut() {
+                    line.push_str(&text.text);
+                }
+            }
+            RunChild::Break(line_break)
+                if serde_json::to_value(line_break).unwrap_or_default()["breakType"]
+                    == "textWrapping" =>
+            {
+                lines.push(String::new())
+            }
+            _ => {}
+        }
+    }
+    lines
+}
+
+/// The font of a run when it names one, Word sizes are in half points and 11 points by default.
+/// Runs in the font of their paragraph style get the nominal size of paragraph text
+fn run_font(property: &serde_json::Value) -> FontSpec {
+    let Some(family) = property["fonts"]["ascii"].as_str() else {
+        return FontSpec::from(16);
+    };
+    FontSpec {
+        family: Some(family.to_string()),
+        size_pt: property["sz"]
+            .as_f64()
+            .map_or(11.0, |size| size as f32 / 2.0),
+        weight: Some(if property["bold"] == true { 700 } else { 400 }),
+    }
+}
+
+/// A color as Word writes it, in hexadecimal without a `#`
+fn word_color(color: Color) -> String {
+    format!("{:02X}{:02X}{:02X}", color.red, color.green, color.blue)
+}
+
+/// The highlight color of a run, Word has more colors than the document model and the
+/// dark ones are read as their light counterparts
+fn highlight_color(name: &str) -> Option<HighlightColor> {
+    match name {
+        "yellow" | "darkYellow" => Some(HighlightColor::Yellow),
+        "green" | "darkGreen" => Some(HighlightColor::Green),
+        "cyan" | "darkCyan" => Some(HighlightColor::Cyan),
+        "magenta" | "darkMagenta" => Some(HighlightColor::Magenta),
+        "blue" | "darkBlue" => Some(HighlightColor::Blue),
+        "red" | "darkRed" => Some(HighlightColor::Red),
+        "lightGray" | "darkGray" => Some(HighlightColor::Gray),
+        _ => None,
+    }
+}
+
+/// Review comments anchored in a paragraph, the range is the text between their start and end
+fn paragraph_comments(paragraph: &docx_rs::Paragraph) -> Vec<Element> {
+    let mut comments: Vec<(usize, Element)> = Vec::new();
+    let mut open: Vec<usize> = Vec::new();
+    for child in &paragraph.children {
+        match child {
+            ParagraphChild::CommentStart(start) => {
+                let comment = &start.comment;
+                let text = comment
+                    .children
+                    .iter()
+                    .filter_map(|child| match child {
+                        docx_rs::CommentChild::Paragraph(paragraph) => Some(
+                            paragraph
+                                .children
+                                .iter()
+                                .filter_map(|child| match child {
+                                    ParagraphChild::Run(run) => Some(run_text(run)),
+                                    _ => None,
+                                })
+                                .collect::<String>(),
+                        ),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let non_empty = |value: &str| Some(value.to_string()).filter(|v| !v.is_empty());
+                comments.push((
+                    start.id,
+                    Element::Comment {
+                        author: non_empty(&comment.author),
+                        date: non_empty(&comment.date),
+                        text,
+                        range: Some(String::new()),
+                    },
+                ));
+                open.push(start.id);
+            }
+            ParagraphChild::CommentEnd(end) => {
+                let id = serde_json::to_value(end).unwrap_or_default()["id"].as_u64();
+                open.retain(|open| Some(*open as u64) != id);
+            }
+            ParagraphChild::Run(run) => {
+                let text = run_text(run);
+                for (id, comment) in &mut comments {
+                    if let Element::Comment {
+                        range: Some(range), ..
+                    } = comment
+                    {
+                        if open.contains(id) {
+                            range.push_str(&text);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    comments
+        .into_iter()
+        .map(|(_, mut comment)| {
+            if let Element::Comment { range, .. } = &mut comment {
+                *range = range.take().filter(|range| !range.is_empty());
+            }
+            comment
+        })
+        .collect()
+}
+
+/// A review comment anchored at the end of a paragraph, Word shows it in the margin
+fn add_comment(
+    paragraph: &mut Paragraph,
+    id: usize,
+    author: &Option<String>,
+    date: &Option<String>,
+    text: &str,
+) {
+    let mut comment = docx_rs::Comment::new(id)
+        .add_paragraph(Paragraph::new().add_run(Run::new().add_text(text)));
+    if let Some(author) = author {
+        comment = comment.author(author);
+    }
+    if let Some(date) = date {
+        comment = comment.date(date);
+    }
+    paragraph
+        .children
+        .push(ParagraphChild::CommentStart(Box::new(
+            CommentRangeStart::new(comment),
+        )));
+    paragraph
+        .children
+        .push(ParagraphChild::CommentEnd(CommentRangeEnd::new(id)));
+}
+
+/// Every block of a blockquote becomes an indented paragraph, nested quotes are flattened
+fn quote_paragraphs(elements: &[Element], paragraphs: &mut Vec<Paragraph>) {
+    for element in elements {
+        match element {
+            Element::Blockquote { elements, .. } => quote_paragraphs(elements, paragraphs),
+            Element::List { elements, .. } => {
+                for item in elements {
+                    quote_paragraphs(std::slice::from_ref(&item.element), paragraphs);
+                }
+            }
+            element => {
+                let mut text = String::new();
+                plain_text(element, &mut text);
+                if !text.is_empty() {
+                    paragraphs.push(
+                        Paragraph::new()
+                            .style(QUOTE)
+                            .add_run(Run::new().add_text(text)),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Every definition is a hanging-indent paragraph, the first one starts with the bold term and
+/// a tab to the indent
+fn definition_paragraphs(items: &[DefinitionItem], paragraphs: &mut Vec<Paragraph>) {
+    for item in items {
+        let mut term = String::new();
+        for element in &item.term {
+            plain_text(element, &mut term);
+        }
+        let mut paragraph = Some(
+            Paragraph::new()
+                .style(DEFINITION)
+                .add_run(Run::new().add_text(term).bold()),
+        );
+        for definition in &item.definitions {
+            let mut text = String::new();
+            plain_text(definition, &mut text);
+            let started = paragraph
+                .take()
+                .unwrap_or_else(|| Paragraph::new().style(DEFINITION));
+            paragraphs.push(started.add_run(Run::new().add_tab().add_text(text)));
+        }
+        paragraphs.extend(paragraph);
+    }
+}
+
+/// The term of a definition paragraph and its definition, the term is the bold text before the
+/// tab and is empty in the paragraphs of further definitions
+fn extract_definition(paragraph: &docx_rs::Paragraph) -> (String, String) {
+    let mut term = String::new();
+    let mut definition = String::new();
+    let mut after_tab = false;
+    for child in &paragraph.children {
+        if let docx_rs::ParagraphChild::Run(run) = child {
+            for run_child in &run.children {
+                match run_child {
+                    RunChild::Tab(_) => after_tab = true,
+                    RunChild::Text(text) if !after_tab && run.run_property.bold.is_some() => {
+                        term.push_str(&text.text)
+                    }
+                    RunChild::Text(text) => definition.push_str(&text.text),
+                    _ => {}
+                }
+            }
+        }
+    }
+    (term, definition)
+}
+
+/// The first picture of a paragraph, its content is looked up in the images of the document
+fn paragraph_image(
+    paragraph: &docx_rs::Paragraph,
+    images: &[(String, String, docx_rs::Image, docx_rs::Png)],
+) -> Option<ImageData> {
+    let pic = paragraph.children.iter().find_map(|child| match child {
+        ParagraphChild::Run(run) => run.children.iter().find_map(|child| match child {
+            RunChild::Drawing(drawing) => match &drawing.data {
+                Some(DrawingData::Pic(pic)) => Some(pic),
+                _ => None,
+            },
+            _ => None,
+        }),
+        _ => None,
+    })?;
+    let (_, path, image, _) = images.iter().find(|(id, ..)| *id == pic.id)?;
+    // Formats without an image type of their own keep the default one
+    let extension = path.rsplit('.').next().unwrap_or_default();
+    let image_type = if ImageType::from_str(&extension.to_lowercase()).is_ok() {
+        extension
+    } else {
+        ""
+    };
+    Some(ImageData::new(
+        Bytes::from(image.0.clone()),
+        String::new(),
+        String::new(),
+        image_type.to_string(),
+        String::new(),
+        ImageDimension::default(),
+    ))
+}
+
+/// The picture of an image, scaled down to the page
+fn picture(image: &ImageData) -> Pic {
+    let mut pic = Pic::new(&image.bytes());
+
+    match &image.size() {
+        &ImageDimension {
+            width: Some(width),
+            height: Some(height),
+        } => {
+            let width = width.parse().unwrap_or(0);
+            let height = height.parse().unwrap_or(0);
+            if width > 0 && height > 0 {
+                pic = pic.size(width, height);
+            }
+        }
+        _ => {}
+    }
+
+    re_size_picture(pic)
+}
+
+/// A table, a table in one of its cells is nested in the cell
+fn docx_table(
+    headers: &[TableHeader],
+    rows: &[TableRow],
+    alignments: &[ColumnAlignment],
+) -> docx_rs::Table {
+    let mut table_rows = Vec::new();
+
+    if !headers.is_empty() {
+        let mut header_cell: Vec<docx_rs::TableCell> = Vec::new();
+        for (column, header) in headers.iter().enumerate() {
+            if let Element::Text { text, font } = &header.element {
+                let cell = docx_rs::TableCell::new().add_paragraph(align_cell(
+                    Paragraph::new().add_run(text_run(text, font)),
+                    alignments,
+                    column,
+                ));
+                header_cell.push(cell);
+            }
+        }
+        let header_row = docx_rs::TableRow::new(header_cell);
+        table_rows.push(header_row)
+    }
+
+    // Grid column, columns spanned and rows left of the cells merged downwards
+    let mut merges: Vec<(usize, usize, usize)> = Vec::new();
+    for row in rows {
+        let mut rows_cell = Vec::new();
+        let mut cells = row.cells.iter();
+        let mut column = 0;
+
+        loop {
+            // A merged cell continues in every row it spans
+            if let Some(merge) = merges
+                .iter_mut()
+                .find(|(start, _, left)| *start == column && *left > 0)
+            {
+                merge.2 -= 1;
+                let mut table_cell = docx_rs::TableCell::new()
+                    .add_paragraph(Paragraph::new())
+                    .vertical_merge(VMergeType::Continue);
+                if merge.1 > 1 {
+                    table_cell = table_cell.grid_span(merge.1);
+                }
+                rows_cell.push(table_cell);
+                column += merge.1;
+                continue;
+            }
+            let Some(cell) = cells.next() else {
+                // Merges right of the last cell still need their cells
+                match merges
+                    .iter()
+                    .filter(|(start, _, left)| *start > column && *left > 0)
+                    .map(|(start, ..)| *start)
+                    .min()
+                {
+                    Some(start) => {
+                        column = start;
+                        continue;
+                    }
+                    None => break,
+                }
+            };
+            let mut table_cell = docx_rs::TableCell::new();
+            // A cell ends with a paragraph, so an empty cell or one ending with a table gets an
+            // empty one
+            let mut ends_with_paragraph = false;
+            for element in &cell.elements {
+                if let Element::Table {
+                    headers,
+                    rows,
+                    alignments,
+                    ..
+                } = element
+                {
+                    table_cell = table_cell.add_table(docx_table(headers, rows, alignments));
+                    ends_with_paragraph = false;
+                    continue;
+                }
+                let mut paragraphs = Vec::new();
+                cell_paragraphs(std::slice::from_ref(element), &mut paragraphs);
+                for paragraph in paragraphs {
+                    table_cell =
+                        table_cell.add_paragraph(align_cell(paragraph, alignments, column));
+                    ends_with_paragraph = true;
+                }
+            }
+            if !ends_with_paragraph {
+                table_cell = table_cell.add_paragraph(Paragraph::new());
+            }
+            if cell.colspan > 1 {
+                table_cell = table_cell.grid_span(cell.colspan);
+            }
+            if cell.rowspan > 1 {
+                table_cell = table_cell.vertical_merge(VMergeType::Restart);
+                merges.retain(|(start, ..)| *start != column);
+                merges.push((column, cell.colspan, cell.rowspan - 1));
+            }
+            rows_cell.push(table_cell);
+            column += cell.colspan.max(1);
+        }
+        let table_row = docx_rs::TableRow::new(rows_cell);
+        table_rows.push(table_row);
+    }
+    docx_rs::Table::new(table_rows)
+}
+
+/// Every block of a table cell becomes a paragraph, list items each get one of their own
+fn cell_paragraphs(elements: &[Element], paragraphs: &mut Vec<Paragraph>) {
+    for element in elements {
+        match element {
+            Element::Text { text, font } => {
+                paragraphs.push(Paragraph::new().add_run(text_run(text, font)))
+            }
+            Element::Image(image) => {
+                paragraphs.push(Paragraph::new().add_run(Run::new().add_image(picture(image))))
+            }
+            Element::Figure { image, caption } => {
+                paragraphs.push(Paragraph::new().add_run(Run::new().add_image(picture(image))));
+                let mut text = String::new();
+                for child in caption {
+                    plain_text(child, &mut text);
+                }
+                paragraphs.push(
+                    Paragraph::new()
+                        .style(CAPTION)
+                        .add_run(Run::new().add_text(text)),
+                );
+            }
+            Element::CodeBlock { code, .. } => paragraphs.push(code_paragraph(code)),
+            Element::HorizontalRule => paragraphs.push(rule_paragraph()),
+            Element::Blockquote { elements, .. } => cell_paragraphs(elements, paragraphs),
+            Element::List { elements, numbered } => {
+                for (index, item) in elements.iter().enumerate() {
+                    if let Element::List { .. } = item.element {
+                        cell_paragraphs(std::slice::from_ref(&item.element), paragraphs);
+                        continue;
+                    }
+                    let mut text = if *numbered {
+                        format!("{}. ", index + 1)
+                    } else {
+                        "• ".to_string()
+                    };
+                    plain_text(&item.element, &mut text);
+                    paragraphs.push(Paragraph::new().add_run(Run::new().add_text(text)));
+                }
+            }
+            // Other content keeps its text
+            element => {
+                let mut text = String::new();
+                plain_text(element, &mut text);
+                paragraphs.push(Paragraph::new().add_run(Run::new().add_text(text)));
+            }
+        }
+    }
+}
+
+/// Aligns the paragraph of a cell as its column
+fn align_cell(paragraph: Paragraph, alignments: &[ColumnAlignment], column: usize) -> Paragraph {
+    match alignments.get(column) {
+        Some(ColumnAlignment::Left) => paragraph.align(AlignmentType::Left),
+        Some(ColumnAlignment::Center) => paragraph.align(AlignmentType::Center),
+        Some(ColumnAlignment::Right) => paragraph.align(AlignmentType::Right),
+        Some(ColumnAlignment::None) | None => paragraph,
+    }
+}
+
+/// Columns spanned by a table cell and its `vMerge` value, `docx-rs` keeps both private so they
+/// are read from the serialized properties
+fn cell_span(property: &docx_rs::TableCellProperty) -> (usize, Option<String>) {
+    let property = serde_json::to_value(property).unwrap_or_default();
+    let colspan = property["gridSpan"]
+        .as_u64()
+        .filter(|span| *span > 0)
+        .map_or(1, |span| span as usize);
+    let merge = property["verticalMerge"].as_str().map(str::to_string);
+    (colspan, merge)
+}
+
+/// Text of an element without its formatting, for paragraphs written as a single run
+fn plain_text(element: &Element, text: &mut String) {
+    match element {
+        Element::Text { text: value, .. } | Element::Header { text: value, .. } => {
+            text.push_str(value)
+        }
+        Element::CodeBlock { code, .. } | Element::Math { tex: code, .. } => text.push_str(code),
+        Element::Hyperlink { title, .. } => text.push_str(title),
+        Element::Ruby { .. } => plain_text(&element.ruby_fallback(), text),
+        Element::LineBreak => text.push(' '),
+        Element::Paragraph { elements } | Element::Styled { elements, .. } => {
+            for child in elements {
+                plain_text(child, text);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A footnote reference run, every block of the content is a paragraph of the note
+fn footnote_run(content: &[Element]) -> Run {
+    let mut footnote = Footnote::new();
+    for element in content {
+        let mut text = String::new();
+        plain_text(element, &mut text);
+        footnote = footnote.add_content(Paragraph::new().add_run(Run::new().add_text(text)));
+    }
+    Run::new().add_footnote_reference(footnote)
+}
+
+/// A run of text in its font, Word sizes are in half points
+fn text_run(text: impl Into<String>, font: &FontSpec) -> Run {
+    let mut run = Run::new()
+        .add_text(text)
+        .size((font.size_pt * 2.0).round() as usize);
+    if let Some(family) = &font.family {
+        run = run.fonts(RunFonts::new().ascii(family).hi_ansi(family).cs(family));
+    }
+    if font.weight.is_some_and(|weight| weight >= 600) {
+        run = run.bold();
+    }
+    run
+}
+
+/// The text runs of a styled span, nested spans add their styles
+fn styled_runs(elements: &[Element], style: TextStyle, runs: &mut Vec<Run>) {
+    for element in elements {
+        let (text, font) = match element {
+            Element::Styled {
+                style: inner,
+                elements,
+            } => {
+                styled_runs(elements, style.combine(*inner), runs);
+                continue;
+            }
+            Element::Text { text, font } => (text.clone(), font.clone()),
+            element => {
+                let mut text = String::new();
+                plain_text(element, &mut text);
+                (text, FontSpec::from(8))
+            }
+        };
+        if text.is_empty() {
+            continue;
+        }
+        let mut run = text_run(text, &font);
+        if style.bold {
+            run = run.bold();
+        }
+        if style.italic {
+            run = run.italic();
+        }
+        if style.underline {
+            run = run.underline("single");
+        }
+        if style.strikethrough {
+            run = run.strike();
+        }
+        if style.superscript {
+            run.run_property = run.run_property.vert_align(VertAlignType::SuperScript);
+        } else if style.subscript {
+            run.run_property = run.run_property.vert_align(VertAlignType::SubScript);
+        }
+        if let Some(color) = style.highlight {
+            run = run.highlight(match color {
+                HighlightColor::Gray => "lightGray".to_string(),
+                color => color.to_string(),
+            });
+        }
+        if let Some(color) = style.color {
+            run = run.color(word_color(color));
+        }
+        if let Some(color) = style.background {
+            run = run.shading(Shading::new().fill(word_color(color)));
+        }
+        runs.push(run);
+    }
+}
+
 //function re_size input picture (if size very big)
 fn re_size_picture(pic: Pic) -> Pic {
     let mut pic = pic;
@@ -44,11 +893,22 @@ fn re_size_picture(pic: Pic) -> Pic {
 }
 
 //recursive function for processing nested elements in Element::List
-fn detect_element_in_list(doc: &mut Docx, element: &Element, numbered: bool, depth: usize) {
+fn detect_element_in_list(
+    doc: &mut Docx,
+    element: &Element,
+    checked: Option<bool>,
+    numbered: bool,
+    depth: usize,
+) {
     match element {
-        Element::Text { text, size } => {
-            let mut paragraph =
-                Paragraph::new().add_run(Run::new().add_text(text).size(*size as usize * 2));
+        Element::Text { text, font } => {
+            // Task list items start with a ballot box
+            let text = &match checked {
+                Some(true) => format!("\u{2611} {}", text),
+                Some(false) => format!("\u{2610} {}", text),
+                None => text.clone(),
+            };
+            let mut paragraph = Paragraph::new().add_run(text_run(text, font));
 
             if numbered {
                 paragraph = paragraph.numbering(NumberingId::new(2), IndentLevel::new(depth));
@@ -56,13 +916,12 @@ fn detect_element_in_list(doc: &mut Docx, element: &Element, numbered: bool, dep
                 // Add the "-" character at the beginning of the text, taking into account the nesting level
                 let indent = " ".repeat(depth * 4); // 4 spaces for each nesting level
                 let modified_text = format!("{}- {}", indent, text);
-                paragraph = Paragraph::new()
-                    .add_run(Run::new().add_text(modified_text).size(*size as usize * 2));
+                paragraph = Paragraph::new().add_run(text_run(modified_text, font));
             }
             *doc = doc.clone().add_paragraph(paragraph);
         }
 
-        Element::Header { level, text } => {
+        Element::Header { level, text, .. } => {
             let size = match level {
                 1 => 18,
                 2 => 16,
@@ -84,7 +943,7 @@ fn detect_element_in_list(doc: &mut Docx, element: &Element, numbered: bool, dep
             size,
         } => {
             let mut hyperlink_paragraph =
-                Paragraph::new().add_run(Run::new().add_text(title).size(*size as usize * 2));
+                Paragraph::new().add_run(text_run(title, &FontSpec::from(*size)));
 
             if numbered {
                 hyperlink_paragraph =
@@ -92,12 +951,11 @@ fn detect_element_in_list(doc: &mut Docx, element: &Element, numbered: bool, dep
             } else {
                 let indent = " ".repeat(depth * 4);
                 let modified_title = format!("{}- {}", indent, title);
-                hyperlink_paragraph = Paragraph::new()
-                    .add_run(Run::new().add_text(modified_title).size(*size as usize * 2));
+                hyperlink_paragraph =
+                    Paragraph::new().add_run(text_run(modified_title, &FontSpec::from(*size)));
             }
 
-            let hyperlink = Hyperlink::new(url, HyperlinkType::External)
-                .add_run(Run::new().add_text(url).size(*size as usize * 2));
+            let hyperlink = hyperlink(url).add_run(text_run(url, &FontSpec::from(*size)));
 
             *doc = doc
                 .clone()
@@ -106,7 +964,13 @@ fn detect_element_in_list(doc: &mut Docx, element: &Element, numbered: bool, dep
 
         Element::List { elements, numbered } => {
             for list_item in elements {
-                detect_element_in_list(doc, &list_item.element, *numbered, depth + 1);
+                detect_element_in_list(
+                    doc,
+                    &list_item.element,
+                    list_item.checked,
+                    *numbered,
+                    depth + 1,
+                );
             }
         }
 
@@ -138,6 +1002,71 @@ impl TransformerTrait for Transformer {
             return result;
         }
 
+        /// A table, tables in its cells are nested in them
+        fn parse_table(table: &docx_rs::Table) -> Element {
+            let mut rows: Vec<TableRow> = vec![];
+            // Grid column of each cell still open for vertical merging
+            let mut merge_origins: Vec<(usize, usize, usize)> = vec![];
+            for row in &table.rows {
+                let docx_rs::TableChild::TableRow(tr) = row;
+                let mut cells = TableRow { cells: vec![] };
+                let mut column = 0;
+
+                for table_cell in &tr.cells {
+                    let TableRowChild::TableCell(tc) = table_cell;
+                    let (colspan, merge) = cell_span(&tc.property);
+                    if merge.as_deref() == Some("continue") {
+                        let origin = merge_origins
+                            .iter()
+                            .find(|(origin_column, ..)| *origin_column == column);
+                        if let Some((_, row_index, cell_index)) = origin {
+                            rows[*row_index].cells[*cell_index].rowspan += 1;
+                            column += colspan;
+                            continue;
+                        }
+                    }
+                    merge_origins.retain(|(origin_column, ..)| *origin_column != column);
+                    if merge.as_deref() == Some("restart") {
+                        merge_origins.push((column, rows.len(), cells.cells.len()));
+                    }
+
+                    // Every paragraph and table of the cell is a block of its own
+                    let mut elements: Vec<Element> = tc
+                        .children
+                        .iter()
+                        .filter_map(|ch| match ch {
+                            docx_rs::TableCellContent::Paragraph(par) => Some(Element::Text {
+                                text: extract_text(par),
+                                font: FontSpec::from(16),
+                            }),
+                            docx_rs::TableCellContent::Table(table) => Some(parse_table(table)),
+                            _ => None,
+                        })
+                        .collect();
+                    // The paragraph a cell has to end with after a table is no content
+                    if let [.., Element::Table { .. }, Element::Text { text, .. }] = &elements[..] {
+                        if text.is_empty() {
+                            elements.pop();
+                        }
+                    }
+                    cells.cells.push(TableCell {
+                        elements,
+                        colspan,
+                        rowspan: 1,
+                    });
+                    column += colspan;
+                }
+                rows.push(cells);
+            }
+
+            Element::Table {
+                headers: vec![],
+                rows,
+                alignments: vec![],
+                caption: vec![],
+            }
+        }
+
         let docx = read_docx(document)?;
         const HEADING1: &str = "Heading1";
         const HEADING2: &str = "Heading2";
@@ -149,8 +1078,18 @@ impl TransformerTrait for Transformer {
 
         let mut current_list: Option<(usize, Vec<ListItem>)> = None;
 
+        // Where a caption that may belong to the table after it was read
+        let mut caption_index: Option<usize> = None;
+
+        let bidi = bidi_paragraphs(document)?;
+        // A document with all of its paragraphs right to left is right to left, otherwise the
+        // paragraphs are in that direction of their own
+        let rtl_document = !bidi.is_empty() && bidi.iter().all(|bidi| *bidi);
+        let mut bidi = bidi.into_iter();
+
         for ch in docx.document.children {
             if let docx_rs::DocumentChild::Paragraph(par) = ch {
+                let rtl = bidi.next().unwrap_or_default() && !rtl_document;
                 if let Some(numbering_property) = &par.property.numbering_property {
                     let num_id = numbering_property
                         .id
@@ -163,8 +1102,9 @@ impl TransformerTrait for Transformer {
                         let list_item = ListItem {
                             element: Element::Text {
                                 text: list_text,
-                                size: 12,
+                                font: FontSpec::from(12),
                             },
+                            checked: None,
                         };
 
                         let numbered = numbering_property
@@ -186,6 +1126,7 @@ impl TransformerTrait for Transformer {
                                 };
                                 list_items.push(ListItem {
                                     element: nested_list,
+                                    checked: None,
                                 });
                             } else if level < last_level {
                                 // Finish the current list and start a new one
@@ -213,27 +1154,41 @@ impl TransformerTrait for Transformer {
                             Some(ParagraphStyle { val }) => match val.as_str() {
                                 HEADING1 => {
                                     let text = extract_text(&par);
-                                    let element = Element::Header { level: 1, text };
+                                    let element = Element::Header {
+                                        level: 1,
+                                        text,
+                                        anchor: bookmark(&par),
+                                    };
 
                                     result.push(element);
                                 }
                                 HEADING2 => {
                                     let text = extract_text(&par);
-                                    let element = Element::Header { level: 2, text };
+                                    let element = Element::Header {
+                                        level: 2,
+                                        text,
+                                        anchor: bookmark(&par),
+                                    };
 
                                     result.push(element);
                                 }
 
                                 BODY_TEXT => {
                                     let text = extract_text(&par);
-                                    let element = Element::Text { text, size: 16 };
+                                    let element = Element::Text {
+                                        text,
+                                        font: FontSpec::from(16),
+                                    };
 
                                     result.push(element);
                                 }
 
                                 NORMAL => {
                                     let text = extract_text(&par);
-                                    let element = Element::Text { text, size: 16 };
+                                    let element = Element::Text {
+                                        text,
+                                        font: FontSpec::from(16),
+                                    };
 
                                     result.push(element);
                                 }
@@ -252,41 +1207,162 @@ impl TransformerTrait for Transformer {
                             numbered: is_list_numbered,
                         });
                     }
+                    if par.property.page_break_before == Some(true) {
+                        result.push(Element::PageBreak);
+                    }
                     match &par.property.style {
                         Some(ParagraphStyle { val }) => match val.as_str() {
                             HEADING1 => {
                                 let text = extract_text(&par);
-                                let element = Element::Header { level: 1, text };
+                                let element = Element::Header {
+                                    level: 1,
+                                    text,
+                                    anchor: bookmark(&par),
+                                };
 
                                 result.push(element);
                             }
                             HEADING2 => {
                                 let text = extract_text(&par);
-                                let element = Element::Header { level: 2, text };
+                                let element = Element::Header {
+                                    level: 2,
+                                    text,
+                                    anchor: bookmark(&par),
+                                };
 
                                 result.push(element);
                             }
 
-                            BODY_TEXT => {
-                                let text = extract_text(&par);
-                                let element = Element::Text { text, size: 16 };
+                            // Raised, lowered and highlighted text keeps its runs apart
+                            BODY_TEXT | NORMAL => match formatted_runs(&par) {
+                                Some(elements) if rtl => result.push(rtl_paragraph(elements)),
+                                Some(elements) => result.push(Element::Paragraph { elements }),
+                                None => {
+                                    let text = extract_text(&par);
+                                    let element = Element::Text {
+                                        text,
+                                        font: FontSpec::from(16),
+                                    };
+
+                                    match rtl {
+                                        true => result.push(rtl_paragraph(vec![element])),
+                                        false => result.push(element),
+                                    }
+                                }
+                            },
 
-                                result.push(element);
+                            CODE => result.push(Element::CodeBlock {
+                                language: None,
+                                code: extract_code(&par),
+                            }),
+
+                            // Consecutive definition paragraphs belong to one list
+                            DEFINITION => {
+                                let (term, definition) = extract_definition(&par);
+                                let text = |text: String| Element::Text {
+                                    text,
+                                    font: FontSpec::from(16),
+                                };
+                                let definitions: Vec<Element> = Some(definition)
+                                    .filter(|definition| !definition.is_empty())
+                                    .map(|definition| Element::Paragraph {
+                                        elements: vec![text(definition)],
+                                    })
+                                    .into_iter()
+                                    .collect();
+                                if !matches!(result.last(), Some(Element::DefinitionList { .. })) {
+                                    result.push(Element::DefinitionList { items: vec![] });
+                                }
+                                if let Some(Element::DefinitionList { items }) = result.last_mut() {
+                                    match items.last_mut() {
+                                        Some(item) if term.is_empty() => {
+                                            item.definitions.extend(definitions)
+                                        }
+                                        _ => items.push(DefinitionItem {
+                                            term: vec![text(term)],
+                                            definitions,
+                                        }),
+                                    }
+                                }
                             }
 
-                            NORMAL => {
+                            // Consecutive quote paragraphs belong to one blockquote, a last one
+                            // starting with a dash names the source
+                            QUOTE => {
                                 let text = extract_text(&par);
-                                let element = Element::Text { text, size: 16 };
+                                let source = text.strip_prefix('—').map(str::trim);
+                                let paragraph = Element::Paragraph {
+                                    elements: vec![Element::Text {
+                                        text: text.clone(),
+                                        font: FontSpec::from(16),
+                                    }],
+                                };
+                                match result.last_mut() {
+                                    Some(Element::Blockquote {
+                                        elements,
+                                        attribution,
+                                    }) => match source {
+                                        Some(source) if attribution.is_empty() => {
+                                            attribution.push(Element::Text {
+                                                text: source.to_string(),
+                                                font: FontSpec::from(16),
+                                            })
+                                        }
+                                        _ => elements.push(paragraph),
+                                    },
+                                    _ => result.push(Element::Blockquote {
+                                        elements: vec![paragraph],
+                                        attribution: vec![],
+                                    }),
+                                }
+                            }
 
-                                result.push(element);
+                            // A caption right after an image makes it a figure, tables have
+                            // theirs before or after them
+                            CAPTION => {
+                                let caption = vec![Element::Text {
+                                    text: extract_text(&par),
+                                    font: FontSpec::from(16),
+                                }];
+                                match result.pop() {
+                                    Some(Element::Image(image)) => {
+                                        result.push(Element::Figure { image, caption })
+                                    }
+                                    Some(Element::Table {
+                                        headers,
+                                        rows,
+                                        alignments,
+                                        caption: table_caption,
+                                    }) if table_caption.is_empty() => result.push(Element::Table {
+                                        headers,
+                                        rows,
+                                        alignments,
+                                        caption,
+                                    }),
+                                    last => {
+                                        result.extend(last);
+                                        result.push(Element::Paragraph { elements: caption });
+                                        caption_index = Some(result.len() - 1);
+                                    }
+                                }
                             }
 
                             _ => {}
                         },
+                        None if par.property.borders.is_some() && par.children.is_empty() => {
+                            result.push(Element::HorizontalRule)
+                        }
                         _ => {
-                            // TODO: Implement other styles
+                            if let Some(image) = paragraph_image(&par, &docx.images) {
+                                result.push(Element::Image(image));
+                            }
                         }
                     }
+                    // Review comments follow the paragraph they are anchored in
+                    result.extend(paragraph_comments(&par));
+                    if has_page_break(&par) {
+                        result.push(Element::PageBreak);
+                    }
                 }
             } else {
                 if let Some((_, list_items)) = current_list.take() {
@@ -296,33 +1372,24 @@ impl TransformerTrait for Transformer {
                     });
                 }
                 match ch {
+                    // The entries of a table of contents are generated from the headers
+                    docx_rs::DocumentChild::StructuredDataTag(tag) => {
+                        if let Some(max_level) = toc_level(&tag) {
+                            result.push(Element::TableOfContents { max_level });
+                        }
+                    }
                     docx_rs::DocumentChild::Table(table) => {
-                        let mut rows = vec![];
-                        for row in &table.rows {
-                            let docx_rs::TableChild::TableRow(tr) = row;
-                            let mut cells = TableRow { cells: vec![] };
-
-                            for table_cell in &tr.cells {
-                                let TableRowChild::TableCell(tc) = table_cell;
-                                for ch in &tc.children {
-                                    match ch {
-                                        docx_rs::TableCellContent::Paragraph(par) => {
-                                            let text = extract_text(par);
-                                            cells.cells.push(TableCell {
-                                                element: Element::Text { text, size: 16 },
-                                            });
-                                        }
-                                        _ => {}
-                                    }
-                                }
+                        let mut table = parse_table(&table);
+                        if caption_index.is_some_and(|index| index + 1 == result.len()) {
+                            if let (
+                                Some(Element::Paragraph { elements }),
+                                Element::Table { caption, .. },
+                            ) = (result.pop(), &mut table)
+                            {
+                                *caption = elements;
                             }
-                            rows.push(cells);
                         }
-
-                        result.push(Element::Table {
-                            headers: vec![],
-                            rows,
-                        });
+                        result.push(table);
                     }
                     _ => {}
                 }
@@ -335,11 +1402,21 @@ impl TransformerTrait for Transformer {
                 numbered: is_list_numbered,
             });
         }
+        // docx-rs does not read charts, they follow the content
+        result.extend(crate::chart::package_charts(document, "word/charts/")?);
 
-        Ok(Document::new(result))
+        let mut parsed = Document::new(result);
+        if rtl_document {
+            parsed.direction = TextDirection::Rtl;
+        }
+        // docx-rs does not read the core properties
+        parsed.metadata = crate::properties::read_metadata(document)?;
+        Ok(parsed)
     }
 
     fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        // Word gives the nesting with the heading styles, the body is flat
+        let document = &document.flatten_sections();
         let mut doc = Docx::new();
 
         // region:    ---abstract_numbering
@@ -381,37 +1458,189 @@ impl TransformerTrait for Transformer {
 
         doc = doc
             .add_abstract_numbering(abstract_numbering)
-            .add_numbering(Numbering::new(2, 2));
+            .add_numbering(Numbering::new(2, 2))
+            .add_style(
+                Style::new(CODE, StyleType::Paragraph)
+                    .name(CODE)
+                    .fonts(RunFonts::new().ascii("Courier New").hi_ansi("Courier New")),
+            )
+            .add_style(
+                Style::new(QUOTE, StyleType::Paragraph)
+                    .name(QUOTE)
+                    .italic()
+                    .indent(Some(720), None, Some(720), None),
+            )
+            .add_style(
+                Style::new(DEFINITION, StyleType::Paragraph)
+                    .name(DEFINITION)
+                    .indent(Some(720), Some(SpecialIndentType::Hanging(720)), None, None),
+            )
+            .add_style(
+                Style::new(CAPTION, StyleType::Paragraph)
+                    .name("caption")
+                    .italic(),
+            );
+        let mut footnote_reference =
+            Style::new(FOOTNOTE_REFERENCE, StyleType::Character).name("footnote reference");
+        footnote_reference.run_property = RunProperty::new().vert_align(VertAlignType::SuperScript);
+        doc = doc.add_style(footnote_reference);
+        let footnotes = document.footnotes();
+        let mut comments = 0;
+        let mut bookmarks = 0;
 
         // TODO: Consider to refactor this code to use the new #Band Enum (header, footer, etc)
         for element in &document.get_all_elements() {
+            let start = doc.document.children.len();
             match element {
-                Element::Header { level, text } => {
+                Element::Header {
+                    level,
+                    text,
+                    anchor,
+                } => {
                     let size = match level {
                         1 => 18,
                         2 => 16,
                         _ => 14,
                     };
-                    doc = doc.add_paragraph(
-                        Paragraph::new().add_run(Run::new().add_text(text).size(size * 2)),
-                    );
+                    let mut paragraph =
+                        Paragraph::new().outline_lvl((*level as usize).saturating_sub(1));
+                    // The bookmark spans the header text, links to the anchor point to it
+                    if let Some(anchor) = anchor {
+                        bookmarks += 1;
+                        paragraph = paragraph.add_bookmark_start(bookmarks, anchor);
+                    }
+                    paragraph = paragraph.add_run(Run::new().add_text(text).size(size * 2));
+                    if anchor.is_some() {
+                        paragraph = paragraph.add_bookmark_end(bookmarks);
+                    }
+                    doc = doc.add_paragraph(paragraph);
                 }
 
-                Element::Text { text, size } => {
-                    doc = doc.add_paragraph(
-                        Paragraph::new()
-                            .add_run(Run::new().add_text(text).size(*size as usize * 2)),
-                    )
+                Element::Text { text, font } => {
+                    doc = doc.add_paragraph(Paragraph::new().add_run(text_run(text, font)))
+                }
+
+                Element::Ruby { .. } => {
+                    let mut text = String::new();
+                    plain_text(element, &mut text);
+                    doc = doc
+                        .add_paragraph(Paragraph::new().add_run(text_run(text, &FontSpec::from(8))))
                 }
 
                 Element::Paragraph { elements } => {
+                    let mut started = false;
+                    // Text after a line break or in a font of its own stays in the paragraph
+                    // of the text before it
+                    let mut broken = false;
                     for paragraph_element in elements {
                         match paragraph_element {
-                            Element::Text { text, size } => {
-                                doc =
-                                    doc.add_paragraph(Paragraph::new().add_run(
-                                        Run::new().add_text(text).size(*size as usize * 2),
-                                    ));
+                            Element::Text { text, font } => {
+                                let run = text_run(text, font);
+                                let joined =
+                                    std::mem::take(&mut broken) || (started && font.is_explicit());
+                                match doc.document.children.last_mut() {
+                                    Some(DocumentChild::Paragraph(paragraph)) if joined => {
+                                        paragraph.children.push(ParagraphChild::Run(Box::new(run)))
+                                    }
+                                    _ => doc = doc.add_paragraph(Paragraph::new().add_run(run)),
+                                }
+                                started = true;
+                            }
+                            // The reference follows the text before it
+                            Element::FootnoteReference { id } => {
+                                let run = footnote_run(
+                                    footnotes.get(id.as_str()).copied().unwrap_or_default(),
+                                );
+                                match doc.document.children.last_mut() {
+                                    Some(DocumentChild::Paragraph(paragraph)) if started => {
+                                        paragraph.children.push(ParagraphChild::Run(Box::new(run)))
+                                    }
+                                    _ => doc = doc.add_paragraph(Paragraph::new().add_run(run)),
+                                }
+                                started = true;
+                            }
+                            // Emphasis, formulas and ruby continue the text before them
+                            Element::Styled { .. }
+                            | Element::Math { .. }
+                            | Element::Ruby { .. } => {
+                                let mut runs = Vec::new();
+                                match paragraph_element {
+                                    Element::Styled { style, elements } => {
+                                        styled_runs(elements, *style, &mut runs)
+                                    }
+                                    Element::Math { tex, .. } => {
+                                        runs.push(Run::new().add_text(tex))
+                                    }
+                                    // Word has ruby but docx-rs does not write it
+                                    Element::Ruby { .. } => styled_runs(
+                                        &[paragraph_element.ruby_fallback()],
+                                        TextStyle::default(),
+                                        &mut runs,
+                                    ),
+                                    _ => {}
+                                }
+                                match doc.document.children.last_mut() {
+                                    Some(DocumentChild::Paragraph(paragraph)) if started => {
+                                        for run in runs {
+                                            paragraph
+                                                .children
+                                                .push(ParagraphChild::Run(Box::new(run)));
+                                        }
+                                    }
+                                    _ => {
+                                        let mut paragraph = Paragraph::new();
+                                        for run in runs {
+                                            paragraph = paragraph.add_run(run);
+                                        }
+                                        doc = doc.add_paragraph(paragraph);
+                                    }
+                                }
+                                started = true;
+                            }
+                            // Links continue the text before them, the title is the link text
+                            Element::Hyperlink {
+                                title, url, size, ..
+                            } => {
+                                let link =
+                                    hyperlink(url).add_run(text_run(title, &FontSpec::from(*size)));
+                                match doc.document.children.last_mut() {
+                                    Some(DocumentChild::Paragraph(paragraph)) if started => {
+                                        paragraph.children.push(ParagraphChild::Hyperlink(link))
+                                    }
+                                    _ => {
+                                        doc =
+                                            doc.add_paragraph(Paragraph::new().add_hyperlink(link))
+                                    }
+                                }
+                                started = true;
+                            }
+                            Element::LineBreak => {
+                                let run = Run::new().add_break(BreakType::TextWrapping);
+                                match doc.document.children.last_mut() {
+                                    Some(DocumentChild::Paragraph(paragraph)) if started => {
+                                        paragraph.children.push(ParagraphChild::Run(Box::new(run)))
+                                    }
+                                    _ => doc = doc.add_paragraph(Paragraph::new().add_run(run)),
+                                }
+                                started = true;
+                                broken = true;
+                            }
+                            // Comments are anchored at the text before them
+                            Element::Comment {
+                                author, date, text, ..
+                            } => {
+                                comments += 1;
+                                match doc.document.children.last_mut() {
+                                    Some(DocumentChild::Paragraph(paragraph)) if started => {
+                                        add_comment(paragraph, comments, author, date, text)
+                                    }
+                                    _ => {
+                                        let mut paragraph = Paragraph::new();
+                                        add_comment(&mut paragraph, comments, author, date, text);
+                                        doc = doc.add_paragraph(paragraph);
+                                    }
+                                }
+                                started = true;
                             }
                             _ => {
                                 error!("Unknown paragraph element");
@@ -420,9 +1649,105 @@ impl TransformerTrait for Transformer {
                     }
                 }
 
+                Element::LineBreak | Element::Section { .. } | Element::Custom { .. } => {}
+
+                Element::HorizontalRule => doc = doc.add_paragraph(rule_paragraph()),
+
+                Element::PageBreak => {
+                    doc = doc.add_paragraph(
+                        Paragraph::new().add_run(Run::new().add_break(BreakType::Page)),
+                    )
+                }
+
+                // Word fills in the page numbers when it updates the field
+                Element::TableOfContents { max_level } => {
+                    let mut toc = docx_rs::TableOfContents::new()
+                        .heading_styles_range(1, *max_level as usize)
+                        .hyperlink()
+                        .dirty();
+                    // Headers have no heading style, their outline level puts them in the table
+                    toc.instr = toc.instr.use_applied_paragraph_line_level();
+                    toc_items(&document.toc_entries(*max_level), &mut toc);
+                    doc = doc.add_table_of_contents(toc);
+                }
+
+                Element::Comment {
+                    author, date, text, ..
+                } => {
+                    comments += 1;
+                    match doc.document.children.last_mut() {
+                        Some(DocumentChild::Paragraph(paragraph)) => {
+                            add_comment(paragraph, comments, author, date, text)
+                        }
+                        _ => {
+                            let mut paragraph = Paragraph::new();
+                            add_comment(&mut paragraph, comments, author, date, text);
+                            doc = doc.add_paragraph(paragraph);
+                        }
+                    }
+                }
+
+                Element::Styled { style, elements } => {
+                    let mut runs = Vec::new();
+                    styled_runs(elements, *style, &mut runs);
+                    let mut paragraph = Paragraph::new();
+                    for run in runs {
+                        paragraph = paragraph.add_run(run);
+                    }
+                    doc = doc.add_paragraph(paragraph);
+                }
+
+                // Word equations are not generated, the formula keeps its TeX source
+                Element::Math { tex, .. } => {
+                    doc = doc.add_paragraph(Paragraph::new().add_run(Run::new().add_text(tex)));
+                }
+
+                // The content is written with the reference
+                Element::Footnote { .. } | Element::FootnoteReference { .. } => {}
+
+                Element::CodeBlock { code, .. } => doc = doc.add_paragraph(code_paragraph(code)),
+
+                Element::Blockquote {
+                    elements,
+                    attribution,
+                } => {
+                    let mut paragraphs = Vec::new();
+                    quote_paragraphs(elements, &mut paragraphs);
+                    // The source closes the quote, set right after a dash
+                    if !attribution.is_empty() {
+                        let mut text = String::from("— ");
+                        for child in attribution {
+                            plain_text(child, &mut text);
+                        }
+                        paragraphs.push(
+                            Paragraph::new()
+                                .style(QUOTE)
+                                .align(AlignmentType::Right)
+                                .add_run(Run::new().add_text(text)),
+                        );
+                    }
+                    for paragraph in paragraphs {
+                        doc = doc.add_paragraph(paragraph);
+                    }
+                }
+
+                Element::DefinitionList { items } => {
+                    let mut paragraphs = Vec::new();
+                    definition_paragraphs(items, &mut paragraphs);
+                    for paragraph in paragraphs {
+                        doc = doc.add_paragraph(paragraph);
+                    }
+                }
+
                 Element::List { elements, numbered } => {
                     for list_item in elements {
-                        detect_element_in_list(&mut doc, &list_item.element, *numbered, 0);
+                        detect_element_in_list(
+                            &mut doc,
+                            &list_item.element,
+                            list_item.checked,
+                            *numbered,
+                            0,
+                        );
                     }
                 }
 
@@ -433,75 +1758,98 @@ impl TransformerTrait for Transformer {
                     size,
                 } => {
                     let _ = alt;
-                    let hyperlink = Hyperlink::new(url, HyperlinkType::External)
-                        .add_run(Run::new().add_text(url).size(*size as usize * 2));
-                    let paragraph = Paragraph::new()
-                        .add_run(Run::new().add_text(title).size(*size as usize * 2));
+                    let hyperlink = hyperlink(url).add_run(text_run(url, &FontSpec::from(*size)));
+                    let paragraph =
+                        Paragraph::new().add_run(text_run(title, &FontSpec::from(*size)));
 
                     doc = doc.add_paragraph(Paragraph::add_hyperlink(paragraph, hyperlink));
                 }
 
                 Element::Image(image) => {
-                    let mut pic = Pic::new(&image.bytes());
-
-                    match &image.size() {
-                        &ImageDimension {
-                            width: Some(width),
-                            height: Some(height),
-                        } => {
-                            let width = width.parse().unwrap_or(0);
-                            let height = height.parse().unwrap_or(0);
-                            if width > 0 && height > 0 {
-                                pic = pic.size(width, height);
-                            }
-                        }
-                        _ => {}
-                    }
-
-                    pic = re_size_picture(pic);
-
-                    let paragraph = Paragraph::new().add_run(Run::new().add_image(pic));
+                    let paragraph = Paragraph::new().add_run(Run::new().add_image(picture(image)));
 
                     doc = doc.add_paragraph(paragraph);
                 }
 
-                Element::Table { headers, rows } => {
-                    let mut table_rows = Vec::new();
+                // The caption paragraph follows the picture
+                Element::Figure { image, caption } => {
+                    let paragraph = Paragraph::new().add_run(Run::new().add_image(picture(image)));
+                    doc = doc.add_paragraph(paragraph);
 
-                    if !headers.is_empty() {
-                        let mut header_cell: Vec<docx_rs::TableCell> = Vec::new();
-                        for header in headers {
-                            if let Element::Text { text, size } = &header.element {
-                                let cell = docx_rs::TableCell::new().add_paragraph(
-                                    Paragraph::new().add_run(
-                                        Run::new().add_text(text).size(*size as usize * 2),
-                                    ),
-                                );
-                                header_cell.push(cell);
-                            }
-                        }
-                        let header_row = docx_rs::TableRow::new(header_cell);
-                        table_rows.push(header_row)
+                    let mut text = String::new();
+                    for child in caption {
+                        plain_text(child, &mut text);
                     }
+                    doc = doc.add_paragraph(
+                        Paragraph::new()
+                            .style(CAPTION)
+                            .add_run(Run::new().add_text(text)),
+                    );
+                }
 
-                    for row in rows {
-                        let mut rows_cell = Vec::new();
+                // Word sets table captions above the table
+                Element::Table {
+                    headers,
+                    rows,
+                    alignments,
+                    caption,
+                } => {
+                    if !caption.is_empty() {
+                        let mut text = String::new();
+                        for child in caption {
+                            plain_text(child, &mut text);
+                        }
+                        doc = doc.add_paragraph(
+                            Paragraph::new()
+                                .style(CAPTION)
+                                .add_run(Run::new().add_text(text)),
+                        );
+                    }
+                    doc = doc.add_table(docx_table(headers, rows, alignments))
+                }
 
-                        for cell in &row.cells {
-                            if let Element::Text { text, size } = &cell.element {
-                                let table_cell = docx_rs::TableCell::new().add_paragraph(
-                                    Paragraph::new().add_run(
-                                        Run::new().add_text(text).size(*size as usize * 2),
-                                    ),
-                                );
-                                rows_cell.push(table_cell);
-                            }
+                // Word has no player, the poster is followed by a link to the source
+                Element::Media {
+                    kind,
+                    source,
+                    poster,
+                } => {
+                    if let Some(poster) = poster {
+                        let run = Run::new().add_image(picture(poster));
+                        doc = doc.add_paragraph(Paragraph::new().add_run(run));
+                    }
+                    let font = FontSpec::from(8);
+                    let paragraph = match source {
+                        MediaSource::Url(url) => Paragraph::new()
+                            .add_hyperlink(hyperlink(url).add_run(text_run(url, &font))),
+                        MediaSource::Embedded { .. } => {
+                            Paragraph::new().add_run(text_run(format!("[{}]", kind), &font))
                         }
-                        let table_row = docx_rs::TableRow::new(rows_cell);
-                        table_rows.push(table_row);
+                    };
+                    doc = doc.add_paragraph(paragraph);
+                }
+
+                // docx-rs writes no charts, the data goes in a table
+                Element::Chart { .. } => {
+                    if let Element::Table {
+                        headers,
+                        rows,
+                        alignments,
+                        ..
+                    } = element.chart_fallback()
+                    {
+                        doc = doc.add_table(docx_table(&headers, &rows, &alignments));
+                    }
+                }
+            }
+            // The paragraphs of the element are right to left in a right to left document or
+            // when the element is a paragraph in that direction of its own
+            let direction = element.paragraph_direction().unwrap_or(document.direction);
+            if direction == TextDirection::Rtl {
+                for child in &mut doc.document.children[start..] {
+                    if let DocumentChild::Paragraph(paragraph) = child {
+                        paragraph.property = paragraph.property.clone().bidi(true);
                     }
-                    let table = docx_rs::Table::new(table_rows);
-                    doc = doc.add_table(table);
                 }
             }
         }
@@ -509,8 +1857,20 @@ impl TransformerTrait for Transformer {
         let buffer = Vec::new();
         let mut cursor = Cursor::new(buffer);
 
+        for (name, value) in &document.metadata.custom {
+            doc = doc.custom_property(name, value);
+        }
+
         doc.build().pack(&mut cursor)?;
-        let buffer = cursor.into_inner();
+        let mut buffer = cursor.into_inner();
+        // docx-rs only writes the dates of the core properties
+        if !document.metadata.is_empty() {
+            buffer = crate::properties::replace_part(
+                &buffer,
+                crate::properties::CORE_PATH,
+                &crate::properties::core_xml(&document.metadata),
+            )?;
+        }
 
         Ok(bytes::Bytes::from(buffer))
     }
@@ -520,7 +1880,9 @@ impl TransformerTrait for Transformer {
 mod tests {
     use super::*;
     use crate::core::tests::init_logger;
-    use crate::core::{disk_image_loader, TransformerWithImageLoaderSaverTrait};
+    use crate::core::{
+        disk_image_loader, MediaKind, Metadata, TransformerWithImageLoaderSaverTrait,
+    };
     use crate::{docx, markdown};
     use bytes::Bytes;
     use log::info;
@@ -553,22 +1915,543 @@ mod tests {
         let parsed = docx::Transformer::parse(&documents_bytes)?;
 
         info!("Parsed - {:#?}", parsed);
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(16),
+        };
+        // The placeholder is shaded yellow
         let elements = vec![
-            Element::Text {
-                text: "Warszawa, dnia {{DATA}} r. ".to_string(),
-                size: 16,
+            Element::Paragraph {
+                elements: vec![
+                    text("Warszawa, dnia "),
+                    Element::Styled {
+                        style: TextStyle {
+                            background: Some(Color::new(255, 255, 0)),
+                            ..Default::default()
+                        },
+                        elements: vec![text("{{DATA}}")],
+                    },
+                    text(" r. "),
+                ],
             },
             Element::Header {
                 level: 1,
                 text: "Header 1.".to_string(),
+                anchor: None,
             },
             Element::Text {
                 text: "".to_string(),
-                size: 16,
+                font: FontSpec::from(16),
             },
         ];
-        let expected_result = Document::new(elements);
+        let mut expected_result = Document::new(elements);
+        expected_result.metadata = Metadata {
+            language: Some("en-US".to_string()),
+            created: Some("2024-04-29T12:11:32Z".to_string()),
+            modified: Some("2024-07-08T16:24:59Z".to_string()),
+            ..Metadata::default()
+        };
         assert_eq!(expected_result, parsed);
         Ok(())
     }
+    #[test]
+    fn test_code_block() -> anyhow::Result<()> {
+        let code = Element::CodeBlock {
+            language: Some("rust".to_string()),
+            code: "fn main() {\n\tprintln!(\"hi\");\n}".to_string(),
+        };
+        let generated = docx::Transformer::generate(&Document::new(vec![code]))?;
+        let parsed = docx::Transformer::parse(&generated)?;
+        // Word has no language tag, the code itself survives
+        assert_eq!(
+            parsed.get_all_elements(),
+            vec![&Element::CodeBlock {
+                language: None,
+                code: "fn main() {\n\tprintln!(\"hi\");\n}".to_string(),
+            }]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_blockquote() -> anyhow::Result<()> {
+        let paragraph = |text: &str| Element::Paragraph {
+            elements: vec![Element::Text {
+                text: text.to_string(),
+                font: FontSpec::from(16),
+            }],
+        };
+        let quote = Element::Blockquote {
+            elements: vec![paragraph("First"), paragraph("Second")],
+            attribution: vec![],
+        };
+        let generated = docx::Transformer::generate(&Document::new(vec![quote.clone()]))?;
+        let parsed = docx::Transformer::parse(&generated)?;
+        // Both quote paragraphs are read back into one blockquote
+        assert_eq!(parsed.get_all_elements(), vec![&quote]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merged_cells() -> anyhow::Result<()> {
+        let cell = |text: &str, colspan, rowspan| TableCell {
+            elements: vec![Element::Text {
+                text: text.to_string(),
+                font: FontSpec::from(16),
+            }],
+            colspan,
+            rowspan,
+        };
+        let table = Element::Table {
+            headers: vec![],
+            rows: vec![
+                TableRow {
+                    cells: vec![cell("Name", 2, 1), cell("Notes", 1, 2)],
+                },
+                TableRow {
+                    cells: vec![cell("First", 1, 1), cell("Last", 1, 1)],
+                },
+            ],
+            alignments: vec![],
+            caption: vec![],
+        };
+        let generated = docx::Transformer::generate(&Document::new(vec![table.clone()]))?;
+        let parsed = docx::Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_all_elements(), vec![&table]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_caption() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(16),
+        };
+        let table = Element::Table {
+            headers: vec![],
+            rows: vec![TableRow {
+                cells: vec![TableCell::new(text("Q1")), TableCell::new(text("12"))],
+            }],
+            alignments: vec![],
+            caption: vec![text("Table 3: Quarterly results")],
+        };
+        let quote = Element::Blockquote {
+            elements: vec![Element::Paragraph {
+                elements: vec![text("Brevity is the soul of wit.")],
+            }],
+            attribution: vec![text("Shakespeare")],
+        };
+        let document = Document::new(vec![table.clone(), quote.clone()]);
+        let parsed = docx::Transformer::parse(&docx::Transformer::generate(&document)?)?;
+        assert_eq!(parsed.get_all_elements(), vec![&table, &quote]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_table() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(16),
+        };
+        let inner = Element::Table {
+            headers: vec![],
+            rows: vec![TableRow {
+                cells: vec![TableCell::new(text("Widget")), TableCell::new(text("4"))],
+            }],
+            alignments: vec![],
+            caption: vec![],
+        };
+        let table = Element::Table {
+            headers: vec![],
+            rows: vec![TableRow {
+                cells: vec![
+                    TableCell::new(text("Items")),
+                    TableCell::with_elements(vec![text("Ordered"), inner]),
+                ],
+            }],
+            alignments: vec![],
+            caption: vec![],
+        };
+        let generated = docx::Transformer::generate(&Document::new(vec![table.clone()]))?;
+        let parsed = docx::Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_all_elements(), vec![&table]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rich_cell() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(16),
+        };
+        let table = Element::Table {
+            headers: vec![],
+            rows: vec![TableRow {
+                cells: vec![TableCell::with_elements(vec![
+                    text("First"),
+                    text("Second"),
+                ])],
+            }],
+            alignments: vec![],
+            caption: vec![],
+        };
+        let generated = docx::Transformer::generate(&Document::new(vec![table.clone()]))?;
+        let parsed = docx::Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_all_elements(), vec![&table]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_figure() -> anyhow::Result<()> {
+        let image = ImageData::new(
+            Bytes::from(std::fs::read("test/data/small.png")?),
+            String::new(),
+            String::new(),
+            "png".to_string(),
+            String::new(),
+            ImageDimension::default(),
+        );
+        let caption = vec![Element::Text {
+            text: "Sales by quarter".to_string(),
+            font: FontSpec::from(16),
+        }];
+        let document = Document::new(vec![Element::Figure {
+            image,
+            caption: caption.clone(),
+        }]);
+        let generated = docx::Transformer::generate(&document)?;
+        let parsed = docx::Transformer::parse(&generated)?;
+        let [Element::Figure {
+            image,
+            caption: parsed_caption,
+        }] = parsed.get_all_elements()[..]
+        else {
+            panic!("expected a figure, got {:?}", parsed.get_all_elements());
+        };
+        assert!(!image.bytes().is_empty());
+        assert_eq!(parsed_caption, &caption);
+        Ok(())
+    }
+
+    #[test]
+    fn test_footnote() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(16),
+        };
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![
+                    text("Claim"),
+                    Element::FootnoteReference {
+                        id: "1".to_string(),
+                    },
+                ],
+            },
+            Element::Footnote {
+                id: "1".to_string(),
+                content: vec![Element::Paragraph {
+                    elements: vec![text("Source of the claim")],
+                }],
+            },
+        ]);
+        let generated = docx::Transformer::generate(&document)?;
+        // The reader skips footnotes, the mark is found by its run style
+        let docx = read_docx(&generated)?;
+        let [DocumentChild::Paragraph(paragraph)] = docx.document.children.as_slice() else {
+            panic!(
+                "expected a single paragraph, got {:?}",
+                docx.document.children
+            );
+        };
+        let marks = paragraph
+            .children
+            .iter()
+            .filter(|child| match child {
+                ParagraphChild::Run(run) => run
+                    .run_property
+                    .style
+                    .as_ref()
+                    .is_some_and(|style| style.val == FOOTNOTE_REFERENCE),
+                _ => false,
+            })
+            .count();
+        assert_eq!(marks, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_formatted_runs() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(16),
+        };
+        let elements = vec![
+            text("x"),
+            Element::Styled {
+                style: TextStyle::SUPERSCRIPT,
+                elements: vec![text("2")],
+            },
+            Element::Styled {
+                style: TextStyle {
+                    highlight: Some(HighlightColor::Gray),
+                    ..Default::default()
+                },
+                elements: vec![text(" marked")],
+            },
+            Element::Styled {
+                style: TextStyle {
+                    color: Some(Color::new(192, 0, 0)),
+                    ..Default::default()
+                },
+                elements: vec![text(" red")],
+            },
+        ];
+        let document = Document::new(vec![Element::Paragraph {
+            elements: elements.clone(),
+        }]);
+        let generated = docx::Transformer::generate(&document)?;
+        let docx = read_docx(&generated)?;
+        let [DocumentChild::Paragraph(paragraph)] = docx.document.children.as_slice() else {
+            panic!(
+                "expected a single paragraph, got {:?}",
+                docx.document.children
+            );
+        };
+        assert_eq!(formatted_runs(paragraph), Some(elements));
+        Ok(())
+    }
+
+    #[test]
+    fn test_internal_link() -> anyhow::Result<()> {
+        let link = vec![
+            Element::Text {
+                text: "See ".to_string(),
+                font: FontSpec::from(16),
+            },
+            Element::Hyperlink {
+                title: "the introduction".to_string(),
+                url: "#intro".to_string(),
+                alt: String::new(),
+                size: 16,
+            },
+        ];
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Introduction".to_string(),
+                anchor: Some("intro".to_string()),
+            },
+            Element::Paragraph {
+                elements: link.clone(),
+            },
+        ]);
+        let generated = docx::Transformer::generate(&document)?;
+        let docx = read_docx(&generated)?;
+        let [DocumentChild::Paragraph(header), DocumentChild::Paragraph(paragraph)] =
+            docx.document.children.as_slice()
+        else {
+            panic!("expected two paragraphs, got {:?}", docx.document.children);
+        };
+        assert_eq!(bookmark(header).as_deref(), Some("intro"));
+        assert_eq!(formatted_runs(paragraph), Some(link));
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_break() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(16),
+        };
+        let address = vec![
+            text("221B Baker Street"),
+            Element::LineBreak,
+            text("London"),
+        ];
+        let document = Document::new(vec![Element::Paragraph {
+            elements: address.clone(),
+        }]);
+        let generated = docx::Transformer::generate(&document)?;
+        let docx = read_docx(&generated)?;
+        let [DocumentChild::Paragraph(paragraph)] = docx.document.children.as_slice() else {
+            panic!("expected one paragraph, got {:?}", docx.document.children);
+        };
+        assert_eq!(formatted_runs(paragraph), Some(address));
+        Ok(())
+    }
+
+    #[test]
+    fn test_font() -> anyhow::Result<()> {
+        let font = FontSpec {
+            family: Some("Georgia".to_string()),
+            size_pt: 10.5,
+            weight: Some(700),
+        };
+        let elements = vec![
+            Element::Text {
+                text: "Amount ".to_string(),
+                font: FontSpec::from(16),
+            },
+            Element::Text {
+                text: "due".to_string(),
+                font,
+            },
+        ];
+        let document = Document::new(vec![Element::Paragraph {
+            elements: elements.clone(),
+        }]);
+        let generated = docx::Transformer::generate(&document)?;
+        let docx = read_docx(&generated)?;
+        let [DocumentChild::Paragraph(paragraph)] = docx.document.children.as_slice() else {
+            panic!("expected one paragraph, got {:?}", docx.document.children);
+        };
+        assert_eq!(formatted_runs(paragraph), Some(elements));
+        Ok(())
+    }
+
+    #[test]
+    fn test_media() -> anyhow::Result<()> {
+        let url = "https://example.com/clip.mp4";
+        let document = Document::new(vec![Element::Media {
+            kind: MediaKind::Video,
+            source: MediaSource::Url(url.to_string()),
+            poster: None,
+        }]);
+        let generated = docx::Transformer::generate(&document)?;
+        let docx = read_docx(&generated)?;
+        let [DocumentChild::Paragraph(paragraph)] = docx.document.children.as_slice() else {
+            panic!(
+                "expected a link paragraph, got {:?}",
+                docx.document.children
+            );
+        };
+        assert!(matches!(
+            paragraph.children[..],
+            [ParagraphChild::Hyperlink(_)]
+        ));
+        assert!(
+            docx.hyperlinks.iter().any(|(_, path, _)| path == url),
+            "{:?}",
+            docx.hyperlinks
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_direction() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(16),
+        };
+        let rtl = Element::Paragraph {
+            elements: vec![Element::Styled {
+                style: TextStyle::RTL,
+                elements: vec![text("שלום עולם")],
+            }],
+        };
+        let mut document = Document::new(vec![rtl.clone(), text("Hello")]);
+        let bidi = |document: &Document| bidi_paragraphs(&docx::Transformer::generate(document)?);
+        assert_eq!(bidi(&document)?, [true, false]);
+        document.direction = TextDirection::Rtl;
+        assert_eq!(bidi(&document)?, [true, true]);
+
+        let paragraph = |text: &str, bidi: bool| {
+            let mut paragraph = Paragraph::new()
+                .style("Normal")
+                .add_run(Run::new().add_text(text));
+            paragraph.property = paragraph.property.bidi(bidi);
+            paragraph
+        };
+        let parse = |docx: Docx| -> anyhow::Result<Document> {
+            let mut cursor = Cursor::new(Vec::new());
+            docx.build().pack(&mut cursor)?;
+            docx::Transformer::parse(&Bytes::from(cursor.into_inner()))
+        };
+        let parsed = parse(
+            Docx::new()
+                .add_paragraph(paragraph("שלום עולם", true))
+                .add_paragraph(paragraph("Hello", false)),
+        )?;
+        assert_eq!(parsed.direction, TextDirection::Ltr);
+        assert_eq!(parsed.get_all_elements(), vec![&rtl, &text("Hello")]);
+        let parsed = parse(Docx::new().add_paragraph(paragraph("مرحبا", true)))?;
+        assert_eq!(parsed.direction, TextDirection::Rtl);
+        assert_eq!(parsed.get_all_elements(), vec![&text("مرحبا")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_break() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::PageBreak]);
+        let generated = docx::Transformer::generate(&document)?;
+        let parsed = docx::Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_all_elements(), vec![&Element::PageBreak]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_of_contents() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::TableOfContents { max_level: 2 },
+            Element::Header {
+                level: 1,
+                text: "Results".to_string(),
+                anchor: None,
+            },
+        ]);
+        let generated = docx::Transformer::generate(&document)?;
+        let parsed = docx::Transformer::parse(&generated)?;
+        assert_eq!(
+            parsed.get_all_elements()[0],
+            &Element::TableOfContents { max_level: 2 }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_comment() -> anyhow::Result<()> {
+        let comment = Element::Comment {
+            author: Some("Ann".to_string()),
+            date: Some("2024-03-01T09:30:00Z".to_string()),
+            text: "Check the totals".to_string(),
+            range: None,
+        };
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: "Revenue grew".to_string(),
+                    font: FontSpec::from(16),
+                }],
+            },
+            comment.clone(),
+        ]);
+        let generated = docx::Transformer::generate(&document)?;
+        let parsed = docx::Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_all_elements(), vec![&comment]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![Element::Header {
+            level: 1,
+            text: "Report".to_string(),
+            anchor: None,
+        }]);
+        document.metadata = Metadata {
+            title: Some("Quarterly report".to_string()),
+            authors: vec!["Ann".to_string(), "Bob".to_string()],
+            subject: Some("Revenue & costs".to_string()),
+            keywords: vec!["finance".to_string(), "2024".to_string()],
+            language: Some("en-GB".to_string()),
+            created: Some("2024-03-01T09:30:00Z".to_string()),
+            modified: None,
+            custom: [("Department".to_string(), "Sales".to_string())].into(),
+        };
+        let generated = docx::Transformer::generate(&document)?;
+        let parsed = docx::Transformer::parse(&generated)?;
+        assert_eq!(parsed.metadata, document.metadata);
+        Ok(())
+    }
 }
@@ -0,0 +1,215 @@
+//! Emoji shortcodes, the `:name:` form chat and issue trackers write emoji in
+//!
+//! The names are the common GitHub and Slack ones. Where several names stand for one emoji the
+//! first is written back.
+use std::cmp::Reverse;
+
+/// Shortcode names with their emoji, emoji drawn from text symbols keep the variation selector
+const SHORTCODES: &[(&str, &str)] = &[
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("grinning", "😀"),
+    ("grin", "😁"),
+    ("laughing", "😆"),
+    ("sweat_smile", "😅"),
+    ("joy", "😂"),
+    ("rofl", "🤣"),
+    ("wink", "😉"),
+    ("blush", "😊"),
+    ("innocent", "😇"),
+    ("slightly_smiling_face", "🙂"),
+    ("upside_down_face", "🙃"),
+    ("heart_eyes", "😍"),
+    ("kissing_heart", "😘"),
+    ("yum", "😋"),
+    ("stuck_out_tongue", "😛"),
+    ("stuck_out_tongue_winking_eye", "😜"),
+    ("thinking", "🤔"),
+    ("neutral_face", "😐"),
+    ("expressionless", "😑"),
+    ("unamused", "😒"),
+    ("roll_eyes", "🙄"),
+    ("grimacing", "😬"),
+    ("relieved", "😌"),
+    ("pensive", "😔"),
+    ("sleepy", "😪"),
+    ("sleeping", "😴"),
+    ("mask", "😷"),
+    ("sunglasses", "😎"),
+    ("nerd_face", "🤓"),
+    ("confused", "😕"),
+    ("worried", "😟"),
+    ("open_mouth", "😮"),
+    ("astonished", "😲"),
+    ("flushed", "😳"),
+    ("cry", "😢"),
+    ("sob", "😭"),
+    ("scream", "😱"),
+    ("sweat", "😓"),
+    ("angry", "😠"),
+    ("rage", "😡"),
+    ("skull", "💀"),
+    ("poop", "💩"),
+    ("hankey", "💩"),
+    ("clown_face", "🤡"),
+    ("ghost", "👻"),
+    ("alien", "👽"),
+    ("robot", "🤖"),
+    ("see_no_evil", "🙈"),
+    ("heart", "❤️"),
+    ("orange_heart", "🧡"),
+    ("yellow_heart", "💛"),
+    ("green_heart", "💚"),
+    ("blue_heart", "💙"),
+    ("purple_heart", "💜"),
+    ("black_heart", "🖤"),
+    ("broken_heart", "💔"),
+    ("sparkling_heart", "💖"),
+    ("100", "💯"),
+    ("boom", "💥"),
+    ("zzz", "💤"),
+    ("wave", "👋"),
+    ("raised_hand", "✋"),
+    ("ok_hand", "👌"),
+    ("+1", "👍"),
+    ("thumbsup", "👍"),
+    ("-1", "👎"),
+    ("thumbsdown", "👎"),
+    ("fist", "👊"),
+    ("facepunch", "👊"),
+    ("clap", "👏"),
+    ("raised_hands", "🙌"),
+    ("pray", "🙏"),
+    ("handshake", "🤝"),
+    ("muscle", "💪"),
+    ("point_up", "☝️"),
+    ("point_down", "👇"),
+    ("point_left", "👈"),
+    ("point_right", "👉"),
+    ("eyes", "👀"),
+    ("brain", "🧠"),
+    ("tada", "🎉"),
+    ("confetti_ball", "🎊"),
+    ("balloon", "🎈"),
+    ("gift", "🎁"),
+    ("trophy", "🏆"),
+    ("fire", "🔥"),
+    ("star", "⭐"),
+    ("sparkles", "✨"),
+    ("zap", "⚡"),
+    ("sunny", "☀️"),
+    ("cloud", "☁️"),
+    ("umbrella", "☔"),
+    ("snowflake", "❄️"),
+    ("rainbow", "🌈"),
+    ("rocket", "🚀"),
+    ("airplane", "✈️"),
+    ("car", "🚗"),
+    ("coffee", "☕"),
+    ("beer", "🍺"),
+    ("pizza", "🍕"),
+    ("cake", "🍰"),
+    ("apple", "🍎"),
+    ("bug", "🐛"),
+    ("cat", "🐱"),
+    ("dog", "🐶"),
+    ("penguin", "🐧"),
+    ("white_check_mark", "✅"),
+    ("heavy_check_mark", "✔️"),
+    ("x", "❌"),
+    ("warning", "⚠️"),
+    ("no_entry", "⛔"),
+    ("no_entry_sign", "🚫"),
+    ("question", "❓"),
+    ("exclamation", "❗"),
+    ("bulb", "💡"),
+    ("memo", "📝"),
+    ("book", "📖"),
+    ("books", "📚"),
+    ("email", "📧"),
+    ("calendar", "📆"),
+    ("pushpin", "📌"),
+    ("paperclip", "📎"),
+    ("lock", "🔒"),
+    ("unlock", "🔓"),
+    ("key", "🔑"),
+    ("hammer", "🔨"),
+    ("wrench", "🔧"),
+    ("gear", "⚙️"),
+    ("link", "🔗"),
+    ("mag", "🔍"),
+    ("bell", "🔔"),
+    ("hourglass", "⌛"),
+    ("computer", "💻"),
+    ("iphone", "📱"),
+    ("chart_with_upwards_trend", "📈"),
+    ("chart_with_downwards_trend", "📉"),
+    ("bar_chart", "📊"),
+    ("moneybag", "💰"),
+    ("construction", "🚧"),
+    ("checkered_flag", "🏁"),
+    ("recycle", "♻️"),
+    ("arrow_right", "➡️"),
+    ("arrow_left", "⬅️"),
+    ("arrow_up", "⬆️"),
+    ("arrow_down", "⬇️"),
+    ("heavy_plus_sign", "➕"),
+    ("heavy_minus_sign", "➖"),
+];
+
+const VARIATION_SELECTOR: char = '\u{fe0f}';
+
+/// Replaces the known `:name:` shortcodes in `text` with their emoji, others are kept as written
+pub(crate) fn shortcodes_to_emoji(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(':') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let name_len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-')))
+            .unwrap_or(after.len());
+        let emoji = (name_len > 0 && after[name_len..].starts_with(':'))
+            .then(|| SHORTCODES.iter().find(|(name, _)| *name == &after[..name_len]))
+            .flatten();
+        match emoji {
+            Some((_, emoji)) => {
+                result.push_str(emoji);
+                rest = &after[name_len + 1..];
+            }
+            // The closing colon may open the next shortcode
+            None => {
+                result.push(':');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Replaces the emoji in `text` that have a shortcode with it, with or without the variation
+/// selector after them
+pub(crate) fn emoji_to_shortcodes(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let found = SHORTCODES
+            .iter()
+            .map(|(name, emoji)| (name, emoji.trim_end_matches(VARIATION_SELECTOR)))
+            .filter(|(_, emoji)| rest.starts_with(emoji))
+            .min_by_key(|(_, emoji)| Reverse(emoji.len()));
+        match found {
+            Some((name, emoji)) => {
+                result.push_str(&format!(":{}:", name));
+                rest = &rest[emoji.len()..];
+                rest = rest.strip_prefix(VARIATION_SELECTOR).unwrap_or(rest);
+            }
+            None => {
+                result.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+    result
+}
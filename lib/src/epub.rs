@@ -0,0 +1,885 @@
+use crate::core::*;
+use crate::html;
+use crate::parse_util::read_entry;
+use bytes::Bytes;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use scraper::{Html, Selector};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        let mut archive = ZipArchive::new(Cursor::new(document.to_vec()))?;
+
+        let container = read_entry(&mut archive, "META-INF/container.xml")?;
+        let opf_path = xml_attributes(&container, "rootfile", &["full-path"])?
+            .into_iter()
+            .find_map(|mut attributes| attributes.remove("full-path"))
+            .ok_or_else(|| ParserError::Malformed("container.xml has no rootfile".to_string()))?;
+        let opf_dir = parent_dir(&opf_path);
+        let opf = read_entry(&mut archive, &opf_path)?;
+
+        let mut manifest: HashMap<String, (String, String)> = HashMap::new();
+        for mut item in xml_attributes(&opf, "item", &["id", "href", "media-type"])? {
+            if let (Some(id), Some(href)) = (item.remove("id"), item.remove("href")) {
+                let media_type = item.remove("media-type").unwrap_or_default();
+                manifest.insert(id, (resolve(&opf_dir, &href), media_type));
+            }
+        }
+
+        // Images are loaded up front so the HTML parser can resolve them without archive access
+        let mut images: HashMap<String, Bytes> = HashMap::new();
+        for (path, media_type) in manifest.values() {
+            if media_type.starts_with("image/") {
+                let bytes = read_entry(&mut archive, path)?;
+                images.insert(path.clone(), Bytes::from(bytes));
+            }
+        }
+
+        let spine = xml_attributes(&opf, "itemref", &["idref"])?;
+        let title_selector = Selector::parse("title").unwrap();
+        let body_selector = Selector::parse("body").unwrap();
+        let mut elements: Vec<Element> = Vec::new();
+        for mut itemref in spine {
+            let Some((path, _)) = itemref.remove("idref").and_then(|id| manifest.get(&id)) else {
+                continue;
+            };
+            let chapter = String::from_utf8(read_entry(&mut archive, path)?)?;
+            let chapter = Html::parse_document(&chapter);
+            let title = chapter
+                .select(&title_selector)
+                .next()
+                .map(|title| title.text().collect::<String>().trim().to_string())
+                .unwrap_or_default();
+            // Only the body is handed over, otherwise the <title> ends up as text
+            let body = chapter
+                .select(&body_selector)
+                .next()
+                .map(|body| body.html())
+                .unwrap_or_default();
+
+            let chapter_dir = parent_dir(path);
+            let parsed = html::Transformer::parse_with_loader(&Bytes::from(body), |src| {
+                let image_path = resolve(&chapter_dir, src);
+                images.get(&image_path).cloned().ok_or_else(|| {
+                    ParserError::Malformed(format!("image {} is not in the manifest", image_path))
+                        .into()
+                })
+            })?;
+            let chapter_elements = parsed.get_all_elements();
+            if chapter_elements.is_empty() {
+                continue;
+            }
+            let starts_with_header = matches!(chapter_elements[0], Element::Header { .. });
+            if !starts_with_header && !title.is_empty() {
+                elements.push(Element::Header {
+                    level: 1,
+                    text: title,
+                    anchor: None,
+                });
+            }
+            elements.extend(chapter_elements.into_iter().cloned());
+        }
+        let mut parsed = Document::new(elements);
+        parsed.metadata = package_metadata(&opf)?;
+        Ok(parsed)
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        let mut images: Vec<(String, &ImageData)> = Vec::new();
+        let mut chapters: Vec<Chapter> = Vec::new();
+
+        // Page headers and footers have no meaning in a reflowable book
+        let document = &document.nest_sections();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                // A top level section is a chapter, its children are the body of the chapter file
+                let content = match element {
+                    Element::Section { level: 1, children } => {
+                        let title = match children.first() {
+                            Some(Element::Header { text, .. }) => text.clone(),
+                            _ => String::new(),
+                        };
+                        chapters.push(Chapter {
+                            title,
+                            body: String::new(),
+                        });
+                        children.as_slice()
+                    }
+                    element => std::slice::from_ref(element),
+                };
+                if chapters.is_empty() {
+                    chapters.push(Chapter {
+                        title: String::new(),
+                        body: String::new(),
+                    });
+                }
+                let chapter = chapters.last_mut().unwrap();
+                for element in content {
+                    generate_element(&mut chapter.body, element, &mut images);
+                }
+            }
+        }
+        if chapters.is_empty() {
+            chapters.push(Chapter {
+                title: String::new(),
+                body: String::new(),
+            });
+        }
+
+        let book_title = document
+            .metadata
+            .title
+            .as_deref()
+            .or_else(|| {
+                chapters
+                    .iter()
+                    .map(|chapter| chapter.title.as_str())
+                    .find(|title| !title.is_empty())
+            })
+            .unwrap_or("Document")
+            .to_string();
+        for (index, chapter) in chapters.iter_mut().enumerate() {
+            if chapter.title.is_empty() {
+                chapter.title = format!("Chapter {}", index + 1);
+            }
+        }
+
+        let mut hasher = DefaultHasher::new();
+        for chapter in &chapters {
+            chapter.body.hash(&mut hasher);
+        }
+        let identifier = format!("urn:shiva:{:016x}", hasher.finish());
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        // The mimetype entry must come first and must not be compressed
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(b"application/epub+zip")?;
+
+        zip.start_file("META-INF/container.xml", deflated)?;
+        zip.write_all(CONTAINER_XML.as_bytes())?;
+
+        zip.start_file("OEBPS/content.opf", deflated)?;
+        zip.write_all(
+            package_document(
+                &identifier,
+                &book_title,
+                &document.metadata,
+                &chapters,
+                &images,
+            )
+            .as_bytes(),
+        )?;
+
+        zip.start_file("OEBPS/nav.xhtml", deflated)?;
+        zip.write_all(navigation_document(&book_title, &chapters).as_bytes())?;
+
+        for (index, chapter) in chapters.iter().enumerate() {
+            zip.start_file(format!("OEBPS/{}", chapter_file(index)), deflated)?;
+            zip.write_all(xhtml(&chapter.title, &chapter.body).as_bytes())?;
+        }
+
+        for (path, image) in &images {
+            zip.start_file(format!("OEBPS/{}", path), stored)?;
+            zip.write_all(image.bytes())?;
+        }
+
+        let cursor = zip.finish()?;
+        Ok(Bytes::from(cursor.into_inner()))
+    }
+}
+
+/// Collects the requested attributes of every `tag` element, ignoring namespace prefixes
+fn xml_attributes(
+    xml: &[u8],
+    tag: &str,
+    names: &[&str],
+) -> anyhow::Result<Vec<HashMap<String, String>>> {
+    let mut reader = Reader::from_reader(xml);
+    let mut buffer = Vec::new();
+    let mut result = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buffer)? {
+            Event::Start(ref e) | Event::Empty(ref e)
+                if e.local_name().as_ref() == tag.as_bytes() =>
+            {
+                let mut attributes = HashMap::new();
+                for name in names {
+                    if let Some(attribute) = e.try_get_attribute(*name)? {
+                        attributes.insert(
+                            name.to_string(),
+                            attribute.decode_and_unescape_value(&reader)?.to_string(),
+                        );
+                    }
+                }
+                result.push(attributes);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buffer.clear();
+    }
+    Ok(result)
+}
+
+fn parent_dir(path: &str) -> String {
+    match path.rfind('/') {
+        Some(index) => path[..index].to_string(),
+        None => String::new(),
+    }
+}
+
+/// Resolves an href relative to the directory of the referencing file inside the container
+fn resolve(base_dir: &str, href: &str) -> String {
+    let href = href.split('#').next().unwrap_or_default();
+    let mut parts: Vec<&str> = base_dir
+        .split('/')
+        .filter(|part| !part.is_empty())
+        .collect();
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+struct Chapter {
+    title: String,
+    body: String,
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+fn chapter_file(index: usize) -> String {
+    format!("chapter{}.xhtml", index + 1)
+}
+
+pub(crate) fn media_type(image_type: &ImageType) -> &'static str {
+    match image_type {
+        ImageType::Png => "image/png",
+        ImageType::Jpeg => "image/jpeg",
+        ImageType::Gif => "image/gif",
+        ImageType::SVG => "image/svg+xml",
+    }
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The Dublin Core properties of the package document, the date is the creation date
+fn package_metadata(opf: &[u8]) -> anyhow::Result<Metadata> {
+    let mut reader = Reader::from_reader(opf);
+    let mut buffer = Vec::new();
+    let mut metadata = Metadata::default();
+    let mut current: Option<String> = None;
+    loop {
+        match reader.read_event_into(&mut buffer)? {
+            Event::Start(ref e) => {
+                let name = String::from_utf8(e.name().as_ref().to_vec())?;
+                current = match name.as_str() {
+                    "meta" => e
+                        .try_get_attribute("property")?
+                        .map(|property| property.decode_and_unescape_value(&reader))
+                        .transpose()?
+                        .map(|property| property.to_string()),
+                    _ => Some(name),
+                };
+            }
+            Event::Text(ref e) => {
+                let value = e.unescape()?.trim().to_string();
+                match current.take().as_deref() {
+                    Some("dc:title") => metadata.title = Some(value),
+                    Some("dc:creator") => metadata.authors.push(value),
+                    Some("dc:subject") => metadata.subject = Some(value),
+                    Some("dc:language") => metadata.language = Some(value),
+                    Some("dc:date") => metadata.created = Some(value),
+                    Some("dcterms:modified") => metadata.modified = Some(value),
+                    _ => {}
+                }
+            }
+            Event::End(_) => current = None,
+            Event::Eof => break,
+            _ => {}
+        }
+        buffer.clear();
+    }
+    Ok(metadata)
+}
+
+fn package_document(
+    identifier: &str,
+    title: &str,
+    metadata: &Metadata,
+    chapters: &[Chapter],
+    images: &[(String, &ImageData)],
+) -> String {
+    let mut manifest = String::from(
+        "    <item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n",
+    );
+    let mut spine = String::new();
+    for index in 0..chapters.len() {
+        manifest.push_str(&format!(
+            "    <item id=\"chapter{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+            index + 1,
+            chapter_file(index)
+        ));
+        spine.push_str(&format!("    <itemref idref=\"chapter{}\"/>\n", index + 1));
+    }
+    let creators: String = metadata
+        .authors
+        .iter()
+        .map(|author| format!("    <dc:creator>{}</dc:creator>\n", escape(author)))
+        .collect();
+    let mut properties = String::new();
+    if let Some(subject) = &metadata.subject {
+        properties.push_str(&format!("    <dc:subject>{}</dc:subject>\n", escape(subject)));
+    }
+    if let Some(created) = &metadata.created {
+        properties.push_str(&format!("    <dc:date>{}</dc:date>\n", escape(created)));
+    }
+    for (index, (path, image)) in images.iter().enumerate() {
+        manifest.push_str(&format!(
+            "    <item id=\"image{}\" href=\"{}\" media-type=\"{}\"/>\n",
+            index + 1,
+            path,
+            media_type(image.image_type())
+        ));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="book-id">{identifier}</dc:identifier>
+    <dc:title>{title}</dc:title>
+{creators}    <dc:language>{language}</dc:language>
+{properties}    <meta property="dcterms:modified">{modified}</meta>
+  </metadata>
+  <manifest>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#,
+        identifier = identifier,
+        title = escape(title),
+        creators = creators,
+        language = escape(metadata.language.as_deref().unwrap_or("en")),
+        properties = properties,
+        modified = modified_timestamp(),
+        manifest = manifest,
+        spine = spine
+    )
+}
+
+fn navigation_document(title: &str, chapters: &[Chapter]) -> String {
+    let mut body = String::from("<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n<ol>\n");
+    for (index, chapter) in chapters.iter().enumerate() {
+        body.push_str(&format!(
+            "<li><a href=\"{}\">{}</a></li>\n",
+            chapter_file(index),
+            escape(&chapter.title)
+        ));
+    }
+    body.push_str("</ol>\n</nav>\n");
+    xhtml(title, &body)
+}
+
+fn xhtml(title: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head>
+<title>{}</title>
+</head>
+<body>
+{}</body>
+</html>
+"#,
+        escape(title),
+        body
+    )
+}
+
+/// `dcterms:modified` is required by EPUB3 and has to be in `CCYY-MM-DDThh:mm:ssZ` form
+pub(crate) fn modified_timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+pub(crate) fn generate_element<'a>(
+    xhtml: &mut String,
+    element: &'a Element,
+    images: &mut Vec<(String, &'a ImageData)>,
+) {
+    match element {
+        Element::Text { text, .. } => {
+            xhtml.push_str(&format!("<p>{}</p>\n", escape(text)));
+        }
+        Element::Header { level, text, .. } => {
+            let level = (*level).clamp(1, 6);
+            xhtml.push_str(&format!("<h{}>{}</h{}>\n", level, escape(text), level));
+        }
+        Element::Paragraph { elements } => {
+            xhtml.push_str("<p>");
+            for child in elements {
+                generate_inline(xhtml, child, images);
+            }
+            xhtml.push_str("</p>\n");
+        }
+        Element::List { elements, numbered } => {
+            let tag = if *numbered { "ol" } else { "ul" };
+            xhtml.push_str(&format!("<{}>\n", tag));
+            for item in elements {
+                xhtml.push_str("<li>");
+                match &item.element {
+                    Element::List { .. } => {
+                        xhtml.push('\n');
+                        generate_element(xhtml, &item.element, images);
+                    }
+                    element => generate_inline(xhtml, element, images),
+                }
+                xhtml.push_str("</li>\n");
+            }
+            xhtml.push_str(&format!("</{}>\n", tag));
+        }
+        Element::Table { headers, rows, .. } => {
+            xhtml.push_str("<table>\n");
+            if !headers.is_empty() {
+                xhtml.push_str("<tr>");
+                for header in headers {
+                    xhtml.push_str("<th>");
+                    generate_inline(xhtml, &header.element, images);
+                    xhtml.push_str("</th>");
+                }
+                xhtml.push_str("</tr>\n");
+            }
+            for row in rows {
+                xhtml.push_str("<tr>");
+                for cell in &row.cells {
+                    xhtml.push_str("<td>");
+                    match &cell.elements[..] {
+                        [element] => generate_inline(xhtml, element, images),
+                        elements => {
+                            xhtml.push('\n');
+                            for element in elements {
+                                generate_element(xhtml, element, images);
+                            }
+                        }
+                    }
+                    xhtml.push_str("</td>");
+                }
+                xhtml.push_str("</tr>\n");
+            }
+            xhtml.push_str("</table>\n");
+        }
+        // The data of the chart as a table, which holds no images
+        Element::Chart { .. } => {
+            generate_element(xhtml, &element.chart_fallback(), &mut Vec::new())
+        }
+        Element::Image(_)
+        | Element::Hyperlink { .. }
+        | Element::Styled { .. }
+        | Element::Math { .. }
+        | Element::Ruby { .. } => {
+            xhtml.push_str("<p>");
+            generate_inline(xhtml, element, images);
+            xhtml.push_str("</p>\n");
+        }
+        Element::Figure { image, caption } => {
+            xhtml.push_str("<figure>");
+            generate_image(xhtml, image, images);
+            if !caption.is_empty() {
+                xhtml.push_str("<figcaption>");
+                for child in caption {
+                    generate_inline(xhtml, child, images);
+                }
+                xhtml.push_str("</figcaption>");
+            }
+            xhtml.push_str("</figure>\n");
+        }
+        Element::LineBreak => {
+            xhtml.push_str("<br/>\n");
+        }
+        Element::HorizontalRule => {
+            xhtml.push_str("<hr/>\n");
+        }
+        // The same break as in HTML, reading systems that paginate honor it
+        Element::PageBreak => {
+            xhtml.push_str(html::PAGE_BREAK);
+            xhtml.push('\n');
+        }
+        // The navigation document is the table of contents of the book
+        Element::TableOfContents { .. } => {}
+        Element::Section { children, .. } => {
+            xhtml.push_str("<section>\n");
+            for child in children {
+                generate_element(xhtml, child, images);
+            }
+            xhtml.push_str("</section>\n");
+        }
+        Element::Custom { .. } => {}
+        Element::Media {
+            kind,
+            source,
+            poster,
+        } => {
+            generate_media(xhtml, kind, source, poster, images);
+            xhtml.push('\n');
+        }
+        Element::DefinitionList { items } => {
+            xhtml.push_str("<dl>\n");
+            for item in items {
+                xhtml.push_str("<dt>");
+                for child in &item.term {
+                    generate_inline(xhtml, child, images);
+                }
+                xhtml.push_str("</dt>\n");
+                for definition in &item.definitions {
+                    xhtml.push_str("<dd>");
+                    generate_inline(xhtml, definition, images);
+                    xhtml.push_str("</dd>\n");
+                }
+            }
+            xhtml.push_str("</dl>\n");
+        }
+        Element::CodeBlock { language, code } => {
+            xhtml.push_str("<pre><code");
+            if let Some(language) = language {
+                xhtml.push_str(&format!(" class=\"language-{}\"", escape(language)));
+            }
+            xhtml.push_str(&format!(">{}</code></pre>\n", escape(code)));
+        }
+        Element::Blockquote { elements, .. } => {
+            xhtml.push_str("<blockquote>\n");
+            for child in elements {
+                generate_element(xhtml, child, images);
+            }
+            xhtml.push_str("</blockquote>\n");
+        }
+        Element::Footnote { id, content } => {
+            xhtml.push_str(&format!(
+                "<aside epub:type=\"footnote\" id=\"fn-{}\">\n",
+                escape(id)
+            ));
+            for child in content {
+                generate_element(xhtml, child, images);
+            }
+            xhtml.push_str("</aside>\n");
+        }
+        Element::FootnoteReference { .. } => {
+            xhtml.push_str("<p>");
+            generate_inline(xhtml, element, images);
+            xhtml.push_str("</p>\n");
+        }
+        // Comments are editorial notes and are not published
+        Element::Comment { .. } => {}
+    }
+}
+
+fn generate_image<'a>(
+    xhtml: &mut String,
+    image: &'a ImageData,
+    images: &mut Vec<(String, &'a ImageData)>,
+) {
+    if image.bytes().is_empty() {
+        return;
+    }
+    let path = format!(
+        "images/image{}{}",
+        images.len() + 1,
+        image.image_type().to_extension()
+    );
+    xhtml.push_str(&format!(
+        "<img src=\"{}\" alt=\"{}\"/>",
+        path,
+        escape(image.alt())
+    ));
+    images.push((path, image));
+}
+
+/// EPUB 3 reading systems play HTML5 media, the poster is packaged with the images
+fn generate_media<'a>(
+    xhtml: &mut String,
+    kind: &MediaKind,
+    source: &MediaSource,
+    poster: &'a Option<ImageData>,
+    images: &mut Vec<(String, &'a ImageData)>,
+) {
+    let mut poster_attribute = String::new();
+    if let Some(poster) = poster.as_ref().filter(|poster| !poster.bytes().is_empty()) {
+        let path = format!(
+            "images/image{}{}",
+            images.len() + 1,
+            poster.image_type().to_extension()
+        );
+        poster_attribute = format!(" poster=\"{}\"", path);
+        images.push((path, poster));
+    }
+    xhtml.push_str(&format!(
+        "<{} src=\"{}\" controls=\"controls\"{}></{}>",
+        kind,
+        escape(&source.to_src()),
+        poster_attribute,
+        kind
+    ));
+}
+
+fn generate_inline<'a>(
+    xhtml: &mut String,
+    element: &'a Element,
+    images: &mut Vec<(String, &'a ImageData)>,
+) {
+    match element {
+        Element::Text { text, .. } => xhtml.push_str(&escape(text)),
+        Element::Header { text, .. } => xhtml.push_str(&escape(text)),
+        Element::Hyperlink { title, url, .. } => {
+            xhtml.push_str(&format!(
+                "<a href=\"{}\">{}</a>",
+                escape(url),
+                escape(title)
+            ));
+        }
+        Element::Image(image) => generate_image(xhtml, image, images),
+        Element::Media {
+            kind,
+            source,
+            poster,
+        } => generate_media(xhtml, kind, source, poster, images),
+        Element::Figure { image, caption } => {
+            generate_image(xhtml, image, images);
+            for child in caption {
+                xhtml.push(' ');
+                generate_inline(xhtml, child, images);
+            }
+        }
+        Element::LineBreak => xhtml.push_str("<br/>"),
+        // Readers without ruby show the reading in the parentheses
+        Element::Ruby { base, annotation } => xhtml.push_str(&format!(
+            "<ruby>{}<rp>(</rp><rt>{}</rt><rp>)</rp></ruby>",
+            escape(base),
+            escape(annotation)
+        )),
+        Element::Paragraph { elements }
+        | Element::Blockquote { elements, .. }
+        | Element::Section {
+            children: elements, ..
+        } => {
+            for child in elements {
+                generate_inline(xhtml, child, images);
+            }
+        }
+        Element::Styled { style, elements } => {
+            let tags = [
+                (style.bold, "strong"),
+                (style.italic, "em"),
+                (style.underline, "u"),
+                (style.strikethrough, "s"),
+            ];
+            for (_, tag) in tags.iter().filter(|(enabled, _)| *enabled) {
+                xhtml.push_str(&format!("<{}>", tag));
+            }
+            for child in elements {
+                generate_inline(xhtml, child, images);
+            }
+            for (_, tag) in tags.iter().rev().filter(|(enabled, _)| *enabled) {
+                xhtml.push_str(&format!("</{}>", tag));
+            }
+        }
+        Element::Comment { .. }
+        | Element::PageBreak
+        | Element::TableOfContents { .. }
+        | Element::Custom { .. } => {}
+        Element::CodeBlock { code, .. } => {
+            xhtml.push_str(&format!("<code>{}</code>", escape(code)));
+        }
+        // The same MathJax markup as in HTML
+        Element::Math { tex, display } => {
+            if *display {
+                xhtml.push_str(&format!(
+                    "<span class=\"math display\">\\[{}\\]</span>",
+                    escape(tex)
+                ));
+            } else {
+                xhtml.push_str(&format!(
+                    "<span class=\"math inline\">\\({}\\)</span>",
+                    escape(tex)
+                ));
+            }
+        }
+        // Chapters are separate files, so the mark is not linked to the note
+        Element::FootnoteReference { id } => {
+            xhtml.push_str(&format!("<sup>{}</sup>", escape(id)));
+        }
+        Element::Footnote { id, content } => {
+            xhtml.push_str(&format!("<sup>{}</sup> ", escape(id)));
+            for child in content {
+                generate_inline(xhtml, child, images);
+            }
+        }
+        Element::List { .. }
+        | Element::Table { .. }
+        | Element::Chart { .. }
+        | Element::HorizontalRule
+        | Element::DefinitionList { .. } => {
+            generate_element(xhtml, element, images);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::*;
+    use crate::epub::*;
+    use crate::markdown;
+    use std::io::Read;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let generated = Transformer::generate(&parsed)?;
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(generated.to_vec()))?;
+        {
+            let mimetype = archive.by_index(0)?;
+            assert_eq!(mimetype.name(), "mimetype");
+            assert_eq!(mimetype.compression(), CompressionMethod::Stored);
+        }
+        let mut opf = String::new();
+        archive
+            .by_name("OEBPS/content.opf")?
+            .read_to_string(&mut opf)?;
+        assert!(opf.contains("<itemref idref=\"chapter1\"/>"));
+        assert!(opf.contains("media-type=\"image/png\""));
+        assert!(archive.by_name("OEBPS/chapter1.xhtml").is_ok());
+        assert!(archive.by_name("META-INF/container.xml").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let generated = Transformer::generate(&parsed)?;
+        let reparsed = Transformer::parse(&generated)?;
+        let elements = reparsed.get_all_elements();
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 1,
+                text: "First header".to_string(),
+                anchor: None,
+            }
+        );
+        let image = elements.iter().find_map(|element| match element {
+            Element::Paragraph { elements } => elements.iter().find_map(|element| match element {
+                Element::Image(image) => Some(image),
+                _ => None,
+            }),
+            _ => None,
+        });
+        assert!(!image.expect("image").bytes().is_empty());
+        assert!(elements
+            .iter()
+            .any(|element| matches!(element, Element::Table { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(
+            resolve("OEBPS/text", "../images/a.png"),
+            "OEBPS/images/a.png"
+        );
+        assert_eq!(resolve("", "chapter1.xhtml#intro"), "chapter1.xhtml");
+    }
+
+    #[test]
+    fn test_split_chapters() -> anyhow::Result<()> {
+        let header = |text: &str| Element::Header {
+            level: 1,
+            text: text.to_string(),
+            anchor: None,
+        };
+        let document = Document::new(vec![
+            header("One"),
+            Element::Text {
+                text: "a & b".to_string(),
+                font: FontSpec::from(8),
+            },
+            header("Two"),
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let mut archive = zip::ZipArchive::new(Cursor::new(generated.to_vec()))?;
+        let mut chapter = String::new();
+        archive
+            .by_name("OEBPS/chapter1.xhtml")?
+            .read_to_string(&mut chapter)?;
+        assert!(chapter.contains("<p>a &amp; b</p>"));
+        assert!(archive.by_name("OEBPS/chapter2.xhtml").is_ok());
+        assert!(archive.by_name("OEBPS/chapter3.xhtml").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,916 @@
+use crate::core::*;
+use crate::parse_util::{collapse_whitespace, parse_xml, Names, XmlChild, XmlNode};
+use bytes::Bytes;
+use std::collections::HashMap;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    /// Sections give the header levels, `<binary>` blocks hold the images.
+    /// Footnote bodies and the book description are not document content.
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        let root = parse_xml(document, Names::Local(&[]))?;
+        let mut binaries = HashMap::new();
+        for binary in root.nodes().filter(|node| node.name == "binary") {
+            if let Some(id) = binary.attribute("id") {
+                binaries.insert(id.to_string(), binary);
+            }
+        }
+        let parser = Parser { binaries };
+
+        let mut elements = Vec::new();
+        for body in root.nodes().filter(|node| node.name == "body") {
+            if body.attribute("name").is_some_and(|name| name == "notes") {
+                continue;
+            }
+            let level = if body.find("title").is_some() { 1 } else { 0 };
+            parser.parse_section(body, level, &mut elements)?;
+        }
+        let mut parsed = Document::new(elements);
+        if let Some(title_info) = root
+            .find("description")
+            .and_then(|description| description.find("title-info"))
+        {
+            parsed.metadata = title_metadata(title_info);
+        }
+        Ok(parsed)
+    }
+
+    /// Headers open nested sections, lists become paragraphs with bullet or number prefixes
+    /// since FictionBook has no list markup. Images are embedded as `<binary>` blocks,
+    /// footnotes go to the notes body.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        let mut generator = Generator {
+            binaries: Vec::new(),
+            sections: Vec::new(),
+            notes: Vec::new(),
+        };
+        let metadata = &document.metadata;
+        let title = metadata.title.as_deref().unwrap_or_else(|| {
+            document
+                .get_all_elements()
+                .into_iter()
+                .find_map(|element| match element {
+                    Element::Header { text, .. } => Some(text.as_str()),
+                    _ => None,
+                })
+                .unwrap_or("Untitled")
+        });
+
+        let mut body = String::new();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                generator.top_level(&mut body, element);
+            }
+        }
+        for _ in generator.sections.drain(..) {
+            body.push_str("</section>\n");
+        }
+
+        let mut xml = String::from(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<FictionBook xmlns=\"http://www.gribuser.ru/xml/fictionbook/2.0\" ",
+            "xmlns:l=\"http://www.w3.org/1999/xlink\">\n"
+        ));
+        xml.push_str("<description>\n<title-info>\n");
+        // The elements of the title info are in the order of the schema
+        for author in &metadata.authors {
+            xml.push_str(&author_xml(author));
+        }
+        xml.push_str(&format!("<book-title>{}</book-title>\n", escape(title)));
+        if let Some(subject) = &metadata.subject {
+            xml.push_str(&format!(
+                "<annotation><p>{}</p></annotation>\n",
+                escape(subject)
+            ));
+        }
+        if let Some(keywords) = metadata.keywords_text() {
+            xml.push_str(&format!("<keywords>{}</keywords>\n", escape(&keywords)));
+        }
+        if let Some(created) = &metadata.created {
+            xml.push_str(&format!("<date>{}</date>\n", escape(created)));
+        }
+        if let Some(language) = &metadata.language {
+            xml.push_str(&format!("<lang>{}</lang>\n", escape(language)));
+        }
+        xml.push_str("</title-info>\n</description>\n");
+        xml.push_str("<body>\n");
+        xml.push_str(&body);
+        xml.push_str("</body>\n");
+        if !generator.notes.is_empty() {
+            xml.push_str("<body name=\"notes\">\n");
+            for (id, content) in std::mem::take(&mut generator.notes) {
+                xml.push_str(&format!(
+                    "<section id=\"fn-{}\">\n<title><p>{}</p></title>\n",
+                    escape(id),
+                    escape(id)
+                ));
+                for child in content {
+                    generator.block(&mut xml, child);
+                }
+                xml.push_str("</section>\n");
+            }
+            xml.push_str("</body>\n");
+        }
+        for (id, image) in &generator.binaries {
+            xml.push_str(&format!(
+                "<binary id=\"{}\" content-type=\"{}\">{}</binary>\n",
+                id,
+                content_type(image.image_type()),
+                image.to_base64()
+            ));
+        }
+        xml.push_str("</FictionBook>\n");
+        Ok(Bytes::from(xml))
+    }
+}
+
+/// The metadata of the `<title-info>` of the description, the annotation is the subject
+fn title_metadata(title_info: &XmlNode) -> Metadata {
+    let mut metadata = Metadata::default();
+    for node in title_info.nodes() {
+        let text = node.text().trim().to_string();
+        match node.name.as_str() {
+            "author" => {
+                let names: Vec<String> = ["first-name", "middle-name", "last-name"]
+                    .into_iter()
+                    .filter_map(|name| node.find(name))
+                    .map(|name| name.text().trim().to_string())
+                    .filter(|name| !name.is_empty())
+                    .collect();
+                let author = match names.is_empty() {
+                    true => node.find("nickname").map(|name| name.text().trim().to_string()),
+                    false => Some(names.join(" ")),
+                };
+                metadata.authors.extend(author);
+            }
+            "book-title" => metadata.title = Some(text),
+            "annotation" => metadata.subject = Some(text),
+            "keywords" => metadata.keywords = Metadata::split_keywords(&text),
+            "date" => metadata.created = Some(node.attribute("value").unwrap_or(&text).to_string()),
+            "lang" => metadata.language = Some(text),
+            _ => {}
+        }
+    }
+    metadata
+}
+
+/// An author of the title info, the last word of the name is the last name
+fn author_xml(author: &str) -> String {
+    match author.trim().rsplit_once(' ') {
+        Some((first, last)) => format!(
+            "<author><first-name>{}</first-name><last-name>{}</last-name></author>\n",
+            escape(first.trim()),
+            escape(last)
+        ),
+        None => format!("<author><nickname>{}</nickname></author>\n", escape(author)),
+    }
+}
+
+struct Parser<'a> {
+    binaries: HashMap<String, &'a XmlNode>,
+}
+
+impl<'a> Parser<'a> {
+    /// Emits the section title at `level` and its content, subsections one level deeper
+    fn parse_section(
+        &self,
+        node: &XmlNode,
+        level: u8,
+        elements: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        for child in &node.children {
+            let child = match child {
+                XmlChild::Node(child) => child,
+                XmlChild::Comment(text) => {
+                    elements.push(Element::Comment {
+                        text: text.clone(),
+                        author: None,
+                        date: None,
+                        range: None,
+                    });
+                    continue;
+                }
+                XmlChild::Text(_) | XmlChild::Instruction(_) => continue,
+            };
+            match child.name.as_str() {
+                "title" => elements.push(Element::Header {
+                    level: level.clamp(1, 6),
+                    text: title_text(child),
+                    anchor: None,
+                }),
+                "section" => self.parse_section(child, level + 1, elements)?,
+                _ => self.parse_block(child, elements)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_block(&self, node: &XmlNode, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        match node.name.as_str() {
+            "p" | "subtitle" | "text-author" => {
+                let mut inline = Vec::new();
+                for child in &node.children {
+                    self.parse_inline(child, &mut inline)?;
+                }
+                trim_paragraph(&mut inline);
+                match inline.as_slice() {
+                    [] => {}
+                    [Element::Image(_)] => elements.append(&mut inline),
+                    _ => elements.push(Element::Paragraph { elements: inline }),
+                }
+            }
+            "image" => elements.extend(self.parse_image(node)?),
+            "table" => elements.push(self.parse_table(node)?),
+            // Verses are the lines of one paragraph
+            "poem" | "stanza" => {
+                let mut lines = Vec::new();
+                for verse in node.nodes() {
+                    match verse.name.as_str() {
+                        "v" => {
+                            if !lines.is_empty() {
+                                lines.push(Element::LineBreak);
+                            }
+                            for child in &verse.children {
+                                self.parse_inline(child, &mut lines)?;
+                            }
+                        }
+                        // Nested stanzas, titles and epigraphs
+                        _ => self.parse_block(verse, elements)?,
+                    }
+                }
+                trim_paragraph(&mut lines);
+                if !lines.is_empty() {
+                    elements.push(Element::Paragraph { elements: lines });
+                }
+            }
+            "cite" => {
+                let mut quote = Vec::new();
+                for child in node.nodes() {
+                    self.parse_block(child, &mut quote)?;
+                }
+                elements.push(Element::Blockquote {
+                    elements: quote,
+                    attribution: vec![],
+                });
+            }
+            "epigraph" | "annotation" => {
+                for child in node.nodes() {
+                    self.parse_block(child, elements)?;
+                }
+            }
+            // `empty-line` only adds vertical space
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// The first row is the header when it uses `th` cells
+    fn parse_table(&self, node: &XmlNode) -> anyhow::Result<Element> {
+        let mut headers = Vec::new();
+        let mut rows = Vec::new();
+        for row in node.nodes().filter(|row| row.name == "tr") {
+            let mut cells = Vec::new();
+            for cell in row.nodes() {
+                let mut inline = Vec::new();
+                for child in &cell.children {
+                    self.parse_inline(child, &mut inline)?;
+                }
+                trim_paragraph(&mut inline);
+                cells.push(match inline.len() {
+                    0 => Element::Text {
+                        text: String::new(),
+                        font: FontSpec::from(8),
+                    },
+                    1 => inline.remove(0),
+                    _ => Element::Paragraph { elements: inline },
+                });
+            }
+            let header = row.nodes().all(|cell| cell.name == "th");
+            if header && headers.is_empty() && rows.is_empty() {
+                headers = cells
+                    .into_iter()
+                    .map(|element| TableHeader {
+                        element,
+                        width: 30.0,
+                    })
+                    .collect();
+            } else {
+                rows.push(TableRow {
+                    cells: cells.into_iter().map(TableCell::new).collect(),
+                });
+            }
+        }
+        Ok(Element::Table {
+            headers,
+            rows,
+            alignments: vec![],
+            caption: vec![],
+        })
+    }
+
+    /// `<image l:href="#id"/>` refers to a `<binary>` block of the same book
+    fn parse_image(&self, node: &XmlNode) -> anyhow::Result<Option<Element>> {
+        let Some(id) = node
+            .attribute("href")
+            .and_then(|href| href.strip_prefix('#'))
+        else {
+            return Ok(None);
+        };
+        let Some(binary) = self.binaries.get(id) else {
+            return Ok(None);
+        };
+        let data: String = binary.text().split_whitespace().collect();
+        let image_type = binary
+            .attribute("content-type")
+            .and_then(|content_type| content_type.strip_prefix("image/"))
+            .unwrap_or(id);
+        Ok(Some(Element::Image(ImageData::from_base64(
+            &data,
+            node.attribute("title").unwrap_or_default().to_string(),
+            node.attribute("alt").unwrap_or_default().to_string(),
+            image_type.to_string(),
+            String::new(),
+            ImageDimension::default(),
+        )?)))
+    }
+
+    fn parse_inline(&self, child: &XmlChild, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        let node = match child {
+            XmlChild::Text(text) => {
+                push_text(elements, &collapse_whitespace(text));
+                return Ok(());
+            }
+            XmlChild::Comment(_) | XmlChild::Instruction(_) => return Ok(()),
+            XmlChild::Node(node) => node,
+        };
+        match node.name.as_str() {
+            "a" => {
+                let url = node.attribute("href").unwrap_or_default();
+                let title = collapse_whitespace(&node.text()).trim().to_string();
+                // Links to footnotes keep only their marker text
+                if url.starts_with('#') || url.is_empty() {
+                    push_text(elements, &title);
+                } else {
+                    elements.push(Element::Hyperlink {
+                        title: if title.is_empty() {
+                            url.to_string()
+                        } else {
+                            title
+                        },
+                        url: url.to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    });
+                }
+            }
+            "image" => elements.extend(self.parse_image(node)?),
+            _ => {
+                for child in &node.children {
+                    self.parse_inline(child, elements)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Titles are made of paragraphs, they are joined into one line
+fn title_text(title: &XmlNode) -> String {
+    let lines: Vec<String> = title
+        .nodes()
+        .map(|line| collapse_whitespace(&line.text()).trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect();
+    if lines.is_empty() {
+        collapse_whitespace(&title.text()).trim().to_string()
+    } else {
+        lines.join(" ")
+    }
+}
+
+fn push_text(elements: &mut Vec<Element>, text: &str) {
+    if let Some(Element::Text { text: last, .. }) = elements.last_mut() {
+        last.push_str(text);
+    } else {
+        elements.push(Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        });
+    }
+}
+
+fn trim_paragraph(elements: &mut Vec<Element>) {
+    let last = elements.len().saturating_sub(1);
+    for (index, element) in elements.iter_mut().enumerate() {
+        if let Element::Text { text, .. } = element {
+            if index == 0 {
+                *text = text.trim_start().to_string();
+            }
+            if index == last {
+                *text = text.trim_end().to_string();
+            }
+        }
+    }
+    elements.retain(|element| !matches!(element, Element::Text { text, .. } if text.is_empty()));
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn content_type(image_type: &ImageType) -> &'static str {
+    match image_type {
+        ImageType::Png => "image/png",
+        ImageType::Jpeg => "image/jpeg",
+        ImageType::Gif => "image/gif",
+        ImageType::SVG => "image/svg+xml",
+    }
+}
+
+struct Generator<'a> {
+    binaries: Vec<(String, &'a ImageData)>,
+    /// Header levels of the open sections
+    sections: Vec<u8>,
+    /// Footnotes by id, written to the notes body after the main one
+    notes: Vec<(&'a str, &'a [Element])>,
+}
+
+impl<'a> Generator<'a> {
+    /// Body content must be inside a section, content before the first header gets its own
+    fn top_level(&mut self, xml: &mut String, element: &'a Element) {
+        if let Element::Header { level, text, .. } = element {
+            while self.sections.last().is_some_and(|open| open >= level) {
+                self.sections.pop();
+                xml.push_str("</section>\n");
+            }
+            self.sections.push(*level);
+            xml.push_str(&format!(
+                "<section>\n<title><p>{}</p></title>\n",
+                escape(text)
+            ));
+            return;
+        }
+        if let Element::Footnote { id, content } = element {
+            self.notes.push((id, content));
+            return;
+        }
+        if self.sections.is_empty() {
+            self.sections.push(1);
+            xml.push_str("<section>\n");
+        }
+        self.block(xml, element);
+    }
+
+    fn block(&mut self, xml: &mut String, element: &'a Element) {
+        match element {
+            Element::Header { text, .. } => {
+                xml.push_str(&format!("<subtitle>{}</subtitle>\n", escape(text)))
+            }
+            Element::List { elements, numbered } => self.list(xml, elements, *numbered, 0),
+            Element::Table { headers, rows, .. } => {
+                xml.push_str("<table>\n");
+                if !headers.is_empty() {
+                    xml.push_str("<tr>");
+                    for header in headers {
+                        xml.push_str("<th>");
+                        self.inline(xml, &header.element);
+                        xml.push_str("</th>");
+                    }
+                    xml.push_str("</tr>\n");
+                }
+                for row in rows {
+                    xml.push_str("<tr>");
+                    for cell in &row.cells {
+                        xml.push_str("<td>");
+                        for (index, element) in cell.elements.iter().enumerate() {
+                            if index > 0 {
+                                xml.push(' ');
+                            }
+                            self.inline(xml, element);
+                        }
+                        xml.push_str("</td>");
+                    }
+                    xml.push_str("</tr>\n");
+                }
+                xml.push_str("</table>\n");
+            }
+            Element::Image(image) => {
+                if let Some(image) = self.image(image) {
+                    xml.push_str(&image);
+                    xml.push('\n');
+                }
+            }
+            // FictionBook has no captions, the caption is an emphasized paragraph below
+            Element::Figure { image, caption } => {
+                if let Some(image) = self.image(image) {
+                    xml.push_str(&image);
+                    xml.push('\n');
+                }
+                if !caption.is_empty() {
+                    xml.push_str("<p><emphasis>");
+                    for child in caption {
+                        self.inline(xml, child);
+                    }
+                    xml.push_str("</emphasis></p>\n");
+                }
+            }
+            Element::Comment { text, .. } => {
+                xml.push_str(&format!("<!-- {} -->\n", text.replace("--", "- -")));
+            }
+            Element::Blockquote { elements, .. } => {
+                xml.push_str("<cite>\n");
+                for child in elements {
+                    self.block(xml, child);
+                }
+                xml.push_str("</cite>\n");
+            }
+            Element::Section { children, .. } => {
+                for child in children {
+                    self.block(xml, child);
+                }
+            }
+            Element::Footnote { id, content } => self.notes.push((id, content)),
+            // FictionBook has no rule, an empty line is its only separator
+            Element::HorizontalRule => xml.push_str("<empty-line/>\n"),
+            // Nor pages, readers lay out the text to their screen
+            Element::PageBreak | Element::TableOfContents { .. } => {}
+            // Nor definition lists, the definitions are indented like list items
+            Element::DefinitionList { items } => {
+                for item in items {
+                    let mut term = String::new();
+                    for child in &item.term {
+                        self.inline(&mut term, child);
+                    }
+                    xml.push_str(&format!(
+                        "<p><strong>{}</strong></p>\n",
+                        term.replace('\n', " ").trim()
+                    ));
+                    for definition in &item.definitions {
+                        let mut content = String::new();
+                        self.inline(&mut content, definition);
+                        xml.push_str(&format!(
+                            "<p>{}{}</p>\n",
+                            "\u{a0}".repeat(4),
+                            content.replace('\n', " ").trim()
+                        ));
+                    }
+                }
+            }
+            Element::CodeBlock { code, .. } => {
+                for line in code.lines() {
+                    if line.trim().is_empty() {
+                        xml.push_str("<empty-line/>\n");
+                        continue;
+                    }
+                    // Leading spaces would be collapsed, no-break spaces keep the indentation
+                    let code = line.trim_start();
+                    let indent = "\u{a0}".repeat(line.len() - code.len());
+                    xml.push_str(&format!("<p><code>{}{}</code></p>\n", indent, escape(code)));
+                }
+            }
+            element => {
+                // Paragraphs cannot hold line breaks, each line is a paragraph of its own
+                let mut content = String::new();
+                self.inline(&mut content, element);
+                for line in content.split('\n') {
+                    if !line.trim().is_empty() {
+                        xml.push_str(&format!("<p>{}</p>\n", line.trim()));
+                    }
+                }
+            }
+        }
+    }
+
+    fn list(&mut self, xml: &mut String, items: &'a [ListItem], numbered: bool, depth: usize) {
+        let indent = "\u{a0}".repeat(depth * 4);
+        let mut number = 0;
+        for item in items {
+            match &item.element {
+                Element::List { elements, numbered } => {
+                    self.list(xml, elements, *numbered, depth + 1)
+                }
+                element => {
+                    number += 1;
+                    let marker = if numbered {
+                        format!("{}.", number)
+                    } else {
+                        "\u{2022}".to_string()
+                    };
+                    let mut content = String::new();
+                    self.inline(&mut content, element);
+                    xml.push_str(&format!(
+                        "<p>{}{} {}</p>\n",
+                        indent,
+                        marker,
+                        content.replace('\n', " ").trim()
+                    ));
+                }
+            }
+        }
+    }
+
+    fn inline(&mut self, xml: &mut String, element: &'a Element) {
+        match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => {
+                xml.push_str(&escape(text))
+            }
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => {
+                for child in elements {
+                    self.inline(xml, child);
+                }
+            }
+            // FictionBook has no underline, such spans keep only the text
+            Element::Styled { style, elements } => {
+                let tags = [
+                    (style.bold, "strong"),
+                    (style.italic, "emphasis"),
+                    (style.strikethrough, "strikethrough"),
+                ];
+                for (_, tag) in tags.iter().filter(|(enabled, _)| *enabled) {
+                    xml.push_str(&format!("<{}>", tag));
+                }
+                for child in elements {
+                    self.inline(xml, child);
+                }
+                for (_, tag) in tags.iter().rev().filter(|(enabled, _)| *enabled) {
+                    xml.push_str(&format!("</{}>", tag));
+                }
+            }
+            Element::Hyperlink { title, url, .. } => xml.push_str(&format!(
+                "<a l:href=\"{}\">{}</a>",
+                escape(url),
+                escape(title)
+            )),
+            Element::Image(image) => {
+                if let Some(image) = self.image(image) {
+                    xml.push_str(&image);
+                }
+            }
+            Element::Figure { image, caption } => {
+                if let Some(image) = self.image(image) {
+                    xml.push_str(&image);
+                }
+                for child in caption {
+                    xml.push(' ');
+                    self.inline(xml, child);
+                }
+            }
+            Element::LineBreak | Element::HorizontalRule => xml.push('\n'),
+            Element::List { elements, .. } => {
+                for (index, item) in elements.iter().enumerate() {
+                    if index > 0 {
+                        xml.push(' ');
+                    }
+                    self.inline(xml, &item.element);
+                }
+            }
+            Element::DefinitionList { items } => {
+                let children = items
+                    .iter()
+                    .flat_map(|item| item.term.iter().chain(&item.definitions));
+                for (index, child) in children.enumerate() {
+                    if index > 0 {
+                        xml.push(' ');
+                    }
+                    self.inline(xml, child);
+                }
+            }
+            Element::CodeBlock { code, .. } => xml.push_str(&format!(
+                "<code>{}</code>",
+                escape(&code.replace('\n', " "))
+            )),
+            // FictionBook has no formulas, the TeX source is set as code
+            Element::Math { tex, .. } => {
+                xml.push_str(&format!("<code>{}</code>", escape(&tex.replace('\n', " "))))
+            }
+            Element::FootnoteReference { id } => xml.push_str(&format!(
+                "<a l:href=\"#fn-{}\" type=\"note\">[{}]</a>",
+                escape(id),
+                escape(id)
+            )),
+            Element::Footnote { id, content } => {
+                xml.push_str(&format!("[{}] ", escape(id)));
+                for child in content {
+                    self.inline(xml, child);
+                }
+            }
+            Element::Ruby { .. } => {
+                if let Element::Text { text, .. } = element.ruby_fallback() {
+                    xml.push_str(&escape(&text));
+                }
+            }
+            Element::Table { .. }
+            | Element::Chart { .. }
+            | Element::Comment { .. }
+            | Element::PageBreak
+            | Element::TableOfContents { .. }
+            | Element::Custom { .. }
+            | Element::Media { .. } => {}
+        }
+    }
+
+    /// Registers the image as a binary and returns the `<image>` reference to it
+    fn image(&mut self, image: &'a ImageData) -> Option<String> {
+        if image.bytes().is_empty() {
+            return None;
+        }
+        let id = format!(
+            "image{}{}",
+            self.binaries.len(),
+            image.image_type().to_extension()
+        );
+        let mut reference = format!("<image l:href=\"#{}\"", id);
+        if !image.alt().is_empty() {
+            reference.push_str(&format!(" alt=\"{}\"", escape(image.alt())));
+        }
+        if !image.title().is_empty() {
+            reference.push_str(&format!(" title=\"{}\"", escape(image.title())));
+        }
+        reference.push_str("/>");
+        self.binaries.push((id, image));
+        Some(reference)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fb2::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let source = r##"<?xml version="1.0" encoding="UTF-8"?>
+<FictionBook xmlns="http://www.gribuser.ru/xml/fictionbook/2.0" xmlns:l="http://www.w3.org/1999/xlink">
+  <description>
+    <title-info><book-title>Tales</book-title></title-info>
+  </description>
+  <body>
+    <title><p>Tales</p></title>
+    <section>
+      <title><p>Chapter</p><p>One</p></title>
+      <p>It was a <emphasis>dark</emphasis> night, see <a l:href="https://example.com">notes</a><a l:href="#n1" type="note">[1]</a>.</p>
+      <empty-line/>
+      <image l:href="#cover.png" alt="Cover"/>
+      <poem><stanza><v>First line</v><v>Second line</v></stanza></poem>
+    </section>
+  </body>
+  <body name="notes">
+    <section id="n1"><p>A footnote</p></section>
+  </body>
+  <binary id="cover.png" content-type="image/png">dGVz
+dA==</binary>
+</FictionBook>"##;
+        let document = Transformer::parse(&Bytes::from(source))?;
+        let elements = document.get_all_elements();
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 1,
+                text: "Tales".to_string(),
+                anchor: None,
+            }
+        );
+        assert_eq!(
+            elements[1],
+            &Element::Header {
+                level: 2,
+                text: "Chapter One".to_string(),
+                anchor: None,
+            }
+        );
+        assert_eq!(
+            elements[2],
+            &Element::Paragraph {
+                elements: vec![
+                    text("It was a dark night, see "),
+                    Element::Hyperlink {
+                        title: "notes".to_string(),
+                        url: "https://example.com".to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    },
+                    text("[1]."),
+                ]
+            }
+        );
+        let Element::Image(image) = elements[3] else {
+            panic!("expected an image, got {:?}", elements[3]);
+        };
+        assert_eq!(image.bytes(), &Bytes::from_static(b"test"));
+        assert_eq!(image.alt(), "Cover");
+        assert_eq!(image.image_type(), &ImageType::Png);
+        assert_eq!(
+            elements[4],
+            &Element::Paragraph {
+                elements: vec![text("First line"), Element::LineBreak, text("Second line")]
+            }
+        );
+        assert_eq!(elements.len(), 5);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let image = ImageData::new(
+            Bytes::from_static(b"\x89PNG"),
+            "Diagram".to_string(),
+            "A diagram".to_string(),
+            "png".to_string(),
+            String::new(),
+            ImageDimension::default(),
+        );
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![
+            text("Preface"),
+            Element::Header {
+                level: 1,
+                text: "First".to_string(),
+                anchor: None,
+            },
+            Element::Paragraph {
+                elements: vec![text("Some <text> & more")],
+            },
+            Element::Image(image.clone()),
+            Element::Header {
+                level: 2,
+                text: "Nested".to_string(),
+                anchor: None,
+            },
+            Element::Table {
+                headers: vec![TableHeader {
+                    element: text("Name"),
+                    width: 30.0,
+                }],
+                rows: vec![TableRow {
+                    cells: vec![TableCell::new(text("Value"))],
+                }],
+                alignments: vec![],
+                caption: vec![],
+            },
+            Element::List {
+                elements: vec![ListItem {
+                    element: text("Item"),
+                    checked: None,
+                }],
+                numbered: false,
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let xml = String::from_utf8(generated.to_vec())?;
+        assert!(xml.contains("<book-title>First</book-title>"));
+        assert!(xml.contains("<p>\u{2022} Item</p>"));
+
+        let parsed = Transformer::parse(&generated)?;
+        let elements = parsed.get_all_elements();
+        assert_eq!(
+            elements[0],
+            &Element::Paragraph {
+                elements: vec![text("Preface")]
+            }
+        );
+        assert_eq!(
+            elements[1],
+            &Element::Header {
+                level: 1,
+                text: "First".to_string(),
+                anchor: None,
+            }
+        );
+        assert_eq!(
+            elements[2],
+            &Element::Paragraph {
+                elements: vec![text("Some <text> & more")]
+            }
+        );
+        assert_eq!(elements[3], &Element::Image(image));
+        assert_eq!(
+            elements[4],
+            &Element::Header {
+                level: 2,
+                text: "Nested".to_string(),
+                anchor: None,
+            }
+        );
+        assert!(matches!(elements[5], Element::Table { headers, rows, .. }
+            if headers.len() == 1 && rows.len() == 1));
+        assert_eq!(elements.len(), 7);
+        Ok(())
+    }
+}
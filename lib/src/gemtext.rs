@@ -0,0 +1,487 @@
+use crate::core::*;
+use bytes::Bytes;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    fn parse_with_loader<F>(_document: &Bytes, _image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Err(anyhow::anyhow!("Gemtext parsing is not supported"))
+    }
+
+    /// Gemtext has no inline markup, so links keep their title in the text and are listed as
+    /// `=>` lines below the paragraph. Images are written through `image_saver` and linked the
+    /// same way. Tables become preformatted blocks and headers deeper than `###` are clamped.
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        // A table of contents is written as the list of its headers
+        let document = &document.expand_toc();
+        let mut generator = Generator {
+            image_saver,
+            image_num: 0,
+            links: Vec::new(),
+        };
+        let mut gemtext = String::new();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                generator.block(&mut gemtext, element)?;
+            }
+        }
+        Ok(Bytes::from(gemtext.trim_end().to_string() + "\n"))
+    }
+}
+
+/// Line types other than text, a text line starting like one of them is indented
+const LINE_PREFIXES: [&str; 5] = ["=>", "#", "* ", ">", "```"];
+
+/// Text lines are taken literally, except for the line type prefixes
+fn escape(line: &str) -> String {
+    if LINE_PREFIXES.iter().any(|prefix| line.starts_with(prefix)) {
+        format!(" {}", line)
+    } else {
+        line.to_string()
+    }
+}
+
+struct Generator<F> {
+    image_saver: F,
+    image_num: usize,
+    /// `=> url title` lines waiting for the end of the current block
+    links: Vec<String>,
+}
+
+impl<F> Generator<F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn block(&mut self, gemtext: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Header { level, text, .. } => {
+                gemtext.push_str(&format!(
+                    "{} {}\n\n",
+                    "#".repeat((*level).clamp(1, 3) as usize),
+                    text.replace('\n', " ")
+                ));
+            }
+            Element::Text { text, .. } if text.contains('\n') => {
+                self.preformatted(gemtext, "", text.trim_end_matches('\n'));
+            }
+            // Gemtext has no rule, a line of dashes reads as one
+            Element::HorizontalRule => gemtext.push_str("---\n\n"),
+            Element::CodeBlock { language, code } => {
+                self.preformatted(gemtext, language.as_deref().unwrap_or_default(), code);
+            }
+            Element::List { elements, numbered } => {
+                self.list(gemtext, elements, *numbered)?;
+                gemtext.push('\n');
+            }
+            Element::Chart { .. } => self.block(gemtext, &element.chart_fallback())?,
+            Element::Table { headers, rows, .. } => self.table(gemtext, headers, rows)?,
+            // The term is a line of its own, its definitions are listed below it
+            Element::DefinitionList { items } => {
+                for item in items {
+                    let mut term = String::new();
+                    for child in &item.term {
+                        term.push_str(&self.inline(child)?);
+                    }
+                    gemtext.push_str(&escape(term.replace('\n', " ").trim()));
+                    gemtext.push('\n');
+                    for definition in &item.definitions {
+                        let text = self.inline(definition)?.replace('\n', " ");
+                        gemtext.push_str(&format!("* {}\n", text.trim()));
+                    }
+                }
+                gemtext.push('\n');
+            }
+            Element::Image(image) => {
+                self.image(image, "")?;
+            }
+            Element::Figure { .. } => {
+                self.inline(element)?;
+            }
+            Element::Blockquote { elements, .. } => {
+                for child in elements {
+                    match child {
+                        Element::Paragraph { .. } | Element::Text { .. } => {
+                            let text = self.inline(child)?;
+                            for line in text.trim().lines() {
+                                gemtext.push_str(&format!("> {}\n", line.trim()));
+                            }
+                            if !text.trim().is_empty() {
+                                gemtext.push('\n');
+                            }
+                        }
+                        child => self.block(gemtext, child)?,
+                    }
+                }
+            }
+            Element::Section { children, .. } => {
+                for child in children {
+                    self.block(gemtext, child)?;
+                }
+            }
+            // Gemtext has no comments or pages
+            Element::Comment { .. } | Element::PageBreak | Element::TableOfContents { .. } => {}
+            element => {
+                let text = self.inline(element)?;
+                for line in text.trim().lines() {
+                    gemtext.push_str(&escape(line.trim()));
+                    gemtext.push('\n');
+                }
+                if !text.trim().is_empty() {
+                    gemtext.push('\n');
+                }
+            }
+        }
+        self.flush_links(gemtext);
+        Ok(())
+    }
+
+    fn flush_links(&mut self, gemtext: &mut String) {
+        if self.links.is_empty() {
+            return;
+        }
+        for link in self.links.drain(..) {
+            gemtext.push_str(&link);
+            gemtext.push('\n');
+        }
+        gemtext.push('\n');
+    }
+
+    /// A line starting with three backticks would end the block, it is indented
+    /// The alt text after the opening fence tells readers what the block holds
+    fn preformatted(&self, gemtext: &mut String, alt: &str, text: &str) {
+        gemtext.push_str(&format!("```{}\n", alt));
+        for line in text.lines() {
+            if line.starts_with("```") {
+                gemtext.push(' ');
+            }
+            gemtext.push_str(line);
+            gemtext.push('\n');
+        }
+        gemtext.push_str("```\n\n");
+    }
+
+    /// Gemtext lists are flat, nested items follow their parent and numbers are written out
+    fn list(
+        &mut self,
+        gemtext: &mut String,
+        items: &[ListItem],
+        numbered: bool,
+    ) -> anyhow::Result<()> {
+        let mut number = 0;
+        for item in items {
+            match &item.element {
+                Element::List { elements, numbered } => self.list(gemtext, elements, *numbered)?,
+                element => {
+                    number += 1;
+                    let text = self.inline(element)?.replace('\n', " ");
+                    if numbered {
+                        gemtext.push_str(&format!("* {}. {}\n", number, text.trim()));
+                    } else {
+                        gemtext.push_str(&format!("* {}\n", text.trim()));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Columns are padded to their widest cell inside a preformatted block
+    fn table(
+        &mut self,
+        gemtext: &mut String,
+        headers: &[TableHeader],
+        rows: &[TableRow],
+    ) -> anyhow::Result<()> {
+        let mut lines: Vec<Vec<String>> = Vec::new();
+        if !headers.is_empty() {
+            let mut cells = Vec::new();
+            for header in headers {
+                cells.push(self.inline(&header.element)?.replace('\n', " "));
+            }
+            lines.push(cells);
+        }
+        for row in rows {
+            let mut cells = Vec::new();
+            for cell in &row.cells {
+                cells.push(self.inline(&cell.element())?.replace('\n', " "));
+            }
+            lines.push(cells);
+        }
+        let columns = lines.iter().map(Vec::len).max().unwrap_or(0);
+        let widths: Vec<usize> = (0..columns)
+            .map(|column| {
+                lines
+                    .iter()
+                    .filter_map(|cells| cells.get(column))
+                    .map(|cell| cell.trim().chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        let mut text = String::new();
+        for (index, cells) in lines.iter().enumerate() {
+            let line: Vec<String> = widths
+                .iter()
+                .enumerate()
+                .map(|(column, width)| {
+                    let cell = cells.get(column).map_or("", |cell| cell.trim());
+                    format!("{:width$}", cell, width = width)
+                })
+                .collect();
+            text.push_str(line.join(" | ").trim_end());
+            text.push('\n');
+            if index == 0 && !headers.is_empty() {
+                let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+                text.push_str(&rule.join("-+-"));
+                text.push('\n');
+            }
+        }
+        self.preformatted(gemtext, "", text.trim_end());
+        Ok(())
+    }
+
+    fn inline(&mut self, element: &Element) -> anyhow::Result<String> {
+        Ok(match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => text.clone(),
+            // Gemtext has no emphasis, only the words are kept
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            }
+            | Element::Styled { elements, .. } => {
+                let mut text = String::new();
+                for child in elements {
+                    text.push_str(&self.inline(child)?);
+                }
+                text
+            }
+            Element::Hyperlink { title, url, .. } => {
+                let title = if title.is_empty() { url } else { title };
+                if title == url {
+                    self.links.push(format!("=> {}", url));
+                } else {
+                    self.links.push(format!("=> {} {}", url, title));
+                }
+                title.clone()
+            }
+            Element::Image(image) => {
+                self.image(image, "")?;
+                String::new()
+            }
+            // The caption labels the link to the image
+            Element::Figure { image, caption } => {
+                let mut label = String::new();
+                for child in caption {
+                    label.push_str(&self.inline(child)?);
+                }
+                self.image(image, label.trim())?;
+                String::new()
+            }
+            Element::LineBreak | Element::HorizontalRule => "\n".to_string(),
+            Element::List { elements, .. } => {
+                let mut items = Vec::new();
+                for item in elements {
+                    items.push(self.inline(&item.element)?);
+                }
+                items.join(" ")
+            }
+            Element::DefinitionList { items } => {
+                let mut texts = Vec::new();
+                for child in items
+                    .iter()
+                    .flat_map(|item| item.term.iter().chain(&item.definitions))
+                {
+                    texts.push(self.inline(child)?);
+                }
+                texts.join(" ")
+            }
+            Element::CodeBlock { code, .. } => code.clone(),
+            Element::Math { tex, .. } => tex.clone(),
+            Element::FootnoteReference { id } => format!("[{}]", id),
+            Element::Footnote { id, content } => {
+                let mut text = format!("[{}] ", id);
+                for child in content {
+                    text.push_str(&self.inline(child)?);
+                }
+                text
+            }
+            Element::Media { .. } => self.inline(&element.media_fallback())?,
+            Element::Ruby { .. } => self.inline(&element.ruby_fallback())?,
+            Element::Table { .. }
+            | Element::Chart { .. }
+            | Element::Comment { .. }
+            | Element::PageBreak
+            | Element::TableOfContents { .. }
+            | Element::Custom { .. } => String::new(),
+        })
+    }
+
+    /// Saves the image and adds a link to it
+    fn image(&mut self, image: &ImageData, label: &str) -> anyhow::Result<()> {
+        if image.bytes().is_empty() {
+            return Ok(());
+        }
+        let target = format!(
+            "image{}{}",
+            self.image_num,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &target)?;
+        self.image_num += 1;
+
+        let title = [label, image.title(), image.alt()]
+            .into_iter()
+            .find(|text| !text.is_empty())
+            .unwrap_or(&target)
+            .replace('\n', " ");
+        self.links.push(format!("=> {} {}", target, title));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gemtext::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 4,
+                text: "Deep".to_string(),
+                anchor: None,
+            },
+            Element::Paragraph {
+                elements: vec![
+                    text("# Not a header, see "),
+                    Element::Hyperlink {
+                        title: "the capsule".to_string(),
+                        url: "gemini://example.org/".to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    },
+                    Element::LineBreak,
+                    text("Second line"),
+                ],
+            },
+            Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("One"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: Element::List {
+                            elements: vec![ListItem {
+                                element: text("Nested"),
+                                checked: None,
+                            }],
+                            numbered: false,
+                        },
+                        checked: None,
+                    },
+                ],
+                numbered: true,
+            },
+            Element::Table {
+                headers: vec![
+                    TableHeader {
+                        element: text("Name"),
+                        width: 30.0,
+                    },
+                    TableHeader {
+                        element: text("Size"),
+                        width: 30.0,
+                    },
+                ],
+                rows: vec![TableRow {
+                    cells: vec![TableCell::new(text("shiva")), TableCell::new(text("1"))],
+                }],
+                alignments: vec![],
+                caption: vec![],
+            },
+            Element::Image(ImageData::new(
+                Bytes::from_static(b"\x89PNG"),
+                "Logo".to_string(),
+                String::new(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension::default(),
+            )),
+            text("```\ncode"),
+        ]);
+        let saved = RefCell::new(Vec::new());
+        let generated = Transformer::generate_with_saver(&document, |_, name| {
+            saved.borrow_mut().push(name.to_string());
+            Ok(())
+        })?;
+        assert_eq!(
+            String::from_utf8(generated.to_vec())?,
+            concat!(
+                "### Deep\n\n",
+                " # Not a header, see the capsule\nSecond line\n\n",
+                "=> gemini://example.org/ the capsule\n\n",
+                "* 1. One\n* Nested\n\n",
+                "```\nName  | Size\n------+-----\nshiva | 1\n```\n\n",
+                "=> image0.png Logo\n\n",
+                "```\n ```\ncode\n```\n",
+            )
+        );
+        assert_eq!(saved.into_inner(), vec!["image0.png"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_document() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let document = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let saved = RefCell::new(Vec::new());
+        let generated = Transformer::generate_with_saver(&document, |_, name| {
+            saved.borrow_mut().push(name.to_string());
+            Ok(())
+        })?;
+        let gemtext = String::from_utf8(generated.to_vec())?;
+        for name in saved.into_inner() {
+            assert!(gemtext.contains(&format!("=> {}", name)));
+        }
+        // Preformatted blocks are balanced
+        assert_eq!(
+            gemtext
+                .lines()
+                .filter(|line| line.starts_with("```"))
+                .count()
+                % 2,
+            0
+        );
+        Ok(())
+    }
+}
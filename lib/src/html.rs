@@ -2,9 +2,20 @@ use crate::core::*;
 use bytes::Bytes;
 use ego_tree::{iter::Children, NodeRef};
 
-use crate::core::Element::{Header, Hyperlink, Image, List, Paragraph, Table, Text};
-use scraper::{Html, Node};
+use crate::core::Element::{
+    Blockquote, Chart, CodeBlock, Comment, DefinitionList, Figure, Footnote, FootnoteReference,
+    Header, HorizontalRule, Hyperlink, Image, LineBreak, List, Math, Media, PageBreak, Paragraph,
+    Ruby, Section, Styled, Table, TableOfContents, Text,
+};
+use html5ever::tendril::TendrilSink;
+use scraper::{CaseSensitivity, Html, Node, Selector};
+use std::io::Read;
 
+/// An empty block ending the page, browsers honor it when printing
+pub(crate) const PAGE_BREAK: &str = "<div style=\"page-break-after: always\"></div>";
+
+/// Parsed documents have no [`Document::source_spans`], the HTML parser does not report where
+/// the nodes it builds are in the input
 pub struct Transformer;
 
 impl TransformerTrait for Transformer {
@@ -15,6 +26,28 @@ impl TransformerTrait for Transformer {
     fn generate(document: &Document) -> anyhow::Result<Bytes> {
         Transformer::generate_with_saver(document, disk_image_saver("."))
     }
+
+    fn parse_reader(reader: impl Read) -> anyhow::Result<Document> {
+        Transformer::parse_reader_with_loader(reader, disk_image_loader("."))
+    }
+}
+
+impl Transformer {
+    /// Builds the tree as the HTML is read, without holding the whole input. Invalid UTF-8 is
+    /// replaced instead of failing as [`TransformerWithImageLoaderSaverTrait::parse_with_loader`]
+    /// does.
+    pub fn parse_reader_with_loader<F>(
+        mut reader: impl Read,
+        image_loader: F,
+    ) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let html = html5ever::driver::parse_document(Html::new_document(), Default::default())
+            .from_utf8()
+            .read_from(&mut reader)?;
+        parse_tree(&html, image_loader)
+    }
 }
 impl TransformerWithImageLoaderSaverTrait for Transformer {
     fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
@@ -22,18 +55,7 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
         F: Fn(&str) -> anyhow::Result<Bytes>,
     {
         let html = String::from_utf8(document.to_vec())?;
-        let document = Html::parse_document(&html);
-        let mut elements: Vec<Element> = Vec::new();
-
-        let image_loader = ImageLoader {
-            function: image_loader,
-        };
-        parse_html(
-            document.root_element().children(),
-            &mut elements,
-            &image_loader,
-        )?;
-        Ok(Document::new(elements))
+        parse_tree(&Html::parse_document(&html), image_loader)
     }
 
     fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
@@ -49,7 +71,7 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
         //TODO: Is this needed? Commented out for now! header_text and footer_text are not read anywhere
         let mut header_text = String::new();
         document.get_page_header().iter().for_each(|el| match el {
-            Text { text, size: _ } => {
+            Text { text, font: _ } => {
                 header_text.push_str(text);
             }
             _ => {}
@@ -57,26 +79,79 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
         let mut footer_text = String::new();
 
         document.get_page_footer().iter().for_each(|el| match el {
-            Text { text, size: _ } => {
+            Text { text, font: _ } => {
                 footer_text.push_str(text);
             }
             _ => {}
         });
 
-        html.push_str("<!DOCTYPE html>\n<html>\n<body>\n");
+        let language = document
+            .metadata
+            .language
+            .as_deref()
+            .map(|language| format!(" lang=\"{}\"", escape_code(language)))
+            .unwrap_or_default();
+        let direction = match document.direction {
+            TextDirection::Ltr => "",
+            TextDirection::Rtl => " dir=\"rtl\"",
+        };
+        html.push_str(&format!("<!DOCTYPE html>\n<html{language}{direction}>\n"));
+        html.push_str(&head(&document.metadata));
+        html.push_str("<body>\n");
 
         let all_elements: Vec<&Element> = document.get_all_elements();
+        // Footnotes are collected at the end of the body
+        let mut footnotes = Vec::new();
 
-        for element in &all_elements {
+        // A section opens a `section` element around its children, the `None` after them
+        // closes it
+        let mut pending: Vec<Option<&Element>> = all_elements.into_iter().rev().map(Some).collect();
+        while let Some(next) = pending.pop() {
+            let Some(element) = next else {
+                html.push_str("</section>\n");
+                continue;
+            };
             match element {
-                Element::Header { level, text } => {
-                    html.push_str(&format!("<h{}>{}</h{}>\n", level, text, level));
+                Section { children, .. } => {
+                    html.push_str("<section>\n");
+                    pending.push(None);
+                    pending.extend(children.iter().rev().map(Some));
                 }
-                Element::Text { text, size: _ } => {
+                Element::Header {
+                    level,
+                    text,
+                    anchor,
+                } => {
+                    html.push_str(&format!(
+                        "<h{}{}>{}</h{}>\n",
+                        level,
+                        id_attribute(anchor),
+                        text,
+                        level
+                    ));
+                }
+                Element::Text { text, font: _ } => {
                     html.push_str(&format!("<p>{}</p>\n", text));
                 }
                 Paragraph { elements } => {
-                    html.push_str("<p>");
+                    // A paragraph in a direction of its own sets it on the paragraph
+                    let elements = match elements.as_slice() {
+                        [Styled { style, elements }]
+                            if style.direction.is_some()
+                                && *style
+                                    == TextStyle {
+                                        direction: style.direction,
+                                        ..Default::default()
+                                    } =>
+                        {
+                            html.push_str(&format!("<p{}>", dir_attribute(style.direction)));
+                            elements
+                        }
+                        _ => {
+                            html.push_str("<p>");
+                            elements
+                        }
+                    };
 
                     for child in elements {
                         html.push_str(&generate_html_for_element(
@@ -96,47 +171,70 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
 
                     html.push_str(&list);
                 }
-                Table { headers, rows } => {
-                    let mut table_html = String::from("<table  border=\"1\">\n");
-
-                    if !headers.is_empty() {
-                        table_html.push_str("<tr>\n");
-
-                        for header in headers {
-                            let header_html = generate_html_for_element(
-                                &header.element,
-                                &mut image_num,
-                                &image_saver,
-                            )?;
-
-                            table_html.push_str(&format!("<th>{}</th>\n", header_html));
-                        }
-
-                        table_html.push_str("</tr>\n");
-                    }
-                    for row in rows {
-                        table_html.push_str("<tr>\n");
-
-                        for cell in &row.cells {
-                            let cell_html = generate_html_for_element(
-                                &cell.element,
-                                &mut image_num,
-                                &image_saver,
-                            )?;
-
-                            table_html.push_str(&format!("<td>{}</td>\n", cell_html));
-                        }
-
-                        table_html.push_str("</tr>\n");
-                    }
-
-                    table_html.push_str("</table>\n");
-                    html.push_str(&table_html)
+                Table {
+                    headers,
+                    rows,
+                    alignments,
+                    caption,
+                } => html.push_str(&table_html(
+                    headers,
+                    rows,
+                    alignments,
+                    caption,
+                    &mut image_num,
+                    &image_saver,
+                )?),
+                // A review comment on its own is an aside, in text it is a span
+                Comment {
+                    author,
+                    date,
+                    text,
+                    range,
+                } if author.is_some() || date.is_some() || range.is_some() => {
+                    html.push_str(&format!(
+                        "<aside class=\"comment\"{}>{}</aside>\n",
+                        comment_attributes(author, date, range),
+                        escape_code(text)
+                    ));
+                }
+                Comment { .. }
+                | CodeBlock { .. }
+                | Blockquote { .. }
+                | HorizontalRule
+                | PageBreak
+                | DefinitionList { .. }
+                | Figure { .. }
+                | Media { .. }
+                | Chart { .. } => {
+                    let block = generate_html_for_element(element, &mut image_num, &image_saver)?;
+                    html.push_str(&block);
+                    html.push('\n');
+                }
+                Styled { .. } | Math { .. } | Ruby { .. } => {
+                    let span = generate_html_for_element(element, &mut image_num, &image_saver)?;
+                    html.push_str(&format!("<p>{}</p>\n", span));
                 }
+                Footnote { .. } => footnotes.push(element),
+                TableOfContents { max_level } => html.push_str(&format!(
+                    "<nav class=\"toc\">{}</nav>\n",
+                    toc_html(&document.toc_entries(*max_level))
+                )),
                 _ => {}
             }
         }
 
+        if !footnotes.is_empty() {
+            html.push_str("<section class=\"footnotes\">\n");
+            for footnote in footnotes {
+                html.push_str(&generate_html_for_element(
+                    footnote,
+                    &mut image_num,
+                    &image_saver,
+                )?);
+            }
+            html.push_str("</section>\n");
+        }
+
         html.push_str("</body>\n</html>");
 
         Ok(Bytes::from(html))
@@ -157,6 +255,35 @@ where
     pub function: F,
 }
 
+fn parse_tree<F>(document: &Html, image_loader: F) -> anyhow::Result<Document>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    let mut elements: Vec<Element> = Vec::new();
+
+    let image_loader = ImageLoader {
+        function: image_loader,
+    };
+    // Start from the document node, comments before `<html>` are not children of the root element
+    parse_html(
+        document.tree.root().children(),
+        &mut elements,
+        &image_loader,
+    )?;
+    let mut parsed = Document::new(elements);
+    // The direction of the whole document is set on the root or on the body
+    let root = document.root_element();
+    let body = root
+        .children()
+        .filter_map(|node| node.value().as_element())
+        .find(|element| element.name() == "body");
+    if let Some(direction) = body.and_then(direction).or_else(|| direction(root.value())) {
+        parsed.direction = direction;
+    }
+    parsed.metadata = parse_metadata(document);
+    Ok(parsed)
+}
+
 fn parse_html<F>(
     children: Children<Node>,
     elements: &mut Vec<Element>,
@@ -165,13 +292,35 @@ fn parse_html<F>(
 where
     F: Fn(&str) -> anyhow::Result<Bytes>,
 {
+    // Breaks after an element are pushed before the next one, the element may end the iteration
+    let mut break_after = false;
     for child in children {
+        if std::mem::take(&mut break_after) {
+            elements.push(PageBreak);
+        }
+        if let Node::Element(ref element) = child.value() {
+            if breaks_page(element, "before") {
+                elements.push(PageBreak);
+            }
+            break_after = breaks_page(element, "after");
+        }
         match child.value() {
             Node::Element(ref element) => match element.name() {
                 "table" => {
                     let mut headers: Vec<TableHeader> = Vec::new();
                     let mut rows: Vec<TableRow> = Vec::new();
+                    let mut alignments: Vec<ColumnAlignment> = Vec::new();
+                    let mut caption: Vec<Element> = Vec::new();
+                    let mut grid = TableGrid::default();
                     for table_child in child.children() {
+                        if table_child
+                            .value()
+                            .as_element()
+                            .is_some_and(|element| element.name() == "caption")
+                        {
+                            parse_html(table_child.children(), &mut caption, image_loader)?;
+                            continue;
+                        }
                         for child in table_child.children() {
                             match child.value() {
                                 Node::Element(ref table_element) => match table_element.name() {
@@ -184,6 +333,11 @@ where
                                                     match tr_element.name() {
                                                         "th" => {
                                                             is_header = true;
+                                                            align_column(
+                                                                &mut alignments,
+                                                                headers.len(),
+                                                                tr_element,
+                                                            );
                                                             let mut header_elements: Vec<Element> =
                                                                 Vec::new();
                                                             parse_html(
@@ -208,11 +362,25 @@ where
                                                                 &mut cell_elements,
                                                                 image_loader,
                                                             )?;
-                                                            cells.extend(
-                                                                cell_elements.into_iter().map(
-                                                                    |element| TableCell { element },
+                                                            // Every cell is kept, an empty one
+                                                            // still takes its place in the row
+                                                            let cell = TableCell {
+                                                                elements: cell_blocks(
+                                                                    cell_elements,
+                                                                ),
+                                                                colspan: span(
+                                                                    tr_element, "colspan",
+                                                                ),
+                                                                rowspan: span(
+                                                                    tr_element, "rowspan",
                                                                 ),
+                                                            };
+                                                            align_column(
+                                                                &mut alignments,
+                                                                grid.place(&cell),
+                                                                tr_element,
                                                             );
+                                                            cells.push(cell);
                                                         }
                                                         _ => { /*  */ }
                                                     }
@@ -221,6 +389,7 @@ where
                                             }
                                         }
                                         if !is_header {
+                                            grid.end_row();
                                             rows.push(TableRow { cells });
                                         }
                                     }
@@ -231,12 +400,33 @@ where
                         }
                     }
                     if !headers.is_empty() || !rows.is_empty() {
-                        elements.push(Table { headers, rows });
+                        elements.push(Table {
+                            headers,
+                            rows,
+                            alignments,
+                            caption,
+                        });
                     }
                 }
                 "p" | "title" => {
                     let mut paragraph_elements: Vec<Element> = Vec::new();
                     parse_html(child.children(), &mut paragraph_elements, image_loader)?;
+                    // A formula on its own is a block rather than a paragraph
+                    if let [Math { display: true, .. }] = paragraph_elements.as_slice() {
+                        elements.append(&mut paragraph_elements);
+                        continue;
+                    }
+                    if let Some(direction) = direction(element) {
+                        if !paragraph_elements.is_empty() {
+                            paragraph_elements = vec![Styled {
+                                style: TextStyle {
+                                    direction: Some(direction),
+                                    ..Default::default()
+                                },
+                                elements: paragraph_elements,
+                            }];
+                        }
+                    }
                     elements.push(Paragraph {
                         elements: paragraph_elements,
                     });
@@ -259,7 +449,12 @@ where
                             .join(" ");
                     }
 
-                    elements.push(Header { text, level });
+                    let anchor = element.id().map(str::to_string);
+                    elements.push(Header {
+                        text,
+                        level,
+                        anchor,
+                    });
                 }
                 "img" => {
                     let src = element.attr("src").unwrap_or_default();
@@ -278,6 +473,34 @@ where
                         ImageDimension { width, height },
                     )));
                 }
+                // The source is the `src` of the element or of its first `<source>`
+                "video" | "audio" => {
+                    let src = element.attr("src").or_else(|| {
+                        child
+                            .children()
+                            .filter_map(|node| node.value().as_element())
+                            .find(|source| source.name() == "source")
+                            .and_then(|source| source.attr("src"))
+                    });
+                    if let Some(src) = src {
+                        let poster = match element.attr("poster") {
+                            Some(poster) => Some(ImageData::new(
+                                (image_loader.function)(poster)?,
+                                String::new(),
+                                String::new(),
+                                poster.to_string(),
+                                String::new(),
+                                ImageDimension::default(),
+                            )),
+                            None => None,
+                        };
+                        elements.push(Media {
+                            kind: element.name().parse()?,
+                            source: MediaSource::from_src(src),
+                            poster,
+                        });
+                    }
+                }
                 "ul" | "ol" => {
                     let mut list_items: Vec<ListItem> = Vec::new();
                     let numbered = element.name() == "ol";
@@ -290,11 +513,24 @@ where
                                     &mut item_elements,
                                     image_loader,
                                 )?;
-                                list_items.extend(
-                                    item_elements
-                                        .into_iter()
-                                        .map(|element| ListItem { element }),
-                                );
+                                // A checkbox makes the item a task list item
+                                let checked = list_child.descendants().find_map(|node| match node
+                                    .value()
+                                {
+                                    Node::Element(input)
+                                        if input.name() == "input"
+                                            && input.attr("type") == Some("checkbox") =>
+                                    {
+                                        Some(input.attr("checked").is_some())
+                                    }
+                                    _ => None,
+                                });
+                                list_items.extend(item_elements.into_iter().enumerate().map(
+                                    |(index, element)| ListItem {
+                                        element,
+                                        checked: checked.filter(|_| index == 0),
+                                    },
+                                ));
                             }
                         }
                     }
@@ -303,6 +539,268 @@ where
                         numbered,
                     });
                 }
+                "dl" => {
+                    let mut items: Vec<DefinitionItem> = Vec::new();
+                    for dl_child in child.children() {
+                        let Node::Element(ref dl_element) = dl_child.value() else {
+                            continue;
+                        };
+                        let mut content: Vec<Element> = Vec::new();
+                        parse_html(dl_child.children(), &mut content, image_loader)?;
+                        match dl_element.name() {
+                            "dt" => items.push(DefinitionItem {
+                                term: content,
+                                definitions: vec![],
+                            }),
+                            "dd" => {
+                                if items.is_empty() {
+                                    items.push(DefinitionItem {
+                                        term: vec![],
+                                        definitions: vec![],
+                                    });
+                                }
+                                // Several blocks make up a single definition
+                                let definition = if content.len() == 1 {
+                                    content.remove(0)
+                                } else {
+                                    Paragraph { elements: content }
+                                };
+                                if let Some(item) = items.last_mut() {
+                                    item.definitions.push(definition);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    elements.push(DefinitionList { items });
+                }
+                "hr" => elements.push(HorizontalRule),
+                "aside" | "span"
+                    if element.has_class("comment", CaseSensitivity::CaseSensitive) =>
+                {
+                    let attribute = |name: &str| element.attr(name).map(str::to_string);
+                    let text: String = child
+                        .descendants()
+                        .filter_map(|node| node.value().as_text())
+                        .map(|text| &**text)
+                        .collect();
+                    elements.push(Comment {
+                        author: attribute("data-author"),
+                        date: attribute("data-date"),
+                        text: text.trim().to_string(),
+                        range: attribute("data-range"),
+                    });
+                }
+                "figure" => {
+                    let mut content: Vec<Element> = Vec::new();
+                    parse_html(child.children(), &mut content, image_loader)?;
+                    let mut caption: Vec<Element> = Vec::new();
+                    let figcaption = child.children().find(|node| {
+                        node.value()
+                            .as_element()
+                            .is_some_and(|element| element.name() == "figcaption")
+                    });
+                    if let Some(figcaption) = figcaption {
+                        parse_html(figcaption.children(), &mut caption, image_loader)?;
+                    }
+                    // Only a single image is a figure, other content is kept as it is
+                    match content.as_slice() {
+                        [Image(_)] => {
+                            let Some(Image(image)) = content.pop() else {
+                                unreachable!()
+                            };
+                            elements.push(Figure { image, caption });
+                        }
+                        // The caption of a quote names its source, after a dash
+                        [Blockquote { attribution, .. }] if attribution.is_empty() => {
+                            if let Some(Text { text, .. }) = caption.first_mut() {
+                                *text = text.trim_start_matches(['—', '―', '-', ' ']).to_string();
+                            }
+                            let Some(Blockquote {
+                                elements: quote, ..
+                            }) = content.pop()
+                            else {
+                                unreachable!()
+                            };
+                            elements.push(Blockquote {
+                                elements: quote,
+                                attribution: caption,
+                            });
+                        }
+                        _ => {
+                            elements.append(&mut content);
+                            if !caption.is_empty() {
+                                elements.push(Paragraph { elements: caption });
+                            }
+                        }
+                    }
+                }
+                // Captions are read with their figure
+                "figcaption" => {}
+                "blockquote" => {
+                    let mut quote_elements: Vec<Element> = Vec::new();
+                    parse_html(child.children(), &mut quote_elements, image_loader)?;
+                    elements.push(Blockquote {
+                        elements: quote_elements,
+                        attribution: vec![],
+                    });
+                }
+                "sup" if element.has_class("footnote-ref", CaseSensitivity::CaseSensitive) => {
+                    let id = child
+                        .descendants()
+                        .filter_map(|node| node.value().as_element())
+                        .find_map(|link| link.attr("href")?.strip_prefix("#fn-"));
+                    if let Some(id) = id {
+                        elements.push(FootnoteReference { id: id.to_string() });
+                    }
+                }
+                // The label is written again from the id
+                "sup" if element.has_class("footnote-label", CaseSensitivity::CaseSensitive) => {}
+                "div" if element.has_class("footnote", CaseSensitivity::CaseSensitive) => {
+                    let mut content: Vec<Element> = Vec::new();
+                    parse_html(child.children(), &mut content, image_loader)?;
+                    let id = element.id().unwrap_or_default();
+                    elements.push(Footnote {
+                        id: id.strip_prefix("fn-").unwrap_or(id).to_string(),
+                        content,
+                    });
+                }
+                "b" | "strong" | "i" | "em" | "u" | "ins" | "s" | "strike" | "del" | "sup"
+                | "sub" | "mark" => {
+                    let style = match element.name() {
+                        "b" | "strong" => TextStyle::BOLD,
+                        "i" | "em" => TextStyle::ITALIC,
+                        "u" | "ins" => TextStyle::UNDERLINE,
+                        "sup" => TextStyle::SUPERSCRIPT,
+                        "sub" => TextStyle::SUBSCRIPT,
+                        // Marked text is yellow unless its style names another color
+                        "mark" => TextStyle {
+                            highlight: Some(
+                                style_property(element, "background-color")
+                                    .and_then(|color| color.to_ascii_lowercase().parse().ok())
+                                    .unwrap_or_default(),
+                            ),
+                            ..Default::default()
+                        },
+                        _ => TextStyle::STRIKETHROUGH,
+                    };
+                    let mut styled_elements: Vec<Element> = Vec::new();
+                    parse_html(child.children(), &mut styled_elements, image_loader)?;
+                    if !styled_elements.is_empty() {
+                        elements.push(Styled {
+                            style,
+                            elements: styled_elements,
+                        });
+                    }
+                }
+                // MathML keeps the TeX source in an annotation when it was converted from TeX,
+                // otherwise the characters of the formula are all that is kept
+                "math" => {
+                    let annotation = child.descendants().find(|node| {
+                        node.value().as_element().is_some_and(|annotation| {
+                            annotation.name() == "annotation"
+                                && annotation.attr("encoding") == Some("application/x-tex")
+                        })
+                    });
+                    let tex: String = annotation
+                        .unwrap_or(child)
+                        .descendants()
+                        .filter_map(|node| node.value().as_text().map(|text| text.to_string()))
+                        .collect();
+                    elements.push(Math {
+                        tex: tex.trim().to_string(),
+                        display: element.attr("display") == Some("block"),
+                    });
+                }
+                // The base is the text outside `rt`, readings split over several `rt` are joined
+                "ruby" => {
+                    let (mut base, mut annotation) = (String::new(), String::new());
+                    for node in child.children() {
+                        match node.value() {
+                            Node::Text(text) => base.push_str(text),
+                            Node::Element(part) if part.name() == "rt" => {
+                                annotation.push_str(&retrieve_deep_text(node, "rt"))
+                            }
+                            Node::Element(part) if part.name() == "rb" => {
+                                base.push_str(&retrieve_deep_text(node, "rb"))
+                            }
+                            _ => {}
+                        }
+                    }
+                    elements.push(Ruby {
+                        base: base.trim().to_string(),
+                        annotation: annotation.trim().to_string(),
+                    });
+                }
+                // MathJax delimiters, as in the generated markup
+                "span" | "div" if element.has_class("math", CaseSensitivity::CaseSensitive) => {
+                    let text: String = child
+                        .descendants()
+                        .filter_map(|node| node.value().as_text().map(|text| text.to_string()))
+                        .collect();
+                    let text = text.trim();
+                    let (tex, display) = if let Some(tex) = text
+                        .strip_prefix("\\[")
+                        .and_then(|tex| tex.strip_suffix("\\]"))
+                    {
+                        (tex, true)
+                    } else if let Some(tex) = text
+                        .strip_prefix("\\(")
+                        .and_then(|tex| tex.strip_suffix("\\)"))
+                    {
+                        (tex, false)
+                    } else {
+                        (
+                            text,
+                            element.has_class("display", CaseSensitivity::CaseSensitive),
+                        )
+                    };
+                    elements.push(Math {
+                        tex: tex.to_string(),
+                        display,
+                    });
+                }
+                "span" | "font"
+                    if span_style(element) != TextStyle::default()
+                        || font_spec(element).is_some() =>
+                {
+                    let mut styled_elements: Vec<Element> = Vec::new();
+                    parse_html(child.children(), &mut styled_elements, image_loader)?;
+                    if let Some(font) = font_spec(element) {
+                        set_font(&mut styled_elements, &font);
+                    }
+                    if span_style(element) == TextStyle::default() {
+                        elements.append(&mut styled_elements);
+                    } else if !styled_elements.is_empty() {
+                        elements.push(Styled {
+                            style: span_style(element),
+                            elements: styled_elements,
+                        });
+                    }
+                }
+                "pre" => {
+                    let code = child.descendants().find(|node| {
+                        matches!(node.value(), Node::Element(ref code) if code.name() == "code")
+                    });
+                    // The language is a `language-*` class of the code or the pre element
+                    let language = code
+                        .iter()
+                        .chain([&child])
+                        .filter_map(|node| node.value().as_element())
+                        .flat_map(|element| element.classes())
+                        .find_map(|class| class.strip_prefix("language-"))
+                        .map(|language| language.to_string());
+                    let text: String = child
+                        .descendants()
+                        .filter_map(|node| node.value().as_text().map(|text| text.to_string()))
+                        .collect();
+                    // A newline right after the start tag is not part of the content
+                    let text = text.strip_prefix('\n').unwrap_or(&text);
+                    elements.push(CodeBlock {
+                        language,
+                        code: text.strip_suffix('\n').unwrap_or(text).to_string(),
+                    });
+                }
                 "a" => {
                     let href = element.attr("href").unwrap_or_default().to_string();
                     let text = child
@@ -323,32 +821,339 @@ where
                         size: 8,
                     });
                 }
+                "br" => elements.push(LineBreak),
+                // The title and `<meta>` properties are the metadata of the document
+                "head" => {}
+                // The entries come from the headers, the depth of the lists gives the level
+                "nav" if element.has_class("toc", CaseSensitivity::CaseSensitive) => {
+                    elements.push(TableOfContents {
+                        max_level: list_depth(child),
+                    })
+                }
                 _ => {
                     parse_html(child.children(), elements, image_loader)?;
                 }
             },
+            Node::Comment(ref comment) => {
+                elements.push(Comment {
+                    text: comment.trim().to_string(),
+                    author: None,
+                    date: None,
+                    range: None,
+                });
+            }
             Node::Text(ref text) => {
                 let txt_strings = text.lines().map(str::trim).filter(|p| !p.is_empty());
                 for text_str in txt_strings {
                     elements.push(Text {
                         text: text_str.to_owned(),
-                        size: 8,
+                        font: FontSpec::from(8),
                     });
                 }
             }
             _ => {}
         }
     }
+    if break_after {
+        elements.push(PageBreak);
+    }
     Ok(())
 }
 
+/// Whether the style of an element breaks the page before or after it, in the CSS 2 or the
+/// CSS 3 property
+fn breaks_page(element: &scraper::node::Element, side: &str) -> bool {
+    let legacy = style_property(element, &format!("page-break-{}", side));
+    let current = style_property(element, &format!("break-{}", side));
+    matches!(legacy, Some("always" | "left" | "right"))
+        || matches!(current, Some("page" | "left" | "right" | "recto" | "verso"))
+}
+
+/// A `colspan` or `rowspan` attribute, missing and invalid values span one cell
+fn span(element: &scraper::node::Element, name: &str) -> usize {
+    element
+        .attr(name)
+        .and_then(|value| value.trim().parse().ok())
+        .filter(|span| *span > 0)
+        .unwrap_or(1)
+}
+
+/// Blocks of a table cell, runs of inline content between them become paragraphs
+fn cell_blocks(elements: Vec<Element>) -> Vec<Element> {
+    if elements.len() < 2 {
+        return elements;
+    }
+    let mut blocks = Vec::new();
+    let mut inline = Vec::new();
+    for element in elements {
+        match element {
+            Text { .. }
+            | Styled { .. }
+            | Hyperlink { .. }
+            | Image(_)
+            | LineBreak
+            | Math { .. }
+            | Comment { .. }
+            | FootnoteReference { .. } => inline.push(element),
+            block => {
+                if !inline.is_empty() {
+                    blocks.push(Paragraph {
+                        elements: std::mem::take(&mut inline),
+                    });
+                }
+                blocks.push(block);
+            }
+        }
+    }
+    if !inline.is_empty() {
+        blocks.push(Paragraph { elements: inline });
+    }
+    blocks
+}
+
+/// Grid columns of the cells of a table, taking the cells covered by row spans into account
+#[derive(Default)]
+struct TableGrid {
+    /// Rows still covered by a row span, for each column
+    covered: Vec<usize>,
+    column: usize,
+}
+
+impl TableGrid {
+    /// The column of the next cell in the current row
+    fn place(&mut self, cell: &TableCell) -> usize {
+        while self.covered.get(self.column).is_some_and(|rows| *rows > 0) {
+            self.column += 1;
+        }
+        let column = self.column;
+        let colspan = cell.colspan.max(1);
+        if cell.rowspan > 1 {
+            if self.covered.len() < column + colspan {
+                self.covered.resize(column + colspan, 0);
+            }
+            self.covered[column..column + colspan].fill(cell.rowspan);
+        }
+        self.column += colspan;
+        column
+    }
+
+    fn end_row(&mut self) {
+        for rows in self.covered.iter_mut() {
+            *rows = rows.saturating_sub(1);
+        }
+        self.column = 0;
+    }
+}
+
+/// The value of a property in the inline style of an element
+fn style_property<'a>(element: &'a scraper::node::Element, name: &str) -> Option<&'a str> {
+    element.attr("style")?.split(';').find_map(|declaration| {
+        let (property, value) = declaration.split_once(':')?;
+        (property.trim().eq_ignore_ascii_case(name)).then(|| value.trim())
+    })
+}
+
+/// The text and background colors of an inline style, or of the legacy `color` attribute of
+/// `<font>`, and the direction of the `dir` attribute
+fn span_style(element: &scraper::node::Element) -> TextStyle {
+    let color = |value: Option<&str>| value.and_then(|value| value.parse().ok());
+    TextStyle {
+        color: color(style_property(element, "color").or_else(|| element.attr("color"))),
+        background: color(
+            style_property(element, "background-color")
+                .or_else(|| style_property(element, "background")),
+        ),
+        direction: direction(element),
+        ..Default::default()
+    }
+}
+
+/// The direction of the `dir` attribute, `auto` leaves it to the text
+fn direction(element: &scraper::node::Element) -> Option<TextDirection> {
+    element
+        .attr("dir")?
+        .trim()
+        .to_ascii_lowercase()
+        .parse()
+        .ok()
+}
+
+/// The font of an inline style, or of the legacy `face` attribute of `<font>`. A font read
+/// from the markup always has a weight, normal text has 400
+fn font_spec(element: &scraper::node::Element) -> Option<FontSpec> {
+    let family = style_property(element, "font-family")
+        .or_else(|| element.attr("face"))
+        .and_then(|families| families.split(',').next())
+        .map(|family| family.trim().trim_matches(['"', '\'']).to_string())
+        .filter(|family| !family.is_empty());
+    // Pixels are 3/4 of a point
+    let size = style_property(element, "font-size").and_then(|size| {
+        let size = size.trim().to_ascii_lowercase();
+        match size.strip_suffix("pt") {
+            Some(points) => points.trim().parse::<f32>().ok(),
+            None => size
+                .strip_suffix("px")
+                .and_then(|pixels| pixels.trim().parse::<f32>().ok())
+                .map(|pixels| pixels * 0.75),
+        }
+    });
+    let weight = style_property(element, "font-weight").and_then(|weight| {
+        match weight.trim().to_ascii_lowercase().as_str() {
+            "normal" => Some(400),
+            "bold" => Some(700),
+            weight => weight.parse().ok(),
+        }
+    });
+    if family.is_none() && size.is_none() && weight.is_none() {
+        return None;
+    }
+    Some(FontSpec {
+        family,
+        size_pt: size.unwrap_or(12.0),
+        weight: weight.or(Some(400)),
+    })
+}
+
+/// Sets the font of the text in parsed inline content, text in a font of its own keeps it
+fn set_font(elements: &mut [Element], font: &FontSpec) {
+    for element in elements {
+        match element {
+            Text {
+                font: text_font, ..
+            } if !text_font.is_explicit() => *text_font = font.clone(),
+            Styled { elements, .. } | Paragraph { elements } => set_font(elements, font),
+            _ => {}
+        }
+    }
+}
+
+/// Sets the alignment of a column from the first of its cells that has one, either as
+/// `text-align` in the style or as the legacy `align` attribute
+fn align_column(
+    alignments: &mut Vec<ColumnAlignment>,
+    column: usize,
+    element: &scraper::node::Element,
+) {
+    let alignment = match style_property(element, "text-align")
+        .or_else(|| element.attr("align"))
+        .map(|value| value.trim().to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("left") | Some("start") => ColumnAlignment::Left,
+        Some("center") => ColumnAlignment::Center,
+        Some("right") | Some("end") => ColumnAlignment::Right,
+        _ => return,
+    };
+    if alignments.len() <= column {
+        alignments.resize(column + 1, ColumnAlignment::None);
+    }
+    if alignments[column] == ColumnAlignment::None {
+        alignments[column] = alignment;
+    }
+}
+
+/// The `text-align` style of a cell in the given column
+fn alignment_style(alignments: &[ColumnAlignment], column: usize) -> String {
+    match alignments.get(column) {
+        Some(ColumnAlignment::None) | None => String::new(),
+        Some(alignment) => format!(" style=\"text-align: {}\"", alignment),
+    }
+}
+
+fn span_attributes(cell: &TableCell) -> String {
+    let mut attributes = String::new();
+    if cell.colspan > 1 {
+        attributes.push_str(&format!(" colspan=\"{}\"", cell.colspan));
+    }
+    if cell.rowspan > 1 {
+        attributes.push_str(&format!(" rowspan=\"{}\"", cell.rowspan));
+    }
+    attributes
+}
+
+/// A table, cells are generated as any other content so a table in a cell is nested
+fn table_html(
+    headers: &[TableHeader],
+    rows: &[TableRow],
+    alignments: &[ColumnAlignment],
+    caption: &[Element],
+    image_num: &mut i32,
+    image_saver: &ImageSaver<impl Fn(&Bytes, &str) -> anyhow::Result<()>>,
+) -> anyhow::Result<String> {
+    let mut table_html = String::from("<table  border=\"1\">\n");
+
+    if !caption.is_empty() {
+        table_html.push_str("<caption>");
+        for element in caption {
+            table_html.push_str(&generate_html_for_element(element, image_num, image_saver)?);
+        }
+        table_html.push_str("</caption>\n");
+    }
+
+    if !headers.is_empty() {
+        table_html.push_str("<tr>\n");
+
+        for (column, header) in headers.iter().enumerate() {
+            let header_html = generate_html_for_element(&header.element, image_num, image_saver)?;
+
+            table_html.push_str(&format!(
+                "<th{}>{}</th>\n",
+                alignment_style(alignments, column),
+                header_html
+            ));
+        }
+
+        table_html.push_str("</tr>\n");
+    }
+    let mut grid = TableGrid::default();
+    for row in rows {
+        table_html.push_str("<tr>\n");
+
+        for cell in &row.cells {
+            let column = grid.place(cell);
+            let mut cell_html = String::new();
+            for element in &cell.elements {
+                cell_html.push_str(&generate_html_for_element(element, image_num, image_saver)?);
+            }
+
+            table_html.push_str(&format!(
+                "<td{}{}>{}</td>\n",
+                span_attributes(cell),
+                alignment_style(alignments, column),
+                cell_html
+            ));
+        }
+        grid.end_row();
+
+        table_html.push_str("</tr>\n");
+    }
+
+    table_html.push_str("</table>\n");
+    Ok(table_html)
+}
+
 fn generate_html_for_element(
     element: &Element,
     image_num: &mut i32,
     image_saver: &ImageSaver<impl Fn(&Bytes, &str) -> anyhow::Result<()>>,
 ) -> anyhow::Result<String> {
     match element {
-        Text { text, size: _ } => Ok(text.to_string()),
+        Text { text, font } if font.is_explicit() => {
+            let mut properties = Vec::new();
+            if let Some(family) = &font.family {
+                properties.push(format!("font-family: '{}'", escape_code(family)));
+            }
+            properties.push(format!("font-size: {}pt", font.size_pt));
+            if let Some(weight) = font.weight {
+                properties.push(format!("font-weight: {}", weight));
+            }
+            Ok(format!(
+                "<span style=\"{}\">{}</span>",
+                properties.join("; "),
+                text
+            ))
+        }
+        Text { text, font: _ } => Ok(text.to_string()),
         Paragraph { elements } => {
             let mut paragraph_html = String::from("<p>");
             for child in elements {
@@ -358,9 +1163,14 @@ fn generate_html_for_element(
             paragraph_html.push_str("</p>");
             Ok(paragraph_html)
         }
-        Header { level, text } => Ok(format!(
-            "<h{level}>{text}</h{level}>",
+        Header {
+            level,
+            text,
+            anchor,
+        } => Ok(format!(
+            "<h{level}{id}>{text}</h{level}>",
             level = level,
+            id = id_attribute(anchor),
             text = text
         )),
         List { elements, numbered } => {
@@ -372,7 +1182,12 @@ fn generate_html_for_element(
                 if let List { .. } = item.element {
                     list_html.push_str(&item_html.to_string());
                 } else {
-                    list_html.push_str(&format!("<li>{}</li>", item_html));
+                    let checkbox = match item.checked {
+                        Some(true) => "<input type=\"checkbox\" disabled checked> ",
+                        Some(false) => "<input type=\"checkbox\" disabled> ",
+                        None => "",
+                    };
+                    list_html.push_str(&format!("<li>{}{}</li>", checkbox, item_html));
                     list_html.push('\n');
                 }
             }
@@ -416,10 +1231,393 @@ fn generate_html_for_element(
             "<a href=\"{}\" title=\"{}\">{}</a>",
             url, alt, title
         )),
+        Figure { image, caption } => {
+            let mut figure_html = String::from("<figure>");
+            figure_html.push_str(&generate_html_for_element(
+                &Image(image.clone()),
+                image_num,
+                image_saver,
+            )?);
+            if !caption.is_empty() {
+                figure_html.push_str("<figcaption>");
+                for child in caption {
+                    figure_html.push_str(&generate_html_for_element(
+                        child,
+                        image_num,
+                        image_saver,
+                    )?);
+                }
+                figure_html.push_str("</figcaption>");
+            }
+            figure_html.push_str("</figure>");
+            Ok(figure_html)
+        }
+        Media {
+            kind,
+            source,
+            poster,
+        } => {
+            let mut poster_attribute = String::new();
+            if let Some(poster) = poster {
+                let image_path = format!("image{}.png", image_num);
+                (image_saver.function)(poster.bytes(), &image_path)?;
+                *image_num += 1;
+                poster_attribute = format!(" poster=\"{}\"", image_path);
+            }
+            Ok(format!(
+                "<{} src=\"{}\" controls{}></{}>",
+                kind,
+                source.to_src(),
+                poster_attribute,
+                kind
+            ))
+        }
+        Chart {
+            chart_type,
+            series,
+            categories,
+        } => Ok(format!(
+            "<figure class=\"chart\">{}</figure>",
+            crate::chart::svg(*chart_type, series, categories)
+        )),
+        LineBreak => Ok("<br />".to_string()),
+        HorizontalRule => Ok("<hr />".to_string()),
+        PageBreak => Ok(PAGE_BREAK.to_string()),
+        Comment {
+            author: None,
+            date: None,
+            text,
+            range: None,
+        } => Ok(format!("<!-- {} -->", text)),
+        Comment {
+            author,
+            date,
+            text,
+            range,
+        } => Ok(format!(
+            "<span class=\"comment\"{}>{}</span>",
+            comment_attributes(author, date, range),
+            escape_code(text)
+        )),
+        CodeBlock { language, code } => {
+            let class = match language {
+                Some(language) => format!(" class=\"language-{}\"", escape_code(language)),
+                None => String::new(),
+            };
+            Ok(format!(
+                "<pre><code{}>{}</code></pre>",
+                class,
+                escape_code(code)
+            ))
+        }
+        DefinitionList { items } => {
+            let mut list_html = String::from("<dl>\n");
+            for item in items {
+                list_html.push_str("<dt>");
+                for child in &item.term {
+                    list_html.push_str(&generate_html_for_element(child, image_num, image_saver)?);
+                }
+                list_html.push_str("</dt>\n");
+                for definition in &item.definitions {
+                    list_html.push_str(&format!(
+                        "<dd>{}</dd>\n",
+                        generate_html_for_element(definition, image_num, image_saver)?
+                    ));
+                }
+            }
+            list_html.push_str("</dl>");
+            Ok(list_html)
+        }
+        Blockquote {
+            elements,
+            attribution,
+        } => {
+            let mut quote_html = String::from("<blockquote>\n");
+            for child in elements {
+                let child_html = generate_html_for_element(child, image_num, image_saver)?;
+                // Bare text is a paragraph of its own inside the quote
+                if let Text { .. } = child {
+                    quote_html.push_str(&format!("<p>{}</p>\n", child_html));
+                } else {
+                    quote_html.push_str(&child_html);
+                    quote_html.push('\n');
+                }
+            }
+            quote_html.push_str("</blockquote>");
+            // The source goes in the caption of a figure around the quote
+            if !attribution.is_empty() {
+                quote_html.insert_str(0, "<figure>\n");
+                quote_html.push_str("\n<figcaption>— ");
+                for child in attribution {
+                    quote_html.push_str(&generate_html_for_element(child, image_num, image_saver)?);
+                }
+                quote_html.push_str("</figcaption>\n</figure>");
+            }
+            Ok(quote_html)
+        }
+        Section { children, .. } => {
+            let mut section_html = String::from("<section>\n");
+            for child in children {
+                let child_html = generate_html_for_element(child, image_num, image_saver)?;
+                if let Text { .. } = child {
+                    section_html.push_str(&format!("<p>{}</p>\n", child_html));
+                } else {
+                    section_html.push_str(&child_html);
+                    section_html.push('\n');
+                }
+            }
+            section_html.push_str("</section>");
+            Ok(section_html)
+        }
+        Styled { style, elements } => {
+            let tags: Vec<&str> = [
+                (style.bold, "strong"),
+                (style.italic, "em"),
+                (style.underline, "u"),
+                (style.strikethrough, "s"),
+                (style.superscript, "sup"),
+                (style.subscript, "sub"),
+            ]
+            .into_iter()
+            .filter_map(|(enabled, tag)| enabled.then_some(tag))
+            .collect();
+            let mut span_html = String::new();
+            match style.highlight {
+                Some(HighlightColor::Yellow) => span_html.push_str("<mark>"),
+                Some(color) => {
+                    span_html.push_str(&format!("<mark style=\"background-color: {}\">", color))
+                }
+                None => {}
+            }
+            let colors: Vec<String> = [
+                ("color", style.color),
+                ("background-color", style.background),
+            ]
+            .into_iter()
+            .filter_map(|(property, color)| Some(format!("{}: {}", property, color?)))
+            .collect();
+            let dir = dir_attribute(style.direction);
+            if !colors.is_empty() {
+                span_html.push_str(&format!("<span style=\"{}\"{}>", colors.join("; "), dir));
+            } else if !dir.is_empty() {
+                span_html.push_str(&format!("<span{}>", dir));
+            }
+            for tag in &tags {
+                span_html.push_str(&format!("<{}>", tag));
+            }
+            for child in elements {
+                span_html.push_str(&generate_html_for_element(child, image_num, image_saver)?);
+            }
+            for tag in tags.iter().rev() {
+                span_html.push_str(&format!("</{}>", tag));
+            }
+            if !colors.is_empty() || !dir.is_empty() {
+                span_html.push_str("</span>");
+            }
+            if style.highlight.is_some() {
+                span_html.push_str("</mark>");
+            }
+            Ok(span_html)
+        }
+        // The delimiters MathJax looks for, in the markup pandoc writes for it
+        Math { tex, display } => Ok(if *display {
+            format!(
+                "<span class=\"math display\">\\[{}\\]</span>",
+                escape_code(tex)
+            )
+        } else {
+            format!(
+                "<span class=\"math inline\">\\({}\\)</span>",
+                escape_code(tex)
+            )
+        }),
+        // Browsers without ruby show the reading in the parentheses
+        Ruby { base, annotation } => Ok(format!(
+            "<ruby>{}<rp>(</rp><rt>{}</rt><rp>)</rp></ruby>",
+            escape_code(base),
+            escape_code(annotation)
+        )),
+        FootnoteReference { id } => Ok(format!(
+            "<sup class=\"footnote-ref\"><a href=\"#fn-{id}\" id=\"fnref-{id}\">{id}</a></sup>",
+            id = escape_code(id)
+        )),
+        // The label goes at the start of the first paragraph of the note
+        Table {
+            headers,
+            rows,
+            alignments,
+            caption,
+        } => table_html(headers, rows, alignments, caption, image_num, image_saver),
+        Footnote { id, content } => {
+            let mut note_html = String::new();
+            for child in content {
+                let child_html = generate_html_for_element(child, image_num, image_saver)?;
+                if let Text { .. } = child {
+                    note_html.push_str(&format!("<p>{}</p>\n", child_html));
+                } else {
+                    note_html.push_str(&child_html);
+                    note_html.push('\n');
+                }
+            }
+            let label = format!("<sup class=\"footnote-label\">{}</sup>", escape_code(id));
+            if note_html.starts_with("<p>") {
+                note_html.insert_str("<p>".len(), &format!("{} ", label));
+            } else {
+                note_html.insert_str(0, &format!("<p>{}</p>\n", label));
+            }
+            Ok(format!(
+                "<div class=\"footnote\" id=\"fn-{}\">\n{}</div>\n",
+                escape_code(id),
+                note_html
+            ))
+        }
         _ => Ok("".to_string()),
     }
 }
 
+/// The author, date and commented text of a review comment as `data-` attributes
+fn comment_attributes(
+    author: &Option<String>,
+    date: &Option<String>,
+    range: &Option<String>,
+) -> String {
+    [("author", author), ("date", date), ("range", range)]
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let value = value.as_deref()?;
+            Some(format!(" data-{}=\"{}\"", name, escape_code(value)))
+        })
+        .collect()
+}
+
+/// Nested ordered lists of the entries of a table of contents, linking to their headers
+pub(crate) fn toc_html(entries: &[TocEntry]) -> String {
+    let mut html = String::from("<ol>");
+    for entry in entries {
+        html.push_str("<li>");
+        match &entry.anchor {
+            Some(anchor) => html.push_str(&format!(
+                "<a href=\"#{}\">{}</a>",
+                escape_code(anchor),
+                escape_code(&entry.text)
+            )),
+            None => html.push_str(&escape_code(&entry.text)),
+        }
+        if !entry.children.is_empty() {
+            html.push_str(&toc_html(&entry.children));
+        }
+        html.push_str("</li>");
+    }
+    html.push_str("</ol>");
+    html
+}
+
+/// How deep lists are nested in a node
+fn list_depth(node: NodeRef<Node>) -> u8 {
+    let depth = node.children().map(list_depth).max().unwrap_or(0);
+    match node.value() {
+        Node::Element(element) if matches!(element.name(), "ol" | "ul") => depth + 1,
+        _ => depth,
+    }
+}
+
+/// The `id` attribute of an element internal links point to
+fn id_attribute(anchor: &Option<String>) -> String {
+    anchor
+        .as_deref()
+        .map(|anchor| format!(" id=\"{}\"", escape_code(anchor)))
+        .unwrap_or_default()
+}
+
+/// The `<title>`, the `<meta>` properties and the `lang` of the root. Subjects are read from
+/// the description, dates from the Dublin Core names
+fn parse_metadata(document: &Html) -> Metadata {
+    let mut metadata = Metadata {
+        language: document.root_element().attr("lang").map(str::to_string),
+        ..Metadata::default()
+    };
+    let title = Selector::parse("head > title").unwrap();
+    metadata.title = document
+        .select(&title)
+        .next()
+        .map(|title| title.text().collect::<String>().trim().to_string());
+    let meta = Selector::parse("head > meta[name][content]").unwrap();
+    for element in document.select(&meta) {
+        let (Some(name), Some(content)) = (element.attr("name"), element.attr("content")) else {
+            continue;
+        };
+        let content = content.trim().to_string();
+        match name.to_ascii_lowercase().as_str() {
+            "author" => metadata.authors.push(content),
+            "description" => metadata.subject = Some(content),
+            "keywords" => metadata.keywords = Metadata::split_keywords(&content),
+            "dcterms.created" => metadata.created = Some(content),
+            "dcterms.modified" => metadata.modified = Some(content),
+            "viewport" | "generator" => {}
+            _ => {
+                metadata.custom.insert(name.to_string(), content);
+            }
+        }
+    }
+    metadata
+}
+
+/// The `<head>` with the title and metadata of a document, documents without any have none
+fn head(metadata: &Metadata) -> String {
+    let mut properties: Vec<(&str, &str)> = Vec::new();
+    properties.extend(metadata.authors.iter().map(|author| ("author", author.as_str())));
+    if let Some(subject) = &metadata.subject {
+        properties.push(("description", subject));
+    }
+    let keywords = metadata.keywords_text();
+    if let Some(keywords) = &keywords {
+        properties.push(("keywords", keywords));
+    }
+    if let Some(created) = &metadata.created {
+        properties.push(("dcterms.created", created));
+    }
+    if let Some(modified) = &metadata.modified {
+        properties.push(("dcterms.modified", modified));
+    }
+    properties.extend(
+        metadata
+            .custom
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str())),
+    );
+    if metadata.title.is_none() && properties.is_empty() {
+        return String::new();
+    }
+    let mut head = String::from("<head>\n<meta charset=\"utf-8\">\n");
+    if let Some(title) = &metadata.title {
+        head.push_str(&format!("<title>{}</title>\n", escape_code(title)));
+    }
+    for (name, content) in properties {
+        head.push_str(&format!(
+            "<meta name=\"{}\" content=\"{}\">\n",
+            escape_code(name),
+            escape_code(content)
+        ));
+    }
+    head.push_str("</head>\n");
+    head
+}
+
+/// The `dir` attribute of text in a direction of its own
+fn dir_attribute(direction: Option<TextDirection>) -> String {
+    direction
+        .map(|direction| format!(" dir=\"{}\"", direction))
+        .unwrap_or_default()
+}
+
+/// Code is written as is, only the characters that would start markup are escaped
+fn escape_code(code: &str) -> String {
+    code.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 fn retrieve_deep_text(node: NodeRef<Node>, tag_name: &str) -> String {
     let mut text = String::new();
     let mut current_node = Some(node);
@@ -517,4 +1715,786 @@ mod tests {
         info!("{}", String::from_utf8(markdown.to_vec())?);
         Ok(())
     }
+    #[test]
+    fn test_parse_reader() -> anyhow::Result<()> {
+        let document_html = "<html dir=\"rtl\"><head><title>Notes</title></head><body>\
+            <h1>Notes</h1><p>Sé <b>bold</b></p><ul><li>One</li></ul></body></html>";
+        // Reads a few bytes at a time, splitting tags and characters
+        let reader = std::io::BufReader::with_capacity(3, document_html.as_bytes());
+        let parsed = Transformer::parse_reader(reader)?;
+        assert_eq!(parsed, Transformer::parse(&Bytes::from(document_html))?);
+        assert_eq!(parsed.metadata.title.as_deref(), Some("Notes"));
+        assert_eq!(parsed.direction, TextDirection::Rtl);
+        Ok(())
+    }
+
+    #[test]
+    fn test_code_block() -> anyhow::Result<()> {
+        let document_html = "<html><body><pre><code class=\"language-rust\">if a &lt; b {\n    swap();\n}\n</code></pre><pre>plain</pre></body></html>";
+        let document = Transformer::parse(&Bytes::from(document_html))?;
+        let elements = document.get_all_elements();
+        assert_eq!(
+            elements[0],
+            &Element::CodeBlock {
+                language: Some("rust".to_string()),
+                code: "if a < b {\n    swap();\n}".to_string()
+            }
+        );
+        assert_eq!(
+            elements[1],
+            &Element::CodeBlock {
+                language: None,
+                code: "plain".to_string()
+            }
+        );
+        let generated = String::from_utf8(Transformer::generate(&document)?.to_vec())?;
+        assert!(generated.contains(
+            "<pre><code class=\"language-rust\">if a &lt; b {\n    swap();\n}</code></pre>"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_blockquote() -> anyhow::Result<()> {
+        let document_html =
+            "<html><body><blockquote><p>Quoted</p><p>Twice</p></blockquote><p>After</p></body></html>";
+        let document = Transformer::parse(&Bytes::from(document_html))?;
+        let elements = document.get_all_elements();
+        let Element::Blockquote {
+            elements: quote, ..
+        } = elements[0]
+        else {
+            panic!("expected a blockquote, got {:?}", elements[0]);
+        };
+        assert_eq!(quote.len(), 2);
+        assert!(matches!(elements[1], Element::Paragraph { .. }));
+
+        let generated = String::from_utf8(Transformer::generate(&document)?.to_vec())?;
+        assert!(generated.contains("<blockquote>\n"));
+        let reparsed = Transformer::parse(&Bytes::from(generated))?;
+        assert_eq!(reparsed.get_all_elements(), elements);
+        Ok(())
+    }
+
+    #[test]
+    fn test_footnote() -> anyhow::Result<()> {
+        let paragraph = |elements: Vec<Element>| Element::Paragraph { elements };
+        let document = Document::new(vec![
+            Element::Footnote {
+                id: "a".to_string(),
+                content: vec![paragraph(vec![Element::Text {
+                    text: "Source".to_string(),
+                    font: FontSpec::from(8),
+                }])],
+            },
+            paragraph(vec![
+                Element::Text {
+                    text: "Claim".to_string(),
+                    font: FontSpec::from(8),
+                },
+                Element::FootnoteReference {
+                    id: "a".to_string(),
+                },
+            ]),
+        ]);
+        let generated = String::from_utf8(Transformer::generate(&document)?.to_vec())?;
+        assert!(generated.contains("<a href=\"#fn-a\" id=\"fnref-a\">a</a>"));
+        // Notes are collected at the end of the body
+        assert!(generated.find("Claim") < generated.find("<section class=\"footnotes\">"));
+        let reparsed = Transformer::parse(&Bytes::from(generated))?;
+        let elements = reparsed.get_all_elements();
+        assert_eq!(elements[0], document.get_all_elements()[1]);
+        assert_eq!(elements[1], document.get_all_elements()[0]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_styled() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![Element::Paragraph {
+            elements: vec![
+                text("Plain"),
+                Element::Styled {
+                    style: TextStyle::BOLD,
+                    elements: vec![
+                        text("bold"),
+                        Element::Styled {
+                            style: TextStyle::ITALIC.combine(TextStyle::UNDERLINE),
+                            elements: vec![text("both")],
+                        },
+                    ],
+                },
+            ],
+        }]);
+        let generated = String::from_utf8(Transformer::generate(&document)?.to_vec())?;
+        assert!(generated.contains("<strong>bold<em><u>both</u></em></strong>"));
+        let reparsed = Transformer::parse(&Bytes::from(generated))?;
+        let Element::Paragraph { elements } = reparsed.get_all_elements()[0] else {
+            panic!("expected a paragraph");
+        };
+        // Every tag of the source becomes a span of its own
+        assert_eq!(
+            elements[1],
+            Element::Styled {
+                style: TextStyle::BOLD,
+                elements: vec![
+                    text("bold"),
+                    Element::Styled {
+                        style: TextStyle::ITALIC,
+                        elements: vec![Element::Styled {
+                            style: TextStyle::UNDERLINE,
+                            elements: vec![text("both")],
+                        }],
+                    },
+                ],
+            }
+        );
+
+        let parsed = Transformer::parse(&Bytes::from("<p>H<sub>2</sub>O, x<sup>2</sup></p>"))?;
+        let Element::Paragraph { elements } = parsed.get_all_elements()[0] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(
+            elements[1],
+            Element::Styled {
+                style: TextStyle::SUBSCRIPT,
+                elements: vec![text("2")],
+            }
+        );
+        let generated = String::from_utf8(Transformer::generate(&parsed)?.to_vec())?;
+        assert!(generated.contains("<sup>2</sup>"), "{}", generated);
+
+        let parsed = Transformer::parse(&Bytes::from(
+            "<p><mark>new</mark> and <mark style=\"background-color: Green\">done</mark></p>",
+        ))?;
+        let Element::Paragraph { elements } = parsed.get_all_elements()[0] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(
+            elements[0],
+            Element::Styled {
+                style: TextStyle::HIGHLIGHT,
+                elements: vec![text("new")],
+            }
+        );
+        let generated = String::from_utf8(Transformer::generate(&parsed)?.to_vec())?;
+        assert!(
+            generated.contains("<mark style=\"background-color: green\">done</mark>"),
+            "{}",
+            generated
+        );
+
+        let parsed = Transformer::parse(&Bytes::from(
+            "<p><span style=\"color: #C00; background-color: rgb(255, 255, 204)\">due</span></p>",
+        ))?;
+        let Element::Paragraph { elements } = parsed.get_all_elements()[0] else {
+            panic!("expected a paragraph");
+        };
+        let style = TextStyle {
+            color: Some(Color::new(204, 0, 0)),
+            background: Some(Color::new(255, 255, 204)),
+            ..Default::default()
+        };
+        assert_eq!(
+            elements[0],
+            Element::Styled {
+                style,
+                elements: vec![text("due")],
+            }
+        );
+        let generated = Transformer::generate(&parsed)?;
+        let html = String::from_utf8(generated.to_vec())?;
+        assert!(
+            html.contains("<span style=\"color: #cc0000; background-color: #ffffcc\">due</span>"),
+            "{}",
+            html
+        );
+        assert_eq!(
+            Transformer::parse(&generated)?.get_all_elements(),
+            parsed.get_all_elements()
+        );
+
+        let parsed = Transformer::parse(&Bytes::from("<p><del>old</del> <ins>new</ins></p>"))?;
+        let Element::Paragraph { elements } = parsed.get_all_elements()[0] else {
+            panic!("expected a paragraph");
+        };
+        assert!(matches!(
+            elements[0],
+            Element::Styled {
+                style: TextStyle::STRIKETHROUGH,
+                ..
+            }
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_list() -> anyhow::Result<()> {
+        let item = |text: &str, checked| ListItem {
+            element: Element::Text {
+                text: text.to_string(),
+                font: FontSpec::from(8),
+            },
+            checked,
+        };
+        let document = Document::new(vec![Element::List {
+            elements: vec![item("Done", Some(true)), item("Todo", Some(false))],
+            numbered: false,
+        }]);
+        let generated = String::from_utf8(Transformer::generate(&document)?.to_vec())?;
+        assert!(generated.contains("<li><input type=\"checkbox\" disabled checked> Done</li>"));
+        let reparsed = Transformer::parse(&Bytes::from(generated))?;
+        let Element::List { elements, .. } = reparsed.get_all_elements()[0] else {
+            panic!("expected a list");
+        };
+        let checked: Vec<Option<bool>> = elements.iter().map(|item| item.checked).collect();
+        assert_eq!(checked, vec![Some(true), Some(false)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_horizontal_rule() -> anyhow::Result<()> {
+        let document = Document::new(vec![HorizontalRule]);
+        let generated = String::from_utf8(Transformer::generate(&document)?.to_vec())?;
+        assert!(generated.contains("<hr />"), "{}", generated);
+        let reparsed = Transformer::parse(&Bytes::from("<p>One</p><hr><p>Two</p>"))?;
+        assert!(matches!(
+            reparsed.get_all_elements()[..],
+            [_, HorizontalRule, _]
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_line_break() -> anyhow::Result<()> {
+        let parsed = Transformer::parse(&Bytes::from("<p>Roses are red,<br>violets are blue</p>"))?;
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let paragraph = Paragraph {
+            elements: vec![text("Roses are red,"), LineBreak, text("violets are blue")],
+        };
+        assert_eq!(parsed.get_all_elements(), vec![&paragraph]);
+        let reparsed = Transformer::parse(&Transformer::generate(&parsed)?)?;
+        assert_eq!(reparsed.get_all_elements(), vec![&paragraph]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_break() -> anyhow::Result<()> {
+        let parsed = Transformer::parse(&Bytes::from(
+            "<p>One</p><p style=\"color: red; page-break-before: always\">Two</p>",
+        ))?;
+        assert!(matches!(
+            parsed.get_all_elements()[..],
+            [Paragraph { .. }, PageBreak, Paragraph { .. }]
+        ));
+        let generated = Transformer::generate(&parsed)?;
+        let html = String::from_utf8(generated.to_vec())?;
+        assert!(html.contains(PAGE_BREAK), "{}", html);
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_of_contents() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![
+            Header {
+                level: 1,
+                text: "Usage".to_string(),
+                anchor: None,
+            },
+            Header {
+                level: 2,
+                text: "Options".to_string(),
+                anchor: None,
+            },
+        ]);
+        document.insert_toc(3);
+        let generated = Transformer::generate(&document)?;
+        let html = String::from_utf8(generated.to_vec())?;
+        assert!(
+            html.contains("<li><a href=\"#options\">Options</a></li>"),
+            "{}",
+            html
+        );
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(
+            parsed.get_all_elements()[1],
+            &TableOfContents { max_level: 2 }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_font() -> anyhow::Result<()> {
+        let style = "font-family: 'Fira Sans', sans-serif; font-size: 16px";
+        let parsed = Transformer::parse(&Bytes::from(format!(
+            "<p><span style=\"{}\">Note</span></p>",
+            style
+        )))?;
+        let font = FontSpec {
+            family: Some("Fira Sans".to_string()),
+            size_pt: 12.0,
+            weight: Some(400),
+        };
+        let paragraph = Paragraph {
+            elements: vec![Text {
+                text: "Note".to_string(),
+                font,
+            }],
+        };
+        assert_eq!(parsed.get_all_elements(), vec![&paragraph]);
+        let generated = Transformer::generate(&parsed)?;
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), vec![&paragraph]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_heading_anchor() -> anyhow::Result<()> {
+        let parsed = Transformer::parse(&Bytes::from("<h2 id=\"scope\">Scope</h2>"))?;
+        let header = Header {
+            level: 2,
+            text: "Scope".to_string(),
+            anchor: Some("scope".to_string()),
+        };
+        assert_eq!(parsed.get_all_elements(), vec![&header]);
+        let generated = String::from_utf8(Transformer::generate(&parsed)?.to_vec())?;
+        assert!(
+            generated.contains("<h2 id=\"scope\">Scope</h2>"),
+            "{}",
+            generated
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_comment() -> anyhow::Result<()> {
+        let comment = Comment {
+            author: Some("Ann".to_string()),
+            date: Some("2024-03-01".to_string()),
+            text: "Check <totals>".to_string(),
+            range: None,
+        };
+        let document = Document::new(vec![comment.clone()]);
+        let generated = String::from_utf8(Transformer::generate(&document)?.to_vec())?;
+        assert!(
+            generated.contains("<aside class=\"comment\" data-author=\"Ann\" data-date=\"2024-03-01\">Check &lt;totals&gt;</aside>"),
+            "{}",
+            generated
+        );
+        let reparsed = Transformer::parse(&Bytes::from(generated))?;
+        assert_eq!(reparsed.get_all_elements(), vec![&comment]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_definition_list() -> anyhow::Result<()> {
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![DefinitionList {
+            items: vec![DefinitionItem {
+                term: vec![text("HTML")],
+                definitions: vec![
+                    Paragraph {
+                        elements: vec![text("A markup language")],
+                    },
+                    Paragraph {
+                        elements: vec![text("Rendered by browsers")],
+                    },
+                ],
+            }],
+        }]);
+        let generated = String::from_utf8(Transformer::generate(&document)?.to_vec())?;
+        assert!(generated.contains("<dt>HTML</dt>"), "{}", generated);
+        assert!(
+            generated.contains("<dd><p>A markup language</p></dd>"),
+            "{}",
+            generated
+        );
+        let reparsed = Transformer::parse(&Bytes::from(generated))?;
+        assert_eq!(reparsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merged_cells() -> anyhow::Result<()> {
+        let html = r#"<table>
+<tr><td colspan="2">Name</td><td rowspan="2">Notes</td></tr>
+<tr><td>First</td><td></td></tr>
+</table>"#;
+        let parsed = Transformer::parse(&Bytes::from(html))?;
+        let [Table { rows, .. }] = parsed.get_all_elements()[..] else {
+            panic!("expected a table, got {:?}", parsed.get_all_elements());
+        };
+        let spans: Vec<Vec<(usize, usize)>> = rows
+            .iter()
+            .map(|row| {
+                row.cells
+                    .iter()
+                    .map(|cell| (cell.colspan, cell.rowspan))
+                    .collect()
+            })
+            .collect();
+        // The empty cell keeps its place in the row
+        assert_eq!(spans, vec![vec![(2, 1), (1, 2)], vec![(1, 1), (1, 1)]]);
+
+        let generated = String::from_utf8(Transformer::generate(&parsed)?.to_vec())?;
+        assert!(
+            generated.contains("<td colspan=\"2\">Name</td>"),
+            "{}",
+            generated
+        );
+        assert!(
+            generated.contains("<td rowspan=\"2\">Notes</td>"),
+            "{}",
+            generated
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rich_cell() -> anyhow::Result<()> {
+        let html = r#"<table>
+<tr><td>Steps</td><td><p>Before</p><ul><li>one</li><li>two</li></ul></td></tr>
+</table>"#;
+        let parsed = Transformer::parse(&Bytes::from(html))?;
+        let [Table { rows, .. }] = parsed.get_all_elements()[..] else {
+            panic!("expected a table, got {:?}", parsed.get_all_elements());
+        };
+        assert_eq!(rows[0].cells[0].elements.len(), 1);
+        assert!(
+            matches!(
+                &rows[0].cells[1].elements[..],
+                [Paragraph { .. }, List { elements, .. }] if elements.len() == 2
+            ),
+            "{:?}",
+            rows[0].cells[1]
+        );
+
+        let generated = String::from_utf8(Transformer::generate(&parsed)?.to_vec())?;
+        assert!(generated.contains("<td><p>Before</p>"), "{}", generated);
+        assert!(generated.contains("<li>two</li>"), "{}", generated);
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_table() -> anyhow::Result<()> {
+        let html = r#"<table>
+<tr><td>Bill to</td><td><table><tr><td>Widget</td><td>4</td></tr></table></td></tr>
+</table>"#;
+        let parsed = Transformer::parse(&Bytes::from(html))?;
+        let [Table { rows, .. }] = parsed.get_all_elements()[..] else {
+            panic!("expected a table, got {:?}", parsed.get_all_elements());
+        };
+        let [Table { rows: inner, .. }] = &rows[0].cells[1].elements[..] else {
+            panic!("expected a nested table, got {:?}", rows[0].cells[1]);
+        };
+        assert_eq!(inner[0].cells.len(), 2);
+
+        let generated = Transformer::generate(&parsed)?;
+        let html = String::from_utf8(generated.to_vec())?;
+        assert!(html.contains("<td><table"), "{}", html);
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_figure() -> anyhow::Result<()> {
+        let html = r#"<figure>
+<img src="chart.png" alt="Sales chart" />
+<figcaption>Sales by <em>quarter</em></figcaption>
+</figure>"#;
+        let parsed =
+            Transformer::parse_with_loader(&Bytes::from(html), |_| Ok(Bytes::from_static(b"png")))?;
+        let [Figure { image, caption }] = parsed.get_all_elements()[..] else {
+            panic!("expected a figure, got {:?}", parsed.get_all_elements());
+        };
+        assert_eq!(image.alt(), "Sales chart");
+        assert!(
+            matches!(&caption[..], [Text { text, .. }, Styled { .. }] if text == "Sales by"),
+            "{:?}",
+            caption
+        );
+
+        let generated =
+            String::from_utf8(Transformer::generate_with_saver(&parsed, |_, _| Ok(()))?.to_vec())?;
+        assert!(
+            generated.contains("<figure><img src=\"image0.png\" alt=\"Sales chart\""),
+            "{}",
+            generated
+        );
+        assert!(
+            generated.contains("<em>quarter</em></figcaption></figure>"),
+            "{}",
+            generated
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_media() -> anyhow::Result<()> {
+        let html = r#"<video src="clip.mp4" poster="still.png"></video>
+<audio controls><source src="talk.mp3" type="audio/mpeg"></audio>"#;
+        let loader = |_: &str| Ok(Bytes::from_static(b"png"));
+        let parsed = Transformer::parse_with_loader(&Bytes::from(html), loader)?;
+        let [Media {
+            kind: MediaKind::Video,
+            source: video,
+            poster: Some(_),
+        }, Media {
+            kind: MediaKind::Audio,
+            source: audio,
+            poster: None,
+        }] = parsed.get_all_elements()[..]
+        else {
+            panic!(
+                "expected video and audio, got {:?}",
+                parsed.get_all_elements()
+            );
+        };
+        assert_eq!(video, &MediaSource::Url("clip.mp4".to_string()));
+        assert_eq!(audio, &MediaSource::Url("talk.mp3".to_string()));
+
+        let generated = Transformer::generate_with_saver(&parsed, |_, _| Ok(()))?;
+        let html = String::from_utf8(generated.to_vec())?;
+        assert!(
+            html.contains("<video src=\"clip.mp4\" controls poster=\"image0.png\"></video>"),
+            "{}",
+            html
+        );
+        assert!(
+            html.contains("<audio src=\"talk.mp3\" controls></audio>"),
+            "{}",
+            html
+        );
+        let reparsed = Transformer::parse_with_loader(&generated, loader)?;
+        assert_eq!(reparsed.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_direction() -> anyhow::Result<()> {
+        let html = r#"<html dir="rtl"><body>
+<p>שלום</p>
+<p dir="ltr">Hello</p>
+<p>مرحبا <span dir="ltr">C++</span></p>
+</body></html>"#;
+        let parsed = Transformer::parse(&Bytes::from(html))?;
+        assert_eq!(parsed.direction, TextDirection::Rtl);
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let ltr = TextStyle {
+            direction: Some(TextDirection::Ltr),
+            ..Default::default()
+        };
+        assert_eq!(
+            parsed.get_all_elements()[1],
+            &Paragraph {
+                elements: vec![Styled {
+                    style: ltr,
+                    elements: vec![text("Hello")],
+                }],
+            }
+        );
+
+        let generated = Transformer::generate(&parsed)?;
+        let html = String::from_utf8(generated.to_vec())?;
+        assert!(html.contains("<html dir=\"rtl\">"), "{}", html);
+        assert!(html.contains("<p dir=\"ltr\">Hello</p>"), "{}", html);
+        assert!(html.contains("<span dir=\"ltr\">C++</span>"), "{}", html);
+        assert_eq!(Transformer::parse(&generated)?, parsed);
+        Ok(())
+    }
+
+    #[test]
+    fn test_chart() -> anyhow::Result<()> {
+        let document = Document::new(vec![Chart {
+            chart_type: ChartType::Bar,
+            series: vec![ChartSeries {
+                name: "Visits & views".to_string(),
+                values: vec![120.0, 80.0],
+            }],
+            categories: vec!["Mon".to_string(), "Tue".to_string()],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        let html = String::from_utf8(generated.to_vec())?;
+        assert!(html.contains("<figure class=\"chart\"><svg"), "{}", html);
+        assert_eq!(html.matches("<rect").count(), 3, "{}", html);
+        assert!(html.contains("Visits &amp; views"), "{}", html);
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_alignment() -> anyhow::Result<()> {
+        let html = r#"<table>
+<tr><th>Name</th><th style="text-align: center">Count</th><th align="right">Price</th></tr>
+<tr><td>Apple</td><td>3</td><td>1.50</td></tr>
+</table>"#;
+        let parsed = Transformer::parse(&Bytes::from(html))?;
+        let [Table { alignments, .. }] = parsed.get_all_elements()[..] else {
+            panic!("expected a table, got {:?}", parsed.get_all_elements());
+        };
+        assert_eq!(
+            alignments,
+            &vec![
+                ColumnAlignment::None,
+                ColumnAlignment::Center,
+                ColumnAlignment::Right
+            ]
+        );
+
+        let generated = String::from_utf8(Transformer::generate(&parsed)?.to_vec())?;
+        assert!(
+            generated.contains("<td style=\"text-align: right\">1.50</td>"),
+            "{}",
+            generated
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_math() -> anyhow::Result<()> {
+        let inline = Element::Math {
+            tex: "a < b".to_string(),
+            display: false,
+        };
+        let display = Element::Math {
+            tex: "\\frac{1}{2}".to_string(),
+            display: true,
+        };
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![inline.clone()],
+            },
+            display.clone(),
+        ]);
+        let generated = String::from_utf8(Transformer::generate(&document)?.to_vec())?;
+        assert!(generated.contains("<span class=\"math inline\">\\(a &lt; b\\)</span>"));
+        let reparsed = Transformer::parse(&Bytes::from(generated))?;
+        let elements = reparsed.get_all_elements();
+        assert!(matches!(elements[0], Element::Paragraph { elements } if elements[0] == inline));
+        assert_eq!(*elements[1], display);
+
+        let mathml = "<p><math display=\"block\"><semantics><mi>x</mi>\
+            <annotation encoding=\"application/x-tex\">x</annotation></semantics></math></p>";
+        let parsed = Transformer::parse(&Bytes::from(mathml))?;
+        assert_eq!(
+            *parsed.get_all_elements()[0],
+            Element::Math {
+                tex: "x".to_string(),
+                display: true,
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_caption() -> anyhow::Result<()> {
+        let html = "<table><caption>Table 3: Quarterly results</caption>\
+            <tr><th>Quarter</th></tr><tr><td>Q1</td></tr></table>\
+            <figure><blockquote><p>Brevity is the soul of wit.</p></blockquote>\
+            <figcaption>— Shakespeare</figcaption></figure>";
+        let parsed = Transformer::parse(&Bytes::from(html))?;
+        let elements = parsed.get_all_elements();
+        let Element::Table { caption, .. } = elements[0] else {
+            panic!("expected a table, got {:?}", elements[0]);
+        };
+        assert_eq!(
+            caption,
+            &vec![Element::Text {
+                text: "Table 3: Quarterly results".to_string(),
+                font: FontSpec::from(8),
+            }]
+        );
+        let Element::Blockquote { attribution, .. } = elements[1] else {
+            panic!("expected a blockquote, got {:?}", elements[1]);
+        };
+        assert!(matches!(&attribution[..], [Element::Text { text, .. }] if text == "Shakespeare"));
+
+        let generated = String::from_utf8(Transformer::generate(&parsed)?.to_vec())?;
+        assert!(generated.contains("<caption>Table 3: Quarterly results</caption>"));
+        assert!(generated.contains("<figcaption>— Shakespeare</figcaption>"));
+        let reparsed = Transformer::parse(&Bytes::from(generated))?;
+        assert_eq!(reparsed.get_all_elements(), elements);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ruby() -> anyhow::Result<()> {
+        let ruby = Element::Ruby {
+            base: "漢字".to_string(),
+            annotation: "かんじ".to_string(),
+        };
+        let document = Document::new(vec![Element::Paragraph {
+            elements: vec![ruby.clone()],
+        }]);
+        let generated = String::from_utf8(Transformer::generate(&document)?.to_vec())?;
+        assert!(generated.contains("<ruby>漢字<rp>(</rp><rt>かんじ</rt><rp>)</rp></ruby>"));
+        let reparsed = Transformer::parse(&Bytes::from(generated))?;
+        assert!(matches!(
+            reparsed.get_all_elements()[0],
+            Element::Paragraph { elements } if elements[0] == ruby
+        ));
+
+        let split = "<p><ruby><rb>漢</rb><rt>かん</rt><rb>字</rb><rt>じ</rt></ruby></p>";
+        let parsed = Transformer::parse(&Bytes::from(split))?;
+        assert!(matches!(
+            parsed.get_all_elements()[0],
+            Element::Paragraph { elements } if elements[0] == ruby
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_section() -> anyhow::Result<()> {
+        let document = Transformer::parse(&Bytes::from(
+            "<h1>One</h1><p>First</p><h2>Nested</h2><p>Second</p><h1>Two</h1>",
+        ))?;
+        let generated = Transformer::generate(&document.nest_sections())?;
+        let generated = String::from_utf8(generated.to_vec())?;
+        assert!(generated.contains(
+            "<section>\n<h1>One</h1>\n<p>First</p>\n\
+             <section>\n<h2>Nested</h2>\n<p>Second</p>\n</section>\n</section>\n\
+             <section>\n<h1>Two</h1>\n</section>\n"
+        ));
+        // The sections are read back as the flat headers they were made from
+        let reparsed = Transformer::parse(&Bytes::from(generated))?;
+        assert_eq!(reparsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata() -> anyhow::Result<()> {
+        let document = Transformer::parse(&Bytes::from(
+            r#"<html lang="fr"><head><title>Rapport</title>
+            <meta name="author" content="Ann"><meta name="keywords" content="finance, 2024">
+            <meta name="department" content="Sales"></head><body><p>Texte</p></body></html>"#,
+        ))?;
+        let metadata = Metadata {
+            title: Some("Rapport".to_string()),
+            authors: vec!["Ann".to_string()],
+            keywords: vec!["finance".to_string(), "2024".to_string()],
+            language: Some("fr".to_string()),
+            custom: [("department".to_string(), "Sales".to_string())].into(),
+            ..Metadata::default()
+        };
+        assert_eq!(document.metadata, metadata);
+        let generated = Transformer::generate(&document)?;
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.metadata, metadata);
+        assert_eq!(reparsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
 }
@@ -0,0 +1,343 @@
+use crate::core::*;
+use crate::markdown;
+use base64::{engine::general_purpose, Engine as _};
+use bytes::Bytes;
+use serde_json::{json, Map, Value};
+use std::cell::RefCell;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    /// Markdown cells go through the markdown parser with `attachment:` images resolved from
+    /// the cell attachments. Code cells become text elements followed by their outputs:
+    /// images, markdown and plain text results. Error outputs are skipped.
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        let notebook: Value = serde_json::from_slice(document)?;
+        let cells = notebook
+            .get("cells")
+            .and_then(Value::as_array)
+            .ok_or_else(|| ParserError::Malformed("notebook has no cells".to_string()))?;
+
+        let mut elements = Vec::new();
+        for cell in cells {
+            let source = multiline(cell.get("source"));
+            match cell.get("cell_type").and_then(Value::as_str) {
+                Some("markdown") => {
+                    let attachments = cell.get("attachments").cloned().unwrap_or(Value::Null);
+                    elements.extend(parse_markdown(&source, &attachments)?);
+                }
+                Some("code") => {
+                    if !source.trim().is_empty() {
+                        elements.push(Element::Text {
+                            text: source.trim_end().to_string(),
+                            font: FontSpec::from(8),
+                        });
+                    }
+                    let outputs = cell.get("outputs").and_then(Value::as_array);
+                    for output in outputs.into_iter().flatten() {
+                        parse_output(output, &mut elements)?;
+                    }
+                }
+                Some("raw") if !source.trim().is_empty() => elements.push(Element::Text {
+                    text: source.trim_end().to_string(),
+                    font: FontSpec::from(8),
+                }),
+                _ => {}
+            }
+        }
+        Ok(Document::new(elements))
+    }
+
+    /// Produces markdown cells only, a new cell starts at every header.
+    /// Images are stored as cell attachments.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        let document = &document.flatten_sections();
+        let mut groups: Vec<Vec<Element>> = Vec::new();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                match groups.last_mut() {
+                    Some(group) if !matches!(element, Element::Header { .. }) => {
+                        group.push(element.clone())
+                    }
+                    _ => groups.push(vec![element.clone()]),
+                }
+            }
+        }
+
+        let mut cells = Vec::new();
+        for (index, group) in groups.into_iter().enumerate() {
+            cells.push(markdown_cell(index + 1, group)?);
+        }
+        let notebook = json!({
+            "cells": cells,
+            "metadata": {},
+            "nbformat": 4,
+            "nbformat_minor": 5,
+        });
+        Ok(Bytes::from(serde_json::to_vec_pretty(&notebook)?))
+    }
+}
+
+/// Image outputs in order of preference
+const IMAGE_TYPES: [(&str, &str); 4] = [
+    ("image/png", "png"),
+    ("image/jpeg", "jpeg"),
+    ("image/gif", "gif"),
+    ("image/svg+xml", "svg"),
+];
+
+/// Notebook text fields are either a string or a list of lines
+fn multiline(value: Option<&Value>) -> String {
+    match value {
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::Array(lines)) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+fn parse_markdown(source: &str, attachments: &Value) -> anyhow::Result<Vec<Element>> {
+    let loader = |url: &str| -> anyhow::Result<Bytes> {
+        let Some(data) = url
+            .strip_prefix("attachment:")
+            .and_then(|name| attachments.get(name))
+        else {
+            // Images outside the notebook are kept without their content
+            return Ok(Bytes::new());
+        };
+        let Some((mime_type, _)) = IMAGE_TYPES
+            .iter()
+            .find(|(mime_type, _)| data.get(mime_type).is_some())
+        else {
+            return Ok(Bytes::new());
+        };
+        image_bytes(mime_type, &multiline(data.get(mime_type)))
+    };
+    let document =
+        markdown::Transformer::parse_with_loader(&Bytes::from(source.to_string()), loader)?;
+    Ok(document.get_all_elements().into_iter().cloned().collect())
+}
+
+fn parse_output(output: &Value, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+    match output.get("output_type").and_then(Value::as_str) {
+        Some("stream") => {
+            let text = multiline(output.get("text"));
+            if !text.trim().is_empty() {
+                elements.push(Element::Text {
+                    text: text.trim_end().to_string(),
+                    font: FontSpec::from(8),
+                });
+            }
+        }
+        Some("execute_result" | "display_data") => {
+            let Some(data) = output.get("data") else {
+                return Ok(());
+            };
+            if let Some((mime_type, image_type)) = IMAGE_TYPES
+                .iter()
+                .find(|(mime_type, _)| data.get(mime_type).is_some())
+            {
+                let bytes = image_bytes(mime_type, &multiline(data.get(mime_type)))?;
+                let text = multiline(data.get("text/plain"));
+                elements.push(Element::Image(ImageData::new(
+                    bytes,
+                    String::new(),
+                    text.trim().to_string(),
+                    image_type.to_string(),
+                    String::new(),
+                    ImageDimension::default(),
+                )));
+            } else if let Some(source) = data.get("text/markdown") {
+                elements.extend(parse_markdown(&multiline(Some(source)), &Value::Null)?);
+            } else if let Some(text) = data.get("text/plain") {
+                elements.push(Element::Text {
+                    text: multiline(Some(text)).trim_end().to_string(),
+                    font: FontSpec::from(8),
+                });
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// SVG outputs are stored as markup, all other images as base64
+fn image_bytes(mime_type: &str, data: &str) -> anyhow::Result<Bytes> {
+    if mime_type == "image/svg+xml" {
+        return Ok(Bytes::from(data.to_string()));
+    }
+    let data: String = data.split_whitespace().collect();
+    Ok(Bytes::from(general_purpose::STANDARD.decode(data)?))
+}
+
+fn markdown_cell(number: usize, elements: Vec<Element>) -> anyhow::Result<Value> {
+    let attachments = RefCell::new(Map::new());
+    let saver = |bytes: &Bytes, name: &str| -> anyhow::Result<()> {
+        let mime_type = IMAGE_TYPES
+            .iter()
+            .find(|(_, extension)| name.ends_with(&format!(".{}", extension)))
+            .map(|(mime_type, _)| *mime_type)
+            .unwrap_or("image/png");
+        let data = if mime_type == "image/svg+xml" {
+            String::from_utf8_lossy(bytes).to_string()
+        } else {
+            general_purpose::STANDARD.encode(bytes)
+        };
+        attachments
+            .borrow_mut()
+            .insert(name.to_string(), json!({ mime_type: data }));
+        Ok(())
+    };
+    let markdown = markdown::Transformer::generate_with_saver(&Document::new(elements), saver)?;
+    let mut markdown = String::from_utf8(markdown.to_vec())?;
+
+    let attachments = attachments.into_inner();
+    for name in attachments.keys() {
+        markdown = markdown.replace(&format!("]({}", name), &format!("](attachment:{}", name));
+    }
+    let source: Vec<&str> = markdown.trim_end().split_inclusive('\n').collect();
+
+    let mut cell = json!({
+        "cell_type": "markdown",
+        "id": format!("cell-{}", number),
+        "metadata": {},
+        "source": source,
+    });
+    if !attachments.is_empty() {
+        cell["attachments"] = Value::Object(attachments);
+    }
+    Ok(cell)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::*;
+    use crate::ipynb::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let notebook = r##"{
+ "cells": [
+  {
+   "cell_type": "markdown",
+   "metadata": {},
+   "source": ["# Report\n", "\n", "![Chart](attachment:chart.png)\n"],
+   "attachments": {"chart.png": {"image/png": "iVBORw0KGgo="}}
+  },
+  {
+   "cell_type": "code",
+   "execution_count": 1,
+   "metadata": {},
+   "source": "print(1 + 1)",
+   "outputs": [
+    {"output_type": "stream", "name": "stdout", "text": ["2\n"]},
+    {"output_type": "display_data", "metadata": {},
+     "data": {"image/png": "iVBORw0KGgo=\n", "text/plain": ["<Figure>"]}},
+    {"output_type": "error", "ename": "ValueError", "evalue": "", "traceback": []}
+   ]
+  }
+ ],
+ "metadata": {},
+ "nbformat": 4,
+ "nbformat_minor": 5
+}"##;
+        let document = Transformer::parse(&Bytes::from(notebook))?;
+        let elements = document.get_all_elements();
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 1,
+                text: "Report".to_string(),
+                anchor: None,
+            }
+        );
+        let images: Vec<&ImageData> = elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Image(image) => Some(image),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(images.len(), 2);
+        assert!(images
+            .iter()
+            .all(|image| image.bytes().starts_with(b"\x89PNG")));
+        assert_eq!(images[1].alt(), "<Figure>");
+
+        let texts: Vec<&str> = elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Text { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(texts, vec!["print(1 + 1)", "2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Analysis".to_string(),
+                anchor: None,
+            },
+            Element::Paragraph {
+                elements: vec![text("Results below")],
+            },
+            Element::Paragraph {
+                elements: vec![Element::Image(ImageData::new(
+                    Bytes::from_static(b"\x89PNG\r\n"),
+                    "Plot".to_string(),
+                    "Plot".to_string(),
+                    "png".to_string(),
+                    String::new(),
+                    ImageDimension::default(),
+                ))],
+            },
+            Element::Header {
+                level: 2,
+                text: "Summary".to_string(),
+                anchor: None,
+            },
+            Element::List {
+                elements: vec![ListItem {
+                    element: text("Item"),
+                    checked: None,
+                }],
+                numbered: false,
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let notebook: Value = serde_json::from_slice(&generated)?;
+        let cells = notebook["cells"].as_array().expect("cells");
+        assert_eq!(cells.len(), 2);
+        assert!(cells[0]["attachments"]["image1.png"]["image/png"].is_string());
+
+        let parsed = Transformer::parse(&generated)?;
+        let elements = parsed.get_all_elements();
+        let headers: Vec<&str> = elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Header { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(headers, vec!["Analysis", "Summary"]);
+        assert!(elements.iter().any(|element| matches!(
+            element,
+            Element::Image(image) if image.bytes().as_ref() == b"\x89PNG\r\n"
+        )));
+        assert!(elements
+            .iter()
+            .any(|element| matches!(element, Element::List { .. })));
+        Ok(())
+    }
+}
@@ -0,0 +1,470 @@
+use crate::core::*;
+use bytes::Bytes;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    fn parse_with_loader<F>(_document: &Bytes, _image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        Err(anyhow::anyhow!("Jira markup parsing is not supported"))
+    }
+
+    /// Produces Jira wiki markup. Images are written through `image_saver` and embedded
+    /// with `!name!`, so they have to be attached to the issue. Multi-line text becomes a
+    /// `{noformat}` block.
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        // A table of contents is written as the list of its headers
+        let document = &document.expand_toc();
+        let mut generator = Generator {
+            image_saver,
+            image_num: 0,
+        };
+        let mut markup = String::new();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                generator.block(&mut markup, element)?;
+            }
+        }
+        Ok(Bytes::from(markup))
+    }
+}
+
+/// Characters which start or end an inline format, a link or a macro
+const SPECIAL: [char; 11] = ['*', '_', '+', '^', '~', '!', '[', ']', '{', '}', '|'];
+
+/// Escapes markup characters, and block markers at the start of every line
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for (index, line) in text.split('\n').enumerate() {
+        if index > 0 {
+            escaped.push('\n');
+        }
+        if line.starts_with(['#', '-']) || heading_marker(line) {
+            escaped.push('\\');
+        }
+        for c in line.chars() {
+            if SPECIAL.contains(&c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+/// `h1.` to `h6.` at the start of a line would become a heading
+fn heading_marker(text: &str) -> bool {
+    let bytes = text.as_bytes();
+    bytes.len() >= 3 && bytes[0] == b'h' && (b'1'..=b'6').contains(&bytes[1]) && bytes[2] == b'.'
+}
+
+struct Generator<F> {
+    image_saver: F,
+    image_num: usize,
+}
+
+impl<F> Generator<F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn block(&mut self, markup: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Header { level, text, .. } => {
+                markup.push_str(&format!(
+                    "h{}. {}\n\n",
+                    (*level).clamp(1, 6),
+                    escape(&text.replace('\n', " "))
+                ));
+            }
+            Element::Text { text, .. } if text.contains('\n') => {
+                markup.push_str("{noformat}\n");
+                markup.push_str(text.trim_end_matches('\n'));
+                markup.push_str("\n{noformat}\n\n");
+            }
+            Element::List { elements, numbered } => {
+                let marker = if *numbered { "#" } else { "*" };
+                self.list(markup, elements, marker)?;
+                markup.push('\n');
+            }
+            Element::Chart { .. } => self.block(markup, &element.chart_fallback())?,
+            Element::Table { headers, rows, .. } => {
+                self.table(markup, headers, rows)?;
+                markup.push('\n');
+            }
+            Element::HorizontalRule => markup.push_str("----\n\n"),
+            // Jira has no definition lists, a bold term line precedes its definitions
+            Element::DefinitionList { items } => {
+                for item in items {
+                    let mut term = String::new();
+                    for child in &item.term {
+                        term.push_str(&self.inline(child)?);
+                    }
+                    markup.push_str(&format!("*{}*\n", term.replace('\n', " ").trim()));
+                    for definition in &item.definitions {
+                        let text = self.inline(definition)?.replace('\n', " ");
+                        markup.push_str(text.trim());
+                        markup.push('\n');
+                    }
+                }
+                markup.push('\n');
+            }
+            Element::CodeBlock { language, code } => {
+                match language {
+                    Some(language) => markup.push_str(&format!("{{code:{}}}\n", language)),
+                    None => markup.push_str("{code}\n"),
+                }
+                markup.push_str(code);
+                markup.push_str("\n{code}\n\n");
+            }
+            Element::Blockquote { elements, .. } => {
+                let mut quote = String::new();
+                for child in elements {
+                    self.block(&mut quote, child)?;
+                }
+                markup.push_str("{quote}\n");
+                markup.push_str(quote.trim_end());
+                markup.push_str("\n{quote}\n\n");
+            }
+            Element::Section { children, .. } => {
+                for child in children {
+                    self.block(markup, child)?;
+                }
+            }
+            // Jira markup has no comments or pages
+            Element::Comment { .. } | Element::PageBreak | Element::TableOfContents { .. } => {}
+            element => {
+                let text = self.inline(element)?;
+                if !text.trim().is_empty() {
+                    markup.push_str(text.trim());
+                    markup.push_str("\n\n");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn list(
+        &mut self,
+        markup: &mut String,
+        items: &[ListItem],
+        prefix: &str,
+    ) -> anyhow::Result<()> {
+        for item in items {
+            match &item.element {
+                // Nested lists extend the prefix of the parent item, markers can be mixed
+                Element::List { elements, numbered } => {
+                    let marker = if *numbered { "#" } else { "*" };
+                    self.list(markup, elements, &format!("{}{}", prefix, marker))?
+                }
+                element => {
+                    let text = self.inline(element)?.replace('\n', " ");
+                    markup.push_str(&format!("{} {}\n", prefix, text.trim()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn table(
+        &mut self,
+        markup: &mut String,
+        headers: &[TableHeader],
+        rows: &[TableRow],
+    ) -> anyhow::Result<()> {
+        if !headers.is_empty() {
+            markup.push_str("||");
+            for header in headers {
+                markup.push_str(&self.cell(&header.element)?);
+                markup.push_str("||");
+            }
+            markup.push('\n');
+        }
+        for row in rows {
+            markup.push('|');
+            for cell in &row.cells {
+                markup.push_str(&self.cell(&cell.element())?);
+                markup.push('|');
+            }
+            markup.push('\n');
+        }
+        Ok(())
+    }
+
+    /// Rows are single lines, an empty cell needs a space to keep its column
+    fn cell(&mut self, element: &Element) -> anyhow::Result<String> {
+        let text = self.inline(element)?.replace('\n', " ");
+        let text = text.trim();
+        Ok(if text.is_empty() {
+            " ".to_string()
+        } else {
+            text.to_string()
+        })
+    }
+
+    fn inline(&mut self, element: &Element) -> anyhow::Result<String> {
+        Ok(match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => {
+                escape(text).replace('\n', "\\\\\n")
+            }
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => {
+                let mut text = String::new();
+                for child in elements {
+                    text.push_str(&self.inline(child)?);
+                }
+                text
+            }
+            Element::Hyperlink { title, url, .. } => {
+                if title.is_empty() || title == url {
+                    format!("[{}]", url)
+                } else {
+                    format!("[{}|{}]", escape(title), url)
+                }
+            }
+            Element::Image(image) => self.image(image)?.unwrap_or_default(),
+            // Jira has no figures, the caption is set in italics on the line below the image
+            Element::Figure { image, caption } => {
+                let mut text = self.image(image)?.unwrap_or_default();
+                let mut caption_text = String::new();
+                for child in caption {
+                    caption_text.push_str(&self.inline(child)?);
+                }
+                if !caption_text.trim().is_empty() {
+                    if !text.is_empty() {
+                        text.push_str("\\\\\n");
+                    }
+                    text.push_str(&format!("_{}_", caption_text.trim()));
+                }
+                text
+            }
+            Element::LineBreak | Element::HorizontalRule => "\\\\\n".to_string(),
+            Element::List { elements, .. } => {
+                let mut items = Vec::new();
+                for item in elements {
+                    items.push(self.inline(&item.element)?);
+                }
+                items.join(" ")
+            }
+            Element::DefinitionList { items } => {
+                let mut texts = Vec::new();
+                for child in items
+                    .iter()
+                    .flat_map(|item| item.term.iter().chain(&item.definitions))
+                {
+                    texts.push(self.inline(child)?);
+                }
+                texts.join(" ")
+            }
+            Element::CodeBlock { code, .. } => format!("{{{{{}}}}}", code.replace('\n', " ")),
+            Element::Styled { style, elements } => {
+                let mut text = String::new();
+                for child in elements {
+                    text.push_str(&self.inline(child)?);
+                }
+                for (enabled, mark) in [
+                    (style.strikethrough, "-"),
+                    (style.underline, "+"),
+                    (style.italic, "_"),
+                    (style.bold, "*"),
+                ] {
+                    if enabled {
+                        text = format!("{}{}{}", mark, text, mark);
+                    }
+                }
+                text
+            }
+            // Jira has no formulas, the TeX source is set as code
+            Element::Math { tex, .. } => format!("{{{{{}}}}}", tex.replace('\n', " ")),
+            Element::FootnoteReference { id } => format!("^{}^", escape(id)),
+            Element::Footnote { id, content } => {
+                let mut text = format!("^{}^ ", escape(id));
+                for child in content {
+                    text.push_str(&self.inline(child)?);
+                }
+                text
+            }
+            Element::Media { .. } => self.inline(&element.media_fallback())?,
+            Element::Ruby { .. } => self.inline(&element.ruby_fallback())?,
+            Element::Table { .. }
+            | Element::Chart { .. }
+            | Element::Comment { .. }
+            | Element::PageBreak
+            | Element::TableOfContents { .. }
+            | Element::Custom { .. } => String::new(),
+        })
+    }
+
+    /// Saves the image and returns the `!name|attributes!` reference to it
+    fn image(&mut self, image: &ImageData) -> anyhow::Result<Option<String>> {
+        if image.bytes().is_empty() {
+            return Ok(None);
+        }
+        let target = format!(
+            "image{}{}",
+            self.image_num,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &target)?;
+        self.image_num += 1;
+
+        let mut attributes = Vec::new();
+        let size = image.size();
+        for (attribute, value) in [("width", &size.width), ("height", &size.height)] {
+            let pixels = value
+                .as_deref()
+                .map(|value| value.trim_end_matches("px"))
+                .filter(|value| !value.is_empty() && value.chars().all(|c| c.is_ascii_digit()));
+            if let Some(pixels) = pixels {
+                attributes.push(format!("{}={}", attribute, pixels));
+            }
+        }
+        match image.align() {
+            ImageAlignment::Left => attributes.push("align=left".to_string()),
+            ImageAlignment::Center => attributes.push("align=center".to_string()),
+            ImageAlignment::Right => attributes.push("align=right".to_string()),
+            ImageAlignment::None => {}
+        }
+        let alt = if image.alt().is_empty() {
+            image.title()
+        } else {
+            image.alt()
+        };
+        if !alt.is_empty() {
+            attributes.push(format!("alt=\"{}\"", alt.replace(['"', '!', '|'], "")));
+        }
+
+        Ok(Some(if attributes.is_empty() {
+            format!("!{}!", target)
+        } else {
+            format!("!{}|{}!", target, attributes.join(","))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::jira::*;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 2,
+                text: "Release 1.2".to_string(),
+                anchor: None,
+            },
+            Element::Paragraph {
+                elements: vec![
+                    text("Fixed [parser] crash, see "),
+                    Element::Hyperlink {
+                        title: "the issue".to_string(),
+                        url: "https://example.com/1".to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    },
+                    Element::LineBreak,
+                    text("- thanks"),
+                ],
+            },
+            Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("Added"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: Element::List {
+                            elements: vec![ListItem {
+                                element: text("Export"),
+                                checked: None,
+                            }],
+                            numbered: false,
+                        },
+                        checked: None,
+                    },
+                ],
+                numbered: true,
+            },
+            Element::Table {
+                headers: vec![
+                    TableHeader {
+                        element: text("Key"),
+                        width: 30.0,
+                    },
+                    TableHeader {
+                        element: text("Value"),
+                        width: 30.0,
+                    },
+                ],
+                rows: vec![TableRow {
+                    cells: vec![TableCell::new(text("a|b")), TableCell::new(text(""))],
+                }],
+                alignments: vec![],
+                caption: vec![],
+            },
+            text("fn main() {\n}"),
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let markup = String::from_utf8(generated.to_vec())?;
+        assert_eq!(
+            markup,
+            concat!(
+                "h2. Release 1.2\n\n",
+                "Fixed \\[parser\\] crash, see [the issue|https://example.com/1]\\\\\n\\- thanks\n\n",
+                "# Added\n#* Export\n\n",
+                "||Key||Value||\n|a\\|b| |\n\n",
+                "{noformat}\nfn main() {\n}\n{noformat}\n\n",
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_images() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let document = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let saved = RefCell::new(Vec::new());
+        let generated = Transformer::generate_with_saver(&document, |_, name| {
+            saved.borrow_mut().push(name.to_string());
+            Ok(())
+        })?;
+        let markup = String::from_utf8(generated.to_vec())?;
+        let saved = saved.into_inner();
+        assert!(!saved.is_empty());
+        for name in &saved {
+            assert!(markup.contains(&format!("!{}", name)));
+        }
+        assert!(markup.lines().any(|line| line.starts_with("h1. ")));
+        assert!(markup.lines().any(|line| line.starts_with("||")));
+        Ok(())
+    }
+}
@@ -1,9 +1,12 @@
 use crate::core::{
-    Document, Element, ImageAlignment, ImageData, ImageDimension, ImageType, ListItem,
-    PageDimensions, PageFormat, TableCell, TableHeader, TableRow, TransformerTrait,
+    ChartSeries, ChartType, Color, ColumnAlignment, DefinitionItem, Document, Element,
+    ElementKind, FontSpec, HighlightColor, ImageAlignment, ImageData, ImageDimension, ImageType,
+    ListItem, MediaKind, MediaSource, PageDimensions, PageFormat, TableCell, TableHeader,
+    TableRow, TextDirection, TextStyle, TransformerTrait,
 };
+use crate::schema::SCHEMA_VERSION;
 use bytes::Bytes;
-use serde_json::Value;
+use serde_json::{json, Map, Value};
 use std::str::FromStr;
 pub struct Transformer;
 
@@ -11,7 +14,20 @@ impl TransformerTrait for Transformer {
     fn parse(document: &Bytes) -> anyhow::Result<Document> {
         // Преобразуем Bytes в строку
         let data_str = std::str::from_utf8(document)?;
-        let json: Value = serde_json::from_str(data_str)?;
+        let mut json: Value = serde_json::from_str(data_str)?;
+
+        // Written since the schema was versioned, documents from before count as version 0
+        let version = json
+            .get("schema_version")
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        if version > SCHEMA_VERSION {
+            for key in ["elements", "page_header", "page_footer"] {
+                if let Some(elements) = json.get_mut(key) {
+                    shim_elements(elements);
+                }
+            }
+        }
 
         // Проверяем, что корневой элемент - объект
         let root = json
@@ -69,145 +85,23 @@ impl TransformerTrait for Transformer {
             page_margin_right,
         });
 
-        let document =
+        let mut document =
             Document::new_with_dimensions(page_header, elements, page_footer, page_custom_format);
+        // Written only for right to left documents
+        if let Some(direction) = root.get("direction").and_then(|v| v.as_str()) {
+            document.direction = TextDirection::from_str(direction)
+                .map_err(|_| anyhow::anyhow!("Invalid direction: {}", direction))?;
+        }
+        if let Some(metadata) = root.get("metadata") {
+            document.metadata = serde_json::from_value(metadata.clone())?;
+        }
         Ok(document)
     }
 
     fn generate(document: &Document) -> anyhow::Result<Bytes> {
-        use serde_json::{Map, Value};
-        // Helper function to serialize an Element into serde_json::Value
-        fn serialize_element(element: &Element) -> Value {
-            match element {
-                Element::Text { text, size } => {
-                    let mut map = Map::new();
-                    map.insert("type".to_string(), Value::String("Text".to_string()));
-                    map.insert("text".to_string(), Value::String(text.clone()));
-                    map.insert("size".to_string(), Value::Number((*size).into()));
-                    Value::Object(map)
-                }
-                Element::Header { level, text } => {
-                    let mut map = Map::new();
-                    map.insert("type".to_string(), Value::String("Header".to_string()));
-                    map.insert("level".to_string(), Value::Number((*level).into()));
-                    map.insert("text".to_string(), Value::String(text.clone()));
-                    Value::Object(map)
-                }
-                Element::Paragraph { elements } => {
-                    let elements_json = elements.iter().map(serialize_element).collect();
-                    let mut map = Map::new();
-                    map.insert("type".to_string(), Value::String("Paragraph".to_string()));
-                    map.insert("elements".to_string(), Value::Array(elements_json));
-                    Value::Object(map)
-                }
-                Element::Table { headers, rows } => {
-                    let headers_json: Vec<Value> = headers
-                        .iter()
-                        .map(|h| {
-                            let mut h_map = Map::new();
-                            h_map.insert("element".to_string(), serialize_element(&h.element));
-                            h_map.insert(
-                                "width".to_string(),
-                                Value::Number(
-                                    serde_json::Number::from_f64(h.width as f64).unwrap(),
-                                ),
-                            );
-                            Value::Object(h_map)
-                        })
-                        .collect();
-
-                    let rows_json: Vec<Value> = rows
-                        .iter()
-                        .map(|r| {
-                            let cells_json: Vec<Value> = r
-                                .cells
-                                .iter()
-                                .map(|c| serialize_element(&c.element))
-                                .collect();
-                            let mut row_map = Map::new();
-                            row_map.insert("cells".to_string(), Value::Array(cells_json));
-                            Value::Object(row_map)
-                        })
-                        .collect();
-
-                    let mut map = Map::new();
-                    map.insert("type".to_string(), Value::String("Table".to_string()));
-                    map.insert("headers".to_string(), Value::Array(headers_json));
-                    map.insert("rows".to_string(), Value::Array(rows_json));
-                    Value::Object(map)
-                }
-                Element::List { elements, numbered } => {
-                    let elements_json: Vec<Value> = elements
-                        .iter()
-                        .map(|item| {
-                            let mut item_map = Map::new();
-                            item_map
-                                .insert("element".to_string(), serialize_element(&item.element));
-                            Value::Object(item_map)
-                        })
-                        .collect();
-
-                    let mut map = Map::new();
-                    map.insert("type".to_string(), Value::String("List".to_string()));
-                    map.insert("numbered".to_string(), Value::Bool(*numbered));
-                    map.insert("elements".to_string(), Value::Array(elements_json));
-                    Value::Object(map)
-                }
-                Element::Image(image_data) => {
-                    let mut map = Map::new();
-                    map.insert("type".to_string(), Value::String("Image".to_string()));
-                    // Encode image bytes to base64 for JSON representation
-                    map.insert(
-                        "bytes".to_string(),
-                        Value::String(base64::encode(&image_data.bytes())),
-                    );
-                    map.insert(
-                        "title".to_string(),
-                        Value::String(image_data.title().to_string()),
-                    );
-                    map.insert(
-                        "alt".to_string(),
-                        Value::String(image_data.alt().to_string()),
-                    );
-                    map.insert(
-                        "image_type".to_string(),
-                        Value::String(image_data.image_type().to_string()),
-                    );
-
-                    map.insert(
-                        "align".to_string(),
-                        Value::String(image_data.align().to_string().to_lowercase()),
-                    );
-
-                    let mut size_map = Map::new();
-                    if let Some(width) = &image_data.size().width {
-                        size_map.insert("width".to_string(), Value::String(width.clone()));
-                    }
-                    if let Some(height) = &image_data.size().height {
-                        size_map.insert("height".to_string(), Value::String(height.clone()));
-                    }
-                    map.insert("size".to_string(), Value::Object(size_map));
-                    Value::Object(map)
-                }
-                Element::Hyperlink {
-                    title,
-                    url,
-                    alt,
-                    size,
-                } => {
-                    let mut map = Map::new();
-                    map.insert("type".to_string(), Value::String("Hyperlink".to_string()));
-                    map.insert("title".to_string(), Value::String(title.clone()));
-                    map.insert("url".to_string(), Value::String(url.clone()));
-                    map.insert("alt".to_string(), Value::String(alt.clone()));
-                    map.insert("size".to_string(), Value::Number((*size).into()));
-                    Value::Object(map)
-                }
-            }
-        }
-
         // Serialize the main Document
         let mut doc_map = Map::new();
+        doc_map.insert("schema_version".to_string(), Value::from(SCHEMA_VERSION));
 
         // Serialize elements
         let elements_json: Vec<Value> = document
@@ -269,6 +163,19 @@ impl TransformerTrait for Transformer {
             ),
         );
 
+        if document.direction != TextDirection::default() {
+            doc_map.insert(
+                "direction".to_string(),
+                Value::String(document.direction.to_string()),
+            );
+        }
+        if !document.metadata.is_empty() {
+            doc_map.insert(
+                "metadata".to_string(),
+                serde_json::to_value(&document.metadata)?,
+            );
+        }
+
         // Serialize page headers
         let page_header_json: Vec<Value> = document
             .get_page_header()
@@ -296,6 +203,455 @@ impl TransformerTrait for Transformer {
     }
 }
 
+/// Serializes an element with images as base64, the NDJSON format shares this representation
+pub(crate) fn serialize_element(element: &Element) -> Value {
+    match element {
+        Element::Text { text, font } => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Text".to_string()));
+            map.insert("text".to_string(), Value::String(text.clone()));
+            // Whole sizes are written as integers, as they were before fractional sizes
+            let size = match font.size_pt.fract() == 0.0 {
+                true => Value::Number((font.size_pt as u64).into()),
+                false => serde_json::Number::from_f64(font.size_pt as f64)
+                    .map_or(Value::Null, Value::Number),
+            };
+            map.insert("size".to_string(), size);
+            if let Some(family) = &font.family {
+                map.insert("family".to_string(), Value::String(family.clone()));
+            }
+            if let Some(weight) = font.weight {
+                map.insert("weight".to_string(), Value::Number(weight.into()));
+            }
+            Value::Object(map)
+        }
+        Element::Header {
+            level,
+            text,
+            anchor,
+        } => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Header".to_string()));
+            map.insert("level".to_string(), Value::Number((*level).into()));
+            map.insert("text".to_string(), Value::String(text.clone()));
+            if let Some(anchor) = anchor {
+                map.insert("anchor".to_string(), Value::String(anchor.clone()));
+            }
+            Value::Object(map)
+        }
+        Element::Paragraph { elements } => {
+            let elements_json = elements.iter().map(serialize_element).collect();
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Paragraph".to_string()));
+            map.insert("elements".to_string(), Value::Array(elements_json));
+            Value::Object(map)
+        }
+        Element::Table {
+            headers,
+            rows,
+            alignments,
+            caption,
+        } => {
+            let headers_json: Vec<Value> = headers
+                .iter()
+                .map(|h| {
+                    let mut h_map = Map::new();
+                    h_map.insert("element".to_string(), serialize_element(&h.element));
+                    h_map.insert(
+                        "width".to_string(),
+                        Value::Number(serde_json::Number::from_f64(h.width as f64).unwrap()),
+                    );
+                    Value::Object(h_map)
+                })
+                .collect();
+
+            let rows_json: Vec<Value> = rows
+                .iter()
+                .map(|r| {
+                    let cells_json: Vec<Value> = r
+                        .cells
+                        .iter()
+                        .map(|c| match &c.elements[..] {
+                            [element] => serialize_element(element),
+                            // Cells with several blocks are arrays of elements
+                            elements => {
+                                Value::Array(elements.iter().map(serialize_element).collect())
+                            }
+                        })
+                        .collect();
+                    let mut row_map = Map::new();
+                    row_map.insert("cells".to_string(), Value::Array(cells_json));
+                    Value::Object(row_map)
+                })
+                .collect();
+
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Table".to_string()));
+            map.insert("headers".to_string(), Value::Array(headers_json));
+            map.insert("rows".to_string(), Value::Array(rows_json));
+            if !alignments.is_empty() {
+                let alignments_json: Vec<Value> = alignments
+                    .iter()
+                    .map(|alignment| Value::String(alignment.to_string()))
+                    .collect();
+                map.insert("alignments".to_string(), Value::Array(alignments_json));
+            }
+            if !caption.is_empty() {
+                map.insert(
+                    "caption".to_string(),
+                    Value::Array(caption.iter().map(serialize_element).collect()),
+                );
+            }
+            Value::Object(map)
+        }
+        Element::List { elements, numbered } => {
+            let elements_json: Vec<Value> = elements
+                .iter()
+                .map(|item| {
+                    let mut item_map = Map::new();
+                    item_map.insert("element".to_string(), serialize_element(&item.element));
+                    if let Some(checked) = item.checked {
+                        item_map.insert("checked".to_string(), Value::Bool(checked));
+                    }
+                    Value::Object(item_map)
+                })
+                .collect();
+
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("List".to_string()));
+            map.insert("numbered".to_string(), Value::Bool(*numbered));
+            map.insert("elements".to_string(), Value::Array(elements_json));
+            Value::Object(map)
+        }
+        Element::Image(image_data) => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Image".to_string()));
+            // Encode image bytes to base64 for JSON representation
+            map.insert(
+                "bytes".to_string(),
+                Value::String(base64::encode(&image_data.bytes())),
+            );
+            map.insert(
+                "title".to_string(),
+                Value::String(image_data.title().to_string()),
+            );
+            map.insert(
+                "alt".to_string(),
+                Value::String(image_data.alt().to_string()),
+            );
+            map.insert(
+                "image_type".to_string(),
+                Value::String(image_data.image_type().to_string()),
+            );
+
+            map.insert(
+                "align".to_string(),
+                Value::String(image_data.align().to_string().to_lowercase()),
+            );
+
+            let mut size_map = Map::new();
+            if let Some(width) = &image_data.size().width {
+                size_map.insert("width".to_string(), Value::String(width.clone()));
+            }
+            if let Some(height) = &image_data.size().height {
+                size_map.insert("height".to_string(), Value::String(height.clone()));
+            }
+            map.insert("size".to_string(), Value::Object(size_map));
+            Value::Object(map)
+        }
+        Element::Hyperlink {
+            title,
+            url,
+            alt,
+            size,
+        } => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Hyperlink".to_string()));
+            map.insert("title".to_string(), Value::String(title.clone()));
+            map.insert("url".to_string(), Value::String(url.clone()));
+            map.insert("alt".to_string(), Value::String(alt.clone()));
+            map.insert("size".to_string(), Value::Number((*size).into()));
+            Value::Object(map)
+        }
+        Element::LineBreak => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("LineBreak".to_string()));
+            Value::Object(map)
+        }
+        Element::HorizontalRule => {
+            let mut map = Map::new();
+            map.insert(
+                "type".to_string(),
+                Value::String("HorizontalRule".to_string()),
+            );
+            Value::Object(map)
+        }
+        Element::PageBreak => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("PageBreak".to_string()));
+            Value::Object(map)
+        }
+        Element::TableOfContents { max_level } => {
+            let mut map = Map::new();
+            map.insert(
+                "type".to_string(),
+                Value::String("TableOfContents".to_string()),
+            );
+            map.insert("max_level".to_string(), Value::Number((*max_level).into()));
+            Value::Object(map)
+        }
+        Element::Comment {
+            author,
+            date,
+            text,
+            range,
+        } => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Comment".to_string()));
+            map.insert("text".to_string(), Value::String(text.clone()));
+            for (key, value) in [("author", author), ("date", date), ("range", range)] {
+                if let Some(value) = value {
+                    map.insert(key.to_string(), Value::String(value.clone()));
+                }
+            }
+            Value::Object(map)
+        }
+        Element::Blockquote {
+            elements,
+            attribution,
+        } => {
+            let elements_json = elements.iter().map(serialize_element).collect();
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Blockquote".to_string()));
+            map.insert("elements".to_string(), Value::Array(elements_json));
+            if !attribution.is_empty() {
+                map.insert(
+                    "attribution".to_string(),
+                    Value::Array(attribution.iter().map(serialize_element).collect()),
+                );
+            }
+            Value::Object(map)
+        }
+        Element::CodeBlock { language, code } => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("CodeBlock".to_string()));
+            if let Some(language) = language {
+                map.insert("language".to_string(), Value::String(language.clone()));
+            }
+            map.insert("code".to_string(), Value::String(code.clone()));
+            Value::Object(map)
+        }
+        Element::Footnote { id, content } => {
+            let content_json = content.iter().map(serialize_element).collect();
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Footnote".to_string()));
+            map.insert("id".to_string(), Value::String(id.clone()));
+            map.insert("content".to_string(), Value::Array(content_json));
+            Value::Object(map)
+        }
+        Element::FootnoteReference { id } => {
+            let mut map = Map::new();
+            map.insert(
+                "type".to_string(),
+                Value::String("FootnoteReference".to_string()),
+            );
+            map.insert("id".to_string(), Value::String(id.clone()));
+            Value::Object(map)
+        }
+        Element::Styled { style, elements } => {
+            let elements_json = elements.iter().map(serialize_element).collect();
+            let mut style_map = Map::new();
+            style_map.insert("bold".to_string(), Value::Bool(style.bold));
+            style_map.insert("italic".to_string(), Value::Bool(style.italic));
+            style_map.insert("underline".to_string(), Value::Bool(style.underline));
+            style_map.insert(
+                "strikethrough".to_string(),
+                Value::Bool(style.strikethrough),
+            );
+            // Older documents have no vertical position, it is written only when set
+            for (name, enabled) in [
+                ("superscript", style.superscript),
+                ("subscript", style.subscript),
+            ] {
+                if enabled {
+                    style_map.insert(name.to_string(), Value::Bool(true));
+                }
+            }
+            if let Some(color) = style.highlight {
+                style_map.insert("highlight".to_string(), Value::String(color.to_string()));
+            }
+            for (name, color) in [("color", style.color), ("background", style.background)] {
+                if let Some(color) = color {
+                    style_map.insert(name.to_string(), Value::String(color.to_string()));
+                }
+            }
+            if let Some(direction) = style.direction {
+                style_map.insert(
+                    "direction".to_string(),
+                    Value::String(direction.to_string()),
+                );
+            }
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Styled".to_string()));
+            map.insert("style".to_string(), Value::Object(style_map));
+            map.insert("elements".to_string(), Value::Array(elements_json));
+            Value::Object(map)
+        }
+        Element::Math { tex, display } => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Math".to_string()));
+            map.insert("tex".to_string(), Value::String(tex.clone()));
+            map.insert("display".to_string(), Value::Bool(*display));
+            Value::Object(map)
+        }
+        Element::DefinitionList { items } => {
+            let items_json: Vec<Value> = items
+                .iter()
+                .map(|item| {
+                    let mut item_map = Map::new();
+                    item_map.insert(
+                        "term".to_string(),
+                        Value::Array(item.term.iter().map(serialize_element).collect()),
+                    );
+                    item_map.insert(
+                        "definitions".to_string(),
+                        Value::Array(item.definitions.iter().map(serialize_element).collect()),
+                    );
+                    Value::Object(item_map)
+                })
+                .collect();
+            let mut map = Map::new();
+            map.insert(
+                "type".to_string(),
+                Value::String("DefinitionList".to_string()),
+            );
+            map.insert("items".to_string(), Value::Array(items_json));
+            Value::Object(map)
+        }
+        Element::Figure { image, caption } => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Figure".to_string()));
+            map.insert(
+                "image".to_string(),
+                serialize_element(&Element::Image(image.clone())),
+            );
+            map.insert(
+                "caption".to_string(),
+                Value::Array(caption.iter().map(serialize_element).collect()),
+            );
+            Value::Object(map)
+        }
+        // The source as HTML has it, embedded media as a data url
+        Element::Media {
+            kind,
+            source,
+            poster,
+        } => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Media".to_string()));
+            map.insert("kind".to_string(), Value::String(kind.to_string()));
+            map.insert("src".to_string(), Value::String(source.to_src()));
+            if let Some(poster) = poster {
+                map.insert(
+                    "poster".to_string(),
+                    serialize_element(&Element::Image(poster.clone())),
+                );
+            }
+            Value::Object(map)
+        }
+        Element::Chart {
+            chart_type,
+            series,
+            categories,
+        } => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Chart".to_string()));
+            map.insert(
+                "chart_type".to_string(),
+                Value::String(chart_type.to_string()),
+            );
+            map.insert(
+                "categories".to_string(),
+                Value::Array(categories.iter().cloned().map(Value::String).collect()),
+            );
+            let series = series
+                .iter()
+                .map(|series| {
+                    let mut map = Map::new();
+                    map.insert("name".to_string(), Value::String(series.name.clone()));
+                    // Values that are not finite have no JSON number, they are written as null
+                    let values = series.values.iter().map(|value| {
+                        serde_json::Number::from_f64(*value).map_or(Value::Null, Value::Number)
+                    });
+                    map.insert("values".to_string(), Value::Array(values.collect()));
+                    Value::Object(map)
+                })
+                .collect();
+            map.insert("series".to_string(), Value::Array(series));
+            Value::Object(map)
+        }
+        Element::Ruby { base, annotation } => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Ruby".to_string()));
+            map.insert("base".to_string(), Value::String(base.clone()));
+            map.insert("annotation".to_string(), Value::String(annotation.clone()));
+            Value::Object(map)
+        }
+        Element::Section { level, children } => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Section".to_string()));
+            map.insert("level".to_string(), Value::Number((*level).into()));
+            map.insert(
+                "children".to_string(),
+                Value::Array(children.iter().map(serialize_element).collect()),
+            );
+            Value::Object(map)
+        }
+        Element::Custom {
+            kind,
+            data,
+            children,
+        } => {
+            let mut map = Map::new();
+            map.insert("type".to_string(), Value::String("Custom".to_string()));
+            map.insert("kind".to_string(), Value::String(kind.clone()));
+            map.insert("data".to_string(), data.clone());
+            map.insert(
+                "children".to_string(),
+                Value::Array(children.iter().map(serialize_element).collect()),
+            );
+            Value::Object(map)
+        }
+    }
+}
+
+/// Replaces the elements of a type this version does not know, from a later version, with
+/// custom elements holding their fields, and the unknown elements nested in known ones
+fn shim_elements(value: &mut Value) {
+    match value {
+        Value::Array(items) => items.iter_mut().for_each(shim_elements),
+        Value::Object(object) => {
+            let type_str = object.get("type").and_then(Value::as_str).map(str::to_string);
+            match type_str.as_deref() {
+                // The data of a custom element is not elements
+                Some("Custom") => {
+                    if let Some(children) = object.get_mut("children") {
+                        shim_elements(children);
+                    }
+                }
+                Some(kind) if ElementKind::from_str(kind).is_err() => {
+                    object.remove("type");
+                    let data = Value::Object(std::mem::take(object));
+                    *value = json!({ "type": "Custom", "kind": kind, "data": data, "children": [] });
+                }
+                // Headers, rows, list and definition items hold elements too
+                _ => object.values_mut().for_each(shim_elements),
+            }
+        }
+        _ => {}
+    }
+}
+
 // Функция для разбора массива элементов
 fn parse_elements(value: &Value) -> anyhow::Result<Vec<Element>> {
     let array = value
@@ -312,7 +668,7 @@ fn parse_elements(value: &Value) -> anyhow::Result<Vec<Element>> {
 }
 
 // Функция для разбора отдельного элемента
-fn parse_element(value: &Value) -> anyhow::Result<Element> {
+pub(crate) fn parse_element(value: &Value) -> anyhow::Result<Element> {
     let obj = value
         .as_object()
         .ok_or_else(|| anyhow::anyhow!("Element is not an object"))?;
@@ -328,11 +684,22 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
                 .and_then(|v| v.as_str())
                 .ok_or_else(|| anyhow::anyhow!("Text element missing 'text' field"))?
                 .to_string();
-            let size =
-                obj.get("size").and_then(|v| v.as_u64()).ok_or_else(|| {
+            let size_pt =
+                obj.get("size").and_then(|v| v.as_f64()).ok_or_else(|| {
                     anyhow::anyhow!("Text element missing or invalid 'size' field")
-                })? as u8;
-            Ok(Element::Text { text, size })
+                })? as f32;
+            let font = FontSpec {
+                family: obj
+                    .get("family")
+                    .and_then(|v| v.as_str())
+                    .map(str::to_string),
+                size_pt,
+                weight: obj
+                    .get("weight")
+                    .and_then(|v| v.as_u64())
+                    .map(|weight| weight as u16),
+            };
+            Ok(Element::Text { text, font })
         }
         "Header" => {
             let level =
@@ -344,7 +711,15 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
                 .and_then(|v| v.as_str())
                 .ok_or_else(|| anyhow::anyhow!("Header element missing 'text' field"))?
                 .to_string();
-            Ok(Element::Header { level, text })
+            let anchor = obj
+                .get("anchor")
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            Ok(Element::Header {
+                level,
+                text,
+                anchor,
+            })
         }
         "Paragraph" => {
             let elements = parse_elements(
@@ -354,6 +729,22 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
             )?;
             Ok(Element::Paragraph { elements })
         }
+        "Blockquote" => {
+            let elements = parse_elements(
+                &obj.get("elements")
+                    .ok_or_else(|| anyhow::anyhow!("Blockquote missing 'elements' field"))?
+                    .clone(),
+            )?;
+            // Optional, quotes without a source have none
+            let attribution = match obj.get("attribution") {
+                Some(attribution) => parse_elements(attribution)?,
+                None => Vec::new(),
+            };
+            Ok(Element::Blockquote {
+                elements,
+                attribution,
+            })
+        }
         "Table" => {
             let headers = parse_table_headers(
                 &obj.get("headers")
@@ -365,7 +756,32 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
                     .ok_or_else(|| anyhow::anyhow!("Table missing 'rows' field"))?
                     .clone(),
             )?;
-            Ok(Element::Table { headers, rows })
+            // Optional, tables written before column alignments have none
+            let alignments = obj
+                .get("alignments")
+                .and_then(Value::as_array)
+                .map(|alignments| {
+                    alignments
+                        .iter()
+                        .map(|alignment| {
+                            alignment
+                                .as_str()
+                                .and_then(|alignment| ColumnAlignment::from_str(alignment).ok())
+                                .unwrap_or_default()
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            let caption = match obj.get("caption") {
+                Some(caption) => parse_elements(caption)?,
+                None => Vec::new(),
+            };
+            Ok(Element::Table {
+                headers,
+                rows,
+                alignments,
+                caption,
+            })
         }
         "List" => {
             let numbered = obj
@@ -465,6 +881,248 @@ fn parse_element(value: &Value) -> anyhow::Result<Element> {
                 size,
             })
         }
+        "LineBreak" => Ok(Element::LineBreak),
+        "HorizontalRule" => Ok(Element::HorizontalRule),
+        "PageBreak" => Ok(Element::PageBreak),
+        "TableOfContents" => {
+            let max_level = obj
+                .get("max_level")
+                .and_then(|v| v.as_u64())
+                .ok_or_else(|| {
+                    anyhow::anyhow!("TableOfContents element missing or invalid 'max_level' field")
+                })? as u8;
+            Ok(Element::TableOfContents { max_level })
+        }
+        "Comment" => {
+            let text = obj
+                .get("text")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Comment element missing 'text' field"))?
+                .to_string();
+            let optional = |key: &str| obj.get(key).and_then(|v| v.as_str()).map(str::to_string);
+            Ok(Element::Comment {
+                text,
+                author: optional("author"),
+                date: optional("date"),
+                range: optional("range"),
+            })
+        }
+        "CodeBlock" => {
+            let language = obj
+                .get("language")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            let code = obj
+                .get("code")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("CodeBlock element missing 'code' field"))?
+                .to_string();
+            Ok(Element::CodeBlock { language, code })
+        }
+        "Footnote" => {
+            let id = obj
+                .get("id")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Footnote element missing 'id' field"))?
+                .to_string();
+            let content = parse_elements(
+                &obj.get("content")
+                    .ok_or_else(|| anyhow::anyhow!("Footnote missing 'content' field"))?
+                    .clone(),
+            )?;
+            Ok(Element::Footnote { id, content })
+        }
+        "FootnoteReference" => {
+            let id = obj
+                .get("id")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("FootnoteReference element missing 'id' field"))?
+                .to_string();
+            Ok(Element::FootnoteReference { id })
+        }
+        "Styled" => {
+            let style_obj = obj
+                .get("style")
+                .and_then(|v| v.as_object())
+                .ok_or_else(|| anyhow::anyhow!("Styled element missing 'style' field"))?;
+            let flag = |name: &str| style_obj.get(name).and_then(|v| v.as_bool()) == Some(true);
+            let color = |name: &str| {
+                style_obj
+                    .get(name)
+                    .and_then(|v| v.as_str())
+                    .map(Color::from_str)
+                    .transpose()
+            };
+            let style = TextStyle {
+                bold: flag("bold"),
+                italic: flag("italic"),
+                underline: flag("underline"),
+                strikethrough: flag("strikethrough"),
+                superscript: flag("superscript"),
+                subscript: flag("subscript"),
+                highlight: style_obj
+                    .get("highlight")
+                    .and_then(|v| v.as_str())
+                    .map(|color| {
+                        HighlightColor::from_str(color)
+                            .map_err(|_| anyhow::anyhow!("Invalid highlight: {}", color))
+                    })
+                    .transpose()?,
+                color: color("color")?,
+                background: color("background")?,
+                direction: style_obj
+                    .get("direction")
+                    .and_then(|v| v.as_str())
+                    .map(|direction| {
+                        TextDirection::from_str(direction)
+                            .map_err(|_| anyhow::anyhow!("Invalid direction: {}", direction))
+                    })
+                    .transpose()?,
+            };
+            let elements = parse_elements(
+                &obj.get("elements")
+                    .ok_or_else(|| anyhow::anyhow!("Styled missing 'elements' field"))?
+                    .clone(),
+            )?;
+            Ok(Element::Styled { style, elements })
+        }
+        "Math" => {
+            let tex = obj
+                .get("tex")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Math element missing 'tex' field"))?
+                .to_string();
+            let display = obj.get("display").and_then(|v| v.as_bool()) == Some(true);
+            Ok(Element::Math { tex, display })
+        }
+        "DefinitionList" => {
+            let items = obj
+                .get("items")
+                .and_then(|v| v.as_array())
+                .ok_or_else(|| anyhow::anyhow!("DefinitionList missing 'items' field"))?
+                .iter()
+                .map(parse_definition_item)
+                .collect::<anyhow::Result<Vec<DefinitionItem>>>()?;
+            Ok(Element::DefinitionList { items })
+        }
+        "Figure" => {
+            let image = obj
+                .get("image")
+                .ok_or_else(|| anyhow::anyhow!("Figure missing 'image' field"))?;
+            let Element::Image(image) = parse_element(image)? else {
+                return Err(anyhow::anyhow!("Figure 'image' is not an image"));
+            };
+            let caption = obj
+                .get("caption")
+                .and_then(|v| v.as_array())
+                .ok_or_else(|| anyhow::anyhow!("Figure missing 'caption' field"))?
+                .iter()
+                .map(parse_element)
+                .collect::<anyhow::Result<Vec<Element>>>()?;
+            Ok(Element::Figure { image, caption })
+        }
+        "Media" => {
+            let kind_str = obj
+                .get("kind")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Media element missing 'kind' field"))?;
+            let kind = MediaKind::from_str(kind_str)
+                .map_err(|_| anyhow::anyhow!("Invalid media kind: {}", kind_str))?;
+            let src = obj
+                .get("src")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Media element missing 'src' field"))?;
+            let poster = match obj.get("poster").map(parse_element).transpose()? {
+                Some(Element::Image(image)) => Some(image),
+                Some(_) => return Err(anyhow::anyhow!("Media 'poster' is not an image")),
+                None => None,
+            };
+            Ok(Element::Media {
+                kind,
+                source: MediaSource::from_src(src),
+                poster,
+            })
+        }
+        "Chart" => {
+            let chart_type_str = obj
+                .get("chart_type")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Chart element missing 'chart_type' field"))?;
+            let chart_type = ChartType::from_str(chart_type_str)
+                .map_err(|_| anyhow::anyhow!("Invalid chart type: {}", chart_type_str))?;
+            let categories = obj
+                .get("categories")
+                .and_then(|v| v.as_array())
+                .map(|categories| {
+                    categories
+                        .iter()
+                        .map(|category| category.as_str().unwrap_or("").to_string())
+                        .collect()
+                })
+                .unwrap_or_default();
+            let series = obj
+                .get("series")
+                .and_then(|v| v.as_array())
+                .ok_or_else(|| anyhow::anyhow!("Chart element missing 'series' field"))?
+                .iter()
+                .map(|series| ChartSeries {
+                    name: series["name"].as_str().unwrap_or("").to_string(),
+                    values: series["values"]
+                        .as_array()
+                        .map(|values| {
+                            values
+                                .iter()
+                                .map(|value| value.as_f64().unwrap_or(f64::NAN))
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                })
+                .collect();
+            Ok(Element::Chart {
+                chart_type,
+                series,
+                categories,
+            })
+        }
+        "Ruby" => {
+            let base = obj
+                .get("base")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Ruby element missing 'base' field"))?;
+            let annotation = obj
+                .get("annotation")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Ruby element missing 'annotation' field"))?;
+            Ok(Element::Ruby {
+                base: base.to_string(),
+                annotation: annotation.to_string(),
+            })
+        }
+        "Section" => {
+            let level = obj.get("level").and_then(|v| v.as_u64()).ok_or_else(|| {
+                anyhow::anyhow!("Section element missing or invalid 'level' field")
+            })? as u8;
+            let children = parse_elements(
+                obj.get("children")
+                    .ok_or_else(|| anyhow::anyhow!("Section element missing 'children' field"))?,
+            )?;
+            Ok(Element::Section { level, children })
+        }
+        "Custom" => {
+            let kind = obj
+                .get("kind")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Custom element missing 'kind' field"))?;
+            let children = match obj.get("children") {
+                Some(children) => parse_elements(children)?,
+                None => Vec::new(),
+            };
+            Ok(Element::Custom {
+                kind: kind.to_string(),
+                data: obj.get("data").cloned().unwrap_or(Value::Null),
+                children,
+            })
+        }
         _ => Err(anyhow::anyhow!("Unknown element type: {}", type_str)),
     }
 }
@@ -516,10 +1174,16 @@ fn parse_table_rows(value: &Value) -> anyhow::Result<Vec<TableRow>> {
             .ok_or_else(|| anyhow::anyhow!("Row 'cells' is not an array"))?;
         let mut table_cells = Vec::new();
         for cell in cells_array {
-            let cell_element = parse_element(cell)?;
-            table_cells.push(TableCell {
-                element: cell_element,
-            });
+            let table_cell = match cell.as_array() {
+                Some(elements) => TableCell::with_elements(
+                    elements
+                        .iter()
+                        .map(parse_element)
+                        .collect::<anyhow::Result<_>>()?,
+                ),
+                None => TableCell::new(parse_element(cell)?),
+            };
+            table_cells.push(table_cell);
         }
         rows.push(TableRow { cells: table_cells });
     }
@@ -537,16 +1201,39 @@ fn parse_list_item(value: &Value) -> anyhow::Result<ListItem> {
             .ok_or_else(|| anyhow::anyhow!("ListItem missing 'element' field"))?
             .clone(),
     )?;
-    Ok(ListItem { element })
+    let checked = obj.get("checked").and_then(Value::as_bool);
+    Ok(ListItem { element, checked })
+}
+
+// Функция для разбора элементов списка определений
+fn parse_definition_item(value: &Value) -> anyhow::Result<DefinitionItem> {
+    let obj = value
+        .as_object()
+        .ok_or_else(|| anyhow::anyhow!("DefinitionItem is not an object"))?;
+    let term = parse_elements(
+        obj.get("term")
+            .ok_or_else(|| anyhow::anyhow!("DefinitionItem missing 'term' field"))?,
+    )?;
+    let definitions = parse_elements(
+        obj.get("definitions")
+            .ok_or_else(|| anyhow::anyhow!("DefinitionItem missing 'definitions' field"))?,
+    )?;
+    Ok(DefinitionItem { term, definitions })
 }
 
 #[cfg(test)]
 mod tests {
     use log::{debug, info};
 
+    use bytes::Bytes;
+    use serde_json::{json, Value};
+
     use crate::core::tests::init_logger;
-    use crate::core::{disk_image_loader, TransformerWithImageLoaderSaverTrait};
+    use crate::core::{
+        disk_image_loader, Document, DocumentType, Element, TransformerWithImageLoaderSaverTrait,
+    };
     use crate::json::TransformerTrait;
+    use crate::schema::SCHEMA_VERSION;
 
     #[test]
     fn test() -> anyhow::Result<()> {
@@ -615,4 +1302,44 @@ blabla2 bla bla blabla bla bla blabla bla bla blabla bla bla bla"#;
         info!("{}", generated_text2);
         Ok(())
     }
+    #[test]
+    fn test_schema_version() -> anyhow::Result<()> {
+        // Written before the schema was versioned
+        let mut document = json!({
+            "page_width": 210.0,
+            "page_height": 297.0,
+            "top_page_indent": 10.0,
+            "bottom_page_indent": 10.0,
+            "left_page_indent": 10.0,
+            "right_page_indent": 10.0,
+            "elements": [{
+                "type": "Paragraph",
+                "elements": [{ "type": "Text", "text": "Hello", "size": 8.0 }],
+            }],
+        });
+        let parsed = Document::parse(&Bytes::from(document.to_string()), DocumentType::Json)?;
+        assert_eq!(parsed.to_plain_text(), "Hello");
+        let generated: Value =
+            serde_json::from_slice(&Document::generate(&parsed, DocumentType::Json)?)?;
+        assert_eq!(generated["schema_version"], json!(SCHEMA_VERSION));
+
+        // A later version may write elements this one does not know
+        let spacer = json!({ "type": "Spacer", "height": 4 });
+        if let Some(elements) = document["elements"][0]["elements"].as_array_mut() {
+            elements.push(spacer);
+        }
+        let input = Bytes::from(document.to_string());
+        assert!(Document::parse(&input, DocumentType::Json).is_err());
+        document["schema_version"] = json!(SCHEMA_VERSION + 1);
+        let input = Bytes::from(document.to_string());
+        let parsed = Document::parse(&input, DocumentType::Json)?;
+        let [Element::Paragraph { elements }] = &parsed.get_detail()[..] else {
+            panic!("Expected a paragraph, got {:?}", parsed.get_detail());
+        };
+        assert!(matches!(
+            &elements[1],
+            Element::Custom { kind, data, .. } if kind == "Spacer" && data["height"] == 4
+        ));
+        Ok(())
+    }
 }
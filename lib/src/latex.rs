@@ -0,0 +1,1452 @@
+use crate::core::*;
+use crate::parse_util::collapse_whitespace;
+use bytes::Bytes;
+use std::collections::HashMap;
+
+pub struct Transformer;
+
+/// Options for LaTeX generation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatexOptions {
+    /// Document class, `article` by default. `book`, `report` and their KOMA variants
+    /// map level 1 headers to `\chapter`.
+    pub document_class: String,
+    /// Class options, e.g. `11pt,twoside`.
+    pub class_options: Option<String>,
+    /// Inserted after the packages shiva relies on and before `\begin{document}`.
+    pub preamble: Option<String>,
+}
+
+impl Default for LatexOptions {
+    fn default() -> Self {
+        LatexOptions {
+            document_class: "article".to_string(),
+            class_options: None,
+            preamble: None,
+        }
+    }
+}
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Parses the commonly used subset of LaTeX: sectioning commands, `itemize`/`enumerate`,
+    /// `tabular`, `verbatim`, `\includegraphics` and `\href`. Unknown commands keep the text
+    /// of their argument, unknown environments are parsed as regular content.
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let source = strip_comments(&String::from_utf8(document.to_vec())?);
+        let (preamble, body) = match source.find(BEGIN_DOCUMENT) {
+            Some(start) => {
+                let body = &source[start + BEGIN_DOCUMENT.len()..];
+                let end = body.find("\\end{document}").unwrap_or(body.len());
+                (&source[..start], &body[..end])
+            }
+            None => ("", source.as_str()),
+        };
+        let argument = |command: &str| {
+            preamble.find(command).and_then(|start| {
+                let mut parser = Parser::new(&preamble[start + command.len()..], &image_loader);
+                parser.group()
+            })
+        };
+        let title = argument("\\title").map(plain_text);
+        let mut metadata = Metadata {
+            title: title.clone(),
+            created: argument("\\date").map(plain_text).filter(|date| !date.is_empty()),
+            subject: argument("pdfsubject=").map(plain_text),
+            keywords: argument("pdfkeywords=")
+                .map(|keywords| Metadata::split_keywords(&plain_text(keywords)))
+                .unwrap_or_default(),
+            language: argument("pdflang=").map(plain_text),
+            ..Metadata::default()
+        };
+        if let Some(authors) = argument("\\author") {
+            metadata.authors = authors
+                .split("\\and")
+                .map(plain_text)
+                .filter(|author| !author.is_empty())
+                .collect();
+        }
+
+        let mut parser = Parser::new(body, &image_loader);
+        parser.chapters = body.contains("\\chapter");
+        parser.title = title;
+        let elements = parser.parse_blocks(None)?.into_iter().flatten().collect();
+        let mut parsed = Document::new(elements);
+        parsed.metadata = metadata;
+        Ok(parsed)
+    }
+
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        Transformer::generate_with_options(document, image_saver, &LatexOptions::default())
+    }
+}
+
+impl Transformer {
+    /// Generates a standalone `.tex` document. Images are written through `image_saver`
+    /// and referenced by file name from `\includegraphics`.
+    pub fn generate_with_options<F>(
+        document: &Document,
+        image_saver: F,
+        options: &LatexOptions,
+    ) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let mut generator = Generator {
+            image_saver,
+            image_num: 0,
+            chapters: matches!(
+                options.document_class.as_str(),
+                "book" | "report" | "memoir" | "scrbook" | "scrreprt"
+            ),
+            footnotes: document.footnotes(),
+        };
+
+        let mut body = String::new();
+        let mut header = String::new();
+        let mut footer = String::new();
+        for band in &document.bands {
+            match band {
+                Band::PageHeader(elements) => {
+                    for element in elements {
+                        generator.inline(&mut header, element)?;
+                    }
+                }
+                Band::PageFooter(elements) => {
+                    for element in elements {
+                        generator.inline(&mut footer, element)?;
+                    }
+                }
+                band => {
+                    for element in band.elements() {
+                        generator.block(&mut body, element)?;
+                    }
+                }
+            }
+        }
+
+        let mut latex = String::new();
+        match &options.class_options {
+            Some(class_options) => latex.push_str(&format!(
+                "\\documentclass[{}]{{{}}}\n",
+                class_options, options.document_class
+            )),
+            None => latex.push_str(&format!("\\documentclass{{{}}}\n", options.document_class)),
+        }
+        latex.push_str("\\usepackage[utf8]{inputenc}\n");
+        latex.push_str("\\usepackage[T1]{fontenc}\n");
+        latex.push_str(&geometry(document));
+        latex.push_str("\\usepackage{graphicx}\n");
+        latex.push_str("\\usepackage{hyperref}\n");
+        latex.push_str("\\usepackage{amsmath}\n");
+        // \sout for strikethrough, normalem keeps \emph in italics
+        latex.push_str("\\usepackage[normalem]{ulem}\n");
+        if !header.is_empty() || !footer.is_empty() {
+            latex.push_str("\\usepackage{fancyhdr}\n");
+            latex.push_str("\\pagestyle{fancy}\n");
+            latex.push_str("\\fancyhf{}\n");
+            latex.push_str(&format!("\\fancyhead[C]{{{}}}\n", header.trim()));
+            latex.push_str(&format!("\\fancyfoot[C]{{{}}}\n", footer.trim()));
+        }
+        latex.push_str(&metadata(&document.metadata));
+        if let Some(preamble) = &options.preamble {
+            latex.push_str(preamble);
+            if !preamble.ends_with('\n') {
+                latex.push('\n');
+            }
+        }
+        latex.push_str("\\begin{document}\n\n");
+        latex.push_str(&body);
+        latex.push_str("\\end{document}\n");
+        Ok(Bytes::from(latex))
+    }
+}
+
+/// `\\title`, `\\author` and `\\date` for `\\maketitle`, and the properties hyperref writes
+/// to the PDF
+fn metadata(metadata: &Metadata) -> String {
+    let mut latex = String::new();
+    if let Some(title) = &metadata.title {
+        latex.push_str(&format!("\\title{{{}}}\n", escape(title)));
+    }
+    if !metadata.authors.is_empty() {
+        let authors: Vec<String> = metadata.authors.iter().map(|author| escape(author)).collect();
+        latex.push_str(&format!("\\author{{{}}}\n", authors.join(" \\and ")));
+    }
+    if let Some(created) = &metadata.created {
+        latex.push_str(&format!("\\date{{{}}}\n", escape(created)));
+    }
+    let mut properties = Vec::new();
+    if let Some(subject) = &metadata.subject {
+        properties.push(format!("pdfsubject={{{}}}", escape(subject)));
+    }
+    if let Some(keywords) = metadata.keywords_text() {
+        properties.push(format!("pdfkeywords={{{}}}", escape(&keywords)));
+    }
+    if let Some(language) = &metadata.language {
+        properties.push(format!("pdflang={{{}}}", escape(language)));
+    }
+    if !properties.is_empty() {
+        latex.push_str(&format!("\\hypersetup{{{}}}\n", properties.join(", ")));
+    }
+    latex
+}
+
+fn geometry(document: &Document) -> String {
+    let dimensions = document.page_format.dimensions();
+    let (width, height) = match document.orientation {
+        PageOrientation::Portrait => (dimensions.page_width, dimensions.page_height),
+        PageOrientation::Landscape => (dimensions.page_height, dimensions.page_width),
+    };
+    format!(
+        "\\usepackage[paperwidth={}mm,paperheight={}mm,top={}mm,bottom={}mm,left={}mm,right={}mm]{{geometry}}\n",
+        width,
+        height,
+        dimensions.page_margin_top,
+        dimensions.page_margin_bottom,
+        dimensions.page_margin_left,
+        dimensions.page_margin_right
+    )
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `\href` reads its URL verbatim except for these characters
+fn escape_url(url: &str) -> String {
+    url.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('#', "\\#")
+}
+
+struct Generator<'a, F> {
+    image_saver: F,
+    image_num: usize,
+    chapters: bool,
+    /// Footnote contents, written at their references
+    footnotes: HashMap<&'a str, &'a [Element]>,
+}
+
+impl<F> Generator<'_, F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn block(&mut self, latex: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Header { level, text, .. } => {
+                const SECTIONS: [&str; 6] = [
+                    "chapter",
+                    "section",
+                    "subsection",
+                    "subsubsection",
+                    "paragraph",
+                    "subparagraph",
+                ];
+                let offset = if self.chapters { 0 } else { 1 };
+                let index = (*level as usize).saturating_sub(1) + offset;
+                let command = SECTIONS[index.min(SECTIONS.len() - 1)];
+                latex.push_str(&format!("\\{}{{{}}}\n\n", command, escape(text)));
+            }
+            Element::List { elements, numbered } => {
+                self.list(latex, elements, *numbered)?;
+                latex.push('\n');
+            }
+            // The float is numbered by LaTeX from its caption
+            Element::Figure { image, caption } => {
+                latex.push_str("\\begin{figure}[h]\n\\centering\n");
+                self.image(latex, image)?;
+                latex.push('\n');
+                if !caption.is_empty() {
+                    let mut text = String::new();
+                    for child in caption {
+                        self.inline(&mut text, child)?;
+                    }
+                    latex.push_str(&format!("\\caption{{{}}}\n", text.trim()));
+                }
+                latex.push_str("\\end{figure}\n\n");
+            }
+            Element::DefinitionList { items } => {
+                latex.push_str("\\begin{description}\n");
+                for item in items {
+                    let mut term = String::new();
+                    for child in &item.term {
+                        self.inline(&mut term, child)?;
+                    }
+                    latex.push_str(&format!("\\item[{}]", term.trim()));
+                    for definition in &item.definitions {
+                        latex.push(' ');
+                        self.inline(latex, definition)?;
+                    }
+                    latex.push('\n');
+                }
+                latex.push_str("\\end{description}\n\n");
+            }
+            Element::Chart { .. } => self.block(latex, &element.chart_fallback())?,
+            Element::Table { headers, rows, .. } => {
+                let columns = rows
+                    .iter()
+                    .map(|row| row.cells.len())
+                    .chain(std::iter::once(headers.len()))
+                    .max()
+                    .unwrap_or(0)
+                    .max(1);
+                latex.push_str(&format!(
+                    "\\begin{{tabular}}{{|{}}}\n\\hline\n",
+                    "l|".repeat(columns)
+                ));
+                if !headers.is_empty() {
+                    let mut cells = Vec::new();
+                    for header in headers {
+                        let mut cell = String::new();
+                        self.inline(&mut cell, &header.element)?;
+                        cells.push(format!("\\textbf{{{}}}", cell));
+                    }
+                    latex.push_str(&format!("{} \\\\\n\\hline\n", cells.join(" & ")));
+                }
+                for row in rows {
+                    let mut cells = Vec::new();
+                    for cell in &row.cells {
+                        let mut text = String::new();
+                        self.inline(&mut text, &cell.element())?;
+                        cells.push(text);
+                    }
+                    latex.push_str(&format!("{} \\\\\n\\hline\n", cells.join(" & ")));
+                }
+                latex.push_str("\\end{tabular}\n\n");
+            }
+            Element::Comment { text, .. } => {
+                for line in text.lines() {
+                    latex.push_str(&format!("% {}\n", line));
+                }
+                latex.push('\n');
+            }
+            // The depth counts from the section, or from the chapter where there are chapters
+            Element::TableOfContents { max_level } => {
+                let depth = *max_level as i32 - if self.chapters { 1 } else { 0 };
+                latex.push_str(&format!(
+                    "\\setcounter{{tocdepth}}{{{}}}\n\\tableofcontents\n\n",
+                    depth
+                ));
+            }
+            Element::Blockquote { elements, .. } => {
+                latex.push_str("\\begin{quote}\n");
+                for child in elements {
+                    self.block(latex, child)?;
+                }
+                latex.push_str("\\end{quote}\n\n");
+            }
+            // The headers open the sectioning commands
+            Element::Section { children, .. } => {
+                for child in children {
+                    self.block(latex, child)?;
+                }
+            }
+            Element::CodeBlock { code, .. } => {
+                latex.push_str(&format!(
+                    "\\begin{{verbatim}}\n{}\n\\end{{verbatim}}\n\n",
+                    code
+                ));
+            }
+            Element::Footnote { .. } => {}
+            Element::Math { tex, display: true } => {
+                latex.push_str(&format!("\\[\n{}\n\\]\n\n", tex));
+            }
+            element => {
+                self.inline(latex, element)?;
+                latex.push_str("\n\n");
+            }
+        }
+        Ok(())
+    }
+
+    fn list(
+        &mut self,
+        latex: &mut String,
+        items: &[ListItem],
+        numbered: bool,
+    ) -> anyhow::Result<()> {
+        let environment = if numbered { "enumerate" } else { "itemize" };
+        latex.push_str(&format!("\\begin{{{}}}\n", environment));
+        for (index, item) in items.iter().enumerate() {
+            match &item.element {
+                // A nested list belongs to the preceding item
+                Element::List { elements, numbered } => {
+                    if index == 0 {
+                        latex.push_str("\\item\n");
+                    }
+                    self.list(latex, elements, *numbered)?;
+                }
+                Element::Header { text, .. } => {
+                    latex.push_str(&format!("\\item \\textbf{{{}}}\n", escape(text)));
+                }
+                element => {
+                    latex.push_str("\\item ");
+                    self.inline(latex, element)?;
+                    latex.push('\n');
+                }
+            }
+        }
+        latex.push_str(&format!("\\end{{{}}}\n", environment));
+        Ok(())
+    }
+
+    fn image(&mut self, latex: &mut String, image: &ImageData) -> anyhow::Result<()> {
+        if image.bytes().is_empty() {
+            return Ok(());
+        }
+        let image_path = format!(
+            "image{}{}",
+            self.image_num,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &image_path)?;
+        self.image_num += 1;
+        let width = image.size().width.as_deref().and_then(image_width);
+        match width {
+            Some(width) => latex.push_str(&format!(
+                "\\includegraphics[width={}]{{{}}}",
+                width, image_path
+            )),
+            None => latex.push_str(&format!("\\includegraphics{{{}}}", image_path)),
+        }
+        Ok(())
+    }
+
+    fn inline(&mut self, latex: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Text { text, .. } => latex.push_str(&escape(text)),
+            Element::Header { text, .. } => {
+                latex.push_str(&format!("\\textbf{{{}}}", escape(text)));
+            }
+            Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => {
+                for child in elements {
+                    self.inline(latex, child)?;
+                }
+            }
+            Element::Paragraph { elements } => {
+                for (index, child) in elements.iter().enumerate() {
+                    if index > 0
+                        && matches!(child, Element::Text { .. } | Element::Hyperlink { .. })
+                    {
+                        latex.push(' ');
+                    }
+                    self.inline(latex, child)?;
+                }
+            }
+            Element::Styled { style, elements } => {
+                let commands = [
+                    (style.bold, "\\textbf"),
+                    (style.italic, "\\textit"),
+                    (style.underline, "\\underline"),
+                    (style.strikethrough, "\\sout"),
+                ];
+                let commands: Vec<&str> = commands
+                    .into_iter()
+                    .filter_map(|(enabled, command)| enabled.then_some(command))
+                    .collect();
+                for command in &commands {
+                    latex.push_str(&format!("{}{{", command));
+                }
+                for child in elements {
+                    self.inline(latex, child)?;
+                }
+                latex.push_str(&"}".repeat(commands.len()));
+            }
+            Element::Hyperlink { title, url, .. } => {
+                latex.push_str(&format!(
+                    "\\href{{{}}}{{{}}}",
+                    escape_url(url),
+                    escape(title)
+                ));
+            }
+            Element::Image(image) => self.image(latex, image)?,
+            Element::Figure { image, caption } => {
+                self.image(latex, image)?;
+                for child in caption {
+                    latex.push(' ');
+                    self.inline(latex, child)?;
+                }
+            }
+            Element::LineBreak => latex.push_str("\\\\\n"),
+            Element::HorizontalRule => latex.push_str("\\noindent\\rule{\\linewidth}{0.4pt}"),
+            Element::PageBreak => latex.push_str("\\newpage\n"),
+            Element::Media { .. } => self.inline(latex, &element.media_fallback())?,
+            Element::Ruby { .. } => self.inline(latex, &element.ruby_fallback())?,
+            Element::TableOfContents { .. } | Element::Custom { .. } => {}
+            Element::List { elements, numbered } => {
+                latex.push('\n');
+                self.list(latex, elements, *numbered)?;
+            }
+            Element::Table { .. } | Element::Chart { .. } | Element::DefinitionList { .. } => {
+                latex.push('\n');
+                self.block(latex, element)?;
+            }
+            Element::CodeBlock { code, .. } => {
+                latex.push_str(&format!("\\texttt{{{}}}", escape(code)));
+            }
+            Element::Math { tex, display } => {
+                if *display {
+                    latex.push_str(&format!("\\[{}\\]", tex));
+                } else {
+                    latex.push_str(&format!("${}$", tex));
+                }
+            }
+            Element::FootnoteReference { id } => match self.footnotes.get(id.as_str()).copied() {
+                Some(content) => {
+                    let mut note = String::new();
+                    for child in content {
+                        self.inline(&mut note, child)?;
+                        note.push(' ');
+                    }
+                    latex.push_str(&format!("\\footnote{{{}}}", note.trim_end()));
+                }
+                None => latex.push_str(&format!("\\textsuperscript{{{}}}", escape(id))),
+            },
+            Element::Footnote { .. } => {}
+            // A comment would swallow the rest of the line
+            Element::Comment { .. } => {}
+        }
+        Ok(())
+    }
+}
+
+/// Converts an HTML style width (`300`, `300px`, `50%`) to a LaTeX length
+fn image_width(width: &str) -> Option<String> {
+    let width = width.trim();
+    if let Some(percent) = width.strip_suffix('%') {
+        let percent: f32 = percent.trim().parse().ok()?;
+        return Some(format!("{}\\linewidth", percent / 100.0));
+    }
+    let pixels: f32 = width.trim_end_matches("px").parse().ok()?;
+    Some(format!("{}px", pixels))
+}
+
+const BEGIN_DOCUMENT: &str = "\\begin{document}";
+
+/// Removes `%` comments, except inside verbatim environments
+fn strip_comments(source: &str) -> String {
+    let mut result = String::with_capacity(source.len());
+    let mut verbatim = false;
+    for line in source.lines() {
+        if line.contains("\\begin{verbatim}") || line.contains("\\begin{lstlisting}") {
+            verbatim = true;
+        }
+        if verbatim {
+            result.push_str(line);
+            if line.contains("\\end{verbatim}") || line.contains("\\end{lstlisting}") {
+                verbatim = false;
+            }
+        } else {
+            let mut escaped = false;
+            let end = line
+                .char_indices()
+                .find(|(_, c)| {
+                    let comment = *c == '%' && !escaped;
+                    escaped = *c == '\\' && !escaped;
+                    comment
+                })
+                .map(|(index, _)| index)
+                .unwrap_or(line.len());
+            result.push_str(&line[..end]);
+        }
+        result.push('\n');
+    }
+    result
+}
+
+/// Commands whose arguments are not document text
+const IGNORED_COMMANDS: &[&str] = &[
+    "label",
+    "ref",
+    "pageref",
+    "cite",
+    "vspace",
+    "hspace",
+    "setlength",
+    "addtolength",
+    "input",
+    "include",
+    "bibliography",
+    "bibliographystyle",
+    "pagestyle",
+    "thispagestyle",
+    "usepackage",
+    "newcommand",
+    "renewcommand",
+    "newpage",
+    "clearpage",
+    "centering",
+    "noindent",
+    "hline",
+    "toprule",
+    "midrule",
+    "bottomrule",
+    "cline",
+];
+
+fn symbol(name: &str) -> Option<&'static str> {
+    match name {
+        "%" => Some("%"),
+        "$" => Some("$"),
+        "&" => Some("&"),
+        "#" => Some("#"),
+        "_" => Some("_"),
+        "{" => Some("{"),
+        "}" => Some("}"),
+        " " => Some(" "),
+        "textbackslash" => Some("\\"),
+        "textasciitilde" => Some("~"),
+        "textasciicircum" => Some("^"),
+        "ldots" | "dots" => Some("\u{2026}"),
+        "LaTeX" => Some("LaTeX"),
+        "TeX" => Some("TeX"),
+        _ => None,
+    }
+}
+
+/// Text of a command argument with markup removed
+fn plain_text(source: &str) -> String {
+    let loader = |_: &str| -> anyhow::Result<Bytes> { Ok(Bytes::new()) };
+    let mut parser = Parser::new(source, &loader);
+    let mut text = String::new();
+    parser.inline_text(&mut text);
+    normalize_text(&text).trim().to_string()
+}
+
+/// Collapses whitespace and replaces the dash and quote ligatures of TeX
+fn normalize_text(text: &str) -> String {
+    collapse_whitespace(text)
+        .replace("---", "\u{2014}")
+        .replace("--", "\u{2013}")
+        .replace("``", "\u{201c}")
+        .replace("''", "\u{201d}")
+}
+
+struct Parser<'a, F> {
+    source: &'a str,
+    position: usize,
+    image_loader: &'a F,
+    chapters: bool,
+    title: Option<String>,
+    /// The `tocdepth` counter, the levels a table of contents lists
+    toc_depth: Option<i32>,
+}
+
+impl<'a, F> Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    fn new(source: &'a str, image_loader: &'a F) -> Parser<'a, F> {
+        Parser {
+            source,
+            position: 0,
+            image_loader,
+            chapters: false,
+            title: None,
+            toc_depth: None,
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.position..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    /// Reads the command name after a backslash, control symbols are a single character
+    fn command(&mut self) -> String {
+        self.position += 1;
+        let rest = self.rest();
+        let length = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        if length == 0 {
+            return match rest.chars().next() {
+                Some(c) => {
+                    self.position += c.len_utf8();
+                    c.to_string()
+                }
+                None => String::new(),
+            };
+        }
+        self.position += length;
+        if self.rest().starts_with('*') {
+            self.position += 1;
+        }
+        rest[..length].to_string()
+    }
+
+    fn delimited(&mut self, open: char, close: char) -> Option<&'a str> {
+        let saved = self.position;
+        self.skip_whitespace();
+        if self.peek() != Some(open) {
+            self.position = saved;
+            return None;
+        }
+        let start = self.position + 1;
+        let mut depth = 0;
+        let mut escaped = false;
+        for (index, c) in self.rest().char_indices() {
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    let end = self.position + index;
+                    self.position = end + 1;
+                    return Some(&self.source[start..end]);
+                }
+            }
+        }
+        self.position = self.source.len();
+        Some(&self.source[start..])
+    }
+
+    fn group(&mut self) -> Option<&'a str> {
+        self.delimited('{', '}')
+    }
+
+    fn optional(&mut self) -> Option<&'a str> {
+        self.delimited('[', ']')
+    }
+
+    /// Raw content up to `\end{environment}`, used where the content is not LaTeX markup
+    fn raw_environment(&mut self, environment: &str) -> &'a str {
+        let end = format!("\\end{{{}}}", environment);
+        let rest = self.rest();
+        match rest.find(&end) {
+            Some(index) => {
+                self.position += index + end.len();
+                &rest[..index]
+            }
+            None => {
+                self.position = self.source.len();
+                rest
+            }
+        }
+    }
+
+    /// Plain text of the remaining source, used for command arguments
+    fn inline_text(&mut self, text: &mut String) {
+        while let Some(c) = self.peek() {
+            match c {
+                '\\' => {
+                    let name = self.command();
+                    if let Some(symbol) = symbol(&name) {
+                        text.push_str(symbol);
+                        if self.rest().starts_with("{}") {
+                            self.position += 2;
+                        }
+                    } else if name == "\\" {
+                        text.push(' ');
+                    } else if IGNORED_COMMANDS.contains(&name.as_str()) {
+                        self.optional();
+                        self.group();
+                    } else {
+                        self.optional();
+                        if let Some(argument) = self.group() {
+                            text.push_str(&plain_text(argument));
+                        }
+                    }
+                }
+                '~' => {
+                    text.push(' ');
+                    self.position += 1;
+                }
+                '{' | '}' | '$' => self.position += 1,
+                c => {
+                    text.push(c);
+                    self.position += c.len_utf8();
+                }
+            }
+        }
+    }
+
+    /// Parses block content until `\end{environment}`. The result is grouped by `\item`,
+    /// content before the first item is the first group.
+    fn parse_blocks(&mut self, environment: Option<&str>) -> anyhow::Result<Vec<Vec<Element>>> {
+        let mut groups: Vec<Vec<Element>> = vec![Vec::new()];
+        let mut paragraph: Vec<Element> = Vec::new();
+        while let Some(c) = self.peek() {
+            match c {
+                '\\' => {
+                    let name = self.command();
+                    let level = match name.as_str() {
+                        "chapter" => Some(1),
+                        "section" => Some(2),
+                        "subsection" => Some(3),
+                        "subsubsection" => Some(4),
+                        "paragraph" => Some(5),
+                        "subparagraph" => Some(6),
+                        _ => None,
+                    };
+                    if let Some(level) = level {
+                        self.optional();
+                        let text = plain_text(self.group().unwrap_or_default());
+                        let level = if self.chapters { level } else { level - 1 };
+                        flush_paragraph(&mut paragraph, groups.last_mut().unwrap());
+                        groups.last_mut().unwrap().push(Element::Header {
+                            level: level.max(1),
+                            text,
+                            anchor: None,
+                        });
+                        continue;
+                    }
+                    match name.as_str() {
+                        "begin" => {
+                            let name = self.group().unwrap_or_default();
+                            flush_paragraph(&mut paragraph, groups.last_mut().unwrap());
+                            let mut elements = self.environment(name)?;
+                            groups.last_mut().unwrap().append(&mut elements);
+                        }
+                        "end" => {
+                            let name = self.group().unwrap_or_default();
+                            if environment == Some(name) {
+                                break;
+                            }
+                        }
+                        "item" => {
+                            self.optional();
+                            flush_paragraph(&mut paragraph, groups.last_mut().unwrap());
+                            groups.push(Vec::new());
+                        }
+                        "par" => flush_paragraph(&mut paragraph, groups.last_mut().unwrap()),
+                        "maketitle" => {
+                            flush_paragraph(&mut paragraph, groups.last_mut().unwrap());
+                            if let Some(title) = self.title.clone() {
+                                groups.last_mut().unwrap().push(Element::Header {
+                                    level: 1,
+                                    text: title,
+                                    anchor: None,
+                                });
+                            }
+                        }
+                        "\\" | "newline" | "linebreak" => {
+                            self.optional();
+                            paragraph.push(Element::LineBreak);
+                        }
+                        "hrule" | "rule" => {
+                            if name == "rule" {
+                                self.optional();
+                                self.group();
+                                self.group();
+                            }
+                            flush_paragraph(&mut paragraph, groups.last_mut().unwrap());
+                            groups.last_mut().unwrap().push(Element::HorizontalRule);
+                        }
+                        "setcounter" => {
+                            let counter = self.group().unwrap_or_default().trim();
+                            let value = self.group().unwrap_or_default();
+                            if counter == "tocdepth" {
+                                self.toc_depth = value.trim().parse().ok();
+                            }
+                        }
+                        // Both the article and the book classes list three levels by default
+                        "tableofcontents" => {
+                            let depth = self.toc_depth.unwrap_or(if self.chapters { 2 } else { 3 });
+                            let level = depth + if self.chapters { 1 } else { 0 };
+                            flush_paragraph(&mut paragraph, groups.last_mut().unwrap());
+                            groups.last_mut().unwrap().push(Element::TableOfContents {
+                                max_level: level.clamp(1, 6) as u8,
+                            });
+                        }
+                        "newpage" | "clearpage" | "cleardoublepage" | "pagebreak" => {
+                            self.optional();
+                            flush_paragraph(&mut paragraph, groups.last_mut().unwrap());
+                            groups.last_mut().unwrap().push(Element::PageBreak);
+                        }
+                        "href" => {
+                            let url = self.group().unwrap_or_default();
+                            let title = self.group().unwrap_or_default();
+                            paragraph.push(Element::Hyperlink {
+                                title: plain_text(title),
+                                url: url.replace("\\%", "%").replace("\\#", "#"),
+                                alt: String::new(),
+                                size: 8,
+                            });
+                        }
+                        "url" => {
+                            let url = self.group().unwrap_or_default().to_string();
+                            paragraph.push(Element::Hyperlink {
+                                title: url.clone(),
+                                url,
+                                alt: String::new(),
+                                size: 8,
+                            });
+                        }
+                        "includegraphics" => {
+                            let options = self.optional();
+                            let path = self.group().unwrap_or_default().trim();
+                            paragraph.push(self.image(path, options)?);
+                        }
+                        name => {
+                            if let Some(symbol) = symbol(name) {
+                                push_text(&mut paragraph, symbol);
+                                if self.rest().starts_with("{}") {
+                                    self.position += 2;
+                                }
+                            } else if IGNORED_COMMANDS.contains(&name) {
+                                self.optional();
+                                self.group();
+                            } else {
+                                self.optional();
+                                if let Some(argument) = self.group() {
+                                    push_text(&mut paragraph, &plain_text(argument));
+                                }
+                            }
+                        }
+                    }
+                }
+                '\n' => {
+                    self.position += 1;
+                    let rest = self.rest();
+                    let line_end = rest.find('\n');
+                    if line_end.is_some_and(|end| rest[..end].trim().is_empty()) {
+                        flush_paragraph(&mut paragraph, groups.last_mut().unwrap());
+                    } else {
+                        push_text(&mut paragraph, " ");
+                    }
+                }
+                '~' => {
+                    self.position += 1;
+                    push_text(&mut paragraph, " ");
+                }
+                '{' | '}' => self.position += 1,
+                '$' => {
+                    let display = self.rest().starts_with("$$");
+                    let delimiter = if display { "$$" } else { "$" };
+                    self.position += delimiter.len();
+                    let rest = self.rest();
+                    let end = rest.find(delimiter).unwrap_or(rest.len());
+                    paragraph.push(Element::Math {
+                        tex: rest[..end].trim().to_string(),
+                        display,
+                    });
+                    self.position += (end + delimiter.len()).min(rest.len());
+                }
+                c => {
+                    let rest = self.rest();
+                    let end = rest
+                        .find(['\\', '\n', '~', '{', '}', '$'])
+                        .unwrap_or(rest.len());
+                    let end = if end == 0 { c.len_utf8() } else { end };
+                    push_text(&mut paragraph, &rest[..end]);
+                    self.position += end;
+                }
+            }
+        }
+        flush_paragraph(&mut paragraph, groups.last_mut().unwrap());
+        Ok(groups)
+    }
+
+    fn environment(&mut self, name: &str) -> anyhow::Result<Vec<Element>> {
+        match name {
+            "itemize" | "enumerate" | "description" => {
+                let mut groups = self.parse_blocks(Some(name))?;
+                // Anything before the first \item is not part of an item
+                let mut elements = groups.remove(0);
+                let mut items = Vec::new();
+                for group in groups {
+                    let (lists, content): (Vec<Element>, Vec<Element>) = group
+                        .into_iter()
+                        .partition(|element| matches!(element, Element::List { .. }));
+                    if let Some(element) = merge_elements(content) {
+                        items.push(ListItem {
+                            element,
+                            checked: None,
+                        });
+                    }
+                    items.extend(lists.into_iter().map(|element| ListItem {
+                        element,
+                        checked: None,
+                    }));
+                }
+                elements.push(Element::List {
+                    elements: items,
+                    numbered: name == "enumerate",
+                });
+                Ok(elements)
+            }
+            "tabular" | "tabular*" | "tabularx" | "longtable" => {
+                if name != "tabular" && name != "longtable" {
+                    self.group();
+                }
+                self.optional();
+                self.group();
+                let raw = self.raw_environment(name);
+                Ok(vec![self.table(raw)?])
+            }
+            "verbatim" | "lstlisting" => {
+                self.optional();
+                let raw = self.raw_environment(name);
+                Ok(vec![Element::Text {
+                    text: raw.trim_matches('\n').to_string(),
+                    font: FontSpec::from(8),
+                }])
+            }
+            "equation" | "equation*" | "align" | "align*" | "displaymath" => {
+                let raw = self.raw_environment(name);
+                Ok(vec![Element::Math {
+                    tex: raw.trim().to_string(),
+                    display: true,
+                }])
+            }
+            // figure, center, quote, minipage and friends only wrap regular content
+            _ => {
+                if name == "minipage" {
+                    self.optional();
+                    self.group();
+                }
+                Ok(self
+                    .parse_blocks(Some(name))?
+                    .into_iter()
+                    .flatten()
+                    .collect())
+            }
+        }
+    }
+
+    fn table(&mut self, raw: &str) -> anyhow::Result<Element> {
+        let mut rows: Vec<(bool, Vec<Element>)> = Vec::new();
+        let mut pending_rule = false;
+        for row in split_top_level(raw, "\\\\") {
+            let (rule, row) = strip_rules(row);
+            pending_rule |= rule;
+            if row.trim().is_empty() {
+                continue;
+            }
+            let mut cells = Vec::new();
+            for cell in split_top_level(row, "&") {
+                let mut parser = Parser::new(cell, self.image_loader);
+                let elements = parser.parse_blocks(None)?.into_iter().flatten().collect();
+                cells.push(merge_elements(elements).unwrap_or(Element::Text {
+                    text: String::new(),
+                    font: FontSpec::from(8),
+                }));
+            }
+            rows.push((pending_rule, cells));
+            pending_rule = false;
+        }
+
+        // A rule right after the first row marks it as the header row
+        let mut headers = Vec::new();
+        if rows.len() > 1 && rows[1].0 {
+            headers = rows
+                .remove(0)
+                .1
+                .into_iter()
+                .map(|element| TableHeader {
+                    element,
+                    width: 30.0,
+                })
+                .collect();
+        }
+        let rows = rows
+            .into_iter()
+            .map(|(_, cells)| TableRow {
+                cells: cells.into_iter().map(TableCell::new).collect(),
+            })
+            .collect();
+        Ok(Element::Table {
+            headers,
+            rows,
+            alignments: vec![],
+            caption: vec![],
+        })
+    }
+
+    fn image(&self, path: &str, options: Option<&str>) -> anyhow::Result<Element> {
+        // Graphics paths may omit the extension
+        let mut result = (self.image_loader)(path).map(|bytes| (bytes, path.to_string()));
+        if result.is_err() && !path.contains('.') {
+            for extension in [".png", ".jpg", ".jpeg"] {
+                let candidate = format!("{}{}", path, extension);
+                if let Ok(bytes) = (self.image_loader)(&candidate) {
+                    result = Ok((bytes, candidate));
+                    break;
+                }
+            }
+        }
+        let (bytes, path) = result?;
+        let width = options.and_then(|options| {
+            options.split(',').find_map(|option| {
+                let (key, value) = option.split_once('=')?;
+                let value = value.trim();
+                (key.trim() == "width" && value.ends_with("px"))
+                    .then(|| value.trim_end_matches("px").to_string())
+            })
+        });
+        Ok(Element::Image(ImageData::new(
+            bytes,
+            String::new(),
+            String::new(),
+            path,
+            String::new(),
+            ImageDimension {
+                width,
+                height: None,
+            },
+        )))
+    }
+}
+
+/// Splits on `separator` outside of braces, skipping escaped characters
+fn split_top_level<'a>(source: &'a str, separator: &str) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut index = 0;
+    let bytes = source.as_bytes();
+    while index < bytes.len() {
+        // Separators are ASCII, a match is always on a char boundary
+        if depth == 0 && bytes[index..].starts_with(separator.as_bytes()) {
+            parts.push(&source[start..index]);
+            index += separator.len();
+            start = index;
+            continue;
+        }
+        match bytes[index] {
+            b'\\' => index += 1,
+            b'{' => depth += 1,
+            b'}' => depth -= 1,
+            _ => {}
+        }
+        index += 1;
+    }
+    parts.push(&source[start.min(source.len())..]);
+    parts
+}
+
+/// Removes leading table rules from a row, returns whether there were any
+fn strip_rules(row: &str) -> (bool, &str) {
+    let mut row = row.trim_start();
+    let mut rule = false;
+    loop {
+        let Some(command) = [
+            "\\hline",
+            "\\toprule",
+            "\\midrule",
+            "\\bottomrule",
+            "\\cline",
+        ]
+        .iter()
+        .find(|command| row.starts_with(**command)) else {
+            return (rule, row);
+        };
+        rule = true;
+        row = row[command.len()..].trim_start();
+        if *command == "\\cline" {
+            if let Some(end) = row.find('}') {
+                row = row[end + 1..].trim_start();
+            }
+        }
+    }
+}
+
+fn push_text(elements: &mut Vec<Element>, text: &str) {
+    if let Some(Element::Text { text: last, .. }) = elements.last_mut() {
+        last.push_str(text);
+    } else {
+        elements.push(Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        });
+    }
+}
+
+fn flush_paragraph(paragraph: &mut Vec<Element>, elements: &mut Vec<Element>) {
+    let last = paragraph.len().saturating_sub(1);
+    for (index, element) in paragraph.iter_mut().enumerate() {
+        if let Element::Text { text, .. } = element {
+            let mut collapsed = normalize_text(text);
+            if index == 0 {
+                collapsed = collapsed.trim_start().to_string();
+            }
+            if index == last {
+                collapsed = collapsed.trim_end().to_string();
+            }
+            *text = collapsed;
+        }
+    }
+    paragraph.retain(|element| !matches!(element, Element::Text { text, .. } if text.is_empty()));
+    if let Some(Element::LineBreak) = paragraph.last() {
+        paragraph.pop();
+    }
+    match paragraph.len() {
+        0 => {}
+        // A figure is an image on its own, not a paragraph
+        1 if matches!(paragraph[0], Element::Image(_)) => elements.append(paragraph),
+        _ => elements.push(Element::Paragraph {
+            elements: std::mem::take(paragraph),
+        }),
+    }
+}
+
+/// Collapses list item or table cell content into a single element
+fn merge_elements(mut elements: Vec<Element>) -> Option<Element> {
+    match elements.len() {
+        0 => None,
+        1 => match elements.remove(0) {
+            Element::Paragraph { mut elements } if elements.len() == 1 => Some(elements.remove(0)),
+            element => Some(element),
+        },
+        _ => Some(Element::Paragraph { elements }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::*;
+    use crate::latex::*;
+    use crate::markdown;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let saved = RefCell::new(Vec::new());
+        let generated = Transformer::generate_with_saver(&parsed, |_bytes, name| {
+            saved.borrow_mut().push(name.to_string());
+            Ok(())
+        })?;
+        let latex = String::from_utf8(generated.to_vec())?;
+        assert!(latex.starts_with("\\documentclass{article}\n"));
+        assert!(latex.contains("\\section{First header}"));
+        assert!(latex.contains("\\subsection{Second header}"));
+        assert!(latex.contains("\\begin{enumerate}"));
+        assert!(latex.contains("\\begin{itemize}"));
+        assert!(latex.contains("\\begin{tabular}{|l|l|l|l|l|l|l|l|}"));
+        assert!(latex.contains("\\href{https://link-url-here.org}{Link text Here}"));
+        assert!(latex.contains("\\includegraphics{image0.png}"));
+        assert_eq!(saved.borrow()[0], "image0.png");
+        assert!(latex.ends_with("\\end{document}\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_options() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Intro".to_string(),
+                anchor: None,
+            },
+            Element::Text {
+                text: "100% of $5 & #1_a".to_string(),
+                font: FontSpec::from(8),
+            },
+        ]);
+        let options = LatexOptions {
+            document_class: "report".to_string(),
+            class_options: Some("11pt".to_string()),
+            preamble: Some("\\usepackage{lmodern}".to_string()),
+        };
+        let generated = Transformer::generate_with_options(&document, |_, _| Ok(()), &options)?;
+        let latex = String::from_utf8(generated.to_vec())?;
+        assert!(latex.starts_with("\\documentclass[11pt]{report}\n"));
+        assert!(latex.contains("\\usepackage{lmodern}\n\\begin{document}"));
+        assert!(latex.contains("\\chapter{Intro}"));
+        assert!(latex.contains("100\\% of \\$5 \\& \\#1\\_a"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let source = r"\documentclass{article}
+\title{Quarterly \textbf{report}}
+\begin{document}
+\maketitle
+\section{Results} % comment
+Revenue grew by 5\% in
+Q3, see \href{https://example.com/a\#b}{the dashboard}.
+
+\subsection*{Details}
+\begin{enumerate}
+  \item First
+  \begin{itemize}
+    \item Nested
+  \end{itemize}
+  \item Second
+\end{enumerate}
+\begin{tabular}{|l|r|}
+\hline
+Name & Value \\
+\hline
+a & 1 \\
+b & 2 \\
+\hline
+\end{tabular}
+\begin{verbatim}
+let x = 1; % not a comment
+\end{verbatim}
+\end{document}
+";
+        let document = Transformer::parse(&Bytes::from(source))?;
+        let elements = document.get_all_elements();
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 1,
+                text: "Quarterly report".to_string(),
+                anchor: None,
+            }
+        );
+        assert_eq!(
+            elements[2],
+            &Element::Paragraph {
+                elements: vec![
+                    text("Revenue grew by 5% in Q3, see "),
+                    Element::Hyperlink {
+                        title: "the dashboard".to_string(),
+                        url: "https://example.com/a#b".to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    },
+                    text("."),
+                ]
+            }
+        );
+        assert_eq!(
+            elements[3],
+            &Element::Header {
+                level: 2,
+                text: "Details".to_string(),
+                anchor: None,
+            }
+        );
+        assert_eq!(
+            elements[4],
+            &Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("First"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: Element::List {
+                            elements: vec![ListItem {
+                                element: text("Nested"),
+                                checked: None,
+                            }],
+                            numbered: false,
+                        },
+                        checked: None,
+                    },
+                    ListItem {
+                        element: text("Second"),
+                        checked: None,
+                    },
+                ],
+                numbered: true,
+            }
+        );
+        let Element::Table { headers, rows, .. } = elements[5] else {
+            panic!("expected a table, got {:?}", elements[5]);
+        };
+        assert_eq!(headers.len(), 2);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].cells[1].elements, vec![text("2")]);
+        assert_eq!(elements[6], &text("let x = 1; % not a comment"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let images = RefCell::new(std::collections::HashMap::new());
+        let generated = Transformer::generate_with_saver(&parsed, |bytes, name| {
+            images.borrow_mut().insert(name.to_string(), bytes.clone());
+            Ok(())
+        })?;
+        let reparsed = Transformer::parse_with_loader(&generated, |name| {
+            Ok(images.borrow().get(name).cloned().unwrap_or_default())
+        })?;
+        let original = parsed.get_all_elements();
+        let elements = reparsed.get_all_elements();
+        assert_eq!(original.len(), elements.len());
+        for (original, element) in original.iter().zip(elements.iter()) {
+            assert_eq!(
+                std::mem::discriminant(*original),
+                std::mem::discriminant(*element)
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_accented_table() -> anyhow::Result<()> {
+        let source = r"\begin{tabular}{ll}
+Café & Crème \\
+\'{E}té & über \\
+\end{tabular}
+";
+        let document = Transformer::parse(&Bytes::from(source))?;
+        let Element::Table { headers, rows, .. } = &document.get_all_elements()[0] else {
+            panic!("expected a table");
+        };
+        let cells: Vec<String> = headers
+            .iter()
+            .map(|header| header.element.plain_text())
+            .chain(rows.iter().flat_map(|row| &row.cells).map(|cell| cell.element().plain_text()))
+            .collect();
+        assert_eq!(cells.len(), 4);
+        assert_eq!(cells[..2], ["Café", "Crème"]);
+        assert_eq!(cells[3], "über");
+        Ok(())
+    }
+}
@@ -4,6 +4,51 @@
 
 pub mod core;
 
+pub mod diff;
+
+pub mod validate;
+
+pub mod sanitize;
+
+#[cfg(feature = "json")]
+pub mod schema;
+
+#[cfg(feature = "async")]
+pub mod asynchronous;
+
+pub mod registry;
+
+mod telemetry;
+
+mod emoji;
+
+#[cfg(any(
+    feature = "html",
+    feature = "typst",
+    feature = "docx",
+    feature = "xlsx"
+))]
+mod chart;
+
+#[cfg(any(feature = "pptx", feature = "odp"))]
+mod slides;
+
+#[cfg(any(feature = "docx", feature = "pptx", feature = "xlsx"))]
+mod properties;
+
+#[cfg(any(
+    feature = "odt",
+    feature = "docbook",
+    feature = "fb2",
+    feature = "pptx",
+    feature = "epub",
+    feature = "latex",
+    feature = "org",
+    feature = "rst",
+    feature = "typst"
+))]
+mod parse_util;
+
 #[cfg(feature = "text")]
 pub mod text;
 
@@ -42,3 +87,111 @@ pub mod ods;
 
 #[cfg(feature = "xls")]
 pub mod xls;
+
+#[cfg(feature = "epub")]
+pub mod epub;
+
+#[cfg(feature = "odt")]
+pub mod odt;
+
+#[cfg(feature = "latex")]
+pub mod latex;
+
+#[cfg(feature = "asciidoc")]
+pub mod asciidoc;
+
+#[cfg(feature = "rst")]
+pub mod rst;
+
+#[cfg(feature = "org")]
+pub mod org;
+
+#[cfg(feature = "docbook")]
+pub mod docbook;
+
+#[cfg(feature = "fb2")]
+pub mod fb2;
+
+#[cfg(feature = "pptx")]
+pub mod pptx;
+
+#[cfg(feature = "odp")]
+pub mod odp;
+
+#[cfg(feature = "ipynb")]
+pub mod ipynb;
+
+#[cfg(feature = "mhtml")]
+pub mod mhtml;
+
+#[cfg(feature = "mediawiki")]
+pub mod mediawiki;
+
+#[cfg(feature = "confluence")]
+pub mod confluence;
+
+#[cfg(feature = "jira")]
+pub mod jira;
+
+#[cfg(feature = "bbcode")]
+pub mod bbcode;
+
+#[cfg(feature = "textile")]
+pub mod textile;
+
+#[cfg(feature = "man")]
+pub mod man;
+
+#[cfg(feature = "gemtext")]
+pub mod gemtext;
+
+#[cfg(feature = "ansi")]
+pub mod ansi;
+
+#[cfg(feature = "opml")]
+pub mod opml;
+
+#[cfg(feature = "quill")]
+pub mod quill;
+
+#[cfg(feature = "prosemirror")]
+pub mod prosemirror;
+
+#[cfg(feature = "slack")]
+pub mod slack;
+
+#[cfg(feature = "tsv")]
+pub mod tsv;
+
+#[cfg(feature = "ndjson")]
+pub mod ndjson;
+
+#[cfg(feature = "yaml")]
+pub mod yaml;
+
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+#[cfg(feature = "arrow")]
+pub mod arrow;
+
+#[cfg(any(feature = "sqlite", feature = "arrow"))]
+pub(crate) mod tables;
+
+#[cfg(feature = "svg")]
+pub mod svg;
+
+#[cfg(feature = "png")]
+pub mod png;
+
+#[cfg(feature = "postscript")]
+pub mod postscript;
+
+#[cfg(feature = "djvu")]
+pub mod djvu;
+
+#[cfg(feature = "mobi")]
+pub mod mobi;
+
+#[cfg(feature = "daisy")]
+pub mod daisy;
@@ -0,0 +1,412 @@
+use crate::core::*;
+use bytes::Bytes;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(_document: &Bytes) -> anyhow::Result<Document> {
+        Err(anyhow::anyhow!("man page parsing is not supported"))
+    }
+
+    /// Produces a page for the groff `man` macros. A lone top level header opening the
+    /// document becomes the `.TH` title and the headers below it become `.SH` sections and
+    /// `.SS` subsections. Tables are written for `tbl`, images are replaced by their alt text.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        // A table of contents is written as the list of its headers, sections by theirs
+        let document = &document.flatten_sections().expand_toc();
+        let elements: Vec<&Element> = document
+            .bands
+            .iter()
+            .filter(|band| !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)))
+            .flat_map(|band| band.elements())
+            .collect();
+
+        let levels = elements.iter().filter_map(|element| match element {
+            Element::Header { level, .. } => Some(*level),
+            _ => None,
+        });
+        let top = levels.clone().min().unwrap_or(1);
+        let title = match elements.first() {
+            Some(Element::Header { level, text, .. })
+                if *level == top && levels.filter(|level| *level == top).count() == 1 =>
+            {
+                Some(text.as_str())
+            }
+            _ => None,
+        };
+
+        let mut roff = String::new();
+        roff.push_str(&format!(
+            ".TH \"{}\" 1\n",
+            quoted(&title.unwrap_or("DOCUMENT").to_uppercase())
+        ));
+        // Section level of the highest header that is not the title
+        let section = if title.is_some() { top + 1 } else { top };
+        for element in elements.iter().skip(usize::from(title.is_some())) {
+            block(&mut roff, element, section);
+        }
+        Ok(Bytes::from(roff))
+    }
+}
+
+/// Escapes backslashes and hyphens, and text that would start a request
+fn escape(text: &str) -> String {
+    text.split('\n')
+        .map(|line| {
+            let line = line.replace('\\', "\\e").replace('-', "\\-");
+            if line.trim_start().starts_with(['.', '\'']) {
+                format!("\\&{}", line)
+            } else {
+                line
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Text lines of a filled paragraph, leading spaces would break the line
+fn fill(text: &str) -> String {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Macro arguments in double quotes, a quote inside is doubled
+fn quoted(text: &str) -> String {
+    escape(&text.replace('\n', " ")).replace('"', "\"\"")
+}
+
+fn block(roff: &mut String, element: &Element, section: u8) {
+    match element {
+        Element::Header { level, text, .. } => {
+            let request = if *level <= section { ".SH" } else { ".SS" };
+            let text = if *level <= section {
+                text.to_uppercase()
+            } else {
+                text.clone()
+            };
+            roff.push_str(&format!("{} \"{}\"\n", request, quoted(&text)));
+        }
+        // Multi-line text keeps its layout without filling
+        Element::Text { text, .. } if text.contains('\n') => {
+            roff.push_str(".PP\n.RS 4\n.nf\n");
+            roff.push_str(&escape(text.trim_end_matches('\n')));
+            roff.push_str("\n.fi\n.RE\n");
+        }
+        // Examples are set in a constant width font
+        Element::CodeBlock { code, .. } => {
+            roff.push_str(".PP\n.RS 4\n.EX\n");
+            roff.push_str(&escape(code));
+            roff.push_str("\n.EE\n.RE\n");
+        }
+        Element::List { elements, numbered } => {
+            roff.push_str(".PP\n");
+            list(roff, elements, *numbered);
+        }
+        Element::Chart { .. } => block(roff, &element.chart_fallback(), section),
+        Element::Table { headers, rows, .. } => table(roff, headers, rows),
+        // Tagged paragraphs, the term is the tag and the definitions are indented below it
+        Element::DefinitionList { items } => {
+            for item in items {
+                let term: String = item.term.iter().map(inline).collect();
+                roff.push_str(&format!(
+                    ".TP\n\\fB{}\\fR\n",
+                    fill(&term).replace('\n', " ")
+                ));
+                for (index, definition) in item.definitions.iter().enumerate() {
+                    if index > 0 {
+                        roff.push_str(".br\n");
+                    }
+                    roff.push_str(&fill(&inline(definition)));
+                    roff.push('\n');
+                }
+            }
+        }
+        // A line as long as the output line
+        Element::HorizontalRule => roff.push_str(".PP\n\\l'\\n(.lu'\n"),
+        // Only printed pages break, terminals keep scrolling
+        Element::PageBreak => roff.push_str(".bp\n"),
+        Element::Blockquote { elements, .. } => {
+            roff.push_str(".RS 4\n");
+            for child in elements {
+                block(roff, child, section);
+            }
+            roff.push_str(".RE\n");
+        }
+        Element::Section { children, .. } => {
+            for child in children {
+                block(roff, child, section);
+            }
+        }
+        Element::Comment { text, .. } => {
+            for line in text.lines() {
+                roff.push_str(&format!(".\\\" {}\n", line));
+            }
+        }
+        element => {
+            let text = fill(&inline(element));
+            if !text.is_empty() {
+                roff.push_str(".PP\n");
+                roff.push_str(&text);
+                roff.push('\n');
+            }
+        }
+    }
+}
+
+/// Nested lists are indented below their parent item
+fn list(roff: &mut String, items: &[ListItem], numbered: bool) {
+    let mut number = 0;
+    for item in items {
+        match &item.element {
+            Element::List { elements, numbered } => {
+                roff.push_str(".RS 4\n");
+                list(roff, elements, *numbered);
+                roff.push_str(".RE\n");
+            }
+            element => {
+                number += 1;
+                if numbered {
+                    roff.push_str(&format!(".IP {}. 4\n", number));
+                } else {
+                    roff.push_str(".IP \\(bu 2\n");
+                }
+                roff.push_str(&fill(&inline(element)));
+                roff.push('\n');
+            }
+        }
+    }
+}
+
+/// `tbl` table with a boxed layout and a bold header row
+fn table(roff: &mut String, headers: &[TableHeader], rows: &[TableRow]) {
+    let columns = rows
+        .iter()
+        .map(|row| row.cells.len())
+        .chain(std::iter::once(headers.len()))
+        .max()
+        .unwrap_or(0);
+    if columns == 0 {
+        return;
+    }
+    let format = |key: &str| vec![key; columns].join(" ");
+    roff.push_str(".TS\nallbox tab(\t);\n");
+    if !headers.is_empty() {
+        roff.push_str(&format!("{}\n", format("lb")));
+    }
+    roff.push_str(&format!("{} .\n", format("lx")));
+    if !headers.is_empty() {
+        let cells: Vec<String> = headers.iter().map(|header| cell(&header.element)).collect();
+        roff.push_str(&format!("{}\n", cells.join("\t")));
+    }
+    for row in rows {
+        let cells: Vec<String> = row
+            .cells
+            .iter()
+            .map(|entry| cell(&entry.element()))
+            .collect();
+        roff.push_str(&format!("{}\n", cells.join("\t")));
+    }
+    roff.push_str(".TE\n");
+}
+
+/// Cells are text blocks, so long text wraps inside the column
+fn cell(element: &Element) -> String {
+    let text = inline(element).replace(['\n', '\t'], " ");
+    let text = text.replace(" .br ", " ");
+    format!("T{{\n{}\nT}}", text.trim())
+}
+
+fn image_text(image: &ImageData) -> String {
+    let text = if image.alt().is_empty() {
+        image.title()
+    } else {
+        image.alt()
+    };
+    if text.is_empty() {
+        String::new()
+    } else {
+        format!("[{}]", escape(text))
+    }
+}
+
+fn inline(element: &Element) -> String {
+    match element {
+        Element::Text { text, .. } | Element::Header { text, .. } => escape(text),
+        Element::Paragraph { elements }
+        | Element::Blockquote { elements, .. }
+        | Element::Section {
+            children: elements, ..
+        } => {
+            let mut text = String::new();
+            for child in elements {
+                text.push_str(&inline(child));
+            }
+            text
+        }
+        // Fonts do not nest and there is no underline or strikethrough font
+        Element::Styled { style, elements } => {
+            let text: String = elements.iter().map(inline).collect();
+            match (style.bold, style.italic) {
+                (true, true) => format!("\\f(BI{}\\fR", text),
+                (true, false) => format!("\\fB{}\\fR", text),
+                (false, true) => format!("\\fI{}\\fR", text),
+                (false, false) => text,
+            }
+        }
+        Element::Hyperlink { title, url, .. } => {
+            if title.is_empty() || title == url {
+                format!("\\fI{}\\fR", escape(url))
+            } else {
+                format!("{} (\\fI{}\\fR)", escape(title), escape(url))
+            }
+        }
+        Element::Image(image) => image_text(image),
+        // Pages have no pictures, the caption follows the text of the image
+        Element::Figure { image, caption } => {
+            let mut text = image_text(image);
+            for child in caption {
+                text.push(' ');
+                text.push_str(&inline(child));
+            }
+            text.trim().to_string()
+        }
+        Element::LineBreak | Element::HorizontalRule => "\n.br\n".to_string(),
+        Element::List { elements, .. } => elements
+            .iter()
+            .map(|item| inline(&item.element))
+            .collect::<Vec<String>>()
+            .join(" "),
+        Element::DefinitionList { items } => items
+            .iter()
+            .flat_map(|item| item.term.iter().chain(&item.definitions))
+            .map(inline)
+            .collect::<Vec<String>>()
+            .join(" "),
+        Element::CodeBlock { code, .. } => format!("\\f(CW{}\\fR", escape(code)),
+        Element::Math { tex, .. } => escape(tex),
+        Element::FootnoteReference { id } => format!("[{}]", escape(id)),
+        Element::Footnote { id, content } => {
+            let mut text = format!("[{}] ", escape(id));
+            for child in content {
+                text.push_str(&inline(child));
+            }
+            text
+        }
+        Element::Media { .. } => inline(&element.media_fallback()),
+        Element::Ruby { .. } => inline(&element.ruby_fallback()),
+        Element::Table { .. }
+        | Element::Chart { .. }
+        | Element::Comment { .. }
+        | Element::PageBreak
+        | Element::TableOfContents { .. }
+        | Element::Custom { .. } => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::man::*;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "shiva".to_string(),
+                anchor: None,
+            },
+            Element::Header {
+                level: 2,
+                text: "Synopsis".to_string(),
+                anchor: None,
+            },
+            Element::Paragraph {
+                elements: vec![
+                    text(".hidden and --to"),
+                    Element::LineBreak,
+                    Element::Hyperlink {
+                        title: "docs".to_string(),
+                        url: "https://example.com".to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    },
+                ],
+            },
+            Element::Header {
+                level: 3,
+                text: "Options".to_string(),
+                anchor: None,
+            },
+            Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("First"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: Element::List {
+                            elements: vec![ListItem {
+                                element: text("Nested"),
+                                checked: None,
+                            }],
+                            numbered: false,
+                        },
+                        checked: None,
+                    },
+                ],
+                numbered: true,
+            },
+            Element::Table {
+                headers: vec![TableHeader {
+                    element: text("Flag"),
+                    width: 30.0,
+                }],
+                rows: vec![TableRow {
+                    cells: vec![TableCell::new(text("-v"))],
+                }],
+                alignments: vec![],
+                caption: vec![],
+            },
+            text("a\\b\n.c"),
+        ]);
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(
+            String::from_utf8(generated.to_vec())?,
+            concat!(
+                ".TH \"SHIVA\" 1\n",
+                ".SH \"SYNOPSIS\"\n",
+                ".PP\n\\&.hidden and \\-\\-to\n.br\ndocs (\\fIhttps://example.com\\fR)\n",
+                ".SS \"Options\"\n",
+                ".PP\n.IP 1. 4\nFirst\n.RS 4\n.IP \\(bu 2\nNested\n.RE\n",
+                ".TS\nallbox tab(\t);\nlb\nlx .\nT{\nFlag\nT}\nT{\n\\-v\nT}\n.TE\n",
+                ".PP\n.RS 4\n.nf\na\\eb\n\\&.c\n.fi\n.RE\n",
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_document() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let document = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let generated = Transformer::generate(&document)?;
+        let roff = String::from_utf8(generated.to_vec())?;
+        assert!(roff.starts_with(".TH "));
+        assert_eq!(roff.matches(".TS\n").count(), roff.matches(".TE\n").count());
+        // Every line is a request or text that cannot be taken for one
+        assert!(roff
+            .lines()
+            .all(|line| !line.starts_with('\'') && !line.starts_with(' ')));
+        Ok(())
+    }
+}
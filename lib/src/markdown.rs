@@ -3,8 +3,14 @@ use crate::core::*;
 use bytes::Bytes;
 use comrak::arena_tree::Node;
 use comrak::Arena;
-use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd, TextMergeStream};
-use std::cell::RefCell;
+use pulldown_cmark::{
+    Alignment, BrokenLink, CodeBlockKind, Event, HeadingLevel, Options, Parser, Tag, TagEnd,
+    TextMergeWithOffset,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::ops::Range;
 
 pub struct Transformer;
 
@@ -13,22 +19,631 @@ impl TransformerTrait for Transformer {
         Transformer::parse_with_loader(document, disk_image_loader("."))
     }
 
+    /// Embeds images as `data:` urls
     fn generate(document: &Document) -> anyhow::Result<Bytes> {
-        Transformer::generate_with_saver(document, disk_image_saver("."))
+        let options = GenerateOptions { embed_images: true };
+        Transformer::generate_with_options(document, disk_image_saver("."), &options)
+    }
+
+    fn parse_reader(reader: impl Read) -> anyhow::Result<Document> {
+        Transformer::parse_reader_with_options(
+            reader,
+            disk_image_loader("."),
+            &ParseOptions::default(),
+        )
     }
 }
 
-struct ImageSaver<F>
+/// Options for markdown parsing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseOptions {
+    /// Turn newlines inside a paragraph into `Element::LineBreak` instead of joining the lines
+    /// (semantic line breaks, like pandoc's `hard_line_breaks` extension).
+    pub hard_line_breaks: bool,
+    /// Parse raw HTML blocks (tables, divs) with the HTML parser. Requires the `html` feature,
+    /// otherwise HTML blocks are skipped.
+    pub allow_html: bool,
+    /// Drop `<!-- ... -->` comments instead of keeping them as `Element::Comment`.
+    pub strip_comments: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            hard_line_breaks: false,
+            allow_html: true,
+            strip_comments: false,
+        }
+    }
+}
+
+/// Options for markdown generation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenerateOptions {
+    /// Write images as base64 `data:` urls instead of saving them with the image saver.
+    pub embed_images: bool,
+}
+
+/// Markdown has no page breaks, the HTML of one is written as is
+const PAGE_BREAK: &str = "<div style=\"page-break-after: always\"></div>";
+
+/// Comments around the list of links of a table of contents, as the `markdown-toc` tool writes
+const TOC_START: &str = "toc";
+const TOC_END: &str = "tocstop";
+
+/// Replaces the elements since the comment starting a table of contents with the table
+fn close_toc(doc_elements: &mut Vec<Element>) -> bool {
+    fn depth(element: &Element) -> u8 {
+        match element {
+            Element::List { elements, .. } => {
+                1 + elements.iter().map(item_depth).max().unwrap_or(0)
+            }
+            _ => 0,
+        }
+    }
+    // An item with a nested list is itself parsed as a list of the item and the nested list
+    fn item_depth(item: &ListItem) -> u8 {
+        match &item.element {
+            Element::List { elements, .. } if is_parent_list(item) => {
+                elements[1..].iter().map(item_depth).max().unwrap_or(0)
+            }
+            element => depth(element),
+        }
+    }
+    let Some(start) = doc_elements
+        .iter()
+        .rposition(|element| matches!(element, Element::Comment { text, .. } if text == TOC_START))
+    else {
+        return false;
+    };
+    let entries = doc_elements.split_off(start);
+    let max_level = entries.iter().map(depth).max().unwrap_or(0).max(1);
+    doc_elements.push(Element::TableOfContents { max_level });
+    true
+}
+
+/// Gives the elements the last event added at the top level, or replaced those there with, the
+/// range of that event. The range of an end event covers the whole element.
+fn record_spans(
+    doc_elements: &[Element],
+    spans: &mut Vec<Range<usize>>,
+    last_kind: &mut Option<ElementKind>,
+    range: &Range<usize>,
+) {
+    let kind = doc_elements.last().map(Element::kind);
+    // Blockquotes, footnotes and tables of contents take the place of the elements they hold
+    if doc_elements.len() < spans.len() || (doc_elements.len() == spans.len() && kind != *last_kind)
+    {
+        spans.truncate(doc_elements.len().saturating_sub(1));
+    }
+    while spans.len() < doc_elements.len() {
+        spans.push(range.clone());
+    }
+    *last_kind = kind;
+}
+
+/// Returns the text of `html` if it is a single `<!-- ... -->` comment
+fn html_comment(html: &str) -> Option<String> {
+    let text = html.trim().strip_prefix("<!--")?.strip_suffix("-->")?;
+    if text.contains("-->") {
+        return None;
+    }
+    Some(text.trim().to_string())
+}
+
+/// Moves the paragraph content since the start of the innermost open style into a styled span
+fn close_style(current_element: &mut Option<Element>, styles: &mut Vec<(TextStyle, usize)>) {
+    let Some((style, start)) = styles.pop() else {
+        return;
+    };
+    if let Some(Element::Paragraph { elements }) = current_element.as_mut() {
+        let styled = elements.split_off(start.min(elements.len()));
+        elements.push(Element::Styled {
+            style,
+            elements: styled,
+        });
+    }
+}
+
+/// Adds paragraph text in which `==` opens and closes highlighted text, the extension is not
+/// known to pulldown-cmark and the marks are left in the text
+fn push_marked_text(
+    current_element: &mut Option<Element>,
+    styles: &mut Vec<(TextStyle, usize)>,
+    text: &str,
+) {
+    for (index, part) in text.split("==").enumerate() {
+        if index > 0 {
+            if styles
+                .last()
+                .is_some_and(|(style, _)| style.highlight.is_some())
+            {
+                close_style(current_element, styles);
+            } else if let Some(Element::Paragraph { elements }) = current_element.as_ref() {
+                styles.push((TextStyle::HIGHLIGHT, elements.len()));
+            }
+        }
+        if let Some(Element::Paragraph { elements }) = current_element.as_mut() {
+            if !part.is_empty() {
+                elements.push(Element::Text {
+                    text: part.to_string(),
+                    font: FontSpec::from(14),
+                });
+            }
+        }
+    }
+}
+
+/// Strips the `: ` marker of a PHP Markdown Extra definition from the start of `line`
+fn strip_definition_marker(line: &mut Vec<Element>) -> bool {
+    let Some(Element::Text { text, .. }) = line.first_mut() else {
+        return false;
+    };
+    match text.strip_prefix(':') {
+        Some(rest) if rest.starts_with(char::is_whitespace) => {
+            *text = rest.trim_start().to_string();
+            if text.is_empty() {
+                line.remove(0);
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Adds a paragraph with PHP Markdown Extra definitions to `doc_elements`. A paragraph that
+/// starts with a definition takes its term from the paragraph before it, consecutive
+/// definition paragraphs join one list. Only the elements from `start` on, which share the
+/// paragraph's blockquote or footnote, are joined. Gives the paragraph back when it has no
+/// definitions.
+fn push_definitions(
+    doc_elements: &mut Vec<Element>,
+    start: usize,
+    elements: Vec<Element>,
+    line_starts: &[usize],
+) -> Result<(), Vec<Element>> {
+    let mut lines = Vec::new();
+    let mut rest = elements.clone();
+    for start in line_starts.iter().rev() {
+        if *start < rest.len() {
+            lines.push(rest.split_off(*start));
+        }
+    }
+    lines.push(rest);
+    lines.reverse();
+    let markers: Vec<bool> = lines.iter_mut().map(strip_definition_marker).collect();
+    let previous = doc_elements
+        .get(start..)
+        .and_then(|siblings| siblings.last());
+    let follows_term = matches!(
+        previous,
+        Some(Element::Paragraph { .. } | Element::DefinitionList { .. })
+    );
+    if !markers.contains(&true) || (markers[0] && !follows_term) {
+        return Err(elements);
+    }
+
+    let mut items = match previous {
+        Some(Element::Paragraph { .. }) if markers[0] => {
+            let term = match doc_elements.pop() {
+                Some(Element::Paragraph { elements }) => elements,
+                _ => unreachable!(),
+            };
+            // A term set apart by blank lines still continues the list before it
+            let mut items = match doc_elements
+                .get(start..)
+                .and_then(|siblings| siblings.last())
+            {
+                Some(Element::DefinitionList { .. }) => match doc_elements.pop() {
+                    Some(Element::DefinitionList { items }) => items,
+                    _ => unreachable!(),
+                },
+                _ => vec![],
+            };
+            items.push(DefinitionItem {
+                term,
+                definitions: vec![],
+            });
+            items
+        }
+        Some(Element::DefinitionList { .. }) => match doc_elements.pop() {
+            Some(Element::DefinitionList { items }) => items,
+            _ => unreachable!(),
+        },
+        _ => vec![],
+    };
+    // Lines leading up to a definition are terms, the lines after the last one continue it
+    let mut is_term = vec![false; lines.len()];
+    for index in (0..lines.len()).rev() {
+        is_term[index] = !markers[index]
+            && (index + 1 < lines.len() && (markers[index + 1] || is_term[index + 1]));
+    }
+    for (index, mut line) in lines.into_iter().enumerate() {
+        // Hard line breaks end the line before a term or definition
+        if is_term[index] || markers.get(index + 1) == Some(&true) {
+            if let Some(Element::LineBreak) = line.last() {
+                line.pop();
+            }
+        }
+        if is_term[index] {
+            items.push(DefinitionItem {
+                term: line,
+                definitions: vec![],
+            });
+        } else if let Some(item) = items.last_mut() {
+            match item.definitions.last_mut() {
+                Some(Element::Paragraph { elements }) if !markers[index] => elements.extend(line),
+                _ => item.definitions.push(Element::Paragraph { elements: line }),
+            }
+        }
+    }
+    doc_elements.push(Element::DefinitionList { items });
+    Ok(())
+}
+
+#[cfg(feature = "html")]
+fn parse_html_block<F>(html: &str, image_loader: &F) -> anyhow::Result<Vec<Element>>
 where
-    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    F: Fn(&str) -> anyhow::Result<Bytes>,
 {
-    pub function: F,
+    let document = crate::html::Transformer::parse_with_loader(
+        &Bytes::from(html.to_string()),
+        image_loader,
+    )?;
+    Ok(document.get_all_elements().into_iter().cloned().collect())
 }
-impl TransformerWithImageLoaderSaverTrait for Transformer {
-    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+
+#[cfg(not(feature = "html"))]
+fn parse_html_block<F>(_html: &str, _image_loader: &F) -> anyhow::Result<Vec<Element>>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    log::warn!("Skipping HTML block in markdown: html feature is not enabled");
+    Ok(vec![])
+}
+
+/// Splits the YAML front matter, the block between two `---` lines at the very start, off
+/// the document
+fn split_front_matter(document: &str) -> (Option<&str>, &str) {
+    let Some(rest) = document
+        .strip_prefix("---\n")
+        .or_else(|| document.strip_prefix("---\r\n"))
+    else {
+        return (None, document);
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if matches!(line.trim_end(), "---" | "...") {
+            return (Some(&rest[..offset]), &rest[offset + line.len()..]);
+        }
+        offset += line.len();
+    }
+    (None, document)
+}
+
+/// Splits markdown read a line at a time into sections that parse on their own, each starting
+/// at a `#` header after a blank line. Lines in front matter, fenced code and raw HTML blocks
+/// that may hold blank lines never start a section.
+#[derive(Default)]
+struct Sections {
+    section: String,
+    /// Lines in the sections before the current one
+    lines_before: usize,
+    bytes_before: usize,
+    lines: usize,
+    previous_blank: bool,
+    front_matter: bool,
+    /// Marker of the open code fence
+    fence: Option<String>,
+    /// End of the open raw HTML block, lowercase
+    raw_html_end: Option<&'static str>,
+}
+
+impl Sections {
+    /// Adds a line, returning the section it ends if it starts a new one
+    fn push_line(&mut self, line: &str) -> Option<String> {
+        let trimmed = line.trim_start();
+        // Only a header can start a section, and no header opens a block
+        let inside = self.front_matter || self.fence.is_some() || self.raw_html_end.is_some();
+        if self.lines_before == 0 && self.lines == 0 && line.trim_end() == "---" {
+            self.front_matter = true;
+        } else if self.front_matter {
+            self.front_matter = !matches!(line.trim_end(), "---" | "...");
+        } else if let Some(fence) = &self.fence {
+            let rest = trimmed.strip_prefix(fence.as_str()).map(|rest| {
+                rest.trim_start_matches(fence.chars().next().unwrap_or('`'))
+            });
+            if rest.is_some_and(|rest| rest.trim().is_empty()) {
+                self.fence = None;
+            }
+        } else if let Some(end) = self.raw_html_end {
+            if line.to_ascii_lowercase().contains(end) {
+                self.raw_html_end = None;
+            }
+        } else if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            let marker = trimmed.chars().next().unwrap_or('`');
+            self.fence = Some(trimmed.chars().take_while(|&c| c == marker).collect());
+        } else {
+            let lowercase = trimmed.to_ascii_lowercase();
+            self.raw_html_end = [
+                ("<!--", "-->"),
+                ("<pre", "</pre>"),
+                ("<script", "</script>"),
+                ("<style", "</style>"),
+                ("<textarea", "</textarea>"),
+            ]
+            .into_iter()
+            .find(|(start, end)| lowercase.starts_with(start) && !lowercase.contains(end))
+            .map(|(_, end)| end);
+        }
+
+        let starts_section = !inside
+            && self.previous_blank
+            && is_atx_header(line)
+            && !self.section.trim().is_empty();
+        let ended = starts_section.then(|| {
+            self.lines_before += self.lines;
+            self.bytes_before += self.section.len();
+            self.lines = 0;
+            std::mem::take(&mut self.section)
+        });
+        self.previous_blank = line.trim().is_empty();
+        self.section.push_str(line);
+        self.lines += 1;
+        ended
+    }
+}
+
+/// Destination and title of link reference definitions, by label as [`link_label`] writes it
+type LinkDefinitions = HashMap<String, (String, String)>;
+
+/// A section parsed, or held until the links it refers to are defined
+enum Section {
+    Parsed(Box<Document>),
+    Unresolved(Bytes),
+}
+
+/// Labels match without regard to case and runs of whitespace
+fn link_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// The link reference definitions of markdown without front matter
+fn link_definitions(markdown: &str) -> impl Iterator<Item = (String, (String, String))> + '_ {
+    let parser = Parser::new_ext(markdown, parser_options());
+    let definitions: Vec<_> = parser
+        .reference_definitions()
+        .iter()
+        .map(|(label, definition)| {
+            let title = definition.title.as_deref().unwrap_or_default();
+            (link_label(label), (definition.dest.to_string(), title.to_string()))
+        })
+        .collect();
+    definitions.into_iter()
+}
+
+fn parser_options() -> Options {
+    let mut parser_options = Options::empty();
+    parser_options.insert(Options::ENABLE_TABLES);
+    parser_options.insert(Options::ENABLE_STRIKETHROUGH);
+    parser_options.insert(Options::ENABLE_SMART_PUNCTUATION);
+    parser_options.insert(Options::ENABLE_MATH);
+    parser_options.insert(Options::ENABLE_GFM);
+    parser_options.insert(Options::ENABLE_FOOTNOTES);
+    parser_options.insert(Options::ENABLE_TASKLISTS);
+    parser_options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+    parser_options
+}
+
+/// A `#` header starting at the beginning of the line
+fn is_atx_header(line: &str) -> bool {
+    let rest = line.trim_start_matches('#');
+    let level = line.len() - rest.len();
+    (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with([' ', '\t', '\r', '\n']))
+}
+
+/// A plain, single or double quoted YAML scalar
+fn yaml_scalar(value: &str) -> String {
+    let value = value.trim();
+    if value.starts_with('"') {
+        if let Ok(value) = serde_json::from_str::<String>(value) {
+            return value;
+        }
+    }
+    match value.strip_prefix('\'').and_then(|value| value.strip_suffix('\'')) {
+        Some(quoted) => quoted.replace("''", "'"),
+        None => value.to_string(),
+    }
+}
+
+/// The metadata of YAML front matter. Keys with a scalar, a flow list or a block list of
+/// scalars are read, nested mappings are skipped
+fn parse_front_matter(yaml: &str) -> Metadata {
+    let mut fields: Vec<(String, Vec<String>)> = Vec::new();
+    for line in yaml.lines() {
+        let trimmed = line.trim_start();
+        if let Some(item) = trimmed.strip_prefix("- ") {
+            if let Some((_, values)) = fields.last_mut() {
+                values.push(yaml_scalar(item));
+            }
+            continue;
+        }
+        if line.starts_with([' ', '\t', '#']) {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(": ").or_else(|| line.split_once(':')) else {
+            continue;
+        };
+        let value = value.trim();
+        let values = match value.strip_prefix('[').and_then(|list| list.strip_suffix(']')) {
+            Some(list) => list.split(',').map(yaml_scalar).collect(),
+            None if value.is_empty() => Vec::new(),
+            None => vec![yaml_scalar(value)],
+        };
+        fields.push((yaml_scalar(key), values));
+    }
+
+    let mut metadata = Metadata::default();
+    for (key, mut values) in fields {
+        let first = values.first().cloned();
+        match key.as_str() {
+            "title" => metadata.title = first,
+            "author" | "authors" => metadata.authors = values,
+            "subject" | "description" => metadata.subject = first,
+            "keywords" | "tags" => {
+                if let [keywords] = values.as_slice() {
+                    values = Metadata::split_keywords(keywords);
+                }
+                metadata.keywords = values;
+            }
+            "lang" | "language" => metadata.language = first,
+            "date" | "created" => metadata.created = first,
+            "modified" | "updated" => metadata.modified = first,
+            _ => {
+                metadata.custom.insert(key, values.join(", "));
+            }
+        }
+    }
+    metadata
+}
+
+/// YAML front matter with the metadata, documents without metadata have none
+fn front_matter(metadata: &Metadata) -> String {
+    if metadata.is_empty() {
+        return String::new();
+    }
+    // JSON strings are double quoted YAML scalars
+    let quote = |value: &str| serde_json::to_string(value).unwrap_or_default();
+    let mut yaml = String::from("---\n");
+    let mut scalar = |key: &str, value: &Option<String>| {
+        if let Some(value) = value {
+            yaml.push_str(&format!("{}: {}\n", key, quote(value)));
+        }
+    };
+    scalar("title", &metadata.title);
+    scalar("subject", &metadata.subject);
+    scalar("lang", &metadata.language);
+    scalar("date", &metadata.created);
+    scalar("modified", &metadata.modified);
+    for (key, values) in [("author", &metadata.authors), ("keywords", &metadata.keywords)] {
+        if !values.is_empty() {
+            yaml.push_str(&format!("{}:\n", key));
+            for value in values {
+                yaml.push_str(&format!("  - {}\n", quote(value)));
+            }
+        }
+    }
+    for (key, value) in &metadata.custom {
+        let plain = key
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        let key = if plain { key.clone() } else { quote(key) };
+        yaml.push_str(&format!("{}: {}\n", key, quote(value)));
+    }
+    yaml.push_str("---\n\n");
+    yaml
+}
+
+impl Transformer {
+    /// Parses markdown as it is read, a section at a time: the input is split before each `#`
+    /// header that follows a blank line outside code blocks. A section that refers to links
+    /// defined further on is held and parsed again once the whole input is read.
+    pub fn parse_reader_with_options<F>(
+        reader: impl Read,
+        image_loader: F,
+        options: &ParseOptions,
+    ) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let mut reader = BufReader::new(reader);
+        let mut sections = Sections::default();
+        let mut definitions = LinkDefinitions::new();
+        // With the lines and bytes in the sections before each
+        let mut parsed_sections: Vec<(Section, usize, usize)> = Vec::new();
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let end = reader.read_line(&mut line)? == 0;
+            let (lines_before, bytes_before) = (sections.lines_before, sections.bytes_before);
+            let section = if end {
+                Some(std::mem::take(&mut sections.section))
+            } else {
+                sections.push_line(&line)
+            };
+            if let Some(section) = section {
+                // The first definition of a label is the one that counts
+                for (label, link) in link_definitions(split_front_matter(&section).1) {
+                    definitions.entry(label).or_insert(link);
+                }
+                let section = Bytes::from(section);
+                let (document, resolved) =
+                    Transformer::parse_section(&section, &image_loader, options, &definitions)?;
+                let section = match resolved {
+                    true => Section::Parsed(Box::new(document)),
+                    false => Section::Unresolved(section),
+                };
+                parsed_sections.push((section, lines_before, bytes_before));
+            }
+            if end {
+                break;
+            }
+        }
+
+        let mut parsed = Document::new(vec![]);
+        for (index, parsed_section) in parsed_sections.into_iter().enumerate() {
+            let (section, lines_before, bytes_before) = parsed_section;
+            let mut document = match section {
+                Section::Parsed(document) => *document,
+                Section::Unresolved(section) => {
+                    Transformer::parse_section(&section, &image_loader, options, &definitions)?.0
+                }
+            };
+            if index == 0 {
+                parsed.metadata = std::mem::take(&mut document.metadata);
+            }
+            let elements = parsed.bands[0].elements_mut();
+            for (mut path, mut span) in document.source_spans {
+                path[1] += elements.len();
+                span.byte_range = span
+                    .byte_range
+                    .map(|range| bytes_before + range.start..bytes_before + range.end);
+                span.line = span.line.map(|line| lines_before + line);
+                parsed.source_spans.insert(path, span);
+            }
+            elements.append(document.bands[0].elements_mut());
+        }
+        Ok(parsed)
+    }
+
+    /// Parse markdown using the given options.
+    pub fn parse_with_options<F>(
+        document: &Bytes,
+        image_loader: F,
+        options: &ParseOptions,
+    ) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let definitions = LinkDefinitions::new();
+        Transformer::parse_section(document, image_loader, options, &definitions)
+            .map(|(document, _)| document)
+    }
+
+    /// Parses markdown that may refer to links defined elsewhere, returning whether all the
+    /// references it has are to links defined in it or in `definitions`
+    fn parse_section<F>(
+        document: &Bytes,
+        image_loader: F,
+        options: &ParseOptions,
+        definitions: &LinkDefinitions,
+    ) -> anyhow::Result<(Document, bool)>
     where
         F: Fn(&str) -> anyhow::Result<Bytes>,
-        Self: Sized,
     {
         fn create_element_list(children: Option<Vec<ListItem>>, numbered: bool) -> Element {
             Element::List {
@@ -61,7 +676,7 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                         }
                         match &new_el {
                             Element::Hyperlink { .. } | Element::Header { .. } => {
-                                if let Some(ListItem { element }) = list_elements.last() {
+                                if let Some(ListItem { element, .. }) = list_elements.last() {
                                     if let Text { .. } = element {
                                         list_elements.pop();
                                     }
@@ -74,6 +689,7 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                         if matches!(new_el, Element::List { .. }) {
                             let list_item_children = ListItem {
                                 element: create_element_list(None, *numbered),
+                                checked: None,
                             };
 
                             if let Element::List {
@@ -89,7 +705,10 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                             }
                         }
 
-                        let li = ListItem { element: new_el };
+                        let li = ListItem {
+                            element: new_el,
+                            checked: None,
+                        };
                         list_elements.push(li);
                     }
                     _ => {}
@@ -100,27 +719,47 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
             }
         }
 
-        let document_str = std::str::from_utf8(document)?;
+        let input = std::str::from_utf8(document)?;
+        let (front_matter, document_str) = split_front_matter(input);
         let mut doc_elements: Vec<Element> = Vec::new();
 
-        let mut options = Options::empty();
-        options.insert(Options::ENABLE_TABLES);
-        options.insert(Options::ENABLE_STRIKETHROUGH);
-        options.insert(Options::ENABLE_SMART_PUNCTUATION);
-        options.insert(Options::ENABLE_MATH);
-        options.insert(Options::ENABLE_GFM);
-
-        let parser = Parser::new_ext(document_str, options);
-        let md_iterator = TextMergeStream::new(parser);
+        let resolved = Cell::new(true);
+        let resolve = |link: BrokenLink| {
+            let definition = definitions.get(&link_label(&link.reference));
+            resolved.set(resolved.get() && definition.is_some());
+            definition.map(|(url, title)| (url.clone().into(), title.clone().into()))
+        };
+        let md_iterator = TextMergeWithOffset::new_ext_with_broken_link_callback(
+            document_str,
+            parser_options(),
+            Some(resolve),
+        );
 
         let mut current_element: Option<Element> = None;
         let mut list_depth = 0;
         let mut table_element: Option<(bool, Element)> = None;
-        for event in md_iterator {
+        let mut html_block: Option<String> = None;
+        let mut code_block: Option<(Option<String>, String)> = None;
+        // Where the elements of each open blockquote start in `doc_elements`
+        let mut blockquotes: Vec<usize> = Vec::new();
+        // Label and start in `doc_elements` of the open footnote definition
+        let mut footnote: Option<(String, usize)> = None;
+        // Style and start in the current paragraph of each open emphasis
+        let mut styles: Vec<(TextStyle, usize)> = Vec::new();
+        // Where each line after the first starts in the current paragraph
+        let mut line_starts: Vec<usize> = Vec::new();
+        // Where each element of `doc_elements` is in `document_str`
+        let mut spans: Vec<Range<usize>> = Vec::new();
+        let mut last_kind: Option<ElementKind> = None;
+        let mut last_range = 0..0;
+        for (event, range) in md_iterator {
+            record_spans(&doc_elements, &mut spans, &mut last_kind, &last_range);
+            last_range = range;
             match event {
                 Event::Start(tag) => {
                     match tag {
                         Tag::Paragraph => {
+                            line_starts.clear();
                             if !matches!(current_element, Some(Element::List { .. })) {
                                 process_element_creation(
                                     &mut current_element,
@@ -129,7 +768,7 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                                 );
                             }
                         }
-                        Tag::Heading { level, .. } => {
+                        Tag::Heading { level, id, .. } => {
                             let level = match level {
                                 HeadingLevel::H1 => 1,
                                 HeadingLevel::H2 => 2,
@@ -143,6 +782,7 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                                 Element::Header {
                                     level,
                                     text: "".to_string(),
+                                    anchor: id.map(|id| id.to_string()),
                                 },
                                 &mut list_depth,
                             );
@@ -166,7 +806,7 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                         Tag::Item => {
                             let list_li = Text {
                                 text: "".to_string(),
-                                size: 14,
+                                font: FontSpec::from(14),
                             };
 
                             process_element_creation(
@@ -175,10 +815,25 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                                 &mut list_depth,
                             );
                         }
-                        Tag::Table(_) => {
+                        Tag::Table(alignments) => {
+                            let mut alignments: Vec<ColumnAlignment> = alignments
+                                .iter()
+                                .map(|alignment| match alignment {
+                                    Alignment::Left => ColumnAlignment::Left,
+                                    Alignment::Center => ColumnAlignment::Center,
+                                    Alignment::Right => ColumnAlignment::Right,
+                                    Alignment::None => ColumnAlignment::None,
+                                })
+                                .collect();
+                            // Columns past the end have no alignment, unaligned tables have none
+                            while alignments.last() == Some(&ColumnAlignment::None) {
+                                alignments.pop();
+                            }
                             let table_el = Table {
                                 headers: vec![],
                                 rows: vec![],
+                                alignments,
+                                caption: vec![],
                             };
 
                             table_element = Some((false, table_el));
@@ -221,18 +876,63 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                             );
                         }
 
+                        Tag::HtmlBlock => {
+                            html_block = Some(String::new());
+                        }
+
+                        Tag::CodeBlock(kind) => {
+                            let language = match kind {
+                                CodeBlockKind::Fenced(info) => info
+                                    .split_whitespace()
+                                    .next()
+                                    .map(|language| language.to_string()),
+                                CodeBlockKind::Indented => None,
+                            };
+                            code_block = Some((language, String::new()));
+                        }
+
+                        Tag::BlockQuote(_) => {
+                            blockquotes.push(doc_elements.len());
+                        }
+
+                        Tag::FootnoteDefinition(label) => {
+                            footnote = Some((label.to_string(), doc_elements.len()));
+                        }
+
+                        Tag::Strong | Tag::Emphasis | Tag::Strikethrough => {
+                            if let Some(Element::Paragraph { elements }) = current_element.as_ref()
+                            {
+                                let style = match tag {
+                                    Tag::Strong => TextStyle::BOLD,
+                                    Tag::Emphasis => TextStyle::ITALIC,
+                                    _ => TextStyle::STRIKETHROUGH,
+                                };
+                                styles.push((style, elements.len()));
+                            }
+                        }
+
                         _rest => {
                             // warn!("The tag parsing is not implemented {:#?}", rest);
                         }
                     }
                 }
                 Event::Text(text) => {
+                    if let Some((_, code)) = code_block.as_mut() {
+                        code.push_str(&text);
+                        continue;
+                    }
+                    if text.contains("==")
+                        && matches!(current_element, Some(Element::Paragraph { .. }))
+                    {
+                        push_marked_text(&mut current_element, &mut styles, &text);
+                        continue;
+                    }
                     if let Some(curr_el) = current_element.as_mut() {
                         match curr_el {
                             Element::Paragraph { ref mut elements } => {
                                 elements.push(Element::Text {
                                     text: text.to_string(),
-                                    size: 14,
+                                    font: FontSpec::from(14),
                                 })
                             }
                             Element::Header { text: el_text, .. } => {
@@ -281,12 +981,12 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                     }
                     match table_element {
                         Some(ref mut t_el) => {
-                            if let (is_header, Element::Table { headers, rows }) = t_el {
+                            if let (is_header, Element::Table { headers, rows, .. }) = t_el {
                                 if *is_header {
                                     headers.push(TableHeader {
                                         element: Text {
                                             text: text.to_string(),
-                                            size: 14,
+                                            font: FontSpec::from(14),
                                         },
                                         width: 30.,
                                     })
@@ -297,30 +997,24 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                                         Some(tr) => {
                                             if tr.cells.len() == headers.len() {
                                                 rows.push(TableRow {
-                                                    cells: vec![TableCell {
-                                                        element: Text {
-                                                            text: text.to_string(),
-                                                            size: 14,
-                                                        },
-                                                    }],
-                                                });
-                                            } else {
-                                                tr.cells.push(TableCell {
-                                                    element: Text {
+                                                    cells: vec![TableCell::new(Text {
                                                         text: text.to_string(),
-                                                        size: 14,
-                                                    },
+                                                        font: FontSpec::from(14),
+                                                    })],
                                                 });
+                                            } else {
+                                                tr.cells.push(TableCell::new(Text {
+                                                    text: text.to_string(),
+                                                    font: FontSpec::from(14),
+                                                }));
                                             }
                                         }
                                         None => {
                                             rows.push(TableRow {
-                                                cells: vec![TableCell {
-                                                    element: Text {
-                                                        text: text.to_string(),
-                                                        size: 14,
-                                                    },
-                                                }],
+                                                cells: vec![TableCell::new(Text {
+                                                    text: text.to_string(),
+                                                    font: FontSpec::from(14),
+                                                })],
                                             });
                                         }
                                     }
@@ -333,10 +1027,35 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                 Event::End(tag) => match tag {
                     TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::Link | TagEnd::Image => {
                         if !matches!(current_element, Some(Element::List { .. })) {
+                            styles.clear();
                             let curr_el = current_element.take();
                             if let Some(curr_el) = curr_el {
                                 match curr_el {
                                     List { .. } => current_element = Some(curr_el),
+                                    // A formula on its own is a block rather than a paragraph
+                                    Element::Paragraph { elements }
+                                        if matches!(
+                                            elements.as_slice(),
+                                            [Element::Math { display: true, .. }]
+                                        ) =>
+                                    {
+                                        doc_elements.extend(elements)
+                                    }
+                                    Element::Paragraph { elements } => {
+                                        let start = blockquotes
+                                            .last()
+                                            .copied()
+                                            .max(footnote.as_ref().map(|(_, start)| *start))
+                                            .unwrap_or(0);
+                                        if let Err(elements) = push_definitions(
+                                            &mut doc_elements,
+                                            start,
+                                            elements,
+                                            &line_starts,
+                                        ) {
+                                            doc_elements.push(Element::Paragraph { elements });
+                                        }
+                                    }
                                     _ => {
                                         doc_elements.push(curr_el);
                                     }
@@ -364,17 +1083,227 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
                             doc_elements.push(t_el);
                         }
                     }
+                    TagEnd::CodeBlock => {
+                        if let Some((language, mut code)) = code_block.take() {
+                            if code.ends_with('\n') {
+                                code.pop();
+                            }
+                            let element = Element::CodeBlock { language, code };
+                            if matches!(current_element, Some(Element::List { .. })) {
+                                process_element_creation(
+                                    &mut current_element,
+                                    element,
+                                    &mut list_depth,
+                                );
+                            } else {
+                                doc_elements.push(element);
+                            }
+                        }
+                    }
+                    TagEnd::BlockQuote => {
+                        if let Some(start) = blockquotes.pop() {
+                            let elements = doc_elements.split_off(start);
+                            doc_elements.push(Element::Blockquote {
+                                elements,
+                                attribution: vec![],
+                            });
+                        }
+                    }
+                    TagEnd::FootnoteDefinition => {
+                        if let Some((id, start)) = footnote.take() {
+                            let content = doc_elements.split_off(start);
+                            doc_elements.push(Element::Footnote { id, content });
+                        }
+                    }
+                    TagEnd::Strong | TagEnd::Emphasis | TagEnd::Strikethrough => {
+                        close_style(&mut current_element, &mut styles);
+                    }
+                    TagEnd::HtmlBlock => {
+                        if let Some(html) = html_block.take() {
+                            if let Some(text) = html_comment(&html) {
+                                if text == TOC_END && close_toc(&mut doc_elements) {
+                                    continue;
+                                }
+                                doc_elements.push(Element::Comment {
+                                    text,
+                                    author: None,
+                                    date: None,
+                                    range: None,
+                                });
+                            } else if html.trim() == PAGE_BREAK {
+                                doc_elements.push(Element::PageBreak);
+                            } else if options.allow_html {
+                                doc_elements.extend(parse_html_block(&html, &image_loader)?);
+                            }
+                        }
+                    }
                     _ => {}
                 },
 
+                Event::Html(html) => {
+                    if let Some(block) = html_block.as_mut() {
+                        block.push_str(&html);
+                    }
+                }
+
+                Event::TaskListMarker(checked) => {
+                    if let Some(Element::List { elements, .. }) = current_element.as_mut() {
+                        let mut items = elements;
+                        for _ in 1..list_depth {
+                            items = items
+                                .last_mut()
+                                .and_then(|item| match &mut item.element {
+                                    Element::List { elements, .. } => Some(elements),
+                                    _ => None,
+                                })
+                                .ok_or_else(|| {
+                                    ParserError::Malformed(
+                                        "task list marker outside of a nested list".to_string(),
+                                    )
+                                })?;
+                        }
+                        if let Some(item) = items.last_mut() {
+                            item.checked = Some(checked);
+                        }
+                    }
+                }
+
+                Event::InlineMath(tex) => {
+                    if let Some(Element::Paragraph { elements }) = current_element.as_mut() {
+                        elements.push(Element::Math {
+                            tex: tex.to_string(),
+                            display: false,
+                        });
+                    }
+                }
+
+                Event::DisplayMath(tex) => {
+                    if let Some(Element::Paragraph { elements }) = current_element.as_mut() {
+                        elements.push(Element::Math {
+                            tex: tex.trim().to_string(),
+                            display: true,
+                        });
+                    }
+                }
+
+                Event::FootnoteReference(label) => {
+                    if let Some(Element::Paragraph { elements }) = current_element.as_mut() {
+                        elements.push(Element::FootnoteReference {
+                            id: label.to_string(),
+                        });
+                    }
+                }
+
+                Event::InlineHtml(html) => {
+                    if let Some(Element::Paragraph { elements }) = current_element.as_mut() {
+                        match html.trim() {
+                            "<u>" => styles.push((TextStyle::UNDERLINE, elements.len())),
+                            "<sup>" => styles.push((TextStyle::SUPERSCRIPT, elements.len())),
+                            "<sub>" => styles.push((TextStyle::SUBSCRIPT, elements.len())),
+                            "</u>" | "</sup>" | "</sub>" => {
+                                close_style(&mut current_element, &mut styles)
+                            }
+                            html => {
+                                if let Some(text) = html_comment(html) {
+                                    elements.push(Element::Comment {
+                                        text,
+                                        author: None,
+                                        date: None,
+                                        range: None,
+                                    });
+                                }
+                            }
+                        }
+                    }
+                }
+
+                Event::Rule => doc_elements.push(Element::HorizontalRule),
+
+                Event::SoftBreak | Event::HardBreak if options.hard_line_breaks => {
+                    if let Some(Element::Paragraph { elements }) = current_element.as_mut() {
+                        elements.push(Element::LineBreak);
+                        line_starts.push(elements.len());
+                    }
+                }
+
+                Event::SoftBreak => {
+                    if let Some(Element::Paragraph { elements }) = current_element.as_mut() {
+                        line_starts.push(elements.len());
+                    }
+                }
+
+                // Trailing spaces or a backslash break the line whatever the options are
+                Event::HardBreak => {
+                    if let Some(Element::Paragraph { elements }) = current_element.as_mut() {
+                        elements.push(Element::LineBreak);
+                        line_starts.push(elements.len());
+                    }
+                }
+
                 _ => {}
             }
         }
 
-        Ok(Document::new(doc_elements))
+        record_spans(&doc_elements, &mut spans, &mut last_kind, &last_range);
+        if options.strip_comments {
+            (doc_elements, spans) = doc_elements
+                .into_iter()
+                .zip(spans)
+                .filter(|(element, _)| !matches!(element, Element::Comment { .. }))
+                .unzip();
+        }
+
+        let mut document = Document::new(doc_elements);
+        document.metadata = front_matter.map(parse_front_matter).unwrap_or_default();
+        // The front matter is not part of `document_str`
+        let offset = input.len() - document_str.len();
+        document.source_spans = spans
+            .into_iter()
+            .enumerate()
+            .map(|(index, range)| {
+                let range = offset + range.start..offset + range.end;
+                (vec![0, index], SourceSpan::in_text(input, range))
+            })
+            .collect();
+        if options.strip_comments {
+            document.strip_comments();
+        }
+        Ok((document, resolved.get()))
+    }
+}
+
+struct ImageSaver<F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    pub function: F,
+    /// Writes images as `data:` urls instead of saving them
+    pub embed: bool,
+}
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+        Self: Sized,
+    {
+        Transformer::parse_with_options(document, image_loader, &ParseOptions::default())
     }
 
+    /// Saves images with `image_saver` as `image1.png`, `image2.jpg` and so on
     fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        Transformer::generate_with_options(document, image_saver, &GenerateOptions::default())
+    }
+}
+
+impl Transformer {
+    pub fn generate_with_options<F>(
+        document: &Document,
+        image_saver: F,
+        options: &GenerateOptions,
+    ) -> anyhow::Result<Bytes>
     where
         F: Fn(&Bytes, &str) -> anyhow::Result<()>,
     {
@@ -382,6 +1311,8 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
         use comrak::{format_commonmark, Arena, Options};
         use std::cell::RefCell;
 
+        // Markdown has no sections, the headings alone give the structure
+        let document = &document.flatten_sections();
         let arena = Arena::new();
 
         let root = arena.alloc(Node::new(RefCell::new(Ast::new(
@@ -393,16 +1324,46 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
 
         let image_saver = ImageSaver {
             function: &image_saver,
+            embed: options.embed_images,
         };
 
         let all_elements: Vec<&Element> = document.get_all_elements();
 
         for element in all_elements {
+            // A table of contents is a list of links to the headers between two comments
+            if let Element::TableOfContents { max_level } = element {
+                let marker = |text: &str| Element::Comment {
+                    text: text.to_string(),
+                    author: None,
+                    date: None,
+                    range: None,
+                };
+                let list = TocEntry::list(&document.toc_entries(*max_level));
+                for element in [marker(TOC_START), list, marker(TOC_END)] {
+                    root.append(element_to_ast_node(
+                        &arena,
+                        &element,
+                        &image_num,
+                        &image_saver,
+                    )?);
+                }
+                continue;
+            }
             let node = element_to_ast_node(&arena, element, &image_num, &image_saver)?;
-            root.append(node);
+            // An image is inline, one on its own is written as a paragraph holding it
+            if matches!(node.data.borrow().value, NodeValue::Image(_)) {
+                let paragraph = arena.alloc(Node::new(RefCell::new(Ast::new(
+                    NodeValue::Paragraph,
+                    LineColumn { line: 0, column: 0 },
+                ))));
+                paragraph.append(node);
+                root.append(paragraph);
+            } else {
+                root.append(node);
+            }
         }
 
-        let mut md = vec![];
+        let mut md = front_matter(&document.metadata).into_bytes();
 
         format_commonmark(root, &Options::default(), &mut md)?;
 
@@ -411,7 +1372,9 @@ impl TransformerWithImageLoaderSaverTrait for Transformer {
 }
 
 use comrak::nodes::{
-    Ast, AstNode, LineColumn, NodeHeading, NodeLink, NodeList, NodeTable, NodeValue, TableAlignment,
+    Ast, AstNode, LineColumn, NodeCodeBlock, NodeDescriptionItem, NodeFootnoteDefinition,
+    NodeFootnoteReference, NodeHeading, NodeHtmlBlock, NodeLink, NodeList, NodeMath, NodeTable,
+    NodeValue, TableAlignment,
 };
 
 fn is_parent_list(list_item: &ListItem) -> bool {
@@ -430,9 +1393,15 @@ fn is_parent_list(list_item: &ListItem) -> bool {
     false
 }
 
-fn create_item_node<'a>(arena: &'a Arena<AstNode<'a>>, numbered: bool) -> &'a AstNode<'a> {
-    let item_node = arena.alloc(Node::new(RefCell::new(Ast::new(
-        NodeValue::Item(NodeList {
+fn create_item_node<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    numbered: bool,
+    checked: Option<bool>,
+) -> &'a AstNode<'a> {
+    let value = match checked {
+        // The list markers come from the parent list
+        Some(checked) => NodeValue::TaskItem(checked.then_some('x')),
+        None => NodeValue::Item(NodeList {
             list_type: if numbered {
                 comrak::nodes::ListType::Ordered
             } else {
@@ -442,6 +1411,9 @@ fn create_item_node<'a>(arena: &'a Arena<AstNode<'a>>, numbered: bool) -> &'a As
             tight: true,
             ..Default::default()
         }),
+    };
+    let item_node = arena.alloc(Node::new(RefCell::new(Ast::new(
+        value,
         LineColumn { line: 0, column: 0 },
     ))));
 
@@ -474,7 +1446,7 @@ fn text_to_paragraph(element: Element) -> Element {
         Element::Paragraph {
             elements: vec![Element::Text {
                 text: text.to_string(),
-                size: 14,
+                font: FontSpec::from(14),
             }],
         }
     } else {
@@ -482,6 +1454,83 @@ fn text_to_paragraph(element: Element) -> Element {
     }
 }
 
+/// GFM cells hold a line of inline content, line breaks are written as `<br>`
+fn table_cell_node<'a, F>(
+    arena: &'a Arena<AstNode<'a>>,
+    elements: &[Element],
+    image_num: &RefCell<i32>,
+    image_saver: &ImageSaver<F>,
+) -> anyhow::Result<&'a AstNode<'a>>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    let node = |value: NodeValue| {
+        arena.alloc(Node::new(RefCell::new(Ast::new(
+            value,
+            LineColumn { line: 0, column: 0 },
+        ))))
+    };
+    let cell_node = node(NodeValue::TableCell);
+    let mut inlines = Vec::new();
+    cell_inlines(elements, &mut inlines);
+    for inline in &inlines {
+        cell_node.append(match inline {
+            Element::LineBreak => node(NodeValue::HtmlInline("<br>".to_string())),
+            inline => element_to_ast_node(arena, inline, image_num, image_saver)?,
+        });
+    }
+    Ok(cell_node)
+}
+
+/// The content of a table cell as inline elements. Blocks are separated by line breaks, list
+/// items are written inline after their marker and other blocks by their text.
+fn cell_inlines(elements: &[Element], inlines: &mut Vec<Element>) {
+    let text = |text: String| Element::Text {
+        text,
+        font: FontSpec::from(8),
+    };
+    for element in elements {
+        if element.is_inline() {
+            inlines.push(element.clone());
+            continue;
+        }
+        if !matches!(inlines.last(), None | Some(Element::LineBreak)) {
+            inlines.push(Element::LineBreak);
+        }
+        match element {
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => cell_inlines(elements, inlines),
+            Element::List { elements, numbered } => {
+                for (index, item) in elements.iter().enumerate() {
+                    if !matches!(inlines.last(), None | Some(Element::LineBreak)) {
+                        inlines.push(Element::LineBreak);
+                    }
+                    let marker = match numbered {
+                        true => format!("{}. ", index + 1),
+                        false => "- ".to_string(),
+                    };
+                    let checkbox = match item.checked {
+                        Some(true) => "[x] ",
+                        Some(false) => "[ ] ",
+                        None => "",
+                    };
+                    inlines.push(text(marker + checkbox));
+                    cell_inlines(std::slice::from_ref(&item.element), inlines);
+                }
+            }
+            element => {
+                let plain = element.plain_text();
+                if !plain.is_empty() {
+                    inlines.push(text(plain.replace('\n', " ")));
+                }
+            }
+        }
+    }
+}
+
 fn element_to_ast_node<'a, F>(
     arena: &'a Arena<AstNode<'a>>,
     element: &Element,
@@ -501,7 +1550,11 @@ where
             Ok(node)
         }
 
-        Element::Header { level, text } => {
+        Element::Header {
+            level,
+            text,
+            anchor,
+        } => {
             let heading = arena.alloc(Node::new(RefCell::new(Ast::new(
                 NodeValue::Heading(NodeHeading {
                     level: *level as u8,
@@ -514,6 +1567,13 @@ where
                 LineColumn { line: 0, column: 0 },
             ))));
             heading.append(text_node);
+            // The heading attribute syntax, written as is so the braces are not escaped
+            if let Some(anchor) = anchor {
+                heading.append(arena.alloc(Node::new(RefCell::new(Ast::new(
+                    NodeValue::HtmlInline(format!(" {{#{}}}", anchor)),
+                    LineColumn { line: 0, column: 0 },
+                )))));
+            }
             Ok(heading)
         }
 
@@ -524,16 +1584,31 @@ where
             ))));
 
             for child_element in elements {
-                let child_node = element_to_ast_node(arena, child_element, image_num, image_saver)?;
-                paragraph.append(child_node);
+                append_inline(arena, paragraph, child_element, image_num, image_saver)?;
             }
             Ok(paragraph)
         }
 
+        Element::Styled { .. } | Element::Math { .. } => {
+            let paragraph = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::Paragraph,
+                LineColumn { line: 0, column: 0 },
+            ))));
+            append_inline(arena, paragraph, element, image_num, image_saver)?;
+            Ok(paragraph)
+        }
+
         Element::List { elements, numbered } => {
             let list_node = create_list_node(arena, *numbered);
             for list_item in elements {
-                let item_node = create_item_node(arena, *numbered);
+                // A nested list keeps the checkbox on the item it follows
+                let checked = match &list_item.element {
+                    Element::List { elements, .. } if is_parent_list(list_item) => {
+                        elements.first().and_then(|parent| parent.checked)
+                    }
+                    _ => list_item.checked,
+                };
+                let item_node = create_item_node(arena, *numbered, checked);
 
                 if is_parent_list(list_item) {
                     if let Element::List { elements, .. } = &list_item.element {
@@ -563,6 +1638,13 @@ where
                             list_node.append(item_node);
                         }
                     }
+                } else if let (Element::List { .. }, Some(previous)) =
+                    (&list_item.element, list_node.last_child())
+                {
+                    // A nested list following an item belongs to that item
+                    let children_node =
+                        element_to_ast_node(arena, &list_item.element, image_num, image_saver)?;
+                    previous.append(children_node);
                 } else {
                     let list_item_element = text_to_paragraph(list_item.element.clone());
 
@@ -576,10 +1658,7 @@ where
         }
 
         Element::Image(image_data) => {
-            // If the image_saver.function returns a special marker, embed as Base64
-            let result = (image_saver.function)(image_data.bytes(), "__base64__");
-            if let Ok(_) = result {
-                // Embed as Base64 markdown
+            if image_saver.embed {
                 let base64_md = image_data.to_base64_markdown();
                 let node = arena.alloc(Node::new(RefCell::new(Ast::new(
                     NodeValue::Paragraph,
@@ -592,7 +1671,6 @@ where
                 node.append(text_node);
                 Ok(node)
             } else {
-                // Default: save image and reference by filename
                 *image_num.borrow_mut() += 1;
                 let image_extension = image_data.image_type().to_extension();
                 let image_filename = format!("image{}{}", image_num.borrow(), image_extension);
@@ -626,11 +1704,23 @@ where
             Ok(link_node)
         }
 
-        Element::Table { headers, rows } => {
+        Element::Table {
+            headers,
+            rows,
+            alignments,
+            ..
+        } => {
             let num_columns = headers.len() as u32;
             let num_rows = rows.len() as u32 + 1;
 
-            let alignments = vec![TableAlignment::None; num_columns as usize];
+            let alignments = (0..num_columns as usize)
+                .map(|column| match alignments.get(column) {
+                    Some(ColumnAlignment::Left) => TableAlignment::Left,
+                    Some(ColumnAlignment::Center) => TableAlignment::Center,
+                    Some(ColumnAlignment::Right) => TableAlignment::Right,
+                    _ => TableAlignment::None,
+                })
+                .collect();
 
             let table_node = arena.alloc(Node::new(RefCell::new(Ast::new(
                 NodeValue::Table(NodeTable {
@@ -648,14 +1738,8 @@ where
                 LineColumn { line: 0, column: 0 },
             ))));
             for header in headers {
-                let cell_node = arena.alloc(Node::new(RefCell::new(Ast::new(
-                    NodeValue::TableCell,
-                    LineColumn { line: 0, column: 0 },
-                ))));
-                let cell_content =
-                    element_to_ast_node(arena, &header.element, image_num, image_saver)?;
-                cell_node.append(cell_content);
-                header_row_node.append(cell_node);
+                let elements = std::slice::from_ref(&header.element);
+                header_row_node.append(table_cell_node(arena, elements, image_num, image_saver)?);
             }
             table_node.append(header_row_node);
 
@@ -666,13 +1750,8 @@ where
                     LineColumn { line: 0, column: 0 },
                 ))));
                 for cell in &row.cells {
-                    let cell_node = arena.alloc(Node::new(RefCell::new(Ast::new(
-                        NodeValue::TableCell,
-                        LineColumn { line: 0, column: 0 },
-                    ))));
-                    let cell_content =
-                        element_to_ast_node(arena, &cell.element, image_num, image_saver)?;
-                    cell_node.append(cell_content);
+                    let cell_node =
+                        table_cell_node(arena, &cell.elements, image_num, image_saver)?;
                     row_node.append(cell_node);
                 }
                 table_node.append(row_node);
@@ -681,9 +1760,189 @@ where
             Ok(table_node)
         }
 
-        _ => {
+        Element::LineBreak => {
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::LineBreak,
+                LineColumn { line: 0, column: 0 },
+            ))));
+            Ok(node)
+        }
+
+        // Markdown has no figures, the caption is set in italics on the line below the image
+        Element::Figure { image, caption } => {
+            let paragraph = Element::Paragraph {
+                elements: vec![
+                    Element::Image(image.clone()),
+                    Element::LineBreak,
+                    Element::Styled {
+                        style: TextStyle {
+                            italic: true,
+                            ..Default::default()
+                        },
+                        elements: caption.clone(),
+                    },
+                ],
+            };
+            element_to_ast_node(arena, &paragraph, image_num, image_saver)
+        }
+
+        Element::HorizontalRule => {
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::ThematicBreak,
+                LineColumn { line: 0, column: 0 },
+            ))));
+            Ok(node)
+        }
+
+        Element::PageBreak => {
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::HtmlBlock(NodeHtmlBlock {
+                    // CommonMark HTML block type 6 starts with a block tag such as `div`
+                    block_type: 6,
+                    literal: format!("{}\n", PAGE_BREAK),
+                }),
+                LineColumn { line: 0, column: 0 },
+            ))));
+            Ok(node)
+        }
+
+        Element::Comment { text, .. } => {
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::HtmlBlock(NodeHtmlBlock {
+                    // CommonMark HTML block type 2 is a comment
+                    block_type: 2,
+                    literal: format!("<!-- {} -->\n", text),
+                }),
+                LineColumn { line: 0, column: 0 },
+            ))));
+            Ok(node)
+        }
+
+        Element::Media { .. } => {
+            element_to_ast_node(arena, &element.media_fallback(), image_num, image_saver)
+        }
+
+        Element::Ruby { .. } => {
+            element_to_ast_node(arena, &element.ruby_fallback(), image_num, image_saver)
+        }
+
+        // Only the application that made it knows what it shows
+        Element::Custom { .. } => Ok(arena.alloc(Node::new(RefCell::new(Ast::new(
+            NodeValue::Text(String::new()),
+            LineColumn { line: 0, column: 0 },
+        ))))),
+
+        // Sections nested in other blocks keep their children in place
+        Element::Section { children, .. } => {
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::Document,
+                LineColumn { line: 0, column: 0 },
+            ))));
+            for child in children {
+                node.append(element_to_ast_node(arena, child, image_num, image_saver)?);
+            }
+            Ok(node)
+        }
+
+        // The data of the chart as a table
+        Element::Chart { .. } => {
+            element_to_ast_node(arena, &element.chart_fallback(), image_num, image_saver)
+        }
+
+        // Only the generator knows the headers, a nested table of contents has no entries
+        Element::TableOfContents { .. } => {
             let node = arena.alloc(Node::new(RefCell::new(Ast::new(
-                NodeValue::Text("".to_string()),
+                NodeValue::HtmlBlock(NodeHtmlBlock {
+                    block_type: 2,
+                    literal: format!("<!-- {} -->\n<!-- {} -->\n", TOC_START, TOC_END),
+                }),
+                LineColumn { line: 0, column: 0 },
+            ))));
+            Ok(node)
+        }
+
+        Element::Blockquote { elements, .. } => {
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::BlockQuote,
+                LineColumn { line: 0, column: 0 },
+            ))));
+            for child in elements {
+                let child = text_to_paragraph(child.clone());
+                node.append(element_to_ast_node(arena, &child, image_num, image_saver)?);
+            }
+            Ok(node)
+        }
+
+        Element::DefinitionList { items } => {
+            let node = |value: NodeValue| {
+                arena.alloc(Node::new(RefCell::new(Ast::new(
+                    value,
+                    LineColumn { line: 0, column: 0 },
+                ))))
+            };
+            let list = node(NodeValue::DescriptionList);
+            for item in items {
+                let item_node = node(NodeValue::DescriptionItem(NodeDescriptionItem::default()));
+                let term = node(NodeValue::DescriptionTerm);
+                let paragraph = node(NodeValue::Paragraph);
+                for child in &item.term {
+                    append_inline(arena, paragraph, child, image_num, image_saver)?;
+                }
+                term.append(paragraph);
+                item_node.append(term);
+                for definition in &item.definitions {
+                    let details = node(NodeValue::DescriptionDetails);
+                    let definition = text_to_paragraph(definition.clone());
+                    details.append(element_to_ast_node(
+                        arena,
+                        &definition,
+                        image_num,
+                        image_saver,
+                    )?);
+                    item_node.append(details);
+                }
+                list.append(item_node);
+            }
+            Ok(list)
+        }
+
+        Element::Footnote { id, content } => {
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::FootnoteDefinition(NodeFootnoteDefinition {
+                    name: id.clone(),
+                    total_references: 0,
+                }),
+                LineColumn { line: 0, column: 0 },
+            ))));
+            for child in content {
+                let child = text_to_paragraph(child.clone());
+                node.append(element_to_ast_node(arena, &child, image_num, image_saver)?);
+            }
+            Ok(node)
+        }
+
+        Element::FootnoteReference { id } => {
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::FootnoteReference(NodeFootnoteReference {
+                    name: id.clone(),
+                    ref_num: 0,
+                    ix: 0,
+                }),
+                LineColumn { line: 0, column: 0 },
+            ))));
+            Ok(node)
+        }
+
+        Element::CodeBlock { language, code } => {
+            let node = arena.alloc(Node::new(RefCell::new(Ast::new(
+                NodeValue::CodeBlock(NodeCodeBlock {
+                    fenced: true,
+                    fence_char: b'`',
+                    fence_length: 3,
+                    fence_offset: 0,
+                    info: language.clone().unwrap_or_default(),
+                    literal: format!("{}\n", code),
+                }),
                 LineColumn { line: 0, column: 0 },
             ))));
             Ok(node)
@@ -691,6 +1950,79 @@ where
     }
 }
 
+/// Appends a paragraph child. Bold, italic and struck out text become emphasis nodes,
+/// underline, superscript and subscript have no Markdown syntax and are wrapped in inline HTML,
+/// highlighted text is between `==` marks.
+fn append_inline<'a, F>(
+    arena: &'a Arena<AstNode<'a>>,
+    parent: &'a AstNode<'a>,
+    element: &Element,
+    image_num: &RefCell<i32>,
+    image_saver: &ImageSaver<F>,
+) -> anyhow::Result<()>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    let node = |value: NodeValue| {
+        arena.alloc(Node::new(RefCell::new(Ast::new(
+            value,
+            LineColumn { line: 0, column: 0 },
+        ))))
+    };
+    match element {
+        Element::Comment { text, .. } => {
+            parent.append(node(NodeValue::HtmlInline(format!("<!-- {} -->", text))));
+        }
+        // Markdown has no syntax for these, they are written as HTML
+        Element::Styled { style, elements } => {
+            let tags: Vec<&str> = [
+                (style.underline, "u"),
+                (style.superscript, "sup"),
+                (style.subscript, "sub"),
+            ]
+            .into_iter()
+            .filter_map(|(enabled, tag)| enabled.then_some(tag))
+            .collect();
+            // Highlighting is the `==` extension, its color is not kept
+            if style.highlight.is_some() {
+                parent.append(node(NodeValue::HtmlInline("==".to_string())));
+            }
+            for tag in &tags {
+                parent.append(node(NodeValue::HtmlInline(format!("<{}>", tag))));
+            }
+            let mut inner = parent;
+            let wrappers = [
+                (style.bold, NodeValue::Strong),
+                (style.italic, NodeValue::Emph),
+                (style.strikethrough, NodeValue::Strikethrough),
+            ];
+            for (_, value) in wrappers.into_iter().filter(|(enabled, _)| *enabled) {
+                let wrapper = node(value);
+                inner.append(wrapper);
+                inner = wrapper;
+            }
+            for child in elements {
+                append_inline(arena, inner, child, image_num, image_saver)?;
+            }
+            for tag in tags.iter().rev() {
+                parent.append(node(NodeValue::HtmlInline(format!("</{}>", tag))));
+            }
+            if style.highlight.is_some() {
+                parent.append(node(NodeValue::HtmlInline("==".to_string())));
+            }
+        }
+        Element::Math { tex, display } => {
+            parent.append(node(NodeValue::Math(NodeMath {
+                dollar_math: true,
+                display_math: *display,
+                literal: tex.clone(),
+            })));
+        }
+        _ => parent.append(element_to_ast_node(arena, element, image_num, image_saver)?),
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use core::str;
@@ -781,14 +2113,17 @@ blabla2 bla bla blabla bla bla blabla bla bla blabla bla bla bla"#;
             Header {
                 level: 1,
                 text: "First header".to_string(),
+                anchor: None,
             },
             Header {
                 level: 2,
                 text: "Second Header".to_string(),
+                anchor: None,
             },
             Header {
                 level: 3,
                 text: "Third Header".to_string(),
+                anchor: None,
             },
         ];
         let result_doc = Document::new(elements);
@@ -811,14 +2146,14 @@ blabla2 bla bla blabla bla bla blabla bla bla blabla bla bla bla"#;
                 TableHeader {
                     element: Text {
                         text: "Syntax".to_string(),
-                        size: 14,
+                        font: FontSpec::from(14),
                     },
                     width: 30.0,
                 },
                 TableHeader {
                     element: Text {
                         text: "Description".to_string(),
-                        size: 14,
+                        font: FontSpec::from(14),
                     },
                     width: 30.0,
                 },
@@ -826,37 +2161,31 @@ blabla2 bla bla blabla bla bla blabla bla bla blabla bla bla bla"#;
             rows: vec![
                 TableRow {
                     cells: vec![
-                        TableCell {
-                            element: Text {
-                                text: "Header".to_string(),
-                                size: 14,
-                            },
-                        },
-                        TableCell {
-                            element: Text {
-                                text: "Title".to_string(),
-                                size: 14,
-                            },
-                        },
+                        TableCell::new(Text {
+                            text: "Header".to_string(),
+                            font: FontSpec::from(14),
+                        }),
+                        TableCell::new(Text {
+                            text: "Title".to_string(),
+                            font: FontSpec::from(14),
+                        }),
                     ],
                 },
                 TableRow {
                     cells: vec![
-                        TableCell {
-                            element: Text {
-                                text: "Paragraph".to_string(),
-                                size: 14,
-                            },
-                        },
-                        TableCell {
-                            element: Text {
-                                text: "Text".to_string(),
-                                size: 14,
-                            },
-                        },
+                        TableCell::new(Text {
+                            text: "Paragraph".to_string(),
+                            font: FontSpec::from(14),
+                        }),
+                        TableCell::new(Text {
+                            text: "Text".to_string(),
+                            font: FontSpec::from(14),
+                        }),
                     ],
                 },
             ],
+            alignments: vec![],
+            caption: vec![],
         }];
 
         let result_doc = Document::new(elements);
@@ -920,6 +2249,652 @@ blabla2 bla bla blabla bla bla blabla bla bla blabla bla bla bla"#;
 
         Ok(())
     }
+
+    #[test]
+    fn test_hard_line_breaks() -> anyhow::Result<()> {
+        let document = Bytes::from("First sentence.\nSecond sentence.\n");
+        let parsed = Transformer::parse_with_options(
+            &document,
+            disk_image_loader("test/data"),
+            &ParseOptions {
+                hard_line_breaks: true,
+                ..Default::default()
+            },
+        )?;
+        let expected = Element::Paragraph {
+            elements: vec![
+                Element::Text {
+                    text: "First sentence.".to_string(),
+                    font: FontSpec::from(14),
+                },
+                Element::LineBreak,
+                Element::Text {
+                    text: "Second sentence.".to_string(),
+                    font: FontSpec::from(14),
+                },
+            ],
+        };
+        assert_eq!(parsed.get_all_elements(), vec![&expected]);
+
+        let parsed = Transformer::parse(&document)?;
+        assert!(!format!("{:?}", parsed).contains("LineBreak"));
+
+        let generated = html::Transformer::generate(&Document::new(vec![expected]))?;
+        assert!(str::from_utf8(&generated)?.contains("First sentence.<br />Second sentence."));
+        Ok(())
+    }
+
+    #[test]
+    fn test_html_block() -> anyhow::Result<()> {
+        let document = Bytes::from(
+            "# Prices\n\n<table>\n<tr><th>Item</th></tr>\n<tr><td>Tea</td></tr>\n</table>\n\nAfter\n",
+        );
+        let parsed = Transformer::parse(&document)?;
+        let elements = parsed.get_all_elements();
+        assert_eq!(elements.len(), 3);
+        assert!(matches!(elements[0], Element::Header { .. }));
+        assert!(matches!(elements[1], Element::Table { .. }));
+        assert!(matches!(elements[2], Element::Paragraph { .. }));
+
+        let strict = ParseOptions {
+            allow_html: false,
+            ..Default::default()
+        };
+        let parsed = Transformer::parse_with_options(&document, disk_image_loader("."), &strict)?;
+        assert_eq!(parsed.get_all_elements().len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_comments() -> anyhow::Result<()> {
+        let document = Bytes::from("<!-- editorial note -->\n\nText <!-- inline --> here\n");
+        let parsed = Transformer::parse(&document)?;
+        let elements = parsed.get_all_elements();
+        assert_eq!(
+            elements[0],
+            &Element::Comment {
+                text: "editorial note".to_string(),
+                author: None,
+                date: None,
+                range: None,
+            }
+        );
+        let generated = Transformer::generate(&parsed)?;
+        let generated = str::from_utf8(&generated)?;
+        assert!(generated.contains("<!-- editorial note -->"));
+        assert!(generated.contains("<!-- inline -->"));
+
+        let text = crate::text::Transformer::generate(&parsed)?;
+        assert!(!str::from_utf8(&text)?.contains("editorial"));
+
+        let strict = ParseOptions {
+            strip_comments: true,
+            ..Default::default()
+        };
+        let parsed = Transformer::parse_with_options(&document, disk_image_loader("."), &strict)?;
+        assert!(!format!("{:?}", parsed).contains("Comment"));
+        Ok(())
+    }
+    #[test]
+    fn test_code_block() -> anyhow::Result<()> {
+        let document = Bytes::from("```rust\nfn main() {\n    *x = 1;\n}\n```\n\n    indented\n");
+        let parsed = Transformer::parse(&document)?;
+        let elements = parsed.get_all_elements();
+        assert_eq!(
+            elements[0],
+            &Element::CodeBlock {
+                language: Some("rust".to_string()),
+                code: "fn main() {\n    *x = 1;\n}".to_string()
+            }
+        );
+        assert_eq!(
+            elements[1],
+            &Element::CodeBlock {
+                language: None,
+                code: "indented".to_string()
+            }
+        );
+        let generated = Transformer::generate(&parsed)?;
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), elements);
+        Ok(())
+    }
+
+    #[test]
+    fn test_blockquote() -> anyhow::Result<()> {
+        let document = Bytes::from("> Quoted text\n>\n> - item\n\nAfter\n");
+        let parsed = Transformer::parse(&document)?;
+        let elements = parsed.get_all_elements();
+        let Element::Blockquote {
+            elements: quote, ..
+        } = elements[0]
+        else {
+            panic!("expected a blockquote, got {:?}", elements[0]);
+        };
+        assert_eq!(quote.len(), 2);
+        assert!(matches!(quote[0], Element::Paragraph { .. }));
+        assert!(matches!(quote[1], Element::List { .. }));
+        assert!(matches!(elements[1], Element::Paragraph { .. }));
+
+        let generated = Transformer::generate(&parsed)?;
+        assert!(String::from_utf8(generated.to_vec())?.starts_with("> "));
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), elements);
+        Ok(())
+    }
+
+    #[test]
+    fn test_footnote() -> anyhow::Result<()> {
+        let document = Bytes::from("Claim[^1] holds.\n\n[^1]: Source of the claim\n");
+        let parsed = Transformer::parse(&document)?;
+        let elements = parsed.get_all_elements();
+        let Element::Paragraph {
+            elements: paragraph,
+        } = elements[0]
+        else {
+            panic!("expected a paragraph, got {:?}", elements[0]);
+        };
+        assert_eq!(
+            paragraph[1],
+            Element::FootnoteReference {
+                id: "1".to_string()
+            }
+        );
+        let Element::Footnote { id, content } = elements[1] else {
+            panic!("expected a footnote, got {:?}", elements[1]);
+        };
+        assert_eq!(id, "1");
+        assert_eq!(content.len(), 1);
+
+        let generated = Transformer::generate(&parsed)?;
+        let markdown = String::from_utf8(generated.to_vec())?;
+        assert!(markdown.contains("[^1]:"), "{}", markdown);
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), elements);
+        Ok(())
+    }
+
+    #[test]
+    fn test_styled() -> anyhow::Result<()> {
+        let document = Bytes::from("Plain **bold *both*** ~~gone~~ <u>under</u>\n");
+        let parsed = Transformer::parse(&document)?;
+        let elements = parsed.get_all_elements();
+        let Element::Paragraph {
+            elements: paragraph,
+        } = elements[0]
+        else {
+            panic!("expected a paragraph, got {:?}", elements[0]);
+        };
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(14),
+        };
+        assert_eq!(
+            paragraph[1],
+            Element::Styled {
+                style: TextStyle::BOLD,
+                elements: vec![
+                    text("bold "),
+                    Element::Styled {
+                        style: TextStyle::ITALIC,
+                        elements: vec![text("both")],
+                    },
+                ],
+            }
+        );
+        assert_eq!(
+            paragraph[3],
+            Element::Styled {
+                style: TextStyle::STRIKETHROUGH,
+                elements: vec![text("gone")],
+            }
+        );
+        assert_eq!(
+            paragraph[5],
+            Element::Styled {
+                style: TextStyle::UNDERLINE,
+                elements: vec![text("under")],
+            }
+        );
+
+        let generated = Transformer::generate(&parsed)?;
+        let markdown = String::from_utf8(generated.to_vec())?;
+        assert!(markdown.contains("**bold *both***"), "{}", markdown);
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), elements);
+        Ok(())
+    }
+
+    #[test]
+    fn test_highlight() -> anyhow::Result<()> {
+        let document = Bytes::from("Read ==the **fine** print== first\n");
+        let parsed = Transformer::parse(&document)?;
+        let elements = parsed.get_all_elements();
+        let Element::Paragraph {
+            elements: paragraph,
+        } = elements[0]
+        else {
+            panic!("expected a paragraph, got {:?}", elements[0]);
+        };
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(14),
+        };
+        assert_eq!(
+            paragraph[1],
+            Element::Styled {
+                style: TextStyle::HIGHLIGHT,
+                elements: vec![
+                    text("the "),
+                    Element::Styled {
+                        style: TextStyle::BOLD,
+                        elements: vec![text("fine")],
+                    },
+                    text(" print"),
+                ],
+            }
+        );
+
+        let generated = Transformer::generate(&parsed)?;
+        let markdown = String::from_utf8(generated.to_vec())?;
+        assert!(markdown.contains("==the **fine** print=="), "{}", markdown);
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), elements);
+        Ok(())
+    }
+
+    #[test]
+    fn test_hard_break() -> anyhow::Result<()> {
+        let markdown = "Roses are red,  \nviolets are blue\\\nsugar";
+        let parsed = Transformer::parse(&Bytes::from(markdown))?;
+        let Element::Paragraph { elements } = parsed.get_all_elements()[0] else {
+            panic!("expected a paragraph");
+        };
+        assert_eq!(
+            elements
+                .iter()
+                .filter(|element| matches!(element, Element::LineBreak))
+                .count(),
+            2
+        );
+        let generated = Transformer::generate(&parsed)?;
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_break() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: "Cover".to_string(),
+                    font: FontSpec::from(14),
+                }],
+            },
+            Element::PageBreak,
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let markdown = String::from_utf8(generated.to_vec())?;
+        assert!(markdown.contains(PAGE_BREAK), "{}", markdown);
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements()[1], &Element::PageBreak);
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_of_contents() -> anyhow::Result<()> {
+        let header = |level: u8, text: &str| Element::Header {
+            level,
+            text: text.to_string(),
+            anchor: None,
+        };
+        let document = Document::new(vec![
+            Element::TableOfContents { max_level: 2 },
+            header(1, "Install"),
+            header(2, "From source"),
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let markdown = String::from_utf8(generated.to_vec())?;
+        assert!(markdown.contains("<!-- tocstop -->"), "{}", markdown);
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_heading_anchor() -> anyhow::Result<()> {
+        let document = Bytes::from("# Introduction {#intro}\n\nThe scope\n");
+        let parsed = Transformer::parse(&document)?;
+        let elements = parsed.get_all_elements();
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 1,
+                text: "Introduction".to_string(),
+                anchor: Some("intro".to_string()),
+            }
+        );
+
+        let generated = Transformer::generate(&parsed)?;
+        let markdown = String::from_utf8(generated.to_vec())?;
+        assert!(markdown.contains("# Introduction {#intro}"), "{}", markdown);
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), elements);
+        Ok(())
+    }
+
+    #[test]
+    fn test_superscript_subscript() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(14),
+        };
+        let document = Document::new(vec![Element::Paragraph {
+            elements: vec![
+                text("H"),
+                Element::Styled {
+                    style: TextStyle::SUBSCRIPT,
+                    elements: vec![text("2")],
+                },
+                text("O and x"),
+                Element::Styled {
+                    style: TextStyle::SUPERSCRIPT,
+                    elements: vec![text("2")],
+                },
+            ],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        let markdown = String::from_utf8(generated.to_vec())?;
+        assert!(
+            markdown.contains("H<sub>2</sub>O and x<sup>2</sup>"),
+            "{}",
+            markdown
+        );
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_math() -> anyhow::Result<()> {
+        let document = Bytes::from("Area $\\pi r^2$\n\n$$\n\\frac{a}{b}\n$$\n");
+        let parsed = Transformer::parse(&document)?;
+        let elements = parsed.get_all_elements();
+        let Element::Paragraph {
+            elements: paragraph,
+        } = elements[0]
+        else {
+            panic!("expected a paragraph, got {:?}", elements[0]);
+        };
+        assert_eq!(
+            paragraph[1],
+            Element::Math {
+                tex: "\\pi r^2".to_string(),
+                display: false,
+            }
+        );
+        assert_eq!(
+            *elements[1],
+            Element::Math {
+                tex: "\\frac{a}{b}".to_string(),
+                display: true,
+            }
+        );
+
+        let generated = Transformer::generate(&parsed)?;
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), elements);
+        Ok(())
+    }
+
+    #[test]
+    fn test_ruby_fallback() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Paragraph {
+            elements: vec![Element::Ruby {
+                base: "東京".to_string(),
+                annotation: "とうきょう".to_string(),
+            }],
+        }]);
+        let generated = String::from_utf8(Transformer::generate(&document)?.to_vec())?;
+        assert_eq!(generated.trim(), "東京(とうきょう)");
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_list() -> anyhow::Result<()> {
+        let document = Bytes::from("- [x] Done\n- [ ] Todo\n- Note\n");
+        let parsed = Transformer::parse(&document)?;
+        let elements = parsed.get_all_elements();
+        let Element::List {
+            elements: items, ..
+        } = elements[0]
+        else {
+            panic!("expected a list, got {:?}", elements[0]);
+        };
+        let checked: Vec<Option<bool>> = items.iter().map(|item| item.checked).collect();
+        assert_eq!(checked, vec![Some(true), Some(false), None]);
+        assert!(matches!(&items[0].element, Text { text, .. } if text == "Done"));
+
+        let generated = Transformer::generate(&parsed)?;
+        let markdown = String::from_utf8(generated.to_vec())?;
+        assert!(
+            markdown.contains("- [x] Done\n- [ ] Todo\n"),
+            "{}",
+            markdown
+        );
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), elements);
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_task_list() -> anyhow::Result<()> {
+        let document = Bytes::from("- Chores\n  - [x] Dishes\n  - [ ] Laundry\n");
+        let parsed = Transformer::parse(&document)?;
+        let checked: Vec<Option<bool>> = parsed
+            .iter_elements()
+            .filter_map(|item| match item.element {
+                Element::List { elements, .. } => Some(elements),
+                _ => None,
+            })
+            .flatten()
+            .filter(|item| matches!(item.element, Text { .. }))
+            .map(|item| item.checked)
+            .collect();
+        assert_eq!(checked, vec![None, Some(true), Some(false)]);
+
+        for input in [
+            "- a\n  - b\n    - [x] c\n- [ ] d\n",
+            "- # h\n  - [x] c\n",
+            "-\n  - [x] c\n",
+        ] {
+            assert!(Transformer::parse(&Bytes::from(input)).is_ok(), "{}", input);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_horizontal_rule() -> anyhow::Result<()> {
+        let document = Bytes::from("Above\n\n---\n\nBelow\n");
+        let parsed = Transformer::parse(&document)?;
+        let elements = parsed.get_all_elements();
+        assert_eq!(elements.len(), 3);
+        assert_eq!(elements[1], &Element::HorizontalRule);
+
+        let generated = Transformer::generate(&parsed)?;
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), elements);
+        Ok(())
+    }
+
+    #[test]
+    fn test_definition_list() -> anyhow::Result<()> {
+        let document = Bytes::from("Apple\n: A fruit\n\nOrange\n: Citrus\n: Juicy\n");
+        let parsed = Transformer::parse(&document)?;
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(14),
+        };
+        let definition = |value: &str| Element::Paragraph {
+            elements: vec![text(value)],
+        };
+        assert_eq!(
+            parsed.get_all_elements(),
+            vec![&Element::DefinitionList {
+                items: vec![
+                    DefinitionItem {
+                        term: vec![text("Apple")],
+                        definitions: vec![definition("A fruit")],
+                    },
+                    DefinitionItem {
+                        term: vec![text("Orange")],
+                        definitions: vec![definition("Citrus"), definition("Juicy")],
+                    },
+                ],
+            }]
+        );
+
+        let generated = Transformer::generate(&parsed)?;
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_alignment() -> anyhow::Result<()> {
+        let document = Bytes::from("| a | b | c |\n|:--|:-:|--:|\n| 1 | 2 | 3 |\n");
+        let parsed = Transformer::parse(&document)?;
+        let [Element::Table { alignments, .. }] = parsed.get_all_elements()[..] else {
+            panic!("expected a table, got {:?}", parsed.get_all_elements());
+        };
+        assert_eq!(
+            alignments,
+            &vec![
+                ColumnAlignment::Left,
+                ColumnAlignment::Center,
+                ColumnAlignment::Right
+            ]
+        );
+
+        let generated = Transformer::generate(&parsed)?;
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rich_table_cells() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let paragraph = |text_: &str| Element::Paragraph {
+            elements: vec![text(text_)],
+        };
+        let list = Element::List {
+            elements: vec![
+                ListItem {
+                    element: text("x"),
+                    checked: None,
+                },
+                ListItem {
+                    element: text("y"),
+                    checked: None,
+                },
+            ],
+            numbered: false,
+        };
+        let document = Document::new(vec![Element::Table {
+            headers: vec![TableHeader {
+                element: text("h"),
+                width: 10.0,
+            }],
+            rows: vec![
+                TableRow {
+                    cells: vec![TableCell::new(list)],
+                },
+                TableRow {
+                    cells: vec![TableCell::with_elements(vec![paragraph("a"), paragraph("b")])],
+                },
+            ],
+            alignments: vec![],
+            caption: vec![],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(generated, "| h |\n| --- |\n| - x<br>- y |\n| a<br>b |\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_spans() -> anyhow::Result<()> {
+        let input = "---\ntitle: Spans\n---\n# Título\n\n> Quoted\n> text\n\n- One\n- Two\n";
+        let parsed = Transformer::parse(&Bytes::from(input))?;
+        assert_eq!(parsed.get_all_elements().len(), 3);
+
+        let header = parsed.source_span(&[0, 0]).unwrap();
+        assert_eq!((header.line, header.column), (Some(4), Some(1)));
+        assert!(input[header.byte_range.clone().unwrap()].starts_with("# Título"));
+        let quote = parsed.source_span(&[0, 1]).unwrap();
+        assert_eq!(quote.line, Some(6));
+        assert_eq!(
+            &input[quote.byte_range.clone().unwrap()],
+            "> Quoted\n> text\n"
+        );
+        // Nested elements fall back to the top level element holding them
+        let item = parsed.source_span(&[0, 2, 1]).unwrap();
+        assert_eq!((item.line, item.page), (Some(9), None));
+        assert_eq!(item.to_string(), "line 9, column 1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_reader() -> anyhow::Result<()> {
+        let input = "---\ntitle: Sections\n\n# not a header\n---\n# One\n\nText\n\n\
+            ```sh\n\n# a comment\n```\n\n<!--\n\n# commented out\n-->\n\n## Two\n\n- a\n\n- b\n\n\
+            # Three\n";
+        let reader = BufReader::with_capacity(8, input.as_bytes());
+        let parsed = Transformer::parse_reader(reader)?;
+        assert_eq!(parsed, Transformer::parse(&Bytes::from(input))?);
+        assert_eq!(parsed.metadata.title.as_deref(), Some("Sections"));
+
+        let mut sections = Sections::default();
+        let starts: Vec<usize> = input
+            .split_inclusive('\n')
+            .enumerate()
+            .filter_map(|(index, line)| sections.push_line(line).map(|_| index + 1))
+            .collect();
+        assert_eq!(starts, vec![20, 26]);
+
+        // Spans are in the whole input, not the section
+        let header = parsed.source_span(&[0, 6]).unwrap();
+        assert_eq!(header.line, Some(26));
+        assert_eq!(&input[header.byte_range.clone().unwrap()], "# Three\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_reader_definitions() -> anyhow::Result<()> {
+        let input = "# One\n\nSee [the site][site], [x] and a note[^1].\n\n# Two\n\n\
+            [site]: https://example.com \"Example\"\n[x]: https://example.org\n\n\
+            [^1]: The note.\n\n# Three\n\n[SITE]\n";
+        let reader = BufReader::with_capacity(8, input.as_bytes());
+        let parsed = Transformer::parse_reader(reader)?;
+        assert_eq!(parsed, Transformer::parse(&Bytes::from(input))?);
+        let urls: Vec<&str> = parsed
+            .select(ElementKind::Hyperlink)
+            .into_iter()
+            .filter_map(|element| match element {
+                Hyperlink { url, .. } => Some(url.as_str()),
+                _ => None,
+            })
+            .collect();
+        // Defined in the next section
+        assert!(urls.contains(&"https://example.org"));
+        assert!(matches!(parsed.get_detail()[4], Element::Footnote { .. }));
+        assert_eq!(parsed.source_span(&[0, 4]).unwrap().line, Some(10));
+        Ok(())
+    }
 }
 
 /// Process markdown content and automatically convert image references to Base64 format
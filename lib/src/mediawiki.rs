@@ -0,0 +1,1079 @@
+use crate::core::*;
+use bytes::Bytes;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Parses `== headings ==`, `*`/`#` lists, `{| |}` tables, `[[File:]]` images, internal
+    /// and external links and preformatted text. Bold and italic quotes are dropped, the text
+    /// is kept. Templates, categories and magic words are skipped.
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let source = String::from_utf8(document.to_vec())?;
+        let lines: Vec<&str> = source
+            .lines()
+            .map(|line| line.trim_end_matches('\r'))
+            .collect();
+        let parser = Parser {
+            image_loader: &image_loader,
+        };
+        let elements = parser.parse_blocks(&lines)?;
+        Ok(Document::new(elements))
+    }
+
+    /// Images are written through `image_saver` and embedded with `[[File:...]]` links.
+    /// Page headers and footers have no wiki equivalent and are skipped.
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let mut generator = Generator {
+            image_saver,
+            image_num: 0,
+        };
+        let mut wiki = String::new();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                generator.block(&mut wiki, element)?;
+            }
+        }
+        Ok(Bytes::from(wiki))
+    }
+}
+
+/// Namespaces of links which embed a file
+const FILE_NAMESPACES: [&str; 2] = ["file:", "image:"];
+
+/// Image options which are not the caption
+const IMAGE_KEYWORDS: [&str; 11] = [
+    "thumb",
+    "thumbnail",
+    "frame",
+    "framed",
+    "frameless",
+    "border",
+    "left",
+    "right",
+    "center",
+    "none",
+    "upright",
+];
+
+/// `== Title ==` returns the level and the title
+fn heading(line: &str) -> Option<(u8, String)> {
+    let line = line.trim_end();
+    let opening = line.chars().take_while(|c| *c == '=').count();
+    let closing = line.chars().rev().take_while(|c| *c == '=').count();
+    if opening == 0 || closing == 0 || line.len() <= opening + closing {
+        return None;
+    }
+    let level = opening.min(closing).min(6);
+    let text = line[level..line.len() - level].trim_matches('=').trim();
+    Some((level as u8, text.to_string()))
+}
+
+/// The `*#:;` prefix of a list line
+fn list_prefix(line: &str) -> &str {
+    let end = line
+        .find(|c| !matches!(c, '*' | '#' | ':' | ';'))
+        .unwrap_or(line.len());
+    &line[..end]
+}
+
+fn is_list_line(line: &str) -> bool {
+    line.starts_with(['*', '#'])
+}
+
+/// Image type of a file name, unknown extensions fall back to PNG
+fn image_type(path: &str) -> &'static str {
+    let extension = path.rsplit('.').next().unwrap_or_default().to_lowercase();
+    match extension.as_str() {
+        "jpg" | "jpeg" => "jpeg",
+        "gif" => "gif",
+        "svg" => "svg",
+        _ => "png",
+    }
+}
+
+/// `[[File:name.png|thumb|200px|alt=Text|Caption]]` options
+fn image_options(options: &[&str]) -> (String, String, String, ImageDimension) {
+    let mut caption = String::new();
+    let mut alt = String::new();
+    let mut align = String::new();
+    let mut size = ImageDimension::default();
+    for option in options {
+        let option = option.trim();
+        if let Some(value) = option.strip_prefix("alt=") {
+            alt = value.to_string();
+        } else if let Some(pixels) = option.strip_suffix("px") {
+            let (width, height) = pixels.split_once('x').unwrap_or((pixels, ""));
+            if !width.is_empty() {
+                size.width = Some(format!("{}px", width));
+            }
+            if !height.is_empty() {
+                size.height = Some(format!("{}px", height));
+            }
+        } else if matches!(option, "left" | "right" | "center") {
+            align = option.to_string();
+        } else if !IMAGE_KEYWORDS.contains(&option) && !option.contains('=') {
+            caption = option.to_string();
+        }
+    }
+    (caption, alt, align, size)
+}
+
+struct Parser<'a, F> {
+    image_loader: &'a F,
+}
+
+impl<'a, F> Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    fn parse_blocks(&self, lines: &[&str]) -> anyhow::Result<Vec<Element>> {
+        let mut elements = Vec::new();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = lines[index];
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with("----") {
+                index += 1;
+                continue;
+            }
+
+            if let Some((level, text)) = heading(line) {
+                let mut inline = Vec::new();
+                self.parse_inline(&text, &mut inline)?;
+                elements.push(Element::Header {
+                    level,
+                    text: plain_text(&inline),
+                    anchor: None,
+                });
+                index += 1;
+                continue;
+            }
+
+            // Magic words and single line templates carry no content
+            if (trimmed.starts_with("__") && trimmed.ends_with("__"))
+                || (trimmed.starts_with("{{") && trimmed.ends_with("}}"))
+            {
+                index += 1;
+                continue;
+            }
+
+            if trimmed.starts_with("<!--") {
+                let end = lines[index..]
+                    .iter()
+                    .position(|line| line.contains("-->"))
+                    .map(|end| index + end)
+                    .unwrap_or(lines.len() - 1);
+                let text = lines[index..=end].join("\n");
+                let text = text.trim().trim_start_matches("<!--");
+                let text = text.split("-->").next().unwrap_or_default();
+                elements.push(Element::Comment {
+                    text: text.trim().to_string(),
+                    author: None,
+                    date: None,
+                    range: None,
+                });
+                index = end + 1;
+                continue;
+            }
+
+            if let Some(tag) = ["<pre", "<syntaxhighlight", "<source"]
+                .iter()
+                .find(|tag| trimmed.starts_with(*tag))
+            {
+                index = self.parse_preformatted_tag(lines, index, &tag[1..], &mut elements);
+                continue;
+            }
+
+            if trimmed.starts_with("{|") {
+                let end = lines[index..]
+                    .iter()
+                    .position(|line| line.trim_start().starts_with("|}"))
+                    .map(|end| index + end)
+                    .unwrap_or(lines.len());
+                elements.push(self.parse_table(&lines[index + 1..end])?);
+                index = end + 1;
+                continue;
+            }
+
+            if is_list_line(line) {
+                let end = lines[index..]
+                    .iter()
+                    .position(|next| {
+                        !is_list_line(next) || next.as_bytes()[0] != line.as_bytes()[0]
+                    })
+                    .map(|end| index + end)
+                    .unwrap_or(lines.len());
+                let items: Vec<(&str, &str)> = lines[index..end]
+                    .iter()
+                    .map(|line| {
+                        let prefix = list_prefix(line);
+                        (prefix, line[prefix.len()..].trim())
+                    })
+                    .collect();
+                let mut position = 0;
+                elements.push(self.parse_list(&items, &mut position, 1)?);
+                index = end;
+                continue;
+            }
+
+            // Lines starting with a space are preformatted
+            if line.starts_with(' ') {
+                let end = lines[index..]
+                    .iter()
+                    .position(|line| !line.starts_with(' ') || line.trim().is_empty())
+                    .map(|end| index + end)
+                    .unwrap_or(lines.len());
+                let text = lines[index..end]
+                    .iter()
+                    .map(|line| &line[1..])
+                    .collect::<Vec<&str>>()
+                    .join("\n");
+                elements.push(Element::Text {
+                    text,
+                    font: FontSpec::from(8),
+                });
+                index = end;
+                continue;
+            }
+
+            // Paragraph, `:` indentation and `;` definition terms are plain paragraphs
+            let mut paragraph = Vec::new();
+            let start = index;
+            while index < lines.len() {
+                let line = lines[index];
+                let trimmed = line.trim();
+                let ends_paragraph = trimmed.is_empty()
+                    || heading(line).is_some()
+                    || trimmed.starts_with("{|")
+                    || trimmed.starts_with("<!--")
+                    || trimmed.starts_with("----")
+                    || is_list_line(line)
+                    || (index > start && line.starts_with([' ', ':', ';']));
+                if ends_paragraph {
+                    break;
+                }
+                if !matches!(paragraph.last(), None | Some(Element::LineBreak)) {
+                    push_text(&mut paragraph, " ");
+                }
+                self.parse_inline(
+                    trimmed.trim_start_matches([':', ';']).trim(),
+                    &mut paragraph,
+                )?;
+                index += 1;
+            }
+            if let Some(Element::LineBreak) = paragraph.last() {
+                paragraph.pop();
+            }
+            match paragraph.as_slice() {
+                [] => {}
+                // A paragraph with only an image is a standalone figure
+                [Element::Image(_)] => elements.append(&mut paragraph),
+                _ => elements.push(Element::Paragraph {
+                    elements: paragraph,
+                }),
+            }
+        }
+        Ok(elements)
+    }
+
+    /// `<pre>`, `<syntaxhighlight>` and `<source>` blocks, returns the index after the block
+    fn parse_preformatted_tag(
+        &self,
+        lines: &[&str],
+        index: usize,
+        tag: &str,
+        elements: &mut Vec<Element>,
+    ) -> usize {
+        let closing = format!("</{}>", tag);
+        let end = lines[index..]
+            .iter()
+            .position(|line| line.contains(&closing))
+            .map(|end| index + end)
+            .unwrap_or(lines.len() - 1);
+        let text = lines[index..=end].join("\n");
+        let text = text
+            .split_once('>')
+            .map(|(_, rest)| rest)
+            .unwrap_or_default();
+        let text = text.split(&closing).next().unwrap_or_default();
+        let text = text.strip_prefix('\n').unwrap_or(text);
+        let text = text.strip_suffix('\n').unwrap_or(text);
+        elements.push(Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        });
+        end + 1
+    }
+
+    /// Items deeper than `depth` become nested lists following their parent item
+    fn parse_list(
+        &self,
+        items: &[(&str, &str)],
+        position: &mut usize,
+        depth: usize,
+    ) -> anyhow::Result<Element> {
+        let numbered = items[*position].0[..depth].ends_with('#');
+        let mut elements = Vec::new();
+        while *position < items.len() {
+            let (prefix, text) = items[*position];
+            if prefix.len() < depth {
+                break;
+            }
+            if prefix.len() > depth {
+                elements.push(ListItem {
+                    element: self.parse_list(items, position, depth + 1)?,
+                    checked: None,
+                });
+                continue;
+            }
+            // The same depth with another marker starts a new list
+            if prefix.ends_with('#') != numbered && !elements.is_empty() {
+                break;
+            }
+            let mut inline = Vec::new();
+            self.parse_inline(text, &mut inline)?;
+            elements.push(ListItem {
+                element: match inline.len() {
+                    0 => Element::Text {
+                        text: String::new(),
+                        font: FontSpec::from(8),
+                    },
+                    1 => inline.remove(0),
+                    _ => Element::Paragraph { elements: inline },
+                },
+                checked: None,
+            });
+            *position += 1;
+        }
+        Ok(Element::List { elements, numbered })
+    }
+
+    /// `!` cells in the first row are the header, `|-` separates rows
+    fn parse_table(&self, lines: &[&str]) -> anyhow::Result<Element> {
+        let mut rows: Vec<(bool, Vec<Element>)> = Vec::new();
+        let mut row: Vec<Element> = Vec::new();
+        let mut header_row = true;
+        let mut flush = |row: &mut Vec<Element>, header_row: &mut bool| {
+            if !row.is_empty() {
+                rows.push((*header_row, std::mem::take(row)));
+            }
+            *header_row = true;
+        };
+        for line in lines {
+            let line = line.trim();
+            if line.starts_with("|-") {
+                flush(&mut row, &mut header_row);
+                continue;
+            }
+            // Captions are not part of the table model
+            if line.starts_with("|+") {
+                continue;
+            }
+            let (cells, separator) = if let Some(cells) = line.strip_prefix('!') {
+                (cells, "!!")
+            } else if let Some(cells) = line.strip_prefix('|') {
+                header_row = false;
+                (cells, "||")
+            } else {
+                continue;
+            };
+            for cell in cells.split(separator) {
+                // `style="..." | content` carries cell attributes before the content
+                let content = match cell.split_once('|') {
+                    Some((attributes, content))
+                        if attributes.contains('=') && !attributes.contains("[[") =>
+                    {
+                        content
+                    }
+                    _ => cell,
+                };
+                let mut inline = Vec::new();
+                self.parse_inline(content.trim(), &mut inline)?;
+                row.push(match inline.len() {
+                    0 => Element::Text {
+                        text: String::new(),
+                        font: FontSpec::from(8),
+                    },
+                    1 => inline.remove(0),
+                    _ => Element::Paragraph { elements: inline },
+                });
+            }
+        }
+        flush(&mut row, &mut header_row);
+
+        let mut rows = rows.into_iter().peekable();
+        let headers = match rows.peek() {
+            Some((true, _)) => rows
+                .next()
+                .map(|(_, cells)| cells)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|element| TableHeader {
+                    element,
+                    width: 30.0,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let rows = rows
+            .map(|(_, cells)| TableRow {
+                cells: cells.into_iter().map(TableCell::new).collect(),
+            })
+            .collect();
+        Ok(Element::Table {
+            headers,
+            rows,
+            alignments: vec![],
+            caption: vec![],
+        })
+    }
+
+    /// Splits a line into text, `[[links]]`, `[url label]` links, bare URLs and images
+    fn parse_inline(&self, line: &str, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        let mut rest = line;
+        while !rest.is_empty() {
+            let next = [
+                "[[", "[", "<br", "<nowiki>", "{{", "<!--", "https://", "http://",
+            ]
+            .iter()
+            .filter_map(|token| rest.find(token).map(|index| (index, *token)))
+            .filter(|(index, token)| {
+                !token.starts_with("http")
+                    || *index == 0
+                    || !rest[..*index].ends_with(|c: char| c.is_alphanumeric() || c == '[')
+            })
+            .min_by_key(|(index, token)| (*index, usize::MAX - token.len()));
+            let Some((start, token)) = next else {
+                push_text(elements, &strip_quotes(rest));
+                break;
+            };
+            push_text(elements, &strip_quotes(&rest[..start]));
+            let after = &rest[start..];
+            let consumed = match token {
+                "[[" => match after.find("]]") {
+                    Some(end) => {
+                        self.parse_internal_link(&after[2..end], elements)?;
+                        end + 2
+                    }
+                    None => {
+                        push_text(elements, "[[");
+                        2
+                    }
+                },
+                "[" => match after.find(']') {
+                    Some(end) if after[1..].starts_with("http") || after[1..].starts_with("//") => {
+                        let (url, title) = after[1..end]
+                            .split_once(' ')
+                            .unwrap_or((&after[1..end], ""));
+                        let title = if title.trim().is_empty() {
+                            url.to_string()
+                        } else {
+                            strip_quotes(title.trim())
+                        };
+                        elements.push(Element::Hyperlink {
+                            title,
+                            url: url.to_string(),
+                            alt: String::new(),
+                            size: 8,
+                        });
+                        end + 1
+                    }
+                    _ => {
+                        push_text(elements, "[");
+                        1
+                    }
+                },
+                "<br" => match after.find('>') {
+                    Some(end) => {
+                        elements.push(Element::LineBreak);
+                        end + 1
+                    }
+                    None => {
+                        push_text(elements, "<br");
+                        3
+                    }
+                },
+                "<nowiki>" => {
+                    let content = &after["<nowiki>".len()..];
+                    let end = content.find("</nowiki>").unwrap_or(content.len());
+                    push_text(elements, &unescape(&content[..end]));
+                    "<nowiki>".len() + (end + "</nowiki>".len()).min(content.len())
+                }
+                "{{" => after.find("}}").map(|end| end + 2).unwrap_or(after.len()),
+                "<!--" => after.find("-->").map(|end| end + 3).unwrap_or(after.len()),
+                _ => {
+                    let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                    let url = after[..end].trim_end_matches(['.', ',', ';', ':', ')']);
+                    elements.push(Element::Hyperlink {
+                        title: url.to_string(),
+                        url: url.to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    });
+                    url.len()
+                }
+            };
+            rest = &after[consumed..];
+        }
+        Ok(())
+    }
+
+    /// `[[File:...]]` embeds an image, `[[Category:...]]` is metadata, the rest are page links
+    fn parse_internal_link(&self, link: &str, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        let lower = link.to_lowercase();
+        if let Some(namespace) = FILE_NAMESPACES
+            .iter()
+            .find(|namespace| lower.starts_with(*namespace))
+        {
+            let mut parts = link[namespace.len()..].split('|');
+            let path = parts.next().unwrap_or_default().trim();
+            let options: Vec<&str> = parts.collect();
+            let (caption, alt, align, size) = image_options(&options);
+            let bytes = (self.image_loader)(path)?;
+            elements.push(Element::Image(ImageData::new(
+                bytes,
+                strip_quotes(&caption),
+                alt,
+                image_type(path).to_string(),
+                align,
+                size,
+            )));
+            return Ok(());
+        }
+        if lower.starts_with("category:") {
+            return Ok(());
+        }
+        let (target, title) = link.split_once('|').unwrap_or((link, link));
+        elements.push(Element::Hyperlink {
+            title: strip_quotes(title.trim()),
+            url: target.trim().trim_start_matches(':').to_string(),
+            alt: String::new(),
+            size: 8,
+        });
+        Ok(())
+    }
+}
+
+/// Drops runs of two or more apostrophes which mark bold and italic text
+fn strip_quotes(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut quotes = 0;
+    for c in text.chars() {
+        if c == '\'' {
+            quotes += 1;
+            continue;
+        }
+        if quotes == 1 {
+            result.push('\'');
+        }
+        quotes = 0;
+        result.push(c);
+    }
+    if quotes == 1 {
+        result.push('\'');
+    }
+    unescape(&result)
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&amp;", "&")
+}
+
+fn plain_text(elements: &[Element]) -> String {
+    let mut text = String::new();
+    for element in elements {
+        match element {
+            Element::Text { text: value, .. } => text.push_str(value),
+            Element::Hyperlink { title, .. } => text.push_str(title),
+            _ => {}
+        }
+    }
+    text
+}
+
+fn push_text(elements: &mut Vec<Element>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(Element::Text { text: last, .. }) = elements.last_mut() {
+        last.push_str(text);
+    } else {
+        elements.push(Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        });
+    }
+}
+
+/// Wraps text in `<nowiki>` when it would otherwise be read as markup
+fn escape(text: &str) -> String {
+    let markup = ["[", "]", "''", "{{", "}}", "<", "&", "~~~", "__"];
+    if markup.iter().any(|token| text.contains(token)) {
+        format!(
+            "<nowiki>{}</nowiki>",
+            text.replace('&', "&amp;").replace('<', "&lt;")
+        )
+    } else {
+        text.to_string()
+    }
+}
+
+struct Generator<F> {
+    image_saver: F,
+    image_num: usize,
+}
+
+impl<F> Generator<F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn block(&mut self, wiki: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Header { level, text, .. } => {
+                let marks = "=".repeat((*level).clamp(1, 6) as usize);
+                wiki.push_str(&format!(
+                    "{} {} {}\n\n",
+                    marks,
+                    escape(&text.replace('\n', " ")),
+                    marks
+                ));
+            }
+            // Multi-line text keeps its layout in a preformatted block
+            Element::Text { text, .. } if text.contains('\n') => {
+                wiki.push_str("<pre>\n");
+                wiki.push_str(&text.replace("</pre>", "&lt;/pre>"));
+                wiki.push_str("\n</pre>\n\n");
+            }
+            Element::List { elements, numbered } => {
+                let marker = if *numbered { "#" } else { "*" };
+                self.list(wiki, elements, marker)?;
+                wiki.push('\n');
+            }
+            Element::Chart { .. } => self.block(wiki, &element.chart_fallback())?,
+            Element::Table { headers, rows, .. } => self.table(wiki, headers, rows)?,
+            Element::DefinitionList { items } => {
+                for item in items {
+                    let mut term = String::new();
+                    for child in &item.term {
+                        term.push_str(&self.inline(child)?);
+                    }
+                    wiki.push_str(&format!("; {}\n", term.trim()));
+                    for definition in &item.definitions {
+                        let text = self.inline(definition)?.replace('\n', " ");
+                        wiki.push_str(&format!(": {}\n", text.trim()));
+                    }
+                }
+                wiki.push('\n');
+            }
+            Element::Comment { text, .. } => {
+                wiki.push_str(&format!("<!-- {} -->\n\n", text.replace("--", "- -")));
+            }
+            Element::HorizontalRule => wiki.push_str("----\n\n"),
+            // Wiki pages are not paged
+            Element::PageBreak => {}
+            // The wiki places its table of contents where the magic word is, with all levels
+            Element::TableOfContents { .. } => wiki.push_str("__TOC__\n\n"),
+            Element::CodeBlock { language, code } => match language {
+                Some(language) => wiki.push_str(&format!(
+                    "<syntaxhighlight lang=\"{}\">\n{}\n</syntaxhighlight>\n\n",
+                    language.replace('"', ""),
+                    code.replace("</syntaxhighlight>", "&lt;/syntaxhighlight>")
+                )),
+                None => wiki.push_str(&format!(
+                    "<pre>\n{}\n</pre>\n\n",
+                    code.replace("</pre>", "&lt;/pre>")
+                )),
+            },
+            Element::Blockquote { elements, .. } => {
+                wiki.push_str("<blockquote>\n");
+                for child in elements {
+                    self.block(wiki, child)?;
+                }
+                wiki.push_str("</blockquote>\n\n");
+            }
+            Element::Section { children, .. } => {
+                for child in children {
+                    self.block(wiki, child)?;
+                }
+            }
+            element => {
+                let text = self.inline(element)?;
+                if !text.trim().is_empty() {
+                    wiki.push_str(text.trim());
+                    wiki.push_str("\n\n");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn list(&mut self, wiki: &mut String, items: &[ListItem], prefix: &str) -> anyhow::Result<()> {
+        for item in items {
+            match &item.element {
+                // Nested lists extend the prefix of the parent item
+                Element::List { elements, numbered } => {
+                    let marker = if *numbered { "#" } else { "*" };
+                    self.list(wiki, elements, &format!("{}{}", prefix, marker))?
+                }
+                element => {
+                    let text = self.inline(element)?.replace('\n', " ");
+                    wiki.push_str(&format!("{} {}\n", prefix, text.trim()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn table(
+        &mut self,
+        wiki: &mut String,
+        headers: &[TableHeader],
+        rows: &[TableRow],
+    ) -> anyhow::Result<()> {
+        wiki.push_str("{| class=\"wikitable\"\n");
+        if !headers.is_empty() {
+            let cells = headers
+                .iter()
+                .map(|header| self.cell(&header.element))
+                .collect::<anyhow::Result<Vec<String>>>()?;
+            wiki.push_str(&format!("! {}\n", cells.join(" !! ")));
+        }
+        for row in rows {
+            let cells = row
+                .cells
+                .iter()
+                .map(|cell| self.cell(&cell.element()))
+                .collect::<anyhow::Result<Vec<String>>>()?;
+            wiki.push_str("|-\n");
+            wiki.push_str(&format!("| {}\n", cells.join(" || ")));
+        }
+        wiki.push_str("|}\n\n");
+        Ok(())
+    }
+
+    /// Table cells are single lines, and `|` would end the cell
+    fn cell(&mut self, element: &Element) -> anyhow::Result<String> {
+        let text = self.inline(element)?.replace('\n', " ");
+        Ok(if text.contains("||") || text.contains("!!") {
+            text.replace('|', "&#124;").replace('!', "&#33;")
+        } else {
+            text
+        })
+    }
+
+    fn inline(&mut self, element: &Element) -> anyhow::Result<String> {
+        Ok(match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => {
+                escape(&text.replace('\n', " "))
+            }
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => {
+                let mut text = String::new();
+                for child in elements {
+                    text.push_str(&self.inline(child)?);
+                }
+                text
+            }
+            Element::Hyperlink { title, url, .. } => {
+                let title = title.replace(['[', ']', '|'], "");
+                if url.contains("://") || url.starts_with("//") {
+                    if title.is_empty() || title == *url {
+                        url.clone()
+                    } else {
+                        format!("[{} {}]", url, title)
+                    }
+                } else if title.is_empty() || title == *url {
+                    format!("[[{}]]", url)
+                } else {
+                    format!("[[{}|{}]]", url, title)
+                }
+            }
+            Element::Image(image) => self.image(image)?.unwrap_or_default(),
+            // A thumbnail shows its last option as the caption under the image
+            Element::Figure { image, caption } => {
+                let mut text = String::new();
+                for child in caption {
+                    text.push_str(&self.inline(child)?);
+                }
+                let text = text.replace(['|', ']', '\n'], " ");
+                match self.image(image)? {
+                    Some(link) => {
+                        format!("{}|thumb|{}]]", link.trim_end_matches("]]"), text.trim())
+                    }
+                    None => text,
+                }
+            }
+            Element::LineBreak | Element::HorizontalRule => "<br />".to_string(),
+            Element::List { elements, .. } => {
+                let mut items = Vec::new();
+                for item in elements {
+                    items.push(self.inline(&item.element)?);
+                }
+                items.join(" ")
+            }
+            Element::DefinitionList { items } => {
+                let mut texts = Vec::new();
+                for child in items
+                    .iter()
+                    .flat_map(|item| item.term.iter().chain(&item.definitions))
+                {
+                    texts.push(self.inline(child)?);
+                }
+                texts.join(" ")
+            }
+            Element::Media { .. } => self.inline(&element.media_fallback())?,
+            Element::Ruby { .. } => self.inline(&element.ruby_fallback())?,
+            Element::Table { .. }
+            | Element::Chart { .. }
+            | Element::PageBreak
+            | Element::TableOfContents { .. }
+            | Element::Custom { .. } => String::new(),
+            Element::Comment { text, .. } => format!("<!-- {} -->", text.replace("--", "- -")),
+            Element::CodeBlock { code, .. } => format!("<code>{}</code>", escape(code)),
+            Element::Styled { style, elements } => {
+                let mut text = String::new();
+                for child in elements {
+                    text.push_str(&self.inline(child)?);
+                }
+                for (enabled, start, end) in [
+                    (style.strikethrough, "<s>", "</s>"),
+                    (style.underline, "<u>", "</u>"),
+                    (style.italic, "''", "''"),
+                    (style.bold, "'''", "'''"),
+                ] {
+                    if enabled {
+                        text = format!("{}{}{}", start, text, end);
+                    }
+                }
+                text
+            }
+            Element::Math { tex, display } => {
+                if *display {
+                    format!("<math display=\"block\">{}</math>", tex)
+                } else {
+                    format!("<math>{}</math>", tex)
+                }
+            }
+            Element::FootnoteReference { id } => format!("<sup>{}</sup>", escape(id)),
+            Element::Footnote { id, content } => {
+                let mut text = format!("<sup>{}</sup> ", escape(id));
+                for child in content {
+                    text.push_str(&self.inline(child)?);
+                }
+                text
+            }
+        })
+    }
+
+    /// Saves the image and returns the `[[File:...]]` link to it
+    fn image(&mut self, image: &ImageData) -> anyhow::Result<Option<String>> {
+        if image.bytes().is_empty() {
+            return Ok(None);
+        }
+        let target = format!(
+            "image{}{}",
+            self.image_num,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &target)?;
+        self.image_num += 1;
+
+        let mut link = format!("[[File:{}", target);
+        if let Some(width) = image.size().width.as_deref() {
+            let width = width.trim_end_matches("px");
+            if width.chars().all(|c| c.is_ascii_digit()) && !width.is_empty() {
+                link.push_str(&format!("|{}px", width));
+            }
+        }
+        if !image.alt().is_empty() {
+            link.push_str(&format!("|alt={}", image.alt().replace(['|', ']'], "")));
+        }
+        if !image.title().is_empty() {
+            link.push_str(&format!("|{}", image.title().replace(['|', ']'], "")));
+        }
+        link.push_str("]]");
+        Ok(Some(link))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mediawiki::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let source = r#"__TOC__
+= Notes =
+{{Infobox}}
+Install the '''cli''' first, see [https://example.com/docs the docs]
+or [[Main Page|the main page]].<br />Done.
+
+* One
+** Nested
+* Two
+# First
+#* Mixed
+
+{| class="wikitable"
+|+ Caption
+! Name !! Value
+|-
+| a || style="color:red" | 1
+|-
+| b
+| 2
+|}
+
+[[File:Logo.JPG|thumb|200px|alt=Logo|The logo]]
+
+ let x = 1;
+ let y = 2;
+<!-- A comment -->
+[[Category:Docs]]
+"#;
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |_| {
+            Ok(Bytes::from_static(b"\x89PNG"))
+        })?;
+        let elements = document.get_all_elements();
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let link = |title: &str, url: &str| Element::Hyperlink {
+            title: title.to_string(),
+            url: url.to_string(),
+            alt: String::new(),
+            size: 8,
+        };
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 1,
+                text: "Notes".to_string(),
+                anchor: None,
+            }
+        );
+        assert_eq!(
+            elements[1],
+            &Element::Paragraph {
+                elements: vec![
+                    text("Install the cli first, see "),
+                    link("the docs", "https://example.com/docs"),
+                    text(" or "),
+                    link("the main page", "Main Page"),
+                    text("."),
+                    Element::LineBreak,
+                    text("Done."),
+                ]
+            }
+        );
+        assert_eq!(
+            elements[2],
+            &Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("One"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: Element::List {
+                            elements: vec![ListItem {
+                                element: text("Nested"),
+                                checked: None,
+                            }],
+                            numbered: false,
+                        },
+                        checked: None,
+                    },
+                    ListItem {
+                        element: text("Two"),
+                        checked: None,
+                    },
+                ],
+                numbered: false,
+            }
+        );
+        assert!(matches!(
+            elements[3],
+            Element::List { numbered: true, elements } if elements.len() == 2
+        ));
+        let Element::Table { headers, rows, .. } = elements[4] else {
+            panic!("expected a table, got {:?}", elements[4]);
+        };
+        assert_eq!(headers[1].element, text("Value"));
+        assert_eq!(rows[0].cells[1].elements, vec![text("1")]);
+        assert_eq!(rows[1].cells[1].elements, vec![text("2")]);
+        let Element::Image(image) = elements[5] else {
+            panic!("expected an image, got {:?}", elements[5]);
+        };
+        assert_eq!(image.title(), "The logo");
+        assert_eq!(image.alt(), "Logo");
+        assert_eq!(image.size().width.as_deref(), Some("200px"));
+        assert_eq!(image.image_type(), &ImageType::Jpeg);
+        assert_eq!(elements[6], &text("let x = 1;\nlet y = 2;"));
+        assert_eq!(
+            elements[7],
+            &Element::Comment {
+                text: "A comment".to_string(),
+                author: None,
+                date: None,
+                range: None,
+            }
+        );
+        assert_eq!(elements.len(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let images = std::cell::RefCell::new(std::collections::HashMap::new());
+        let generated = Transformer::generate_with_saver(&parsed, |bytes, name| {
+            images.borrow_mut().insert(name.to_string(), bytes.clone());
+            Ok(())
+        })?;
+        let reparsed = Transformer::parse_with_loader(&generated, |name| {
+            Ok(images.borrow().get(name).cloned().unwrap_or_default())
+        })?;
+        let original = parsed.get_all_elements();
+        let elements = reparsed.get_all_elements();
+        assert_eq!(original.len(), elements.len());
+        for (original, element) in original.iter().zip(elements.iter()) {
+            assert_eq!(
+                std::mem::discriminant(*original),
+                std::mem::discriminant(*element)
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,374 @@
+use crate::core::*;
+use crate::html;
+use base64::{engine::general_purpose, Engine as _};
+use bytes::Bytes;
+use std::collections::HashMap;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    /// The root HTML part goes through the HTML parser. Images are resolved from the other
+    /// parts by `cid:` reference or by their `Content-Location`, images missing from the
+    /// archive are kept without content.
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        let root = Part::parse(document)?;
+        let mut parts = Vec::new();
+        root.flatten(&mut parts);
+
+        let start = root
+            .content_type
+            .parameter("start")
+            .map(|start| start.trim_matches(|c| c == '<' || c == '>').to_string());
+        let html_part = parts
+            .iter()
+            .find(|part| start.is_some() && part.content_id() == start)
+            .or_else(|| {
+                parts
+                    .iter()
+                    .find(|part| part.content_type.value == "text/html")
+            })
+            .ok_or_else(|| ParserError::Malformed("MHTML archive has no HTML part".to_string()))?;
+        let mut html = String::from_utf8_lossy(&html_part.body).to_string();
+
+        // The HTML parser takes the image type from the source reference, so references are
+        // rewritten to names carrying the extension of the part they point to
+        let mut images = HashMap::new();
+        for (index, part) in parts.iter().enumerate() {
+            let Some(image_type) = part.image_type() else {
+                continue;
+            };
+            let name = format!("mhtml-part{}{}", index, image_type.to_extension());
+            let mut references = Vec::new();
+            if let Some(id) = part.content_id() {
+                references.push(format!("cid:{}", id));
+            }
+            if let Some(location) = part.headers.get("content-location") {
+                references.push(location.clone());
+            }
+            for reference in references {
+                for reference in [reference.replace('&', "&amp;"), reference] {
+                    for quote in ['"', '\''] {
+                        html = html.replace(
+                            &format!("{}{}{}", quote, reference, quote),
+                            &format!("{}{}{}", quote, name, quote),
+                        );
+                    }
+                }
+            }
+            images.insert(name, part.body.clone());
+        }
+
+        html::Transformer::parse_with_loader(&Bytes::from(html), |source| {
+            Ok(images.get(source).cloned().unwrap_or_default())
+        })
+    }
+
+    fn generate(_document: &Document) -> anyhow::Result<Bytes> {
+        Err(anyhow::anyhow!("MHTML generation is not supported"))
+    }
+}
+
+/// Header value with its `; name=value` parameters
+struct HeaderValue {
+    value: String,
+    parameters: HashMap<String, String>,
+}
+
+impl HeaderValue {
+    fn parse(header: &str) -> HeaderValue {
+        let mut fields = split_parameters(header).into_iter();
+        let value = fields.next().unwrap_or_default().trim().to_lowercase();
+        let parameters = fields
+            .filter_map(|field| {
+                let (name, value) = field.split_once('=')?;
+                Some((
+                    name.trim().to_lowercase(),
+                    value.trim().trim_matches('"').to_string(),
+                ))
+            })
+            .collect();
+        HeaderValue { value, parameters }
+    }
+
+    fn parameter(&self, name: &str) -> Option<&str> {
+        self.parameters.get(name).map(String::as_str)
+    }
+}
+
+/// Splits on `;` outside of quoted strings
+fn split_parameters(header: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    for c in header.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                field.push(c);
+            }
+            ';' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// One MIME entity with its decoded body. Multipart entities keep their parts instead.
+struct Part {
+    headers: HashMap<String, String>,
+    content_type: HeaderValue,
+    body: Bytes,
+    parts: Vec<Part>,
+}
+
+impl Part {
+    fn parse(data: &[u8]) -> anyhow::Result<Part> {
+        let (header, body) = split_header(data);
+        let headers = parse_headers(&String::from_utf8_lossy(header));
+        let content_type = HeaderValue::parse(
+            headers
+                .get("content-type")
+                .map(String::as_str)
+                .unwrap_or("text/plain"),
+        );
+
+        if content_type.value.starts_with("multipart/") {
+            let boundary = content_type
+                .parameter("boundary")
+                .ok_or_else(|| ParserError::Malformed("multipart without boundary".to_string()))?;
+            let parts = split_multipart(body, boundary)
+                .into_iter()
+                .map(Part::parse)
+                .collect::<anyhow::Result<Vec<Part>>>()?;
+            return Ok(Part {
+                headers,
+                content_type,
+                body: Bytes::new(),
+                parts,
+            });
+        }
+
+        let encoding = headers
+            .get("content-transfer-encoding")
+            .map(|encoding| encoding.trim().to_lowercase())
+            .unwrap_or_default();
+        let body = match encoding.as_str() {
+            "base64" => {
+                let data: Vec<u8> = body
+                    .iter()
+                    .copied()
+                    .filter(|byte| !byte.is_ascii_whitespace())
+                    .collect();
+                Bytes::from(general_purpose::STANDARD.decode(data)?)
+            }
+            "quoted-printable" => Bytes::from(decode_quoted_printable(body)),
+            _ => Bytes::from(body.to_vec()),
+        };
+        Ok(Part {
+            headers,
+            content_type,
+            body,
+            parts: Vec::new(),
+        })
+    }
+
+    /// Leaf parts in archive order
+    fn flatten<'a>(&'a self, parts: &mut Vec<&'a Part>) {
+        if self.parts.is_empty() {
+            parts.push(self);
+        }
+        for part in &self.parts {
+            part.flatten(parts);
+        }
+    }
+
+    fn content_id(&self) -> Option<String> {
+        self.headers
+            .get("content-id")
+            .map(|id| id.trim().trim_matches(|c| c == '<' || c == '>').to_string())
+    }
+
+    fn image_type(&self) -> Option<ImageType> {
+        match self.content_type.value.as_str() {
+            "image/png" => Some(ImageType::Png),
+            "image/jpeg" | "image/jpg" => Some(ImageType::Jpeg),
+            "image/gif" => Some(ImageType::Gif),
+            "image/svg+xml" => Some(ImageType::SVG),
+            _ => None,
+        }
+    }
+}
+
+/// Splits an entity at the first empty line
+fn split_header(data: &[u8]) -> (&[u8], &[u8]) {
+    for (index, window) in data.windows(2).enumerate() {
+        if window == b"\n\n" {
+            return (&data[..index], &data[index + 2..]);
+        }
+        if window == b"\n\r" && data.get(index + 2) == Some(&b'\n') {
+            return (&data[..index], &data[index + 3..]);
+        }
+    }
+    (data, &[])
+}
+
+/// Header names are lower-cased, folded lines are joined
+fn parse_headers(header: &str) -> HashMap<String, String> {
+    let mut headers: HashMap<String, String> = HashMap::new();
+    let mut last: Option<String> = None;
+    for line in header.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.starts_with([' ', '\t']) {
+            if let Some(value) = last.as_ref().and_then(|name| headers.get_mut(name)) {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let name = name.trim().to_lowercase();
+            headers.insert(name.clone(), value.trim().to_string());
+            last = Some(name);
+        }
+    }
+    headers
+}
+
+/// Bodies between `--boundary` delimiter lines, the preamble and epilogue are dropped
+fn split_multipart<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut start: Option<usize> = None;
+    let mut position = 0;
+    while position < body.len() {
+        let end = body[position..]
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map(|offset| position + offset + 1)
+            .unwrap_or(body.len());
+        let line = body[position..end].trim_ascii_end();
+        if line.starts_with(delimiter.as_bytes()) {
+            if let Some(start) = start {
+                // The line break before the delimiter belongs to the delimiter
+                let mut part = &body[start..position];
+                part = part.strip_suffix(b"\n").unwrap_or(part);
+                part = part.strip_suffix(b"\r").unwrap_or(part);
+                parts.push(part);
+            }
+            if line[delimiter.len()..].starts_with(b"--") {
+                break;
+            }
+            start = Some(end);
+        }
+        position = end;
+    }
+    parts
+}
+
+fn decode_quoted_printable(data: &[u8]) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(data.len());
+    let mut index = 0;
+    while index < data.len() {
+        if data[index] != b'=' {
+            decoded.push(data[index]);
+            index += 1;
+            continue;
+        }
+        // Soft line break
+        if data[index + 1..].starts_with(b"\r\n") {
+            index += 3;
+            continue;
+        }
+        if data[index + 1..].starts_with(b"\n") {
+            index += 2;
+            continue;
+        }
+        let value = data
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match value {
+            Some(value) => {
+                decoded.push(value);
+                index += 3;
+            }
+            None => {
+                decoded.push(b'=');
+                index += 1;
+            }
+        }
+    }
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::*;
+    use crate::mhtml::*;
+
+    const ARCHIVE: &str = "From: <Saved by Blink>\r
+Subject: Report\r
+MIME-Version: 1.0\r
+Content-Type: multipart/related;\r
+\ttype=\"text/html\";\r
+\tboundary=\"----MultipartBoundary--abc\"\r
+\r
+------MultipartBoundary--abc\r
+Content-Type: text/html\r
+Content-Transfer-Encoding: quoted-printable\r
+Content-Location: https://example.com/report.html\r
+\r
+<html><body><h1>Quarterly =E2=80=93 report</h1><p>Revenue grew by a very long stretch of=\r
+ text.</p><img src=3D\"cid:chart@example\" alt=3D\"Chart\"><img src=3D\"https://example.com/logo.gif\"></body></html>\r
+------MultipartBoundary--abc\r
+Content-Type: image/png\r
+Content-Transfer-Encoding: base64\r
+Content-ID: <chart@example>\r
+\r
+iVBORw0K\r
+Ggo=\r
+------MultipartBoundary--abc\r
+Content-Type: image/gif\r
+Content-Transfer-Encoding: base64\r
+Content-Location: https://example.com/logo.gif\r
+\r
+R0lGODlh\r
+------MultipartBoundary--abc--\r
+";
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let document = Transformer::parse(&Bytes::from(ARCHIVE))?;
+        let elements = document.get_all_elements();
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 1,
+                text: "Quarterly – report".to_string(),
+                anchor: None,
+            }
+        );
+        let images: Vec<&ImageData> = elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Image(image) => Some(image),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].bytes().as_ref(), b"\x89PNG\r\n\x1a\n");
+        assert_eq!(images[0].image_type(), &ImageType::Png);
+        assert_eq!(images[1].bytes().as_ref(), b"GIF89a");
+        assert_eq!(images[1].image_type(), &ImageType::Gif);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_quoted_printable() {
+        assert_eq!(
+            decode_quoted_printable(b"a=3Db=\r\nc=E2=80=93=ZZ"),
+            "a=bc\u{2013}=ZZ".as_bytes()
+        );
+    }
+}
@@ -0,0 +1,427 @@
+use crate::core::*;
+use crate::epub::{escape, generate_element};
+use bytes::Bytes;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Uncompressed size of a text record
+const RECORD_SIZE: usize = 4096;
+
+/// Marks the end of the book records
+const EOF_RECORD: &[u8] = b"\xe9\x8e\x0d\x0a";
+
+const NO_INDEX: u32 = 0xffff_ffff;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(_document: &Bytes) -> anyhow::Result<Document> {
+        Err(anyhow::anyhow!("MOBI parsing is not supported"))
+    }
+
+    /// Writes a MOBI 6 book, the format older Kindle devices read. The content is the markup
+    /// of the EPUB chapters, each level 1 header after the first starts a new page. Text is
+    /// PalmDOC compressed and images are stored as records. KF8 (AZW3) sections are not
+    /// written, newer devices read MOBI 6 as well. SVG images are left out, only alt text
+    /// remains, as the format has no support for them.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        // Chapters start at the level 1 headers, nested sections are not needed
+        let document = &document.flatten_sections();
+        let mut body = String::new();
+        let mut images: Vec<(String, &ImageData)> = Vec::new();
+        let mut title: Option<String> = None;
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                if let Element::Header { level: 1, text, .. } = element {
+                    if !body.is_empty() {
+                        body.push_str("<mbp:pagebreak/>\n");
+                    }
+                    title.get_or_insert_with(|| text.clone());
+                }
+                if let Element::Image(image) = element {
+                    if matches!(image.image_type(), ImageType::SVG) {
+                        body.push_str(&format!("<p>{}</p>\n", escape(image.alt())));
+                        continue;
+                    }
+                }
+                generate_element(&mut body, element, &mut images);
+            }
+        }
+        // Images are referenced by their record, counted from the first image record
+        for (index, (path, _)) in images.iter().enumerate() {
+            body = body.replace(
+                &format!("src=\"{}\"", path),
+                &format!("recindex=\"{:05}\"", index + 1),
+            );
+        }
+        let title = document
+            .metadata
+            .title
+            .clone()
+            .or(title)
+            .unwrap_or_else(|| "Document".to_string());
+        let html = format!(
+            "<html><head><guide></guide></head><body>\n{}</body></html>",
+            body
+        );
+
+        let mut records: Vec<Vec<u8>> = vec![Vec::new()];
+        let text = html.as_bytes();
+        let mut position = 0;
+        while position < text.len() {
+            let end = (position + RECORD_SIZE).min(text.len());
+            let mut record = compress(&text[position..end]);
+            // A character cut at the end of the record is repeated as trailing entry
+            let overlap: Vec<u8> = text[end..]
+                .iter()
+                .take(3)
+                .take_while(|byte| **byte & 0xc0 == 0x80)
+                .copied()
+                .collect();
+            record.extend_from_slice(&overlap);
+            record.push(overlap.len() as u8);
+            records.push(record);
+            position = end;
+        }
+        let text_records = records.len() - 1;
+        let first_image = records.len();
+        records.extend(images.iter().map(|(_, image)| image.bytes().to_vec()));
+        let last_content = records.len() - 1;
+        records.push(EOF_RECORD.to_vec());
+
+        let mut hasher = DefaultHasher::new();
+        html.hash(&mut hasher);
+        let unique_id = hasher.finish() as u32;
+        records[0] = header_record(
+            &title,
+            &document.metadata,
+            text.len(),
+            text_records,
+            unique_id,
+            (!images.is_empty()).then_some(first_image),
+            last_content,
+        );
+        Ok(Bytes::from(database(&title, &records)))
+    }
+}
+
+fn push_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+fn push_u32(buffer: &mut Vec<u8>, value: u32) {
+    buffer.extend_from_slice(&value.to_be_bytes());
+}
+
+/// The Palm database around the records
+fn database(title: &str, records: &[Vec<u8>]) -> Vec<u8> {
+    let mut name: Vec<u8> = title
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c as u8
+            } else {
+                b'_'
+            }
+        })
+        .take(31)
+        .collect();
+    name.resize(32, 0);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as u32);
+
+    let mut pdb = name;
+    push_u16(&mut pdb, 0); // attributes
+    push_u16(&mut pdb, 0); // version
+    push_u32(&mut pdb, now); // creation
+    push_u32(&mut pdb, now); // modification
+    push_u32(&mut pdb, 0); // backup
+    push_u32(&mut pdb, 0); // modification number
+    push_u32(&mut pdb, 0); // app info
+    push_u32(&mut pdb, 0); // sort info
+    pdb.extend_from_slice(b"BOOKMOBI");
+    push_u32(&mut pdb, (records.len() * 2).saturating_sub(1) as u32); // unique id seed
+    push_u32(&mut pdb, 0); // next record list
+    push_u16(&mut pdb, records.len() as u16);
+
+    let mut offset = pdb.len() + records.len() * 8 + 2;
+    for (index, record) in records.iter().enumerate() {
+        push_u32(&mut pdb, offset as u32);
+        push_u32(&mut pdb, (index * 2) as u32 & 0x00ff_ffff);
+        offset += record.len();
+    }
+    push_u16(&mut pdb, 0);
+    for record in records {
+        pdb.extend_from_slice(record);
+    }
+    pdb
+}
+
+/// Record 0: the PalmDOC header, the MOBI header, the EXTH metadata and the full title
+fn header_record(
+    title: &str,
+    metadata: &Metadata,
+    text_length: usize,
+    text_records: usize,
+    unique_id: u32,
+    first_image: Option<usize>,
+    last_content: usize,
+) -> Vec<u8> {
+    const MOBI_HEADER_LENGTH: u32 = 0xe8;
+
+    // Authors, subject, publishing date, title and language
+    let mut properties: Vec<(u32, &str)> = metadata
+        .authors
+        .iter()
+        .map(|author| (100, author.as_str()))
+        .collect();
+    properties.extend(metadata.subject.as_deref().map(|subject| (105, subject)));
+    properties.extend(metadata.created.as_deref().map(|created| (106, created)));
+    properties.push((503, title));
+    properties.push((524, metadata.language.as_deref().unwrap_or("en")));
+    let mut exth_records: Vec<u8> = Vec::new();
+    for (kind, value) in &properties {
+        push_u32(&mut exth_records, *kind);
+        push_u32(&mut exth_records, 8 + value.len() as u32);
+        exth_records.extend_from_slice(value.as_bytes());
+    }
+    let mut exth = b"EXTH".to_vec();
+    push_u32(&mut exth, 12 + exth_records.len() as u32);
+    push_u32(&mut exth, properties.len() as u32);
+    exth.extend(exth_records);
+    exth.resize(exth.len().div_ceil(4) * 4, 0);
+
+    let mut record = Vec::new();
+    push_u16(&mut record, 2); // PalmDOC compression
+    push_u16(&mut record, 0);
+    push_u32(&mut record, text_length as u32);
+    push_u16(&mut record, text_records as u16);
+    push_u16(&mut record, RECORD_SIZE as u16);
+    push_u16(&mut record, 0); // no encryption
+    push_u16(&mut record, 0);
+
+    let full_name_offset = 16 + MOBI_HEADER_LENGTH as usize + exth.len();
+    record.extend_from_slice(b"MOBI");
+    push_u32(&mut record, MOBI_HEADER_LENGTH);
+    push_u32(&mut record, 2); // book
+    push_u32(&mut record, 65001); // UTF-8
+    push_u32(&mut record, unique_id);
+    push_u32(&mut record, 6); // file version
+    for _ in 0..10 {
+        push_u32(&mut record, NO_INDEX); // dictionary and extra indexes
+    }
+    push_u32(&mut record, text_records as u32 + 1); // first non-book record
+    push_u32(&mut record, full_name_offset as u32);
+    push_u32(&mut record, title.len() as u32);
+    push_u32(&mut record, 9); // English
+    push_u32(&mut record, 0); // input language
+    push_u32(&mut record, 0); // output language
+    push_u32(&mut record, 6); // minimum reader version
+    push_u32(
+        &mut record,
+        first_image.map_or(NO_INDEX, |index| index as u32),
+    );
+    for _ in 0..4 {
+        push_u32(&mut record, 0); // huffman records
+    }
+    push_u32(&mut record, 0x40); // EXTH present
+    record.extend_from_slice(&[0; 32]);
+    push_u32(&mut record, NO_INDEX);
+    push_u32(&mut record, NO_INDEX); // DRM offset
+    push_u32(&mut record, 0); // DRM count
+    push_u32(&mut record, 0); // DRM size
+    push_u32(&mut record, 0); // DRM flags
+    record.extend_from_slice(&[0; 8]);
+    push_u16(&mut record, 1); // first content record
+    push_u16(&mut record, last_content as u16);
+    push_u32(&mut record, 1);
+    push_u32(&mut record, NO_INDEX); // FCIS
+    push_u32(&mut record, 1);
+    push_u32(&mut record, NO_INDEX); // FLIS
+    push_u32(&mut record, 1);
+    record.extend_from_slice(&[0; 8]);
+    push_u32(&mut record, NO_INDEX);
+    push_u32(&mut record, 0);
+    push_u32(&mut record, NO_INDEX);
+    push_u32(&mut record, NO_INDEX);
+    push_u32(&mut record, 1); // text records end with multibyte overlap entries
+    push_u32(&mut record, NO_INDEX); // INDX
+
+    record.extend(exth);
+    record.extend_from_slice(title.as_bytes());
+    record.resize((record.len() + 2).div_ceil(4) * 4, 0);
+    record
+}
+
+/// PalmDOC compression: back references of 3 to 10 bytes up to 2047 bytes back, a space
+/// merged with the next character and escaped runs of bytes that would read as codes
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut index = 0;
+    while index < data.len() {
+        if index > 10 && data.len() - index > 10 {
+            let start = index.saturating_sub(2047);
+            let mut best = (0, 0);
+            for candidate in (start..index).rev() {
+                let length = data[candidate..index]
+                    .iter()
+                    .zip(&data[index..])
+                    .take(10)
+                    .take_while(|(left, right)| left == right)
+                    .count();
+                if length > best.1 {
+                    best = (index - candidate, length);
+                    if length == 10 {
+                        break;
+                    }
+                }
+            }
+            if best.1 >= 3 {
+                let code = 0x8000 | (best.0 << 3) as u16 | (best.1 - 3) as u16;
+                output.extend_from_slice(&code.to_be_bytes());
+                index += best.1;
+                continue;
+            }
+        }
+
+        let byte = data[index];
+        index += 1;
+        if byte == b' '
+            && data
+                .get(index)
+                .is_some_and(|next| (0x40..0x80).contains(next))
+        {
+            output.push(data[index] ^ 0x80);
+            index += 1;
+        } else if byte == 0 || (0x09..0x80).contains(&byte) {
+            output.push(byte);
+        } else {
+            let run_end = data[index - 1..]
+                .iter()
+                .take(8)
+                .position(|byte| *byte == 0 || (0x09..0x80).contains(byte))
+                .map_or((index - 1 + 8).min(data.len()), |length| index - 1 + length);
+            output.push((run_end - (index - 1)) as u8);
+            output.extend_from_slice(&data[index - 1..run_end]);
+            index = run_end;
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mobi::*;
+
+    fn decompress(data: &[u8]) -> Vec<u8> {
+        let mut output: Vec<u8> = Vec::new();
+        let mut index = 0;
+        while index < data.len() {
+            let byte = data[index];
+            index += 1;
+            match byte {
+                0x01..=0x08 => {
+                    output.extend_from_slice(&data[index..index + byte as usize]);
+                    index += byte as usize;
+                }
+                0x80..=0xbf => {
+                    let code = u16::from_be_bytes([byte, data[index]]) & 0x3fff;
+                    index += 1;
+                    let distance = (code >> 3) as usize;
+                    for _ in 0..(code & 7) + 3 {
+                        output.push(output[output.len() - distance]);
+                    }
+                }
+                0xc0..=0xff => output.extend_from_slice(&[b' ', byte ^ 0x80]),
+                byte => output.push(byte),
+            }
+        }
+        output
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> usize {
+        u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    #[test]
+    fn test_compress() {
+        let text =
+            "<p>Ünïcode text, text, text and more text repeated</p>\x01\x02\u{1F600}".repeat(20);
+        let compressed = compress(text.as_bytes());
+        assert!(compressed.len() < text.len() / 2);
+        assert_eq!(decompress(&compressed), text.as_bytes());
+    }
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let mut elements = vec![Element::Header {
+            level: 1,
+            text: "Bücher".to_string(),
+            anchor: None,
+        }];
+        for index in 0..300 {
+            elements.push(Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: format!("Paragraph {} — äöü", index),
+                    font: FontSpec::from(8),
+                }],
+            });
+        }
+        elements.push(Element::Header {
+            level: 1,
+            text: "Second".to_string(),
+            anchor: None,
+        });
+        elements.push(Element::Image(ImageData::new(
+            Bytes::from_static(b"\xff\xd8\xff\xd9"),
+            "Cover".to_string(),
+            "Cover".to_string(),
+            "jpeg".to_string(),
+            "center".to_string(),
+            ImageDimension::default(),
+        )));
+        let generated = Transformer::generate(&Document::new(elements))?;
+        assert_eq!(&generated[60..68], b"BOOKMOBI");
+
+        let count = u16::from_be_bytes([generated[76], generated[77]]) as usize;
+        let offsets: Vec<usize> = (0..count)
+            .map(|index| u32_at(&generated, 78 + index * 8))
+            .chain([generated.len()])
+            .collect();
+        let record = |index: usize| &generated[offsets[index]..offsets[index + 1]];
+
+        let header = record(0);
+        assert_eq!(&header[16..20], b"MOBI");
+        let text_length = u32_at(header, 4);
+        let text_records = u16::from_be_bytes([header[8], header[9]]) as usize;
+        assert!(text_records > 1);
+        let title_offset = u32_at(header, 16 + 0x44);
+        let title_length = u32_at(header, 16 + 0x48);
+        assert_eq!(
+            &header[title_offset..title_offset + title_length],
+            "Bücher".as_bytes()
+        );
+
+        let mut text = Vec::new();
+        for index in 1..=text_records {
+            let data = record(index);
+            let overlap = (data[data.len() - 1] & 3) as usize;
+            text.extend(decompress(&data[..data.len() - 1 - overlap]));
+        }
+        assert_eq!(text.len(), text_length);
+        let html = String::from_utf8(text)?;
+        assert!(html.contains("<p>Paragraph 299 — äöü</p>"));
+        assert!(html.contains("<mbp:pagebreak/>\n<h1>Second</h1>"));
+        assert!(html.contains("<img recindex=\"00001\" alt=\"Cover\"/>"));
+
+        let first_image = u32_at(header, 16 + 0x5c);
+        assert_eq!(record(first_image), b"\xff\xd8\xff\xd9");
+        assert_eq!(record(count - 1), EOF_RECORD);
+        Ok(())
+    }
+}
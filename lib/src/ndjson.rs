@@ -0,0 +1,71 @@
+use crate::core::*;
+use crate::json::{parse_element, serialize_element};
+use bytes::Bytes;
+use serde_json::Value;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    /// Reads one element per line in the representation of the JSON format's `elements`,
+    /// blank lines are skipped.
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        let mut elements = Vec::new();
+        for (index, line) in document.split(|byte| *byte == b'\n').enumerate() {
+            if line.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            let element = serde_json::from_slice::<Value>(line)
+                .map_err(anyhow::Error::from)
+                .and_then(|value| parse_element(&value))
+                .map_err(|error| {
+                    ParserError::Malformed(format!("line {}: {}", index + 1, error))
+                })?;
+            elements.push(element);
+        }
+        Ok(Document::new(elements))
+    }
+
+    /// Writes every element on its own line, page headers and footers are not included.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        let mut ndjson = Vec::new();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                serde_json::to_writer(&mut ndjson, &serialize_element(element))?;
+                ndjson.push(b'\n');
+            }
+        }
+        Ok(Bytes::from(ndjson))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ndjson::*;
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let generated = Transformer::generate(&parsed)?;
+        assert_eq!(
+            generated.split(|byte| *byte == b'\n').count() - 1,
+            parsed.get_all_elements().len()
+        );
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), parsed.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_error_line() {
+        let document = Bytes::from("{\"type\":\"LineBreak\"}\n\n{\"type\":\"Text\"\n");
+        let error = Transformer::parse(&document).unwrap_err();
+        assert!(error.to_string().contains("line 3"), "{}", error);
+    }
+}
@@ -0,0 +1,427 @@
+use crate::core::*;
+use crate::odt;
+use crate::parse_util::{parse_xml, read_entry, Names, XmlNode};
+use crate::slides::{split_slides, Slide};
+use bytes::Bytes;
+use std::collections::HashSet;
+use std::io::{Cursor, Write};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    /// Every `draw:page` yields a level 1 header from its title frame followed by the
+    /// content of its text boxes, tables and images. Speaker notes are skipped.
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        let mut archive = ZipArchive::new(Cursor::new(document.to_vec()))?;
+        let content = parse_xml(&read_entry(&mut archive, "content.xml")?, Names::Qualified)?;
+
+        let mut numbered_styles = HashSet::new();
+        odt::collect_numbered_list_styles(&content, &mut numbered_styles);
+        if let Ok(styles) = read_entry(&mut archive, "styles.xml") {
+            let styles = parse_xml(&styles, Names::Qualified)?;
+            odt::collect_numbered_list_styles(&styles, &mut numbered_styles);
+        }
+
+        let presentation = content
+            .find("office:body")
+            .and_then(|body| body.find("office:presentation"))
+            .ok_or_else(|| {
+                ParserError::Malformed("content.xml has no presentation body".to_string())
+            })?;
+
+        let mut parser = odt::Parser {
+            archive,
+            numbered_styles,
+        };
+        let mut elements = Vec::new();
+        for page in presentation.nodes().filter(|page| page.name == "draw:page") {
+            parse_page(&mut parser, page, &mut elements)?;
+        }
+        let mut parsed = Document::new(elements);
+        parsed.metadata = odt::read_metadata(&mut parser.archive)?;
+        Ok(parsed)
+    }
+
+    /// Slides are split the same way as for PPTX. Text, lists and deeper headers share a
+    /// text box, tables and images get their own frames stacked below it.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        let mut generator = odt::Generator::default();
+        let mut pages = String::new();
+        for (index, slide) in split_slides(document).iter().enumerate() {
+            generate_page(&mut pages, index + 1, slide, &mut generator);
+        }
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        // The mimetype entry must come first and must not be compressed
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(MIMETYPE.as_bytes())?;
+
+        zip.start_file("META-INF/manifest.xml", deflated)?;
+        zip.write_all(odt::manifest(MIMETYPE, &generator.images).as_bytes())?;
+
+        zip.start_file("content.xml", deflated)?;
+        zip.write_all(content_document(&pages).as_bytes())?;
+
+        zip.start_file("styles.xml", deflated)?;
+        zip.write_all(styles_document().as_bytes())?;
+
+        zip.start_file("meta.xml", deflated)?;
+        zip.write_all(odt::meta_document(&document.metadata).as_bytes())?;
+
+        for (path, image) in &generator.images {
+            zip.start_file(path.as_str(), stored)?;
+            zip.write_all(image.bytes())?;
+        }
+
+        let cursor = zip.finish()?;
+        Ok(Bytes::from(cursor.into_inner()))
+    }
+}
+
+const MIMETYPE: &str = "application/vnd.oasis.opendocument.presentation";
+
+const PRESENTATION_NAMESPACE: &str =
+    r#"xmlns:presentation="urn:oasis:names:tc:opendocument:xmlns:presentation:1.0""#;
+
+/// 16:9 slide in centimeters
+const SLIDE_WIDTH: f32 = 28.0;
+const SLIDE_HEIGHT: f32 = 15.75;
+const MARGIN: f32 = 1.27;
+const TITLE_TOP: f32 = 0.63;
+const TITLE_HEIGHT: f32 = 2.54;
+const CONTENT_TOP: f32 = 3.81;
+const CONTENT_WIDTH: f32 = SLIDE_WIDTH - 2.0 * MARGIN;
+const CONTENT_BOTTOM: f32 = SLIDE_HEIGHT - MARGIN;
+const GAP: f32 = 0.4;
+
+/// Rough text metrics at the 18pt body size, used to stack frames
+const TEXT_SIZE: u32 = 18;
+const LINE_HEIGHT: f32 = 0.8;
+const CHARACTERS_PER_LINE: usize = 70;
+const TABLE_ROW_HEIGHT: f32 = 0.9;
+
+/// Presentation classes of frames that repeat on every slide or belong to the notes view
+const SKIPPED_CLASSES: [&str; 5] = ["header", "footer", "date-time", "page-number", "notes"];
+
+fn parse_page(
+    parser: &mut odt::Parser,
+    page: &XmlNode,
+    elements: &mut Vec<Element>,
+) -> anyhow::Result<()> {
+    let mut shapes = Vec::new();
+    collect_shapes(page, &mut shapes);
+
+    let title = shapes
+        .iter()
+        .position(|shape| shape.attribute("presentation:class") == Some("title"));
+    if let Some(index) = title {
+        let text = odt::collapse_whitespace(&odt::text_content(shapes.remove(index)))
+            .trim()
+            .to_string();
+        if !text.is_empty() {
+            elements.push(Element::Header {
+                level: 1,
+                text,
+                anchor: None,
+            });
+        }
+    }
+
+    for shape in shapes {
+        if shape
+            .attribute("presentation:class")
+            .is_some_and(|class| SKIPPED_CLASSES.contains(&class))
+        {
+            continue;
+        }
+        if shape.name != "draw:frame" {
+            // Custom shapes and rectangles hold their paragraphs directly
+            parser.parse_blocks(shape, elements)?;
+        } else if let Some(text_box) = shape.find("draw:text-box") {
+            parser.parse_blocks(text_box, elements)?;
+        } else if let Some(table) = shape.find("table:table") {
+            parser.parse_block(table, elements)?;
+        } else {
+            parser.parse_block(shape, elements)?;
+        }
+    }
+    Ok(())
+}
+
+/// Shapes in document order, groups are flattened
+fn collect_shapes<'a>(node: &'a XmlNode, shapes: &mut Vec<&'a XmlNode>) {
+    for child in node.nodes() {
+        match child.name.as_str() {
+            "draw:frame" | "draw:custom-shape" | "draw:rect" => shapes.push(child),
+            "draw:g" => collect_shapes(child, shapes),
+            _ => {}
+        }
+    }
+}
+
+fn generate_page<'a>(
+    xml: &mut String,
+    number: usize,
+    slide: &Slide<'a>,
+    generator: &mut odt::Generator<'a>,
+) {
+    xml.push_str(&format!(
+        "<draw:page draw:name=\"page{}\" draw:master-page-name=\"Default\">",
+        number
+    ));
+    if !slide.title.is_empty() {
+        xml.push_str(&frame(
+            Some("title"),
+            TITLE_TOP,
+            TITLE_HEIGHT,
+            &format!(
+                "<draw:text-box><text:p text:style-name=\"Title\">{}</text:p></draw:text-box>",
+                odt::escape(&slide.title)
+            ),
+        ));
+    }
+
+    let mut top = CONTENT_TOP;
+    let mut text: Vec<&Element> = Vec::new();
+    for element in &slide.content {
+        match element {
+            Element::Table { headers, rows, .. } => {
+                top = text_box(xml, &mut text, top, generator);
+                let mut table = String::new();
+                odt::generate_block(&mut table, element, generator);
+                let height =
+                    (rows.len() + usize::from(!headers.is_empty())) as f32 * TABLE_ROW_HEIGHT;
+                xml.push_str(&frame(None, top, height, &table));
+                top += height + GAP;
+            }
+            Element::Image(image) if !image.bytes().is_empty() => {
+                top = text_box(xml, &mut text, top, generator);
+                top = picture(xml, image, top, generator);
+            }
+            element => text.push(element),
+        }
+    }
+    text_box(xml, &mut text, top, generator);
+    xml.push_str("</draw:page>");
+}
+
+/// Writes the pending text elements into one text box and returns the top of the next frame
+fn text_box<'a>(
+    xml: &mut String,
+    text: &mut Vec<&'a Element>,
+    top: f32,
+    generator: &mut odt::Generator<'a>,
+) -> f32 {
+    if text.is_empty() {
+        return top;
+    }
+    let mut content = String::from("<draw:text-box>");
+    let mut lines = 0;
+    for element in text.drain(..) {
+        lines += line_count(element);
+        odt::generate_block(&mut content, element, generator);
+    }
+    content.push_str("</draw:text-box>");
+    let height = lines.max(1) as f32 * LINE_HEIGHT;
+    xml.push_str(&frame(Some("outline"), top, height, &content));
+    top + height + GAP
+}
+
+fn picture<'a>(
+    xml: &mut String,
+    image: &'a ImageData,
+    top: f32,
+    generator: &mut odt::Generator<'a>,
+) -> f32 {
+    let (width, height) = odt::image_size(image);
+    let (width, height) = match (centimeters(&width), centimeters(&height)) {
+        (Some(width), Some(height)) if width > 0.0 && height > 0.0 => (width, height),
+        _ => (8.0, 6.0),
+    };
+    // Shrink to the space left on the slide, keeping the aspect ratio
+    let available = (CONTENT_BOTTOM - top).max(2.0);
+    let scale = (CONTENT_WIDTH / width).min(available / height).min(1.0);
+    let (width, height) = (width * scale, height * scale);
+
+    let number = generator.images.len() + 1;
+    let path = format!(
+        "Pictures/image{}{}",
+        number,
+        image.image_type().to_extension()
+    );
+    xml.push_str(&format!(
+        "<draw:frame draw:name=\"Image{}\" svg:x=\"{:.3}cm\" svg:y=\"{:.3}cm\" svg:width=\"{:.3}cm\" svg:height=\"{:.3}cm\">",
+        number, MARGIN, top, width, height
+    ));
+    xml.push_str(&format!(
+        "<draw:image xlink:href=\"{}\" xlink:type=\"simple\" xlink:show=\"embed\" xlink:actuate=\"onLoad\"/>",
+        path
+    ));
+    if !image.title().is_empty() {
+        xml.push_str(&format!(
+            "<svg:title>{}</svg:title>",
+            odt::escape(image.title())
+        ));
+    }
+    if !image.alt().is_empty() {
+        xml.push_str(&format!(
+            "<svg:desc>{}</svg:desc>",
+            odt::escape(image.alt())
+        ));
+    }
+    xml.push_str("</draw:frame>");
+    generator.images.push((path, image));
+    top + height + GAP
+}
+
+fn frame(class: Option<&str>, top: f32, height: f32, content: &str) -> String {
+    let class = class
+        .map(|class| format!(" presentation:class=\"{}\"", class))
+        .unwrap_or_default();
+    format!(
+        "<draw:frame{} svg:x=\"{:.3}cm\" svg:y=\"{:.3}cm\" svg:width=\"{:.3}cm\" svg:height=\"{:.3}cm\">{}</draw:frame>",
+        class, MARGIN, top, CONTENT_WIDTH, height, content
+    )
+}
+
+/// Estimated number of lines an element takes in a text box
+fn line_count(element: &Element) -> usize {
+    match element {
+        Element::List { elements, .. } => {
+            elements.iter().map(|item| line_count(&item.element)).sum()
+        }
+        Element::Paragraph { elements } => {
+            let breaks = elements
+                .iter()
+                .filter(|element| matches!(element, Element::LineBreak))
+                .count();
+            let length: usize = elements.iter().map(text_length).sum();
+            1 + breaks + length / CHARACTERS_PER_LINE
+        }
+        element => 1 + text_length(element) / CHARACTERS_PER_LINE,
+    }
+}
+
+fn text_length(element: &Element) -> usize {
+    match element {
+        Element::Text { text, .. } | Element::Header { text, .. } => text.chars().count(),
+        Element::Hyperlink { title, .. } => title.chars().count(),
+        Element::Paragraph { elements } => elements.iter().map(text_length).sum(),
+        _ => 0,
+    }
+}
+
+/// Converts an ODF length to centimeters
+fn centimeters(length: &str) -> Option<f32> {
+    const UNITS: [(&str, f32); 5] = [
+        ("cm", 1.0),
+        ("mm", 0.1),
+        ("in", 2.54),
+        ("pt", 2.54 / 72.0),
+        ("pc", 2.54 / 6.0),
+    ];
+    UNITS.iter().find_map(|(unit, factor)| {
+        length
+            .strip_suffix(unit)
+            .and_then(|value| value.trim().parse::<f32>().ok())
+            .map(|value| value * factor)
+    })
+}
+
+fn content_document(pages: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content {} {}><office:automatic-styles/><office:body><office:presentation>{}</office:presentation></office:body></office:document-content>
+"#,
+        PRESENTATION_NAMESPACE,
+        odt::NAMESPACES,
+        pages
+    )
+}
+
+fn styles_document() -> String {
+    let mut styles = odt::paragraph_styles(TEXT_SIZE);
+    styles.push_str(r#"<style:default-style style:family="graphic"><style:graphic-properties draw:stroke="none" draw:fill="none" draw:textarea-vertical-align="top"/></style:default-style>"#);
+    styles.push_str(r#"<style:style style:name="Title" style:family="paragraph" style:parent-style-name="Standard"><style:text-properties fo:font-size="32pt" fo:font-weight="bold"/></style:style>"#);
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-styles {namespace} {namespaces}><office:styles>{styles}</office:styles><office:automatic-styles><style:page-layout style:name="PM1"><style:page-layout-properties fo:margin-top="0cm" fo:margin-bottom="0cm" fo:margin-left="0cm" fo:margin-right="0cm" fo:page-width="{width}cm" fo:page-height="{height}cm" style:print-orientation="landscape"/></style:page-layout></office:automatic-styles><office:master-styles><style:master-page style:name="Default" style:page-layout-name="PM1"/></office:master-styles></office:document-styles>
+"#,
+        namespace = PRESENTATION_NAMESPACE,
+        namespaces = odt::NAMESPACES,
+        styles = styles,
+        width = SLIDE_WIDTH,
+        height = SLIDE_HEIGHT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::*;
+    use crate::markdown;
+    use crate::odp::*;
+    use std::io::Read;
+
+    fn document() -> anyhow::Result<Document> {
+        let document = std::fs::read("test/data/document.md")?;
+        markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )
+    }
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let generated = Transformer::generate(&document()?)?;
+        let mut archive = ZipArchive::new(Cursor::new(generated))?;
+
+        let mut mimetype = String::new();
+        archive.by_index(0)?.read_to_string(&mut mimetype)?;
+        assert_eq!(mimetype, MIMETYPE);
+
+        let mut content = String::new();
+        archive
+            .by_name("content.xml")?
+            .read_to_string(&mut content)?;
+        assert_eq!(content.matches("<draw:page ").count(), 2);
+        assert!(content.contains("<text:p text:style-name=\"Title\">Second header</text:p>"));
+        assert!(content.contains("<draw:image xlink:href=\"Pictures/image1.png\""));
+        assert!(archive.by_name("Pictures/image1.png").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let generated = Transformer::generate(&document()?)?;
+        let parsed = Transformer::parse(&generated)?;
+        let elements = parsed.get_all_elements();
+
+        let headers: Vec<&str> = elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Header { level: 1, text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(headers, vec!["First header", "Second header"]);
+        assert!(elements
+            .iter()
+            .any(|element| matches!(element, Element::List { numbered: true, .. })));
+        let table = elements.iter().find_map(|element| match element {
+            Element::Table { headers, rows, .. } => Some((headers.len(), rows.len())),
+            _ => None,
+        });
+        assert_eq!(table, Some((8, 3)));
+        let image = elements.iter().find_map(|element| match element {
+            Element::Image(image) => Some(image),
+            _ => None,
+        });
+        assert_eq!(image.expect("image").image_type(), &ImageType::Png);
+        Ok(())
+    }
+}
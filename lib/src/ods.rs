@@ -36,7 +36,7 @@ impl TransformerTrait for Transformer {
                                 .map(|header| TableHeader {
                                     element: Text {
                                         text: header.to_string(),
-                                        size: 8,
+                                        font: FontSpec::from(8),
                                     },
                                     width: 10.0,
                                 })
@@ -45,11 +45,11 @@ impl TransformerTrait for Transformer {
                         } else {
                             let cells = row
                                 .iter()
-                                .map(|header| TableCell {
-                                    element: Text {
+                                .map(|header| {
+                                    TableCell::new(Text {
                                         text: header.to_string(),
-                                        size: 8,
-                                    },
+                                        font: FontSpec::from(8),
+                                    })
                                 })
                                 .collect();
                             table_rows.push(TableRow { cells });
@@ -58,6 +58,8 @@ impl TransformerTrait for Transformer {
                     data.push(Table {
                         headers: table_headers.clone(),
                         rows: table_rows.clone(),
+                        alignments: vec![],
+                        caption: vec![],
                     });
                 }
                 Err(err) => {
@@ -80,7 +82,7 @@ impl TransformerTrait for Transformer {
             sheet_index: i32,
         ) -> anyhow::Result<()> {
             match element {
-                Table { headers, rows } => {
+                Table { headers, rows, .. } => {
                     let mut worksheet = Sheet::new("Sheet".to_string() + &sheet_index.to_string());
                     let mut row_index = 1;
                     let mut col_index = 0;
@@ -94,7 +96,7 @@ impl TransformerTrait for Transformer {
                     for row in rows {
                         let mut col_index = 0;
                         for (_cell_index, cell) in row.cells.iter().enumerate() {
-                            if let Text { text, .. } = cell.element.clone() {
+                            if let Text { text, .. } = cell.element().clone() {
                                 worksheet.set_value(row_index, col_index, text);
                                 col_index += 1;
                             }
@@ -107,6 +109,7 @@ impl TransformerTrait for Transformer {
             }
             Ok(())
         }
+        let document = document.flatten_sections();
         let mut sheet_index = 1;
         for element in &document.get_all_elements() {
             generate_element(element, &mut workbook, sheet_index)?;
@@ -0,0 +1,1383 @@
+use crate::core::*;
+use crate::parse_util::{
+    collapse_whitespace_with, parse_xml, read_entry, unwrap_paragraph, Names, XmlChild, XmlNode,
+};
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Write};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        let mut archive = ZipArchive::new(Cursor::new(document.to_vec()))?;
+        let content = parse_xml(&read_entry(&mut archive, "content.xml")?, Names::Qualified)?;
+        // styles.xml is optional, flat documents without it still carry the body
+        let styles = match read_entry(&mut archive, "styles.xml") {
+            Ok(styles) => Some(parse_xml(&styles, Names::Qualified)?),
+            Err(_) => None,
+        };
+
+        let mut numbered_styles = HashSet::new();
+        collect_numbered_list_styles(&content, &mut numbered_styles);
+        if let Some(styles) = &styles {
+            collect_numbered_list_styles(styles, &mut numbered_styles);
+        }
+
+        let mut parser = Parser {
+            archive,
+            numbered_styles,
+        };
+
+        let body = content
+            .find("office:body")
+            .and_then(|body| body.find("office:text"))
+            .ok_or_else(|| ParserError::Malformed("content.xml has no text body".to_string()))?;
+        let mut elements = Vec::new();
+        parser.parse_blocks(body, &mut elements)?;
+
+        let mut page_header = Vec::new();
+        let mut page_footer = Vec::new();
+        if let Some(master_page) = styles
+            .as_ref()
+            .and_then(|styles| styles.find("office:master-styles"))
+            .and_then(|master_styles| master_styles.find("style:master-page"))
+        {
+            if let Some(header) = master_page.find("style:header") {
+                parser.parse_blocks(header, &mut page_header)?;
+            }
+            if let Some(footer) = master_page.find("style:footer") {
+                parser.parse_blocks(footer, &mut page_footer)?;
+            }
+        }
+
+        let mut bands = Vec::new();
+        if !page_header.is_empty() {
+            bands.push(Band::PageHeader(page_header));
+        }
+        bands.push(Band::Detail(elements));
+        if !page_footer.is_empty() {
+            bands.push(Band::PageFooter(page_footer));
+        }
+        Ok(Document {
+            bands,
+            page_format: PageFormat::default(),
+            orientation: PageOrientation::default(),
+            direction: TextDirection::default(),
+            metadata: read_metadata(&mut parser.archive)?,
+            source_spans: Default::default(),
+        })
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        let mut generator = Generator {
+            footnotes: document.footnotes(),
+            toc: document.toc_entries(6),
+            ..Generator::default()
+        };
+
+        let mut body = String::new();
+        let mut header = String::new();
+        let mut footer = String::new();
+        for band in &document.bands {
+            let xml = match band {
+                Band::PageHeader(_) => &mut header,
+                Band::PageFooter(_) => &mut footer,
+                _ => &mut body,
+            };
+            for element in band.elements() {
+                generate_block(xml, element, &mut generator);
+            }
+        }
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        // The mimetype entry must come first and must not be compressed
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(MIMETYPE.as_bytes())?;
+
+        zip.start_file("META-INF/manifest.xml", deflated)?;
+        zip.write_all(manifest(MIMETYPE, &generator.images).as_bytes())?;
+
+        zip.start_file("content.xml", deflated)?;
+        zip.write_all(content_document(&body).as_bytes())?;
+
+        zip.start_file("styles.xml", deflated)?;
+        zip.write_all(styles_document(document, &header, &footer).as_bytes())?;
+
+        zip.start_file("meta.xml", deflated)?;
+        zip.write_all(meta_document(&document.metadata).as_bytes())?;
+
+        for (path, image) in &generator.images {
+            zip.start_file(path.as_str(), stored)?;
+            zip.write_all(image.bytes())?;
+        }
+
+        let cursor = zip.finish()?;
+        Ok(Bytes::from(cursor.into_inner()))
+    }
+}
+
+const MIMETYPE: &str = "application/vnd.oasis.opendocument.text";
+
+pub(crate) const NAMESPACES: &str = concat!(
+    r#"xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" "#,
+    r#"xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" "#,
+    r#"xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" "#,
+    r#"xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" "#,
+    r#"xmlns:draw="urn:oasis:names:tc:opendocument:xmlns:drawing:1.0" "#,
+    r#"xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0" "#,
+    r#"xmlns:svg="urn:oasis:names:tc:opendocument:xmlns:svg-compatible:1.0" "#,
+    r#"xmlns:xlink="http://www.w3.org/1999/xlink" "#,
+    r#"xmlns:dc="http://purl.org/dc/elements/1.1/" "#,
+    r#"office:version="1.3""#
+);
+
+/// Default frame size for images without dimensions or a readable header
+const DEFAULT_IMAGE_WIDTH_CM: f32 = 8.0;
+const DEFAULT_IMAGE_HEIGHT_CM: f32 = 6.0;
+const MAX_IMAGE_WIDTH_CM: f32 = 16.0;
+
+/// Text content with the spaces, tabs and line breaks of ODF markup, without annotations and
+/// notes
+pub(crate) fn text_content(node: &XmlNode) -> String {
+    node.text_with(&|child| match child {
+        XmlChild::Node(node) => match node.name.as_str() {
+            "text:s" => Some(PRESERVED_SPACE.to_string().repeat(spaces(node))),
+            "text:tab" => Some("\t".to_string()),
+            "text:line-break" => Some("\n".to_string()),
+            "office:annotation" | "text:note" => Some(String::new()),
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+fn spaces(node: &XmlNode) -> usize {
+    node.attribute("text:c")
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(1)
+}
+
+/// List styles whose first level is numbered, used to tell `<ol>` from `<ul>` style lists
+pub(crate) fn collect_numbered_list_styles(node: &XmlNode, numbered: &mut HashSet<String>) {
+    for child in node.nodes() {
+        if child.name == "text:list-style" {
+            let first_level = child
+                .nodes()
+                .find(|level| level.attribute("text:level").unwrap_or("1") == "1");
+            if let (Some(name), Some(level)) = (child.attribute("style:name"), first_level) {
+                if level.name == "text:list-level-style-number" {
+                    numbered.insert(name.to_string());
+                }
+            }
+        } else {
+            collect_numbered_list_styles(child, numbered);
+        }
+    }
+}
+
+pub(crate) struct Parser {
+    pub archive: ZipArchive<Cursor<Vec<u8>>>,
+    pub numbered_styles: HashSet<String>,
+}
+
+impl Parser {
+    pub(crate) fn parse_blocks(
+        &mut self,
+        node: &XmlNode,
+        elements: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        for child in node.nodes() {
+            self.parse_block(child, elements)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn parse_block(
+        &mut self,
+        node: &XmlNode,
+        elements: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        match node.name.as_str() {
+            "text:h" => {
+                let level = node
+                    .attribute("text:outline-level")
+                    .and_then(|level| level.parse().ok())
+                    .unwrap_or(1);
+                let text = collapse_whitespace(&text_content(node)).trim().to_string();
+                if !text.is_empty() {
+                    elements.push(Element::Header {
+                        level,
+                        text,
+                        anchor: None,
+                    });
+                }
+            }
+            // The index body is generated from the headers, only its depth is kept
+            "text:table-of-content" => {
+                let max_level = node
+                    .nodes()
+                    .find(|child| child.name == "text:table-of-content-source")
+                    .and_then(|source| source.attribute("text:outline-level"))
+                    .and_then(|level| level.parse::<u8>().ok())
+                    .unwrap_or(10);
+                elements.push(Element::TableOfContents {
+                    max_level: max_level.clamp(1, 6),
+                });
+            }
+            "text:p" if node.attribute("text:style-name") == Some(HORIZONTAL_LINE) => {
+                elements.push(Element::HorizontalRule);
+            }
+            "text:p" if node.attribute("text:style-name") == Some(PAGE_BREAK) => {
+                elements.push(Element::PageBreak);
+            }
+            "text:p" if node.attribute("text:style-name") == Some(PREFORMATTED) => {
+                let text = text_content(node);
+                let lines: Vec<String> = text.split('\n').map(collapse_whitespace).collect();
+                elements.push(Element::CodeBlock {
+                    language: None,
+                    code: lines.join("\n"),
+                });
+            }
+            // Consecutive quotation paragraphs belong to one blockquote
+            "text:p" if node.attribute("text:style-name") == Some(QUOTATIONS) => {
+                let mut paragraph = Vec::new();
+                self.parse_inline(node, &mut paragraph)?;
+                trim_paragraph(&mut paragraph);
+                if paragraph.is_empty() {
+                    return Ok(());
+                }
+                let paragraph = Element::Paragraph {
+                    elements: paragraph,
+                };
+                match elements.last_mut() {
+                    Some(Element::Blockquote { elements, .. }) => elements.push(paragraph),
+                    _ => elements.push(Element::Blockquote {
+                        elements: vec![paragraph],
+                        attribution: vec![],
+                    }),
+                }
+            }
+            // Headings and contents of consecutive list paragraphs make up a definition list
+            "text:p" if node.attribute("text:style-name") == Some(LIST_HEADING) => {
+                let mut term = Vec::new();
+                self.parse_inline(node, &mut term)?;
+                trim_paragraph(&mut term);
+                let item = DefinitionItem {
+                    term,
+                    definitions: vec![],
+                };
+                match elements.last_mut() {
+                    Some(Element::DefinitionList { items }) => items.push(item),
+                    _ => elements.push(Element::DefinitionList { items: vec![item] }),
+                }
+            }
+            "text:p" if node.attribute("text:style-name") == Some(LIST_CONTENTS) => {
+                let mut paragraph = Vec::new();
+                self.parse_inline(node, &mut paragraph)?;
+                trim_paragraph(&mut paragraph);
+                let definition = Element::Paragraph {
+                    elements: paragraph,
+                };
+                if !matches!(elements.last(), Some(Element::DefinitionList { .. })) {
+                    elements.push(Element::DefinitionList { items: vec![] });
+                }
+                if let Some(Element::DefinitionList { items }) = elements.last_mut() {
+                    match items.last_mut() {
+                        Some(item) => item.definitions.push(definition),
+                        None => items.push(DefinitionItem {
+                            term: vec![],
+                            definitions: vec![definition],
+                        }),
+                    }
+                }
+            }
+            "text:p" => {
+                let mut paragraph = Vec::new();
+                self.parse_inline(node, &mut paragraph)?;
+                trim_paragraph(&mut paragraph);
+                match paragraph.as_slice() {
+                    [] => {}
+                    // A paragraph holding only a comment or an image anchors it, not wraps it
+                    [Element::Comment { .. }] | [Element::Image(_)] => {
+                        elements.append(&mut paragraph)
+                    }
+                    _ => elements.push(Element::Paragraph {
+                        elements: paragraph,
+                    }),
+                }
+            }
+            "text:list" => {
+                let numbered = node
+                    .attribute("text:style-name")
+                    .map(|name| self.numbered_styles.contains(name))
+                    .unwrap_or(false);
+                elements.push(self.parse_list(node, numbered)?);
+            }
+            "table:table" => elements.push(self.parse_table(node)?),
+            "draw:frame" => {
+                if let Some(image) = self.parse_image(node)? {
+                    elements.push(image);
+                }
+            }
+            "office:annotation" => elements.push(parse_annotation(node)),
+            "text:section" => self.parse_blocks(node, elements)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn parse_list(&mut self, node: &XmlNode, numbered: bool) -> anyhow::Result<Element> {
+        let mut items = Vec::new();
+        for item in node.nodes().filter(|item| item.name == "text:list-item") {
+            let mut item_elements = Vec::new();
+            for child in item.nodes() {
+                if child.name == "text:list" {
+                    // Nested lists without their own style continue the parent numbering scheme
+                    let nested_numbered = child
+                        .attribute("text:style-name")
+                        .map(|name| self.numbered_styles.contains(name))
+                        .unwrap_or(numbered);
+                    item_elements.push(self.parse_list(child, nested_numbered)?);
+                } else {
+                    self.parse_block(child, &mut item_elements)?;
+                }
+            }
+            for element in item_elements {
+                items.push(ListItem {
+                    element: unwrap_paragraph(element),
+                    checked: None,
+                });
+            }
+        }
+        Ok(Element::List {
+            elements: items,
+            numbered,
+        })
+    }
+
+    fn parse_table(&mut self, node: &XmlNode) -> anyhow::Result<Element> {
+        let mut headers = Vec::new();
+        let mut rows = Vec::new();
+        for child in node.nodes() {
+            match child.name.as_str() {
+                "table:table-header-rows" => {
+                    for row in child.nodes().filter(|row| row.name == "table:table-row") {
+                        for cell in self.parse_row(row)? {
+                            headers.push(TableHeader {
+                                element: cell,
+                                width: 30.0,
+                            });
+                        }
+                    }
+                }
+                "table:table-row" => {
+                    let cells = self
+                        .parse_row(child)?
+                        .into_iter()
+                        .map(TableCell::new)
+                        .collect();
+                    rows.push(TableRow { cells });
+                }
+                "table:table-rows" => {
+                    for row in child.nodes().filter(|row| row.name == "table:table-row") {
+                        let cells = self
+                            .parse_row(row)?
+                            .into_iter()
+                            .map(TableCell::new)
+                            .collect();
+                        rows.push(TableRow { cells });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(Element::Table {
+            headers,
+            rows,
+            alignments: vec![],
+            caption: vec![],
+        })
+    }
+
+    fn parse_row(&mut self, row: &XmlNode) -> anyhow::Result<Vec<Element>> {
+        let mut cells = Vec::new();
+        for cell in row.nodes() {
+            if cell.name != "table:table-cell" && cell.name != "table:covered-table-cell" {
+                continue;
+            }
+            let mut cell_elements = Vec::new();
+            self.parse_blocks(cell, &mut cell_elements)?;
+            let element = match cell_elements.len() {
+                0 => Element::Text {
+                    text: String::new(),
+                    font: FontSpec::from(8),
+                },
+                1 => unwrap_paragraph(cell_elements.remove(0)),
+                _ => Element::Paragraph {
+                    elements: cell_elements,
+                },
+            };
+            let repeated = cell
+                .attribute("table:number-columns-repeated")
+                .and_then(|count| count.parse().ok())
+                .unwrap_or(1usize);
+            // Trailing repeated empty cells pad rows to the full sheet width, drop them
+            if repeated > 1
+                && element
+                    == (Element::Text {
+                        text: String::new(),
+                        font: FontSpec::from(8),
+                    })
+            {
+                continue;
+            }
+            for _ in 1..repeated {
+                cells.push(element.clone());
+            }
+            cells.push(element);
+        }
+        Ok(cells)
+    }
+
+    fn parse_inline(&mut self, node: &XmlNode, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        for child in &node.children {
+            let child = match child {
+                XmlChild::Text(text) => {
+                    push_text(elements, text);
+                    continue;
+                }
+                XmlChild::Node(child) => child,
+                XmlChild::Comment(_) | XmlChild::Instruction(_) => continue,
+            };
+            match child.name.as_str() {
+                "text:s" => push_text(elements, &PRESERVED_SPACE.to_string().repeat(spaces(child))),
+                "text:tab" => push_text(elements, "\t"),
+                "text:line-break" => elements.push(Element::LineBreak),
+                "text:a" => elements.push(Element::Hyperlink {
+                    title: collapse_whitespace(&text_content(child)).trim().to_string(),
+                    url: child
+                        .attribute("xlink:href")
+                        .unwrap_or_default()
+                        .to_string(),
+                    alt: child
+                        .attribute("office:title")
+                        .unwrap_or_default()
+                        .to_string(),
+                    size: 8,
+                }),
+                "draw:frame" => {
+                    if let Some(image) = self.parse_image(child)? {
+                        elements.push(image);
+                    }
+                }
+                "office:annotation" => elements.push(parse_annotation(child)),
+                "text:ruby" => {
+                    let text = |name: &str| {
+                        child
+                            .find(name)
+                            .map(|part| collapse_whitespace(&text_content(part)).trim().to_string())
+                            .unwrap_or_default()
+                    };
+                    elements.push(Element::Ruby {
+                        base: text("text:ruby-base"),
+                        annotation: text("text:ruby-text"),
+                    })
+                }
+                "text:note" | "office:annotation-end" | "text:bookmark" => {}
+                _ => self.parse_inline(child, elements)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_image(&mut self, frame: &XmlNode) -> anyhow::Result<Option<Element>> {
+        let Some(image) = frame.find("draw:image") else {
+            return Ok(None);
+        };
+        let Some(href) = image.attribute("xlink:href") else {
+            return Ok(None);
+        };
+        // Linked (not embedded) images have nothing to load from the container
+        let bytes = match read_entry(&mut self.archive, href) {
+            Ok(bytes) => Bytes::from(bytes),
+            Err(_) => return Ok(None),
+        };
+        let title = frame
+            .find("svg:title")
+            .map(text_content)
+            .or_else(|| frame.attribute("draw:name").map(str::to_string))
+            .unwrap_or_default();
+        let alt = frame
+            .find("svg:desc")
+            .map(text_content)
+            .unwrap_or_default();
+        let size = ImageDimension {
+            width: frame.attribute("svg:width").map(str::to_string),
+            height: frame.attribute("svg:height").map(str::to_string),
+        };
+        Ok(Some(Element::Image(ImageData::new(
+            bytes,
+            title,
+            alt,
+            href.to_string(),
+            String::new(),
+            size,
+        ))))
+    }
+}
+
+fn parse_annotation(node: &XmlNode) -> Element {
+    let text = node
+        .nodes()
+        .filter(|child| child.name == "text:p")
+        .map(|paragraph| collapse_whitespace(&text_content(paragraph)).trim().to_string())
+        .collect::<Vec<String>>()
+        .join("\n");
+    Element::Comment {
+        text,
+        author: None,
+        date: None,
+        range: None,
+    }
+}
+
+fn push_text(elements: &mut Vec<Element>, text: &str) {
+    if let Some(Element::Text { text: last, .. }) = elements.last_mut() {
+        last.push_str(text);
+    } else {
+        elements.push(Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        });
+    }
+}
+
+/// Stands in for `text:s` spaces until whitespace collapsing is done
+/// Paragraph style of blockquotes, the name LibreOffice uses for quotations
+const QUOTATIONS: &str = "Quotations";
+
+/// Paragraph style of code blocks, the name LibreOffice uses for preformatted text
+const PREFORMATTED: &str = "Preformatted_20_Text";
+
+/// Paragraph style of horizontal rules, the name LibreOffice uses for them
+const HORIZONTAL_LINE: &str = "Horizontal_20_Line";
+
+/// Paragraph style of the empty paragraphs ending a page
+const PAGE_BREAK: &str = "Page_20_Break";
+
+/// Paragraph style of figure captions, the name LibreOffice uses for them
+const CAPTION: &str = "Caption";
+
+/// Paragraph styles of definition list terms and definitions, the LibreOffice names
+const LIST_HEADING: &str = "List_20_Heading";
+const LIST_CONTENTS: &str = "List_20_Contents";
+
+/// Character styles of styled spans, the first two are the LibreOffice names
+const STRONG: &str = "Strong_20_Emphasis";
+const EMPHASIS: &str = "Emphasis";
+const UNDERLINE: &str = "Underline";
+const STRIKETHROUGH: &str = "Strikethrough";
+
+const PRESERVED_SPACE: char = '\u{e000}';
+
+/// ODF collapses whitespace in character data, only `text:s` produces runs of spaces
+pub(crate) fn collapse_whitespace(text: &str) -> String {
+    collapse_whitespace_with(text, |c| c == ' ' || c == '\n' || c == '\r')
+        .replace(PRESERVED_SPACE, " ")
+}
+
+fn trim_paragraph(elements: &mut Vec<Element>) {
+    let last = elements.len().saturating_sub(1);
+    for (index, element) in elements.iter_mut().enumerate() {
+        if let Element::Text { text, .. } = element {
+            let mut collapsed = collapse_whitespace(text);
+            if index == 0 {
+                collapsed = collapsed.trim_start().to_string();
+            }
+            if index == last {
+                collapsed = collapsed.trim_end().to_string();
+            }
+            *text = collapsed;
+        }
+    }
+    elements.retain(|element| !matches!(element, Element::Text { text, .. } if text.is_empty()));
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Escapes character data and keeps spaces, tabs and newlines that ODF would otherwise collapse
+fn generate_text(xml: &mut String, text: &str) {
+    let mut spaces = 0;
+    let flush = |xml: &mut String, spaces: &mut usize| {
+        if *spaces > 0 {
+            xml.push(' ');
+            if *spaces > 1 {
+                xml.push_str(&format!("<text:s text:c=\"{}\"/>", *spaces - 1));
+            }
+            *spaces = 0;
+        }
+    };
+    for c in text.chars() {
+        match c {
+            ' ' => spaces += 1,
+            '\t' => {
+                flush(xml, &mut spaces);
+                xml.push_str("<text:tab/>");
+            }
+            '\n' => {
+                flush(xml, &mut spaces);
+                xml.push_str("<text:line-break/>");
+            }
+            c => {
+                flush(xml, &mut spaces);
+                match c {
+                    '&' => xml.push_str("&amp;"),
+                    '<' => xml.push_str("&lt;"),
+                    '>' => xml.push_str("&gt;"),
+                    c => xml.push(c),
+                }
+            }
+        }
+    }
+    flush(xml, &mut spaces);
+}
+
+#[derive(Default)]
+pub(crate) struct Generator<'a> {
+    pub images: Vec<(String, &'a ImageData)>,
+    tables: usize,
+    /// Footnote contents, written as notes at their references
+    footnotes: HashMap<&'a str, &'a [Element]>,
+    /// Headers of the document, written as the body of a table of contents
+    toc: Vec<TocEntry>,
+}
+
+fn generate_toc_entries(xml: &mut String, entries: &[TocEntry], max_level: u8) {
+    for entry in entries.iter().filter(|entry| entry.level <= max_level) {
+        xml.push_str(&format!(
+            "<text:p text:style-name=\"Contents_20_{}\">",
+            entry.level.clamp(1, 6)
+        ));
+        generate_text(xml, &entry.text);
+        xml.push_str("</text:p>");
+        generate_toc_entries(xml, &entry.children, max_level);
+    }
+}
+
+pub(crate) fn generate_block<'a>(
+    xml: &mut String,
+    element: &'a Element,
+    generator: &mut Generator<'a>,
+) {
+    match element {
+        Element::Header { level, text, .. } => {
+            let level = (*level).clamp(1, 6);
+            xml.push_str(&format!(
+                "<text:h text:style-name=\"Heading_20_{}\" text:outline-level=\"{}\">",
+                level, level
+            ));
+            generate_text(xml, text);
+            xml.push_str("</text:h>");
+        }
+        Element::List { elements, numbered } => {
+            let style = if *numbered { "Numbering" } else { "Bullet" };
+            xml.push_str(&format!("<text:list text:style-name=\"{}\">", style));
+            generate_list_items(xml, elements, generator);
+            xml.push_str("</text:list>");
+        }
+        Element::Table { headers, rows, .. } => {
+            let columns = rows
+                .iter()
+                .map(|row| row.cells.len())
+                .chain(std::iter::once(headers.len()))
+                .max()
+                .unwrap_or(0)
+                .max(1);
+            generator.tables += 1;
+            xml.push_str(&format!(
+                "<table:table table:name=\"Table{}\">",
+                generator.tables
+            ));
+            xml.push_str(&format!(
+                "<table:table-column table:number-columns-repeated=\"{}\"/>",
+                columns
+            ));
+            if !headers.is_empty() {
+                xml.push_str("<table:table-header-rows><table:table-row>");
+                for header in headers {
+                    generate_cell(
+                        xml,
+                        std::slice::from_ref(&header.element),
+                        "Table_20_Heading",
+                        generator,
+                    );
+                }
+                xml.push_str("</table:table-row></table:table-header-rows>");
+            }
+            for row in rows {
+                xml.push_str("<table:table-row>");
+                for cell in &row.cells {
+                    generate_cell(xml, &cell.elements, "Table_20_Contents", generator);
+                }
+                xml.push_str("</table:table-row>");
+            }
+            xml.push_str("</table:table>");
+        }
+        // The data of the chart as a table, it only holds text so it is generated apart from
+        // the images of the document
+        Element::Chart { .. } => {
+            let table = element.chart_fallback();
+            let mut chart = Generator {
+                tables: generator.tables,
+                ..Default::default()
+            };
+            generate_block(xml, &table, &mut chart);
+            generator.tables = chart.tables;
+        }
+        Element::Blockquote { elements, .. } => {
+            for child in elements {
+                match child {
+                    Element::Text { .. }
+                    | Element::Paragraph { .. }
+                    | Element::Hyperlink { .. }
+                    | Element::Styled { .. }
+                    | Element::Math { .. }
+                    | Element::Image(_) => {
+                        xml.push_str(&format!("<text:p text:style-name=\"{}\">", QUOTATIONS));
+                        generate_inline(xml, child, generator);
+                        xml.push_str("</text:p>");
+                    }
+                    child => generate_block(xml, child, generator),
+                }
+            }
+        }
+        // The outline levels of the headings give the nesting
+        Element::Section { children, .. } => {
+            for child in children {
+                generate_block(xml, child, generator);
+            }
+        }
+        Element::CodeBlock { code, .. } => {
+            xml.push_str(&format!("<text:p text:style-name=\"{}\">", PREFORMATTED));
+            generate_text(xml, code);
+            xml.push_str("</text:p>");
+        }
+        Element::HorizontalRule => {
+            xml.push_str(&format!(
+                "<text:p text:style-name=\"{}\"/>",
+                HORIZONTAL_LINE
+            ));
+        }
+        Element::PageBreak => {
+            xml.push_str(&format!("<text:p text:style-name=\"{}\"/>", PAGE_BREAK));
+        }
+        Element::TableOfContents { max_level } => {
+            let max_level = (*max_level).clamp(1, 6);
+            xml.push_str("<text:table-of-content text:name=\"Table of Contents\">");
+            xml.push_str(&format!(
+                "<text:table-of-content-source text:outline-level=\"{}\"/>",
+                max_level
+            ));
+            xml.push_str("<text:index-body>");
+            generate_toc_entries(xml, &generator.toc, max_level);
+            xml.push_str("</text:index-body></text:table-of-content>");
+        }
+        Element::DefinitionList { items } => {
+            for item in items {
+                xml.push_str(&format!("<text:p text:style-name=\"{}\">", LIST_HEADING));
+                for child in &item.term {
+                    generate_inline(xml, child, generator);
+                }
+                xml.push_str("</text:p>");
+                for definition in &item.definitions {
+                    xml.push_str(&format!("<text:p text:style-name=\"{}\">", LIST_CONTENTS));
+                    generate_inline(xml, definition, generator);
+                    xml.push_str("</text:p>");
+                }
+            }
+        }
+        Element::Figure { image, caption } => {
+            xml.push_str("<text:p text:style-name=\"Standard\">");
+            generate_image(xml, image, generator);
+            xml.push_str("</text:p>");
+            xml.push_str(&format!("<text:p text:style-name=\"{}\">", CAPTION));
+            for child in caption {
+                generate_inline(xml, child, generator);
+            }
+            xml.push_str("</text:p>");
+        }
+        Element::Footnote { .. } => {}
+        element => {
+            xml.push_str("<text:p text:style-name=\"Standard\">");
+            generate_inline(xml, element, generator);
+            xml.push_str("</text:p>");
+        }
+    }
+}
+
+fn generate_image<'a>(xml: &mut String, image: &'a ImageData, generator: &mut Generator<'a>) {
+    if image.bytes().is_empty() {
+        return;
+    }
+    let path = format!(
+        "Pictures/image{}{}",
+        generator.images.len() + 1,
+        image.image_type().to_extension()
+    );
+    let (width, height) = image_size(image);
+    xml.push_str(&format!(
+        "<draw:frame draw:name=\"Image{}\" text:anchor-type=\"as-char\" svg:width=\"{}\" svg:height=\"{}\">",
+        generator.images.len() + 1,
+        width,
+        height
+    ));
+    xml.push_str(&format!(
+        "<draw:image xlink:href=\"{}\" xlink:type=\"simple\" xlink:show=\"embed\" xlink:actuate=\"onLoad\"/>",
+        path
+    ));
+    if !image.title().is_empty() {
+        xml.push_str(&format!("<svg:title>{}</svg:title>", escape(image.title())));
+    }
+    if !image.alt().is_empty() {
+        xml.push_str(&format!("<svg:desc>{}</svg:desc>", escape(image.alt())));
+    }
+    xml.push_str("</draw:frame>");
+    generator.images.push((path, image));
+}
+
+fn generate_list_items<'a>(xml: &mut String, items: &'a [ListItem], generator: &mut Generator<'a>) {
+    for item in items {
+        xml.push_str("<text:list-item>");
+        generate_block(xml, &item.element, generator);
+        xml.push_str("</text:list-item>");
+    }
+}
+
+fn generate_cell<'a>(
+    xml: &mut String,
+    elements: &'a [Element],
+    style: &str,
+    generator: &mut Generator<'a>,
+) {
+    xml.push_str("<table:table-cell office:value-type=\"string\">");
+    for element in elements {
+        match element {
+            Element::Header { .. }
+            | Element::List { .. }
+            | Element::Table { .. }
+            | Element::Chart { .. } => generate_block(xml, element, generator),
+            element => {
+                xml.push_str(&format!("<text:p text:style-name=\"{}\">", style));
+                generate_inline(xml, element, generator);
+                xml.push_str("</text:p>");
+            }
+        }
+    }
+    xml.push_str("</table:table-cell>");
+}
+
+fn generate_inline<'a>(xml: &mut String, element: &'a Element, generator: &mut Generator<'a>) {
+    match element {
+        Element::Text { text, .. } | Element::Header { text, .. } => generate_text(xml, text),
+        Element::Paragraph { elements }
+        | Element::Blockquote { elements, .. }
+        | Element::Section {
+            children: elements, ..
+        } => {
+            for child in elements {
+                generate_inline(xml, child, generator);
+            }
+        }
+        Element::Styled { style, elements } => {
+            let styles: Vec<&str> = [
+                (style.bold, STRONG),
+                (style.italic, EMPHASIS),
+                (style.underline, UNDERLINE),
+                (style.strikethrough, STRIKETHROUGH),
+            ]
+            .into_iter()
+            .filter_map(|(enabled, name)| enabled.then_some(name))
+            .collect();
+            for name in &styles {
+                xml.push_str(&format!("<text:span text:style-name=\"{}\">", name));
+            }
+            for child in elements {
+                generate_inline(xml, child, generator);
+            }
+            xml.push_str(&"</text:span>".repeat(styles.len()));
+        }
+        Element::Hyperlink {
+            title, url, alt, ..
+        } => {
+            xml.push_str(&format!(
+                "<text:a xlink:type=\"simple\" xlink:href=\"{}\"",
+                escape(url)
+            ));
+            if !alt.is_empty() {
+                xml.push_str(&format!(" office:title=\"{}\"", escape(alt)));
+            }
+            xml.push('>');
+            generate_text(xml, title);
+            xml.push_str("</text:a>");
+        }
+        Element::Image(image) => generate_image(xml, image, generator),
+        Element::Ruby { base, annotation } => {
+            xml.push_str("<text:ruby><text:ruby-base>");
+            generate_text(xml, base);
+            xml.push_str("</text:ruby-base><text:ruby-text>");
+            generate_text(xml, annotation);
+            xml.push_str("</text:ruby-text></text:ruby>");
+        }
+        Element::Figure { image, caption } => {
+            generate_image(xml, image, generator);
+            xml.push_str("<text:line-break/>");
+            for child in caption {
+                generate_inline(xml, child, generator);
+            }
+        }
+        // The poster and a link to the source, the fallback of `Element::media_fallback`
+        Element::Media {
+            kind,
+            source,
+            poster,
+        } => {
+            if let Some(poster) = poster {
+                generate_image(xml, poster, generator);
+                xml.push_str("<text:line-break/>");
+            }
+            match source {
+                MediaSource::Url(url) => {
+                    xml.push_str(&format!(
+                        "<text:a xlink:type=\"simple\" xlink:href=\"{}\">",
+                        escape(url)
+                    ));
+                    generate_text(xml, url);
+                    xml.push_str("</text:a>");
+                }
+                MediaSource::Embedded { .. } => generate_text(xml, &format!("[{}]", kind)),
+            }
+        }
+        // A rule has no inline form, it still separates what is around it
+        Element::LineBreak | Element::HorizontalRule => xml.push_str("<text:line-break/>"),
+        Element::PageBreak | Element::TableOfContents { .. } | Element::Custom { .. } => {}
+        Element::Comment { text, .. } => {
+            xml.push_str("<office:annotation>");
+            for line in text.lines() {
+                xml.push_str("<text:p>");
+                generate_text(xml, line);
+                xml.push_str("</text:p>");
+            }
+            xml.push_str("</office:annotation>");
+        }
+        // Block content inside a paragraph is flattened to its text
+        Element::List { elements, .. } => {
+            for item in elements {
+                generate_inline(xml, &item.element, generator);
+                xml.push(' ');
+            }
+        }
+        Element::DefinitionList { items } => {
+            for child in items
+                .iter()
+                .flat_map(|item| item.term.iter().chain(&item.definitions))
+            {
+                generate_inline(xml, child, generator);
+                xml.push(' ');
+            }
+        }
+        Element::CodeBlock { code, .. } | Element::Math { tex: code, .. } => {
+            generate_text(xml, code)
+        }
+        Element::FootnoteReference { id } => match generator.footnotes.get(id.as_str()).copied() {
+            Some(content) => {
+                xml.push_str(&format!(
+                    "<text:note text:id=\"fn-{}\" text:note-class=\"footnote\">",
+                    escape(id)
+                ));
+                xml.push_str(&format!(
+                    "<text:note-citation>{}</text:note-citation><text:note-body>",
+                    escape(id)
+                ));
+                for child in content {
+                    generate_block(xml, child, generator);
+                }
+                xml.push_str("</text:note-body></text:note>");
+            }
+            None => generate_text(xml, &format!("[{}]", id)),
+        },
+        Element::Footnote { id, content } => {
+            generate_text(xml, &format!("[{}] ", id));
+            for child in content {
+                generate_inline(xml, child, generator);
+            }
+        }
+        Element::Table { .. } | Element::Chart { .. } => {}
+    }
+}
+
+/// Frame size in ODF units, from the image dimensions or the PNG/GIF header at 96 dpi
+pub(crate) fn image_size(image: &ImageData) -> (String, String) {
+    let dimension = image.size();
+    let length = |value: &Option<String>| -> Option<String> {
+        let value = value.as_deref()?.trim();
+        if let Ok(pixels) = value.trim_end_matches("px").parse::<f32>() {
+            return Some(format!("{:.3}cm", pixels * 2.54 / 96.0));
+        }
+        const UNITS: [&str; 5] = ["cm", "mm", "in", "pt", "pc"];
+        if UNITS.iter().any(|unit| value.ends_with(unit)) {
+            return Some(value.to_string());
+        }
+        None
+    };
+    if let (Some(width), Some(height)) = (length(&dimension.width), length(&dimension.height)) {
+        return (width, height);
+    }
+
+    let bytes = image.bytes();
+    let pixels = if bytes.len() >= 24 && bytes.starts_with(b"\x89PNG") {
+        Some((
+            u32::from_be_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]),
+            u32::from_be_bytes([bytes[20], bytes[21], bytes[22], bytes[23]]),
+        ))
+    } else if bytes.len() >= 10 && bytes.starts_with(b"GIF") {
+        Some((
+            u16::from_le_bytes([bytes[6], bytes[7]]) as u32,
+            u16::from_le_bytes([bytes[8], bytes[9]]) as u32,
+        ))
+    } else {
+        None
+    };
+    match pixels {
+        Some((width, height)) if width > 0 && height > 0 => {
+            // Keep the aspect ratio but never exceed the default text width
+            let width_cm = width as f32 * 2.54 / 96.0;
+            let scale = (MAX_IMAGE_WIDTH_CM / width_cm).min(1.0);
+            let height_cm = height as f32 * 2.54 / 96.0;
+            (
+                format!("{:.3}cm", width_cm * scale),
+                format!("{:.3}cm", height_cm * scale),
+            )
+        }
+        _ => (
+            format!("{:.3}cm", DEFAULT_IMAGE_WIDTH_CM),
+            format!("{:.3}cm", DEFAULT_IMAGE_HEIGHT_CM),
+        ),
+    }
+}
+
+pub(crate) fn manifest(mimetype: &str, images: &[(String, &ImageData)]) -> String {
+    let mut entries = String::new();
+    for (path, image) in images {
+        let media_type = match image.image_type() {
+            ImageType::Png => "image/png",
+            ImageType::Jpeg => "image/jpeg",
+            ImageType::Gif => "image/gif",
+            ImageType::SVG => "image/svg+xml",
+        };
+        entries.push_str(&format!(
+            " <manifest:file-entry manifest:full-path=\"{}\" manifest:media-type=\"{}\"/>\n",
+            path, media_type
+        ));
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.3">
+ <manifest:file-entry manifest:full-path="/" manifest:version="1.3" manifest:media-type="{}"/>
+ <manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>
+ <manifest:file-entry manifest:full-path="styles.xml" manifest:media-type="text/xml"/>
+ <manifest:file-entry manifest:full-path="meta.xml" manifest:media-type="text/xml"/>
+{}</manifest:manifest>
+"#,
+        mimetype, entries
+    )
+}
+
+/// meta.xml with the document properties. ODF has a single creator, several authors share it
+pub(crate) fn meta_document(metadata: &Metadata) -> String {
+    let mut meta = String::new();
+    let mut property = |name: &str, value: &str| {
+        meta.push_str(&format!("  <{name}>{}</{name}>\n", escape(value)));
+    };
+    if let Some(title) = &metadata.title {
+        property("dc:title", title);
+    }
+    if !metadata.authors.is_empty() {
+        property("dc:creator", &metadata.authors.join("; "));
+    }
+    if let Some(subject) = &metadata.subject {
+        property("dc:subject", subject);
+    }
+    for keyword in &metadata.keywords {
+        property("meta:keyword", keyword);
+    }
+    if let Some(language) = &metadata.language {
+        property("dc:language", language);
+    }
+    if let Some(created) = &metadata.created {
+        property("meta:creation-date", created);
+    }
+    if let Some(modified) = &metadata.modified {
+        property("dc:date", modified);
+    }
+    for (name, value) in &metadata.custom {
+        meta.push_str(&format!(
+            "  <meta:user-defined meta:name=\"{}\">{}</meta:user-defined>\n",
+            escape(name),
+            escape(value)
+        ));
+    }
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-meta xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:meta="urn:oasis:names:tc:opendocument:xmlns:meta:1.0" xmlns:dc="http://purl.org/dc/elements/1.1/" office:version="1.3">
+ <office:meta>
+{meta} </office:meta>
+</office:document-meta>
+"#
+    )
+}
+
+/// The document properties of meta.xml, packages without it have none
+pub(crate) fn read_metadata(archive: &mut ZipArchive<Cursor<Vec<u8>>>) -> anyhow::Result<Metadata> {
+    let mut metadata = Metadata::default();
+    let Ok(meta) = read_entry(archive, "meta.xml") else {
+        return Ok(metadata);
+    };
+    let meta = parse_xml(&meta, Names::Qualified)?;
+    let Some(properties) = meta.find("office:meta") else {
+        return Ok(metadata);
+    };
+    for property in properties.nodes() {
+        let value = text_content(property).trim().to_string();
+        match property.name.as_str() {
+            "dc:title" => metadata.title = Some(value),
+            "dc:creator" | "meta:initial-creator" if metadata.authors.is_empty() => {
+                metadata.authors = value.split(';').map(|author| author.trim().to_string()).collect()
+            }
+            "dc:subject" => metadata.subject = Some(value),
+            "meta:keyword" => metadata.keywords.push(value),
+            "dc:language" => metadata.language = Some(value),
+            "meta:creation-date" => metadata.created = Some(value),
+            "dc:date" => metadata.modified = Some(value),
+            "meta:user-defined" => {
+                if let Some(name) = property.attribute("meta:name") {
+                    metadata.custom.insert(name.to_string(), value);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(metadata)
+}
+
+fn list_style(name: &str, numbered: bool) -> String {
+    let mut style = format!("<text:list-style style:name=\"{}\">", name);
+    for level in 1..=10 {
+        let (tag, format) = if numbered {
+            (
+                "text:list-level-style-number",
+                "style:num-suffix=\".\" style:num-format=\"1\"".to_string(),
+            )
+        } else {
+            (
+                "text:list-level-style-bullet",
+                "text:bullet-char=\"•\"".to_string(),
+            )
+        };
+        let indent = 0.635 * (level as f32 + 1.0);
+        style.push_str(&format!(
+            "<{tag} text:level=\"{level}\" {format}><style:list-level-properties text:list-level-position-and-space-mode=\"label-alignment\"><style:list-level-label-alignment text:label-followed-by=\"listtab\" text:list-tab-stop-position=\"{indent:.3}cm\" fo:text-indent=\"-0.635cm\" fo:margin-left=\"{indent:.3}cm\"/></style:list-level-properties></{tag}>",
+            tag = tag,
+            level = level,
+            format = format,
+            indent = indent
+        ));
+    }
+    style.push_str("</text:list-style>");
+    style
+}
+
+fn content_document(body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content {}><office:automatic-styles/><office:body><office:text>{}</office:text></office:body></office:document-content>
+"#,
+        NAMESPACES, body
+    )
+}
+
+/// Paragraph and list styles referenced by the generated body
+pub(crate) fn paragraph_styles(font_size: u32) -> String {
+    let mut styles = format!(
+        r#"<style:default-style style:family="paragraph"><style:text-properties fo:font-size="{}pt"/></style:default-style>"#,
+        font_size
+    );
+    styles.push_str(
+        r#"<style:style style:name="Standard" style:family="paragraph" style:class="text"/>"#,
+    );
+    styles.push_str(r#"<style:style style:name="Table_20_Contents" style:display-name="Table Contents" style:family="paragraph" style:parent-style-name="Standard"/>"#);
+    styles.push_str(r#"<style:style style:name="Table_20_Heading" style:display-name="Table Heading" style:family="paragraph" style:parent-style-name="Table_20_Contents"><style:text-properties fo:font-weight="bold"/></style:style>"#);
+    styles.push_str(&format!(
+        r#"<style:style style:name="{}" style:display-name="Preformatted Text" style:family="paragraph" style:parent-style-name="Standard"><style:text-properties fo:font-family="'Liberation Mono'" style:font-family-generic="modern" style:font-pitch="fixed"/></style:style>"#,
+        PREFORMATTED
+    ));
+    styles.push_str(&format!(
+        r#"<style:style style:name="{}" style:family="paragraph" style:parent-style-name="Standard"><style:paragraph-properties fo:margin-left="1cm" fo:margin-right="1cm" fo:margin-bottom="0.247cm"/></style:style>"#,
+        QUOTATIONS
+    ));
+    styles.push_str(&format!(
+        r#"<style:style style:name="{}" style:display-name="Horizontal Line" style:family="paragraph" style:parent-style-name="Standard"><style:paragraph-properties fo:margin-bottom="0.5cm" fo:border-bottom="0.06pt solid #808080" fo:padding="0cm"/><style:text-properties fo:font-size="6pt"/></style:style>"#,
+        HORIZONTAL_LINE
+    ));
+    styles.push_str(&format!(
+        r#"<style:style style:name="{}" style:display-name="Page Break" style:family="paragraph" style:parent-style-name="Standard"><style:paragraph-properties fo:break-after="page"/><style:text-properties fo:font-size="2pt"/></style:style>"#,
+        PAGE_BREAK
+    ));
+    styles.push_str(&format!(
+        r#"<style:style style:name="{}" style:display-name="List Heading" style:family="paragraph" style:parent-style-name="Standard"><style:text-properties fo:font-weight="bold"/></style:style>"#,
+        LIST_HEADING
+    ));
+    styles.push_str(&format!(
+        r#"<style:style style:name="{}" style:display-name="List Contents" style:family="paragraph" style:parent-style-name="Standard"><style:paragraph-properties fo:margin-left="1cm"/></style:style>"#,
+        LIST_CONTENTS
+    ));
+    for (level, size) in [(1, 130), (2, 115), (3, 101), (4, 95), (5, 85), (6, 85)] {
+        styles.push_str(&format!(
+            r#"<style:style style:name="Heading_20_{level}" style:display-name="Heading {level}" style:family="paragraph" style:parent-style-name="Standard" style:default-outline-level="{level}"><style:paragraph-properties fo:margin-top="0.423cm" fo:margin-bottom="0.212cm" fo:keep-with-next="always"/><style:text-properties fo:font-size="{size}%" fo:font-weight="bold"/></style:style>"#,
+            level = level,
+            size = size
+        ));
+    }
+    styles.push_str(&format!(
+        r#"<style:style style:name="{}" style:display-name="Strong Emphasis" style:family="text"><style:text-properties fo:font-weight="bold"/></style:style>"#,
+        STRONG
+    ));
+    styles.push_str(&format!(
+        r#"<style:style style:name="{}" style:family="text"><style:text-properties fo:font-style="italic"/></style:style>"#,
+        EMPHASIS
+    ));
+    styles.push_str(&format!(
+        r#"<style:style style:name="{}" style:family="text"><style:text-properties style:text-underline-style="solid" style:text-underline-width="auto" style:text-underline-color="font-color"/></style:style>"#,
+        UNDERLINE
+    ));
+    styles.push_str(&format!(
+        r#"<style:style style:name="{}" style:family="text"><style:text-properties style:text-line-through-style="solid"/></style:style>"#,
+        STRIKETHROUGH
+    ));
+    styles.push_str(&list_style("Numbering", true));
+    styles.push_str(&list_style("Bullet", false));
+    styles
+}
+
+fn styles_document(document: &Document, header: &str, footer: &str) -> String {
+    let dimensions = document.page_format.dimensions();
+    let (width, height) = match document.orientation {
+        PageOrientation::Portrait => (dimensions.page_width, dimensions.page_height),
+        PageOrientation::Landscape => (dimensions.page_height, dimensions.page_width),
+    };
+    let orientation = match document.orientation {
+        PageOrientation::Portrait => "portrait",
+        PageOrientation::Landscape => "landscape",
+    };
+
+    let styles = paragraph_styles(12);
+
+    let mut master_page =
+        String::from(r#"<style:master-page style:name="Standard" style:page-layout-name="pm1">"#);
+    if !header.is_empty() {
+        master_page.push_str(&format!("<style:header>{}</style:header>", header));
+    }
+    if !footer.is_empty() {
+        master_page.push_str(&format!("<style:footer>{}</style:footer>", footer));
+    }
+    master_page.push_str("</style:master-page>");
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-styles {namespaces}><office:styles>{styles}</office:styles><office:automatic-styles><style:page-layout style:name="pm1"><style:page-layout-properties fo:page-width="{width}mm" fo:page-height="{height}mm" style:print-orientation="{orientation}" fo:margin-top="{top}mm" fo:margin-bottom="{bottom}mm" fo:margin-left="{left}mm" fo:margin-right="{right}mm"/></style:page-layout></office:automatic-styles><office:master-styles>{master_page}</office:master-styles></office:document-styles>
+"#,
+        namespaces = NAMESPACES,
+        styles = styles,
+        width = width,
+        height = height,
+        orientation = orientation,
+        top = dimensions.page_margin_top,
+        bottom = dimensions.page_margin_bottom,
+        left = dimensions.page_margin_left,
+        right = dimensions.page_margin_right,
+        master_page = master_page
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::*;
+    use crate::markdown;
+    use crate::odt::*;
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let generated = Transformer::generate(&parsed)?;
+        let reparsed = Transformer::parse(&generated)?;
+        let original = parsed.get_all_elements();
+        let elements = reparsed.get_all_elements();
+        assert_eq!(original.len(), elements.len());
+        assert_eq!(original[0], elements[0]);
+        for (original, element) in original.iter().zip(elements.iter()) {
+            assert_eq!(
+                std::mem::discriminant(*original),
+                std::mem::discriminant(*element)
+            );
+            if let (Element::List { numbered: a, .. }, Element::List { numbered: b, .. }) =
+                (original, element)
+            {
+                assert_eq!(a, b);
+            }
+        }
+        let image = elements.iter().find_map(|element| match element {
+            Element::Image(image) => Some(image),
+            _ => None,
+        });
+        assert_eq!(image.expect("image").image_type(), &ImageType::Png);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_header_and_spaces() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new_with_dimensions(
+            vec![Element::Paragraph {
+                elements: vec![text("Header")],
+            }],
+            vec![Element::Paragraph {
+                elements: vec![text("a  b\tc & d"), Element::LineBreak, text("e")],
+            }],
+            vec![Element::Paragraph {
+                elements: vec![text("Footer")],
+            }],
+            PageFormat::Letter,
+        );
+        let generated = Transformer::generate(&document)?;
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.bands, document.bands);
+        Ok(())
+    }
+}
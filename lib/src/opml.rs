@@ -0,0 +1,472 @@
+use crate::core::*;
+use bytes::Bytes;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    /// Outlines with children become headers, their depth gives the level. Deeper than six
+    /// levels they continue as nested lists. Leaf outlines become paragraphs, or hyperlinks
+    /// for `type="link"` outlines. The `_note` attribute used by outliners adds a paragraph.
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        let outlines = parse_outlines(document)?;
+        let mut elements = Vec::new();
+        for outline in &outlines {
+            parse_outline(outline, 1, &mut elements);
+        }
+        Ok(Document::new(elements))
+    }
+
+    /// Headers open nested outlines, the content below a header is added as leaf outlines.
+    /// Nested lists become children of their previous item, so that item reads back as a
+    /// header. Table rows become outlines with ` | ` between the cells, images keep their alt
+    /// text and comments are dropped.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        // The headers open the outlines, sections are written as theirs
+        let document = &document.flatten_sections();
+        let elements: Vec<&Element> = document
+            .bands
+            .iter()
+            .filter(|band| !matches!(band, Band::PageHeader(_) | Band::PageFooter(_)))
+            .flat_map(|band| band.elements())
+            .collect();
+
+        let mut opml =
+            String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<opml version=\"2.0\">\n");
+        opml.push_str("  <head>\n");
+        if let Some(title) = elements.iter().find_map(|element| match element {
+            Element::Header { text, .. } => Some(text),
+            _ => None,
+        }) {
+            opml.push_str(&format!("    <title>{}</title>\n", escape(title)));
+        }
+        opml.push_str("  </head>\n  <body>\n");
+
+        let mut headers: Vec<u8> = Vec::new();
+        for element in elements {
+            if let Element::Header { level, text, .. } = element {
+                while headers.last().is_some_and(|open| open >= level) {
+                    headers.pop();
+                    opml.push_str(&format!("{}</outline>\n", indent(headers.len())));
+                }
+                opml.push_str(&format!(
+                    "{}<outline text=\"{}\">\n",
+                    indent(headers.len()),
+                    escape(text)
+                ));
+                headers.push(*level);
+            } else {
+                block(&mut opml, element, headers.len() + 1);
+            }
+        }
+        while headers.pop().is_some() {
+            opml.push_str(&format!("{}</outline>\n", indent(headers.len())));
+        }
+        opml.push_str("  </body>\n</opml>\n");
+        Ok(Bytes::from(opml))
+    }
+}
+
+#[derive(Default)]
+struct Outline {
+    attributes: HashMap<String, String>,
+    children: Vec<Outline>,
+}
+
+impl Outline {
+    fn attribute(&self, name: &str) -> &str {
+        self.attributes.get(name).map_or("", String::as_str)
+    }
+}
+
+/// Outlines of the `body`, other elements are skipped
+fn parse_outlines(xml: &[u8]) -> anyhow::Result<Vec<Outline>> {
+    let mut reader = Reader::from_reader(xml);
+    let mut stack = vec![Outline::default()];
+    let mut in_body = false;
+    let read = |start: &BytesStart, reader: &Reader<&[u8]>| -> anyhow::Result<Outline> {
+        let mut outline = Outline::default();
+        for attribute in start.attributes() {
+            let attribute = attribute?;
+            outline.attributes.insert(
+                String::from_utf8(attribute.key.local_name().as_ref().to_vec())?,
+                attribute.decode_and_unescape_value(reader)?.to_string(),
+            );
+        }
+        Ok(outline)
+    };
+    loop {
+        match reader.read_event()? {
+            Event::Start(ref e) if e.local_name().as_ref() == b"body" => in_body = true,
+            Event::End(ref e) if e.local_name().as_ref() == b"body" => in_body = false,
+            Event::Start(ref e) if in_body && e.local_name().as_ref() == b"outline" => {
+                stack.push(read(e, &reader)?);
+            }
+            Event::Empty(ref e) if in_body && e.local_name().as_ref() == b"outline" => {
+                let outline = read(e, &reader)?;
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(outline);
+                }
+            }
+            Event::End(ref e) if in_body && e.local_name().as_ref() == b"outline" => {
+                let outline = stack
+                    .pop()
+                    .filter(|_| !stack.is_empty())
+                    .ok_or_else(|| ParserError::Malformed("unbalanced outline".to_string()))?;
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(outline);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if stack.len() != 1 {
+        return Err(ParserError::Malformed("unclosed outline".to_string()).into());
+    }
+    Ok(stack.remove(0).children)
+}
+
+fn parse_outline(outline: &Outline, depth: u8, elements: &mut Vec<Element>) {
+    let text = outline.attribute("text");
+    if outline.children.is_empty() {
+        let url = outline.attribute("url");
+        if outline.attribute("type") == "link" && !url.is_empty() {
+            elements.push(Element::Paragraph {
+                elements: vec![Element::Hyperlink {
+                    title: text.to_string(),
+                    url: url.to_string(),
+                    alt: String::new(),
+                    size: 8,
+                }],
+            });
+        } else if !text.is_empty() {
+            elements.push(paragraph(text));
+        }
+    } else if depth <= 6 {
+        elements.push(Element::Header {
+            level: depth,
+            text: text.to_string(),
+            anchor: None,
+        });
+    } else {
+        elements.push(Element::List {
+            elements: list_items(outline),
+            numbered: false,
+        });
+        return;
+    }
+    let note = outline.attribute("_note");
+    if !note.is_empty() {
+        elements.push(paragraph(note));
+    }
+    for child in &outline.children {
+        parse_outline(child, depth + 1, elements);
+    }
+}
+
+/// Items of an outline below the header levels, nested outlines follow their parent item
+fn list_items(outline: &Outline) -> Vec<ListItem> {
+    let mut items = vec![ListItem {
+        element: Element::Text {
+            text: outline.attribute("text").to_string(),
+            font: FontSpec::from(8),
+        },
+        checked: None,
+    }];
+    if !outline.children.is_empty() {
+        items.push(ListItem {
+            element: Element::List {
+                elements: outline.children.iter().flat_map(list_items).collect(),
+                numbered: false,
+            },
+            checked: None,
+        });
+    }
+    items
+}
+
+fn paragraph(text: &str) -> Element {
+    Element::Paragraph {
+        elements: vec![Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        }],
+    }
+}
+
+fn indent(depth: usize) -> String {
+    "  ".repeat(depth + 2)
+}
+
+/// Escapes text for attribute values, line breaks are kept as character references
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+        .replace('\t', "&#9;")
+}
+
+fn block(opml: &mut String, element: &Element, depth: usize) {
+    match element {
+        Element::Paragraph { elements } => match elements.as_slice() {
+            [Element::Hyperlink { title, url, .. }] => {
+                let title = if title.is_empty() { url } else { title };
+                opml.push_str(&format!(
+                    "{}<outline text=\"{}\" type=\"link\" url=\"{}\"/>\n",
+                    indent(depth - 1),
+                    escape(title),
+                    escape(url)
+                ));
+            }
+            _ => leaf(opml, &inline(element), depth),
+        },
+        Element::Hyperlink { .. } => block(
+            opml,
+            &Element::Paragraph {
+                elements: vec![element.clone()],
+            },
+            depth,
+        ),
+        Element::List { elements, .. } => list(opml, elements, depth),
+        // The definitions are the children of their term
+        Element::DefinitionList { items } => {
+            for item in items {
+                let term: String = item.term.iter().map(inline).collect();
+                if item.definitions.is_empty() {
+                    leaf(opml, &term, depth);
+                    continue;
+                }
+                opml.push_str(&format!(
+                    "{}<outline text=\"{}\">\n",
+                    indent(depth - 1),
+                    escape(term.trim())
+                ));
+                for definition in &item.definitions {
+                    leaf(opml, &inline(definition), depth + 1);
+                }
+                opml.push_str(&format!("{}</outline>\n", indent(depth - 1)));
+            }
+        }
+        Element::Chart { .. } => block(opml, &element.chart_fallback(), depth),
+        Element::Table { headers, rows, .. } => {
+            if !headers.is_empty() {
+                let cells: Vec<String> = headers
+                    .iter()
+                    .map(|header| inline(&header.element))
+                    .collect();
+                leaf(opml, &cells.join(" | "), depth);
+            }
+            for row in rows {
+                let cells: Vec<String> = row
+                    .cells
+                    .iter()
+                    .map(|cell| inline(&cell.element()))
+                    .collect();
+                leaf(opml, &cells.join(" | "), depth);
+            }
+        }
+        // Outlines have no quotes, the quoted blocks stay at the same depth
+        Element::Blockquote { elements, .. } => {
+            for child in elements {
+                block(opml, child, depth);
+            }
+        }
+        Element::Comment { .. } => {}
+        element => leaf(opml, &inline(element), depth),
+    }
+}
+
+fn leaf(opml: &mut String, text: &str, depth: usize) {
+    let text = text.trim();
+    if !text.is_empty() {
+        opml.push_str(&format!(
+            "{}<outline text=\"{}\"/>\n",
+            indent(depth - 1),
+            escape(text)
+        ));
+    }
+}
+
+/// A nested list becomes the children of the item before it
+fn list(opml: &mut String, items: &[ListItem], depth: usize) {
+    let mut index = 0;
+    while index < items.len() {
+        let text = inline(&items[index].element);
+        if let Element::List { elements, .. } = &items[index].element {
+            list(opml, elements, depth);
+            index += 1;
+            continue;
+        }
+        index += 1;
+        match items.get(index) {
+            Some(ListItem {
+                element: Element::List { elements, .. },
+                checked: None,
+            }) => {
+                opml.push_str(&format!(
+                    "{}<outline text=\"{}\">\n",
+                    indent(depth - 1),
+                    escape(text.trim())
+                ));
+                list(opml, elements, depth + 1);
+                opml.push_str(&format!("{}</outline>\n", indent(depth - 1)));
+                index += 1;
+            }
+            _ => leaf(opml, &text, depth),
+        }
+    }
+}
+
+fn inline(element: &Element) -> String {
+    match element {
+        Element::Text { text, .. } | Element::Header { text, .. } => text.clone(),
+        Element::Paragraph { elements }
+        | Element::Blockquote { elements, .. }
+        | Element::Section {
+            children: elements, ..
+        }
+        | Element::Styled { elements, .. } => elements.iter().map(inline).collect(),
+        Element::Hyperlink { title, url, .. } => {
+            if title.is_empty() {
+                url.clone()
+            } else {
+                title.clone()
+            }
+        }
+        Element::Image(image) => {
+            if image.alt().is_empty() {
+                image.title().to_string()
+            } else {
+                image.alt().to_string()
+            }
+        }
+        Element::Figure { image, caption } if caption.is_empty() => {
+            inline(&Element::Image(image.clone()))
+        }
+        Element::Figure { caption, .. } => caption.iter().map(inline).collect(),
+        Element::LineBreak => "\n".to_string(),
+        Element::List { elements, .. } => elements
+            .iter()
+            .map(|item| inline(&item.element))
+            .collect::<Vec<String>>()
+            .join(" "),
+        Element::DefinitionList { items } => items
+            .iter()
+            .flat_map(|item| item.term.iter().chain(&item.definitions))
+            .map(inline)
+            .collect::<Vec<String>>()
+            .join(" "),
+        Element::CodeBlock { code, .. } => code.clone(),
+        Element::Math { tex, .. } => tex.clone(),
+        Element::FootnoteReference { id } => format!("[{}]", id),
+        Element::Footnote { id, content } => {
+            let content: String = content.iter().map(inline).collect();
+            format!("[{}] {}", id, content)
+        }
+        Element::Media { .. } => inline(&element.media_fallback()),
+        Element::Ruby { .. } => inline(&element.ruby_fallback()),
+        // Outlines have no separators
+        Element::Table { .. }
+        | Element::Chart { .. }
+        | Element::Comment { .. }
+        | Element::HorizontalRule
+        | Element::PageBreak
+        | Element::TableOfContents { .. }
+        | Element::Custom { .. } => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::opml::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let opml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<opml version="2.0">
+  <head><title>Plans</title></head>
+  <body>
+    <outline text="Project" _note="Kick-off in May">
+      <outline text="Goals">
+        <outline text="Ship &amp; test"/>
+      </outline>
+      <outline text="Site" type="link" url="https://example.com"/>
+    </outline>
+    <outline text="Loose idea"/>
+  </body>
+</opml>"#;
+        let document = Transformer::parse(&Bytes::from(opml))?;
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let elements = document.get_all_elements();
+        assert_eq!(
+            elements,
+            vec![
+                &Element::Header {
+                    level: 1,
+                    text: "Project".to_string(),
+                    anchor: None,
+                },
+                &Element::Paragraph {
+                    elements: vec![text("Kick-off in May")]
+                },
+                &Element::Header {
+                    level: 2,
+                    text: "Goals".to_string(),
+                    anchor: None,
+                },
+                &Element::Paragraph {
+                    elements: vec![text("Ship & test")]
+                },
+                &Element::Paragraph {
+                    elements: vec![Element::Hyperlink {
+                        title: "Site".to_string(),
+                        url: "https://example.com".to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    }]
+                },
+                &Element::Paragraph {
+                    elements: vec![text("Loose idea")]
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let generated = Transformer::generate(&parsed)?;
+        let reparsed = Transformer::parse(&generated)?;
+        let headers = |document: &Document| -> Vec<String> {
+            document
+                .get_all_elements()
+                .into_iter()
+                .filter_map(|element| match element {
+                    Element::Header { text, .. } => Some(text.clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+        // List items with nested lists come back as headers as well
+        let original = headers(&parsed);
+        let reparsed: Vec<String> = headers(&reparsed)
+            .into_iter()
+            .filter(|header| original.contains(header))
+            .collect();
+        assert_eq!(reparsed, original);
+        Ok(())
+    }
+}
@@ -0,0 +1,1178 @@
+use crate::core::*;
+use crate::parse_util::unwrap_paragraph;
+use bytes::Bytes;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Parses headlines, plain and ordered lists, tables, links, source and example blocks
+    /// and links to image files. Emphasis markers are dropped, the text is kept.
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let source = String::from_utf8(document.to_vec())?;
+        let lines: Vec<&str> = source.lines().map(str::trim_end).collect();
+        let parser = Parser {
+            image_loader: &image_loader,
+        };
+        let elements = parser.parse_blocks(&lines)?;
+        Ok(Document::new(elements))
+    }
+
+    /// Images are written through `image_saver` and linked with `file:` links.
+    /// Page headers and footers have no Org equivalent and are skipped.
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let mut generator = Generator {
+            image_saver,
+            image_num: 0,
+        };
+        let mut org = String::new();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                generator.block(&mut org, element)?;
+            }
+        }
+        Ok(Bytes::from(org))
+    }
+}
+
+const IMAGE_EXTENSIONS: [&str; 6] = [".png", ".jpg", ".jpeg", ".gif", ".svg", ".webp"];
+
+/// Headline keywords which are not part of the title
+const TODO_KEYWORDS: [&str; 2] = ["TODO", "DONE"];
+
+/// Emphasis markers: bold, italic, underline, verbatim, code and strike-through
+const EMPHASIS_MARKERS: [char; 6] = ['*', '/', '_', '=', '~', '+'];
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// `** Title :tag:` returns the level and the title without keywords and tags
+fn headline(line: &str) -> Option<(u8, String)> {
+    let level = line.chars().take_while(|c| *c == '*').count();
+    let title = line[level..].strip_prefix(' ')?;
+    if level == 0 {
+        return None;
+    }
+    let mut title = title.trim();
+    if let Some((keyword, rest)) = title.split_once(' ') {
+        if TODO_KEYWORDS.contains(&keyword) {
+            title = rest.trim_start();
+        }
+    }
+    // Priority cookie `[#A]`
+    if title.starts_with("[#") && title.get(3..4) == Some("]") {
+        title = title[4..].trim_start();
+    }
+    // Trailing tags `:work:urgent:`
+    if let Some((rest, tags)) = title.rsplit_once(' ') {
+        if tags.len() > 1 && tags.starts_with(':') && tags.ends_with(':') {
+            title = rest.trim_end();
+        }
+    }
+    Some((level.min(6) as u8, title.to_string()))
+}
+
+/// Returns the marker width including the following space and whether the list is ordered
+fn list_marker(line: &str) -> Option<(usize, bool)> {
+    let trimmed = line.trim_start();
+    let indent = line.len() - trimmed.len();
+    let (marker, _) = trimmed.split_once(' ')?;
+    let width = indent + marker.len() + 1;
+    // A star at the start of the line is a headline
+    if marker == "-" || marker == "+" || (marker == "*" && indent > 0) {
+        return Some((width, false));
+    }
+    let number = marker
+        .strip_suffix('.')
+        .or_else(|| marker.strip_suffix(')'))?;
+    let ordered = !number.is_empty()
+        && (number.chars().all(|c| c.is_ascii_digit())
+            || (number.len() == 1 && number.chars().all(|c| c.is_ascii_alphabetic())));
+    ordered.then_some((width, true))
+}
+
+/// `#+KEY: value` returns the upper-cased key and the value
+fn keyword(line: &str) -> Option<(String, &str)> {
+    let rest = line.trim_start().strip_prefix("#+")?;
+    let (key, value) = rest.split_once(':')?;
+    (!key.contains(' ')).then(|| (key.to_uppercase(), value.trim()))
+}
+
+fn is_image_path(path: &str) -> bool {
+    let path = path.to_lowercase();
+    IMAGE_EXTENSIONS
+        .iter()
+        .any(|extension| path.ends_with(extension))
+}
+
+/// `:width 300px :alt Some text` attributes of `#+ATTR_HTML`, `#+ATTR_ORG` and the like
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let start = attributes.find(&format!(":{} ", name))? + name.len() + 2;
+    let rest = &attributes[start..];
+    let end = rest.find(" :").unwrap_or(rest.len());
+    Some(rest[..end].trim().to_string())
+}
+
+struct Parser<'a, F> {
+    image_loader: &'a F,
+}
+
+impl<'a, F> Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    fn parse_blocks(&self, lines: &[&str]) -> anyhow::Result<Vec<Element>> {
+        let mut elements = Vec::new();
+        let mut caption = None;
+        let mut attributes = String::new();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = lines[index];
+            let trimmed = line.trim_start();
+            if trimmed.is_empty() {
+                index += 1;
+                continue;
+            }
+
+            if let Some((level, text)) = headline(line) {
+                elements.push(Element::Header {
+                    level,
+                    text: strip_emphasis(&text),
+                    anchor: None,
+                });
+                index += 1;
+                continue;
+            }
+
+            if let Some((key, value)) = keyword(line) {
+                index += 1;
+                match key.as_str() {
+                    "TITLE" => elements.push(Element::Header {
+                        level: 1,
+                        text: value.to_string(),
+                        anchor: None,
+                    }),
+                    "CAPTION" => caption = Some(value.to_string()),
+                    // `#+TOC: headlines 2`, without a depth all headlines are listed
+                    "TOC" if value.starts_with("headlines") => {
+                        let depth = value["headlines".len()..].trim().parse::<u8>().ok();
+                        elements.push(Element::TableOfContents {
+                            max_level: depth.unwrap_or(6).clamp(1, 6),
+                        })
+                    }
+                    key if key.starts_with("ATTR_") => {
+                        attributes.push(' ');
+                        attributes.push_str(value);
+                    }
+                    // Export settings and other in-buffer settings
+                    _ => {}
+                }
+                continue;
+            }
+
+            if let Some(block) = trimmed.strip_prefix("#+") {
+                if block.to_uppercase().starts_with("BEGIN_") {
+                    index = self.parse_block(lines, index, &mut elements)?;
+                    continue;
+                }
+                // A stray `#+END_...` or a keyword without a value, such as `#+RESULTS`
+                index += 1;
+                continue;
+            }
+
+            if trimmed == "#" || trimmed.starts_with("# ") {
+                let mut text = Vec::new();
+                while index < lines.len() {
+                    let line = lines[index].trim_start();
+                    let Some(comment) = line.strip_prefix('#') else {
+                        break;
+                    };
+                    if !(comment.is_empty() || comment.starts_with(' ')) {
+                        break;
+                    }
+                    text.push(comment.trim());
+                    index += 1;
+                }
+                elements.push(Element::Comment {
+                    text: text.join("\n"),
+                    author: None,
+                    date: None,
+                    range: None,
+                });
+                continue;
+            }
+
+            // Drawers such as `:PROPERTIES:` hold metadata only
+            if trimmed.starts_with(':') && trimmed.ends_with(':') && trimmed.len() > 2 {
+                while index < lines.len() && !lines[index].trim().eq_ignore_ascii_case(":END:") {
+                    index += 1;
+                }
+                index += 1;
+                continue;
+            }
+
+            if trimmed.starts_with('|') {
+                let end = lines[index..]
+                    .iter()
+                    .position(|line| !line.trim_start().starts_with('|'))
+                    .map(|end| index + end)
+                    .unwrap_or(lines.len());
+                elements.push(self.parse_table(&lines[index..end])?);
+                index = end;
+                continue;
+            }
+
+            if let Some((_, ordered)) = list_marker(line) {
+                index = self.parse_list(lines, index, ordered, &mut elements)?;
+                continue;
+            }
+
+            // Paragraph, `\\` at the end of a line forces a line break
+            let mut paragraph = Vec::new();
+            let start = index;
+            while index < lines.len() {
+                let line = lines[index].trim();
+                let ends_paragraph = line.is_empty()
+                    || headline(lines[index]).is_some()
+                    || line.starts_with("#+")
+                    || line.starts_with('|')
+                    || (index > start && list_marker(lines[index]).is_some());
+                if ends_paragraph {
+                    break;
+                }
+                if let Some(line) = line.strip_suffix("\\\\") {
+                    self.parse_inline(line.trim_end(), &mut paragraph)?;
+                    paragraph.push(Element::LineBreak);
+                } else {
+                    if !matches!(paragraph.last(), None | Some(Element::LineBreak)) {
+                        push_text(&mut paragraph, " ");
+                    }
+                    self.parse_inline(line, &mut paragraph)?;
+                }
+                index += 1;
+            }
+            if let Some(Element::LineBreak) = paragraph.last() {
+                paragraph.pop();
+            }
+
+            // A lone image link is a figure which takes the preceding caption and attributes
+            if let [Element::Image(image)] = paragraph.as_slice() {
+                let alt = attribute(&attributes, "alt").unwrap_or_else(|| image.alt().to_string());
+                elements.push(Element::Image(ImageData::new(
+                    image.bytes().clone(),
+                    caption.take().unwrap_or_default(),
+                    alt,
+                    image
+                        .image_type()
+                        .to_extension()
+                        .trim_start_matches('.')
+                        .to_string(),
+                    attribute(&attributes, "align").unwrap_or_default(),
+                    ImageDimension {
+                        width: attribute(&attributes, "width"),
+                        height: attribute(&attributes, "height"),
+                    },
+                )));
+            } else if !paragraph.is_empty() {
+                elements.push(Element::Paragraph {
+                    elements: paragraph,
+                });
+            }
+            caption = None;
+            attributes.clear();
+        }
+        Ok(elements)
+    }
+
+    /// `#+BEGIN_NAME` ... `#+END_NAME`, returns the index after the block
+    fn parse_block(
+        &self,
+        lines: &[&str],
+        index: usize,
+        elements: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let begin = lines[index].trim_start()[2..].to_uppercase();
+        let name = begin[6..]
+            .split_whitespace()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let begin_marker = format!("#+BEGIN_{}", name);
+        let end_marker = format!("#+END_{}", name);
+        // Blocks of the same name nest, except the verbatim ones
+        let nests = !matches!(name.as_str(), "SRC" | "EXAMPLE" | "VERSE");
+        let mut depth = 0;
+        let end = lines[index + 1..]
+            .iter()
+            .position(|line| {
+                let line = line.trim().to_uppercase();
+                let begins = line
+                    .strip_prefix(&begin_marker)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace));
+                if nests && begins {
+                    depth += 1;
+                } else if line == end_marker {
+                    if depth == 0 {
+                        return true;
+                    }
+                    depth -= 1;
+                }
+                false
+            })
+            .map(|end| index + 1 + end)
+            .unwrap_or(lines.len());
+        let content = &lines[index + 1..end];
+
+        match name.as_str() {
+            "SRC" | "EXAMPLE" | "VERSE" => {
+                let indent = content
+                    .iter()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| indentation(line))
+                    .min()
+                    .unwrap_or(0);
+                let text = content
+                    .iter()
+                    .map(|line| line.get(indent..).unwrap_or_default())
+                    .collect::<Vec<&str>>()
+                    .join("\n");
+                elements.push(Element::Text {
+                    text,
+                    font: FontSpec::from(8),
+                });
+            }
+            // Raw export blocks are meant for a single backend
+            "EXPORT" => {}
+            // QUOTE, CENTER and special blocks contain regular Org markup
+            _ => elements.append(&mut self.parse_blocks(content)?),
+        }
+        Ok((end + 1).min(lines.len()))
+    }
+
+    fn parse_list(
+        &self,
+        lines: &[&str],
+        mut index: usize,
+        ordered: bool,
+        elements: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let indent = indentation(lines[index]);
+        let mut items = Vec::new();
+        while index < lines.len() {
+            let line = lines[index];
+            let Some((width, item_ordered)) = list_marker(line) else {
+                break;
+            };
+            if indentation(line) != indent || item_ordered != ordered {
+                break;
+            }
+            // The item continues with every line indented past its marker
+            let mut end = index + 1;
+            while end < lines.len()
+                && (lines[end].trim().is_empty() || indentation(lines[end]) > indent)
+            {
+                // Two blank lines end the list
+                if lines[end].trim().is_empty()
+                    && lines
+                        .get(end + 1)
+                        .is_some_and(|line| line.trim().is_empty())
+                {
+                    break;
+                }
+                end += 1;
+            }
+            let mut body = vec![&line[width..]];
+            body.extend(lines[index + 1..end].iter().map(|line| {
+                let strip = width.min(indentation(line));
+                &line[strip..]
+            }));
+            let mut content = self.parse_blocks(&body)?;
+
+            // Nested lists follow their parent item in the model
+            let nested = content
+                .iter()
+                .position(|element| matches!(element, Element::List { .. }))
+                .map(|position| content.split_off(position))
+                .unwrap_or_default();
+            match content.len() {
+                0 => {}
+                1 => items.push(ListItem {
+                    element: unwrap_paragraph(content.remove(0)),
+                    checked: None,
+                }),
+                _ => items.push(ListItem {
+                    element: Element::Paragraph { elements: content },
+                    checked: None,
+                }),
+            }
+            items.extend(nested.into_iter().map(|element| ListItem {
+                element,
+                checked: None,
+            }));
+
+            index = end;
+            while index < lines.len() && lines[index].trim().is_empty() {
+                if lines
+                    .get(index + 1)
+                    .is_some_and(|line| line.trim().is_empty())
+                {
+                    break;
+                }
+                index += 1;
+            }
+        }
+        elements.push(Element::List {
+            elements: items,
+            numbered: ordered,
+        });
+        Ok(index)
+    }
+
+    /// The rows above the first `|---+---|` rule are the header
+    fn parse_table(&self, lines: &[&str]) -> anyhow::Result<Element> {
+        let mut rows: Vec<Vec<Element>> = Vec::new();
+        let mut header_rows = 0;
+        for line in lines {
+            let line = line.trim();
+            if line.starts_with("|-") {
+                if header_rows == 0 {
+                    header_rows = rows.len();
+                }
+                continue;
+            }
+            let line = line.trim_start_matches('|');
+            let line = line.strip_suffix('|').unwrap_or(line);
+            let mut cells = Vec::new();
+            for cell in line.split('|') {
+                let mut inline = Vec::new();
+                self.parse_inline(cell.trim(), &mut inline)?;
+                cells.push(match inline.len() {
+                    0 => Element::Text {
+                        text: String::new(),
+                        font: FontSpec::from(8),
+                    },
+                    1 => inline.remove(0),
+                    _ => Element::Paragraph { elements: inline },
+                });
+            }
+            rows.push(cells);
+        }
+        // A rule under the last row does not make the whole table a header
+        if header_rows == rows.len() {
+            header_rows = 0;
+        }
+
+        let headers = rows
+            .drain(..header_rows)
+            .flatten()
+            .map(|element| TableHeader {
+                element,
+                width: 30.0,
+            })
+            .collect();
+        let rows = rows
+            .into_iter()
+            .map(|cells| TableRow {
+                cells: cells.into_iter().map(TableCell::new).collect(),
+            })
+            .collect();
+        Ok(Element::Table {
+            headers,
+            rows,
+            alignments: vec![],
+            caption: vec![],
+        })
+    }
+
+    /// Splits a line into text, `[[link][description]]` links, bare URLs and inline images
+    fn parse_inline(&self, line: &str, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        let mut rest = line;
+        while !rest.is_empty() {
+            let link = rest.find("[[");
+            let url = ["https://", "http://"]
+                .iter()
+                .filter_map(|scheme| rest.find(scheme))
+                .filter(|index| *index == 0 || !rest[..*index].ends_with(char::is_alphanumeric))
+                .min();
+
+            match (link, url) {
+                (Some(start), url) if url.is_none_or(|url| start < url) => {
+                    let Some(end) = rest[start..].find("]]").map(|end| start + end) else {
+                        push_text(elements, &strip_emphasis(rest));
+                        break;
+                    };
+                    push_text(elements, &strip_emphasis(&rest[..start]));
+                    let (target, description) = match rest[start + 2..end].split_once("][") {
+                        Some((target, description)) => (target, Some(description)),
+                        None => (&rest[start + 2..end], None),
+                    };
+                    self.parse_link(target, description, elements)?;
+                    rest = &rest[end + 2..];
+                }
+                (_, Some(start)) => {
+                    push_text(elements, &strip_emphasis(&rest[..start]));
+                    let end = rest[start..]
+                        .find(char::is_whitespace)
+                        .map(|end| start + end)
+                        .unwrap_or(rest.len());
+                    let url = rest[start..end].trim_end_matches(['.', ',', ';', ':', ')']);
+                    elements.push(Element::Hyperlink {
+                        title: url.to_string(),
+                        url: url.to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    });
+                    rest = &rest[start + url.len()..];
+                }
+                _ => {
+                    push_text(elements, &strip_emphasis(rest));
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Links to image files without a description are inline images
+    fn parse_link(
+        &self,
+        target: &str,
+        description: Option<&str>,
+        elements: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        let path = target.strip_prefix("file:").unwrap_or(target);
+        if description.is_none() && is_image_path(path) && !path.contains("://") {
+            let bytes = (self.image_loader)(path)?;
+            elements.push(Element::Image(ImageData::new(
+                bytes,
+                String::new(),
+                String::new(),
+                path.to_string(),
+                String::new(),
+                ImageDimension::default(),
+            )));
+            return Ok(());
+        }
+        let title = description
+            .map(strip_emphasis)
+            .unwrap_or_else(|| target.to_string());
+        elements.push(Element::Hyperlink {
+            title,
+            url: target.to_string(),
+            alt: String::new(),
+            size: 8,
+        });
+        Ok(())
+    }
+}
+
+/// Drops emphasis markers that open or close a word
+fn strip_emphasis(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::with_capacity(text.len());
+    for (index, c) in chars.iter().enumerate() {
+        if EMPHASIS_MARKERS.contains(c) {
+            let before = index.checked_sub(1).map(|index| chars[index]);
+            let after = chars.get(index + 1).copied();
+            let open = before.is_none_or(|c| c.is_whitespace() || "({'\"-".contains(c))
+                && after.is_some_and(|c| !c.is_whitespace() && !EMPHASIS_MARKERS.contains(&c));
+            let close = before
+                .is_some_and(|c| !c.is_whitespace() && !EMPHASIS_MARKERS.contains(&c))
+                && after.is_none_or(|c| c.is_whitespace() || ".,;:!?)}'\"-".contains(c));
+            // A closing marker needs a matching opening one on the same line
+            let matched = if open {
+                chars[index + 1..].contains(c)
+            } else {
+                close && chars[..index].contains(c)
+            };
+            if matched {
+                continue;
+            }
+        }
+        result.push(*c);
+    }
+    result
+}
+
+fn push_text(elements: &mut Vec<Element>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(Element::Text { text: last, .. }) = elements.last_mut() {
+        last.push_str(text);
+    } else {
+        elements.push(Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        });
+    }
+}
+
+
+struct Generator<F> {
+    image_saver: F,
+    image_num: usize,
+}
+
+impl<F> Generator<F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn block(&mut self, org: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Header { level, text, .. } => {
+                let stars = "*".repeat((*level).max(1) as usize);
+                org.push_str(&format!("{} {}\n\n", stars, text.replace('\n', " ")));
+            }
+            // Multi-line text keeps its layout in an example block
+            Element::Text { text, .. } if text.contains('\n') => {
+                org.push_str("#+BEGIN_EXAMPLE\n");
+                for line in text.lines() {
+                    org.push_str(line);
+                    org.push('\n');
+                }
+                org.push_str("#+END_EXAMPLE\n\n");
+            }
+            // Two blank lines end a list, so adjacent lists stay apart
+            Element::List { elements, numbered } => {
+                self.list(org, elements, *numbered, 0)?;
+                org.push_str("\n\n");
+            }
+            // Description list items, later definitions continue the item on indented lines
+            Element::DefinitionList { items } => {
+                for item in items {
+                    let mut term = String::new();
+                    for child in &item.term {
+                        term.push_str(&self.inline(child)?);
+                    }
+                    org.push_str(&format!("- {} ::", term.replace("\\\\\n", " ").trim()));
+                    for (index, definition) in item.definitions.iter().enumerate() {
+                        let text = self.inline(definition)?.replace("\\\\\n", " ");
+                        if index == 0 {
+                            org.push(' ');
+                        } else {
+                            org.push_str("\n  ");
+                        }
+                        org.push_str(text.trim());
+                    }
+                    org.push('\n');
+                }
+                org.push_str("\n\n");
+            }
+            Element::Chart { .. } => self.block(org, &element.chart_fallback())?,
+            Element::Table { headers, rows, .. } => self.table(org, headers, rows)?,
+            Element::Image(image) => {
+                if let Some(link) = self.image(image)? {
+                    org.push_str(&image_attributes(image, image.title()));
+                    org.push_str(&link);
+                    org.push_str("\n\n");
+                }
+            }
+            Element::Figure { image, caption } => {
+                let mut text = String::new();
+                for child in caption {
+                    text.push_str(&self.inline(child)?);
+                }
+                let text = text.replace("\\\\\n", " ");
+                match self.image(image)? {
+                    Some(link) => {
+                        org.push_str(&image_attributes(image, text.trim()));
+                        org.push_str(&link);
+                    }
+                    None => org.push_str(text.trim()),
+                }
+                org.push_str("\n\n");
+            }
+            Element::Comment { text, .. } => {
+                for line in text.lines() {
+                    org.push_str(format!("# {}", line).trim_end());
+                    org.push('\n');
+                }
+                org.push('\n');
+            }
+            Element::Blockquote { elements, .. } => {
+                org.push_str("#+BEGIN_QUOTE\n");
+                for child in elements {
+                    match child {
+                        // A heading would end the block
+                        Element::Header { text, .. } => {
+                            org.push_str(&format!("*{}*\n\n", text.replace('\n', " ")))
+                        }
+                        child => self.block(org, child)?,
+                    }
+                }
+                org.push_str("#+END_QUOTE\n\n");
+            }
+            // The stars of the headings give the nesting
+            Element::Section { children, .. } => {
+                for child in children {
+                    self.block(org, child)?;
+                }
+            }
+            Element::HorizontalRule => org.push_str("-----\n\n"),
+            // Org has no page breaks, only exporter specific keywords
+            Element::PageBreak => {}
+            Element::TableOfContents { max_level } => {
+                org.push_str(&format!("#+TOC: headlines {}\n\n", max_level))
+            }
+            Element::CodeBlock { language, code } => {
+                match language {
+                    Some(language) => org.push_str(&format!("#+BEGIN_SRC {}\n", language)),
+                    None => org.push_str("#+BEGIN_EXAMPLE\n"),
+                }
+                for line in code.lines() {
+                    // A leading comma protects lines that Org would read as markup
+                    if line.starts_with('*') || line.trim_start().starts_with("#+") {
+                        org.push(',');
+                    }
+                    org.push_str(line);
+                    org.push('\n');
+                }
+                match language {
+                    Some(_) => org.push_str("#+END_SRC\n\n"),
+                    None => org.push_str("#+END_EXAMPLE\n\n"),
+                }
+            }
+            element => {
+                let text = self.inline(element)?;
+                if !text.trim().is_empty() {
+                    org.push_str(text.trim());
+                    org.push_str("\n\n");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn list(
+        &mut self,
+        org: &mut String,
+        items: &[ListItem],
+        numbered: bool,
+        indent: usize,
+    ) -> anyhow::Result<()> {
+        let padding = " ".repeat(indent);
+        let mut number = 0;
+        for item in items {
+            match &item.element {
+                // Nested lists are indented past the parent item marker
+                Element::List { elements, numbered } => {
+                    self.list(org, elements, *numbered, indent + 3)?
+                }
+                element => {
+                    number += 1;
+                    let marker = if numbered {
+                        format!("{}.", number)
+                    } else {
+                        "-".to_string()
+                    };
+                    let text = self.inline(element)?.replace("\\\\\n", " ");
+                    org.push_str(&format!("{}{} {}\n", padding, marker, text.trim()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn table(
+        &mut self,
+        org: &mut String,
+        headers: &[TableHeader],
+        rows: &[TableRow],
+    ) -> anyhow::Result<()> {
+        let header = headers
+            .iter()
+            .map(|header| self.cell(&header.element))
+            .collect::<anyhow::Result<Vec<String>>>()?;
+        let body = rows
+            .iter()
+            .map(|row| {
+                row.cells
+                    .iter()
+                    .map(|cell| self.cell(&cell.element()))
+                    .collect::<anyhow::Result<Vec<String>>>()
+            })
+            .collect::<anyhow::Result<Vec<Vec<String>>>>()?;
+        let columns = body
+            .iter()
+            .map(Vec::len)
+            .chain(std::iter::once(header.len()))
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let mut widths = vec![1; columns];
+        for row in body.iter().chain(std::iter::once(&header)) {
+            for (column, cell) in row.iter().enumerate() {
+                widths[column] = widths[column].max(cell.chars().count());
+            }
+        }
+
+        let row_line = |cells: &[String]| {
+            let mut line = String::from("|");
+            for (column, width) in widths.iter().enumerate() {
+                let cell = cells.get(column).map(String::as_str).unwrap_or_default();
+                let padding = width - cell.chars().count();
+                line.push_str(&format!(" {}{} |", cell, " ".repeat(padding)));
+            }
+            line.push('\n');
+            line
+        };
+        if !headers.is_empty() {
+            org.push_str(&row_line(&header));
+            let rule: Vec<String> = widths.iter().map(|width| "-".repeat(width + 2)).collect();
+            org.push_str(&format!("|{}|\n", rule.join("+")));
+        }
+        for row in &body {
+            org.push_str(&row_line(row));
+        }
+        org.push('\n');
+        Ok(())
+    }
+
+    /// Table cells are single lines, and `|` would end the cell
+    fn cell(&mut self, element: &Element) -> anyhow::Result<String> {
+        Ok(self
+            .inline(element)?
+            .replace("\\\\\n", " ")
+            .replace('|', "\\vert{}"))
+    }
+
+    fn inline(&mut self, element: &Element) -> anyhow::Result<String> {
+        Ok(match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => text.replace('\n', " "),
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => {
+                let mut text = String::new();
+                for child in elements {
+                    text.push_str(&self.inline(child)?);
+                }
+                text
+            }
+            Element::Hyperlink { title, url, .. } => {
+                if title.is_empty() || title == url {
+                    format!("[[{}]]", url)
+                } else {
+                    format!("[[{}][{}]]", url, title.replace(['[', ']'], ""))
+                }
+            }
+            Element::Image(image) => self.image(image)?.unwrap_or_default(),
+            Element::Figure { image, caption } => {
+                let mut text = self.image(image)?.unwrap_or_default();
+                for child in caption {
+                    text.push(' ');
+                    text.push_str(&self.inline(child)?);
+                }
+                text.trim().to_string()
+            }
+            Element::LineBreak | Element::HorizontalRule => "\\\\\n".to_string(),
+            Element::Media { .. } => self.inline(&element.media_fallback())?,
+            Element::Ruby { .. } => self.inline(&element.ruby_fallback())?,
+            Element::PageBreak | Element::TableOfContents { .. } | Element::Custom { .. } => {
+                String::new()
+            }
+            Element::List { elements, .. } => {
+                let mut items = Vec::new();
+                for item in elements {
+                    items.push(self.inline(&item.element)?);
+                }
+                items.join(" ")
+            }
+            Element::DefinitionList { items } => {
+                let mut texts = Vec::new();
+                for child in items
+                    .iter()
+                    .flat_map(|item| item.term.iter().chain(&item.definitions))
+                {
+                    texts.push(self.inline(child)?);
+                }
+                texts.join(" ")
+            }
+            Element::CodeBlock { code, .. } => format!("~{}~", code),
+            Element::Styled { style, elements } => {
+                let mut text = String::new();
+                for child in elements {
+                    text.push_str(&self.inline(child)?);
+                }
+                for (enabled, mark) in [
+                    (style.strikethrough, "+"),
+                    (style.underline, "_"),
+                    (style.italic, "/"),
+                    (style.bold, "*"),
+                ] {
+                    if enabled {
+                        text = format!("{}{}{}", mark, text, mark);
+                    }
+                }
+                text
+            }
+            Element::Math { tex, display } => {
+                if *display {
+                    format!("\\[{}\\]", tex)
+                } else {
+                    format!("\\({}\\)", tex)
+                }
+            }
+            Element::FootnoteReference { id } => format!("[fn:{}]", id),
+            // A definition starts a line, block content follows the label
+            Element::Footnote { id, content } => {
+                let mut text = format!("[fn:{}] ", id);
+                for child in content {
+                    text.push_str(&self.inline(child)?);
+                }
+                text
+            }
+            Element::Table { .. } | Element::Chart { .. } | Element::Comment { .. } => {
+                String::new()
+            }
+        })
+    }
+
+    /// Saves the image and returns the `[[file:...]]` link to it
+    fn image(&mut self, image: &ImageData) -> anyhow::Result<Option<String>> {
+        if image.bytes().is_empty() {
+            return Ok(None);
+        }
+        let target = format!(
+            "image{}{}",
+            self.image_num,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &target)?;
+        self.image_num += 1;
+        Ok(Some(format!("[[file:{}]]", target)))
+    }
+}
+
+/// `#+CAPTION` and `#+ATTR_HTML` lines written above a standalone image
+fn image_attributes(image: &ImageData, caption: &str) -> String {
+    let mut lines = String::new();
+    if !caption.is_empty() {
+        lines.push_str(&format!("#+CAPTION: {}\n", caption));
+    }
+    let mut attributes = String::new();
+    if !image.alt().is_empty() {
+        attributes.push_str(&format!(" :alt {}", image.alt()));
+    }
+    if let Some(width) = &image.size().width {
+        attributes.push_str(&format!(" :width {}", width));
+    }
+    if let Some(height) = &image.size().height {
+        attributes.push_str(&format!(" :height {}", height));
+    }
+    if !attributes.is_empty() {
+        lines.push_str(&format!("#+ATTR_HTML:{}\n", attributes));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::org::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let source = r#"#+TITLE: Notes
+#+AUTHOR: Someone
+
+* TODO Setup :work:
+:PROPERTIES:
+:ID: 42
+:END:
+Install the *cli* first, see [[https://example.com/docs][the docs]]
+or https://example.com/faq.
+
+- One
+   - Nested
+- Two
+
+1. First
+2. Second
+
+| Name | Value |
+|------+-------|
+| a    | 1     |
+| b    | 2     |
+
+#+CAPTION: The logo
+#+ATTR_HTML: :alt Logo :width 200px
+[[file:logo.png]]
+
+#+BEGIN_SRC rust
+  let x = 1;
+#+END_SRC
+
+# A comment
+"#;
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |_| {
+            Ok(Bytes::from_static(b"\x89PNG"))
+        })?;
+        let elements = document.get_all_elements();
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let link = |title: &str, url: &str| Element::Hyperlink {
+            title: title.to_string(),
+            url: url.to_string(),
+            alt: String::new(),
+            size: 8,
+        };
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 1,
+                text: "Notes".to_string(),
+                anchor: None,
+            }
+        );
+        assert_eq!(
+            elements[1],
+            &Element::Header {
+                level: 1,
+                text: "Setup".to_string(),
+                anchor: None,
+            }
+        );
+        assert_eq!(
+            elements[2],
+            &Element::Paragraph {
+                elements: vec![
+                    text("Install the cli first, see "),
+                    link("the docs", "https://example.com/docs"),
+                    text(" or "),
+                    link("https://example.com/faq", "https://example.com/faq"),
+                    text("."),
+                ]
+            }
+        );
+        assert_eq!(
+            elements[3],
+            &Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("One"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: Element::List {
+                            elements: vec![ListItem {
+                                element: text("Nested"),
+                                checked: None,
+                            }],
+                            numbered: false,
+                        },
+                        checked: None,
+                    },
+                    ListItem {
+                        element: text("Two"),
+                        checked: None,
+                    },
+                ],
+                numbered: false,
+            }
+        );
+        assert!(matches!(
+            elements[4],
+            Element::List { numbered: true, elements } if elements.len() == 2
+        ));
+        let Element::Table { headers, rows, .. } = elements[5] else {
+            panic!("expected a table, got {:?}", elements[5]);
+        };
+        assert_eq!(headers[1].element, text("Value"));
+        assert_eq!(rows[1].cells[0].elements, vec![text("b")]);
+        let Element::Image(image) = elements[6] else {
+            panic!("expected an image, got {:?}", elements[6]);
+        };
+        assert_eq!(image.title(), "The logo");
+        assert_eq!(image.alt(), "Logo");
+        assert_eq!(image.size().width.as_deref(), Some("200px"));
+        assert_eq!(elements[7], &text("let x = 1;"));
+        assert_eq!(
+            elements[8],
+            &Element::Comment {
+                text: "A comment".to_string(),
+                author: None,
+                date: None,
+                range: None,
+            }
+        );
+        assert_eq!(elements.len(), 9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let images = std::cell::RefCell::new(std::collections::HashMap::new());
+        let generated = Transformer::generate_with_saver(&parsed, |bytes, name| {
+            images.borrow_mut().insert(name.to_string(), bytes.clone());
+            Ok(())
+        })?;
+        let reparsed = Transformer::parse_with_loader(&generated, |name| {
+            Ok(images.borrow().get(name).cloned().unwrap_or_default())
+        })?;
+        let original = parsed.get_all_elements();
+        let elements = reparsed.get_all_elements();
+        assert_eq!(original.len(), elements.len());
+        for (original, element) in original.iter().zip(elements.iter()) {
+            assert_eq!(
+                std::mem::discriminant(*original),
+                std::mem::discriminant(*element)
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_stray_block_lines() -> anyhow::Result<()> {
+        let source = "Text\n#+END_QUOTE\n#+RESULTS\n#+\nMore\n";
+        let document = Transformer::parse(&Bytes::from(source))?;
+        assert_eq!(document.to_plain_text(), "Text\n\nMore");
+
+        let source = "#+BEGIN_QUOTE\n#+BEGIN_QUOTE\nx\n#+END_QUOTE\n#+END_QUOTE\nAfter\n";
+        let document = Transformer::parse(&Bytes::from(source))?;
+        assert_eq!(document.to_plain_text(), "x\n\nAfter");
+
+        // A nested blockquote reads back
+        let quote = |elements| Element::Blockquote {
+            elements,
+            attribution: vec![],
+        };
+        let document = Document::new(vec![quote(vec![quote(vec![Element::Paragraph {
+            elements: vec![Element::Text {
+                text: "x".to_string(),
+                font: FontSpec::from(8),
+            }],
+        }])])]);
+        let generated = Transformer::generate(&document)?;
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.to_plain_text(), "x");
+        Ok(())
+    }
+}
@@ -0,0 +1,278 @@
+//! Helpers shared by the readers: a minimal XML element tree, entries of zip containers and the
+//! cleanup of parsed text
+#[cfg(any(
+    feature = "epub",
+    feature = "odt",
+    feature = "docbook",
+    feature = "fb2",
+    feature = "pptx",
+    feature = "org",
+    feature = "rst",
+    feature = "typst"
+))]
+use crate::core::*;
+#[cfg(any(feature = "odt", feature = "docbook", feature = "fb2", feature = "pptx"))]
+use quick_xml::{
+    events::{BytesStart, Event},
+    name::QName,
+    Reader,
+};
+#[cfg(any(feature = "odt", feature = "docbook", feature = "fb2", feature = "pptx"))]
+use std::collections::HashMap;
+#[cfg(any(feature = "epub", feature = "odt", feature = "pptx"))]
+use std::io::{Cursor, Read};
+#[cfg(any(feature = "epub", feature = "odt", feature = "pptx"))]
+use zip::ZipArchive;
+
+/// How [`parse_xml`] names elements and attributes
+#[cfg(any(feature = "odt", feature = "docbook", feature = "fb2", feature = "pptx"))]
+pub(crate) enum Names {
+    /// As written, with the namespace prefix
+    #[cfg_attr(not(feature = "odt"), allow(dead_code))]
+    Qualified,
+    /// Without the namespace prefix, except attributes with one of these prefixes
+    #[cfg_attr(not(any(feature = "docbook", feature = "fb2", feature = "pptx")), allow(dead_code))]
+    Local(&'static [&'static str]),
+}
+
+/// Minimal element tree, the XML of the formats read this way is small enough to be held in
+/// memory
+#[cfg(any(feature = "odt", feature = "docbook", feature = "fb2", feature = "pptx"))]
+pub(crate) struct XmlNode {
+    pub(crate) name: String,
+    attributes: HashMap<String, String>,
+    pub(crate) children: Vec<XmlChild>,
+}
+
+#[cfg(any(feature = "odt", feature = "docbook", feature = "fb2", feature = "pptx"))]
+pub(crate) enum XmlChild {
+    Node(XmlNode),
+    Text(String),
+    /// Trimmed text of a comment
+    Comment(#[cfg_attr(not(feature = "fb2"), allow(dead_code))] String),
+    /// Target and content of a processing instruction
+    Instruction(#[cfg_attr(not(feature = "docbook"), allow(dead_code))] String),
+}
+
+#[cfg(any(feature = "odt", feature = "docbook", feature = "fb2", feature = "pptx"))]
+impl XmlNode {
+    fn new(name: String) -> XmlNode {
+        XmlNode {
+            name,
+            attributes: HashMap::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn from_start(
+        start: &BytesStart,
+        reader: &Reader<&[u8]>,
+        names: &Names,
+    ) -> anyhow::Result<XmlNode> {
+        let name = |name: QName, keep: &[&str]| match names {
+            Names::Qualified => String::from_utf8(name.as_ref().to_vec()),
+            Names::Local(_) => match name.prefix() {
+                Some(prefix) if keep.iter().any(|keep| keep.as_bytes() == prefix.as_ref()) => {
+                    String::from_utf8(name.as_ref().to_vec())
+                }
+                _ => String::from_utf8(name.local_name().as_ref().to_vec()),
+            },
+        };
+        let keep = match names {
+            Names::Qualified => &[][..],
+            Names::Local(keep) => keep,
+        };
+        let mut node = XmlNode::new(name(start.name(), &[])?);
+        for attribute in start.attributes() {
+            let attribute = attribute?;
+            node.attributes.insert(
+                name(attribute.key, keep)?,
+                attribute.decode_and_unescape_value(reader)?.to_string(),
+            );
+        }
+        Ok(node)
+    }
+
+    pub(crate) fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str)
+    }
+
+    pub(crate) fn nodes(&self) -> impl Iterator<Item = &XmlNode> {
+        self.children.iter().filter_map(|child| match child {
+            XmlChild::Node(node) => Some(node),
+            _ => None,
+        })
+    }
+
+    pub(crate) fn find(&self, name: &str) -> Option<&XmlNode> {
+        self.nodes().find(|node| node.name == name)
+    }
+
+    /// Finds the first descendant with the given name
+    #[cfg(feature = "docbook")]
+    pub(crate) fn descendant(&self, name: &str) -> Option<&XmlNode> {
+        self.nodes().find_map(|node| {
+            (node.name == name)
+                .then_some(node)
+                .or_else(|| node.descendant(name))
+        })
+    }
+
+    /// Follows a path of child names
+    #[cfg(feature = "pptx")]
+    pub(crate) fn path(&self, names: &[&str]) -> Option<&XmlNode> {
+        names.iter().try_fold(self, |node, name| node.find(name))
+    }
+
+    /// Text content without comments and processing instructions
+    #[cfg_attr(not(any(feature = "fb2", feature = "pptx")), allow(dead_code))]
+    pub(crate) fn text(&self) -> String {
+        self.text_with(&|_| None)
+    }
+
+    /// Text content, `child_text` gives the text of the children the format reads differently
+    pub(crate) fn text_with(&self, child_text: &dyn Fn(&XmlChild) -> Option<String>) -> String {
+        let mut text = String::new();
+        for child in &self.children {
+            match (child_text(child), child) {
+                (Some(value), _) => text.push_str(&value),
+                (None, XmlChild::Text(value)) => text.push_str(value),
+                (None, XmlChild::Node(node)) => text.push_str(&node.text_with(child_text)),
+                (None, XmlChild::Comment(_) | XmlChild::Instruction(_)) => {}
+            }
+        }
+        text
+    }
+}
+
+#[cfg(any(feature = "odt", feature = "docbook", feature = "fb2", feature = "pptx"))]
+pub(crate) fn parse_xml(xml: &[u8], names: Names) -> anyhow::Result<XmlNode> {
+    let mut reader = Reader::from_reader(xml);
+    let mut stack = vec![XmlNode::new(String::new())];
+    loop {
+        let child = match reader.read_event()? {
+            Event::Start(ref e) => {
+                stack.push(XmlNode::from_start(e, &reader, &names)?);
+                continue;
+            }
+            Event::Empty(ref e) => XmlChild::Node(XmlNode::from_start(e, &reader, &names)?),
+            Event::End(_) => XmlChild::Node(
+                stack
+                    .pop()
+                    .ok_or_else(|| ParserError::Malformed("unbalanced XML".to_string()))?,
+            ),
+            Event::Text(e) => XmlChild::Text(e.unescape()?.to_string()),
+            Event::CData(e) => XmlChild::Text(String::from_utf8(e.into_inner().to_vec())?),
+            Event::Comment(e) => XmlChild::Comment(e.unescape()?.trim().to_string()),
+            Event::PI(e) => XmlChild::Instruction(String::from_utf8(e.into_inner().to_vec())?),
+            Event::Eof => break,
+            _ => continue,
+        };
+        stack
+            .last_mut()
+            .ok_or_else(|| ParserError::Malformed("unbalanced XML".to_string()))?
+            .children
+            .push(child);
+    }
+    if stack.len() != 1 {
+        return Err(ParserError::Malformed("unclosed XML element".to_string()).into());
+    }
+    let document = stack.remove(0);
+    document
+        .children
+        .into_iter()
+        .find_map(|child| match child {
+            XmlChild::Node(node) => Some(node),
+            _ => None,
+        })
+        .ok_or_else(|| ParserError::Malformed("empty XML document".to_string()).into())
+}
+
+/// Reads an entry of a zip container, a missing one makes the document malformed
+#[cfg(any(feature = "epub", feature = "odt", feature = "pptx"))]
+pub(crate) fn read_entry(
+    archive: &mut ZipArchive<Cursor<Vec<u8>>>,
+    name: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|_| ParserError::Malformed(format!("missing {} in the container", name)))?;
+    let mut bytes = Vec::new();
+    entry.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Collapses runs of whitespace other than no-break spaces into one space
+#[cfg(any(feature = "docbook", feature = "fb2", feature = "latex"))]
+pub(crate) fn collapse_whitespace(text: &str) -> String {
+    collapse_whitespace_with(text, |c| c.is_whitespace() && c != '\u{a0}')
+}
+
+/// Collapses runs of the characters `is_space` accepts into one space
+#[cfg(any(feature = "docbook", feature = "fb2", feature = "latex", feature = "odt"))]
+pub(crate) fn collapse_whitespace_with(text: &str, is_space: impl Fn(char) -> bool) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut last_space = false;
+    for c in text.chars() {
+        if is_space(c) {
+            if !last_space {
+                result.push(' ');
+            }
+            last_space = true;
+        } else {
+            result.push(c);
+            last_space = false;
+        }
+    }
+    result
+}
+
+/// The only element of a paragraph in place of the paragraph, for content that is a single
+/// paragraph such as a list item or a table cell
+#[cfg(any(
+    feature = "odt",
+    feature = "docbook",
+    feature = "org",
+    feature = "rst",
+    feature = "typst"
+))]
+pub(crate) fn unwrap_paragraph(element: Element) -> Element {
+    match element {
+        Element::Paragraph { mut elements } if elements.len() == 1 => elements.remove(0),
+        element => element,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse_util::*;
+
+    #[test]
+    #[cfg(feature = "docbook")]
+    fn test_parse_xml() -> anyhow::Result<()> {
+        let xml = br#"<a:doc xmlns:a="urn:a" a:lang="en" r:id="rId1"><a:p>One<?linebreak?><![CDATA[<two>]]></a:p></a:doc>"#;
+        let root = parse_xml(xml, Names::Local(&["r"]))?;
+        assert_eq!(root.name, "doc");
+        assert_eq!(root.attribute("lang"), Some("en"));
+        assert_eq!(root.attribute("r:id"), Some("rId1"));
+        let paragraph = root.find("p").unwrap();
+        assert!(matches!(&paragraph.children[1], XmlChild::Instruction(pi) if pi == "linebreak"));
+        assert_eq!(paragraph.text(), "One<two>");
+
+        let root = parse_xml(xml, Names::Qualified)?;
+        assert_eq!(root.find("a:p").unwrap().text(), "One<two>");
+        assert_eq!(root.attribute("a:lang"), Some("en"));
+        let root = parse_xml(b"<a><!-- A note -->text</a>", Names::Qualified)?;
+        assert!(matches!(&root.children[0], XmlChild::Comment(text) if text == "A note"));
+        assert_eq!(root.text(), "text");
+        assert!(parse_xml(b"<a><b></a>", Names::Qualified).is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "latex")]
+    fn test_collapse_whitespace() {
+        assert_eq!(collapse_whitespace(" a \n\t b\u{a0}\u{a0}c "), " a b\u{a0}\u{a0}c ");
+        assert_eq!(collapse_whitespace_with("a \t b", |c| c == ' '), "a \t b");
+    }
+}
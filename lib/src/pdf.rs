@@ -1,13 +1,20 @@
 use crate::core::Element::{List, Paragraph, Text};
-use crate::core::{Document, Element, ListItem, ParserError, TransformerTrait};
+use crate::core::{
+    CancellationToken, Document, Element, FontSpec, ListItem, Metadata, ParserError,
+    SourceSpan, TransformerTrait,
+};
 
 use anyhow;
 use bytes::Bytes;
 use log::{debug, warn};
 use lopdf::content::Content;
-use lopdf::{Document as PdfDocument, Object, ObjectId};
+use lopdf::xref::XrefEntry;
+use lopdf::{Document as PdfDocument, Object, ObjectId, Stream};
 use std::collections::BTreeMap;
-use typst::{eval::Tracer, foundations::Smart};
+use std::ops::RangeInclusive;
+use typst::foundations::Smart;
+
+pub use crate::typst::{Margins, PdfOptions};
 
 /// Attempts to decode PDF text bytes using multiple fallback strategies
 fn decode_pdf_text_robust(encoding: Option<&str>, bytes: &[u8]) -> String {
@@ -72,36 +79,98 @@ fn decode_pdf_text_robust(encoding: Option<&str>, bytes: &[u8]) -> String {
     decoded_text
 }
 
+/// Options for PDF parsing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ParseOptions {
+    /// Checked before each page is parsed.
+    pub cancellation: Option<CancellationToken>,
+    /// Pages to parse, from 1. All of them by default.
+    pub pages: Option<RangeInclusive<usize>>,
+}
+
+/// `TJ` arrays nested deeper than this are skipped instead of being walked recursively
+const MAX_TEXT_ARRAY_DEPTH: usize = 32;
+
 pub struct Transformer;
 impl TransformerTrait for Transformer {
     fn parse(document: &Bytes) -> anyhow::Result<Document> {
-        let mut elements: Vec<Element> = Vec::new();
-        let pdf_document = PdfDocument::load_mem(document)?;
-        use crate::core::{ImageData, ImageDimension};
-        for (_id, page_id) in pdf_document.get_pages() {
-            // Extract images from page resources
-            let (resources_opt, _) = pdf_document.get_page_resources(page_id);
-            if let Some(resources) = resources_opt {
-                if let Ok(xobjects) = resources.get(b"XObject") {
-                    if let Ok(xobj_dict) = xobjects.as_dict() {
-                        for (name, xobj_ref) in xobj_dict.iter() {
-                            if let Ok(xobj_id) = xobj_ref.as_reference() {
-                                if let Ok(xobj) = pdf_document.get_object(xobj_id) {
-                                    if let Ok(dict) = xobj.as_dict() {
-                                        if let Ok(subtype) = dict.get(b"Subtype") {
-                                            if subtype.as_name_str()? == "Image" {
-                                                if let Ok(stream) = xobj.as_stream() {
-                                                    let image_bytes = Bytes::from(stream.content.clone());
-                                                    let image_data = ImageData::new(
-                                                        image_bytes,
-                                                        format!("PDF Image {}", String::from_utf8_lossy(name)),
-                                                        "PDF Image".to_string(),
-                                                        "png".to_string(), // Assume PNG for now
-                                                        "center".to_string(),
-                                                        ImageDimension::default(),
-                                                    );
-                                                    elements.push(Element::Image(image_data));
-                                                }
+        Transformer::parse_with_options(document, &ParseOptions::default())
+    }
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_options(document, &PdfOptions::default())
+    }
+}
+
+impl Transformer {
+    /// Parse a PDF using the given options.
+    pub fn parse_with_options(
+        document: &Bytes,
+        options: &ParseOptions,
+    ) -> anyhow::Result<Document> {
+        parse_document(document, options)
+    }
+
+    /// Generate a PDF using the given options (page margins...)
+    pub fn generate_with_options(
+        document: &Document,
+        options: &PdfOptions,
+    ) -> anyhow::Result<Bytes> {
+        let compiled = crate::typst::compile_document(document, options)?;
+
+        // Converting to pdf then to bytes
+        let pdf = typst_pdf::pdf(&compiled, Smart::Auto, None);
+
+        let bytes = Bytes::from(add_metadata(pdf, &document.metadata)?);
+        Ok(bytes)
+    }
+}
+
+fn parse_document(document: &Bytes, options: &ParseOptions) -> anyhow::Result<Document> {
+    use crate::core::{ImageData, ImageDimension};
+    let mut elements: Vec<Element> = Vec::new();
+    let mut spans = BTreeMap::new();
+    let pdf_document = load_document(document)?;
+    let pages = pdf_document.get_pages();
+    let page_count = pages.len();
+    for (page_number, page_id) in pages {
+        if let Some(pages) = &options.pages {
+            if !pages.contains(&(page_number as usize)) {
+                continue;
+            }
+        }
+        crate::telemetry::page(page_number as usize, page_count);
+        let page_start = elements.len();
+        if let Some(token) = &options.cancellation {
+            if token.is_cancelled() {
+                return Err(ParserError::Cancelled.into());
+            }
+        }
+        // Extract images from page resources
+        let (resources_opt, _) = pdf_document.get_page_resources(page_id);
+        if let Some(resources) = resources_opt {
+            if let Ok(xobjects) = resources.get(b"XObject") {
+                if let Ok(xobj_dict) = xobjects.as_dict() {
+                    for (name, xobj_ref) in xobj_dict.iter() {
+                        if let Ok(xobj_id) = xobj_ref.as_reference() {
+                            if let Ok(xobj) = pdf_document.get_object(xobj_id) {
+                                if let Ok(dict) = xobj.as_dict() {
+                                    if let Ok(subtype) = dict.get(b"Subtype") {
+                                        if matches!(subtype.as_name_str(), Ok("Image")) {
+                                            if let Ok(stream) = xobj.as_stream() {
+                                                let image_bytes =
+                                                    Bytes::from(stream.content.clone());
+                                                let image_data = ImageData::new(
+                                                    image_bytes,
+                                                    format!(
+                                                        "PDF Image {}",
+                                                        String::from_utf8_lossy(name)
+                                                    ),
+                                                    "PDF Image".to_string(),
+                                                    "png".to_string(), // Assume PNG for now
+                                                    "center".to_string(),
+                                                    ImageDimension::default(),
+                                                );
+                                                elements.push(Element::Image(image_data));
                                             }
                                         }
                                     }
@@ -111,36 +180,309 @@ impl TransformerTrait for Transformer {
                     }
                 }
             }
-            let objects = pdf_document.get_page_contents(page_id);
-            for object_id in objects {
-                let object = pdf_document.get_object(object_id)?;
-                parse_object(page_id, &pdf_document, object, &mut elements)?;
-            }
         }
-        Ok(Document::new(elements))
+        let objects = pdf_document.get_page_contents(page_id);
+        for object_id in objects {
+            let object = pdf_document.get_object(object_id)?;
+            parse_object(page_id, &pdf_document, object, &mut elements)?;
+        }
+        elements.extend(page_comments(&pdf_document, page_id));
+        // Paragraphs and lists continued from the page before keep the page they start on
+        for index in page_start..elements.len() {
+            spans.insert(vec![0, index], SourceSpan::on_page(page_number as usize));
+        }
     }
-    fn generate(document: &Document) -> anyhow::Result<Bytes> {
-        let (text, img_map) = crate::typst::generate_document(document)?;
+    let mut parsed = Document::new(elements);
+    parsed.source_spans = spans;
+    parsed.metadata = document_metadata(&pdf_document);
+    Ok(parsed)
+}
+
+/// Loads the document without the object streams lopdf would read past the end of, a document
+/// with objects stored in one of those is malformed
+fn load_document(document: &[u8]) -> anyhow::Result<PdfDocument> {
+    let reader = lopdf::Reader {
+        buffer: document,
+        document: PdfDocument::new(),
+    };
+    let pdf_document = reader.read(Some(skip_malformed_object_stream))?;
+    let missing = pdf_document
+        .reference_table
+        .entries
+        .values()
+        .find_map(|entry| match *entry {
+            XrefEntry::Compressed { container, .. }
+                if !pdf_document.objects.contains_key(&(container, 0)) =>
+            {
+                Some(container)
+            }
+            _ => None,
+        });
+    match missing {
+        Some(container) => Err(ParserError::Malformed(format!(
+            "Object stream {} is missing or malformed",
+            container
+        ))
+        .into()),
+        None => Ok(pdf_document),
+    }
+}
 
-        let world = crate::typst::ShivaWorld::new(text, img_map);
-        let mut tracer = Tracer::default();
+/// Leaves out an object stream when its index has an offset past the end of the stream
+fn skip_malformed_object_stream(
+    object_id: ObjectId,
+    object: &mut Object,
+) -> Option<(ObjectId, Object)> {
+    if let Ok(stream) = object.as_stream_mut() {
+        if stream.dict.type_is(b"ObjStm") {
+            // lopdf decompresses it before reading the objects as well
+            stream.decompress();
+            if !object_offsets_in_bounds(stream) {
+                warn!("Skipping malformed object stream {:?}", object_id);
+                return None;
+            }
+        }
+    }
+    Some((object_id, object.clone()))
+}
 
-        let document = typst::compile(&world, &mut tracer).unwrap();
-        let warnings = tracer.warnings();
+/// The index of an object stream is pairs of object numbers and offsets from `First`
+fn object_offsets_in_bounds(stream: &Stream) -> bool {
+    let first = stream
+        .dict
+        .get(b"First")
+        .and_then(Object::as_i64)
+        .unwrap_or(0)
+        .max(0) as usize;
+    // lopdf reports an index it can't read as an error
+    let Some(Ok(index)) = stream.content.get(..first).map(std::str::from_utf8) else {
+        return true;
+    };
+    let numbers: Vec<Option<u32>> = index
+        .split_whitespace()
+        .map(|number| number.parse().ok())
+        .collect();
+    numbers.chunks_exact(2).all(|pair| match pair {
+        [Some(_), Some(offset)] => first + *offset as usize <= stream.content.len(),
+        _ => true,
+    })
+}
 
-        if !warnings.is_empty() {
-            // Trowing any warnings if necessary
-            for warn in warnings {
-                warn!("Warning - {}", warn.message);
+/// The metadata of the document information dictionary and the language of the catalog. The
+/// names of the programs that made the file are skipped
+fn document_metadata(pdf_document: &PdfDocument) -> Metadata {
+    let mut metadata = Metadata {
+        language: pdf_document
+            .catalog()
+            .and_then(|catalog| catalog.get(b"Lang"))
+            .and_then(|language| language.as_str())
+            .map(pdf_string)
+            .ok(),
+        ..Metadata::default()
+    };
+    let info = pdf_document
+        .trailer
+        .get(b"Info")
+        .and_then(|info| pdf_document.dereference(info))
+        .and_then(|(_, info)| info.as_dict());
+    let Ok(info) = info else {
+        return metadata;
+    };
+    for (key, value) in info.iter() {
+        let Ok(value) = pdf_document
+            .dereference(value)
+            .and_then(|(_, value)| value.as_str())
+            .map(pdf_string)
+        else {
+            continue;
+        };
+        match key.as_slice() {
+            b"Title" => metadata.title = Some(value),
+            b"Author" => metadata.authors = Metadata::split_keywords(&value),
+            b"Subject" => metadata.subject = Some(value),
+            b"Keywords" => metadata.keywords = Metadata::split_keywords(&value),
+            b"CreationDate" => metadata.created = Some(pdf_date(&value)),
+            b"ModDate" => metadata.modified = Some(pdf_date(&value)),
+            b"Creator" | b"Producer" | b"Trapped" => {}
+            key => {
+                let key = String::from_utf8_lossy(key).to_string();
+                metadata.custom.insert(key, value);
             }
         }
+    }
+    metadata
+}
 
-        // Converting to pdf then to bytes
-        let pdf = typst_pdf::pdf(&document, Smart::Auto, None);
+/// Writes the subject, dates and custom properties to the document information dictionary,
+/// typst writes the title, authors and keywords
+fn add_metadata(pdf: Vec<u8>, metadata: &Metadata) -> anyhow::Result<Vec<u8>> {
+    let mut entries: Vec<(String, String)> = metadata
+        .custom
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    if let Some(subject) = &metadata.subject {
+        entries.push(("Subject".to_string(), subject.clone()));
+    }
+    if let Some(created) = &metadata.created {
+        entries.push(("CreationDate".to_string(), to_pdf_date(created)));
+    }
+    if let Some(modified) = &metadata.modified {
+        entries.push(("ModDate".to_string(), to_pdf_date(modified)));
+    }
+    if entries.is_empty() {
+        return Ok(pdf);
+    }
+    let mut pdf_document = PdfDocument::load_mem(&pdf)?;
+    let info_id = match pdf_document.trailer.get(b"Info").and_then(Object::as_reference) {
+        Ok(id) => id,
+        Err(_) => {
+            let id = pdf_document.add_object(lopdf::Dictionary::new());
+            pdf_document.trailer.set("Info", id);
+            id
+        }
+    };
+    let info = pdf_document.get_object_mut(info_id)?.as_dict_mut()?;
+    for (key, value) in entries {
+        info.set(key, text_string(&value));
+    }
+    let mut bytes = Vec::new();
+    pdf_document.save_to(&mut bytes)?;
+    Ok(bytes)
+}
 
-        let bytes = Bytes::from(pdf);
-        Ok(bytes)
+/// A PDF text string, PDFDocEncoding for ASCII and UTF-16 otherwise
+fn text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        return Object::string_literal(text);
     }
+    let mut bytes = vec![0xFE, 0xFF];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+    Object::String(bytes, lopdf::StringFormat::Hexadecimal)
+}
+
+/// An ISO 8601 date as a PDF date, left as written when it is not one
+fn to_pdf_date(date: &str) -> String {
+    // The zone follows the time
+    let (local, zone) = match date.find('T') {
+        Some(time) => match date[time..].find(['Z', '+', '-']) {
+            Some(zone) => date.split_at(time + zone),
+            None => (date, ""),
+        },
+        None => (date, ""),
+    };
+    let digits: String = local.chars().filter(char::is_ascii_digit).collect();
+    let well_formed = local
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '-' | ':' | 'T'));
+    if !well_formed
+        || digits.len() < 4
+        || digits.len() > 14
+        || !digits.len().is_multiple_of(2)
+    {
+        return date.to_string();
+    }
+    let zone = match zone {
+        "" => String::new(),
+        "Z" => "Z".to_string(),
+        zone if zone.len() == 6 && zone.is_ascii() => format!("{}'{}'", &zone[..3], &zone[4..]),
+        _ => return date.to_string(),
+    };
+    format!("D:{}{}", digits, zone)
+}
+
+/// Review comments of a page, from the annotations that carry a note. Links, form fields and
+/// the popups showing other annotations are skipped
+fn page_comments(pdf_document: &PdfDocument, page_id: ObjectId) -> Vec<Element> {
+    let annotations = pdf_document
+        .get_dictionary(page_id)
+        .and_then(|page| page.get(b"Annots"))
+        .and_then(|annotations| pdf_document.dereference(annotations))
+        .and_then(|(_, annotations)| annotations.as_array());
+    let Ok(annotations) = annotations else {
+        return Vec::new();
+    };
+    let text = |annotation: &lopdf::Dictionary, key: &[u8]| {
+        annotation
+            .get(key)
+            .and_then(|value| pdf_document.dereference(value))
+            .and_then(|(_, value)| value.as_str())
+            .map(pdf_string)
+            .ok()
+            .filter(|text| !text.is_empty())
+    };
+    annotations
+        .iter()
+        .filter_map(|annotation| pdf_document.dereference(annotation).ok())
+        .filter_map(|(_, annotation)| annotation.as_dict().ok())
+        .filter(|annotation| {
+            !matches!(
+                annotation
+                    .get(b"Subtype")
+                    .and_then(|subtype| subtype.as_name_str()),
+                Ok("Link" | "Widget" | "Popup")
+            )
+        })
+        .filter_map(|annotation| {
+            Some(Element::Comment {
+                author: text(annotation, b"T"),
+                date: text(annotation, b"M").map(|date| pdf_date(&date)),
+                text: text(annotation, b"Contents")?,
+                range: None,
+            })
+        })
+        .collect()
+}
+
+/// A PDF text string, UTF-16 when it starts with a byte order mark and PDFDocEncoding
+/// (read as Latin-1) otherwise
+fn pdf_string(bytes: &[u8]) -> String {
+    match bytes {
+        [0xFE, 0xFF, utf16 @ ..] => {
+            let units: Vec<u16> = utf16
+                .chunks_exact(2)
+                .map(|chunk| u16::from_be_bytes([chunk[0], chunk[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        }
+        _ => bytes.iter().map(|&b| b as char).collect(),
+    }
+}
+
+/// A PDF date (`D:YYYYMMDDHHmmSSOHH'mm`) as an ISO 8601 date, left as written when it is not one
+fn pdf_date(date: &str) -> String {
+    let digits = date.strip_prefix("D:").unwrap_or(date);
+    let end = digits
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(digits.len());
+    if end < 4 || end % 2 != 0 || end > 14 {
+        return date.to_string();
+    }
+    // Missing fields take their earliest value
+    let fields = format!("{}{}", &digits[..end], &"00000101000000"[end..]);
+    let offset = match &digits[end..] {
+        "" => String::new(),
+        zone if zone.starts_with('Z') => "Z".to_string(),
+        zone if !zone.is_ascii() => return date.to_string(),
+        zone => {
+            let zone = zone.replace('\'', "");
+            match zone.len() {
+                3 => format!("{}:00", zone),
+                5 => format!("{}:{}", &zone[..3], &zone[3..]),
+                _ => return date.to_string(),
+            }
+        }
+    };
+    format!(
+        "{}-{}-{}T{}:{}:{}{}",
+        &fields[..4],
+        &fields[4..6],
+        &fields[6..8],
+        &fields[8..10],
+        &fields[10..12],
+        &fields[12..14],
+        offset
+    )
 }
 
 fn parse_object(
@@ -154,7 +496,12 @@ fn parse_object(
         encoding: Option<&str>,
         operands: &[Object],
         elements: &mut Vec<Element>,
+        depth: usize,
     ) -> anyhow::Result<()> {
+        if depth > MAX_TEXT_ARRAY_DEPTH {
+            warn!("Skipping text array nested deeper than {}", MAX_TEXT_ARRAY_DEPTH);
+            return Ok(());
+        }
         for operand in operands.iter() {
             debug!("2 {:?}", operand);
             match *operand {
@@ -162,81 +509,44 @@ fn parse_object(
                     let decoded_text = decode_pdf_text_robust(encoding, bytes);
                     text.push_str(&decoded_text);
                     if bytes.len() == 1 && bytes[0] == 1 {
-                        match elements.last() {
-                            None => {
-                                let list_element = List {
+                        match elements.last_mut() {
+                            Some(List {
+                                elements: list_elements,
+                                ..
+                            }) => {
+                                list_elements.push(ListItem {
+                                    element: Text {
+                                        text: text.clone(),
+                                        font: FontSpec::from(8),
+                                    },
+                                    checked: None,
+                                });
+                                text.clear();
+                            }
+                            Some(Paragraph {
+                                elements: paragraph_elements,
+                            }) => {
+                                paragraph_elements.push(Text {
+                                    text: text.clone(),
+                                    font: FontSpec::from(8),
+                                });
+                                text.clear();
+                                elements.push(List {
                                     elements: vec![],
                                     numbered: false,
-                                };
-                                elements.push(list_element);
+                                });
                             }
-                            Some(el) => {
-                                match el {
-                                    List { .. } => {
-                                        let old_list = elements.pop().unwrap();
-                                        // let list = old_list.list_as_ref()?;
-                                        if let List {
-                                            elements: list_elements,
-                                            numbered,
-                                        } = old_list
-                                        {
-                                            let mut list_item_elements = list_elements.clone();
-                                            let text_element = Text {
-                                                text: text.clone(),
-                                                size: 8,
-                                            };
-                                            let new_list_item_element = ListItem {
-                                                element: text_element,
-                                            };
-                                            list_item_elements.push(new_list_item_element);
-                                            let new_list = List {
-                                                elements: list_item_elements,
-                                                numbered,
-                                            };
-                                            elements.push(new_list);
-                                            text.clear();
-                                        }
-                                    }
-                                    Paragraph { .. } => {
-                                        let old_paragraph = elements.pop().unwrap();
-                                        // let paragraph = old_paragraph.paragraph_as_ref()?;
-                                        if let Paragraph {
-                                            elements: paragraph_elements,
-                                        } = old_paragraph
-                                        {
-                                            let mut paragraph_elements = paragraph_elements.clone();
-                                            let text_element = Text {
-                                                text: text.clone(),
-                                                size: 8,
-                                            };
-                                            paragraph_elements.push(text_element);
-                                            let new_paragraph = Paragraph {
-                                                elements: paragraph_elements,
-                                            };
-                                            elements.push(new_paragraph);
-                                            text.clear();
-
-                                            let list_element = List {
-                                                elements: vec![],
-                                                numbered: false,
-                                            };
-                                            elements.push(list_element);
-                                        }
-                                    }
-                                    _ => {
-                                        let list_element = List {
-                                            elements: vec![],
-                                            numbered: false,
-                                        };
-                                        elements.push(*Box::new(list_element));
-                                    }
-                                }
+                            _ => {
+                                elements.push(List {
+                                    elements: vec![],
+                                    numbered: false,
+                                });
                             }
                         }
                     }
                 }
                 Object::Array(ref arr) => {
-                    let _ = collect_text(text, encoding, arr, elements);
+                    let _ = collect_text(text, encoding, arr, elements, depth + 1);
                     text.push(' ');
                 }
                 Object::Integer(i) => {
@@ -270,34 +580,22 @@ fn parse_object(
             "Tm" => {
                 let text_element = Text {
                     text: text.clone(),
-                    size: 8,
+                    font: FontSpec::from(8),
                 };
-                match elements.last() {
+                match elements.last_mut() {
                     None => {
-                        let paragraph_element = Paragraph {
+                        elements.push(Paragraph {
                             elements: vec![text_element],
-                        };
-                        elements.push(paragraph_element);
+                        });
+                    }
+                    Some(Paragraph {
+                        elements: paragraph_elements,
+                    }) => {
+                        paragraph_elements.push(text_element);
+                    }
+                    Some(_) => {
+                        elements.push(text_element);
                     }
-                    Some(el) => match el {
-                        Paragraph { .. } => {
-                            let old_paragraph = elements.pop().unwrap();
-                            if let Paragraph {
-                                elements: paragraph_elements,
-                            } = old_paragraph
-                            {
-                                let mut paragraph_elements = paragraph_elements.clone();
-                                paragraph_elements.push(text_element);
-                                let new_paragraph = Paragraph {
-                                    elements: paragraph_elements,
-                                };
-                                elements.push(new_paragraph);
-                            }
-                        }
-                        _ => {
-                            elements.push(text_element);
-                        }
-                    },
                 }
                 text.clear();
             }
@@ -305,12 +603,14 @@ fn parse_object(
                 let current_font = operation
                     .operands
                     .first()
-                    .ok_or(ParserError::Common)?
+                    .ok_or_else(|| {
+                        ParserError::Malformed("Tf operator without a font operand".to_string())
+                    })?
                     .as_name()?;
                 current_encoding = encodings.get(current_font).cloned();
             }
             "Tj" | "TJ" => {
-                _ = collect_text(&mut text, current_encoding, &operation.operands, elements);
+                _ = collect_text(&mut text, current_encoding, &operation.operands, elements, 0);
             }
             "ET" => {
                 if !text.ends_with('\n') {
@@ -324,54 +624,29 @@ fn parse_object(
     if !text.is_empty() {
         let text_element = Text {
             text: text.clone(),
-            size: 8,
+            font: FontSpec::from(8),
         };
-        match elements.last() {
+        match elements.last_mut() {
             None => {
-                let paragraph_element = Paragraph {
+                elements.push(Paragraph {
                     elements: vec![text_element],
-                };
-                elements.push(*Box::new(paragraph_element));
+                });
             }
-            Some(el) => {
-                match el {
-                    Paragraph { .. } => {
-                        let old_paragraph = elements.pop().unwrap();
-                        if let Paragraph {
-                            elements: paragraph_elements,
-                        } = old_paragraph
-                        {
-                            let mut paragraph_elements = paragraph_elements.clone();
-                            paragraph_elements.push(text_element);
-                            let new_paragraph = Paragraph {
-                                elements: paragraph_elements,
-                            };
-                            elements.push(*Box::new(new_paragraph));
-                        }
-                    }
-                    List { .. } => {
-                        let old_list = elements.pop().unwrap();
-                        // let list = old_list.list_as_ref()?;
-                        if let List {
-                            elements: list_elements,
-                            numbered,
-                        } = old_list
-                        {
-                            let mut list_item_elements = list_elements.clone();
-                            let new_list_item_element = ListItem {
-                                element: text_element,
-                            };
-                            list_item_elements.push(new_list_item_element);
-                            let new_list = List {
-                                elements: list_item_elements,
-                                numbered,
-                            };
-                            elements.push(*Box::new(new_list));
-                        }
-                    }
-                    _ => {}
-                }
+            Some(Paragraph {
+                elements: paragraph_elements,
+            }) => {
+                paragraph_elements.push(text_element);
+            }
+            Some(List {
+                elements: list_elements,
+                ..
+            }) => {
+                list_elements.push(ListItem {
+                    element: text_element,
+                    checked: None,
+                });
             }
+            Some(_) => {}
         }
     }
 
@@ -394,6 +669,8 @@ mod tests {
         let parsed = Transformer::parse(&pdf_bytes);
         assert!(parsed.is_ok());
         let parsed_document = parsed.unwrap();
+        let first_page = parsed_document.source_span(&[0, 0]);
+        assert_eq!(first_page.and_then(|span| span.page), Some(1));
         info!("==========================");
         info!("{:?}", parsed_document);
         info!("==========================");
@@ -437,12 +714,12 @@ mod tests {
         debug!("==========================");
         parsed_document.set_page_header(vec![Element::Text {
             text: "header".to_string(),
-            size: 10,
+            font: FontSpec::from(10),
         }]);
 
         parsed_document.set_page_footer(vec![Element::Text {
             text: "footer".to_string(),
-            size: 10,
+            font: FontSpec::from(10),
         }]);
         let generated_result = Transformer::generate(&parsed_document);
         assert!(generated_result.is_ok());
@@ -459,15 +736,15 @@ mod tests {
                 elements: vec![
                     Text {
                         text: "Line 1".to_owned(),
-                        size: 8,
+                        font: FontSpec::from(8),
                     },
                     Text {
                         text: "Line 2".to_owned(),
-                        size: 8,
+                        font: FontSpec::from(8),
                     },
                     Text {
                         text: "Line 3".to_owned(),
-                        size: 8,
+                        font: FontSpec::from(8),
                     },
                 ],
             },
@@ -502,6 +779,21 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_mirrored_margins() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Text {
+            text: "Left and right pages".to_string(),
+            font: FontSpec::from(8),
+        }]);
+        let options = pdf::PdfOptions {
+            margins: Some(pdf::Margins::mirrored(30.0, 15.0, 20.0, 20.0)),
+            ..Default::default()
+        };
+        let generated_result = Transformer::generate_with_options(&document, &options)?;
+        assert!(!generated_result.is_empty());
+        Ok(())
+    }
+
     #[test]
     fn simple_test() {
         let content = std::fs::read("test/data/test.txt").unwrap();
@@ -512,4 +804,139 @@ mod tests {
 
         std::fs::write("test/data/test.pdf", output_bytes).unwrap();
     }
+
+    #[test]
+    fn test_annotations() -> anyhow::Result<()> {
+        use lopdf::{dictionary, Object, StringFormat};
+        let document = Document::new(vec![Element::Text {
+            text: "Revenue grew".to_string(),
+            font: FontSpec::from(8),
+        }]);
+        let generated = Transformer::generate(&document)?;
+        let mut pdf = lopdf::Document::load_mem(&generated)?;
+        let author: Vec<u8> = [0xFE, 0xFF]
+            .into_iter()
+            .chain("Ann".encode_utf16().flat_map(u16::to_be_bytes))
+            .collect();
+        let note = pdf.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Text",
+            "Rect" => vec![100.into(), 100.into(), 120.into(), 120.into()],
+            "Contents" => Object::string_literal("Check the totals"),
+            "T" => Object::String(author, StringFormat::Hexadecimal),
+            "M" => Object::string_literal("D:20240301093000+01'00'"),
+        });
+        let page_id = *pdf.get_pages().values().next().unwrap();
+        pdf.get_object_mut(page_id)?
+            .as_dict_mut()?
+            .set("Annots", vec![Object::Reference(note)]);
+        let mut annotated = Vec::new();
+        pdf.save_to(&mut annotated)?;
+
+        let parsed = Transformer::parse(&Bytes::from(annotated))?;
+        assert_eq!(
+            parsed.get_all_elements().last(),
+            Some(&&Element::Comment {
+                author: Some("Ann".to_string()),
+                date: Some("2024-03-01T09:30:00+01:00".to_string()),
+                text: "Check the totals".to_string(),
+                range: None,
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pages() -> anyhow::Result<()> {
+        let document = DocumentBuilder::new()
+            .paragraph(|p| p.text("First page"))
+            .page_break()
+            .paragraph(|p| p.text("Second page"))
+            .build();
+        let pdf = Transformer::generate(&document)?;
+        let options = pdf::ParseOptions {
+            pages: Some(2..=2),
+            ..Default::default()
+        };
+        let second_page = Transformer::parse_with_options(&pdf, &options)?;
+        assert_eq!(second_page.source_span(&[0, 0]).unwrap().page, Some(2));
+        assert!(second_page.source_spans.values().all(|span| span.page == Some(2)));
+        // Text of the embedded fonts is glyph ids, compared rather than read
+        let both_pages = Transformer::parse(&pdf)?;
+        assert!(both_pages.to_plain_text().ends_with(&second_page.to_plain_text()));
+        assert_ne!(both_pages.to_plain_text(), second_page.to_plain_text());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_malformed() -> anyhow::Result<()> {
+        let pdf = std::fs::read("test/data/document.pdf")?;
+        let truncated = Bytes::copy_from_slice(&pdf[..pdf.len() / 2]);
+        assert!(Transformer::parse(&truncated).is_err());
+        let garbage = Bytes::from_static(b"%PDF-1.4\n1 0 obj << /Type /Page >>\n%%EOF");
+        assert!(Transformer::parse(&garbage).is_err());
+        assert!(Transformer::parse(&Bytes::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_fuzz_inputs() -> anyhow::Result<()> {
+        // An object stream index with offsets past the end of the stream
+        let pdf = Bytes::from(std::fs::read("test/data/malformed_object_stream.pdf")?);
+        let result = Transformer::parse(&pdf);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<ParserError>(),
+            Some(ParserError::Malformed(_))
+        ));
+        // A zone that is not ASCII, in the dates of the document information dictionary
+        assert_eq!(pdf::pdf_date("D:2020+0\u{e9}1"), "D:2020+0\u{e9}1");
+        assert_eq!(pdf::to_pdf_date("2020-01-01T00:00+0\u{e9}:1"), "2020-01-01T00:00+0\u{e9}:1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_template() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Text {
+            text: "Body".to_string(),
+            font: FontSpec::from(8),
+        }]);
+        let options = pdf::PdfOptions {
+            template: Some("#let letterhead(".to_string()),
+            ..Default::default()
+        };
+        let result = Transformer::generate_with_options(&document, &options);
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancellation() -> anyhow::Result<()> {
+        let pdf = Bytes::from(std::fs::read("test/data/document.pdf")?);
+        let token = CancellationToken::new();
+        token.cancel();
+        let options = pdf::ParseOptions {
+            cancellation: Some(token.clone()),
+            ..Default::default()
+        };
+        let result = Transformer::parse_with_options(&pdf, &options);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<ParserError>(),
+            Some(ParserError::Cancelled)
+        ));
+
+        let options = pdf::PdfOptions {
+            cancellation: Some(token),
+            ..Default::default()
+        };
+        let document = Document::new(vec![Element::Text {
+            text: "Body".to_string(),
+            font: FontSpec::from(8),
+        }]);
+        let result = Transformer::generate_with_options(&document, &options);
+        assert!(matches!(
+            result.unwrap_err().downcast_ref::<GeneratorError>(),
+            Some(GeneratorError::Cancelled)
+        ));
+        Ok(())
+    }
 }
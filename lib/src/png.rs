@@ -0,0 +1,157 @@
+use crate::core::*;
+use crate::typst::{compile_document, PdfOptions};
+use bytes::Bytes;
+use typst::layout::Abs;
+use typst::visualize::Color;
+
+/// Space between the pages of a merged image, in points
+const PAGE_GAP: f64 = 5.0;
+
+/// Upper bound for the resolution, a page at this DPI is already about 10000 pixels wide
+const MAX_DPI: f32 = 1200.0;
+
+/// Options for PNG rendering.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PngOptions {
+    /// Resolution in dots per inch, 72 renders one pixel per point.
+    pub dpi: f32,
+    /// Page layout, shared with the PDF output.
+    pub layout: PdfOptions,
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        PngOptions {
+            dpi: 144.0,
+            layout: PdfOptions::default(),
+        }
+    }
+}
+
+impl PngOptions {
+    /// Options for the given resolution and the default layout
+    pub fn with_dpi(dpi: f32) -> PngOptions {
+        PngOptions {
+            dpi,
+            ..PngOptions::default()
+        }
+    }
+
+    fn pixel_per_pt(&self) -> anyhow::Result<f32> {
+        if !(self.dpi > 0.0 && self.dpi <= MAX_DPI) {
+            return Err(anyhow::anyhow!(
+                "DPI must be between 0 and {}, got {}",
+                MAX_DPI,
+                self.dpi
+            ));
+        }
+        Ok(self.dpi / 72.0)
+    }
+}
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(_document: &Bytes) -> anyhow::Result<Document> {
+        Err(anyhow::anyhow!("PNG parsing is not supported"))
+    }
+
+    /// All pages laid out as for the PDF output, stacked in one image
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_options(document, &PngOptions::default())
+    }
+}
+
+impl Transformer {
+    /// Generate one image of all pages using the given options (DPI, page margins...)
+    pub fn generate_with_options(
+        document: &Document,
+        options: &PngOptions,
+    ) -> anyhow::Result<Bytes> {
+        let pixel_per_pt = options.pixel_per_pt()?;
+        let document = compile_document(document, &options.layout)?;
+        let pixmap = typst_render::render_merged(
+            &document,
+            pixel_per_pt,
+            Color::WHITE,
+            Abs::pt(PAGE_GAP),
+            Color::WHITE,
+        );
+        Ok(Bytes::from(pixmap.encode_png()?))
+    }
+
+    /// Generate an image for every page, e.g. for thumbnails and previews
+    pub fn generate_pages(document: &Document, options: &PngOptions) -> anyhow::Result<Vec<Bytes>> {
+        let pixel_per_pt = options.pixel_per_pt()?;
+        let document = compile_document(document, &options.layout)?;
+        document
+            .pages
+            .iter()
+            .map(|page| {
+                if let Some(token) = &options.layout.cancellation {
+                    if token.is_cancelled() {
+                        return Err(GeneratorError::Cancelled.into());
+                    }
+                }
+                let pixmap = typst_render::render(&page.frame, pixel_per_pt, Color::WHITE);
+                Ok(Bytes::from(pixmap.encode_png()?))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::png::*;
+
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+    /// Width and height from the IHDR chunk
+    fn dimensions(png: &[u8]) -> (u32, u32) {
+        let number = |offset: usize| {
+            u32::from_be_bytes([
+                png[offset],
+                png[offset + 1],
+                png[offset + 2],
+                png[offset + 3],
+            ])
+        };
+        (number(16), number(20))
+    }
+
+    #[test]
+    fn test_generate_pages() -> anyhow::Result<()> {
+        let document = Document::new(
+            (0..100)
+                .map(|index| Element::Header {
+                    level: 1,
+                    text: format!("Section {}", index),
+                    anchor: None,
+                })
+                .collect(),
+        );
+        let pages = Transformer::generate_pages(&document, &PngOptions::with_dpi(36.0))?;
+        assert!(pages.len() > 1);
+        assert!(pages.iter().all(|page| page.starts_with(SIGNATURE)));
+        // An A4 page is 595pt wide, half a pixel per point at 36 DPI
+        assert_eq!(dimensions(&pages[0]).0, 298);
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_dpi() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Header {
+            level: 1,
+            text: "Preview".to_string(),
+            anchor: None,
+        }]);
+        let small = Transformer::generate_with_options(&document, &PngOptions::with_dpi(36.0))?;
+        let large = Transformer::generate_with_options(&document, &PngOptions::with_dpi(72.0))?;
+        assert!(small.starts_with(SIGNATURE));
+        // One pixel per point at 72 DPI
+        assert_eq!(dimensions(&large).0, 595);
+        assert!(dimensions(&large).1 > dimensions(&small).1);
+        assert!(Transformer::generate_with_options(&document, &PngOptions::with_dpi(0.0)).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+use crate::core::*;
+use crate::typst::{compile_document, PdfOptions};
+use bytes::Bytes;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::Write;
+use typst::visualize::Color;
+
+/// Upper bound for the resolution, print devices rarely go beyond it
+const MAX_DPI: f32 = 1200.0;
+
+/// Options for PostScript generation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostScriptOptions {
+    /// Resolution the pages are rendered at, in dots per inch.
+    pub dpi: f32,
+    /// Page layout, shared with the PDF output.
+    pub layout: PdfOptions,
+}
+
+impl Default for PostScriptOptions {
+    fn default() -> Self {
+        PostScriptOptions {
+            dpi: 300.0,
+            layout: PdfOptions::default(),
+        }
+    }
+}
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(_document: &Bytes) -> anyhow::Result<Document> {
+        Err(anyhow::anyhow!("PostScript parsing is not supported"))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_options(document, &PostScriptOptions::default())
+    }
+}
+
+impl Transformer {
+    /// Lays out the pages as for the PDF output and renders every page into a compressed
+    /// image, so the output prints exactly like the PDF. The file is DSC conforming
+    /// Level 3 PostScript, one `%%Page` per page with its size set through `setpagedevice`.
+    pub fn generate_with_options(
+        document: &Document,
+        options: &PostScriptOptions,
+    ) -> anyhow::Result<Bytes> {
+        if !(options.dpi > 0.0 && options.dpi <= MAX_DPI) {
+            return Err(anyhow::anyhow!(
+                "DPI must be between 0 and {}, got {}",
+                MAX_DPI,
+                options.dpi
+            ));
+        }
+        let pixel_per_pt = options.dpi / 72.0;
+        let document = compile_document(document, &options.layout)?;
+        let sizes: Vec<(f64, f64)> = document
+            .pages
+            .iter()
+            .map(|page| (page.frame.width().to_pt(), page.frame.height().to_pt()))
+            .collect();
+        let (width, height) = sizes.iter().fold((0.0, 0.0), |(width, height), size| {
+            (f64::max(width, size.0), f64::max(height, size.1))
+        });
+
+        let mut ps = String::from("%!PS-Adobe-3.0\n");
+        ps.push_str("%%Creator: shiva\n");
+        ps.push_str("%%LanguageLevel: 3\n");
+        ps.push_str(&format!("%%Pages: {}\n", document.pages.len()));
+        ps.push_str(&format!(
+            "%%BoundingBox: 0 0 {} {}\n",
+            width.ceil(),
+            height.ceil()
+        ));
+        ps.push_str("%%EndComments\n");
+        for (index, (page, (width, height))) in document.pages.iter().zip(&sizes).enumerate() {
+            if let Some(token) = &options.layout.cancellation {
+                if token.is_cancelled() {
+                    return Err(GeneratorError::Cancelled.into());
+                }
+            }
+            let pixmap = typst_render::render(&page.frame, pixel_per_pt, Color::WHITE);
+            // The page is rendered on white, so the premultiplied pixels are plain RGB
+            let rgb: Vec<u8> = pixmap
+                .data()
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect();
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&rgb)?;
+            let data = ascii85(&encoder.finish()?);
+
+            ps.push_str(&format!("%%Page: {} {}\n", index + 1, index + 1));
+            ps.push_str(&format!(
+                "%%PageBoundingBox: 0 0 {} {}\n",
+                width.ceil(),
+                height.ceil()
+            ));
+            ps.push_str(&format!(
+                "<< /PageSize [{:.2} {:.2}] >> setpagedevice\n",
+                width, height
+            ));
+            ps.push_str("gsave\n");
+            ps.push_str(&format!("{:.2} {:.2} scale\n", width, height));
+            ps.push_str("/DeviceRGB setcolorspace\n");
+            ps.push_str(&format!(
+                "<< /ImageType 1 /Width {w} /Height {h} /BitsPerComponent 8 \
+                 /Decode [0 1 0 1 0 1] /ImageMatrix [{w} 0 0 -{h} 0 {h}] \
+                 /DataSource currentfile /ASCII85Decode filter /FlateDecode filter >> image\n",
+                w = pixmap.width(),
+                h = pixmap.height()
+            ));
+            ps.push_str(&data);
+            ps.push_str("~>\n");
+            ps.push_str("grestore\nshowpage\n");
+        }
+        ps.push_str("%%Trailer\n%%EOF\n");
+        Ok(Bytes::from(ps))
+    }
+}
+
+/// ASCII85 without the `~>` end marker, in lines of 75 characters
+fn ascii85(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len() * 5 / 4 + data.len() / 60 + 8);
+    let mut line = 0;
+    let mut push = |encoded: &mut String, c: char| {
+        encoded.push(c);
+        line += 1;
+        if line == 75 {
+            encoded.push('\n');
+            line = 0;
+        }
+    };
+    for chunk in data.chunks(4) {
+        let mut bytes = [0u8; 4];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        let mut value = u32::from_be_bytes(bytes);
+        if value == 0 && chunk.len() == 4 {
+            push(&mut encoded, 'z');
+            continue;
+        }
+        let mut digits = [0u8; 5];
+        for digit in digits.iter_mut().rev() {
+            *digit = (value % 85) as u8 + b'!';
+            value /= 85;
+        }
+        for digit in &digits[..chunk.len() + 1] {
+            push(&mut encoded, *digit as char);
+        }
+    }
+    if line > 0 {
+        encoded.push('\n');
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::postscript::*;
+
+    #[test]
+    fn test_ascii85() {
+        assert_eq!(ascii85(b"Man "), "9jqo^\n");
+        assert_eq!(ascii85(b"Man"), "9jqo\n");
+        assert_eq!(ascii85(&[0, 0, 0, 0, 1]), "z!<\n");
+        assert_eq!(ascii85(b""), "");
+    }
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let document = Document::new(
+            (0..100)
+                .map(|index| Element::Header {
+                    level: 1,
+                    text: format!("Section {}", index),
+                    anchor: None,
+                })
+                .collect(),
+        );
+        let options = PostScriptOptions {
+            dpi: 36.0,
+            ..PostScriptOptions::default()
+        };
+        let generated = Transformer::generate_with_options(&document, &options)?;
+        let ps = std::str::from_utf8(&generated)?;
+        assert!(ps.starts_with("%!PS-Adobe-3.0\n"));
+        assert!(ps.ends_with("%%EOF\n"));
+        let pages = ps.matches("\nshowpage\n").count();
+        assert!(pages > 1);
+        assert!(ps.contains(&format!("%%Pages: {}\n", pages)));
+        // An A4 page, rendered at half a pixel per point
+        assert!(ps.contains("<< /PageSize [595.28 841.89] >> setpagedevice"));
+        assert!(ps.contains("/Width 298 /Height 421"));
+        Ok(())
+    }
+}
@@ -0,0 +1,1430 @@
+use crate::core::*;
+use crate::properties;
+use crate::slides::{split_slides, Slide};
+use crate::parse_util::{parse_xml, read_entry, Names, XmlNode};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    /// Slides are read in presentation order. The slide title becomes a level 1 header,
+    /// followed by the text frames, bullet lists, tables and pictures in shape order.
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        let mut archive = ZipArchive::new(Cursor::new(document.to_vec()))?;
+
+        let root_relationships = relationships(&mut archive, "")?;
+        let presentation_path = root_relationships
+            .values()
+            .find(|(kind, _)| kind.ends_with("/officeDocument"))
+            .map(|(_, target)| target.clone())
+            .ok_or_else(|| ParserError::Malformed("missing presentation part".to_string()))?;
+        let presentation = parse_xml(&read_entry(&mut archive, &presentation_path)?, NAMES)?;
+        let presentation_relationships = relationships(&mut archive, &presentation_path)?;
+
+        let mut elements = Vec::new();
+        let slide_ids = presentation
+            .find("sldIdLst")
+            .map(|list| {
+                list.nodes()
+                    .filter_map(|slide| slide.attribute("r:id"))
+                    .collect()
+            })
+            .unwrap_or_else(Vec::new);
+        for id in slide_ids {
+            let Some((_, slide_path)) = presentation_relationships.get(id) else {
+                continue;
+            };
+            let slide_path = slide_path.clone();
+            let slide = parse_xml(&read_entry(&mut archive, &slide_path)?, NAMES)?;
+            let relationships = relationships(&mut archive, &slide_path)?;
+            SlideParser {
+                archive: &mut archive,
+                relationships,
+            }
+            .parse_slide(&slide, &mut elements)?;
+        }
+        let mut parsed = Document::new(elements);
+        parsed.metadata = properties::read_metadata(document)?;
+        Ok(parsed)
+    }
+
+    /// Every level 1 or 2 header starts a slide titled with the header text.
+    /// Text, lists and deeper headers share a text box, tables and images get their own shapes
+    /// stacked below it. Page headers and footers are skipped.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        let slides = split_slides(document);
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+        let mut images: Vec<(String, &ImageData)> = Vec::new();
+        for (index, slide) in slides.iter().enumerate() {
+            let mut generator = SlideGenerator {
+                shapes: String::new(),
+                relationships: Vec::new(),
+                images: &mut images,
+                next_id: 2,
+            };
+            generator.slide(slide);
+            let relationships = generator.relationships;
+            let shapes = generator.shapes;
+
+            zip.start_file(format!("ppt/slides/slide{}.xml", index + 1), deflated)?;
+            zip.write_all(slide_document(&shapes).as_bytes())?;
+            zip.start_file(
+                format!("ppt/slides/_rels/slide{}.xml.rels", index + 1),
+                deflated,
+            )?;
+            zip.write_all(slide_relationships(&relationships).as_bytes())?;
+        }
+
+        zip.start_file("[Content_Types].xml", deflated)?;
+        zip.write_all(content_types(slides.len(), &images).as_bytes())?;
+        zip.start_file("_rels/.rels", deflated)?;
+        zip.write_all(root_relationships().as_bytes())?;
+        zip.start_file(properties::CORE_PATH, deflated)?;
+        zip.write_all(properties::core_xml(&document.metadata).as_bytes())?;
+        zip.start_file(properties::CUSTOM_PATH, deflated)?;
+        zip.write_all(properties::custom_xml(&document.metadata).as_bytes())?;
+        zip.start_file("ppt/presentation.xml", deflated)?;
+        zip.write_all(presentation(slides.len()).as_bytes())?;
+        zip.start_file("ppt/_rels/presentation.xml.rels", deflated)?;
+        zip.write_all(presentation_relationships(slides.len()).as_bytes())?;
+        zip.start_file("ppt/slideMasters/slideMaster1.xml", deflated)?;
+        zip.write_all(SLIDE_MASTER.as_bytes())?;
+        zip.start_file("ppt/slideMasters/_rels/slideMaster1.xml.rels", deflated)?;
+        zip.write_all(SLIDE_MASTER_RELATIONSHIPS.as_bytes())?;
+        zip.start_file("ppt/slideLayouts/slideLayout1.xml", deflated)?;
+        zip.write_all(SLIDE_LAYOUT.as_bytes())?;
+        zip.start_file("ppt/slideLayouts/_rels/slideLayout1.xml.rels", deflated)?;
+        zip.write_all(SLIDE_LAYOUT_RELATIONSHIPS.as_bytes())?;
+        zip.start_file("ppt/theme/theme1.xml", deflated)?;
+        zip.write_all(THEME.as_bytes())?;
+        for (name, image) in &images {
+            zip.start_file(format!("ppt/media/{}", name), stored)?;
+            zip.write_all(image.bytes())?;
+        }
+
+        let cursor = zip.finish()?;
+        Ok(Bytes::from(cursor.into_inner()))
+    }
+}
+
+/// Namespace prefixes are removed from element and attribute names. Only `r:id` style
+/// attributes keep their prefix since `id` is taken by shape ids.
+const NAMES: Names = Names::Local(&["r"]);
+
+fn parent_dir(path: &str) -> &str {
+    path.rfind('/')
+        .map(|index| &path[..index])
+        .unwrap_or_default()
+}
+
+/// Resolves a relationship target relative to the directory of the source part
+fn resolve(base_dir: &str, target: &str) -> String {
+    let mut parts: Vec<&str> = if target.starts_with('/') {
+        Vec::new()
+    } else {
+        base_dir
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .collect()
+    };
+    for part in target.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// Path of the relationships part belonging to `part`
+fn relationships_of(part: &str) -> String {
+    let name = part.rsplit('/').next().unwrap_or_default();
+    let dir = parent_dir(part);
+    if dir.is_empty() {
+        format!("_rels/{}.rels", name)
+    } else {
+        format!("{}/_rels/{}.rels", dir, name)
+    }
+}
+
+/// Relationship id to type and target. Internal targets are resolved to package paths.
+fn relationships(
+    archive: &mut ZipArchive<Cursor<Vec<u8>>>,
+    part: &str,
+) -> anyhow::Result<HashMap<String, (String, String)>> {
+    let path = relationships_of(part);
+    let mut result = HashMap::new();
+    if archive.by_name(&path).is_err() {
+        return Ok(result);
+    }
+    let root = parse_xml(&read_entry(archive, &path)?, NAMES)?;
+    for relationship in root.nodes() {
+        let (Some(id), Some(kind), Some(target)) = (
+            relationship.attribute("Id"),
+            relationship.attribute("Type"),
+            relationship.attribute("Target"),
+        ) else {
+            continue;
+        };
+        let target = if relationship.attribute("TargetMode") == Some("External") {
+            target.to_string()
+        } else {
+            resolve(parent_dir(part), target)
+        };
+        result.insert(id.to_string(), (kind.to_string(), target));
+    }
+    Ok(result)
+}
+
+struct SlideParser<'a> {
+    archive: &'a mut ZipArchive<Cursor<Vec<u8>>>,
+    relationships: HashMap<String, (String, String)>,
+}
+
+impl<'a> SlideParser<'a> {
+    fn parse_slide(&mut self, slide: &XmlNode, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        let Some(tree) = slide.path(&["cSld", "spTree"]) else {
+            return Ok(());
+        };
+        let mut shapes = Vec::new();
+        collect_shapes(tree, &mut shapes);
+
+        let title = shapes
+            .iter()
+            .position(|shape| matches!(placeholder_type(shape), Some("title" | "ctrTitle")));
+        if let Some(index) = title {
+            let shape = shapes.remove(index);
+            let text = shape
+                .find("txBody")
+                .map(|body| {
+                    body.nodes()
+                        .filter(|paragraph| paragraph.name == "p")
+                        .map(|paragraph| paragraph.text().trim().to_string())
+                        .filter(|line| !line.is_empty())
+                        .collect::<Vec<String>>()
+                        .join(" ")
+                })
+                .unwrap_or_default();
+            if !text.is_empty() {
+                elements.push(Element::Header {
+                    level: 1,
+                    text,
+                    anchor: None,
+                });
+            }
+        }
+
+        for shape in shapes {
+            match shape.name.as_str() {
+                "sp" => self.parse_text_frame(shape, elements)?,
+                "graphicFrame" => {
+                    if let Some(table) = shape.path(&["graphic", "graphicData", "tbl"]) {
+                        elements.push(self.parse_table(table)?);
+                    }
+                }
+                "pic" => elements.extend(self.parse_picture(shape)?),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Consecutive bulleted paragraphs form a list, nested by their `lvl`
+    fn parse_text_frame(&self, shape: &XmlNode, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        let Some(body) = shape.find("txBody") else {
+            return Ok(());
+        };
+        // Body placeholders are bulleted unless a paragraph turns bullets off
+        let bulleted_by_default = matches!(placeholder_type(shape), Some("body" | "obj"))
+            || (is_placeholder(shape) && placeholder_type(shape).is_none());
+
+        let mut list: Vec<(usize, bool, Element)> = Vec::new();
+        for paragraph in body.nodes().filter(|paragraph| paragraph.name == "p") {
+            let properties = paragraph.find("pPr");
+            let level = properties
+                .and_then(|properties| properties.attribute("lvl"))
+                .and_then(|level| level.parse::<usize>().ok())
+                .unwrap_or(0);
+            let numbered =
+                properties.is_some_and(|properties| properties.find("buAutoNum").is_some());
+            let bulleted = match properties {
+                Some(properties) if properties.find("buNone").is_some() => false,
+                Some(properties)
+                    if properties.find("buChar").is_some()
+                        || properties.find("buAutoNum").is_some() =>
+                {
+                    true
+                }
+                _ => bulleted_by_default,
+            };
+
+            let mut inline = Vec::new();
+            self.parse_runs(paragraph, &mut inline);
+            trim_paragraph(&mut inline);
+            if inline.is_empty() {
+                continue;
+            }
+            let content = if inline.len() == 1 {
+                inline.remove(0)
+            } else {
+                Element::Paragraph { elements: inline }
+            };
+            if bulleted {
+                list.push((level, numbered, content));
+            } else {
+                push_lists(&mut list, elements);
+                elements.push(match content {
+                    Element::Paragraph { .. } => content,
+                    content => Element::Paragraph {
+                        elements: vec![content],
+                    },
+                });
+            }
+        }
+        push_lists(&mut list, elements);
+        Ok(())
+    }
+
+    fn parse_runs(&self, paragraph: &XmlNode, elements: &mut Vec<Element>) {
+        for run in paragraph.nodes() {
+            match run.name.as_str() {
+                "r" | "fld" => {
+                    let text = run.find("t").map(XmlNode::text).unwrap_or_default();
+                    let link = run
+                        .path(&["rPr", "hlinkClick"])
+                        .and_then(|link| link.attribute("r:id"))
+                        .and_then(|id| self.relationships.get(id))
+                        .map(|(_, target)| target.clone());
+                    match link {
+                        // A link split over several runs stays one hyperlink
+                        Some(url) => match elements.last_mut() {
+                            Some(Element::Hyperlink {
+                                title, url: last, ..
+                            }) if *last == url => title.push_str(&text),
+                            _ => elements.push(Element::Hyperlink {
+                                title: text,
+                                url,
+                                alt: String::new(),
+                                size: 8,
+                            }),
+                        },
+                        None => push_text(elements, &text),
+                    }
+                }
+                "br" => elements.push(Element::LineBreak),
+                _ => {}
+            }
+        }
+    }
+
+    /// The first row is the header when the table marks it with `firstRow`
+    fn parse_table(&self, table: &XmlNode) -> anyhow::Result<Element> {
+        let first_row = table
+            .find("tblPr")
+            .and_then(|properties| properties.attribute("firstRow"))
+            .is_some_and(|value| value == "1" || value == "true");
+        let mut headers = Vec::new();
+        let mut rows = Vec::new();
+        for (index, row) in table.nodes().filter(|row| row.name == "tr").enumerate() {
+            let mut cells = Vec::new();
+            for cell in row.nodes().filter(|cell| cell.name == "tc") {
+                let mut inline = Vec::new();
+                if let Some(body) = cell.find("txBody") {
+                    for paragraph in body.nodes().filter(|paragraph| paragraph.name == "p") {
+                        if !inline.is_empty() {
+                            inline.push(Element::LineBreak);
+                        }
+                        self.parse_runs(paragraph, &mut inline);
+                    }
+                }
+                trim_paragraph(&mut inline);
+                cells.push(match inline.len() {
+                    0 => Element::Text {
+                        text: String::new(),
+                        font: FontSpec::from(8),
+                    },
+                    1 => inline.remove(0),
+                    _ => Element::Paragraph { elements: inline },
+                });
+            }
+            if index == 0 && first_row {
+                headers = cells
+                    .into_iter()
+                    .map(|element| TableHeader {
+                        element,
+                        width: 30.0,
+                    })
+                    .collect();
+            } else {
+                rows.push(TableRow {
+                    cells: cells.into_iter().map(TableCell::new).collect(),
+                });
+            }
+        }
+        Ok(Element::Table {
+            headers,
+            rows,
+            alignments: vec![],
+            caption: vec![],
+        })
+    }
+
+    fn parse_picture(&mut self, picture: &XmlNode) -> anyhow::Result<Option<Element>> {
+        let Some((_, path)) = picture
+            .path(&["blipFill", "blip"])
+            .and_then(|blip| blip.attribute("r:embed"))
+            .and_then(|id| self.relationships.get(id))
+        else {
+            return Ok(None);
+        };
+        let path = path.clone();
+        let bytes = read_entry(self.archive, &path)?;
+        let properties = picture.path(&["nvPicPr", "cNvPr"]);
+        let extent = picture.path(&["spPr", "xfrm", "ext"]);
+        let pixels = |name: &str| {
+            extent
+                .and_then(|extent| extent.attribute(name))
+                .and_then(|value| value.parse::<i64>().ok())
+                .map(|value| format!("{}px", value / EMU_PER_PIXEL))
+        };
+        Ok(Some(Element::Image(ImageData::new(
+            Bytes::from(bytes),
+            properties
+                .and_then(|properties| properties.attribute("title"))
+                .unwrap_or_default()
+                .to_string(),
+            properties
+                .and_then(|properties| properties.attribute("descr"))
+                .unwrap_or_default()
+                .to_string(),
+            path,
+            String::new(),
+            ImageDimension {
+                width: pixels("cx"),
+                height: pixels("cy"),
+            },
+        ))))
+    }
+}
+
+/// Shapes in document order, group shapes are flattened
+fn collect_shapes<'a>(tree: &'a XmlNode, shapes: &mut Vec<&'a XmlNode>) {
+    for node in tree.nodes() {
+        match node.name.as_str() {
+            "sp" | "graphicFrame" | "pic" => shapes.push(node),
+            "grpSp" => collect_shapes(node, shapes),
+            _ => {}
+        }
+    }
+}
+
+fn placeholder(shape: &XmlNode) -> Option<&XmlNode> {
+    shape
+        .nodes()
+        .find(|node| node.name.starts_with("nv"))
+        .and_then(|properties| properties.path(&["nvPr", "ph"]))
+}
+
+fn is_placeholder(shape: &XmlNode) -> bool {
+    placeholder(shape).is_some()
+}
+
+fn placeholder_type(shape: &XmlNode) -> Option<&str> {
+    placeholder(shape).and_then(|placeholder| placeholder.attribute("type"))
+}
+
+/// Turns pending `(level, numbered, content)` items into lists.
+/// A change between bullets and numbering on the top level starts a new list.
+fn push_lists(list: &mut Vec<(usize, bool, Element)>, elements: &mut Vec<Element>) {
+    let mut items = list.drain(..).peekable();
+    while items.peek().is_some() {
+        elements.push(build_list(&mut items, 0));
+    }
+}
+
+/// Builds a list from `(level, numbered, content)` items, deeper items become nested lists
+fn build_list(
+    items: &mut std::iter::Peekable<impl Iterator<Item = (usize, bool, Element)>>,
+    level: usize,
+) -> Element {
+    let numbered = items.peek().is_some_and(|(_, numbered, _)| *numbered);
+    let mut elements = Vec::new();
+    while let Some((item_level, item_numbered, _)) = items.peek() {
+        let restarts = *item_level == level && *item_numbered != numbered;
+        if *item_level < level || (restarts && !elements.is_empty()) {
+            break;
+        }
+        if *item_level > level {
+            elements.push(ListItem {
+                element: build_list(items, level + 1),
+                checked: None,
+            });
+            continue;
+        }
+        if let Some((_, _, element)) = items.next() {
+            elements.push(ListItem {
+                element,
+                checked: None,
+            });
+        }
+    }
+    Element::List { elements, numbered }
+}
+
+fn push_text(elements: &mut Vec<Element>, text: &str) {
+    if let Some(Element::Text { text: last, .. }) = elements.last_mut() {
+        last.push_str(text);
+    } else {
+        elements.push(Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        });
+    }
+}
+
+fn trim_paragraph(elements: &mut Vec<Element>) {
+    let last = elements.len().saturating_sub(1);
+    for (index, element) in elements.iter_mut().enumerate() {
+        if let Element::Text { text, .. } = element {
+            if index == 0 {
+                *text = text.trim_start().to_string();
+            }
+            if index == last {
+                *text = text.trim_end().to_string();
+            }
+        }
+    }
+    elements.retain(|element| !matches!(element, Element::Text { text, .. } if text.is_empty()));
+}
+
+/// 16:9 slide in EMU
+const SLIDE_WIDTH: i64 = 12192000;
+const SLIDE_HEIGHT: i64 = 6858000;
+const MARGIN: i64 = 457200;
+const TITLE_TOP: i64 = 365125;
+const TITLE_HEIGHT: i64 = 1005840;
+const CONTENT_TOP: i64 = 1554480;
+const CONTENT_WIDTH: i64 = SLIDE_WIDTH - 2 * MARGIN;
+const CONTENT_BOTTOM: i64 = SLIDE_HEIGHT - MARGIN;
+const GAP: i64 = 182880;
+
+/// Body text size in hundredths of a point and the matching line height in EMU
+const TEXT_SIZE: i64 = 1800;
+const LINE_HEIGHT: i64 = 12700 * TEXT_SIZE * 12 / 1000;
+/// Rough number of body text characters fitting on one line of the content area
+const CHARACTERS_PER_LINE: i64 = CONTENT_WIDTH / (12700 * TEXT_SIZE / 200);
+const TABLE_TEXT_SIZE: i64 = 1400;
+const TABLE_ROW_HEIGHT: i64 = 370840;
+/// Bullet indentation per list level
+const LIST_INDENT: i64 = 342900;
+const EMU_PER_PIXEL: i64 = 9525;
+
+const PRESENTATION_NAMESPACES: &str = concat!(
+    "xmlns:a=\"http://schemas.openxmlformats.org/drawingml/2006/main\" ",
+    "xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\" ",
+    "xmlns:p=\"http://schemas.openxmlformats.org/presentationml/2006/main\""
+);
+
+const RELATIONSHIP_TYPES: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn content_type(image_type: &ImageType) -> &'static str {
+    match image_type {
+        ImageType::Png => "image/png",
+        ImageType::Jpeg => "image/jpeg",
+        ImageType::Gif => "image/gif",
+        ImageType::SVG => "image/svg+xml",
+    }
+}
+
+/// Image size in EMU from its dimensions or the PNG/GIF header, scaled to fit `max`
+fn image_extent(image: &ImageData, max_width: i64, max_height: i64) -> (i64, i64) {
+    let length = |value: &Option<String>| -> Option<f64> {
+        let value = value.as_deref()?.trim();
+        value.trim_end_matches("px").parse::<f64>().ok()
+    };
+    let bytes = image.bytes();
+    let pixels = match (length(&image.size().width), length(&image.size().height)) {
+        (Some(width), Some(height)) => Some((width, height)),
+        _ if bytes.len() >= 24 && bytes.starts_with(b"\x89PNG") => Some((
+            u32::from_be_bytes([bytes[16], bytes[17], bytes[18], bytes[19]]) as f64,
+            u32::from_be_bytes([bytes[20], bytes[21], bytes[22], bytes[23]]) as f64,
+        )),
+        _ if bytes.len() >= 10 && bytes.starts_with(b"GIF") => Some((
+            u16::from_le_bytes([bytes[6], bytes[7]]) as f64,
+            u16::from_le_bytes([bytes[8], bytes[9]]) as f64,
+        )),
+        _ => None,
+    };
+    let (width, height) = match pixels {
+        Some((width, height)) if width > 0.0 && height > 0.0 => {
+            (width * EMU_PER_PIXEL as f64, height * EMU_PER_PIXEL as f64)
+        }
+        _ => (max_width as f64 / 2.0, max_height as f64 / 2.0),
+    };
+    let scale = (max_width as f64 / width)
+        .min(max_height as f64 / height)
+        .min(1.0);
+    ((width * scale) as i64, (height * scale) as i64)
+}
+
+enum Relationship {
+    Image(String),
+    Hyperlink(String),
+}
+
+struct SlideGenerator<'a, 'd> {
+    shapes: String,
+    relationships: Vec<Relationship>,
+    /// Media of the whole presentation, shared by all slides
+    images: &'a mut Vec<(String, &'d ImageData)>,
+    next_id: usize,
+}
+
+impl<'a, 'd> SlideGenerator<'a, 'd> {
+    fn slide(&mut self, slide: &Slide<'d>) {
+        if !slide.title.is_empty() {
+            let id = self.shape_id();
+            self.shapes.push_str(&format!(
+                concat!(
+                    "<p:sp><p:nvSpPr><p:cNvPr id=\"{}\" name=\"Title {}\"/>",
+                    "<p:cNvSpPr><a:spLocks noGrp=\"1\"/></p:cNvSpPr>",
+                    "<p:nvPr><p:ph type=\"title\"/></p:nvPr></p:nvSpPr>",
+                    "<p:spPr><a:xfrm><a:off x=\"{}\" y=\"{}\"/><a:ext cx=\"{}\" cy=\"{}\"/></a:xfrm></p:spPr>",
+                    "<p:txBody><a:bodyPr/><a:lstStyle/><a:p><a:r><a:rPr lang=\"en-US\" dirty=\"0\"/>",
+                    "<a:t>{}</a:t></a:r></a:p></p:txBody></p:sp>"
+                ),
+                id,
+                id - 1,
+                MARGIN,
+                TITLE_TOP,
+                CONTENT_WIDTH,
+                TITLE_HEIGHT,
+                escape(&slide.title)
+            ));
+        }
+
+        let mut top = CONTENT_TOP;
+        let mut paragraphs = Vec::new();
+        let mut lines = 0;
+        let mut pictures: Vec<&'d ImageData> = Vec::new();
+        for element in &slide.content {
+            match element {
+                Element::Table { headers, rows, .. } => {
+                    top = self.text_box(&mut paragraphs, lines, top);
+                    lines = 0;
+                    top = self.table(headers, rows, top);
+                }
+                Element::Image(image) => {
+                    top = self.text_box(&mut paragraphs, lines, top);
+                    lines = 0;
+                    top = self.picture(image, top);
+                }
+                // The caption starts the text below the picture
+                Element::Figure { image, caption } => {
+                    top = self.text_box(&mut paragraphs, lines, top);
+                    top = self.picture(image, top);
+                    let mut runs = String::new();
+                    let mut length = 0;
+                    for child in caption {
+                        length += self.runs(child, " i=\"1\"", &mut runs, &mut pictures);
+                    }
+                    lines = 0;
+                    if !runs.is_empty() {
+                        paragraphs.push(format!("<a:p><a:pPr algn=\"ctr\"/>{}</a:p>", runs));
+                        lines = 1 + length / CHARACTERS_PER_LINE;
+                    }
+                }
+                element => {
+                    lines += self.paragraphs(element, 0, &mut paragraphs, &mut pictures);
+                    // Images inside text follow the text they belong to
+                    if !pictures.is_empty() {
+                        top = self.text_box(&mut paragraphs, lines, top);
+                        lines = 0;
+                        for image in pictures.drain(..) {
+                            top = self.picture(image, top);
+                        }
+                    }
+                }
+            }
+        }
+        self.text_box(&mut paragraphs, lines, top);
+    }
+
+    fn shape_id(&mut self) -> usize {
+        self.next_id += 1;
+        self.next_id - 1
+    }
+
+    fn relationship(&mut self, relationship: Relationship) -> String {
+        self.relationships.push(relationship);
+        // rId1 is the slide layout
+        format!("rId{}", self.relationships.len() + 1)
+    }
+
+    /// Writes the pending paragraphs as one text box and returns the top of the next shape
+    fn text_box(&mut self, paragraphs: &mut Vec<String>, lines: i64, top: i64) -> i64 {
+        if paragraphs.is_empty() {
+            return top;
+        }
+        let height =
+            (lines.max(1) * LINE_HEIGHT + GAP).min((CONTENT_BOTTOM - top).max(LINE_HEIGHT));
+        let id = self.shape_id();
+        self.shapes.push_str(&format!(
+            concat!(
+                "<p:sp><p:nvSpPr><p:cNvPr id=\"{}\" name=\"TextBox {}\"/><p:cNvSpPr txBox=\"1\"/>",
+                "<p:nvPr/></p:nvSpPr><p:spPr><a:xfrm><a:off x=\"{}\" y=\"{}\"/>",
+                "<a:ext cx=\"{}\" cy=\"{}\"/></a:xfrm><a:prstGeom prst=\"rect\"><a:avLst/></a:prstGeom>",
+                "</p:spPr><p:txBody><a:bodyPr wrap=\"square\"><a:normAutofit/></a:bodyPr><a:lstStyle/>{}",
+                "</p:txBody></p:sp>"
+            ),
+            id,
+            id - 1,
+            MARGIN,
+            top,
+            CONTENT_WIDTH,
+            height,
+            paragraphs.join("")
+        ));
+        paragraphs.clear();
+        top + height + GAP
+    }
+
+    /// Adds the `<a:p>` paragraphs of a text element and returns the estimated line count
+    fn paragraphs(
+        &mut self,
+        element: &'d Element,
+        depth: i64,
+        paragraphs: &mut Vec<String>,
+        pictures: &mut Vec<&'d ImageData>,
+    ) -> i64 {
+        match element {
+            Element::List { elements, numbered } => {
+                let mut lines = 0;
+                for item in elements {
+                    if let Element::List { .. } = item.element {
+                        lines += self.paragraphs(&item.element, depth + 1, paragraphs, pictures);
+                        continue;
+                    }
+                    let bullet = if *numbered {
+                        "<a:buFont typeface=\"+mj-lt\"/><a:buAutoNum type=\"arabicPeriod\"/>"
+                    } else {
+                        "<a:buFont typeface=\"Arial\"/><a:buChar char=\"&#8226;\"/>"
+                    };
+                    let mut runs = String::new();
+                    let length = self.runs(&item.element, "", &mut runs, pictures);
+                    paragraphs.push(format!(
+                        "<a:p><a:pPr marL=\"{}\" lvl=\"{}\" indent=\"-{}\">{}</a:pPr>{}</a:p>",
+                        LIST_INDENT * (depth + 1),
+                        depth,
+                        LIST_INDENT,
+                        bullet,
+                        runs
+                    ));
+                    lines += 1 + length / CHARACTERS_PER_LINE;
+                }
+                lines
+            }
+            Element::Header { text, .. } => {
+                paragraphs.push(format!(
+                    "<a:p><a:r><a:rPr lang=\"en-US\" sz=\"{}\" b=\"1\" dirty=\"0\"/><a:t>{}</a:t></a:r></a:p>",
+                    TEXT_SIZE + 200,
+                    escape(text)
+                ));
+                2
+            }
+            Element::Blockquote { elements, .. } => {
+                let mut lines = 0;
+                for child in elements {
+                    let mut runs = String::new();
+                    let length = self.runs(child, " i=\"1\"", &mut runs, pictures);
+                    if runs.is_empty() {
+                        continue;
+                    }
+                    paragraphs.push(format!(
+                        "<a:p><a:pPr marL=\"{}\"/>{}</a:p>",
+                        LIST_INDENT, runs
+                    ));
+                    lines += 1 + length / CHARACTERS_PER_LINE;
+                }
+                lines
+            }
+            // Bold terms with their definitions indented below them
+            Element::DefinitionList { items } => {
+                let mut lines = 0;
+                for item in items {
+                    let mut runs = String::new();
+                    let length: i64 = item
+                        .term
+                        .iter()
+                        .map(|child| self.runs(child, " b=\"1\"", &mut runs, pictures))
+                        .sum();
+                    paragraphs.push(format!("<a:p>{}</a:p>", runs));
+                    lines += 1 + length / CHARACTERS_PER_LINE;
+                    for definition in &item.definitions {
+                        let mut runs = String::new();
+                        let length = self.runs(definition, "", &mut runs, pictures);
+                        paragraphs.push(format!(
+                            "<a:p><a:pPr marL=\"{}\"/>{}</a:p>",
+                            LIST_INDENT, runs
+                        ));
+                        lines += 1 + length / CHARACTERS_PER_LINE;
+                    }
+                }
+                lines
+            }
+            // Comments have no place on a slide, page breaks already started a new one
+            Element::Comment { .. } | Element::PageBreak | Element::TableOfContents { .. } => 0,
+            element => {
+                let mut runs = String::new();
+                let length = self.runs(element, "", &mut runs, pictures);
+                if runs.is_empty() {
+                    return 0;
+                }
+                paragraphs.push(format!("<a:p>{}</a:p>", runs));
+                1 + length / CHARACTERS_PER_LINE
+            }
+        }
+    }
+
+    /// Writes the `<a:r>` runs of inline content and returns the text length
+    fn runs(
+        &mut self,
+        element: &'d Element,
+        properties: &str,
+        runs: &mut String,
+        pictures: &mut Vec<&'d ImageData>,
+    ) -> i64 {
+        match element {
+            Element::Text { text, .. }
+            | Element::Header { text, .. }
+            | Element::Math { tex: text, .. } => {
+                if text.is_empty() {
+                    return 0;
+                }
+                runs.push_str(&format!(
+                    "<a:r><a:rPr lang=\"en-US\" sz=\"{}\"{} dirty=\"0\"/><a:t>{}</a:t></a:r>",
+                    TEXT_SIZE,
+                    properties,
+                    escape(text)
+                ));
+                text.chars().count() as i64
+            }
+            // PowerPoint ruby is not written, the reading follows the text in parentheses
+            Element::Ruby { .. } => {
+                let Element::Text { text, .. } = element.ruby_fallback() else {
+                    return 0;
+                };
+                runs.push_str(&format!(
+                    "<a:r><a:rPr lang=\"en-US\" sz=\"{}\"{} dirty=\"0\"/><a:t>{}</a:t></a:r>",
+                    TEXT_SIZE,
+                    properties,
+                    escape(&text)
+                ));
+                text.chars().count() as i64
+            }
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => elements
+                .iter()
+                .map(|child| self.runs(child, properties, runs, pictures))
+                .sum(),
+            Element::Styled { style, elements } => {
+                let mut properties = properties.to_string();
+                for (enabled, attribute) in [
+                    (style.bold, " b=\"1\""),
+                    (style.italic, " i=\"1\""),
+                    (style.underline, " u=\"sng\""),
+                    (style.strikethrough, " strike=\"sngStrike\""),
+                ] {
+                    // Table headers and quotes already set some of them
+                    if enabled && !properties.contains(attribute) {
+                        properties.push_str(attribute);
+                    }
+                }
+                elements
+                    .iter()
+                    .map(|child| self.runs(child, &properties, runs, pictures))
+                    .sum()
+            }
+            Element::Hyperlink { title, url, .. } => {
+                let id = self.relationship(Relationship::Hyperlink(url.clone()));
+                let title = if title.is_empty() { url } else { title };
+                runs.push_str(&format!(
+                    "<a:r><a:rPr lang=\"en-US\" sz=\"{}\"{} dirty=\"0\"><a:hlinkClick r:id=\"{}\"/></a:rPr><a:t>{}</a:t></a:r>",
+                    TEXT_SIZE,
+                    properties,
+                    id,
+                    escape(title)
+                ));
+                title.chars().count() as i64
+            }
+            Element::LineBreak | Element::HorizontalRule => {
+                runs.push_str("<a:br/>");
+                CHARACTERS_PER_LINE
+            }
+            Element::Image(image) => {
+                pictures.push(image);
+                0
+            }
+            Element::Figure { image, caption } => {
+                pictures.push(image);
+                caption
+                    .iter()
+                    .map(|child| self.runs(child, properties, runs, pictures))
+                    .sum()
+            }
+            Element::List { elements, .. } => elements
+                .iter()
+                .map(|item| self.runs(&item.element, properties, runs, pictures))
+                .sum(),
+            Element::DefinitionList { items } => {
+                let mut length = 0;
+                let children = items
+                    .iter()
+                    .flat_map(|item| item.term.iter().chain(&item.definitions));
+                for (index, child) in children.enumerate() {
+                    if index > 0 {
+                        runs.push_str(&format!(
+                            "<a:r><a:rPr lang=\"en-US\" sz=\"{}\"{} dirty=\"0\"/><a:t> </a:t></a:r>",
+                            TEXT_SIZE, properties
+                        ));
+                        length += 1;
+                    }
+                    length += self.runs(child, properties, runs, pictures);
+                }
+                length
+            }
+            Element::CodeBlock { code, .. } => {
+                let mut length = 0;
+                for (index, line) in code.lines().enumerate() {
+                    if index > 0 {
+                        runs.push_str("<a:br/>");
+                        length += CHARACTERS_PER_LINE;
+                    }
+                    runs.push_str(&format!(
+                        "<a:r><a:rPr lang=\"en-US\" sz=\"{}\"{} dirty=\"0\"><a:latin typeface=\"Courier New\"/></a:rPr><a:t>{}</a:t></a:r>",
+                        TEXT_SIZE,
+                        properties,
+                        escape(line)
+                    ));
+                    length += line.chars().count() as i64;
+                }
+                length
+            }
+            Element::FootnoteReference { id } | Element::Footnote { id, .. } => {
+                runs.push_str(&format!(
+                    "<a:r><a:rPr lang=\"en-US\" sz=\"{}\"{} baseline=\"30000\" dirty=\"0\"/><a:t>{}</a:t></a:r>",
+                    TEXT_SIZE,
+                    properties,
+                    escape(id)
+                ));
+                let mut length = id.chars().count() as i64;
+                // A footnote on a slide keeps its label in front of the content
+                if let Element::Footnote { content, .. } = element {
+                    for child in content {
+                        runs.push_str(&format!(
+                            "<a:r><a:rPr lang=\"en-US\" sz=\"{}\"{} dirty=\"0\"/><a:t> </a:t></a:r>",
+                            TEXT_SIZE, properties
+                        ));
+                        length += 1 + self.runs(child, properties, runs, pictures);
+                    }
+                }
+                length
+            }
+            Element::Table { .. }
+            | Element::Chart { .. }
+            | Element::Comment { .. }
+            | Element::PageBreak
+            | Element::TableOfContents { .. }
+            | Element::Custom { .. }
+            | Element::Media { .. } => 0,
+        }
+    }
+
+    fn table(&mut self, headers: &'d [TableHeader], rows: &'d [TableRow], top: i64) -> i64 {
+        let columns = rows
+            .iter()
+            .map(|row| row.cells.len())
+            .chain(std::iter::once(headers.len()))
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let column_width = CONTENT_WIDTH / columns as i64;
+        let row_count = rows.len() + usize::from(!headers.is_empty());
+        let height = row_count as i64 * TABLE_ROW_HEIGHT;
+
+        let mut xml = format!(
+            "<a:tbl><a:tblPr firstRow=\"{}\" bandRow=\"1\"/><a:tblGrid>",
+            u8::from(!headers.is_empty())
+        );
+        for _ in 0..columns {
+            xml.push_str(&format!("<a:gridCol w=\"{}\"/>", column_width));
+        }
+        xml.push_str("</a:tblGrid>");
+        let mut pictures = Vec::new();
+        if !headers.is_empty() {
+            let cells: Vec<&[Element]> = headers
+                .iter()
+                .map(|header| std::slice::from_ref(&header.element))
+                .collect();
+            self.table_row(&mut xml, &cells, columns, " b=\"1\"", &mut pictures);
+        }
+        for row in rows {
+            let cells: Vec<&[Element]> = row.cells.iter().map(|cell| &cell.elements[..]).collect();
+            self.table_row(&mut xml, &cells, columns, "", &mut pictures);
+        }
+        xml.push_str("</a:tbl>");
+
+        let id = self.shape_id();
+        self.shapes.push_str(&format!(
+            concat!(
+                "<p:graphicFrame><p:nvGraphicFramePr><p:cNvPr id=\"{}\" name=\"Table {}\"/>",
+                "<p:cNvGraphicFramePr><a:graphicFrameLocks noGrp=\"1\"/></p:cNvGraphicFramePr><p:nvPr/>",
+                "</p:nvGraphicFramePr><p:xfrm><a:off x=\"{}\" y=\"{}\"/><a:ext cx=\"{}\" cy=\"{}\"/></p:xfrm>",
+                "<a:graphic><a:graphicData uri=\"http://schemas.openxmlformats.org/drawingml/2006/table\">",
+                "{}</a:graphicData></a:graphic></p:graphicFrame>"
+            ),
+            id,
+            id - 1,
+            MARGIN,
+            top,
+            column_width * columns as i64,
+            height,
+            xml
+        ));
+        top + height + GAP
+    }
+
+    /// Rows are padded to the column count, images in cells are dropped
+    fn table_row(
+        &mut self,
+        xml: &mut String,
+        cells: &[&'d [Element]],
+        columns: usize,
+        properties: &str,
+        pictures: &mut Vec<&'d ImageData>,
+    ) {
+        xml.push_str(&format!("<a:tr h=\"{}\">", TABLE_ROW_HEIGHT));
+        for column in 0..columns {
+            // Each block of the cell is a paragraph of its own, empty cells keep one
+            let mut paragraphs = String::new();
+            for element in cells.get(column).copied().unwrap_or_default() {
+                let mut runs = String::new();
+                self.runs(element, properties, &mut runs, pictures);
+                paragraphs.push_str(&format!("<a:p>{}</a:p>", runs));
+            }
+            if paragraphs.is_empty() {
+                paragraphs.push_str("<a:p></a:p>");
+            }
+            let paragraphs = paragraphs.replace(
+                &format!("sz=\"{}\"", TEXT_SIZE),
+                &format!("sz=\"{}\"", TABLE_TEXT_SIZE),
+            );
+            xml.push_str(&format!(
+                "<a:tc><a:txBody><a:bodyPr/><a:lstStyle/>{}</a:txBody><a:tcPr/></a:tc>",
+                paragraphs
+            ));
+        }
+        xml.push_str("</a:tr>");
+        pictures.clear();
+    }
+
+    fn picture(&mut self, image: &'d ImageData, top: i64) -> i64 {
+        if image.bytes().is_empty() {
+            return top;
+        }
+        let name = format!(
+            "image{}{}",
+            self.images.len() + 1,
+            image.image_type().to_extension()
+        );
+        self.images.push((name.clone(), image));
+        let relationship = self.relationship(Relationship::Image(name));
+
+        let available = (CONTENT_BOTTOM - top).max(SLIDE_HEIGHT / 4);
+        let (width, height) = image_extent(image, CONTENT_WIDTH, available);
+        // Centered horizontally
+        let left = MARGIN + (CONTENT_WIDTH - width) / 2;
+        let id = self.shape_id();
+        self.shapes.push_str(&format!(
+            concat!(
+                "<p:pic><p:nvPicPr><p:cNvPr id=\"{}\" name=\"Picture {}\" descr=\"{}\" title=\"{}\"/>",
+                "<p:cNvPicPr><a:picLocks noChangeAspect=\"1\"/></p:cNvPicPr><p:nvPr/></p:nvPicPr>",
+                "<p:blipFill><a:blip r:embed=\"{}\"/><a:stretch><a:fillRect/></a:stretch></p:blipFill>",
+                "<p:spPr><a:xfrm><a:off x=\"{}\" y=\"{}\"/><a:ext cx=\"{}\" cy=\"{}\"/></a:xfrm>",
+                "<a:prstGeom prst=\"rect\"><a:avLst/></a:prstGeom></p:spPr></p:pic>"
+            ),
+            id,
+            id - 1,
+            escape(image.alt()),
+            escape(image.title()),
+            relationship,
+            left,
+            top,
+            width,
+            height
+        ));
+        top + height + GAP
+    }
+}
+
+fn slide_document(shapes: &str) -> String {
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+            "<p:sld {}><p:cSld><p:spTree><p:nvGrpSpPr><p:cNvPr id=\"1\" name=\"\"/><p:cNvGrpSpPr/>",
+            "<p:nvPr/></p:nvGrpSpPr><p:grpSpPr/>{}</p:spTree></p:cSld>",
+            "<p:clrMapOvr><a:masterClrMapping/></p:clrMapOvr></p:sld>"
+        ),
+        PRESENTATION_NAMESPACES, shapes
+    )
+}
+
+fn slide_relationships(relationships: &[Relationship]) -> String {
+    let mut xml = format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+            "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+            "<Relationship Id=\"rId1\" Type=\"{}/slideLayout\" Target=\"../slideLayouts/slideLayout1.xml\"/>"
+        ),
+        RELATIONSHIP_TYPES
+    );
+    for (index, relationship) in relationships.iter().enumerate() {
+        match relationship {
+            Relationship::Image(name) => xml.push_str(&format!(
+                "<Relationship Id=\"rId{}\" Type=\"{}/image\" Target=\"../media/{}\"/>",
+                index + 2,
+                RELATIONSHIP_TYPES,
+                name
+            )),
+            Relationship::Hyperlink(url) => xml.push_str(&format!(
+                "<Relationship Id=\"rId{}\" Type=\"{}/hyperlink\" Target=\"{}\" TargetMode=\"External\"/>",
+                index + 2,
+                RELATIONSHIP_TYPES,
+                escape(url)
+            )),
+        }
+    }
+    xml.push_str("</Relationships>");
+    xml
+}
+
+fn content_types(slides: usize, images: &[(String, &ImageData)]) -> String {
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+        "<Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">",
+        "<Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>",
+        "<Default Extension=\"xml\" ContentType=\"application/xml\"/>"
+    ));
+    let mut extensions: Vec<(&str, &str)> = images
+        .iter()
+        .map(|(_, image)| {
+            (
+                image.image_type().to_extension().trim_start_matches('.'),
+                content_type(image.image_type()),
+            )
+        })
+        .collect();
+    extensions.sort();
+    extensions.dedup();
+    for (extension, content_type) in extensions {
+        xml.push_str(&format!(
+            "<Default Extension=\"{}\" ContentType=\"{}\"/>",
+            extension, content_type
+        ));
+    }
+    let main = "application/vnd.openxmlformats-officedocument.presentationml";
+    xml.push_str(&format!(
+        concat!(
+            "<Override PartName=\"/ppt/presentation.xml\" ContentType=\"{0}.presentation.main+xml\"/>",
+            "<Override PartName=\"/ppt/slideMasters/slideMaster1.xml\" ContentType=\"{0}.slideMaster+xml\"/>",
+            "<Override PartName=\"/ppt/slideLayouts/slideLayout1.xml\" ContentType=\"{0}.slideLayout+xml\"/>",
+            "<Override PartName=\"/ppt/theme/theme1.xml\" ",
+            "ContentType=\"application/vnd.openxmlformats-officedocument.theme+xml\"/>"
+        ),
+        main
+    ));
+    for (path, content_type) in [
+        (properties::CORE_PATH, properties::CORE_CONTENT_TYPE),
+        (properties::CUSTOM_PATH, properties::CUSTOM_CONTENT_TYPE),
+    ] {
+        xml.push_str(&format!(
+            "<Override PartName=\"/{}\" ContentType=\"{}\"/>",
+            path, content_type
+        ));
+    }
+    for index in 1..=slides {
+        xml.push_str(&format!(
+            "<Override PartName=\"/ppt/slides/slide{}.xml\" ContentType=\"{}.slide+xml\"/>",
+            index, main
+        ));
+    }
+    xml.push_str("</Types>");
+    xml
+}
+
+fn presentation(slides: usize) -> String {
+    let mut ids = String::new();
+    for index in 0..slides {
+        // Slide ids start at 256, relationships after the master and the theme
+        ids.push_str(&format!(
+            "<p:sldId id=\"{}\" r:id=\"rId{}\"/>",
+            256 + index,
+            index + 3
+        ));
+    }
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+            "<p:presentation {}><p:sldMasterIdLst><p:sldMasterId id=\"2147483648\" r:id=\"rId1\"/>",
+            "</p:sldMasterIdLst><p:sldIdLst>{}</p:sldIdLst>",
+            "<p:sldSz cx=\"{}\" cy=\"{}\"/><p:notesSz cx=\"6858000\" cy=\"9144000\"/>",
+            "</p:presentation>"
+        ),
+        PRESENTATION_NAMESPACES, ids, SLIDE_WIDTH, SLIDE_HEIGHT
+    )
+}
+
+fn presentation_relationships(slides: usize) -> String {
+    let mut xml = format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+            "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+            "<Relationship Id=\"rId1\" Type=\"{0}/slideMaster\" Target=\"slideMasters/slideMaster1.xml\"/>",
+            "<Relationship Id=\"rId2\" Type=\"{0}/theme\" Target=\"theme/theme1.xml\"/>"
+        ),
+        RELATIONSHIP_TYPES
+    );
+    for index in 1..=slides {
+        xml.push_str(&format!(
+            "<Relationship Id=\"rId{}\" Type=\"{}/slide\" Target=\"slides/slide{}.xml\"/>",
+            index + 2,
+            RELATIONSHIP_TYPES,
+            index
+        ));
+    }
+    xml.push_str("</Relationships>");
+    xml
+}
+
+/// The presentation and the document properties
+fn root_relationships() -> String {
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+            "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+            "<Relationship Id=\"rId1\" ",
+            "Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument\" ",
+            "Target=\"ppt/presentation.xml\"/>",
+            "<Relationship Id=\"rId2\" Type=\"{}\" Target=\"{}\"/>",
+            "<Relationship Id=\"rId3\" Type=\"{}\" Target=\"{}\"/></Relationships>"
+        ),
+        properties::CORE_RELATIONSHIP,
+        properties::CORE_PATH,
+        properties::CUSTOM_RELATIONSHIP,
+        properties::CUSTOM_PATH
+    )
+}
+
+const SLIDE_MASTER: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+    "<p:sldMaster xmlns:a=\"http://schemas.openxmlformats.org/drawingml/2006/main\" ",
+    "xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\" ",
+    "xmlns:p=\"http://schemas.openxmlformats.org/presentationml/2006/main\">",
+    "<p:cSld><p:bg><p:bgRef idx=\"1001\"><a:schemeClr val=\"bg1\"/></p:bgRef></p:bg><p:spTree>",
+    "<p:nvGrpSpPr><p:cNvPr id=\"1\" name=\"\"/><p:cNvGrpSpPr/><p:nvPr/></p:nvGrpSpPr><p:grpSpPr/>",
+    "<p:sp><p:nvSpPr><p:cNvPr id=\"2\" name=\"Title Placeholder 1\"/>",
+    "<p:cNvSpPr><a:spLocks noGrp=\"1\"/></p:cNvSpPr><p:nvPr><p:ph type=\"title\"/></p:nvPr></p:nvSpPr>",
+    "<p:spPr><a:xfrm><a:off x=\"457200\" y=\"365125\"/><a:ext cx=\"11277600\" cy=\"1005840\"/></a:xfrm>",
+    "<a:prstGeom prst=\"rect\"><a:avLst/></a:prstGeom></p:spPr>",
+    "<p:txBody><a:bodyPr anchor=\"b\"/><a:lstStyle/><a:p><a:endParaRPr lang=\"en-US\"/></a:p></p:txBody></p:sp>",
+    "</p:spTree></p:cSld>",
+    "<p:clrMap bg1=\"lt1\" tx1=\"dk1\" bg2=\"lt2\" tx2=\"dk2\" accent1=\"accent1\" accent2=\"accent2\" ",
+    "accent3=\"accent3\" accent4=\"accent4\" accent5=\"accent5\" accent6=\"accent6\" hlink=\"hlink\" ",
+    "folHlink=\"folHlink\"/>",
+    "<p:sldLayoutIdLst><p:sldLayoutId id=\"2147483649\" r:id=\"rId1\"/></p:sldLayoutIdLst>",
+    "<p:txStyles><p:titleStyle><a:lvl1pPr algn=\"l\"><a:defRPr sz=\"3600\" b=\"1\">",
+    "<a:solidFill><a:schemeClr val=\"tx1\"/></a:solidFill><a:latin typeface=\"+mj-lt\"/></a:defRPr>",
+    "</a:lvl1pPr></p:titleStyle><p:bodyStyle><a:lvl1pPr><a:defRPr sz=\"1800\">",
+    "<a:solidFill><a:schemeClr val=\"tx1\"/></a:solidFill><a:latin typeface=\"+mn-lt\"/></a:defRPr>",
+    "</a:lvl1pPr></p:bodyStyle><p:otherStyle><a:lvl1pPr><a:defRPr sz=\"1800\">",
+    "<a:solidFill><a:schemeClr val=\"tx1\"/></a:solidFill><a:latin typeface=\"+mn-lt\"/></a:defRPr>",
+    "</a:lvl1pPr></p:otherStyle></p:txStyles></p:sldMaster>"
+);
+
+const SLIDE_MASTER_RELATIONSHIPS: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+    "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+    "<Relationship Id=\"rId1\" ",
+    "Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/slideLayout\" ",
+    "Target=\"../slideLayouts/slideLayout1.xml\"/>",
+    "<Relationship Id=\"rId2\" ",
+    "Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/theme\" ",
+    "Target=\"../theme/theme1.xml\"/></Relationships>"
+);
+
+const SLIDE_LAYOUT: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+    "<p:sldLayout xmlns:a=\"http://schemas.openxmlformats.org/drawingml/2006/main\" ",
+    "xmlns:r=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships\" ",
+    "xmlns:p=\"http://schemas.openxmlformats.org/presentationml/2006/main\" ",
+    "type=\"titleOnly\" preserve=\"1\"><p:cSld name=\"Title Only\"><p:spTree>",
+    "<p:nvGrpSpPr><p:cNvPr id=\"1\" name=\"\"/><p:cNvGrpSpPr/><p:nvPr/></p:nvGrpSpPr><p:grpSpPr/>",
+    "<p:sp><p:nvSpPr><p:cNvPr id=\"2\" name=\"Title 1\"/>",
+    "<p:cNvSpPr><a:spLocks noGrp=\"1\"/></p:cNvSpPr><p:nvPr><p:ph type=\"title\"/></p:nvPr></p:nvSpPr>",
+    "<p:spPr/><p:txBody><a:bodyPr/><a:lstStyle/><a:p><a:endParaRPr lang=\"en-US\"/></a:p></p:txBody></p:sp>",
+    "</p:spTree></p:cSld><p:clrMapOvr><a:masterClrMapping/></p:clrMapOvr></p:sldLayout>"
+);
+
+const SLIDE_LAYOUT_RELATIONSHIPS: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+    "<Relationships xmlns=\"http://schemas.openxmlformats.org/package/2006/relationships\">",
+    "<Relationship Id=\"rId1\" ",
+    "Type=\"http://schemas.openxmlformats.org/officeDocument/2006/relationships/slideMaster\" ",
+    "Target=\"../slideMasters/slideMaster1.xml\"/></Relationships>"
+);
+
+const THEME: &str = concat!(
+    "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+    "<a:theme xmlns:a=\"http://schemas.openxmlformats.org/drawingml/2006/main\" name=\"Shiva\">",
+    "<a:themeElements><a:clrScheme name=\"Shiva\">",
+    "<a:dk1><a:sysClr val=\"windowText\" lastClr=\"000000\"/></a:dk1>",
+    "<a:lt1><a:sysClr val=\"window\" lastClr=\"FFFFFF\"/></a:lt1>",
+    "<a:dk2><a:srgbClr val=\"44546A\"/></a:dk2><a:lt2><a:srgbClr val=\"E7E6E6\"/></a:lt2>",
+    "<a:accent1><a:srgbClr val=\"4472C4\"/></a:accent1><a:accent2><a:srgbClr val=\"ED7D31\"/></a:accent2>",
+    "<a:accent3><a:srgbClr val=\"A5A5A5\"/></a:accent3><a:accent4><a:srgbClr val=\"FFC000\"/></a:accent4>",
+    "<a:accent5><a:srgbClr val=\"5B9BD5\"/></a:accent5><a:accent6><a:srgbClr val=\"70AD47\"/></a:accent6>",
+    "<a:hlink><a:srgbClr val=\"0563C1\"/></a:hlink><a:folHlink><a:srgbClr val=\"954F72\"/></a:folHlink>",
+    "</a:clrScheme><a:fontScheme name=\"Shiva\">",
+    "<a:majorFont><a:latin typeface=\"Calibri Light\"/><a:ea typeface=\"\"/><a:cs typeface=\"\"/></a:majorFont>",
+    "<a:minorFont><a:latin typeface=\"Calibri\"/><a:ea typeface=\"\"/><a:cs typeface=\"\"/></a:minorFont>",
+    "</a:fontScheme><a:fmtScheme name=\"Shiva\"><a:fillStyleLst>",
+    "<a:solidFill><a:schemeClr val=\"phClr\"/></a:solidFill>",
+    "<a:solidFill><a:schemeClr val=\"phClr\"/></a:solidFill>",
+    "<a:solidFill><a:schemeClr val=\"phClr\"/></a:solidFill>",
+    "</a:fillStyleLst><a:lnStyleLst>",
+    "<a:ln w=\"6350\"><a:solidFill><a:schemeClr val=\"phClr\"/></a:solidFill></a:ln>",
+    "<a:ln w=\"12700\"><a:solidFill><a:schemeClr val=\"phClr\"/></a:solidFill></a:ln>",
+    "<a:ln w=\"19050\"><a:solidFill><a:schemeClr val=\"phClr\"/></a:solidFill></a:ln>",
+    "</a:lnStyleLst><a:effectStyleLst>",
+    "<a:effectStyle><a:effectLst/></a:effectStyle><a:effectStyle><a:effectLst/></a:effectStyle>",
+    "<a:effectStyle><a:effectLst/></a:effectStyle></a:effectStyleLst><a:bgFillStyleLst>",
+    "<a:solidFill><a:schemeClr val=\"phClr\"/></a:solidFill>",
+    "<a:solidFill><a:schemeClr val=\"phClr\"/></a:solidFill>",
+    "<a:solidFill><a:schemeClr val=\"phClr\"/></a:solidFill>",
+    "</a:bgFillStyleLst></a:fmtScheme></a:themeElements></a:theme>"
+);
+
+#[cfg(test)]
+mod tests {
+    use crate::pptx::*;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let document = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let pptx = Transformer::generate(&document)?;
+        let mut archive = ZipArchive::new(Cursor::new(pptx.to_vec()))?;
+
+        let presentation = String::from_utf8(read_entry(&mut archive, "ppt/presentation.xml")?)?;
+        assert_eq!(presentation.matches("<p:sldId ").count(), 2);
+        let content_types = String::from_utf8(read_entry(&mut archive, "[Content_Types].xml")?)?;
+        assert!(content_types.contains("/ppt/slides/slide2.xml"));
+        assert!(content_types.contains("Extension=\"png\""));
+
+        let first = String::from_utf8(read_entry(&mut archive, "ppt/slides/slide1.xml")?)?;
+        assert!(first.contains("<a:t>First header</a:t>"));
+        assert!(first.contains("<a:buAutoNum type=\"arabicPeriod\"/>"));
+        assert!(first.contains("<a:buChar char=\"&#8226;\"/>"));
+        assert!(first.contains("<p:pic>"));
+        assert!(first.contains("<a:hlinkClick r:id="));
+        let relationships =
+            String::from_utf8(read_entry(&mut archive, "ppt/slides/_rels/slide1.xml.rels")?)?;
+        assert!(relationships.contains("Target=\"https://link-url-here.org\""));
+        assert!(relationships.contains("Target=\"../media/image1.png\""));
+        assert!(archive.by_name("ppt/media/image1.png").is_ok());
+
+        let second = String::from_utf8(read_entry(&mut archive, "ppt/slides/slide2.xml")?)?;
+        assert!(second.contains("<a:t>Second header</a:t>"));
+        assert_eq!(second.matches("<a:gridCol ").count(), 8);
+        assert_eq!(second.matches("<a:tr ").count(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let document = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let pptx = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&pptx)?;
+        let elements = parsed.get_all_elements();
+
+        let headers: Vec<&Element> = elements
+            .iter()
+            .filter(|element| matches!(element, Element::Header { level: 1, .. }))
+            .copied()
+            .collect();
+        assert_eq!(
+            headers,
+            vec![
+                &Element::Header {
+                    level: 1,
+                    text: "First header".to_string(),
+                    anchor: None,
+                },
+                &Element::Header {
+                    level: 1,
+                    text: "Second header".to_string(),
+                    anchor: None,
+                },
+            ]
+        );
+        assert!(elements
+            .iter()
+            .any(|element| matches!(element, Element::List { numbered: true, .. })));
+        assert!(elements.iter().any(|element| matches!(
+            element,
+            Element::List {
+                numbered: false,
+                ..
+            }
+        )));
+        assert!(elements.iter().any(|element| matches!(
+            element,
+            Element::Image(image) if *image.image_type() == ImageType::Png && !image.bytes().is_empty()
+        )));
+        let table = elements.iter().find_map(|element| match element {
+            Element::Table { headers, rows, .. } => Some((headers.len(), rows.len())),
+            _ => None,
+        });
+        assert_eq!(table, Some((8, 3)));
+        Ok(())
+    }
+}
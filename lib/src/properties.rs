@@ -0,0 +1,222 @@
+//! Document properties of Office Open XML packages, the `docProps/core.xml` and
+//! `docProps/custom.xml` parts Word, Excel and PowerPoint share
+//!
+//! The core part has a single creator, several authors share it separated by semicolons.
+use crate::core::Metadata;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+#[cfg(any(feature = "docx", feature = "xlsx"))]
+use std::io::Write;
+use std::io::{Cursor, Read};
+use zip::ZipArchive;
+#[cfg(any(feature = "docx", feature = "xlsx"))]
+use zip::ZipWriter;
+
+pub(crate) const CORE_PATH: &str = "docProps/core.xml";
+pub(crate) const CUSTOM_PATH: &str = "docProps/custom.xml";
+
+#[cfg(feature = "pptx")]
+pub(crate) const CORE_CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-package.core-properties+xml";
+#[cfg(feature = "pptx")]
+pub(crate) const CUSTOM_CONTENT_TYPE: &str =
+    "application/vnd.openxmlformats-officedocument.custom-properties+xml";
+
+#[cfg(feature = "pptx")]
+pub(crate) const CORE_RELATIONSHIP: &str =
+    "http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties";
+#[cfg(feature = "pptx")]
+pub(crate) const CUSTOM_RELATIONSHIP: &str =
+    "http://schemas.openxmlformats.org/officeDocument/2006/relationships/custom-properties";
+
+/// Identifies the user defined properties, every custom property is in this set
+#[cfg(feature = "pptx")]
+const USER_DEFINED_PROPERTIES: &str = "{D5CDD505-2E9C-101B-9397-08002B2CF9AE}";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The core properties part of the metadata
+pub(crate) fn core_xml(metadata: &Metadata) -> String {
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+        "<cp:coreProperties ",
+        "xmlns:cp=\"http://schemas.openxmlformats.org/package/2006/metadata/core-properties\" ",
+        "xmlns:dc=\"http://purl.org/dc/elements/1.1/\" ",
+        "xmlns:dcterms=\"http://purl.org/dc/terms/\" ",
+        "xmlns:dcmitype=\"http://purl.org/dc/dcmitype/\" ",
+        "xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">"
+    ));
+    let authors = (!metadata.authors.is_empty()).then(|| metadata.authors.join("; "));
+    let properties = [
+        ("dc:title", &metadata.title),
+        ("dc:subject", &metadata.subject),
+        ("dc:creator", &authors),
+        ("cp:keywords", &metadata.keywords_text()),
+        ("dc:language", &metadata.language),
+    ];
+    for (name, value) in properties {
+        if let Some(value) = value {
+            xml.push_str(&format!("<{name}>{}</{name}>", escape(value)));
+        }
+    }
+    for (name, value) in [
+        ("dcterms:created", &metadata.created),
+        ("dcterms:modified", &metadata.modified),
+    ] {
+        if let Some(value) = value {
+            xml.push_str(&format!(
+                "<{name} xsi:type=\"dcterms:W3CDTF\">{}</{name}>",
+                escape(value)
+            ));
+        }
+    }
+    xml.push_str("</cp:coreProperties>");
+    xml
+}
+
+/// The custom properties part of the metadata, all of them are strings
+#[cfg(feature = "pptx")]
+pub(crate) fn custom_xml(metadata: &Metadata) -> String {
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n",
+        "<Properties ",
+        "xmlns=\"http://schemas.openxmlformats.org/officeDocument/2006/custom-properties\" ",
+        "xmlns:vt=\"http://schemas.openxmlformats.org/officeDocument/2006/docPropsVTypes\">"
+    ));
+    // Property ids start at 2
+    for (index, (name, value)) in metadata.custom.iter().enumerate() {
+        xml.push_str(&format!(
+            "<property fmtid=\"{}\" pid=\"{}\" name=\"{}\"><vt:lpwstr>{}</vt:lpwstr></property>",
+            USER_DEFINED_PROPERTIES,
+            index + 2,
+            escape(name),
+            escape(value)
+        ));
+    }
+    xml.push_str("</Properties>");
+    xml
+}
+
+/// The metadata of a package, from the core and custom properties it has
+pub(crate) fn read_metadata(package: &[u8]) -> anyhow::Result<Metadata> {
+    let mut archive = ZipArchive::new(Cursor::new(package))?;
+    let mut metadata = Metadata::default();
+    if let Some(core) = read_part(&mut archive, CORE_PATH)? {
+        for (name, value) in read_values(&core)? {
+            if value.is_empty() {
+                continue;
+            }
+            match name.as_str() {
+                "title" => metadata.title = Some(value),
+                "subject" => metadata.subject = Some(value),
+                "creator" => {
+                    metadata.authors = value
+                        .split(';')
+                        .map(|author| author.trim().to_string())
+                        .filter(|author| !author.is_empty())
+                        .collect()
+                }
+                "keywords" => metadata.keywords = Metadata::split_keywords(&value),
+                "language" => metadata.language = Some(value),
+                "created" => metadata.created = Some(value),
+                "modified" => metadata.modified = Some(value),
+                _ => {}
+            }
+        }
+    }
+    if let Some(custom) = read_part(&mut archive, CUSTOM_PATH)? {
+        let mut reader = Reader::from_reader(custom.as_slice());
+        let mut name = None;
+        loop {
+            match reader.read_event()? {
+                Event::Start(start) if start.local_name().as_ref() == b"property" => {
+                    name = start
+                        .try_get_attribute("name")?
+                        .map(|name| {
+                            name.decode_and_unescape_value(&reader)
+                                .map(|name| name.to_string())
+                        })
+                        .transpose()?;
+                }
+                Event::Text(text) => {
+                    if let Some(name) = name.take() {
+                        metadata.custom.insert(name, text.unescape()?.trim().to_string());
+                    }
+                }
+                Event::End(end) if end.local_name().as_ref() == b"property" => name = None,
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+    }
+    Ok(metadata)
+}
+
+fn read_part(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    name: &str,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let Ok(mut part) = archive.by_name(name) else {
+        return Ok(None);
+    };
+    let mut xml = Vec::new();
+    part.read_to_end(&mut xml)?;
+    Ok(Some(xml))
+}
+
+/// The text of the children of the root by their names without the namespace prefix
+fn read_values(xml: &[u8]) -> anyhow::Result<Vec<(String, String)>> {
+    let mut reader = Reader::from_reader(xml);
+    let mut values = Vec::new();
+    let mut depth = 0;
+    let mut current: Option<(String, String)> = None;
+    loop {
+        match reader.read_event()? {
+            Event::Start(start) => {
+                depth += 1;
+                if depth == 2 {
+                    let name = String::from_utf8(start.local_name().as_ref().to_vec())?;
+                    current = Some((name, String::new()));
+                }
+            }
+            Event::Text(text) => {
+                if let Some((_, value)) = &mut current {
+                    value.push_str(&text.unescape()?);
+                }
+            }
+            Event::End(_) => {
+                if depth == 2 {
+                    if let Some((name, value)) = current.take() {
+                        values.push((name, value.trim().to_string()));
+                    }
+                }
+                depth -= 1;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(values)
+}
+
+/// A copy of a package with one of its parts replaced
+#[cfg(any(feature = "docx", feature = "xlsx"))]
+pub(crate) fn replace_part(package: &[u8], name: &str, content: &str) -> anyhow::Result<Vec<u8>> {
+    let mut archive = ZipArchive::new(Cursor::new(package))?;
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for index in 0..archive.len() {
+        let part = archive.by_index_raw(index)?;
+        if part.name() == name {
+            continue;
+        }
+        zip.raw_copy_file(part)?;
+    }
+    zip.start_file(name, zip::write::FileOptions::default())?;
+    zip.write_all(content.as_bytes())?;
+    Ok(zip.finish()?.into_inner())
+}
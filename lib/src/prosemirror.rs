@@ -0,0 +1,766 @@
+use crate::core::*;
+use base64::{engine::general_purpose, Engine as _};
+use bytes::Bytes;
+use serde_json::{json, Map, Value};
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Reads the node tree of a `doc` node. Both the TipTap names (`bulletList`, `hardBreak`)
+    /// and the snake case names of prosemirror-schema-basic (`bullet_list`, `hard_break`) are
+    /// accepted. Only the link mark is kept, images with a `data:` URL are decoded and other
+    /// sources go through `image_loader`.
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let doc: Value = serde_json::from_slice(document)?;
+        if doc.get("type").and_then(Value::as_str) != Some("doc") {
+            return Err(ParserError::Malformed("root node is not a doc".to_string()).into());
+        }
+        let parser = Parser {
+            image_loader: &image_loader,
+        };
+        let mut elements = Vec::new();
+        for node in content(&doc) {
+            parser.block(node, &mut elements)?;
+        }
+        Ok(Document::new(elements))
+    }
+
+    /// Produces TipTap node names. Multi-line text becomes a `codeBlock`, the first row of a
+    /// table holds `tableHeader` cells and images are written through `image_saver`.
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        // A table of contents is written as the list of its headers
+        let document = &document.expand_toc();
+        let mut generator = Generator {
+            image_saver,
+            image_num: 0,
+        };
+        let mut nodes = Vec::new();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                generator.block(element, &mut nodes)?;
+            }
+        }
+        let doc = json!({ "type": "doc", "content": nodes });
+        Ok(Bytes::from(serde_json::to_vec_pretty(&doc)?))
+    }
+}
+
+const IMAGE_TYPES: [(&str, &str); 4] = [
+    ("image/png", "png"),
+    ("image/jpeg", "jpeg"),
+    ("image/gif", "gif"),
+    ("image/svg+xml", "svg"),
+];
+
+/// Image type of a URL from its extension, `ImageData` only knows png, jpeg, gif and svg
+fn image_type(src: &str) -> &'static str {
+    let path = src.split(['?', '#']).next().unwrap_or(src);
+    match path.rsplit('.').next().map(str::to_lowercase).as_deref() {
+        Some("jpg" | "jpeg") => "jpeg",
+        Some("gif") => "gif",
+        Some("svg") => "svg",
+        _ => "png",
+    }
+}
+
+/// Node type with the snake case names of prosemirror-schema-basic mapped to TipTap names
+fn node_type(node: &Value) -> &str {
+    match node.get("type").and_then(Value::as_str).unwrap_or("") {
+        "bullet_list" => "bulletList",
+        "ordered_list" => "orderedList",
+        "list_item" => "listItem",
+        "code_block" => "codeBlock",
+        "hard_break" => "hardBreak",
+        "horizontal_rule" => "horizontalRule",
+        "table_row" => "tableRow",
+        "table_cell" => "tableCell",
+        "table_header" => "tableHeader",
+        name => name,
+    }
+}
+
+fn content(node: &Value) -> impl Iterator<Item = &Value> {
+    node.get("content")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+}
+
+/// String attribute, numbers are written out
+fn attribute(node: &Value, name: &str) -> Option<String> {
+    match node.get("attrs")?.get(name)? {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+fn plain_text(node: &Value) -> String {
+    match node_type(node) {
+        "text" => node
+            .get("text")
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_string(),
+        "hardBreak" => "\n".to_string(),
+        _ => content(node).map(plain_text).collect(),
+    }
+}
+
+/// A single inline element stands alone, several become a paragraph
+fn unwrap_inline(mut elements: Vec<Element>) -> Element {
+    match elements.len() {
+        0 => Element::Text {
+            text: String::new(),
+            font: FontSpec::from(8),
+        },
+        1 => elements.remove(0),
+        _ => Element::Paragraph { elements },
+    }
+}
+
+struct Parser<'a, F> {
+    image_loader: &'a F,
+}
+
+impl<'a, F> Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    fn block(&self, node: &Value, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        match node_type(node) {
+            "heading" => elements.push(Element::Header {
+                level: node
+                    .get("attrs")
+                    .and_then(|attrs| attrs.get("level"))
+                    .and_then(Value::as_u64)
+                    .unwrap_or(1)
+                    .clamp(1, 6) as u8,
+                text: plain_text(node),
+                anchor: None,
+            }),
+            "paragraph" => {
+                let inline = self.inline(node)?;
+                match inline.as_slice() {
+                    [] => {}
+                    [Element::Image(_)] => elements.extend(inline),
+                    _ => elements.push(Element::Paragraph { elements: inline }),
+                }
+            }
+            "codeBlock" => elements.push(Element::Text {
+                text: plain_text(node),
+                font: FontSpec::from(8),
+            }),
+            "bulletList" | "orderedList" | "taskList" => elements.push(self.list(node)?),
+            "table" => elements.push(self.table(node)?),
+            "image" => elements.push(self.image(node)?),
+            "horizontalRule" => elements.push(Element::HorizontalRule),
+            "blockquote" => {
+                let mut quote = Vec::new();
+                for child in content(node) {
+                    self.block(child, &mut quote)?;
+                }
+                elements.push(Element::Blockquote {
+                    elements: quote,
+                    attribution: vec![],
+                });
+            }
+            // Unknown containers keep their content
+            _ => {
+                for child in content(node) {
+                    self.block(child, elements)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The paragraphs of an item become list items, nested lists follow them
+    fn list(&self, node: &Value) -> anyhow::Result<Element> {
+        let mut elements = Vec::new();
+        for item in content(node) {
+            // Task items carry their state, it belongs to the first paragraph
+            let mut checked = item
+                .get("attrs")
+                .and_then(|attrs| attrs.get("checked"))
+                .and_then(Value::as_bool);
+            for child in content(item) {
+                match node_type(child) {
+                    "bulletList" | "orderedList" | "taskList" => elements.push(ListItem {
+                        element: self.list(child)?,
+                        checked: None,
+                    }),
+                    _ => {
+                        let mut blocks = Vec::new();
+                        self.block(child, &mut blocks)?;
+                        for block in blocks {
+                            let element = match block {
+                                Element::Paragraph { elements } => unwrap_inline(elements),
+                                element => element,
+                            };
+                            elements.push(ListItem {
+                                element,
+                                checked: checked.take(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Element::List {
+            elements,
+            numbered: node_type(node) == "orderedList",
+        })
+    }
+
+    /// A first row made of header cells gives the table headers
+    fn table(&self, node: &Value) -> anyhow::Result<Element> {
+        let mut headers = Vec::new();
+        let mut rows = Vec::new();
+        for (index, row) in content(node).enumerate() {
+            let is_header = content(row).all(|cell| node_type(cell) == "tableHeader");
+            let mut cells = Vec::new();
+            for cell in content(row) {
+                let mut inline = Vec::new();
+                for (number, paragraph) in content(cell).enumerate() {
+                    if number > 0 {
+                        inline.push(Element::LineBreak);
+                    }
+                    inline.extend(self.inline(paragraph)?);
+                }
+                cells.push(unwrap_inline(inline));
+            }
+            if index == 0 && is_header && !cells.is_empty() {
+                headers = cells
+                    .into_iter()
+                    .map(|element| TableHeader {
+                        element,
+                        width: 30.0,
+                    })
+                    .collect();
+            } else {
+                rows.push(TableRow {
+                    cells: cells.into_iter().map(TableCell::new).collect(),
+                });
+            }
+        }
+        Ok(Element::Table {
+            headers,
+            rows,
+            alignments: vec![],
+            caption: vec![],
+        })
+    }
+
+    fn inline(&self, node: &Value) -> anyhow::Result<Vec<Element>> {
+        let mut elements: Vec<Element> = Vec::new();
+        for child in content(node) {
+            match node_type(child) {
+                "text" => {
+                    let text = child.get("text").and_then(Value::as_str).unwrap_or("");
+                    let link = child
+                        .get("marks")
+                        .and_then(Value::as_array)
+                        .into_iter()
+                        .flatten()
+                        .find(|mark| mark.get("type").and_then(Value::as_str) == Some("link"))
+                        .and_then(|mark| attribute(mark, "href"));
+                    match (link, elements.last_mut()) {
+                        (Some(url), _) => elements.push(Element::Hyperlink {
+                            title: text.to_string(),
+                            url,
+                            alt: String::new(),
+                            size: 8,
+                        }),
+                        // Text split by marks that are not kept is joined again
+                        (None, Some(Element::Text { text: previous, .. })) => {
+                            previous.push_str(text)
+                        }
+                        (None, _) => elements.push(Element::Text {
+                            text: text.to_string(),
+                            font: FontSpec::from(8),
+                        }),
+                    }
+                }
+                "hardBreak" => elements.push(Element::LineBreak),
+                "image" => elements.push(self.image(child)?),
+                _ => elements.extend(self.inline(child)?),
+            }
+        }
+        Ok(elements)
+    }
+
+    fn image(&self, node: &Value) -> anyhow::Result<Element> {
+        let src = attribute(node, "src").unwrap_or_default();
+        let (bytes, image_type) = match src
+            .strip_prefix("data:")
+            .and_then(|data| data.split_once(";base64,"))
+        {
+            Some((mime_type, data)) => {
+                let image_type = IMAGE_TYPES
+                    .iter()
+                    .find(|(known, _)| *known == mime_type)
+                    .map_or("png", |(_, image_type)| *image_type);
+                let data: String = data.split_whitespace().collect();
+                (
+                    Bytes::from(general_purpose::STANDARD.decode(data)?),
+                    image_type,
+                )
+            }
+            None => ((self.image_loader)(&src)?, image_type(&src)),
+        };
+        Ok(Element::Image(ImageData::new(
+            bytes,
+            attribute(node, "title").unwrap_or_default(),
+            attribute(node, "alt").unwrap_or_default(),
+            image_type.to_string(),
+            String::new(),
+            ImageDimension {
+                width: attribute(node, "width"),
+                height: attribute(node, "height"),
+            },
+        )))
+    }
+}
+
+struct Generator<F> {
+    image_saver: F,
+    image_num: usize,
+}
+
+impl<F> Generator<F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn block(&mut self, element: &Element, nodes: &mut Vec<Value>) -> anyhow::Result<()> {
+        match element {
+            Element::Header { level, text, .. } => nodes.push(json!({
+                "type": "heading",
+                "attrs": { "level": level },
+                "content": text_nodes(&text.replace('\n', " ")),
+            })),
+            // Code blocks keep their line breaks in the text
+            Element::Text { text, .. } if text.contains('\n') => nodes.push(json!({
+                "type": "codeBlock",
+                "content": [{ "type": "text", "text": text.trim_end_matches('\n') }],
+            })),
+            Element::CodeBlock { language, code } => {
+                let mut node = json!({ "type": "codeBlock", "attrs": { "language": language } });
+                if !code.is_empty() {
+                    node["content"] = json!([{ "type": "text", "text": code }]);
+                }
+                nodes.push(node);
+            }
+            Element::List { elements, numbered } => nodes.push(self.list(elements, *numbered)?),
+            // The basic schema has no definition lists, a bold term precedes its definitions
+            Element::DefinitionList { items } => {
+                for item in items {
+                    nodes.push(self.paragraph(&Element::Styled {
+                        style: TextStyle::BOLD,
+                        elements: item.term.clone(),
+                    })?);
+                    for definition in &item.definitions {
+                        nodes.push(self.paragraph(definition)?);
+                    }
+                }
+            }
+            Element::HorizontalRule => nodes.push(json!({ "type": "horizontalRule" })),
+            Element::Chart { .. } => self.block(&element.chart_fallback(), nodes)?,
+            Element::Table { headers, rows, .. } => {
+                let mut table_rows = Vec::new();
+                if !headers.is_empty() {
+                    let mut cells = Vec::new();
+                    for header in headers {
+                        cells.push(self.cell("tableHeader", &header.element)?);
+                    }
+                    table_rows.push(json!({ "type": "tableRow", "content": cells }));
+                }
+                for row in rows {
+                    let mut cells = Vec::new();
+                    for cell in &row.cells {
+                        cells.push(self.cell("tableCell", &cell.element())?);
+                    }
+                    table_rows.push(json!({ "type": "tableRow", "content": cells }));
+                }
+                nodes.push(json!({ "type": "table", "content": table_rows }));
+            }
+            Element::Image(image) => nodes.push(self.image(image)?),
+            // The caption is an italic paragraph below the image
+            Element::Figure { image, caption } => {
+                nodes.push(self.image(image)?);
+                if !caption.is_empty() {
+                    nodes.push(self.paragraph(&Element::Styled {
+                        style: TextStyle {
+                            italic: true,
+                            ..Default::default()
+                        },
+                        elements: caption.clone(),
+                    })?);
+                }
+            }
+            Element::Blockquote { elements, .. } => {
+                let mut content = Vec::new();
+                for child in elements {
+                    self.block(child, &mut content)?;
+                }
+                // A blockquote needs at least one block
+                if content.is_empty() {
+                    content.push(json!({ "type": "paragraph" }));
+                }
+                nodes.push(json!({ "type": "blockquote", "content": content }));
+            }
+            // The default schema has no sections, the headings stand between the blocks
+            Element::Section { children, .. } => {
+                for child in children {
+                    self.block(child, nodes)?;
+                }
+            }
+            Element::Comment { .. } | Element::PageBreak | Element::TableOfContents { .. } => {}
+            element => nodes.push(self.paragraph(element)?),
+        }
+        Ok(())
+    }
+
+    fn paragraph(&mut self, element: &Element) -> anyhow::Result<Value> {
+        let mut inline = Vec::new();
+        self.inline(element, &mut inline)?;
+        let mut paragraph = json!({ "type": "paragraph" });
+        // Nodes without content must not have an empty content array
+        if !inline.is_empty() {
+            paragraph["content"] = Value::Array(inline);
+        }
+        Ok(paragraph)
+    }
+
+    /// A nested list goes into the item before it
+    fn list(&mut self, items: &[ListItem], numbered: bool) -> anyhow::Result<Value> {
+        let mut list_items: Vec<Value> = Vec::new();
+        for item in items {
+            match &item.element {
+                Element::List { elements, numbered } => {
+                    let nested = self.list(elements, *numbered)?;
+                    match list_items
+                        .last_mut()
+                        .and_then(|item| item["content"].as_array_mut())
+                    {
+                        Some(content) => content.push(nested),
+                        None => list_items.push(json!({
+                            "type": "listItem",
+                            "content": [{ "type": "paragraph" }, nested],
+                        })),
+                    }
+                }
+                element => list_items.push(json!({
+                    "type": "listItem",
+                    "content": [self.paragraph(element)?],
+                })),
+            }
+        }
+        let list_type = if numbered {
+            "orderedList"
+        } else {
+            "bulletList"
+        };
+        Ok(json!({ "type": list_type, "content": list_items }))
+    }
+
+    fn cell(&mut self, cell_type: &str, element: &Element) -> anyhow::Result<Value> {
+        Ok(json!({
+            "type": cell_type,
+            "content": [self.paragraph(element)?],
+        }))
+    }
+
+    fn inline(&mut self, element: &Element, nodes: &mut Vec<Value>) -> anyhow::Result<()> {
+        match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => {
+                nodes.extend(text_nodes(text))
+            }
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => {
+                for child in elements {
+                    self.inline(child, nodes)?;
+                }
+            }
+            Element::Styled { style, elements } => {
+                let marks: Vec<Value> = [
+                    (style.bold, "bold"),
+                    (style.italic, "italic"),
+                    (style.underline, "underline"),
+                    (style.strikethrough, "strike"),
+                ]
+                .into_iter()
+                .filter(|(enabled, _)| *enabled)
+                .map(|(_, mark)| json!({ "type": mark }))
+                .collect();
+                let mut children = Vec::new();
+                for child in elements {
+                    self.inline(child, &mut children)?;
+                }
+                for mut node in children {
+                    if node["type"] == "text" {
+                        let mut node_marks = node["marks"].as_array().cloned().unwrap_or_default();
+                        node_marks.extend(marks.iter().cloned());
+                        node["marks"] = Value::Array(node_marks);
+                    }
+                    nodes.push(node);
+                }
+            }
+            Element::Hyperlink { title, url, .. } => {
+                let title = if title.is_empty() { url } else { title };
+                for mut node in text_nodes(title) {
+                    if node["type"] == "text" {
+                        node["marks"] = json!([{ "type": "link", "attrs": { "href": url } }]);
+                    }
+                    nodes.push(node);
+                }
+            }
+            Element::Image(image) => nodes.push(self.image(image)?),
+            Element::Figure { image, caption } => {
+                nodes.push(self.image(image)?);
+                nodes.push(json!({ "type": "hardBreak" }));
+                for child in caption {
+                    self.inline(child, nodes)?;
+                }
+            }
+            Element::LineBreak | Element::HorizontalRule => {
+                nodes.push(json!({ "type": "hardBreak" }))
+            }
+            Element::List { elements, .. } => {
+                for item in elements {
+                    self.inline(&item.element, nodes)?;
+                    nodes.extend(text_nodes(" "));
+                }
+            }
+            Element::DefinitionList { items } => {
+                for child in items
+                    .iter()
+                    .flat_map(|item| item.term.iter().chain(&item.definitions))
+                {
+                    self.inline(child, nodes)?;
+                    nodes.extend(text_nodes(" "));
+                }
+            }
+            Element::CodeBlock { code, .. } => {
+                if !code.is_empty() {
+                    nodes.push(json!({
+                        "type": "text",
+                        "text": code.replace('\n', " "),
+                        "marks": [{ "type": "code" }],
+                    }));
+                }
+            }
+            Element::Math { tex, .. } => nodes.extend(text_nodes(tex)),
+            Element::FootnoteReference { id } => nodes.extend(text_nodes(&format!("[{}]", id))),
+            Element::Footnote { id, content } => {
+                nodes.extend(text_nodes(&format!("[{}] ", id)));
+                for child in content {
+                    self.inline(child, nodes)?;
+                }
+            }
+            Element::Media { .. } => self.inline(&element.media_fallback(), nodes)?,
+            Element::Ruby { .. } => self.inline(&element.ruby_fallback(), nodes)?,
+            Element::Table { .. }
+            | Element::Chart { .. }
+            | Element::Comment { .. }
+            | Element::PageBreak
+            | Element::TableOfContents { .. }
+            | Element::Custom { .. } => {}
+        }
+        Ok(())
+    }
+
+    fn image(&mut self, image: &ImageData) -> anyhow::Result<Value> {
+        let src = format!(
+            "image{}{}",
+            self.image_num,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &src)?;
+        self.image_num += 1;
+
+        let mut attrs = Map::new();
+        attrs.insert("src".to_string(), json!(src));
+        attrs.insert("alt".to_string(), json!(image.alt()));
+        attrs.insert("title".to_string(), json!(image.title()));
+        if let Some(width) = &image.size().width {
+            attrs.insert("width".to_string(), json!(width));
+        }
+        if let Some(height) = &image.size().height {
+            attrs.insert("height".to_string(), json!(height));
+        }
+        Ok(json!({ "type": "image", "attrs": attrs }))
+    }
+}
+
+/// Text nodes with hard breaks for the line breaks, ProseMirror rejects empty text nodes
+fn text_nodes(text: &str) -> Vec<Value> {
+    let mut nodes = Vec::new();
+    for (index, line) in text.split('\n').enumerate() {
+        if index > 0 {
+            nodes.push(json!({ "type": "hardBreak" }));
+        }
+        if !line.is_empty() {
+            nodes.push(json!({ "type": "text", "text": line }));
+        }
+    }
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prosemirror::*;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let doc = r#"{"type": "doc", "content": [
+            {"type": "heading", "attrs": {"level": 2}, "content": [{"type": "text", "text": "Notes"}]},
+            {"type": "paragraph", "content": [
+                {"type": "text", "text": "See "},
+                {"type": "text", "text": "bold", "marks": [{"type": "bold"}]},
+                {"type": "hard_break"},
+                {"type": "text", "text": "docs", "marks": [{"type": "link", "attrs": {"href": "https://example.com"}}]}
+            ]},
+            {"type": "bulletList", "content": [
+                {"type": "listItem", "content": [
+                    {"type": "paragraph", "content": [{"type": "text", "text": "One"}]},
+                    {"type": "orderedList", "content": [
+                        {"type": "listItem", "content": [{"type": "paragraph", "content": [{"type": "text", "text": "Nested"}]}]}
+                    ]}
+                ]}
+            ]},
+            {"type": "codeBlock", "content": [{"type": "text", "text": "a\nb"}]},
+            {"type": "table", "content": [
+                {"type": "tableRow", "content": [
+                    {"type": "tableHeader", "content": [{"type": "paragraph", "content": [{"type": "text", "text": "Name"}]}]}
+                ]},
+                {"type": "tableRow", "content": [
+                    {"type": "tableCell", "content": [{"type": "paragraph", "content": [{"type": "text", "text": "shiva"}]}]}
+                ]}
+            ]},
+            {"type": "image", "attrs": {"src": "logo.jpg", "alt": "Logo", "title": null, "width": 120}}
+        ]}"#;
+        let document = Transformer::parse_with_loader(&Bytes::from(doc), |src| {
+            assert_eq!(src, "logo.jpg");
+            Ok(Bytes::from_static(b"JPEG"))
+        })?;
+        let elements = document.get_all_elements();
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        assert_eq!(
+            elements[..5],
+            [
+                &Element::Header {
+                    level: 2,
+                    text: "Notes".to_string(),
+                    anchor: None,
+                },
+                &Element::Paragraph {
+                    elements: vec![
+                        text("See bold"),
+                        Element::LineBreak,
+                        Element::Hyperlink {
+                            title: "docs".to_string(),
+                            url: "https://example.com".to_string(),
+                            alt: String::new(),
+                            size: 8,
+                        },
+                    ]
+                },
+                &Element::List {
+                    elements: vec![
+                        ListItem {
+                            element: text("One"),
+                            checked: None,
+                        },
+                        ListItem {
+                            element: Element::List {
+                                elements: vec![ListItem {
+                                    element: text("Nested"),
+                                    checked: None,
+                                }],
+                                numbered: true,
+                            },
+                            checked: None,
+                        },
+                    ],
+                    numbered: false,
+                },
+                &text("a\nb"),
+                &Element::Table {
+                    headers: vec![TableHeader {
+                        element: text("Name"),
+                        width: 30.0,
+                    }],
+                    rows: vec![TableRow {
+                        cells: vec![TableCell::new(text("shiva"))]
+                    }],
+                    alignments: vec![],
+                    caption: vec![],
+                },
+            ]
+        );
+        let Element::Image(image) = elements[5] else {
+            panic!("expected an image, got {:?}", elements[5]);
+        };
+        assert_eq!(image.bytes(), &Bytes::from_static(b"JPEG"));
+        assert_eq!(image.image_type(), &ImageType::Jpeg);
+        assert_eq!(image.alt(), "Logo");
+        assert_eq!(image.size().width.as_deref(), Some("120"));
+        assert_eq!(elements.len(), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let images = RefCell::new(HashMap::new());
+        let generated = Transformer::generate_with_saver(&parsed, |bytes, name| {
+            images.borrow_mut().insert(name.to_string(), bytes.clone());
+            Ok(())
+        })?;
+        let reparsed = Transformer::parse_with_loader(&generated, |name| {
+            Ok(images.borrow().get(name).cloned().unwrap_or_default())
+        })?;
+        let original = parsed.get_all_elements();
+        let elements = reparsed.get_all_elements();
+        assert_eq!(original.len(), elements.len());
+        for (original, element) in original.iter().zip(elements.iter()) {
+            assert_eq!(
+                std::mem::discriminant(*original),
+                std::mem::discriminant(*element)
+            );
+        }
+        Ok(())
+    }
+}
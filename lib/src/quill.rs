@@ -0,0 +1,808 @@
+use crate::core::*;
+use base64::{engine::general_purpose, Engine as _};
+use bytes::Bytes;
+use serde_json::{json, Map, Value};
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    /// Reads the insert operations of a document delta, either `{"ops": [...]}` or the bare
+    /// array. The attributes of each newline format the line before it: headers, lists with
+    /// their indent, code blocks and table rows. Links are kept from the inline attributes,
+    /// other inline formats like bold and italic are dropped. Images with a `data:` URL are
+    /// decoded, other image URLs are kept without their content. Formula embeds are inline
+    /// math.
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        let delta: Value = serde_json::from_slice(document)?;
+        let ops = delta
+            .get("ops")
+            .unwrap_or(&delta)
+            .as_array()
+            .ok_or_else(|| ParserError::Malformed("delta has no ops".to_string()))?;
+
+        let mut parser = Parser::default();
+        for op in ops {
+            let attributes = op.get("attributes").and_then(Value::as_object);
+            match op.get("insert") {
+                Some(Value::String(text)) => {
+                    let mut segments = text.split('\n').peekable();
+                    while let Some(segment) = segments.next() {
+                        parser.text(segment, attributes);
+                        if segments.peek().is_some() {
+                            parser.end_line(attributes);
+                        }
+                    }
+                }
+                Some(Value::Object(embed)) => parser.embed(embed, attributes)?,
+                _ => {}
+            }
+        }
+        parser.end_line(None);
+        parser.flush();
+        Ok(Document::new(parser.elements))
+    }
+
+    /// Produces a canonical delta: adjacent inserts with the same attributes are merged.
+    /// Images are embedded as `data:` URLs, tables use the `table` line format of Quill 2
+    /// with the header cells in bold, and comments are dropped.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        // A table of contents is written as the list of its headers
+        let document = &document.expand_toc();
+        let mut generator = Generator::default();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                generator.block(element);
+            }
+        }
+        Ok(Bytes::from(serde_json::to_vec_pretty(
+            &json!({ "ops": generator.ops }),
+        )?))
+    }
+}
+
+const IMAGE_TYPES: [(&str, &str); 4] = [
+    ("image/png", "png"),
+    ("image/jpeg", "jpeg"),
+    ("image/gif", "gif"),
+    ("image/svg+xml", "svg"),
+];
+
+/// Image type of a URL from its extension, `ImageData` only knows png, jpeg, gif and svg
+fn image_type(src: &str) -> &'static str {
+    let path = src.split(['?', '#']).next().unwrap_or(src);
+    match path.rsplit('.').next().map(str::to_lowercase).as_deref() {
+        Some("jpg" | "jpeg") => "jpeg",
+        Some("gif") => "gif",
+        Some("svg") => "svg",
+        _ => "png",
+    }
+}
+
+/// Content of a line before its newline, images wait for the line format to get their alignment
+enum Inline {
+    Text {
+        text: String,
+        link: Option<String>,
+    },
+    Image {
+        bytes: Bytes,
+        alt: String,
+        image_type: String,
+        size: ImageDimension,
+    },
+    Math(String),
+}
+
+/// Consecutive lines of one block format are collected before they become an element
+enum Pending {
+    /// Indent, numbered and content of every item
+    List(Vec<(usize, bool, Element)>),
+    Code(Vec<String>),
+    /// Row id and cells of every row
+    Table(Vec<(String, Vec<TableCell>)>),
+}
+
+#[derive(Default)]
+struct Parser {
+    elements: Vec<Element>,
+    line: Vec<Inline>,
+    pending: Option<Pending>,
+}
+
+impl Parser {
+    fn text(&mut self, text: &str, attributes: Option<&Map<String, Value>>) {
+        if text.is_empty() {
+            return;
+        }
+        let link = attributes
+            .and_then(|attributes| attributes.get("link"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        match self.line.last_mut() {
+            Some(Inline::Text {
+                text: previous,
+                link: previous_link,
+            }) if *previous_link == link => previous.push_str(text),
+            _ => self.line.push(Inline::Text {
+                text: text.to_string(),
+                link,
+            }),
+        }
+    }
+
+    fn embed(
+        &mut self,
+        embed: &Map<String, Value>,
+        attributes: Option<&Map<String, Value>>,
+    ) -> anyhow::Result<()> {
+        let attribute = |name: &str| {
+            attributes
+                .and_then(|attributes| attributes.get(name))
+                .and_then(|value| match value {
+                    Value::String(value) => Some(value.clone()),
+                    Value::Number(value) => Some(value.to_string()),
+                    _ => None,
+                })
+        };
+        if let Some(src) = embed.get("image").and_then(Value::as_str) {
+            let (bytes, image_type) = match src
+                .strip_prefix("data:")
+                .and_then(|data| data.split_once(";base64,"))
+            {
+                Some((mime_type, data)) => {
+                    let image_type = IMAGE_TYPES
+                        .iter()
+                        .find(|(known, _)| *known == mime_type)
+                        .map_or("png", |(_, image_type)| *image_type);
+                    let data: String = data.split_whitespace().collect();
+                    (
+                        Bytes::from(general_purpose::STANDARD.decode(data)?),
+                        image_type,
+                    )
+                }
+                None => (Bytes::new(), image_type(src)),
+            };
+            self.line.push(Inline::Image {
+                bytes,
+                alt: attribute("alt").unwrap_or_default(),
+                image_type: image_type.to_string(),
+                size: ImageDimension {
+                    width: attribute("width"),
+                    height: attribute("height"),
+                },
+            });
+        } else if let Some(url) = embed.get("video").and_then(Value::as_str) {
+            self.line.push(Inline::Text {
+                text: url.to_string(),
+                link: Some(url.to_string()),
+            });
+        } else if let Some(formula) = embed.get("formula").and_then(Value::as_str) {
+            self.line.push(Inline::Math(formula.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Formats the collected line with the attributes of its newline
+    fn end_line(&mut self, attributes: Option<&Map<String, Value>>) {
+        let line = std::mem::take(&mut self.line);
+        let attribute = |name: &str| attributes.and_then(|attributes| attributes.get(name));
+        let align = attribute("align").and_then(Value::as_str).unwrap_or("");
+
+        if let Some(level) = attribute("header").and_then(Value::as_u64) {
+            self.flush();
+            self.elements.push(Element::Header {
+                level: level.clamp(1, 6) as u8,
+                text: plain_text(&line),
+                anchor: None,
+            });
+        } else if let Some(kind) = attribute("list").and_then(Value::as_str) {
+            let indent = attribute("indent").and_then(Value::as_u64).unwrap_or(0) as usize;
+            let item = (indent, kind == "ordered", content(line, align));
+            match &mut self.pending {
+                Some(Pending::List(items)) => items.push(item),
+                _ => {
+                    self.flush();
+                    self.pending = Some(Pending::List(vec![item]));
+                }
+            }
+        } else if attribute("code-block").is_some_and(|value| value != &Value::Bool(false)) {
+            let text = plain_text(&line);
+            match &mut self.pending {
+                Some(Pending::Code(lines)) => lines.push(text),
+                _ => {
+                    self.flush();
+                    self.pending = Some(Pending::Code(vec![text]));
+                }
+            }
+        } else if let Some(row) = attribute("table").and_then(Value::as_str) {
+            let cell = TableCell::new(content(line, align));
+            match &mut self.pending {
+                Some(Pending::Table(rows)) => match rows.last_mut() {
+                    Some((id, cells)) if id == row => cells.push(cell),
+                    _ => rows.push((row.to_string(), vec![cell])),
+                },
+                _ => {
+                    self.flush();
+                    self.pending = Some(Pending::Table(vec![(row.to_string(), vec![cell])]));
+                }
+            }
+        } else {
+            self.flush();
+            if line.is_empty() {
+                return;
+            }
+            match content(line, align) {
+                Element::Image(image) => self.elements.push(Element::Image(image)),
+                Element::Paragraph { elements } => {
+                    self.elements.push(Element::Paragraph { elements })
+                }
+                element => self.elements.push(Element::Paragraph {
+                    elements: vec![element],
+                }),
+            }
+        }
+    }
+
+    fn flush(&mut self) {
+        match self.pending.take() {
+            Some(Pending::List(items)) => {
+                let mut items = items.into_iter().peekable();
+                let (elements, numbered) = list(&mut items, 0);
+                self.elements.push(Element::List { elements, numbered });
+            }
+            Some(Pending::Code(lines)) => self.elements.push(Element::Text {
+                text: lines.join("\n"),
+                font: FontSpec::from(8),
+            }),
+            Some(Pending::Table(rows)) => self.elements.push(Element::Table {
+                headers: Vec::new(),
+                rows: rows
+                    .into_iter()
+                    .map(|(_, cells)| TableRow { cells })
+                    .collect(),
+                alignments: vec![],
+                caption: vec![],
+            }),
+            None => {}
+        }
+    }
+}
+
+/// Items at `indent` and deeper, deeper items form a nested list after their parent
+fn list(
+    items: &mut std::iter::Peekable<impl Iterator<Item = (usize, bool, Element)>>,
+    indent: usize,
+) -> (Vec<ListItem>, bool) {
+    let mut elements = Vec::new();
+    let mut numbered = None;
+    while let Some((item_indent, item_numbered, _)) = items.peek() {
+        if *item_indent < indent {
+            break;
+        }
+        if *item_indent > indent {
+            let (nested, nested_numbered) = list(items, indent + 1);
+            elements.push(ListItem {
+                element: Element::List {
+                    elements: nested,
+                    numbered: nested_numbered,
+                },
+                checked: None,
+            });
+            continue;
+        }
+        numbered.get_or_insert(*item_numbered);
+        if let Some((_, _, element)) = items.next() {
+            elements.push(ListItem {
+                element,
+                checked: None,
+            });
+        }
+    }
+    (elements, numbered.unwrap_or(false))
+}
+
+fn plain_text(line: &[Inline]) -> String {
+    line.iter()
+        .map(|inline| match inline {
+            Inline::Text { text, .. } => text.as_str(),
+            Inline::Image { alt, .. } => alt.as_str(),
+            Inline::Math(tex) => tex.as_str(),
+        })
+        .collect()
+}
+
+/// A single text, link or image stands alone, mixed content becomes a paragraph
+fn content(line: Vec<Inline>, align: &str) -> Element {
+    let mut elements: Vec<Element> = line
+        .into_iter()
+        .map(|inline| match inline {
+            Inline::Text { text, link: None } => Element::Text {
+                text,
+                font: FontSpec::from(8),
+            },
+            Inline::Text {
+                text,
+                link: Some(url),
+            } => Element::Hyperlink {
+                title: text,
+                url,
+                alt: String::new(),
+                size: 8,
+            },
+            Inline::Image {
+                bytes,
+                alt,
+                image_type,
+                size,
+            } => Element::Image(ImageData::new(
+                bytes,
+                String::new(),
+                alt,
+                image_type,
+                align.to_string(),
+                size,
+            )),
+            Inline::Math(tex) => Element::Math {
+                tex,
+                display: false,
+            },
+        })
+        .collect();
+    match elements.len() {
+        0 => Element::Text {
+            text: String::new(),
+            font: FontSpec::from(8),
+        },
+        1 => elements.remove(0),
+        _ => Element::Paragraph { elements },
+    }
+}
+
+#[derive(Default)]
+struct Generator {
+    ops: Vec<Value>,
+    rows: usize,
+}
+
+impl Generator {
+    /// Appends an insert, merging it into the previous one when both are text with the same
+    /// attributes
+    fn push(&mut self, insert: Value, attributes: Map<String, Value>) {
+        if let (Value::String(text), Some(previous)) = (&insert, self.ops.last_mut()) {
+            let same_attributes = previous
+                .get("attributes")
+                .and_then(Value::as_object)
+                .map_or(attributes.is_empty(), |previous| *previous == attributes);
+            if let Some(Value::String(previous)) = previous.get_mut("insert") {
+                if same_attributes {
+                    previous.push_str(text);
+                    return;
+                }
+            }
+        }
+        let mut op = Map::new();
+        op.insert("insert".to_string(), insert);
+        if !attributes.is_empty() {
+            op.insert("attributes".to_string(), Value::Object(attributes));
+        }
+        self.ops.push(Value::Object(op));
+    }
+
+    fn newline(&mut self, attributes: Map<String, Value>) {
+        self.push(Value::String("\n".to_string()), attributes);
+    }
+
+    fn block(&mut self, element: &Element) {
+        match element {
+            Element::Header { level, text, .. } => {
+                self.text(&text.replace('\n', " "), Map::new());
+                self.newline(attributes([("header", json!(level))]));
+            }
+            // Multi-line text is a code block, every line ends with the format
+            Element::Text { text, .. } if text.contains('\n') => {
+                for line in text.trim_end_matches('\n').split('\n') {
+                    self.text(line, Map::new());
+                    self.newline(attributes([("code-block", json!(true))]));
+                }
+            }
+            // Quill keeps the language as the value of the format
+            Element::CodeBlock { language, code } => {
+                let format = match language {
+                    Some(language) => json!(language),
+                    None => json!(true),
+                };
+                for line in code.split('\n') {
+                    self.text(line, Map::new());
+                    self.newline(attributes([("code-block", format.clone())]));
+                }
+            }
+            // Every quoted line ends with the format, other blocks inside lose the quote
+            Element::Blockquote { elements, .. } => {
+                let format = attributes([("blockquote", json!(true))]);
+                for child in elements {
+                    match child {
+                        Element::Paragraph { .. } | Element::Text { .. } => {
+                            self.inline(child, &Map::new(), &format);
+                            self.newline(format.clone());
+                        }
+                        child => self.block(child),
+                    }
+                }
+            }
+            Element::Section { children, .. } => {
+                for child in children {
+                    self.block(child);
+                }
+            }
+            Element::List { elements, numbered } => self.list(elements, *numbered, 0),
+            // A bold term line, the definitions are indented lines below it
+            Element::DefinitionList { items } => {
+                let bold = attributes([("bold", json!(true))]);
+                let indent = attributes([("indent", json!(1))]);
+                for item in items {
+                    for child in &item.term {
+                        self.inline(child, &bold, &Map::new());
+                    }
+                    self.newline(Map::new());
+                    for definition in &item.definitions {
+                        self.inline(definition, &Map::new(), &indent);
+                        self.newline(indent.clone());
+                    }
+                }
+            }
+            Element::Chart { .. } => self.block(&element.chart_fallback()),
+            Element::Table { headers, rows, .. } => {
+                if !headers.is_empty() {
+                    self.rows += 1;
+                    let row = format!("row-{}", self.rows);
+                    for header in headers {
+                        self.cell(&header.element, &row, true);
+                    }
+                }
+                for cells in rows {
+                    self.rows += 1;
+                    let row = format!("row-{}", self.rows);
+                    for cell in &cells.cells {
+                        self.cell(&cell.element(), &row, false);
+                    }
+                }
+            }
+            Element::Image(image) => {
+                self.image(image);
+                let align = match image.align() {
+                    ImageAlignment::Left => "left",
+                    ImageAlignment::Center => "center",
+                    ImageAlignment::Right => "right",
+                    ImageAlignment::None => "",
+                };
+                if align.is_empty() {
+                    self.newline(Map::new());
+                } else {
+                    self.newline(attributes([("align", json!(align))]));
+                }
+            }
+            // The caption is an italic line below the image
+            Element::Figure { image, caption } => {
+                self.block(&Element::Image(image.clone()));
+                let italic = attributes([("italic", json!(true))]);
+                for child in caption {
+                    self.inline(child, &italic, &Map::new());
+                }
+                self.newline(Map::new());
+            }
+            Element::Comment { .. } | Element::PageBreak | Element::TableOfContents { .. } => {}
+            element => {
+                self.inline(element, &Map::new(), &Map::new());
+                self.newline(Map::new());
+            }
+        }
+    }
+
+    fn list(&mut self, items: &[ListItem], numbered: bool, indent: usize) {
+        for item in items {
+            if let Element::List { elements, numbered } = &item.element {
+                self.list(elements, *numbered, indent + 1);
+                continue;
+            }
+            let mut format =
+                attributes([("list", json!(if numbered { "ordered" } else { "bullet" }))]);
+            if indent > 0 {
+                format.insert("indent".to_string(), json!(indent));
+            }
+            self.inline(&item.element, &Map::new(), &format);
+            self.newline(format);
+        }
+    }
+
+    /// Cells hold a single line, a cell without content still needs its newline
+    fn cell(&mut self, element: &Element, row: &str, header: bool) {
+        let format = attributes([("table", json!(row))]);
+        let inline = if header {
+            attributes([("bold", json!(true))])
+        } else {
+            Map::new()
+        };
+        self.inline(element, &inline, &format);
+        self.newline(format);
+    }
+
+    /// Inline content, line breaks inside it end the line with the `line` format
+    fn inline(
+        &mut self,
+        element: &Element,
+        format: &Map<String, Value>,
+        line: &Map<String, Value>,
+    ) {
+        match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => {
+                let mut lines = text.split('\n').peekable();
+                while let Some(text) = lines.next() {
+                    self.text(text, format.clone());
+                    if lines.peek().is_some() {
+                        self.newline(line.clone());
+                    }
+                }
+            }
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => {
+                for child in elements {
+                    self.inline(child, format, line);
+                }
+            }
+            // Quill formulas are always inline
+            Element::Math { tex, .. } => self.push(json!({ "formula": tex }), format.clone()),
+            // The divider embed of the Quill guides, editors without it skip the embed
+            Element::HorizontalRule => self.push(json!({ "divider": true }), Map::new()),
+            Element::Styled { style, elements } => {
+                let mut format = format.clone();
+                for (enabled, attribute) in [
+                    (style.bold, "bold"),
+                    (style.italic, "italic"),
+                    (style.underline, "underline"),
+                    (style.strikethrough, "strike"),
+                ] {
+                    if enabled {
+                        format.insert(attribute.to_string(), json!(true));
+                    }
+                }
+                for child in elements {
+                    self.inline(child, &format, line);
+                }
+            }
+            Element::Hyperlink { title, url, .. } => {
+                let title = if title.is_empty() { url } else { title };
+                let mut format = format.clone();
+                format.insert("link".to_string(), json!(url));
+                self.text(title, format);
+            }
+            Element::Image(image) => self.image(image),
+            Element::Figure { image, caption } => {
+                self.image(image);
+                self.newline(line.clone());
+                for child in caption {
+                    self.inline(child, format, line);
+                }
+            }
+            Element::LineBreak => self.newline(line.clone()),
+            Element::List { elements, .. } => {
+                for item in elements {
+                    self.inline(&item.element, format, line);
+                    self.text(" ", format.clone());
+                }
+            }
+            Element::DefinitionList { items } => {
+                for child in items
+                    .iter()
+                    .flat_map(|item| item.term.iter().chain(&item.definitions))
+                {
+                    self.inline(child, format, line);
+                    self.text(" ", format.clone());
+                }
+            }
+            Element::CodeBlock { code, .. } => {
+                let mut format = format.clone();
+                format.insert("code".to_string(), json!(true));
+                self.text(&code.replace('\n', " "), format);
+            }
+            Element::FootnoteReference { id } | Element::Footnote { id, .. } => {
+                let mut mark = format.clone();
+                mark.insert("script".to_string(), json!("super"));
+                self.text(id, mark);
+                if let Element::Footnote { content, .. } = element {
+                    self.text(" ", format.clone());
+                    for child in content {
+                        self.inline(child, format, line);
+                    }
+                }
+            }
+            Element::Media { .. } => self.inline(&element.media_fallback(), format, line),
+            Element::Ruby { .. } => self.inline(&element.ruby_fallback(), format, line),
+            Element::Table { .. }
+            | Element::Chart { .. }
+            | Element::Comment { .. }
+            | Element::PageBreak
+            | Element::TableOfContents { .. }
+            | Element::Custom { .. } => {}
+        }
+    }
+
+    fn text(&mut self, text: &str, format: Map<String, Value>) {
+        if !text.is_empty() {
+            self.push(Value::String(text.to_string()), format);
+        }
+    }
+
+    fn image(&mut self, image: &ImageData) {
+        let mime_type = match image.image_type() {
+            ImageType::Png => "image/png",
+            ImageType::Jpeg => "image/jpeg",
+            ImageType::Gif => "image/gif",
+            ImageType::SVG => "image/svg+xml",
+        };
+        let mut format = Map::new();
+        let alt = if image.alt().is_empty() {
+            image.title()
+        } else {
+            image.alt()
+        };
+        if !alt.is_empty() {
+            format.insert("alt".to_string(), json!(alt));
+        }
+        if let Some(width) = &image.size().width {
+            format.insert("width".to_string(), json!(width));
+        }
+        if let Some(height) = &image.size().height {
+            format.insert("height".to_string(), json!(height));
+        }
+        self.push(
+            json!({
+                "image": format!(
+                    "data:{};base64,{}",
+                    mime_type,
+                    general_purpose::STANDARD.encode(image.bytes())
+                )
+            }),
+            format,
+        );
+    }
+}
+
+fn attributes<const N: usize>(entries: [(&str, Value); N]) -> Map<String, Value> {
+    entries
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::quill::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let delta = r#"{"ops": [
+            {"insert": "Title"},
+            {"insert": "\n", "attributes": {"header": 1}},
+            {"insert": "Read "},
+            {"insert": "the docs", "attributes": {"link": "https://example.com", "bold": true}},
+            {"insert": " now", "attributes": {"italic": true}},
+            {"insert": "\nOne"},
+            {"insert": "\n", "attributes": {"list": "bullet"}},
+            {"insert": "Nested"},
+            {"insert": "\n", "attributes": {"list": "ordered", "indent": 1}},
+            {"insert": "let x = 1;"},
+            {"insert": "\n", "attributes": {"code-block": true}},
+            {"insert": "x += 1;"},
+            {"insert": "\n", "attributes": {"code-block": "rust"}},
+            {"insert": "a"},
+            {"insert": "\n", "attributes": {"table": "row-1"}},
+            {"insert": "b"},
+            {"insert": "\n", "attributes": {"table": "row-1"}},
+            {"insert": {"image": "data:image/gif;base64,R0lGOA=="}, "attributes": {"alt": "Logo", "width": "120"}},
+            {"insert": "\n", "attributes": {"align": "center"}}
+        ]}"#;
+        let document = Transformer::parse(&Bytes::from(delta))?;
+        let elements = document.get_all_elements();
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        assert_eq!(
+            elements[..5],
+            [
+                &Element::Header {
+                    level: 1,
+                    text: "Title".to_string(),
+                    anchor: None,
+                },
+                &Element::Paragraph {
+                    elements: vec![
+                        text("Read "),
+                        Element::Hyperlink {
+                            title: "the docs".to_string(),
+                            url: "https://example.com".to_string(),
+                            alt: String::new(),
+                            size: 8,
+                        },
+                        text(" now"),
+                    ]
+                },
+                &Element::List {
+                    elements: vec![
+                        ListItem {
+                            element: text("One"),
+                            checked: None,
+                        },
+                        ListItem {
+                            element: Element::List {
+                                elements: vec![ListItem {
+                                    element: text("Nested"),
+                                    checked: None,
+                                }],
+                                numbered: true,
+                            },
+                            checked: None,
+                        },
+                    ],
+                    numbered: false,
+                },
+                &text("let x = 1;\nx += 1;"),
+                &Element::Table {
+                    headers: vec![],
+                    rows: vec![TableRow {
+                        cells: vec![TableCell::new(text("a")), TableCell::new(text("b"))]
+                    }],
+                    alignments: vec![],
+                    caption: vec![],
+                },
+            ]
+        );
+        let Element::Image(image) = elements[5] else {
+            panic!("expected an image, got {:?}", elements[5]);
+        };
+        assert_eq!(image.bytes(), &Bytes::from_static(b"GIF8"));
+        assert_eq!(image.image_type(), &ImageType::Gif);
+        assert_eq!(image.alt(), "Logo");
+        assert_eq!(image.align(), &ImageAlignment::Center);
+        assert_eq!(image.size().width.as_deref(), Some("120"));
+        assert_eq!(elements.len(), 6);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let generated = Transformer::generate(&parsed)?;
+        let delta: Value = serde_json::from_slice(&generated)?;
+        // A document delta ends with a newline
+        let last = delta["ops"].as_array().and_then(|ops| ops.last());
+        assert!(last.is_some_and(|op| op["insert"]
+            .as_str()
+            .is_some_and(|text| text.ends_with('\n'))));
+
+        let reparsed = Transformer::parse(&generated)?;
+        let images = |document: &Document| -> Vec<Bytes> {
+            document
+                .get_all_elements()
+                .into_iter()
+                .filter_map(|element| match element {
+                    Element::Image(image) => Some(image.bytes().clone()),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert!(!images(&parsed).is_empty());
+        assert_eq!(images(&reparsed), images(&parsed));
+        Ok(())
+    }
+}
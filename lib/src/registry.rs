@@ -0,0 +1,294 @@
+//! Formats added at runtime, by crates with a [`TransformerTrait`] for a format shiva does not
+//! have or one to use in place of a built-in format
+//!
+//! [`Document::parse`], [`Document::generate`] and the CLI consult [`FormatRegistry::global`]: a
+//! format registered in place of a [`DocumentType`] is used for it, one registered for other
+//! extensions is reached through [`Document::parse_by_extension`] and
+//! [`Document::generate_by_extension`].
+use std::sync::{OnceLock, RwLock};
+
+use bytes::Bytes;
+
+use crate::core::{Document, DocumentType, GeneratorOptions, TransformerTrait};
+
+/// A format added to a [`FormatRegistry`]
+#[derive(Debug, Clone)]
+pub struct Format {
+    pub name: String,
+    /// File extensions without the dot, lowercase
+    pub extensions: Vec<String>,
+    /// The built-in format it is used in place of
+    pub replaces: Option<DocumentType>,
+    parse: fn(&Bytes) -> anyhow::Result<Document>,
+    generate: fn(&Document) -> anyhow::Result<Bytes>,
+}
+
+impl Format {
+    pub fn parse(&self, input: &Bytes) -> anyhow::Result<Document> {
+        (self.parse)(input)
+    }
+
+    pub fn generate(&self, document: &Document) -> anyhow::Result<Bytes> {
+        (self.generate)(document)
+    }
+}
+
+/// Formats by name. When two claim an extension the one registered last is used
+#[derive(Debug, Default)]
+pub struct FormatRegistry {
+    formats: RwLock<Vec<Format>>,
+}
+
+impl FormatRegistry {
+    pub fn new() -> FormatRegistry {
+        FormatRegistry::default()
+    }
+
+    /// The registry of the process, the one documents and the CLI consult
+    pub fn global() -> &'static FormatRegistry {
+        static REGISTRY: OnceLock<FormatRegistry> = OnceLock::new();
+        REGISTRY.get_or_init(FormatRegistry::new)
+    }
+
+    /// Registers `T` as the format `name` of files with these extensions, replacing the format
+    /// registered with that name before
+    pub fn register<T: TransformerTrait>(&self, name: &str, extensions: &[&str]) {
+        self.insert(Format {
+            name: name.to_string(),
+            extensions: extensions.iter().map(|e| e.to_ascii_lowercase()).collect(),
+            replaces: None,
+            parse: T::parse,
+            generate: T::generate,
+        });
+    }
+
+    /// Registers `T` in place of the built-in format, for its extensions as well. The format is
+    /// named after the document type
+    pub fn replace<T: TransformerTrait>(&self, document_type: DocumentType) {
+        let mut extensions: Vec<String> = DocumentType::supported_extensions()
+            .into_iter()
+            .filter(|extension| DocumentType::from_extension(extension) == Some(document_type))
+            .map(str::to_string)
+            .collect();
+        extensions.sort();
+        self.insert(Format {
+            name: document_type.to_string(),
+            extensions,
+            replaces: Some(document_type),
+            parse: T::parse,
+            generate: T::generate,
+        });
+    }
+
+    /// Removes the format `name`, returns whether there was one
+    pub fn unregister(&self, name: &str) -> bool {
+        let mut formats = self
+            .formats
+            .write()
+            .unwrap_or_else(|error| error.into_inner());
+        let count = formats.len();
+        formats.retain(|format| format.name != name);
+        formats.len() != count
+    }
+
+    /// The format of files with the extension, in any case
+    pub fn by_extension(&self, extension: &str) -> Option<Format> {
+        let extension = extension.to_ascii_lowercase();
+        self.find(|format| format.extensions.contains(&extension))
+    }
+
+    /// The format registered in place of the built-in one
+    pub fn by_type(&self, document_type: DocumentType) -> Option<Format> {
+        self.find(|format| format.replaces == Some(document_type))
+    }
+
+    /// Extensions of the registered formats and the built-in ones, sorted
+    pub fn supported_extensions(&self) -> Vec<String> {
+        let formats = self
+            .formats
+            .read()
+            .unwrap_or_else(|error| error.into_inner());
+        let mut extensions: Vec<String> = DocumentType::supported_extensions()
+            .into_iter()
+            .map(str::to_string)
+            .chain(formats.iter().flat_map(|format| format.extensions.clone()))
+            .collect();
+        extensions.sort();
+        extensions.dedup();
+        extensions
+    }
+
+    /// Parses a document of the format of files with the extension, a registered format or a
+    /// built-in one
+    pub fn parse_by_extension(&self, input: &Bytes, extension: &str) -> anyhow::Result<Document> {
+        match self.by_extension(extension) {
+            Some(format) => format.parse(input),
+            None => Document::parse(input, built_in(extension)?),
+        }
+    }
+
+    /// Generates the document in the format of files with the extension, a registered format or
+    /// a built-in one. Registered formats get the document as the options change it, see
+    /// [`GeneratorOptions`]
+    pub fn generate_by_extension(
+        &self,
+        document: &Document,
+        extension: &str,
+        options: &GeneratorOptions,
+    ) -> anyhow::Result<Bytes> {
+        match self.by_extension(extension) {
+            Some(format) => {
+                let prepared = options.prepare(document);
+                format.generate(prepared.as_ref().unwrap_or(document))
+            }
+            None => document.generate_with_options(built_in(extension)?, options),
+        }
+    }
+
+    fn insert(&self, format: Format) {
+        let mut formats = self
+            .formats
+            .write()
+            .unwrap_or_else(|error| error.into_inner());
+        formats.retain(|registered| registered.name != format.name);
+        formats.push(format);
+    }
+
+    fn find(&self, predicate: impl Fn(&Format) -> bool) -> Option<Format> {
+        let formats = self
+            .formats
+            .read()
+            .unwrap_or_else(|error| error.into_inner());
+        formats
+            .iter()
+            .rev()
+            .find(|format| predicate(format))
+            .cloned()
+    }
+}
+
+impl Document {
+    /// Parses a document of the format of files with the extension, a format registered in
+    /// [`FormatRegistry::global`] or a built-in one
+    pub fn parse_by_extension(input: &Bytes, extension: &str) -> anyhow::Result<Document> {
+        FormatRegistry::global().parse_by_extension(input, extension)
+    }
+
+    /// Generates the document in the format of files with the extension, a format registered in
+    /// [`FormatRegistry::global`] or a built-in one
+    pub fn generate_by_extension(
+        &self,
+        extension: &str,
+        options: &GeneratorOptions,
+    ) -> anyhow::Result<Bytes> {
+        FormatRegistry::global().generate_by_extension(self, extension, options)
+    }
+}
+
+fn built_in(extension: &str) -> anyhow::Result<DocumentType> {
+    DocumentType::from_extension(&extension.to_ascii_lowercase())
+        .ok_or_else(|| anyhow::anyhow!("Unsupported format: {}", extension))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{DocumentBuilder, Element, ImageData, ImageDimension, ImageEmbedding};
+
+    /// Lines of text in capitals, one paragraph each
+    struct Shout;
+
+    impl TransformerTrait for Shout {
+        fn parse(document: &Bytes) -> anyhow::Result<Document> {
+            let mut builder = DocumentBuilder::new();
+            for line in std::str::from_utf8(document)?.lines() {
+                builder = builder.paragraph(|p| p.text(line.to_lowercase()));
+            }
+            Ok(builder.build())
+        }
+
+        fn generate(document: &Document) -> anyhow::Result<Bytes> {
+            let lines: Vec<String> = document
+                .get_detail()
+                .iter()
+                .map(|element| element.plain_text().to_uppercase() + "\n")
+                .collect();
+            Ok(Bytes::from(lines.concat()))
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = FormatRegistry::new();
+        assert!(registry.by_extension("shout").is_none());
+
+        registry.register::<Shout>("shout", &["shout", "SHT"]);
+        let format = registry.by_extension("sht").unwrap();
+        assert_eq!(format.name, "shout");
+        assert_eq!(format.extensions, vec!["shout", "sht"]);
+        assert!(registry.by_type(DocumentType::Opml).is_none());
+        assert!(registry.supported_extensions().contains(&"sht".to_string()));
+        assert!(registry.supported_extensions().contains(&"md".to_string()));
+
+        registry.register::<Shout>("shout", &["yell"]);
+        assert!(registry.by_extension("shout").is_none());
+        registry.replace::<Shout>(DocumentType::Opml);
+        let format = registry.by_extension("opml").unwrap();
+        assert_eq!(format.replaces, Some(DocumentType::Opml));
+        assert_eq!(registry.by_type(DocumentType::Opml).unwrap().name, "opml");
+
+        assert!(registry.unregister("shout"));
+        assert!(!registry.unregister("shout"));
+        assert!(registry.by_extension("yell").is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "markdown")]
+    fn test_document() -> anyhow::Result<()> {
+        let registry = FormatRegistry::new();
+        registry.register::<Shout>("shout", &["shout"]);
+        let document = registry.parse_by_extension(&Bytes::from("HELLO\nWORLD\n"), "shout")?;
+        assert_eq!(document.to_plain_text(), "hello\n\nworld");
+        let markdown =
+            registry.generate_by_extension(&document, "MD", &GeneratorOptions::default())?;
+        assert_eq!(markdown, "hello\n\nworld\n");
+        let options = GeneratorOptions {
+            images: ImageEmbedding::Skip,
+            ..GeneratorOptions::default()
+        };
+        let image = ImageData::new(
+            Bytes::from_static(b"\x89PNG"),
+            "Logo".to_string(),
+            "Logo".to_string(),
+            "png".to_string(),
+            "center".to_string(),
+            ImageDimension::default(),
+        );
+        let document = DocumentBuilder::new()
+            .header(1, "Title")
+            .image(image)
+            .build();
+        let shout =
+            registry.generate_by_extension(&document, "shout", &GeneratorOptions::default())?;
+        assert_eq!(shout, "TITLE\nLOGO\n");
+        assert_eq!(
+            registry.generate_by_extension(&document, "shout", &options)?,
+            "TITLE\n"
+        );
+
+        // Used in place of the built-in format for its extensions too
+        registry.replace::<Shout>(DocumentType::Opml);
+        let parsed = registry.parse_by_extension(&Bytes::from("OUTLINE\n"), "opml")?;
+        assert!(matches!(
+            parsed.get_detail()[..],
+            [Element::Paragraph { .. }]
+        ));
+        let generated = registry.generate_by_extension(&parsed, "opml", &options)?;
+        assert_eq!(generated, "OUTLINE\n");
+
+        // The registry of the process is left alone
+        assert!(FormatRegistry::global().by_extension("shout").is_none());
+        assert!(Document::parse_by_extension(&Bytes::new(), "shout").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,1359 @@
+use crate::core::*;
+use crate::parse_util::unwrap_paragraph;
+use bytes::Bytes;
+use std::collections::HashMap;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Parses section titles, bullet and enumerated lists, grid tables, line blocks,
+    /// literal blocks, `image`/`figure` directives and inline or named hyperlinks.
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let source = String::from_utf8(document.to_vec())?;
+        let lines: Vec<String> = source
+            .lines()
+            .map(|line| line.replace('\t', "        ").trim_end().to_string())
+            .collect();
+
+        // Hyperlink targets may be defined anywhere in the document
+        let mut targets = HashMap::new();
+        for line in &lines {
+            if let Some((name, url)) = hyperlink_target(line) {
+                targets.insert(name.to_lowercase(), url.to_string());
+            }
+        }
+
+        let mut parser = Parser {
+            image_loader: &image_loader,
+            section_styles: Vec::new(),
+            targets,
+        };
+        let elements = parser.parse_blocks(&lines)?;
+        Ok(Document::new(elements))
+    }
+
+    /// Images are written through `image_saver` and referenced from `image` directives.
+    /// Page headers and footers are skipped.
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let mut generator = Generator {
+            image_saver,
+            image_num: 0,
+        };
+        let mut rst = String::new();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                generator.block(&mut rst, element)?;
+            }
+        }
+        Ok(Bytes::from(rst))
+    }
+}
+
+/// Underline characters used for header levels 1 to 6
+const SECTION_CHARACTERS: [char; 6] = ['=', '-', '~', '^', '"', '\''];
+
+const ADMONITIONS: [&str; 10] = [
+    "attention",
+    "caution",
+    "danger",
+    "error",
+    "hint",
+    "important",
+    "note",
+    "tip",
+    "warning",
+    "admonition",
+];
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Section adornment: a line of at least three identical punctuation characters
+fn adornment(line: &str) -> Option<char> {
+    let first = line.chars().next()?;
+    (line.len() >= 3 && first.is_ascii_punctuation() && line.chars().all(|c| c == first))
+        .then_some(first)
+}
+
+/// `.. _name: url`
+fn hyperlink_target(line: &str) -> Option<(&str, &str)> {
+    let rest = line.trim_start().strip_prefix(".. _")?;
+    let (name, url) = rest.split_once(": ")?;
+    let name = name.trim_matches('`');
+    Some((name, url.trim()))
+}
+
+/// Returns the marker width and whether the list is enumerated
+fn list_marker(line: &str) -> Option<(usize, bool)> {
+    let marker_end = line.find(' ').unwrap_or(line.len());
+    let marker = &line[..marker_end];
+    if marker_end == line.len() || line[marker_end..].trim().is_empty() {
+        return None;
+    }
+    let width = marker_end + 1;
+    if marker == "*" || marker == "-" || marker == "+" || marker == "\u{2022}" {
+        return Some((width, false));
+    }
+    let enumerator = marker
+        .strip_prefix('(')
+        .and_then(|marker| marker.strip_suffix(')'))
+        .or_else(|| marker.strip_suffix('.'))
+        .or_else(|| marker.strip_suffix(')'))?;
+    let valid = enumerator == "#"
+        || (!enumerator.is_empty() && enumerator.chars().all(|c| c.is_ascii_digit()))
+        || (enumerator.len() == 1 && enumerator.chars().all(|c| c.is_ascii_alphabetic()));
+    valid.then_some((width, true))
+}
+
+fn is_grid_border(line: &str) -> bool {
+    line.starts_with('+')
+        && line.ends_with('+')
+        && line.len() > 2
+        && line.chars().all(|c| matches!(c, '+' | '-' | '='))
+}
+
+/// Removes `width` columns of indentation, blank lines stay blank
+fn dedent(lines: &[String], width: usize) -> Vec<String> {
+    lines
+        .iter()
+        .map(|line| {
+            line.get(width.min(indentation(line))..)
+                .unwrap_or_default()
+                .to_string()
+        })
+        .collect()
+}
+
+struct Parser<'a, F> {
+    image_loader: &'a F,
+    /// Adornment styles in order of first appearance, the index is the header level
+    section_styles: Vec<(char, bool)>,
+    targets: HashMap<String, String>,
+}
+
+impl<'a, F> Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    fn section_level(&mut self, style: (char, bool)) -> u8 {
+        let index = match self.section_styles.iter().position(|known| *known == style) {
+            Some(index) => index,
+            None => {
+                self.section_styles.push(style);
+                self.section_styles.len() - 1
+            }
+        };
+        (index + 1).min(6) as u8
+    }
+
+    /// Lines following `start` indented deeper than the surrounding text, with blank lines
+    fn indented_block(lines: &[String], start: usize) -> usize {
+        let mut end = start;
+        while end < lines.len() && (lines[end].is_empty() || indentation(&lines[end]) > 0) {
+            end += 1;
+        }
+        // Trailing blank lines are not part of the block
+        while end > start && lines[end - 1].is_empty() {
+            end -= 1;
+        }
+        end
+    }
+
+    fn parse_blocks(&mut self, lines: &[String]) -> anyhow::Result<Vec<Element>> {
+        let mut elements = Vec::new();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = lines[index].as_str();
+            if line.is_empty() {
+                index += 1;
+                continue;
+            }
+
+            // Title with overline and underline
+            if let (Some(over), Some(title), Some(under)) = (
+                adornment(line),
+                lines.get(index + 1),
+                lines.get(index + 2).and_then(|line| adornment(line)),
+            ) {
+                if over == under && !title.trim().is_empty() && adornment(title).is_none() {
+                    let level = self.section_level((over, true));
+                    elements.push(Element::Header {
+                        level,
+                        text: strip_markup(title.trim()),
+                        anchor: None,
+                    });
+                    index += 3;
+                    continue;
+                }
+            }
+            // Title with underline only
+            if let Some(under) = lines.get(index + 1).and_then(|line| adornment(line)) {
+                if indentation(line) == 0
+                    && adornment(line).is_none()
+                    && lines[index + 1].chars().count() >= line.chars().count().min(4)
+                {
+                    let level = self.section_level((under, false));
+                    elements.push(Element::Header {
+                        level,
+                        text: strip_markup(line.trim()),
+                        anchor: None,
+                    });
+                    index += 2;
+                    continue;
+                }
+            }
+
+            if indentation(line) > 0 {
+                // Block quote, its content is regular body elements
+                let end = Self::indented_block(lines, index);
+                let width = lines[index..end]
+                    .iter()
+                    .filter(|line| !line.is_empty())
+                    .map(|line| indentation(line))
+                    .min()
+                    .unwrap_or(0);
+                elements.append(&mut self.parse_blocks(&dedent(&lines[index..end], width))?);
+                index = end;
+                continue;
+            }
+
+            if let Some(rest) = line.strip_prefix("..") {
+                if rest.is_empty() || rest.starts_with(' ') {
+                    index = self.parse_explicit_markup(lines, index, &mut elements)?;
+                    continue;
+                }
+            }
+
+            if is_grid_border(line) {
+                let end = lines[index..]
+                    .iter()
+                    .position(|line| !(line.starts_with('+') || line.starts_with('|')))
+                    .map(|end| index + end)
+                    .unwrap_or(lines.len());
+                elements.push(self.parse_grid_table(&lines[index..end])?);
+                index = end;
+                continue;
+            }
+
+            if let Some((_, numbered)) = list_marker(line) {
+                index = self.parse_list(lines, index, numbered, &mut elements)?;
+                continue;
+            }
+
+            if line == "|" || line.starts_with("| ") {
+                // Line block, every line is kept
+                let mut paragraph = Vec::new();
+                while index < lines.len() && (lines[index] == "|" || lines[index].starts_with("| "))
+                {
+                    if !paragraph.is_empty() {
+                        paragraph.push(Element::LineBreak);
+                    }
+                    self.parse_inline(lines[index].get(2..).unwrap_or_default(), &mut paragraph);
+                    index += 1;
+                }
+                elements.push(Element::Paragraph {
+                    elements: paragraph,
+                });
+                continue;
+            }
+
+            // Paragraph
+            let start = index;
+            while index < lines.len() && !lines[index].is_empty() && indentation(&lines[index]) == 0
+            {
+                index += 1;
+            }
+            let mut text = lines[start..index].join(" ");
+            let literal = text.ends_with("::");
+            if literal {
+                // "Paragraph::" keeps one colon, a lone or space separated "::" disappears
+                text.truncate(text.len() - 2);
+                if !text.is_empty() && !text.ends_with(' ') {
+                    text.push(':');
+                }
+                text = text.trim_end().to_string();
+            }
+            if !text.is_empty() {
+                let mut paragraph = Vec::new();
+                self.parse_inline(&text, &mut paragraph);
+                elements.push(Element::Paragraph {
+                    elements: paragraph,
+                });
+            }
+            if literal {
+                while index < lines.len() && lines[index].is_empty() {
+                    index += 1;
+                }
+                let end = Self::indented_block(lines, index);
+                if end > index {
+                    let width = lines[index..end]
+                        .iter()
+                        .filter(|line| !line.is_empty())
+                        .map(|line| indentation(line))
+                        .min()
+                        .unwrap_or(0);
+                    elements.push(Element::Text {
+                        text: dedent(&lines[index..end], width).join("\n"),
+                        font: FontSpec::from(8),
+                    });
+                    index = end;
+                }
+            }
+        }
+        Ok(elements)
+    }
+
+    /// Directives, hyperlink targets, substitution definitions and comments
+    fn parse_explicit_markup(
+        &mut self,
+        lines: &[String],
+        index: usize,
+        elements: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let first = lines[index][2..].trim();
+        let end = Self::indented_block(lines, index + 1);
+        let body = dedent(&lines[index + 1..end], 3);
+
+        if hyperlink_target(&lines[index]).is_some() || first.starts_with('|') {
+            return Ok(end);
+        }
+        let Some((name, argument)) = first.split_once("::") else {
+            // Anything else is a comment
+            let mut text = vec![first.to_string()];
+            text.extend(body.iter().map(|line| line.trim().to_string()));
+            let text = text.join("\n").trim().to_string();
+            elements.push(Element::Comment {
+                text,
+                author: None,
+                date: None,
+                range: None,
+            });
+            return Ok(end);
+        };
+        let name = name.trim();
+        let argument = argument.trim();
+
+        // Directive options come first, separated from the content by a blank line
+        let options_end = body
+            .iter()
+            .position(|line| !line.starts_with(':'))
+            .unwrap_or(body.len());
+        let options: HashMap<&str, &str> = body[..options_end]
+            .iter()
+            .filter_map(|line| {
+                let (key, value) = line[1..].split_once(':')?;
+                Some((key, value.trim()))
+            })
+            .collect();
+        let content = &body[options_end..];
+
+        match name {
+            "image" | "figure" => {
+                let bytes = (self.image_loader)(argument)?;
+                let image = ImageData::new(
+                    bytes,
+                    String::new(),
+                    options.get("alt").unwrap_or(&"").to_string(),
+                    argument.to_string(),
+                    options.get("align").unwrap_or(&"").to_string(),
+                    ImageDimension {
+                        width: options.get("width").map(|width| width.to_string()),
+                        height: options.get("height").map(|height| height.to_string()),
+                    },
+                );
+                // A figure caption is the first paragraph of its content, a legend may follow
+                let mut content = if name == "figure" {
+                    self.parse_blocks(content)?
+                } else {
+                    Vec::new()
+                };
+                match content.first() {
+                    Some(Element::Paragraph { .. }) => {
+                        let Element::Paragraph { elements: caption } = content.remove(0) else {
+                            unreachable!()
+                        };
+                        elements.push(Element::Figure { image, caption });
+                    }
+                    _ => elements.push(Element::Image(image)),
+                }
+                elements.append(&mut content);
+            }
+            "code" | "code-block" | "sourcecode" => {
+                let start = content.iter().position(|line| !line.is_empty());
+                if let Some(start) = start {
+                    elements.push(Element::Text {
+                        text: content[start..].join("\n"),
+                        font: FontSpec::from(8),
+                    });
+                }
+            }
+            name if ADMONITIONS.contains(&name) => {
+                // Specific admonitions may start their content on the directive line
+                let (label, mut content) = if name == "admonition" {
+                    (argument.to_string(), self.parse_blocks(content)?)
+                } else {
+                    let mut lines = vec![argument.to_string()];
+                    lines.extend_from_slice(content);
+                    (name.to_uppercase(), self.parse_blocks(&lines)?)
+                };
+                match content.first_mut() {
+                    Some(Element::Paragraph { elements }) => {
+                        elements.insert(
+                            0,
+                            Element::Text {
+                                text: format!("{}: ", label),
+                                font: FontSpec::from(8),
+                            },
+                        );
+                        merge_text(elements);
+                    }
+                    _ => content.insert(
+                        0,
+                        Element::Paragraph {
+                            elements: vec![Element::Text {
+                                text: format!("{}:", label),
+                                font: FontSpec::from(8),
+                            }],
+                        },
+                    ),
+                }
+                elements.append(&mut content);
+            }
+            // Without a depth all section levels are listed
+            "contents" => elements.push(Element::TableOfContents {
+                max_level: options
+                    .get("depth")
+                    .and_then(|depth| depth.parse::<u8>().ok())
+                    .unwrap_or(6)
+                    .clamp(1, 6),
+            }),
+            // Content of other directives (toctree, raw, include...) is not document text
+            _ => {}
+        }
+        Ok(end)
+    }
+
+    fn parse_list(
+        &mut self,
+        lines: &[String],
+        mut index: usize,
+        numbered: bool,
+        elements: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let mut items = Vec::new();
+        while index < lines.len() {
+            let line = &lines[index];
+            let Some((width, item_numbered)) = list_marker(line) else {
+                break;
+            };
+            if indentation(line) > 0 || item_numbered != numbered {
+                break;
+            }
+            // The item body is the first line after the marker and everything indented under it
+            let mut end = index + 1;
+            while end < lines.len() && (lines[end].is_empty() || indentation(&lines[end]) >= width)
+            {
+                end += 1;
+            }
+            while end > index + 1 && lines[end - 1].is_empty() {
+                end -= 1;
+            }
+            let mut body = vec![line[width..].to_string()];
+            body.extend(dedent(&lines[index + 1..end], width));
+            let mut content = self.parse_blocks(&body)?;
+            // Nested lists follow their parent item in the model
+            let nested: Vec<Element> = content
+                .iter()
+                .position(|element| matches!(element, Element::List { .. }))
+                .map(|position| content.split_off(position))
+                .unwrap_or_default();
+            match content.len() {
+                0 => {}
+                1 => items.push(ListItem {
+                    element: unwrap_paragraph(content.remove(0)),
+                    checked: None,
+                }),
+                _ => {
+                    let mut paragraph = Vec::new();
+                    for element in content {
+                        match element {
+                            Element::Paragraph { mut elements } => paragraph.append(&mut elements),
+                            element => paragraph.push(element),
+                        }
+                    }
+                    items.push(ListItem {
+                        element: Element::Paragraph {
+                            elements: paragraph,
+                        },
+                        checked: None,
+                    });
+                }
+            }
+            items.extend(nested.into_iter().map(|element| ListItem {
+                element,
+                checked: None,
+            }));
+
+            index = end;
+            while index < lines.len() && lines[index].is_empty() {
+                index += 1;
+            }
+        }
+        elements.push(Element::List {
+            elements: items,
+            numbered,
+        });
+        Ok(index)
+    }
+
+    fn parse_grid_table(&mut self, lines: &[String]) -> anyhow::Result<Element> {
+        let border = &lines[0];
+        let columns: Vec<usize> = border
+            .char_indices()
+            .filter(|(_, c)| *c == '+')
+            .map(|(index, _)| index)
+            .collect();
+
+        let mut rows: Vec<Vec<Element>> = Vec::new();
+        let mut header_rows = 0;
+        let mut row_lines: Vec<&str> = Vec::new();
+        for line in &lines[1..] {
+            if !is_grid_border(line) {
+                row_lines.push(line);
+                continue;
+            }
+            let mut cells = Vec::new();
+            for bounds in columns.windows(2) {
+                let text = row_lines
+                    .iter()
+                    .map(|line| {
+                        line.get(bounds[0] + 1..bounds[1])
+                            .unwrap_or_default()
+                            .trim()
+                    })
+                    .filter(|text| !text.is_empty())
+                    .collect::<Vec<&str>>()
+                    .join(" ");
+                let mut inline = Vec::new();
+                self.parse_inline(&text, &mut inline);
+                cells.push(match inline.len() {
+                    0 => Element::Text {
+                        text: String::new(),
+                        font: FontSpec::from(8),
+                    },
+                    1 => inline.remove(0),
+                    _ => Element::Paragraph { elements: inline },
+                });
+            }
+            row_lines.clear();
+            rows.push(cells);
+            if line.contains('=') {
+                header_rows = rows.len();
+            }
+        }
+
+        let headers = rows
+            .drain(..header_rows)
+            .flatten()
+            .map(|element| TableHeader {
+                element,
+                width: 30.0,
+            })
+            .collect();
+        let rows = rows
+            .into_iter()
+            .map(|cells| TableRow {
+                cells: cells.into_iter().map(TableCell::new).collect(),
+            })
+            .collect();
+        Ok(Element::Table {
+            headers,
+            rows,
+            alignments: vec![],
+            caption: vec![],
+        })
+    }
+
+    /// Splits text into plain text and hyperlinks, inline markup is dropped
+    fn parse_inline(&self, text: &str, elements: &mut Vec<Element>) {
+        let mut rest = text;
+        while !rest.is_empty() {
+            let backtick = rest.find('`');
+            let url = ["https://", "http://"]
+                .iter()
+                .filter_map(|scheme| rest.find(scheme))
+                .filter(|index| *index == 0 || !rest[..*index].ends_with(char::is_alphanumeric))
+                .min();
+
+            match (backtick, url) {
+                (Some(start), url) if url.is_none_or(|url| start < url) => {
+                    push_text(elements, &strip_markup(&rest[..start]));
+                    rest = self.parse_interpreted(&rest[start..], elements);
+                }
+                (_, Some(start)) => {
+                    push_text(elements, &strip_markup(&rest[..start]));
+                    let end = rest[start..]
+                        .find(|c: char| c.is_whitespace() || c == '>' || c == '`')
+                        .map(|end| start + end)
+                        .unwrap_or(rest.len());
+                    let url = rest[start..end].trim_end_matches(['.', ',', ';', ':', ')']);
+                    elements.push(Element::Hyperlink {
+                        title: url.to_string(),
+                        url: url.to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    });
+                    rest = &rest[start + url.len()..];
+                }
+                _ => {
+                    push_text(elements, &strip_markup(rest));
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Handles text starting with a backtick: links, references, literals and roles
+    fn parse_interpreted<'t>(&self, text: &'t str, elements: &mut Vec<Element>) -> &'t str {
+        if let Some(literal) = text.strip_prefix("``") {
+            return match literal.find("``") {
+                Some(end) => {
+                    push_text(elements, &literal[..end]);
+                    &literal[end + 2..]
+                }
+                None => {
+                    push_text(elements, text);
+                    ""
+                }
+            };
+        }
+        let Some(end) = text[1..].find('`').map(|end| end + 1) else {
+            push_text(elements, &strip_markup(text));
+            return "";
+        };
+        let content = &text[1..end];
+        let mut rest = &text[end + 1..];
+        let reference = rest.starts_with('_');
+        rest = rest.trim_start_matches('_');
+        if !reference {
+            push_text(elements, content);
+            return rest;
+        }
+
+        // `title <url>`_ or `name`_ resolved through the hyperlink targets
+        let (title, url) = match content.rfind(" <").filter(|_| content.ends_with('>')) {
+            Some(split) => (
+                content[..split].trim(),
+                Some(content[split + 2..content.len() - 1].to_string()),
+            ),
+            None => (content, self.targets.get(&content.to_lowercase()).cloned()),
+        };
+        match url {
+            Some(url) => elements.push(Element::Hyperlink {
+                title: title.to_string(),
+                url,
+                alt: String::new(),
+                size: 8,
+            }),
+            None => push_text(elements, title),
+        }
+        rest
+    }
+}
+
+/// Drops emphasis markers, role prefixes and backslash escapes
+fn strip_markup(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let chars: Vec<char> = text.chars().collect();
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        if c == '\\' {
+            if let Some(next) = chars.get(index + 1) {
+                result.push(*next);
+            }
+            index += 2;
+            continue;
+        }
+        if c == '*' {
+            // `*` and `**` runs are markup when they open or close a word
+            let run = chars[index..].iter().take_while(|c| **c == '*').count();
+            let before = index.checked_sub(1).map(|index| chars[index]);
+            let after = chars.get(index + run).copied();
+            let open = before.is_none_or(|c| c.is_whitespace() || "([{'\"".contains(c))
+                && after.is_some_and(|c| !c.is_whitespace());
+            let close = before.is_some_and(|c| !c.is_whitespace())
+                && after.is_none_or(|c| c.is_whitespace() || c.is_ascii_punctuation());
+            if run <= 2 && (open || close) {
+                index += run;
+                continue;
+            }
+        }
+        if c == ':' {
+            // :role: prefix of interpreted text
+            let rest: String = chars[index + 1..].iter().collect();
+            if let Some(end) = rest.find(':') {
+                let role = &rest[..end];
+                if !role.is_empty()
+                    && role.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                    && rest[end + 1..].starts_with('`')
+                {
+                    index += end + 2;
+                    continue;
+                }
+            }
+        }
+        result.push(c);
+        index += 1;
+    }
+    result
+}
+
+fn push_text(elements: &mut Vec<Element>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(Element::Text { text: last, .. }) = elements.last_mut() {
+        last.push_str(text);
+    } else {
+        elements.push(Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        });
+    }
+}
+
+fn merge_text(elements: &mut Vec<Element>) {
+    let mut merged: Vec<Element> = Vec::with_capacity(elements.len());
+    for element in elements.drain(..) {
+        match element {
+            Element::Text { text, .. } => push_text(&mut merged, &text),
+            element => merged.push(element),
+        }
+    }
+    *elements = merged;
+}
+
+
+/// Escapes characters that would start inline markup
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '`' | '|') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+struct Generator<F> {
+    image_saver: F,
+    image_num: usize,
+}
+
+impl<F> Generator<F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn block(&mut self, rst: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Header { level, text, .. } => {
+                let character = SECTION_CHARACTERS[(*level).clamp(1, 6) as usize - 1];
+                let underline = character.to_string().repeat(text.chars().count().max(3));
+                rst.push_str(&format!("{}\n{}\n\n", text, underline));
+            }
+            Element::List { elements, numbered } => {
+                self.list(rst, elements, *numbered, 0)?;
+            }
+            Element::Chart { .. } => self.block(rst, &element.chart_fallback())?,
+            Element::Table { headers, rows, .. } => self.table(rst, headers, rows)?,
+            // The definitions are indented below their term
+            Element::DefinitionList { items } => {
+                let mut images = Vec::new();
+                for item in items {
+                    let term: Vec<String> = item
+                        .term
+                        .iter()
+                        .map(|child| self.inline(child, &mut images))
+                        .collect();
+                    rst.push_str(term.concat().replace('\n', " ").trim());
+                    rst.push('\n');
+                    for (index, definition) in item.definitions.iter().enumerate() {
+                        if index > 0 {
+                            rst.push('\n');
+                        }
+                        let text = self.inline(definition, &mut images).replace('\n', " ");
+                        rst.push_str(&format!("   {}\n", text.trim()));
+                    }
+                }
+                rst.push('\n');
+                for image in images {
+                    self.image(rst, image, 0)?;
+                }
+            }
+            Element::Image(image) => self.image(rst, image, 0)?,
+            Element::Figure { image, caption } => self.figure(rst, image, caption)?,
+            Element::Comment { text, .. } => {
+                rst.push_str("..\n");
+                for line in text.lines() {
+                    rst.push_str(&format!("   {}\n", line));
+                }
+                rst.push('\n');
+            }
+            // An indented block is a block quote
+            Element::Blockquote { elements, .. } => {
+                let mut quote = String::new();
+                for child in elements {
+                    match child {
+                        // Sections cannot be nested in a block quote
+                        Element::Header { text, .. } => {
+                            quote.push_str(&format!("**{}**\n\n", escape(text)))
+                        }
+                        child => self.block(&mut quote, child)?,
+                    }
+                }
+                for line in quote.trim_end().lines() {
+                    if line.is_empty() {
+                        rst.push('\n');
+                    } else {
+                        rst.push_str(&format!("   {}\n", line));
+                    }
+                }
+                rst.push('\n');
+            }
+            // The underlines of the titles give the nesting
+            Element::Section { children, .. } => {
+                for child in children {
+                    self.block(rst, child)?;
+                }
+            }
+            Element::CodeBlock { language, code } => {
+                match language {
+                    Some(language) => rst.push_str(&format!(".. code-block:: {}\n\n", language)),
+                    None => rst.push_str("::\n\n"),
+                }
+                for line in code.lines() {
+                    if line.is_empty() {
+                        rst.push('\n');
+                    } else {
+                        rst.push_str(&format!("   {}\n", line));
+                    }
+                }
+                rst.push('\n');
+            }
+            // A transition, which needs blank lines around it
+            Element::HorizontalRule => rst.push_str("----\n\n"),
+            // reStructuredText has no page breaks, only writer specific raw directives
+            Element::PageBreak => {}
+            Element::TableOfContents { max_level } => {
+                rst.push_str(&format!(".. contents::\n   :depth: {}\n\n", max_level))
+            }
+            Element::Math { tex, display: true } => {
+                rst.push_str(".. math::\n\n");
+                for line in tex.lines() {
+                    rst.push_str(&format!("   {}\n", line));
+                }
+                rst.push('\n');
+            }
+            element => {
+                let mut images = Vec::new();
+                let text = self.inline(element, &mut images);
+                if text.contains('\n') {
+                    // Line breaks only exist in line blocks
+                    for line in text.lines() {
+                        rst.push_str(&format!("| {}\n", line));
+                    }
+                    rst.push('\n');
+                } else if !text.trim().is_empty() {
+                    rst.push_str(text.trim());
+                    rst.push_str("\n\n");
+                }
+                for image in images {
+                    self.image(rst, image, 0)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn list(
+        &mut self,
+        rst: &mut String,
+        items: &[ListItem],
+        numbered: bool,
+        indent: usize,
+    ) -> anyhow::Result<()> {
+        let marker = if numbered { "#." } else { "*" };
+        let padding = " ".repeat(indent);
+        for item in items {
+            match &item.element {
+                // Nested lists are indented to the parent item text and set off by blank lines
+                Element::List { elements, numbered } => {
+                    self.list(rst, elements, *numbered, indent + marker.len() + 1)?
+                }
+                element => {
+                    let mut images = Vec::new();
+                    let text = self.inline(element, &mut images).replace('\n', " ");
+                    rst.push_str(&format!("{}{} {}\n\n", padding, marker, text.trim()));
+                    for image in images {
+                        self.image(rst, image, indent + marker.len() + 1)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn table(
+        &mut self,
+        rst: &mut String,
+        headers: &[TableHeader],
+        rows: &[TableRow],
+    ) -> anyhow::Result<()> {
+        let mut images = Vec::new();
+        let header: Vec<String> = headers
+            .iter()
+            .map(|header| self.inline(&header.element, &mut images).replace('\n', " "))
+            .collect();
+        let body: Vec<Vec<String>> = rows
+            .iter()
+            .map(|row| {
+                row.cells
+                    .iter()
+                    .map(|cell| {
+                        let texts: Vec<String> = cell
+                            .elements
+                            .iter()
+                            .map(|element| self.inline(element, &mut images))
+                            .collect();
+                        texts.join(" ").replace('\n', " ")
+                    })
+                    .collect()
+            })
+            .collect();
+        let columns = body
+            .iter()
+            .map(Vec::len)
+            .chain(std::iter::once(header.len()))
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let mut widths = vec![1; columns];
+        for row in body.iter().chain(std::iter::once(&header)) {
+            for (column, cell) in row.iter().enumerate() {
+                widths[column] = widths[column].max(cell.chars().count());
+            }
+        }
+
+        let border = |fill: char| {
+            let mut line = String::from("+");
+            for width in &widths {
+                line.push_str(&fill.to_string().repeat(width + 2));
+                line.push('+');
+            }
+            line.push('\n');
+            line
+        };
+        let row_line = |cells: &[String]| {
+            let mut line = String::from("|");
+            for (column, width) in widths.iter().enumerate() {
+                let cell = cells.get(column).map(String::as_str).unwrap_or_default();
+                let padding = width - cell.chars().count();
+                line.push_str(&format!(" {}{} |", cell, " ".repeat(padding)));
+            }
+            line.push('\n');
+            line
+        };
+
+        rst.push_str(&border('-'));
+        if !headers.is_empty() {
+            rst.push_str(&row_line(&header));
+            rst.push_str(&border('='));
+        }
+        for row in &body {
+            rst.push_str(&row_line(row));
+            rst.push_str(&border('-'));
+        }
+        rst.push('\n');
+        for image in images {
+            self.image(rst, image, 0)?;
+        }
+        Ok(())
+    }
+
+    fn image(&mut self, rst: &mut String, image: &ImageData, indent: usize) -> anyhow::Result<()> {
+        self.directive(rst, "image", image, indent)
+    }
+
+    /// The caption is the paragraph indented under the `figure` options
+    fn figure(
+        &mut self,
+        rst: &mut String,
+        image: &ImageData,
+        caption: &[Element],
+    ) -> anyhow::Result<()> {
+        let mut images = Vec::new();
+        let text: String = caption
+            .iter()
+            .map(|child| self.inline(child, &mut images))
+            .collect();
+        let text = text.replace('\n', " ");
+        if image.bytes().is_empty() || text.trim().is_empty() {
+            self.image(rst, image, 0)?;
+            if !text.trim().is_empty() {
+                rst.push_str(&format!("{}\n\n", text.trim()));
+            }
+        } else {
+            self.directive(rst, "figure", image, 0)?;
+            rst.push_str(&format!("   {}\n\n", text.trim()));
+        }
+        for image in images {
+            self.image(rst, image, 0)?;
+        }
+        Ok(())
+    }
+
+    fn directive(
+        &mut self,
+        rst: &mut String,
+        name: &str,
+        image: &ImageData,
+        indent: usize,
+    ) -> anyhow::Result<()> {
+        if image.bytes().is_empty() {
+            return Ok(());
+        }
+        let target = format!(
+            "image{}{}",
+            self.image_num,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &target)?;
+        self.image_num += 1;
+
+        let padding = " ".repeat(indent);
+        rst.push_str(&format!("{}.. {}:: {}\n", padding, name, target));
+        if !image.alt().is_empty() {
+            rst.push_str(&format!("{}   :alt: {}\n", padding, image.alt()));
+        }
+        if let Some(width) = &image.size().width {
+            rst.push_str(&format!("{}   :width: {}\n", padding, width));
+        }
+        if let Some(height) = &image.size().height {
+            rst.push_str(&format!("{}   :height: {}\n", padding, height));
+        }
+        rst.push('\n');
+        Ok(())
+    }
+
+    /// Inline text of an element, images are collected to be emitted as directives
+    fn inline<'e>(&self, element: &'e Element, images: &mut Vec<&'e ImageData>) -> String {
+        match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => escape(text),
+            Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => elements
+                .iter()
+                .map(|child| self.inline(child, images))
+                .collect::<Vec<String>>()
+                .join(" "),
+            Element::Paragraph { elements } => {
+                let mut text = String::new();
+                for child in elements {
+                    let child_text = self.inline(child, images);
+                    let separate = !text.is_empty()
+                        && !text.ends_with(char::is_whitespace)
+                        && !child_text.starts_with(char::is_whitespace)
+                        && matches!(
+                            child,
+                            Element::Text { .. }
+                                | Element::Hyperlink { .. }
+                                | Element::FootnoteReference { .. }
+                        );
+                    if separate {
+                        text.push(' ');
+                    }
+                    text.push_str(&child_text);
+                }
+                text
+            }
+            // Inline markup does not nest and has no underline or strikethrough
+            Element::Styled { style, elements } => {
+                let text: String = elements
+                    .iter()
+                    .map(|child| self.inline(child, images))
+                    .collect();
+                if text.trim().is_empty() {
+                    text
+                } else if style.bold {
+                    format!("**{}**", text)
+                } else if style.italic {
+                    format!("*{}*", text)
+                } else {
+                    text
+                }
+            }
+            Element::Hyperlink { title, url, .. } => {
+                if title.is_empty() || title == url {
+                    url.clone()
+                } else {
+                    format!("`{} <{}>`__", title.replace(['<', '`'], ""), url)
+                }
+            }
+            Element::Image(image) => {
+                images.push(image);
+                String::new()
+            }
+            Element::Figure { image, caption } => {
+                images.push(image);
+                caption
+                    .iter()
+                    .map(|child| self.inline(child, images))
+                    .collect()
+            }
+            // The poster is collected from the element, the fallback only lives for the call
+            Element::Media { poster, .. } => {
+                let text = self.inline(&element.media_fallback(), &mut Vec::new());
+                images.extend(poster);
+                text
+            }
+            Element::Ruby { .. } => self.inline(&element.ruby_fallback(), &mut Vec::new()),
+            Element::LineBreak | Element::HorizontalRule => "\n".to_string(),
+            Element::PageBreak | Element::TableOfContents { .. } | Element::Custom { .. } => {
+                String::new()
+            }
+            Element::List { elements, .. } => elements
+                .iter()
+                .map(|item| self.inline(&item.element, images))
+                .collect::<Vec<String>>()
+                .join(" "),
+            Element::DefinitionList { items } => items
+                .iter()
+                .flat_map(|item| item.term.iter().chain(&item.definitions))
+                .map(|child| self.inline(child, images))
+                .collect::<Vec<String>>()
+                .join(" "),
+            Element::CodeBlock { code, .. } => format!("``{}``", code),
+            Element::Math { tex, .. } => format!(":math:`{}`", tex),
+            Element::FootnoteReference { id } => format!("[{}]_", footnote_label(id)),
+            Element::Footnote { id, content } => {
+                let content: Vec<String> = content
+                    .iter()
+                    .map(|child| self.inline(child, images))
+                    .collect();
+                format!(".. [{}] {}", footnote_label(id), content.join(" "))
+            }
+            Element::Table { .. } | Element::Chart { .. } | Element::Comment { .. } => {
+                String::new()
+            }
+        }
+    }
+}
+
+/// Numbers are manually numbered footnotes, other ids become auto-numbered labels
+fn footnote_label(id: &str) -> String {
+    if !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) {
+        id.to_string()
+    } else {
+        format!("#{}", id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rst::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let source = r#"
+=====
+Guide
+=====
+
+Setup
+-----
+
+Install the **cli** first, see `the docs <https://example.com/docs>`_
+or the `FAQ`_.
+
+.. _FAQ: https://example.com/faq
+
+* One
+
+  * Nested
+
+* Two
+
+#. First
+#. Second
+
++------+-------+
+| Name | Value |
++======+=======+
+| a    | 1     |
++------+-------+
+| b    | 2     |
++------+-------+
+
+.. image:: logo.png
+   :alt: Logo
+   :width: 200px
+
+.. note:: Requires Rust.
+
+Example::
+
+    let x = 1;
+"#;
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |_| {
+            Ok(Bytes::from_static(b"\x89PNG"))
+        })?;
+        let elements = document.get_all_elements();
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let link = |title: &str, url: &str| Element::Hyperlink {
+            title: title.to_string(),
+            url: url.to_string(),
+            alt: String::new(),
+            size: 8,
+        };
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 1,
+                text: "Guide".to_string(),
+                anchor: None,
+            }
+        );
+        assert_eq!(
+            elements[1],
+            &Element::Header {
+                level: 2,
+                text: "Setup".to_string(),
+                anchor: None,
+            }
+        );
+        assert_eq!(
+            elements[2],
+            &Element::Paragraph {
+                elements: vec![
+                    text("Install the cli first, see "),
+                    link("the docs", "https://example.com/docs"),
+                    text(" or the "),
+                    link("FAQ", "https://example.com/faq"),
+                    text("."),
+                ]
+            }
+        );
+        assert_eq!(
+            elements[3],
+            &Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("One"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: Element::List {
+                            elements: vec![ListItem {
+                                element: text("Nested"),
+                                checked: None,
+                            }],
+                            numbered: false,
+                        },
+                        checked: None,
+                    },
+                    ListItem {
+                        element: text("Two"),
+                        checked: None,
+                    },
+                ],
+                numbered: false,
+            }
+        );
+        assert!(matches!(
+            elements[4],
+            Element::List { numbered: true, elements } if elements.len() == 2
+        ));
+        let Element::Table { headers, rows, .. } = elements[5] else {
+            panic!("expected a table, got {:?}", elements[5]);
+        };
+        assert_eq!(headers[1].element, text("Value"));
+        assert_eq!(rows[1].cells[0].elements, vec![text("b")]);
+        let Element::Image(image) = elements[6] else {
+            panic!("expected an image, got {:?}", elements[6]);
+        };
+        assert_eq!(image.alt(), "Logo");
+        assert_eq!(
+            elements[7],
+            &Element::Paragraph {
+                elements: vec![text("NOTE: Requires Rust.")]
+            }
+        );
+        assert_eq!(
+            elements[8],
+            &Element::Paragraph {
+                elements: vec![text("Example:")]
+            }
+        );
+        assert_eq!(elements[9], &text("let x = 1;"));
+        assert_eq!(elements.len(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let images = std::cell::RefCell::new(HashMap::new());
+        let generated = Transformer::generate_with_saver(&parsed, |bytes, name| {
+            images.borrow_mut().insert(name.to_string(), bytes.clone());
+            Ok(())
+        })?;
+        let reparsed = Transformer::parse_with_loader(&generated, |name| {
+            Ok(images.borrow().get(name).cloned().unwrap_or_default())
+        })?;
+        let original = parsed.get_all_elements();
+        let elements = reparsed.get_all_elements();
+        assert_eq!(original.len(), elements.len());
+        for (original, element) in original.iter().zip(elements.iter()) {
+            assert_eq!(
+                std::mem::discriminant(*original),
+                std::mem::discriminant(*element)
+            );
+        }
+        let Element::Table { headers, rows, .. } = elements[elements.len() - 3] else {
+            panic!("expected a table");
+        };
+        assert_eq!(headers.len(), 8);
+        assert_eq!(rows.len(), 3);
+        Ok(())
+    }
+}
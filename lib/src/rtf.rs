@@ -1,4 +1,4 @@
-use crate::core::{Document, Element, TableHeader, TableRow, TransformerTrait};
+use crate::core::{Document, Element, FontSpec, TableHeader, TableRow, TransformerTrait};
 use bytes::Bytes;
 use image::io::Reader as ImageReader;
 use image::GenericImageView;
@@ -64,7 +64,7 @@ fn detect_element_in_list(
     depth: usize,
 ) {
     match element {
-        Element::Text { text, size } => {
+        Element::Text { text, font } => {
             let indent = " ".repeat(depth * 4); // 4 пробела для каждого уровня вложенности
             let modified_text = if numbered {
                 let numbering = parent_indices
@@ -76,11 +76,15 @@ fn detect_element_in_list(
             } else {
                 format!("{}- {}", indent, text)
             };
-            rtf_content.push_str(&format!("{{\\fs{} {}}} ", *size as i32 * 2, modified_text));
+            rtf_content.push_str(&format!(
+                "{{\\fs{} {}}} ",
+                (font.size_pt * 2.0).round() as i32,
+                modified_text
+            ));
             rtf_content.push_str("\\par ");
         }
 
-        Element::Header { level, text } => {
+        Element::Header { level, text, .. } => {
             let header_size = 30 + (level);
             let indent = " ".repeat(depth * 4); // 4 пробела для каждого уровня вложенности
             let modified_text = if numbered {
@@ -189,6 +193,7 @@ impl TransformerTrait for Transformer {
                 document.add_element(Element::Header {
                     level: level,
                     text: styleblock.text.to_owned(),
+                    anchor: None,
                 });
                 level += 1
             } else {
@@ -196,7 +201,7 @@ impl TransformerTrait for Transformer {
                     document.add_element(Element::Paragraph {
                         elements: vec![Element::Text {
                             text: styleblock.text.to_owned(),
-                            size: styleblock.painter.font_size as u8,
+                            font: FontSpec::from(styleblock.painter.font_size as u8),
                         }],
                     })
                 }
@@ -206,13 +211,14 @@ impl TransformerTrait for Transformer {
     }
 
     fn generate(document: &Document) -> anyhow::Result<bytes::Bytes> {
+        let document = &document.flatten_sections();
         let mut rtf_content = String::new();
         let mut parent_indices = Vec::new();
 
         rtf_content.push_str("{\\rtf1\\ansi\\deff0"); //the standard title of an RTF document, which indicates that it is an RTF document using ANSI characters and the default font
         for element in &document.get_all_elements() {
             match element {
-                Element::Header { level, text } => {
+                Element::Header { level, text, .. } => {
                     let header_size = 30 + (level);
 
                     //formatting the string RTF
@@ -220,14 +226,22 @@ impl TransformerTrait for Transformer {
                         .push_str(&format!("{{\\fs{}\\b {} \\b0}}\\par ", header_size, text));
                 }
 
-                Element::Text { text, size } => {
-                    rtf_content.push_str(&format!("{{\\fs{} {}}} ", *size as i32 * 2, text));
+                Element::Text { text, font } => {
+                    rtf_content.push_str(&format!(
+                        "{{\\fs{} {}}} ",
+                        (font.size_pt * 2.0).round() as i32,
+                        text
+                    ));
                 }
 
                 Element::Paragraph { elements } => {
                     for elem in elements {
-                        if let Element::Text { text, size } = elem {
-                            rtf_content.push_str(&format!("{{\\fs{} {}}}", *size as i32 * 2, text));
+                        if let Element::Text { text, font } = elem {
+                            rtf_content.push_str(&format!(
+                                "{{\\fs{} {}}}",
+                                (font.size_pt * 2.0).round() as i32,
+                                text
+                            ));
                         }
                     }
                     rtf_content.push_str("\\par ");
@@ -292,7 +306,7 @@ impl TransformerTrait for Transformer {
                     rtf_content.push_str("\\par ");
                 }
 
-                Element::Table { headers, rows } => {
+                Element::Table { headers, rows, .. } => {
                     let column_widths = calculate_column_widths(headers, rows);
                     let mut current_x = 0;
 
@@ -305,10 +319,10 @@ impl TransformerTrait for Transformer {
                     rtf_content.push_str("\\intbl");
 
                     for header in headers {
-                        if let Element::Text { text, size } = &header.element {
+                        if let Element::Text { text, font } = &header.element {
                             rtf_content.push_str(&format!(
                                 "{{\\fs{} {}}}\\cell",
-                                *size as i32 * 2,
+                                (font.size_pt * 2.0).round() as i32,
                                 text
                             ));
                         }
@@ -317,10 +331,10 @@ impl TransformerTrait for Transformer {
 
                     for row in rows {
                         for cell in &row.cells {
-                            if let Element::Text { text, size } = &cell.element {
+                            if let Element::Text { text, font } = &cell.element() {
                                 rtf_content.push_str(&format!(
                                     "{{\\fs{} {}}}\\cell",
-                                    *size as i32 * 2,
+                                    (font.size_pt * 2.0).round() as i32,
                                     text
                                 ));
                             }
@@ -354,7 +368,7 @@ fn calculate_column_widths(headers: &Vec<TableHeader>, rows: &Vec<TableRow>) ->
 
     for row in rows {
         for (i, cell) in row.cells.iter().enumerate() {
-            if let Element::Text { text, .. } = &cell.element {
+            if let Element::Text { text, .. } = &cell.element() {
                 column_content_lengths[i] = text.len().max(column_content_lengths[i]);
             }
         }
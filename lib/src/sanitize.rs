@@ -0,0 +1,273 @@
+//! Makes documents from untrusted sources safe to convert, as those uploaded to a web service
+//!
+//! [`Document::sanitize`] turns links with scripting urls into their text, drops media on other
+//! hosts, comments and custom blocks and flattens content nested too deep. HTML carries more than
+//! a document keeps, [`sanitize_html`] strips scripts, frames, embedded objects and event
+//! handlers from it before parsing.
+use crate::core::{Document, Element, ElementVisitorMut, FontSpec, MediaSource};
+
+/// What [`Document::sanitize`] and [`sanitize_html`] keep
+#[derive(Debug, Clone)]
+pub struct SanitizePolicy {
+    /// Schemes links may use, lowercase. Relative urls and anchors are always kept
+    pub url_schemes: Vec<String>,
+    /// Keeps images and media loaded from other hosts, without it they are dropped and media
+    /// falls back to its poster
+    pub external_resources: bool,
+    /// Elements nested deeper than this are replaced by their plain text
+    pub max_depth: usize,
+    pub keep_comments: bool,
+    /// Keeps [`Element::Custom`] blocks, whose data is up to the application
+    pub keep_custom: bool,
+}
+
+impl Default for SanitizePolicy {
+    fn default() -> SanitizePolicy {
+        SanitizePolicy {
+            url_schemes: ["http", "https", "mailto", "tel"]
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            external_resources: false,
+            max_depth: 32,
+            keep_comments: false,
+            keep_custom: false,
+        }
+    }
+}
+
+impl SanitizePolicy {
+    /// Whether a link may point at `url`
+    pub fn allows_url(&self, url: &str) -> bool {
+        match scheme(url) {
+            Some(scheme) => self.url_schemes.contains(&scheme),
+            None => true,
+        }
+    }
+
+    /// Whether an image or media may be loaded from `url`, content carried in `data:` urls is
+    /// always allowed
+    pub fn allows_resource(&self, url: &str) -> bool {
+        match scheme(url).as_deref() {
+            Some("data") => true,
+            Some(_) => self.external_resources && self.allows_url(url),
+            None => self.external_resources || !url.trim_start().starts_with("//"),
+        }
+    }
+}
+
+impl Document {
+    /// Removes from the document what `policy` does not keep, see [`SanitizePolicy`]. Elements
+    /// left empty are removed with what they held.
+    pub fn sanitize(&mut self, policy: &SanitizePolicy) {
+        struct Sanitize<'a> {
+            policy: &'a SanitizePolicy,
+            depth: usize,
+            removed: bool,
+        }
+        impl ElementVisitorMut for Sanitize<'_> {
+            fn visit_element(&mut self, element: &mut Element) {
+                if self.depth >= self.policy.max_depth {
+                    *element = text(element.plain_text());
+                }
+                self.depth += 1;
+                let replacement = match element {
+                    Element::Hyperlink { title, url, .. } if !self.policy.allows_url(url) => {
+                        Some(text(title.clone()))
+                    }
+                    Element::Media {
+                        source: MediaSource::Url(url),
+                        poster,
+                        ..
+                    } if !self.policy.allows_resource(url) => {
+                        Some(poster.take().map(Element::Image).unwrap_or_else(empty))
+                    }
+                    Element::Comment { .. } if !self.policy.keep_comments => Some(empty()),
+                    Element::Custom { .. } if !self.policy.keep_custom => Some(empty()),
+                    _ => None,
+                };
+                if let Some(replacement) = replacement {
+                    self.removed |= replacement == empty();
+                    *element = replacement;
+                }
+            }
+
+            fn leave_element(&mut self, _element: &mut Element) {
+                self.depth -= 1;
+            }
+        }
+
+        let mut sanitize = Sanitize {
+            policy,
+            depth: 0,
+            removed: false,
+        };
+        self.walk_mut(&mut sanitize);
+        if sanitize.removed {
+            *self = self.filter(|element| *element != empty());
+        }
+    }
+}
+
+/// Strips from `html` what could run code or load content once it is shown: scripts, styles,
+/// frames and embedded objects, event handler attributes, urls `policy` does not allow and,
+/// without external resources, images from other hosts
+#[cfg(feature = "html")]
+pub fn sanitize_html(html: &str, policy: &SanitizePolicy) -> String {
+    use scraper::{Html, Node};
+
+    const BLOCKED: [&str; 8] = [
+        "script", "style", "iframe", "frame", "frameset", "object", "embed", "applet",
+    ];
+    const URL_ATTRIBUTES: [&str; 6] = ["href", "src", "action", "formaction", "poster", "cite"];
+    const RESOURCE_ATTRIBUTES: [&str; 2] = ["src", "poster"];
+
+    let mut document = Html::parse_document(html);
+    let mut detached = Vec::new();
+    for node in document.tree.nodes() {
+        if let Node::Element(element) = node.value() {
+            let external_image = element.name() == "img"
+                && element
+                    .attr("src")
+                    .is_some_and(|src| !policy.allows_resource(src));
+            if BLOCKED.contains(&element.name()) || external_image {
+                detached.push(node.id());
+            }
+        }
+    }
+    for id in detached {
+        if let Some(mut node) = document.tree.get_mut(id) {
+            node.detach();
+        }
+    }
+
+    let ids: Vec<_> = document.tree.nodes().map(|node| node.id()).collect();
+    for id in ids {
+        let Some(mut node) = document.tree.get_mut(id) else {
+            continue;
+        };
+        if let Node::Element(element) = node.value() {
+            element.attrs.retain(|name, value| {
+                let name = name.local.to_ascii_lowercase();
+                let name: &str = &name;
+                if name.starts_with("on") {
+                    false
+                } else if RESOURCE_ATTRIBUTES.contains(&name) {
+                    policy.allows_resource(value)
+                } else if URL_ATTRIBUTES.contains(&name) {
+                    policy.allows_url(value)
+                } else {
+                    true
+                }
+            });
+        }
+    }
+    document.html()
+}
+
+/// The scheme of an absolute url, lowercase. Browsers skip control characters and spaces in it,
+/// so `java\tscript:` has the scheme `javascript`
+fn scheme(url: &str) -> Option<String> {
+    let url: String = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect();
+    let (scheme, _) = url.split_once(':')?;
+    let mut chars = scheme.chars();
+    let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    valid.then(|| scheme.to_ascii_lowercase())
+}
+
+fn text(text: String) -> Element {
+    Element::Text {
+        text,
+        font: FontSpec::from(8),
+    }
+}
+
+/// Stands in for a removed element until the document is filtered
+fn empty() -> Element {
+    text(String::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{DocumentBuilder, MediaKind};
+
+    #[test]
+    fn test_sanitize() {
+        let mut document = DocumentBuilder::new()
+            .paragraph(|p| {
+                p.text("Click ")
+                    .link("here", "JavaScript:alert(1)")
+                    .text(" or ")
+                    .link("there", "https://example.com")
+            })
+            .element(Element::Media {
+                kind: MediaKind::Video,
+                source: MediaSource::Url("https://cdn.example.com/intro.mp4".to_string()),
+                poster: None,
+            })
+            .element(Element::Comment {
+                author: None,
+                date: None,
+                text: "internal".to_string(),
+                range: None,
+            })
+            .element(Element::Blockquote {
+                elements: vec![Element::Blockquote {
+                    elements: vec![Element::Paragraph {
+                        elements: vec![text("Deep".to_string())],
+                    }],
+                    attribution: vec![],
+                }],
+                attribution: vec![],
+            })
+            .build();
+
+        document.sanitize(&SanitizePolicy {
+            max_depth: 2,
+            ..SanitizePolicy::default()
+        });
+        let detail = document.get_detail();
+        assert_eq!(detail.len(), 2);
+        let Element::Paragraph { elements } = detail[0] else {
+            panic!("Expected a paragraph, got {:?}", detail[0]);
+        };
+        assert_eq!(elements[1], text("here".to_string()));
+        assert!(matches!(&elements[3], Element::Hyperlink { title, .. } if title == "there"));
+        assert_eq!(
+            detail[1],
+            &Element::Blockquote {
+                elements: vec![Element::Blockquote {
+                    elements: vec![text("Deep".to_string())],
+                    attribution: vec![],
+                }],
+                attribution: vec![],
+            }
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "html")]
+    fn test_sanitize_html() {
+        let html = r#"<html><body>
+            <p onclick="steal()">Hello <a href=" javascript:alert(1)">link</a></p>
+            <script>alert(1)</script>
+            <iframe src="https://example.com"></iframe>
+            <img src="https://example.com/track.png" alt="Tracker">
+            <img src="logo.png" alt="Logo">
+            <a href="https://example.com">Site</a>
+        </body></html>"#;
+        let sanitized = sanitize_html(html, &SanitizePolicy::default());
+        assert!(!sanitized.contains("onclick"));
+        assert!(!sanitized.contains("script"));
+        assert!(!sanitized.contains("iframe"));
+        assert!(!sanitized.contains("track.png"));
+        assert!(sanitized.contains(r#"<a>link</a>"#));
+        assert!(sanitized.contains("logo.png"));
+        assert!(sanitized.contains(r#"<a href="https://example.com">Site</a>"#));
+    }
+}
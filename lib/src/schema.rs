@@ -0,0 +1,252 @@
+//! The serialized form of a document, as the YAML format writes it and services store it
+//!
+//! Documents are serialized with `schema_version` set to [`SCHEMA_VERSION`]. Fields added to the
+//! model have defaults and renamed ones aliases, so a document serialized by an earlier version
+//! reads as it is, one written before the version field was added counts as version 0. A change
+//! those can't absorb bumps the version and migrates older documents in
+//! [`Document::from_json_value`].
+//!
+//! Documents from a later version may hold elements this one does not know,
+//! [`Document::from_json_value`] reads them as custom elements of that kind instead of failing.
+//! The JSON format writes a form of its own, with the same version and the same handling of
+//! unknown elements.
+use std::str::FromStr;
+
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
+
+use crate::core::{Document, ElementKind};
+
+/// Version of the serialized form this version of shiva writes
+pub const SCHEMA_VERSION: u64 = 1;
+
+impl Serialize for Document {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut document = serializer.serialize_struct("Document", 6)?;
+        document.serialize_field("schema_version", &SCHEMA_VERSION)?;
+        document.serialize_field("bands", &self.bands)?;
+        document.serialize_field("page_format", &self.page_format)?;
+        document.serialize_field("orientation", &self.orientation)?;
+        document.serialize_field("direction", &self.direction)?;
+        document.serialize_field("metadata", &self.metadata)?;
+        document.end()
+    }
+}
+
+impl Document {
+    /// The serialized form of the document
+    pub fn to_json_value(&self) -> anyhow::Result<Value> {
+        Ok(serde_json::to_value(self)?)
+    }
+
+    /// Reads a document serialized by this or another version of shiva
+    pub fn from_json_value(mut value: Value) -> anyhow::Result<Document> {
+        let version = value
+            .get("schema_version")
+            .and_then(Value::as_u64)
+            .unwrap_or(0);
+        if version > SCHEMA_VERSION {
+            if let Some(bands) = value.get_mut("bands").and_then(Value::as_array_mut) {
+                bands.iter_mut().for_each(shim_band);
+            }
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+/// Image data as base64, which text formats carry better than an array of numbers
+pub(crate) mod base64_bytes {
+    use base64::{engine::general_purpose, Engine as _};
+    use bytes::Bytes;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &Bytes, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&general_purpose::STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Bytes, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        general_purpose::STANDARD
+            .decode(text)
+            .map(Bytes::from)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// A band is `{"Detail": [...]}`, a custom band `{"Custom": ["name", [...]]}`
+fn shim_band(band: &mut Value) {
+    let Some(band) = band.as_object_mut() else {
+        return;
+    };
+    for (kind, elements) in band.iter_mut() {
+        let elements = match kind.as_str() {
+            "Custom" => elements.get_mut(1),
+            _ => Some(elements),
+        };
+        if let Some(elements) = elements {
+            shim_elements(elements);
+        }
+    }
+}
+
+fn shim_elements(elements: &mut Value) {
+    if let Some(elements) = elements.as_array_mut() {
+        elements.iter_mut().for_each(shim_element);
+    }
+}
+
+/// Replaces an element of a variant this version does not know with a custom element holding
+/// its fields, and the unknown elements nested in known ones
+fn shim_element(element: &mut Value) {
+    // Variants without fields are serialized as their name, the others as a single key object
+    let variant = match &*element {
+        Value::String(variant) => variant.clone(),
+        Value::Object(object) if object.len() == 1 => match object.keys().next() {
+            Some(variant) => variant.clone(),
+            None => return,
+        },
+        _ => return,
+    };
+    if ElementKind::from_str(&variant).is_err() {
+        let data = match element {
+            Value::Object(object) => object.remove(&variant).unwrap_or(Value::Null),
+            _ => Value::Null,
+        };
+        *element = json!({ "Custom": { "kind": variant, "data": data, "children": [] } });
+        return;
+    }
+    let Some(fields) = element.get_mut(&variant) else {
+        return;
+    };
+    match variant.as_str() {
+        "List" => {
+            for item in array(fields, "elements") {
+                if let Some(element) = item.get_mut("element") {
+                    shim_element(element);
+                }
+            }
+        }
+        "Table" => {
+            for header in array(fields, "headers") {
+                if let Some(element) = header.get_mut("element") {
+                    shim_element(element);
+                }
+            }
+            for row in array(fields, "rows") {
+                for cell in array(row, "cells") {
+                    if let Some(elements) = cell.get_mut("elements") {
+                        shim_elements(elements);
+                    }
+                }
+            }
+            if let Some(caption) = fields.get_mut("caption") {
+                shim_elements(caption);
+            }
+        }
+        "DefinitionList" => {
+            for item in array(fields, "items") {
+                for key in ["term", "definitions"] {
+                    if let Some(elements) = item.get_mut(key) {
+                        shim_elements(elements);
+                    }
+                }
+            }
+        }
+        _ => {
+            for key in ["elements", "children", "content", "caption", "attribution"] {
+                if let Some(elements) = fields.get_mut(key) {
+                    shim_elements(elements);
+                }
+            }
+        }
+    }
+}
+
+fn array<'a>(fields: &'a mut Value, key: &str) -> impl Iterator<Item = &'a mut Value> {
+    fields
+        .get_mut(key)
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{DocumentBuilder, Element, ImageData, ImageDimension};
+    use bytes::Bytes;
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let image = ImageData::new(
+            Bytes::from_static(b"\x89PNG"),
+            "Logo".to_string(),
+            "Logo".to_string(),
+            "png".to_string(),
+            "center".to_string(),
+            ImageDimension::default(),
+        );
+        let document = DocumentBuilder::new()
+            .page_header("Report")
+            .header(1, "Results")
+            .image(image)
+            .list(["One", "Two"], true)
+            .build();
+
+        let value = document.to_json_value()?;
+        assert_eq!(value["schema_version"], json!(SCHEMA_VERSION));
+        assert_eq!(value["bands"][1]["Detail"][1]["Image"]["bytes"], json!("iVBORw=="));
+        assert_eq!(Document::from_json_value(value)?, document);
+
+        // Written before the version field and image data were serialized
+        let unversioned = json!({
+            "bands": [{ "Detail": [{ "Text": { "text": "Old", "size": 8 } }] }],
+            "page_format": "A4",
+            "orientation": "Portrait"
+        });
+        let document = Document::from_json_value(unversioned)?;
+        assert_eq!(document.get_detail().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_elements() -> anyhow::Result<()> {
+        let later = json!({
+            "schema_version": SCHEMA_VERSION + 1,
+            "bands": [{ "Detail": [
+                { "Callout": { "tone": "warning" } },
+                { "List": { "numbered": false, "elements": [
+                    { "element": "Spacer", "checked": null }
+                ] } },
+                "LineBreak"
+            ] }],
+            "page_format": "A4",
+            "orientation": "Portrait"
+        });
+        let document = Document::from_json_value(later.clone())?;
+        let detail = document.get_detail();
+        assert_eq!(
+            detail[0],
+            &Element::Custom {
+                kind: "Callout".to_string(),
+                data: json!({ "tone": "warning" }),
+                children: vec![],
+            }
+        );
+        assert!(matches!(
+            detail[1],
+            Element::List { elements, .. } if matches!(
+                &elements[0].element,
+                Element::Custom { kind, .. } if kind == "Spacer"
+            )
+        ));
+        assert_eq!(detail[2], &Element::LineBreak);
+
+        // Unknown elements of this version are errors, not someone else's extension
+        let mut current = later;
+        current["schema_version"] = json!(SCHEMA_VERSION);
+        assert!(Document::from_json_value(current).is_err());
+        Ok(())
+    }
+}
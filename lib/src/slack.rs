@@ -0,0 +1,516 @@
+use crate::core::*;
+use bytes::Bytes;
+use serde_json::json;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(_document: &Bytes) -> anyhow::Result<Document> {
+        Err(anyhow::anyhow!("Slack mrkdwn parsing is not supported"))
+    }
+
+    /// Produces a Block Kit payload of `mrkdwn` section blocks, ready for `chat.postMessage`.
+    /// Headers become bold lines, lists use `•` bullets, multi-line text and tables become
+    /// code blocks and links are written as `<url|label>`. Blocks are filled up to the
+    /// 3000 character limit of a section, longer elements are split across blocks.
+    /// Horizontal rules become divider blocks.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        // A table of contents is written as the list of its headers, the rules of sections
+        // divide the message as the others do
+        let document = &document.flatten_sections().expand_toc();
+        // The chunks between dividers
+        let mut sections = vec![Vec::new()];
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                if let Element::HorizontalRule = element {
+                    sections.push(Vec::new());
+                    continue;
+                }
+                let text = block(element);
+                if !text.is_empty() {
+                    sections.last_mut().unwrap().push(text);
+                }
+            }
+        }
+        let mut blocks: Vec<serde_json::Value> = Vec::new();
+        for (index, chunks) in sections.into_iter().enumerate() {
+            if index > 0 {
+                blocks.push(json!({ "type": "divider" }));
+            }
+            blocks.extend(pack(chunks, BLOCK_LIMIT).into_iter().map(
+                |text| json!({ "type": "section", "text": { "type": "mrkdwn", "text": text } }),
+            ));
+        }
+        Ok(Bytes::from(serde_json::to_vec_pretty(
+            &json!({ "blocks": blocks }),
+        )?))
+    }
+}
+
+/// Maximum length of the text of a section block
+const BLOCK_LIMIT: usize = 3000;
+
+const FENCE: &str = "```";
+
+/// Slack only requires the control characters of its link syntax to be escaped
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn length(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// Joins chunks with blank lines into blocks of at most `limit` characters
+fn pack(chunks: Vec<String>, limit: usize) -> Vec<String> {
+    let mut blocks: Vec<String> = Vec::new();
+    for chunk in chunks.into_iter().flat_map(|chunk| split(&chunk, limit)) {
+        match blocks.last_mut() {
+            Some(block) if length(block) + 2 + length(&chunk) <= limit => {
+                block.push_str("\n\n");
+                block.push_str(&chunk);
+            }
+            _ => blocks.push(chunk),
+        }
+    }
+    blocks
+}
+
+/// Splits a chunk longer than `limit` at line breaks, then at spaces. A code block is
+/// split inside its fences and every piece gets fences of its own.
+fn split(chunk: &str, limit: usize) -> Vec<String> {
+    if length(chunk) <= limit {
+        return vec![chunk.to_string()];
+    }
+    if let Some(code) = chunk
+        .strip_prefix("```\n")
+        .and_then(|chunk| chunk.strip_suffix("\n```"))
+    {
+        let fences = length(FENCE) * 2 + 2;
+        return split(code, limit.saturating_sub(fences).max(1))
+            .into_iter()
+            .map(|piece| format!("{}\n{}\n{}", FENCE, piece, FENCE))
+            .collect();
+    }
+
+    let mut pieces: Vec<String> = Vec::new();
+    let mut piece = String::new();
+    for line in chunk.split('\n') {
+        let mut line = line.to_string();
+        while length(&line) > limit {
+            let boundary = line
+                .char_indices()
+                .nth(limit)
+                .map_or(line.len(), |(index, _)| index);
+            let cut = line[..boundary]
+                .rfind(' ')
+                .filter(|index| *index > 0)
+                .unwrap_or(boundary);
+            if !piece.is_empty() {
+                pieces.push(std::mem::take(&mut piece));
+            }
+            pieces.push(line[..cut].to_string());
+            line = line[cut..].trim_start().to_string();
+        }
+        if !piece.is_empty() && length(&piece) + 1 + length(&line) > limit {
+            pieces.push(std::mem::take(&mut piece));
+        }
+        if !piece.is_empty() {
+            piece.push('\n');
+        }
+        piece.push_str(&line);
+    }
+    if !piece.is_empty() {
+        pieces.push(piece);
+    }
+    pieces
+}
+
+fn block(element: &Element) -> String {
+    match element {
+        // mrkdwn has no headings
+        Element::Header { text, .. } => {
+            let text = text.replace('\n', " ");
+            if text.trim().is_empty() {
+                String::new()
+            } else {
+                format!("*{}*", escape(text.trim()))
+            }
+        }
+        Element::Text { text, .. } if text.contains('\n') => {
+            format!(
+                "{}\n{}\n{}",
+                FENCE,
+                escape(text.trim_end_matches('\n')),
+                FENCE
+            )
+        }
+        // mrkdwn code blocks take no language
+        Element::CodeBlock { code, .. } => format!("{}\n{}\n{}", FENCE, escape(code), FENCE),
+        Element::List { elements, numbered } => {
+            let mut lines = Vec::new();
+            list(&mut lines, elements, *numbered, 0);
+            lines.join("\n")
+        }
+        Element::Chart { .. } => block(&element.chart_fallback()),
+        Element::Table { headers, rows, .. } => table(headers, rows),
+        // A bold term line, the definitions are indented below it
+        Element::DefinitionList { items } => {
+            let mut lines = Vec::new();
+            for item in items {
+                let term: String = item.term.iter().map(inline).collect();
+                lines.push(format!("*{}*", term.replace('\n', " ").trim()));
+                for definition in &item.definitions {
+                    let text = inline(definition).replace('\n', " ");
+                    lines.push(format!("    {}", text.trim()));
+                }
+            }
+            lines.join("\n")
+        }
+        Element::Blockquote { elements, .. } => elements
+            .iter()
+            .map(block)
+            .filter(|block| !block.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n\n")
+            .lines()
+            .map(|line| format!("> {}", line).trim_end().to_string())
+            .collect::<Vec<String>>()
+            .join("\n"),
+        Element::Section { children, .. } => children
+            .iter()
+            .map(block)
+            .filter(|block| !block.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n\n"),
+        Element::Comment { .. }
+        | Element::HorizontalRule
+        | Element::PageBreak
+        | Element::TableOfContents { .. } => String::new(),
+        element => inline(element).trim().to_string(),
+    }
+}
+
+/// Nested lists are indented by four spaces
+fn list(lines: &mut Vec<String>, items: &[ListItem], numbered: bool, depth: usize) {
+    let indent = " ".repeat(depth * 4);
+    let mut number = 0;
+    for item in items {
+        if let Element::List { elements, numbered } = &item.element {
+            list(lines, elements, *numbered, depth + 1);
+            continue;
+        }
+        number += 1;
+        let text = inline(&item.element).replace('\n', " ");
+        if numbered {
+            lines.push(format!("{}{}. {}", indent, number, text.trim()));
+        } else {
+            lines.push(format!("{}• {}", indent, text.trim()));
+        }
+    }
+}
+
+/// Slack has no tables, the columns are padded inside a code block
+fn table(headers: &[TableHeader], rows: &[TableRow]) -> String {
+    let mut lines: Vec<Vec<String>> = Vec::new();
+    if !headers.is_empty() {
+        lines.push(
+            headers
+                .iter()
+                .map(|header| plain_text(&header.element))
+                .collect(),
+        );
+    }
+    for row in rows {
+        lines.push(
+            row.cells
+                .iter()
+                .map(|cell| plain_text(&cell.element()))
+                .collect(),
+        );
+    }
+    let columns = lines.iter().map(Vec::len).max().unwrap_or(0);
+    if columns == 0 {
+        return String::new();
+    }
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            lines
+                .iter()
+                .filter_map(|cells| cells.get(column))
+                .map(|cell| length(cell))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut text = Vec::new();
+    for (index, cells) in lines.iter().enumerate() {
+        let line: Vec<String> = widths
+            .iter()
+            .enumerate()
+            .map(|(column, width)| {
+                let cell = cells.get(column).map_or("", String::as_str);
+                format!("{:width$}", cell, width = width)
+            })
+            .collect();
+        text.push(line.join(" | ").trim_end().to_string());
+        if index == 0 && !headers.is_empty() {
+            let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+            text.push(rule.join("-+-"));
+        }
+    }
+    format!("{}\n{}\n{}", FENCE, escape(&text.join("\n")), FENCE)
+}
+
+/// Cell text on one line, links keep their label only
+fn plain_text(element: &Element) -> String {
+    match element {
+        Element::Text { text, .. } | Element::Header { text, .. } => text.replace('\n', " "),
+        Element::Paragraph { elements }
+        | Element::Blockquote { elements, .. }
+        | Element::Section {
+            children: elements, ..
+        }
+        | Element::Styled { elements, .. } => elements.iter().map(plain_text).collect(),
+        Element::Hyperlink { title, url, .. } => {
+            if title.is_empty() {
+                url.clone()
+            } else {
+                title.clone()
+            }
+        }
+        Element::Image(image) => image.alt().to_string(),
+        Element::Figure { caption, .. } => caption.iter().map(plain_text).collect(),
+        Element::LineBreak => " ".to_string(),
+        Element::List { elements, .. } => elements
+            .iter()
+            .map(|item| plain_text(&item.element))
+            .collect::<Vec<String>>()
+            .join(" "),
+        Element::DefinitionList { items } => items
+            .iter()
+            .flat_map(|item| item.term.iter().chain(&item.definitions))
+            .map(plain_text)
+            .collect::<Vec<String>>()
+            .join(" "),
+        Element::CodeBlock { code, .. } => code.replace('\n', " "),
+        Element::Math { tex, .. } => tex.replace('\n', " "),
+        Element::FootnoteReference { id } => format!("[{}]", id),
+        Element::Footnote { id, content } => {
+            let content: Vec<String> = content.iter().map(plain_text).collect();
+            format!("[{}] {}", id, content.join(" "))
+        }
+        Element::Media { .. } => plain_text(&element.media_fallback()),
+        Element::Ruby { .. } => plain_text(&element.ruby_fallback()),
+        Element::Table { .. }
+        | Element::Chart { .. }
+        | Element::Comment { .. }
+        | Element::HorizontalRule
+        | Element::PageBreak
+        | Element::TableOfContents { .. }
+        | Element::Custom { .. } => String::new(),
+    }
+    .trim()
+    .to_string()
+}
+
+fn inline(element: &Element) -> String {
+    match element {
+        Element::Text { text, .. } | Element::Header { text, .. } => escape(text),
+        Element::Paragraph { elements }
+        | Element::Blockquote { elements, .. }
+        | Element::Section {
+            children: elements, ..
+        } => elements.iter().map(inline).collect(),
+        // Slack has no underline, such spans keep only the text
+        Element::Styled { style, elements } => {
+            let mut text: String = elements.iter().map(inline).collect();
+            for (enabled, mark) in [
+                (style.strikethrough, "~"),
+                (style.italic, "_"),
+                (style.bold, "*"),
+            ] {
+                if enabled {
+                    text = format!("{}{}{}", mark, text, mark);
+                }
+            }
+            text
+        }
+        Element::Hyperlink { title, url, .. } => {
+            let url = url.replace(['<', '>', '|', '\n'], "");
+            if title.is_empty() || *title == url {
+                format!("<{}>", url)
+            } else {
+                format!("<{}|{}>", url, escape(&title.replace(['|', '\n'], " ")))
+            }
+        }
+        Element::Image(image) => {
+            let text = if image.alt().is_empty() {
+                image.title()
+            } else {
+                image.alt()
+            };
+            if text.is_empty() {
+                String::new()
+            } else {
+                format!("_{}_", escape(text))
+            }
+        }
+        // The caption stands in for the image like its alt text does
+        Element::Figure { image, caption } => {
+            let text: String = caption.iter().map(inline).collect();
+            if text.trim().is_empty() {
+                inline(&Element::Image(image.clone()))
+            } else {
+                format!("_{}_", text.trim())
+            }
+        }
+        Element::LineBreak | Element::HorizontalRule => "\n".to_string(),
+        Element::List { elements, .. } => elements
+            .iter()
+            .map(|item| inline(&item.element))
+            .collect::<Vec<String>>()
+            .join(" "),
+        Element::DefinitionList { items } => items
+            .iter()
+            .flat_map(|item| item.term.iter().chain(&item.definitions))
+            .map(inline)
+            .collect::<Vec<String>>()
+            .join(" "),
+        Element::CodeBlock { code, .. } => format!("`{}`", escape(&code.replace('\n', " "))),
+        // Slack has no formulas, the TeX source is set as code
+        Element::Math { tex, .. } => format!("`{}`", escape(&tex.replace('\n', " "))),
+        Element::FootnoteReference { id } => format!("[{}]", escape(id)),
+        Element::Footnote { id, content } => {
+            let content: Vec<String> = content.iter().map(inline).collect();
+            format!("[{}] {}", escape(id), content.join(" "))
+        }
+        Element::Media { .. } => inline(&element.media_fallback()),
+        Element::Ruby { .. } => inline(&element.ruby_fallback()),
+        Element::Table { .. }
+        | Element::Chart { .. }
+        | Element::Comment { .. }
+        | Element::PageBreak
+        | Element::TableOfContents { .. }
+        | Element::Custom { .. } => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::slack::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Release".to_string(),
+                anchor: None,
+            },
+            Element::Paragraph {
+                elements: vec![
+                    text("1 < 2, see "),
+                    Element::Hyperlink {
+                        title: "the notes".to_string(),
+                        url: "https://example.com".to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    },
+                ],
+            },
+            Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("One"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: Element::List {
+                            elements: vec![ListItem {
+                                element: text("Nested"),
+                                checked: None,
+                            }],
+                            numbered: true,
+                        },
+                        checked: None,
+                    },
+                ],
+                numbered: false,
+            },
+            text("let x = 1;\nx += 1;"),
+            Element::Table {
+                headers: vec![TableHeader {
+                    element: text("Name"),
+                    width: 30.0,
+                }],
+                rows: vec![TableRow {
+                    cells: vec![TableCell::new(text("shiva"))],
+                }],
+                alignments: vec![],
+                caption: vec![],
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let payload: Value = serde_json::from_slice(&generated)?;
+        assert_eq!(payload["blocks"].as_array().map(Vec::len), Some(1));
+        assert_eq!(payload["blocks"][0]["text"]["type"], "mrkdwn");
+        assert_eq!(
+            payload["blocks"][0]["text"]["text"],
+            concat!(
+                "*Release*\n\n",
+                "1 &lt; 2, see <https://example.com|the notes>\n\n",
+                "• One\n    1. Nested\n\n",
+                "```\nlet x = 1;\nx += 1;\n```\n\n",
+                "```\nName\n-----\nshiva\n```",
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_blocks() -> anyhow::Result<()> {
+        let words = "lorem ipsum ".repeat(400);
+        let code = "let x = 1;\n".repeat(400);
+        let document = Document::new(vec![
+            Element::Paragraph {
+                elements: vec![Element::Text {
+                    text: words,
+                    font: FontSpec::from(8),
+                }],
+            },
+            Element::Text {
+                text: code,
+                font: FontSpec::from(8),
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let payload: Value = serde_json::from_slice(&generated)?;
+        let blocks: Vec<&str> = payload["blocks"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|block| block["text"]["text"].as_str())
+            .collect();
+        assert!(blocks.len() > 3);
+        assert!(blocks
+            .iter()
+            .all(|text| text.chars().count() <= BLOCK_LIMIT));
+        // Code blocks are closed in every block they span
+        assert!(blocks
+            .iter()
+            .all(|text| text.matches(FENCE).count() % 2 == 0));
+        Ok(())
+    }
+}
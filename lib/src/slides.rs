@@ -0,0 +1,99 @@
+use crate::core::*;
+
+/// One slide of a presentation built from the document structure
+pub struct Slide<'a> {
+    pub title: String,
+    pub content: Vec<&'a Element>,
+}
+
+/// Starts a new slide at every level 1 or 2 header, and an untitled one at every page break.
+/// Content before the first header gets an untitled slide, a document without content still
+/// produces one empty slide. Sections are split at their headers as well.
+pub fn split_slides(document: &Document) -> Vec<Slide<'_>> {
+    fn split<'a>(elements: &'a [Element], slides: &mut Vec<Slide<'a>>) {
+        for element in elements {
+            match element {
+                Element::Section { children, .. } => split(children, slides),
+                Element::Header { level, text, .. } if *level <= 2 => slides.push(Slide {
+                    title: text.clone(),
+                    content: Vec::new(),
+                }),
+                Element::PageBreak if !slides.is_empty() => slides.push(Slide {
+                    title: String::new(),
+                    content: Vec::new(),
+                }),
+                Element::PageBreak => {}
+                element => match slides.last_mut() {
+                    Some(slide) => slide.content.push(element),
+                    None => slides.push(Slide {
+                        title: String::new(),
+                        content: vec![element],
+                    }),
+                },
+            }
+        }
+    }
+
+    let mut slides: Vec<Slide> = Vec::new();
+    for band in &document.bands {
+        if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+            continue;
+        }
+        split(band.elements(), &mut slides);
+    }
+    if slides.is_empty() {
+        slides.push(Slide {
+            title: String::new(),
+            content: Vec::new(),
+        });
+    }
+    slides
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::slides::*;
+
+    #[test]
+    fn test_split_slides() {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let header = |level: u8, text: &str| Element::Header {
+            level,
+            text: text.to_string(),
+            anchor: None,
+        };
+        let document = Document::new(vec![
+            text("Intro"),
+            header(1, "Agenda"),
+            text("Topics"),
+            header(2, "Details"),
+            header(3, "Subtopic"),
+            text("More"),
+        ]);
+        let slides = split_slides(&document);
+        let titles: Vec<&str> = slides.iter().map(|slide| slide.title.as_str()).collect();
+        assert_eq!(titles, vec!["", "Agenda", "Details"]);
+        assert_eq!(slides[0].content, vec![&text("Intro")]);
+        assert_eq!(
+            slides[2].content,
+            vec![&header(3, "Subtopic"), &text("More")]
+        );
+
+        assert_eq!(split_slides(&Document::new(vec![])).len(), 1);
+
+        let document = Document::new(vec![
+            Element::PageBreak,
+            header(1, "Agenda"),
+            text("Topics"),
+            Element::PageBreak,
+            text("More"),
+        ]);
+        let slides = split_slides(&document);
+        let titles: Vec<&str> = slides.iter().map(|slide| slide.title.as_str()).collect();
+        assert_eq!(titles, vec!["Agenda", ""]);
+        assert_eq!(slides[1].content, vec![&text("More")]);
+    }
+}
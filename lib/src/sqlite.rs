@@ -0,0 +1,199 @@
+use crate::core::*;
+use crate::tables::{sheets, ColumnType, Sheet};
+use bytes::Bytes;
+use rusqlite::types::Value;
+use rusqlite::{Connection, DatabaseName};
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(_document: &Bytes) -> anyhow::Result<Document> {
+        Err(anyhow::anyhow!("SQLite parsing is not supported"))
+    }
+
+    /// Writes every table of the document into a SQLite database, one SQL table per table.
+    /// A table is named after the closest header above it, or `table_N`, and its columns after
+    /// the header cells, or `column_N`. Columns holding only integers or only numbers get the
+    /// `INTEGER` and `REAL` types, everything else is `TEXT` and empty cells are `NULL`.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        let mut connection = Connection::open_in_memory()?;
+        let transaction = connection.transaction()?;
+        for sheet in sheets(document) {
+            create_table(&transaction, &sheet)?;
+        }
+        transaction.commit()?;
+        let data = connection.serialize(DatabaseName::Main)?;
+        Ok(Bytes::copy_from_slice(&data))
+    }
+}
+
+fn create_table(connection: &Connection, sheet: &Sheet) -> anyhow::Result<()> {
+    let definitions: Vec<String> = sheet
+        .columns
+        .iter()
+        .zip(&sheet.types)
+        .map(|(column, column_type)| {
+            let column_type = match column_type {
+                ColumnType::Integer => "INTEGER",
+                ColumnType::Real => "REAL",
+                ColumnType::Text => "TEXT",
+            };
+            format!("{} {}", quote(column), column_type)
+        })
+        .collect();
+    connection.execute(
+        &format!(
+            "CREATE TABLE {} ({})",
+            quote(&sheet.name),
+            definitions.join(", ")
+        ),
+        (),
+    )?;
+
+    let placeholders = vec!["?"; sheet.columns.len()].join(", ");
+    let mut statement = connection.prepare(&format!(
+        "INSERT INTO {} VALUES ({})",
+        quote(&sheet.name),
+        placeholders
+    ))?;
+    for row in &sheet.rows {
+        let values = row
+            .iter()
+            .zip(&sheet.types)
+            .map(|(cell, column_type)| value(cell, *column_type));
+        statement.execute(rusqlite::params_from_iter(values))?;
+    }
+    Ok(())
+}
+
+fn value(cell: &str, column_type: ColumnType) -> Value {
+    if cell.is_empty() {
+        return Value::Null;
+    }
+    match column_type {
+        ColumnType::Integer => cell.parse().map_or(Value::Null, Value::Integer),
+        ColumnType::Real => cell.parse().map_or(Value::Null, Value::Real),
+        ColumnType::Text => Value::Text(cell.to_string()),
+    }
+}
+
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sqlite::*;
+
+    fn cell(text: &str) -> Element {
+        Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        }
+    }
+
+    fn open(bytes: &Bytes, name: &str) -> anyhow::Result<Connection> {
+        let path = std::env::temp_dir().join(format!("shiva-{}-{}.db", name, std::process::id()));
+        std::fs::write(&path, bytes)?;
+        let connection = Connection::open(&path)?;
+        std::fs::remove_file(&path)?;
+        Ok(connection)
+    }
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Element::Header {
+                level: 2,
+                text: "Sales 2024".to_string(),
+                anchor: None,
+            },
+            Element::Table {
+                headers: vec![
+                    TableHeader {
+                        element: cell("Region"),
+                        width: 30.0,
+                    },
+                    TableHeader {
+                        element: cell("Units"),
+                        width: 30.0,
+                    },
+                    TableHeader {
+                        element: cell("Share %"),
+                        width: 30.0,
+                    },
+                ],
+                rows: vec![
+                    TableRow {
+                        cells: vec![
+                            TableCell::new(cell("North")),
+                            TableCell::new(cell("12")),
+                            TableCell::new(cell("0.4")),
+                        ],
+                    },
+                    TableRow {
+                        cells: vec![TableCell::new(cell("South")), TableCell::new(cell("18"))],
+                    },
+                ],
+                alignments: vec![],
+                caption: vec![],
+            },
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let connection = open(&generated, "generate")?;
+        let (units, share): (i64, Option<f64>) =
+            connection.query_row("SELECT SUM(units), MAX(share) FROM sales_2024", (), |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+        assert_eq!(units, 30);
+        assert_eq!(share, Some(0.4));
+        let empty: Option<f64> = connection.query_row(
+            "SELECT share FROM sales_2024 WHERE region = 'South'",
+            (),
+            |row| row.get(0),
+        )?;
+        assert_eq!(empty, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_names() -> anyhow::Result<()> {
+        let table = Element::Table {
+            headers: vec![],
+            rows: vec![TableRow {
+                cells: vec![TableCell::new(cell("a")), TableCell::new(cell("b"))],
+            }],
+            alignments: vec![],
+            caption: vec![],
+        };
+        let document = Document::new(vec![
+            Element::Header {
+                level: 1,
+                text: "Data".to_string(),
+                anchor: None,
+            },
+            table.clone(),
+            table.clone(),
+            Element::Header {
+                level: 1,
+                text: "Data".to_string(),
+                anchor: None,
+            },
+            table,
+        ]);
+        let generated = Transformer::generate(&document)?;
+        let connection = open(&generated, "names")?;
+        let mut statement = connection
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY rowid")?;
+        let names: Vec<String> = statement
+            .query_map((), |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        assert_eq!(names, vec!["data", "table_2", "data_2"]);
+        let columns: Vec<String> = connection
+            .prepare("SELECT name FROM pragma_table_info('data')")?
+            .query_map((), |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        assert_eq!(columns, vec!["column_1", "column_2"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+use crate::core::*;
+use crate::typst::{compile_document, PdfOptions};
+use bytes::Bytes;
+use typst::layout::Abs;
+
+/// Space between the pages of a merged SVG, in points
+const PAGE_GAP: f64 = 5.0;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(_document: &Bytes) -> anyhow::Result<Document> {
+        Err(anyhow::anyhow!("SVG parsing is not supported"))
+    }
+
+    /// All pages laid out as for the PDF output, stacked in one SVG
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_options(document, &PdfOptions::default())
+    }
+}
+
+impl Transformer {
+    /// Generate one SVG of all pages using the given options (page margins...)
+    pub fn generate_with_options(
+        document: &Document,
+        options: &PdfOptions,
+    ) -> anyhow::Result<Bytes> {
+        let document = compile_document(document, options)?;
+        Ok(Bytes::from(typst_svg::svg_merged(
+            &document,
+            Abs::pt(PAGE_GAP),
+        )))
+    }
+
+    /// Generate an SVG for every page, to embed single pages into web pages
+    pub fn generate_pages(document: &Document, options: &PdfOptions) -> anyhow::Result<Vec<Bytes>> {
+        let document = compile_document(document, options)?;
+        Ok(document
+            .pages
+            .iter()
+            .map(|page| Bytes::from(typst_svg::svg(&page.frame)))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::svg::*;
+
+    fn headers(count: usize) -> Document {
+        Document::new(
+            (0..count)
+                .map(|index| Element::Header {
+                    level: 1,
+                    text: format!("Section {}", index),
+                    anchor: None,
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_generate() -> anyhow::Result<()> {
+        let generated = Transformer::generate(&headers(3))?;
+        let svg = std::str::from_utf8(&generated)?;
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_pages() -> anyhow::Result<()> {
+        let pages = Transformer::generate_pages(&headers(100), &PdfOptions::default())?;
+        assert!(pages.len() > 1);
+        assert!(pages.iter().all(|page| page.starts_with(b"<svg")));
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+//! Tables of a document prepared for the database and columnar exports
+use crate::core::*;
+use std::collections::HashSet;
+
+/// A table with names that can be used in queries unquoted and a type for every column
+pub(crate) struct Sheet {
+    pub name: String,
+    pub columns: Vec<String>,
+    pub types: Vec<ColumnType>,
+    /// Every row has a cell for each column, empty cells are empty strings
+    pub rows: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ColumnType {
+    Integer,
+    Real,
+    Text,
+}
+
+/// A table is named after the closest header above it, or `table_N`, and its columns after
+/// the header cells, or `column_N`. Columns holding only integers or only numbers get the
+/// `Integer` and `Real` types, everything else is `Text`. Tables without cells are skipped.
+pub(crate) fn sheets(document: &Document) -> Vec<Sheet> {
+    let document = &document.flatten_sections();
+    let mut sheets = Vec::new();
+    let mut names = HashSet::new();
+    let mut title: Option<&str> = None;
+    let mut count = 0;
+    for band in &document.bands {
+        if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+            continue;
+        }
+        for element in band.elements() {
+            match element {
+                Element::Header { text, .. } => title = Some(text),
+                Element::Table { headers, rows, .. } => {
+                    count += 1;
+                    let name = title
+                        .take()
+                        .and_then(identifier)
+                        .filter(|name| !name.starts_with("sqlite_"))
+                        .unwrap_or_else(|| format!("table_{}", count));
+                    if let Some(sheet) = sheet(unique(name, &mut names), headers, rows) {
+                        sheets.push(sheet);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    sheets
+}
+
+fn sheet(name: String, headers: &[TableHeader], rows: &[TableRow]) -> Option<Sheet> {
+    let mut rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.cells.iter().map(|cell| text(&cell.element())).collect())
+        .collect();
+    let width = rows
+        .iter()
+        .map(Vec::len)
+        .max()
+        .unwrap_or(0)
+        .max(headers.len());
+    if width == 0 {
+        return None;
+    }
+    for row in &mut rows {
+        row.resize(width, String::new());
+    }
+
+    let mut names = HashSet::new();
+    let columns = (0..width)
+        .map(|index| {
+            let name = headers
+                .get(index)
+                .and_then(|header| identifier(&text(&header.element)))
+                .unwrap_or_else(|| format!("column_{}", index + 1));
+            unique(name, &mut names)
+        })
+        .collect();
+    let types = (0..width)
+        .map(|index| {
+            column_type(
+                rows.iter()
+                    .map(|row| &row[index])
+                    .filter(|cell| !cell.is_empty()),
+            )
+        })
+        .collect();
+    Some(Sheet {
+        name,
+        columns,
+        types,
+        rows,
+    })
+}
+
+/// The narrowest type that holds every non-empty cell of a column
+fn column_type<'a>(mut cells: impl Iterator<Item = &'a String> + Clone) -> ColumnType {
+    if cells.clone().all(|cell| cell.parse::<i64>().is_ok()) {
+        ColumnType::Integer
+    } else if cells.all(|cell| cell.parse::<f64>().is_ok()) {
+        ColumnType::Real
+    } else {
+        ColumnType::Text
+    }
+}
+
+/// Lower case letters, digits and underscores
+fn identifier(text: &str) -> Option<String> {
+    let mut name = String::new();
+    for c in text.trim().to_lowercase().chars() {
+        if c.is_alphanumeric() {
+            name.push(c);
+        } else if !name.is_empty() && !name.ends_with('_') {
+            name.push('_');
+        }
+    }
+    let name = name.trim_end_matches('_');
+    if name.is_empty() {
+        None
+    } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+        Some(format!("_{}", name))
+    } else {
+        Some(name.to_string())
+    }
+}
+
+/// Names are compared case-insensitively, repeated names get a numeric suffix
+fn unique(name: String, names: &mut HashSet<String>) -> String {
+    let mut candidate = name.clone();
+    let mut suffix = 1;
+    while !names.insert(candidate.to_lowercase()) {
+        suffix += 1;
+        candidate = format!("{}_{}", name, suffix);
+    }
+    candidate
+}
+
+/// Cell text on one line, links keep their label only
+fn text(element: &Element) -> String {
+    match element {
+        Element::Text { text, .. } | Element::Header { text, .. } => text.replace('\n', " "),
+        Element::Paragraph { elements }
+        | Element::Blockquote { elements, .. }
+        | Element::Section {
+            children: elements, ..
+        }
+        | Element::Styled { elements, .. } => elements.iter().map(text).collect(),
+        Element::Hyperlink { title, url, .. } => {
+            if title.is_empty() {
+                url.clone()
+            } else {
+                title.clone()
+            }
+        }
+        Element::Image(image) => image.alt().to_string(),
+        Element::Figure { caption, .. } => caption.iter().map(text).collect(),
+        Element::LineBreak => " ".to_string(),
+        Element::List { elements, .. } => elements
+            .iter()
+            .map(|item| text(&item.element))
+            .collect::<Vec<String>>()
+            .join(" "),
+        Element::DefinitionList { items } => items
+            .iter()
+            .flat_map(|item| item.term.iter().chain(&item.definitions))
+            .map(text)
+            .collect::<Vec<String>>()
+            .join(" "),
+        Element::CodeBlock { code, .. } => code.replace('\n', " "),
+        Element::Math { tex, .. } => tex.replace('\n', " "),
+        Element::FootnoteReference { id } => format!("[{}]", id),
+        Element::Footnote { id, content } => {
+            let content: Vec<String> = content.iter().map(text).collect();
+            format!("[{}] {}", id, content.join(" "))
+        }
+        Element::Media { .. } => text(&element.media_fallback()),
+        Element::Ruby { .. } => text(&element.ruby_fallback()),
+        Element::Table { .. }
+        | Element::Chart { .. }
+        | Element::Comment { .. }
+        | Element::HorizontalRule
+        | Element::PageBreak
+        | Element::TableOfContents { .. }
+        | Element::Custom { .. } => String::new(),
+    }
+    .trim()
+    .to_string()
+}
@@ -0,0 +1,108 @@
+//! Operation context for parse/generate calls.
+//!
+//! With the `tracing` feature every operation runs inside a `shiva` span carrying the
+//! format, input size and element count, so events emitted by transformers can be
+//! correlated. Without it the same phase boundaries are logged through `log`.
+
+use crate::core::{Document, DocumentType};
+use bytes::Bytes;
+
+pub(crate) struct Operation {
+    name: &'static str,
+    format: DocumentType,
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+}
+
+impl Operation {
+    pub(crate) fn parse(format: DocumentType, input_size: usize) -> Operation {
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "shiva",
+            operation = "parse",
+            format = %format,
+            input_size,
+            element_count = tracing::field::Empty,
+        )
+        .entered();
+        #[cfg(feature = "tracing")]
+        tracing::debug!("parse started");
+        #[cfg(not(feature = "tracing"))]
+        log::debug!("parse {} started: {} bytes", format, input_size);
+        Operation {
+            name: "parse",
+            format,
+            #[cfg(feature = "tracing")]
+            span,
+        }
+    }
+
+    pub(crate) fn generate(format: DocumentType, element_count: usize) -> Operation {
+        #[cfg(feature = "tracing")]
+        let span = tracing::info_span!(
+            "shiva",
+            operation = "generate",
+            format = %format,
+            element_count,
+            output_size = tracing::field::Empty,
+        )
+        .entered();
+        #[cfg(feature = "tracing")]
+        tracing::debug!("generate started");
+        #[cfg(not(feature = "tracing"))]
+        log::debug!("generate {} started: {} elements", format, element_count);
+        Operation {
+            name: "generate",
+            format,
+            #[cfg(feature = "tracing")]
+            span,
+        }
+    }
+
+    pub(crate) fn finish_parse(self, result: &anyhow::Result<Document>) {
+        match result {
+            Ok(document) => {
+                let element_count = document.get_all_elements().len();
+                #[cfg(feature = "tracing")]
+                {
+                    self.span.record("element_count", element_count);
+                    tracing::debug!(element_count, "parse finished");
+                }
+                #[cfg(not(feature = "tracing"))]
+                log::debug!("parse {} finished: {} elements", self.format, element_count);
+            }
+            Err(error) => self.failed(error),
+        }
+    }
+
+    pub(crate) fn finish_generate(self, result: &anyhow::Result<Bytes>) {
+        match result {
+            Ok(output) => {
+                #[cfg(feature = "tracing")]
+                {
+                    self.span.record("output_size", output.len());
+                    tracing::debug!(output_size = output.len(), "generate finished");
+                }
+                #[cfg(not(feature = "tracing"))]
+                log::debug!("generate {} finished: {} bytes", self.format, output.len());
+            }
+            Err(error) => self.failed(error),
+        }
+    }
+
+    fn failed(&self, error: &anyhow::Error) {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(error = %error, format = %self.format, "{} failed", self.name);
+        #[cfg(not(feature = "tracing"))]
+        log::warn!("{} {} failed: {}", self.name, self.format, error);
+    }
+}
+
+/// Emitted by page oriented parsers before each page is processed.
+#[cfg(feature = "pdf")]
+pub(crate) fn page(number: usize, total: usize) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(page = number, total, "page");
+    #[cfg(not(feature = "tracing"))]
+    log::debug!("page {}/{}", number, total);
+}
@@ -2,7 +2,9 @@ use crate::core::Element::{Image, Paragraph, Table};
 use crate::core::*;
 use bytes::Bytes;
 use log::debug;
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
 
 pub struct Transformer;
 impl TransformerTrait for Transformer {
@@ -10,31 +12,21 @@ impl TransformerTrait for Transformer {
     where
         Self: Sized,
     {
-        let mut elements: Vec<Element> = vec![];
         let document: &str = std::str::from_utf8(document.as_ref())?;
-        let lines = document.lines();
-        let lines_vec: Vec<&str> = lines.collect();
-        let mut i = 0;
-        while i < lines_vec.len() {
-            let line = lines_vec[i].to_string();
-            elements.push(Element::Text {
-                text: line,
-                size: 8,
-            });
-            elements.push(Element::Text {
-                text: "\n".to_string(),
-                size: 8,
-            });
-            i += 1;
-        }
-        let new_paragraph = Paragraph { elements };
-        Ok(Document::new(vec![new_paragraph]))
+        parse_lines(document.lines().map(|line| Ok(line.to_string())))
+    }
+
+    /// Parses the text a line at a time
+    fn parse_reader(reader: impl Read) -> anyhow::Result<Document> {
+        parse_lines(BufReader::new(reader).lines().map(|line| Ok(line?)))
     }
 
     fn generate(document: &Document) -> anyhow::Result<Bytes>
     where
         Self: Sized,
     {
+        // A table of contents is written as the list of its headers
+        let document = &document.expand_toc();
         let mut images: HashMap<String, Bytes> = HashMap::new();
         let mut image_num: i32 = 0;
 
@@ -70,6 +62,11 @@ impl TransformerTrait for Transformer {
                 markdown.push_str(&"  ".repeat(list_depth - 1));
                 if let Element::Text { .. } = element.element {
                     markdown.push_str(&prefix);
+                    match element.checked {
+                        Some(true) => markdown.push_str("☑ "),
+                        Some(false) => markdown.push_str("☐ "),
+                        None => {}
+                    }
                 }
                 generate_element(
                     &element.element,
@@ -87,7 +84,7 @@ impl TransformerTrait for Transformer {
             }
 
             match element {
-                Element::Header { level: _, text } => {
+                Element::Header { level: _, text, .. } => {
                     markdown.push_str(text);
                     markdown.push('\n');
                     markdown.push('\n');
@@ -128,12 +125,145 @@ impl TransformerTrait for Transformer {
                         markdown.push('\n');
                     }
                 }
-                Element::Text { text, size: _ } => {
+                Element::Text { text, font: _ } => {
                     markdown.push_str(text);
                     if !text.ends_with(' ') {
                         markdown.push(' ');
                     }
                 }
+                Element::LineBreak => {
+                    markdown.push('\n');
+                }
+                Element::HorizontalRule => {
+                    markdown.push_str("---\n\n");
+                }
+                // A form feed, printers start a new page at it
+                Element::PageBreak => {
+                    markdown.push_str("\u{c}\n");
+                }
+                // Comments are editorial notes and are not part of the published text
+                Element::Comment { .. } => {}
+                // Already expanded into a list
+                Element::TableOfContents { .. } => {}
+                // Only the application that made it knows what it shows
+                Element::Custom { .. } => {}
+                Element::Section { children, .. } => {
+                    for child in children {
+                        generate_element(
+                            child,
+                            markdown,
+                            list_depth,
+                            list_counters,
+                            list_types,
+                            images,
+                            image_num,
+                        )?;
+                    }
+                }
+                // Code keeps its line breaks and indentation
+                Element::CodeBlock { code, .. } => {
+                    markdown.push_str(code);
+                    markdown.push('\n');
+                    markdown.push('\n');
+                }
+                // Quoted lines are marked as in e-mail replies
+                Element::Blockquote { elements, .. } => {
+                    let mut quote = String::new();
+                    for child in elements {
+                        generate_element(
+                            child,
+                            &mut quote,
+                            list_depth,
+                            list_counters,
+                            list_types,
+                            images,
+                            image_num,
+                        )?;
+                    }
+                    for line in quote.trim_end().lines() {
+                        markdown.push_str(format!("> {}", line).trim_end());
+                        markdown.push('\n');
+                    }
+                    markdown.push('\n');
+                }
+                // Each term is on a line of its own, its definitions are indented below it
+                Element::DefinitionList { items } => {
+                    for item in items {
+                        let mut term = String::new();
+                        for child in &item.term {
+                            generate_element(
+                                child,
+                                &mut term,
+                                list_depth,
+                                list_counters,
+                                list_types,
+                                images,
+                                image_num,
+                            )?;
+                        }
+                        markdown.push_str(term.trim());
+                        markdown.push('\n');
+                        for definition in &item.definitions {
+                            let mut text = String::new();
+                            generate_element(
+                                definition,
+                                &mut text,
+                                list_depth,
+                                list_counters,
+                                list_types,
+                                images,
+                                image_num,
+                            )?;
+                            for line in text.trim_end().lines() {
+                                markdown.push_str(format!("    {}", line).trim_end());
+                                markdown.push('\n');
+                            }
+                        }
+                    }
+                    markdown.push('\n');
+                }
+                Element::FootnoteReference { id } => {
+                    markdown.push_str(&format!("[{}] ", id));
+                }
+                // The TeX source is the most readable form plain text has for a formula
+                Element::Math { tex, display } => {
+                    markdown.push_str(tex);
+                    if *display {
+                        markdown.push('\n');
+                        markdown.push('\n');
+                    } else {
+                        markdown.push(' ');
+                    }
+                }
+                // Plain text has no emphasis, only the words are kept
+                Element::Styled { elements, .. } => {
+                    for child in elements {
+                        generate_element(
+                            child,
+                            markdown,
+                            list_depth,
+                            list_counters,
+                            list_types,
+                            images,
+                            image_num,
+                        )?;
+                    }
+                }
+                // Notes are listed as they are defined, after their marker
+                Element::Footnote { id, content } => {
+                    markdown.push_str(&format!("[{}] ", id));
+                    for child in content {
+                        generate_element(
+                            child,
+                            markdown,
+                            list_depth,
+                            list_counters,
+                            list_types,
+                            images,
+                            image_num,
+                        )?;
+                    }
+                }
                 Element::Hyperlink {
                     title, url, alt, ..
                 } => {
@@ -154,55 +284,68 @@ impl TransformerTrait for Transformer {
                     images.insert(image_path.to_string(), image.bytes().clone());
                     *image_num += 1;
                 }
-                Table { headers, rows } => {
-                    let mut max_lengths: Vec<usize> = Vec::new();
-
-                    for header in headers {
-                        if let Element::Text { text, size: _ } = header.element.clone() {
-                            max_lengths.push(text.len());
-                        }
-                    }
-                    for row in rows {
-                        for (cell_index, cell) in row.cells.iter().enumerate() {
-                            if let Element::Text { text, size: _ } = cell.element.clone() {
-                                if cell_index < max_lengths.len() {
-                                    max_lengths[cell_index] =
-                                        max_lengths[cell_index].max(text.len());
-                                }
-                            }
-                        }
-                    }
-
-                    for (index, header) in headers.iter().enumerate() {
-                        if let Element::Text { text, size: _ } = header.element.clone() {
-                            let padding = max_lengths[index] - text.len();
-                            markdown.push_str("| ");
-                            markdown.push_str(text.as_str());
-                            markdown.push_str(&" ".repeat(padding));
-                            markdown.push(' ');
-                        }
-                    }
-                    markdown.push_str("|\n");
-
-                    for max_length in &max_lengths {
-                        markdown.push('|');
-                        markdown.push_str(&"-".repeat(*max_length + 2));
-                    }
-                    markdown.push_str("|\n");
-
-                    for row in rows {
-                        for (cell_index, cell) in row.cells.iter().enumerate() {
-                            if let Element::Text { text, size: _ } = cell.element.clone() {
-                                let padding = max_lengths[cell_index] - text.len();
-                                markdown.push_str("| ");
-                                markdown.push_str(text.as_str());
-                                markdown.push_str(&" ".repeat(padding));
-                                markdown.push(' ');
-                            }
-                        }
-                        markdown.push_str("|\n");
+                // The caption is a paragraph of its own below the image
+                Element::Figure { image, caption } => {
+                    generate_element(
+                        &Image(image.clone()),
+                        markdown,
+                        list_depth,
+                        list_counters,
+                        list_types,
+                        images,
+                        image_num,
+                    )?;
+                    markdown.push('\n');
+                    for child in caption {
+                        generate_element(
+                            child,
+                            markdown,
+                            list_depth,
+                            list_counters,
+                            list_types,
+                            images,
+                            image_num,
+                        )?;
                     }
                     markdown.push('\n');
+                    markdown.push('\n');
+                }
+                Element::Ruby { .. } => {
+                    generate_element(
+                        &element.ruby_fallback(),
+                        markdown,
+                        list_depth,
+                        list_counters,
+                        list_types,
+                        images,
+                        image_num,
+                    )?;
+                }
+                Element::Media { .. } => {
+                    generate_element(
+                        &element.media_fallback(),
+                        markdown,
+                        list_depth,
+                        list_counters,
+                        list_types,
+                        images,
+                        image_num,
+                    )?;
+                }
+                Element::Chart { .. } => {
+                    generate_element(
+                        &element.chart_fallback(),
+                        markdown,
+                        list_depth,
+                        list_counters,
+                        list_types,
+                        images,
+                        image_num,
+                    )?;
+                }
+                Table { headers, rows, .. } => {
+                    generate_table(markdown, headers, rows);
+                    markdown.push('\n');
                 }
             }
             Ok(())
@@ -229,6 +372,172 @@ impl TransformerTrait for Transformer {
     }
 }
 
+impl Transformer {
+    /// Generates the text with lines wrapped at spaces to at most `width` characters. Table rows
+    /// and words longer than the width are left as they are
+    pub fn generate_with_width(document: &Document, width: usize) -> anyhow::Result<Bytes> {
+        let text = String::from_utf8(Transformer::generate(document)?.to_vec())?;
+        let mut wrapped = String::with_capacity(text.len());
+        for line in text.split_inclusive('\n') {
+            let (line, newline) = match line.strip_suffix('\n') {
+                Some(line) => (line, "\n"),
+                None => (line, ""),
+            };
+            wrap_line(&mut wrapped, line, width);
+            wrapped.push_str(newline);
+        }
+        Ok(Bytes::from(wrapped))
+    }
+}
+
+/// Appends `line` broken before the words that would go past `width`, continued lines keep its
+/// indentation
+fn wrap_line(wrapped: &mut String, line: &str, width: usize) {
+    if line.chars().count() <= width || line.starts_with('|') {
+        wrapped.push_str(line);
+        return;
+    }
+    let content = line.trim_start();
+    let indent = &line[..line.len() - content.len()];
+    let mut length = 0;
+    for (index, word) in content.split(' ').enumerate() {
+        let word_length = word.chars().count();
+        if index == 0 {
+            wrapped.push_str(indent);
+            length = indent.chars().count();
+        } else if length + 1 + word_length > width {
+            wrapped.push('\n');
+            wrapped.push_str(indent);
+            length = indent.chars().count();
+        } else {
+            wrapped.push(' ');
+            length += 1;
+        }
+        wrapped.push_str(word);
+        length += word_length;
+    }
+}
+
+/// Appends a table padded to the longest text of each column.
+///
+/// Column widths are measured in a single pass without cloning cells, the output is reserved
+/// up front and padding is sliced from shared buffers, which keeps large tables cheap.
+fn generate_table(markdown: &mut String, headers: &[TableHeader], rows: &[TableRow]) {
+    /// The text of a cell on a single line, its blocks are separated by spaces
+    fn cell_text(elements: &[Element]) -> Cow<'_, str> {
+        match elements {
+            [Element::Text { text, .. }] => Cow::Borrowed(text),
+            elements => {
+                let mut text = String::new();
+                for (index, element) in elements.iter().enumerate() {
+                    if index > 0 {
+                        text.push(' ');
+                    }
+                    line_text(element, &mut text);
+                }
+                Cow::Owned(text)
+            }
+        }
+    }
+
+    fn line_text(element: &Element, text: &mut String) {
+        match element {
+            Element::Text { text: value, .. }
+            | Element::Header { text: value, .. }
+            | Element::CodeBlock { code: value, .. }
+            | Element::Math { tex: value, .. } => text.push_str(&value.replace('\n', " ")),
+            Element::Hyperlink { title, url, .. } => {
+                text.push_str(if title.is_empty() { url } else { title })
+            }
+            Element::Image(image) => text.push_str(image.alt()),
+            Element::Figure { caption, .. } => {
+                for child in caption {
+                    line_text(child, text);
+                }
+            }
+            Element::LineBreak => text.push(' '),
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Styled { elements, .. } => {
+                for child in elements {
+                    line_text(child, text);
+                }
+            }
+            Element::List { elements, .. } => {
+                for (index, item) in elements.iter().enumerate() {
+                    if index > 0 {
+                        text.push(' ');
+                    }
+                    line_text(&item.element, text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn push_cell(markdown: &mut String, text: &str, width: usize, spaces: &str) {
+        markdown.push_str("| ");
+        markdown.push_str(text);
+        markdown.push_str(&spaces[..width.saturating_sub(text.len())]);
+        markdown.push(' ');
+    }
+
+    let mut max_lengths: Vec<usize> = headers
+        .iter()
+        .map(|header| cell_text(std::slice::from_ref(&header.element)).len())
+        .collect();
+    for row in rows {
+        for (cell, max_length) in row.cells.iter().zip(max_lengths.iter_mut()) {
+            *max_length = (*max_length).max(cell_text(&cell.elements).len());
+        }
+    }
+
+    let widest = max_lengths.iter().copied().max().unwrap_or(0);
+    let spaces = " ".repeat(widest);
+    let dashes = "-".repeat(widest + 2);
+    let line_length: usize = max_lengths.iter().map(|length| length + 3).sum::<usize>() + 2;
+    markdown.reserve(line_length * (rows.len() + 2) + 1);
+
+    for (index, header) in headers.iter().enumerate() {
+        let text = cell_text(std::slice::from_ref(&header.element));
+        let width = max_lengths.get(index).copied().unwrap_or(0);
+        push_cell(markdown, &text, width, &spaces);
+    }
+    markdown.push_str("|\n");
+
+    for max_length in &max_lengths {
+        markdown.push('|');
+        markdown.push_str(&dashes[..max_length + 2]);
+    }
+    markdown.push_str("|\n");
+
+    for row in rows {
+        for (cell_index, cell) in row.cells.iter().enumerate() {
+            let text = cell_text(&cell.elements);
+            let width = max_lengths.get(cell_index).copied().unwrap_or(0);
+            push_cell(markdown, &text, width, &spaces);
+        }
+        markdown.push_str("|\n");
+    }
+}
+
+/// A paragraph of the lines, each followed by a line break
+fn parse_lines(lines: impl Iterator<Item = anyhow::Result<String>>) -> anyhow::Result<Document> {
+    let mut elements: Vec<Element> = vec![];
+    for line in lines {
+        elements.push(Element::Text {
+            text: line?,
+            font: FontSpec::from(8),
+        });
+        elements.push(Element::Text {
+            text: "\n".to_string(),
+            font: FontSpec::from(8),
+        });
+    }
+    let new_paragraph = Paragraph { elements };
+    Ok(Document::new(vec![new_paragraph]))
+}
+
 #[cfg(test)]
 mod tests {
     use log::{debug, info};
@@ -272,10 +581,12 @@ Second header
         let header = Header {
             level: 0,
             text: std::string::String::from("page header string"),
+            anchor: None,
         };
         let footer = Header {
             level: 0,
             text: std::string::String::from("page footer string"),
+            anchor: None,
         };
         footer_elements.push(footer);
         header_elements.push(header);
@@ -289,6 +600,98 @@ Second header
         info!("{}", generated_text);
         Ok(())
     }
+
+    #[test]
+    fn test_parse_reader() -> anyhow::Result<()> {
+        let document = "First line\r\nSecond line\n\nLast line";
+        let parsed = Transformer::parse_reader(document.as_bytes())?;
+        assert_eq!(parsed, Transformer::parse(&document.as_bytes().into())?);
+        assert_eq!(parsed.get_all_elements().len(), 1);
+        assert!(Transformer::parse_reader(&b"\xff\xfe"[..]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_table() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![Element::Table {
+            headers: vec![
+                TableHeader {
+                    element: text("Name"),
+                    width: 10.0,
+                },
+                TableHeader {
+                    element: text("Qty"),
+                    width: 10.0,
+                },
+            ],
+            rows: vec![
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Tea")),
+                        TableCell::new(text("1")),
+                    ],
+                },
+                TableRow {
+                    cells: vec![
+                        TableCell::new(text("Coffee beans")),
+                        TableCell::new(text("12")),
+                    ],
+                },
+            ],
+            alignments: vec![],
+            caption: vec![],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(
+            std::str::from_utf8(&generated)?,
+            "| Name         | Qty |\n\
+             |--------------|-----|\n\
+             | Tea          | 1   |\n\
+             | Coffee beans | 12  |\n\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_rich_cell() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let item = |value: &str| ListItem {
+            element: text(value),
+            checked: None,
+        };
+        let document = Document::new(vec![Element::Table {
+            headers: vec![TableHeader {
+                element: text("Notes"),
+                width: 10.0,
+            }],
+            rows: vec![TableRow {
+                cells: vec![TableCell::with_elements(vec![
+                    text("Steps"),
+                    Element::List {
+                        elements: vec![item("one"), item("two")],
+                        numbered: false,
+                    },
+                ])],
+            }],
+            alignments: vec![],
+            caption: vec![],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(
+            std::str::from_utf8(&generated)?,
+            "| Notes         |\n\
+             |---------------|\n\
+             | Steps one two |\n\n"
+        );
+        Ok(())
+    }
 }
 
 /// Process text content and automatically convert image references to Base64 format
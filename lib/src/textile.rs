@@ -0,0 +1,1081 @@
+use crate::core::*;
+use bytes::Bytes;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
+    }
+
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
+    }
+}
+
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Parses `h1.` to `h6.` headings, `*`/`#` lists, `|table|` rows, `"link":url` links,
+    /// `!image!` images and `bc.`/`pre.` code blocks. Phrase modifiers such as `*strong*` are
+    /// dropped, the text is kept. Block quotes become plain paragraphs.
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let source = String::from_utf8(document.to_vec())?;
+        let lines: Vec<&str> = source
+            .lines()
+            .map(|line| line.trim_end_matches('\r'))
+            .collect();
+        let parser = Parser {
+            image_loader: &image_loader,
+        };
+        let elements = parser.parse_blocks(&lines)?;
+        Ok(Document::new(elements))
+    }
+
+    /// Images are written through `image_saver` and embedded with `!name!`.
+    /// Page headers and footers have no Textile equivalent and are skipped.
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        // A table of contents is written as the list of its headers
+        let document = &document.expand_toc();
+        let mut generator = Generator {
+            image_saver,
+            image_num: 0,
+        };
+        let mut textile = String::new();
+        for band in &document.bands {
+            if matches!(band, Band::PageHeader(_) | Band::PageFooter(_)) {
+                continue;
+            }
+            for element in band.elements() {
+                generator.block(&mut textile, element)?;
+            }
+        }
+        Ok(Bytes::from(textile))
+    }
+}
+
+/// Phrase modifiers: strong, emphasis, code, inserted, deleted, superscript and subscript
+const PHRASE_MARKERS: [char; 7] = ['*', '_', '@', '+', '-', '^', '~'];
+
+/// Block signatures, `h1` to `h6` are handled separately
+const BLOCK_TAGS: [&str; 7] = ["p", "bq", "bc", "pre", "notextile", "###", "table"];
+
+/// Block signature of a line: the tag, whether the block is extended (`..`) and the content
+fn signature(line: &str) -> Option<(&str, bool, &str)> {
+    let end = line
+        .find(|c: char| !c.is_ascii_alphanumeric() && c != '#')
+        .unwrap_or(line.len());
+    let tag = &line[..end];
+    let heading = matches!(tag.as_bytes(), [b'h', b'1'..=b'6']);
+    if !heading && !BLOCK_TAGS.contains(&tag) {
+        return None;
+    }
+    let rest = skip_modifiers(&line[end..]);
+    let (extended, rest) = match rest.strip_prefix("..") {
+        Some(rest) => (true, rest),
+        None => (false, rest.strip_prefix('.')?),
+    };
+    if !rest.is_empty() && !rest.starts_with(' ') {
+        return None;
+    }
+    Some((tag, extended, rest.trim_start()))
+}
+
+/// Skips `(class)`, `{style}`, `[lang]` and alignment modifiers
+fn skip_modifiers(text: &str) -> &str {
+    let mut rest = text;
+    loop {
+        let closing = match rest.chars().next() {
+            Some('(') => ')',
+            Some('{') => '}',
+            Some('[') => ']',
+            Some('<' | '>' | '=' | '^' | '~') => {
+                rest = &rest[1..];
+                continue;
+            }
+            _ => return rest,
+        };
+        match rest.find(closing) {
+            Some(end) => rest = &rest[end + 1..],
+            None => return rest,
+        }
+    }
+}
+
+fn is_list_line(line: &str) -> bool {
+    let prefix = list_prefix(line);
+    !prefix.is_empty() && line[prefix.len()..].starts_with(' ') && signature(line).is_none()
+}
+
+fn list_prefix(line: &str) -> &str {
+    let end = line.find(|c| c != '*' && c != '#').unwrap_or(line.len());
+    &line[..end]
+}
+
+fn is_table_line(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with('|') && line.len() > 1
+}
+
+/// Drops runs of phrase markers that open or close a word
+fn strip_phrases(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result = String::with_capacity(text.len());
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        let end = chars[index..]
+            .iter()
+            .position(|next| *next != c)
+            .map_or(chars.len(), |length| index + length);
+        if PHRASE_MARKERS.contains(&c) && end - index <= 2 {
+            let before = index.checked_sub(1).map(|index| chars[index]);
+            let after = chars.get(end).copied();
+            let open = before.is_none_or(|c| c.is_whitespace() || "([{'\"".contains(c))
+                && after.is_some_and(|c| !c.is_whitespace());
+            let close = before.is_some_and(|c| !c.is_whitespace())
+                && after.is_none_or(|c| c.is_whitespace() || ".,;:!?)]}'\"".contains(c));
+            let run: String = chars[index..end].iter().collect();
+            // A closing run needs a matching opening one on the same line
+            let matched = if open {
+                chars[end..].iter().collect::<String>().contains(&run)
+            } else {
+                close && chars[..index].iter().collect::<String>().contains(&run)
+            };
+            if matched {
+                index = end;
+                continue;
+            }
+        }
+        result.extend(&chars[index..end]);
+        index = end;
+    }
+    result
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&amp;", "&")
+}
+
+fn push_text(elements: &mut Vec<Element>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(Element::Text { text: last, .. }) = elements.last_mut() {
+        last.push_str(text);
+    } else {
+        elements.push(Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        });
+    }
+}
+
+/// A single element stays as it is, several are wrapped in a paragraph
+fn single(mut elements: Vec<Element>) -> Element {
+    match elements.len() {
+        0 => Element::Text {
+            text: String::new(),
+            font: FontSpec::from(8),
+        },
+        1 => elements.remove(0),
+        _ => Element::Paragraph { elements },
+    }
+}
+
+/// Image type of an image address, unknown extensions fall back to PNG
+fn image_type(source: &str) -> &'static str {
+    let path = source.split(['?', '#']).next().unwrap_or_default();
+    let extension = path.rsplit('.').next().unwrap_or_default().to_lowercase();
+    match extension.as_str() {
+        "jpg" | "jpeg" => "jpeg",
+        "gif" => "gif",
+        "svg" => "svg",
+        _ => "png",
+    }
+}
+
+/// `width:200px; height:100px` style declarations
+fn style_size(style: &str) -> ImageDimension {
+    let mut size = ImageDimension::default();
+    for declaration in style.split(';') {
+        if let Some((property, value)) = declaration.split_once(':') {
+            match property.trim() {
+                "width" => size.width = Some(value.trim().to_string()),
+                "height" => size.height = Some(value.trim().to_string()),
+                _ => {}
+            }
+        }
+    }
+    size
+}
+
+struct Parser<'a, F> {
+    image_loader: &'a F,
+}
+
+impl<'a, F> Parser<'a, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    fn parse_blocks(&self, lines: &[&str]) -> anyhow::Result<Vec<Element>> {
+        let mut elements = Vec::new();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = lines[index];
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                index += 1;
+                continue;
+            }
+
+            if trimmed.starts_with("<pre") || trimmed.starts_with("<notextile>") {
+                let tag = if trimmed.starts_with("<pre") {
+                    "pre"
+                } else {
+                    "notextile"
+                };
+                let closing = format!("</{}>", tag);
+                let end = lines[index..]
+                    .iter()
+                    .position(|line| line.contains(&closing))
+                    .map_or(lines.len() - 1, |end| index + end);
+                let text = lines[index..=end].join("\n");
+                let text = text.split_once('>').map_or("", |(_, rest)| rest);
+                let text = text.split(&closing).next().unwrap_or_default();
+                let text = text.strip_prefix('\n').unwrap_or(text);
+                let text = text.strip_suffix('\n').unwrap_or(text);
+                let text = text.strip_prefix("<code>").unwrap_or(text);
+                let text = text.strip_suffix("</code>").unwrap_or(text);
+                elements.push(Element::Text {
+                    text: unescape(text),
+                    font: FontSpec::from(8),
+                });
+                index = end + 1;
+                continue;
+            }
+
+            if let Some((tag, extended, content)) = signature(line) {
+                // Extended blocks run up to the next block signature, others to a blank line
+                let end = if extended {
+                    (index + 1..lines.len())
+                        .find(|next| {
+                            lines[*next - 1].trim().is_empty() && signature(lines[*next]).is_some()
+                        })
+                        .unwrap_or(lines.len())
+                } else {
+                    lines[index..]
+                        .iter()
+                        .position(|line| line.trim().is_empty())
+                        .map_or(lines.len(), |end| index + end)
+                };
+                let mut block = vec![content];
+                block.extend(&lines[index + 1..end]);
+                while block.last().is_some_and(|line| line.trim().is_empty()) {
+                    block.pop();
+                }
+                self.parse_signature_block(tag, &block, &mut elements)?;
+                index = end;
+                continue;
+            }
+
+            if is_table_line(line) {
+                let end = lines[index..]
+                    .iter()
+                    .position(|line| !is_table_line(line))
+                    .map_or(lines.len(), |end| index + end);
+                elements.push(self.parse_table(&lines[index..end])?);
+                index = end;
+                continue;
+            }
+
+            if is_list_line(line) {
+                let end = lines[index..]
+                    .iter()
+                    .position(|next| {
+                        !is_list_line(next) || next.as_bytes()[0] != line.as_bytes()[0]
+                    })
+                    .map_or(lines.len(), |end| index + end);
+                let items: Vec<(&str, &str)> = lines[index..end]
+                    .iter()
+                    .map(|line| {
+                        let prefix = list_prefix(line);
+                        (prefix, line[prefix.len()..].trim())
+                    })
+                    .collect();
+                let mut position = 0;
+                elements.push(self.parse_list(&items, &mut position, 1)?);
+                index = end;
+                continue;
+            }
+
+            let end = lines[index..]
+                .iter()
+                .position(|line| line.trim().is_empty())
+                .map_or(lines.len(), |end| index + end);
+            self.parse_paragraph(&lines[index..end], &mut elements)?;
+            index = end;
+        }
+        Ok(elements)
+    }
+
+    fn parse_signature_block(
+        &self,
+        tag: &str,
+        lines: &[&str],
+        elements: &mut Vec<Element>,
+    ) -> anyhow::Result<()> {
+        match tag {
+            "bc" | "pre" | "notextile" => elements.push(Element::Text {
+                text: lines.join("\n"),
+                font: FontSpec::from(8),
+            }),
+            "###" => elements.push(Element::Comment {
+                text: lines.join("\n").trim().to_string(),
+                author: None,
+                date: None,
+                range: None,
+            }),
+            // The table signature only carries attributes of the rows that follow
+            "table" => {}
+            "p" | "bq" => {
+                for paragraph in lines.split(|line| line.trim().is_empty()) {
+                    self.parse_paragraph(paragraph, elements)?;
+                }
+            }
+            heading => {
+                let mut inline = Vec::new();
+                self.parse_inline(&lines.join(" "), &mut inline)?;
+                let text = inline
+                    .iter()
+                    .map(|element| match element {
+                        Element::Text { text, .. } => text.as_str(),
+                        Element::Hyperlink { title, .. } => title.as_str(),
+                        _ => "",
+                    })
+                    .collect::<String>();
+                elements.push(Element::Header {
+                    level: heading.as_bytes()[1] - b'0',
+                    text: text.trim().to_string(),
+                    anchor: None,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Line breaks inside a paragraph are kept
+    fn parse_paragraph(&self, lines: &[&str], elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        let mut paragraph = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            if index > 0 {
+                paragraph.push(Element::LineBreak);
+            }
+            self.parse_inline(line.trim(), &mut paragraph)?;
+        }
+        match paragraph.as_slice() {
+            [] => {}
+            // A paragraph with only an image is a standalone figure
+            [Element::Image(_)] => elements.append(&mut paragraph),
+            _ => elements.push(Element::Paragraph {
+                elements: paragraph,
+            }),
+        }
+        Ok(())
+    }
+
+    /// Items deeper than `depth` become nested lists following their parent item
+    fn parse_list(
+        &self,
+        items: &[(&str, &str)],
+        position: &mut usize,
+        depth: usize,
+    ) -> anyhow::Result<Element> {
+        let numbered = items[*position].0[..depth].ends_with('#');
+        let mut elements = Vec::new();
+        while *position < items.len() {
+            let (prefix, text) = items[*position];
+            if prefix.len() < depth {
+                break;
+            }
+            if prefix.len() > depth {
+                elements.push(ListItem {
+                    element: self.parse_list(items, position, depth + 1)?,
+                    checked: None,
+                });
+                continue;
+            }
+            // The same depth with another marker starts a new list
+            if prefix.ends_with('#') != numbered && !elements.is_empty() {
+                break;
+            }
+            let mut inline = Vec::new();
+            self.parse_inline(text, &mut inline)?;
+            elements.push(ListItem {
+                element: single(inline),
+                checked: None,
+            });
+            *position += 1;
+        }
+        Ok(Element::List { elements, numbered })
+    }
+
+    /// `|_. Header|` cells in the first row are the header
+    fn parse_table(&self, lines: &[&str]) -> anyhow::Result<Element> {
+        let mut headers = Vec::new();
+        let mut rows = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            let line = line.trim();
+            // Row modifiers come before the first cell, as in `(odd). |a|b|`
+            let line = line.strip_suffix('|').unwrap_or(line);
+            let mut cells = Vec::new();
+            let mut header_row = true;
+            for cell in line.split('|').skip(1) {
+                let (is_header, content) = match cell.strip_prefix("_.") {
+                    Some(content) => (true, content),
+                    None => {
+                        let rest = skip_modifiers(cell);
+                        match rest.strip_prefix(". ") {
+                            Some(content) if rest.len() < cell.len() => (false, content),
+                            _ => (false, cell),
+                        }
+                    }
+                };
+                header_row &= is_header;
+                let mut inline = Vec::new();
+                self.parse_inline(content.trim(), &mut inline)?;
+                cells.push(single(inline));
+            }
+            if index == 0 && header_row && !cells.is_empty() {
+                headers = cells
+                    .into_iter()
+                    .map(|element| TableHeader {
+                        element,
+                        width: 30.0,
+                    })
+                    .collect();
+            } else {
+                rows.push(TableRow {
+                    cells: cells.into_iter().map(TableCell::new).collect(),
+                });
+            }
+        }
+        Ok(Element::Table {
+            headers,
+            rows,
+            alignments: vec![],
+            caption: vec![],
+        })
+    }
+
+    /// Splits a line into text, `"title":url` links, `!image!` images and `==raw==` text
+    fn parse_inline(&self, line: &str, elements: &mut Vec<Element>) -> anyhow::Result<()> {
+        let mut rest = line;
+        let mut text = String::new();
+        while !rest.is_empty() {
+            let Some(start) = rest.find(['"', '!', '=']) else {
+                text.push_str(rest);
+                break;
+            };
+            text.push_str(&rest[..start]);
+            let after = &rest[start..];
+            let consumed = if let Some(raw) = after.strip_prefix("==") {
+                match raw.find("==") {
+                    Some(end) => {
+                        push_text(elements, &strip_phrases(&std::mem::take(&mut text)));
+                        push_text(elements, &raw[..end]);
+                        end + 4
+                    }
+                    None => 0,
+                }
+            } else if after.starts_with('"') {
+                self.parse_link(after, &mut text, elements)
+            } else if after.starts_with('!') {
+                self.parse_image(after, &mut text, elements)?
+            } else {
+                0
+            };
+            if consumed == 0 {
+                let length = after.chars().next().map_or(1, char::len_utf8);
+                text.push_str(&after[..length]);
+                rest = &after[length..];
+            } else {
+                rest = &after[consumed..];
+            }
+        }
+        push_text(elements, &strip_phrases(&text));
+        Ok(())
+    }
+
+    /// `"title(tooltip)":url`, returns the consumed length or 0 when it is not a link
+    fn parse_link(&self, text: &str, pending: &mut String, elements: &mut Vec<Element>) -> usize {
+        let Some(end) = text[1..].find("\":").map(|end| end + 1) else {
+            return 0;
+        };
+        let url_start = end + 2;
+        let url_end = text[url_start..]
+            .find(char::is_whitespace)
+            .map_or(text.len(), |end| url_start + end);
+        let url = text[url_start..url_end].trim_end_matches(['.', ',', ';', ':', '!', '?', ')']);
+        if url.is_empty() || text[1..end].is_empty() {
+            return 0;
+        }
+        let mut title = &text[1..end];
+        let mut alt = String::new();
+        if let Some(open) = title.rfind('(').filter(|_| title.ends_with(')')) {
+            alt = title[open + 1..title.len() - 1].to_string();
+            title = title[..open].trim_end();
+        }
+        push_text(elements, &strip_phrases(&std::mem::take(pending)));
+        elements.push(Element::Hyperlink {
+            title: strip_phrases(title),
+            url: url.to_string(),
+            alt,
+            size: 8,
+        });
+        url_start + url.len()
+    }
+
+    /// `!<{width:200px}image.png(alt)!:link`, returns the consumed length or 0 when it is not
+    /// an image
+    fn parse_image(
+        &self,
+        text: &str,
+        pending: &mut String,
+        elements: &mut Vec<Element>,
+    ) -> anyhow::Result<usize> {
+        let Some(end) = text[1..].find('!').map(|end| end + 1) else {
+            return Ok(0);
+        };
+        let mut content = &text[1..end];
+        let mut align = "";
+        let mut size = ImageDimension::default();
+        loop {
+            if let Some(rest) = content.strip_prefix('<') {
+                align = "left";
+                content = rest;
+            } else if let Some(rest) = content.strip_prefix('>') {
+                align = "right";
+                content = rest;
+            } else if let Some(rest) = content.strip_prefix('=') {
+                align = "center";
+                content = rest;
+            } else if let Some(rest) = content.strip_prefix('{') {
+                let Some(close) = rest.find('}') else {
+                    return Ok(0);
+                };
+                size = style_size(&rest[..close]);
+                content = &rest[close + 1..];
+            } else if let Some(rest) = content.strip_prefix('(') {
+                let Some(close) = rest.find(')') else {
+                    return Ok(0);
+                };
+                content = &rest[close + 1..];
+            } else {
+                break;
+            }
+        }
+        let (source, alt) = match content.split_once('(') {
+            Some((source, alt)) => (source, alt.trim_end_matches(')')),
+            None => (content, ""),
+        };
+        let source = source.trim();
+        if source.is_empty() || source.contains(char::is_whitespace) {
+            return Ok(0);
+        }
+        let mut consumed = end + 1;
+        // `!image!:url` links the image, the link has no element of its own
+        if text[consumed..].starts_with(':') {
+            consumed += text[consumed..]
+                .find(char::is_whitespace)
+                .unwrap_or(text.len() - consumed);
+        }
+
+        push_text(elements, &strip_phrases(&std::mem::take(pending)));
+        let bytes = (self.image_loader)(source)?;
+        elements.push(Element::Image(ImageData::new(
+            bytes,
+            alt.to_string(),
+            alt.to_string(),
+            image_type(source).to_string(),
+            align.to_string(),
+            size,
+        )));
+        Ok(consumed)
+    }
+}
+
+/// Wraps text in `==` when it would otherwise be read as markup
+fn escape(text: &str) -> String {
+    let markup = strip_phrases(text) != text
+        || text.matches('!').count() > 1
+        || text.contains("\":")
+        || text.contains('|');
+    if markup && !text.contains("==") {
+        format!("=={}==", text)
+    } else {
+        text.to_string()
+    }
+}
+
+struct Generator<F> {
+    image_saver: F,
+    image_num: usize,
+}
+
+impl<F> Generator<F>
+where
+    F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+{
+    fn block(&mut self, textile: &mut String, element: &Element) -> anyhow::Result<()> {
+        match element {
+            Element::Header { level, text, .. } => {
+                textile.push_str(&format!(
+                    "h{}. {}\n\n",
+                    (*level).clamp(1, 6),
+                    escape(&text.replace('\n', " "))
+                ));
+            }
+            // Multi-line text keeps its layout, blank lines included
+            Element::Text { text, .. } if text.contains('\n') => {
+                textile.push_str("<pre>\n");
+                textile.push_str(
+                    &text
+                        .replace('&', "&amp;")
+                        .replace('<', "&lt;")
+                        .replace('>', "&gt;"),
+                );
+                textile.push_str("\n</pre>\n\n");
+            }
+            Element::List { elements, numbered } => {
+                let marker = if *numbered { "#" } else { "*" };
+                self.list(textile, elements, marker)?;
+                textile.push('\n');
+            }
+            Element::Chart { .. } => self.block(textile, &element.chart_fallback())?,
+            Element::Table { headers, rows, .. } => {
+                self.table(textile, headers, rows)?;
+                textile.push('\n');
+            }
+            // `- term := definition` lines, a definition is a single line
+            Element::DefinitionList { items } => {
+                for item in items {
+                    let mut term = String::new();
+                    for child in &item.term {
+                        term.push_str(&self.inline(child)?);
+                    }
+                    let mut definitions = Vec::new();
+                    for definition in &item.definitions {
+                        definitions.push(self.inline(definition)?.replace('\n', " "));
+                    }
+                    textile.push_str(&format!(
+                        "- {} := {}\n",
+                        term.replace('\n', " ").trim(),
+                        definitions.join(" ").trim()
+                    ));
+                }
+                textile.push('\n');
+            }
+            Element::Comment { text, .. } => {
+                textile.push_str(&format!("###. {}\n\n", text.replace("\n\n", "\n")));
+            }
+            // `bq..` would run on into the following paragraphs, each paragraph is quoted
+            Element::Blockquote { elements, .. } => {
+                for child in elements {
+                    match child {
+                        Element::Paragraph { .. } | Element::Text { .. } => {
+                            let text = self.inline(child)?;
+                            if !text.trim().is_empty() {
+                                textile.push_str(&format!("bq. {}\n\n", text.trim()));
+                            }
+                        }
+                        child => self.block(textile, child)?,
+                    }
+                }
+            }
+            Element::Section { children, .. } => {
+                for child in children {
+                    self.block(textile, child)?;
+                }
+            }
+            // Textile has no rule of its own, HTML passes through
+            Element::HorizontalRule => textile.push_str("<hr />\n\n"),
+            // Textile has no pages
+            Element::PageBreak | Element::TableOfContents { .. } => {}
+            // `bc.` would end at the first blank line of the code
+            Element::CodeBlock { language, code } => {
+                match language {
+                    Some(language) => textile.push_str(&format!(
+                        "<pre><code class=\"language-{}\">",
+                        language.replace('"', "")
+                    )),
+                    None => textile.push_str("<pre><code>"),
+                }
+                textile.push_str(
+                    &code
+                        .replace('&', "&amp;")
+                        .replace('<', "&lt;")
+                        .replace('>', "&gt;"),
+                );
+                textile.push_str("</code></pre>\n\n");
+            }
+            element => {
+                let text = self.inline(element)?;
+                let text = text.trim();
+                if !text.is_empty() {
+                    // A paragraph starting like a list, a table or a block needs its signature
+                    if signature(text).is_some() || is_list_line(text) || is_table_line(text) {
+                        textile.push_str("p. ");
+                    }
+                    textile.push_str(text);
+                    textile.push_str("\n\n");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn list(
+        &mut self,
+        textile: &mut String,
+        items: &[ListItem],
+        prefix: &str,
+    ) -> anyhow::Result<()> {
+        for item in items {
+            match &item.element {
+                // Nested lists extend the prefix of the parent item
+                Element::List { elements, numbered } => {
+                    let marker = if *numbered { "#" } else { "*" };
+                    self.list(textile, elements, &format!("{}{}", prefix, marker))?
+                }
+                element => {
+                    let text = self.inline(element)?.replace('\n', " ");
+                    textile.push_str(&format!("{} {}\n", prefix, text.trim()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn table(
+        &mut self,
+        textile: &mut String,
+        headers: &[TableHeader],
+        rows: &[TableRow],
+    ) -> anyhow::Result<()> {
+        if !headers.is_empty() {
+            textile.push('|');
+            for header in headers {
+                let text = self.inline(&header.element)?.replace('\n', " ");
+                textile.push_str(&format!("_. {}|", text.trim()));
+            }
+            textile.push('\n');
+        }
+        for row in rows {
+            textile.push('|');
+            for cell in &row.cells {
+                let text = self.inline(&cell.element())?.replace('\n', " ");
+                textile.push_str(&format!("{}|", text.trim()));
+            }
+            textile.push('\n');
+        }
+        Ok(())
+    }
+
+    fn inline(&mut self, element: &Element) -> anyhow::Result<String> {
+        Ok(match element {
+            Element::Text { text, .. } | Element::Header { text, .. } => text
+                .split('\n')
+                .map(escape)
+                .collect::<Vec<String>>()
+                .join("\n"),
+            Element::Paragraph { elements }
+            | Element::Blockquote { elements, .. }
+            | Element::Section {
+                children: elements, ..
+            } => {
+                let mut text = String::new();
+                for child in elements {
+                    text.push_str(&self.inline(child)?);
+                }
+                text
+            }
+            Element::Hyperlink {
+                title, url, alt, ..
+            } => {
+                let title = if title.is_empty() { url } else { title };
+                let title = title.replace('"', "'");
+                if alt.is_empty() {
+                    format!("\"{}\":{}", title, url)
+                } else {
+                    format!(
+                        "\"{}({})\":{}",
+                        title,
+                        alt.replace(['(', ')', '"'], ""),
+                        url
+                    )
+                }
+            }
+            Element::Image(image) => self.image(image)?.unwrap_or_default(),
+            // The caption is an emphasized line below the image
+            Element::Figure { image, caption } => {
+                let mut text = String::new();
+                for child in caption {
+                    text.push_str(&self.inline(child)?);
+                }
+                let image = self.image(image)?.unwrap_or_default();
+                match (image.is_empty(), text.trim().is_empty()) {
+                    (_, true) => image,
+                    (true, false) => format!("_{}_", text.trim()),
+                    (false, false) => format!("{}\n_{}_", image, text.trim()),
+                }
+            }
+            Element::LineBreak | Element::HorizontalRule => "\n".to_string(),
+            Element::List { elements, .. } => {
+                let mut items = Vec::new();
+                for item in elements {
+                    items.push(self.inline(&item.element)?);
+                }
+                items.join(" ")
+            }
+            Element::DefinitionList { items } => {
+                let mut texts = Vec::new();
+                for child in items
+                    .iter()
+                    .flat_map(|item| item.term.iter().chain(&item.definitions))
+                {
+                    texts.push(self.inline(child)?);
+                }
+                texts.join(" ")
+            }
+            Element::CodeBlock { code, .. } => format!("@{}@", code.replace('\n', " ")),
+            Element::Styled { style, elements } => {
+                let mut text = String::new();
+                for child in elements {
+                    text.push_str(&self.inline(child)?);
+                }
+                for (enabled, mark) in [
+                    (style.strikethrough, "-"),
+                    (style.underline, "+"),
+                    (style.italic, "_"),
+                    (style.bold, "*"),
+                ] {
+                    if enabled {
+                        text = format!("{}{}{}", mark, text, mark);
+                    }
+                }
+                text
+            }
+            // Textile has no formulas, the TeX source is set as code
+            Element::Math { tex, .. } => format!("@{}@", tex.replace('\n', " ")),
+            Element::FootnoteReference { id } => format!("[{}]", id),
+            Element::Footnote { id, content } => {
+                let mut text = format!("fn{}. ", id);
+                for child in content {
+                    text.push_str(&self.inline(child)?);
+                }
+                text
+            }
+            Element::Media { .. } => self.inline(&element.media_fallback())?,
+            Element::Ruby { .. } => self.inline(&element.ruby_fallback())?,
+            Element::Table { .. }
+            | Element::Chart { .. }
+            | Element::Comment { .. }
+            | Element::PageBreak
+            | Element::TableOfContents { .. }
+            | Element::Custom { .. } => String::new(),
+        })
+    }
+
+    /// Saves the image and returns the `!name(alt)!` reference to it
+    fn image(&mut self, image: &ImageData) -> anyhow::Result<Option<String>> {
+        if image.bytes().is_empty() {
+            return Ok(None);
+        }
+        let target = format!(
+            "image{}{}",
+            self.image_num,
+            image.image_type().to_extension()
+        );
+        (self.image_saver)(image.bytes(), &target)?;
+        self.image_num += 1;
+
+        let mut tag = String::from("!");
+        match image.align() {
+            ImageAlignment::Left => tag.push('<'),
+            ImageAlignment::Right => tag.push('>'),
+            ImageAlignment::Center => tag.push('='),
+            ImageAlignment::None => {}
+        }
+        let size = image.size();
+        let style: Vec<String> = [("width", &size.width), ("height", &size.height)]
+            .iter()
+            .filter_map(|(property, value)| {
+                value
+                    .as_deref()
+                    .map(|value| format!("{}:{}", property, value.replace([';', '}'], "")))
+            })
+            .collect();
+        if !style.is_empty() {
+            tag.push_str(&format!("{{{}}}", style.join(";")));
+        }
+        tag.push_str(&target);
+        let alt = if image.alt().is_empty() {
+            image.title()
+        } else {
+            image.alt()
+        };
+        if !alt.is_empty() {
+            tag.push_str(&format!("({})", alt.replace(['(', ')', '!'], "")));
+        }
+        tag.push('!');
+        Ok(Some(tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::textile::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let source = r#"h1(#intro). Getting *started*
+
+Read the "wiki(Project wiki)":https://example.com/wiki, then run @make@.
+Well-known _issues_ are ==*not* markup==.
+
+* One
+** Nested
+* Two
+
+# First
+
+|_. Name|_. Value|
+|a|{color:red}. 1|
+
+!>{width:200px}logo.jpg(The logo)!
+
+bc. cargo build
+
+###. Internal note
+"#;
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |_| {
+            Ok(Bytes::from_static(b"\xff\xd8\xff"))
+        })?;
+        let elements = document.get_all_elements();
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        assert_eq!(
+            elements[0],
+            &Element::Header {
+                level: 1,
+                text: "Getting started".to_string(),
+                anchor: None,
+            }
+        );
+        assert_eq!(
+            elements[1],
+            &Element::Paragraph {
+                elements: vec![
+                    text("Read the "),
+                    Element::Hyperlink {
+                        title: "wiki".to_string(),
+                        url: "https://example.com/wiki".to_string(),
+                        alt: "Project wiki".to_string(),
+                        size: 8,
+                    },
+                    text(", then run make."),
+                    Element::LineBreak,
+                    text("Well-known issues are *not* markup."),
+                ]
+            }
+        );
+        assert_eq!(
+            elements[2],
+            &Element::List {
+                elements: vec![
+                    ListItem {
+                        element: text("One"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: Element::List {
+                            elements: vec![ListItem {
+                                element: text("Nested"),
+                                checked: None,
+                            }],
+                            numbered: false,
+                        },
+                        checked: None,
+                    },
+                    ListItem {
+                        element: text("Two"),
+                        checked: None,
+                    },
+                ],
+                numbered: false,
+            }
+        );
+        assert!(matches!(elements[3], Element::List { numbered: true, .. }));
+        let Element::Table { headers, rows, .. } = elements[4] else {
+            panic!("expected a table, got {:?}", elements[4]);
+        };
+        assert_eq!(headers[1].element, text("Value"));
+        assert_eq!(rows[0].cells[1].elements, vec![text("1")]);
+        let Element::Image(image) = elements[5] else {
+            panic!("expected an image, got {:?}", elements[5]);
+        };
+        assert_eq!(image.alt(), "The logo");
+        assert_eq!(image.image_type(), &ImageType::Jpeg);
+        assert_eq!(image.align(), &ImageAlignment::Right);
+        assert_eq!(image.size().width.as_deref(), Some("200px"));
+        assert_eq!(elements[6], &text("cargo build"));
+        assert_eq!(
+            elements[7],
+            &Element::Comment {
+                text: "Internal note".to_string(),
+                author: None,
+                date: None,
+                range: None,
+            }
+        );
+        assert_eq!(elements.len(), 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let document = std::fs::read("test/data/document.md")?;
+        let parsed = crate::markdown::Transformer::parse_with_loader(
+            &Bytes::from(document),
+            disk_image_loader("test/data"),
+        )?;
+        let images = std::cell::RefCell::new(std::collections::HashMap::new());
+        let generated = Transformer::generate_with_saver(&parsed, |bytes, name| {
+            images.borrow_mut().insert(name.to_string(), bytes.clone());
+            Ok(())
+        })?;
+        let reparsed = Transformer::parse_with_loader(&generated, |name| {
+            Ok(images.borrow().get(name).cloned().unwrap_or_default())
+        })?;
+        let original = parsed.get_all_elements();
+        let elements = reparsed.get_all_elements();
+        assert_eq!(original.len(), elements.len());
+        for (original, element) in original.iter().zip(elements.iter()) {
+            assert_eq!(
+                std::mem::discriminant(*original),
+                std::mem::discriminant(*element)
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,72 @@
+use crate::core::{Document, TransformerTrait};
+use crate::csv::{generate_dialect, parse_dialect, parse_dialect_reader, TSV};
+use bytes::Bytes;
+use std::io::Read;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    /// Parses tab-separated values like CSV, the first line holds the table headers.
+    /// Quotes have no special meaning.
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        parse_dialect(document, &TSV)
+    }
+
+    /// Tabs and line breaks inside cells are replaced by spaces, TSV has no quoting.
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        generate_dialect(document, &TSV)
+    }
+
+    fn parse_reader(reader: impl Read) -> anyhow::Result<Document> {
+        parse_dialect_reader(reader, &TSV)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::*;
+    use crate::tsv::*;
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let document = "Name\tQuote\nJohn \"Doe\"\tsay \"hi\", then leave\n";
+        let parsed = Transformer::parse(&Bytes::from(document))?;
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let Element::Table { headers, rows, .. } = parsed.get_all_elements()[0] else {
+            panic!("expected a table");
+        };
+        assert_eq!(headers[1].element, text("Quote"));
+        assert_eq!(rows[0].cells[0].elements, vec![text("John \"Doe\"")]);
+        assert_eq!(
+            rows[0].cells[1].elements,
+            vec![text("say \"hi\", then leave")]
+        );
+        assert_eq!(Transformer::generate(&parsed)?, Bytes::from(document));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_without_quoting() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![Element::Table {
+            headers: vec![TableHeader {
+                element: text("Notes"),
+                width: 10.0,
+            }],
+            rows: vec![TableRow {
+                cells: vec![TableCell::new(text("tab\there,\nnew \"line\""))],
+            }],
+            alignments: vec![],
+            caption: vec![],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        assert_eq!(generated, Bytes::from("Notes\ntab here, new \"line\"\n"));
+        Ok(())
+    }
+}
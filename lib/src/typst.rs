@@ -1,6 +1,17 @@
-use crate::core::Element::{Header, Hyperlink, Image, List, Paragraph, Table, Text};
+use crate::core::Element::{
+    Blockquote, Chart, CodeBlock, Comment, Custom, DefinitionList, Figure, Footnote,
+    FootnoteReference, Header, HorizontalRule, Hyperlink, Image, LineBreak, List, Math, Media,
+    PageBreak, Paragraph, Ruby, Section, Styled, Table, TableOfContents, Text,
+};
 
-use crate::core::{Document, Element, ListItem, TableHeader, TableRow, TransformerTrait};
+use crate::core::{
+    disk_image_loader, disk_image_saver, CancellationToken, ColumnAlignment, DefinitionItem,
+    Document, Element, FontSpec, GeneratorError, HighlightColor, ImageData, ImageDimension,
+    ListItem, Metadata, PageFormat, PageOrientation, ParserError, TableCell, TableHeader,
+    TableRow, TextDirection, TextStyle, TransformerTrait, TransformerWithImageLoaderSaverTrait,
+};
+pub use crate::core::Margins;
+use crate::parse_util::unwrap_paragraph;
 use anyhow;
 use bytes::Bytes;
 use comemo::Prehashed;
@@ -11,14 +22,52 @@ use time::{OffsetDateTime, UtcOffset};
 
 use typst::{
     diag::{FileError, FileResult},
+    eval::Tracer,
     foundations::Datetime,
-    syntax::{FileId, Source},
+    syntax::{
+        ast::{self, AstNode},
+        FileId, Source,
+    },
     text::{Font, FontBook},
     Library, World,
 };
 
 type TypstString = String;
 
+impl Margins {
+    fn to_typst(&self) -> TypstString {
+        let Margins {
+            top,
+            bottom,
+            left,
+            right,
+            mirror,
+        } = self;
+        if *mirror {
+            format!("(top: {top}mm, bottom: {bottom}mm, inside: {left}mm, outside: {right}mm)")
+        } else {
+            format!("(top: {top}mm, bottom: {bottom}mm, left: {left}mm, right: {right}mm)")
+        }
+    }
+}
+
+/// Options for PDF (typst) generation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PdfOptions {
+    /// Page margins. `None` keeps typst's default margins.
+    pub margins: Option<Margins>,
+    /// Typst preamble placed before the page setup and the document body, its `#set` rules
+    /// apply unless the document sets the same property.
+    pub template: Option<String>,
+    /// Function of the preamble the body is passed to through `#show`, like `template` for
+    /// `#let template(body) = ...`. `None` leaves the body as it is.
+    pub template_function: Option<String>,
+    /// Checked before each element is converted.
+    pub cancellation: Option<CancellationToken>,
+    /// Review comments are set as notes in the right margin, otherwise they are left out.
+    pub comments: bool,
+}
+
 pub struct ShivaWorld {
     fonts: Vec<Font>,
     book: Prehashed<FontBook>,
@@ -180,26 +229,123 @@ impl World for ShivaWorld {
 pub struct Transformer;
 
 impl TransformerTrait for Transformer {
-    #[allow(unused)]
-    fn parse(document: &bytes::Bytes) -> anyhow::Result<Document> {
-        todo!()
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Transformer::parse_with_loader(document, disk_image_loader("."))
     }
 
-    fn generate(document: &Document) -> anyhow::Result<bytes::Bytes> {
-        let (text, _) = generate_document(document)?;
-        let bytes = Bytes::from(text);
-        Ok(bytes)
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Transformer::generate_with_saver(document, disk_image_saver("."))
     }
 }
 
+impl TransformerWithImageLoaderSaverTrait for Transformer {
+    /// Parses headings, paragraphs, lists, tables, links, images and raw blocks. Strong,
+    /// emphasized and inline raw text keep only their text, scripting and math are skipped.
+    /// The `header` and `footer` of a `#set page` rule become the page header and footer,
+    /// the `dir` of a `#set text` rule the direction of the document.
+    /// Footnotes are numbered in order, their contents follow the body.
+    fn parse_with_loader<F>(document: &Bytes, image_loader: F) -> anyhow::Result<Document>
+    where
+        F: Fn(&str) -> anyhow::Result<Bytes>,
+    {
+        let root = typst::syntax::parse(std::str::from_utf8(document)?);
+        if let Some(error) = root.errors().first() {
+            return Err(ParserError::Malformed(format!("Typst: {}", error.message)).into());
+        }
+        let markup: ast::Markup = root
+            .cast()
+            .ok_or_else(|| ParserError::Malformed("Typst: expected markup".to_string()))?;
+        let mut parser = Parser {
+            image_loader: &image_loader,
+            page_header: Vec::new(),
+            page_footer: Vec::new(),
+            direction: TextDirection::default(),
+            metadata: Metadata::default(),
+            footnotes: Vec::new(),
+        };
+        let mut elements = parser.blocks(markup)?;
+        elements.append(&mut parser.footnotes);
+        let mut parsed = Document::new_with_dimensions(
+            parser.page_header,
+            elements,
+            parser.page_footer,
+            PageFormat::default(),
+        );
+        parsed.direction = parser.direction;
+        parsed.metadata = parser.metadata;
+        Ok(parsed)
+    }
+
+    /// Writes the source the PDF output is compiled from, so the layout can be tuned by hand
+    /// before compiling it with typst. Images are written through `image_saver` as
+    /// `image<N>.<ext>` and referenced by that name.
+    fn generate_with_saver<F>(document: &Document, image_saver: F) -> anyhow::Result<Bytes>
+    where
+        F: Fn(&Bytes, &str) -> anyhow::Result<()>,
+    {
+        let (text, img_map) = generate_document(document)?;
+        for (name, bytes) in &img_map {
+            image_saver(&Bytes::copy_from_slice(bytes), name)?;
+        }
+        Ok(Bytes::from(text))
+    }
+}
+
+/// Compiles the document into laid out typst pages, shared by the PDF and SVG outputs
+pub fn compile_document(
+    document: &Document,
+    options: &PdfOptions,
+) -> anyhow::Result<typst::model::Document> {
+    let (text, img_map) = generate_document_with_options(document, options)?;
+
+    let world = ShivaWorld::new(text, img_map);
+    let mut tracer = Tracer::default();
+
+    let document = typst::compile(&world, &mut tracer).map_err(|errors| {
+        let messages: Vec<String> = errors.iter().map(|e| e.message.to_string()).collect();
+        GeneratorError::Typst(messages.join("; "))
+    })?;
+    if let Some(token) = &options.cancellation {
+        if token.is_cancelled() {
+            return Err(GeneratorError::Cancelled.into());
+        }
+    }
+    let warnings = tracer.warnings();
+
+    if !warnings.is_empty() {
+        // Trowing any warnings if necessary
+        for warn in warnings {
+            warn!("Warning - {}", warn.message);
+        }
+    }
+    Ok(document)
+}
+
 /// Converts Document into a typst::model::Document
 pub fn generate_document(
     document: &Document,
+) -> anyhow::Result<(TypstString, HashMap<String, typst::foundations::Bytes>)> {
+    generate_document_with_options(document, &PdfOptions::default())
+}
+
+/// Converts Document into a typst::model::Document using the given generation options
+pub fn generate_document_with_options(
+    document: &Document,
+    options: &PdfOptions,
 ) -> anyhow::Result<(TypstString, HashMap<String, typst::foundations::Bytes>)> {
     // Array of methods to process Document object into a typst string repr
-    fn process_header(source: &mut TypstString, level: usize, text: &str) -> anyhow::Result<()> {
+    fn process_header(
+        source: &mut TypstString,
+        level: usize,
+        text: &str,
+        anchor: &Option<String>,
+    ) -> anyhow::Result<()> {
         let header_depth = "=".repeat(level);
-        let header_text = format!("{header_depth} {text}");
+        let mut header_text = format!("{header_depth} {text}");
+        // Internal links point to the label of the heading
+        if let Some(label) = anchor.as_deref().filter(|anchor| is_label(anchor)) {
+            header_text.push_str(&format!(" <{label}>"));
+        }
         source.push_str(&header_text);
         source.push('\n');
 
@@ -208,22 +354,42 @@ pub fn generate_document(
 
     fn process_text(
         source: &mut TypstString,
-        _size: u8,
+        font: &FontSpec,
         text: &str,
         is_bold: bool,
     ) -> anyhow::Result<()> {
+        let mut text = escape_markup(text);
         if is_bold {
-            let bold_text = format!("*{text}*");
-            source.push_str(&bold_text);
-        } else {
-            source.push_str(text);
+            text = format!("*{text}*");
+        }
+        // Nominal sizes are left to the page, only a font read from the source is set
+        if font.is_explicit() {
+            let mut arguments = Vec::new();
+            if let Some(family) = &font.family {
+                arguments.push(format!("font: {}", string_literal(family)));
+            }
+            arguments.push(format!("size: {}pt", font.size_pt));
+            if let Some(weight) = font.weight {
+                arguments.push(format!("weight: {}", weight));
+            }
+            text = format!("#text({})[{text}]", arguments.join(", "));
         }
+        source.push_str(&text);
 
         Ok(())
     }
 
-    fn process_link(source: &mut TypstString, url: &str) -> anyhow::Result<()> {
-        let link = format!("#link(\"{url}\")");
+    fn process_link(source: &mut TypstString, url: &str, title: &str) -> anyhow::Result<()> {
+        // A link to a label needs a body, it has no text of its own
+        if let Some(label) = url.strip_prefix('#').filter(|anchor| is_label(anchor)) {
+            let title = if title.is_empty() { label } else { title };
+            source.push_str(&format!("#link(<{label}>)[{}]", escape_markup(title)));
+            return Ok(());
+        }
+        let mut link = format!("#link({})", string_literal(url));
+        if !title.is_empty() && title != url {
+            link.push_str(&format!("[{}]", escape_markup(title)));
+        }
 
         source.push_str(&link);
 
@@ -232,16 +398,19 @@ pub fn generate_document(
 
     fn process_table(
         source: &mut TypstString,
+        img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        footnotes: &HashMap<&str, &[Element]>,
         headers: &Vec<TableHeader>,
         rows: &Vec<TableRow>,
+        alignments: &[ColumnAlignment],
     ) -> anyhow::Result<()> {
         let mut headers_text = TypstString::new();
 
         for header in headers {
             match &header.element {
-                Text { text, size } => {
+                Text { text, font } => {
                     headers_text.push('[');
-                    process_text(&mut headers_text, *size, text, true)?;
+                    process_text(&mut headers_text, font, text, true)?;
                     headers_text.push(']');
                     headers_text.push(',');
                 }
@@ -257,35 +426,62 @@ pub fn generate_document(
         let mut cells_text = TypstString::new();
 
         for row in rows {
+            cells_text.push_str("  ");
             for cell in &row.cells {
-                match &cell.element {
-                    Text { text, size } => {
+                // Merged cells are set with their spans, the cells they cover are left out
+                if cell.colspan > 1 || cell.rowspan > 1 {
+                    cells_text.push_str(&format!(
+                        "table.cell(colspan: {}, rowspan: {})",
+                        cell.colspan, cell.rowspan
+                    ));
+                }
+                match &cell.elements[..] {
+                    [Text { text, font }] => {
                         cells_text.push('[');
-                        process_text(&mut cells_text, *size, text, false)?;
+                        process_text(&mut cells_text, font, text, false)?;
                         cells_text.push(']');
-                        cells_text.push(',');
                     }
-                    _ => {
-                        warn!(
-                            "Should implement element for processing in inside cell - {:?}",
-                            cell.element
-                        );
+                    // Block content starts on a line of its own so lists are set as lists
+                    elements => {
+                        cells_text.push_str("[\n");
+                        for element in elements {
+                            process_element(&mut cells_text, img_map, footnotes, element)?;
+                        }
+                        cells_text.push(']');
                     }
                 }
+                cells_text.push(',');
             }
 
             cells_text.push('\n');
         }
 
-        let columns = headers.len();
+        let columns = headers.len().max(rows.first().map_or(0, |row| {
+            row.cells.iter().map(|cell| cell.colspan.max(1)).sum()
+        }));
+        // Unaligned columns keep the default alignment of the table
+        let align_text = if columns == 0
+            || alignments
+                .iter()
+                .all(|alignment| *alignment == ColumnAlignment::None)
+        {
+            String::new()
+        } else {
+            let aligns: Vec<&str> = (0..columns)
+                .map(|column| match alignments.get(column) {
+                    Some(ColumnAlignment::Left) => "left",
+                    Some(ColumnAlignment::Center) => "center",
+                    Some(ColumnAlignment::Right) => "right",
+                    _ => "auto",
+                })
+                .collect();
+            // A single item needs the trailing comma to be an array
+            let trailing = if aligns.len() == 1 { "," } else { "" };
+            format!("  align: ({}{}),\n", aligns.join(", "), trailing)
+        };
+        // Written from the start of the line, so a preceding list does not take it in
         let table_text = format!(
-            r#"
-        #table(
-            columns:{columns},
-            {headers_text}
-            {cells_text}
-        )
-        "#
+            "#table(\n  columns: {columns},\n{align_text}  {headers_text}\n{cells_text})\n"
         );
 
         source.push_str(&table_text);
@@ -295,102 +491,395 @@ pub fn generate_document(
     fn process_list(
         source: &mut TypstString,
         img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        footnotes: &HashMap<&str, &[Element]>,
         list: &Vec<ListItem>,
         numbered: bool,
         depth: usize,
     ) -> anyhow::Result<()> {
-        source.push_str(&" ".repeat(depth));
         for el in list {
             if let List { elements, numbered } = &el.element {
-                process_list(source, img_map, elements, *numbered, depth + 1)?;
+                process_list(source, img_map, footnotes, elements, *numbered, depth + 1)?;
             } else {
+                source.push_str(&"  ".repeat(depth));
                 if numbered {
                     source.push_str("+ ")
                 } else {
                     source.push_str("- ")
                 };
+                match el.checked {
+                    Some(true) => source.push_str("☑ "),
+                    Some(false) => source.push_str("☐ "),
+                    None => {}
+                }
 
-                process_element(source, img_map, &el.element)?;
+                process_element(source, img_map, footnotes, &el.element)?;
             }
         }
 
         Ok(())
     }
 
-    fn process_image(
+    fn process_image(source: &mut TypstString, path: &str, alt: &str) -> anyhow::Result<()> {
+        let image_text = format!(
+            "#image({}, alt: {})\n",
+            string_literal(path),
+            string_literal(alt)
+        );
+        source.push_str(&image_text);
+        Ok(())
+    }
+
+    /// The file name the image is compiled from, `None` when it has no data
+    fn insert_image(
+        img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        image: &ImageData,
+    ) -> Option<String> {
+        if image.bytes().is_empty() {
+            return None;
+        }
+        let key = format!(
+            "image{}{}",
+            img_map.len(),
+            image.image_type().to_extension()
+        );
+        img_map.insert(
+            key.clone(),
+            typst::foundations::Bytes::from(image.bytes().to_vec()),
+        );
+        Some(key)
+    }
+
+    /// Figures are numbered by typst, the caption is set below the image
+    fn process_figure(
         source: &mut TypstString,
-        bytes: &Bytes,
-        title: &str,
-        alt: &str,
-        image_type: &str,
+        img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        footnotes: &HashMap<&str, &[Element]>,
+        image: &ImageData,
+        caption: &[Element],
     ) -> anyhow::Result<()> {
-        if !bytes.is_empty() {
-            let image_text = format!(
-                "
-            #image(\"{title}{image_type}\", alt: \"{alt}\")
-            "
-            );
-            source.push_str(&image_text);
+        let mut body = TypstString::new();
+        for element in caption {
+            process_element(&mut body, img_map, footnotes, element)?;
+        }
+        match insert_image(img_map, image) {
+            Some(key) => source.push_str(&format!(
+                "#figure(image({}, alt: {}), caption: [\n{body}])\n\n",
+                string_literal(&key),
+                string_literal(image.alt())
+            )),
+            None => source.push_str(&format!("{body}\n")),
         }
-        // need to think how to implement using raw bytes
+        Ok(())
+    }
+
+    fn process_code_block(
+        source: &mut TypstString,
+        language: &Option<String>,
+        code: &str,
+    ) -> anyhow::Result<()> {
+        // The fence is longer than any run of backticks in the code
+        let longest_run = code.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+        let fence = "`".repeat(longest_run.max(2) + 1);
+        let language = language
+            .as_deref()
+            .filter(|language| {
+                language
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || matches!(c, '-' | '+' | '#'))
+            })
+            .unwrap_or_default();
+        source.push_str(&format!("{fence}{language}\n{code}\n{fence}\n"));
+        Ok(())
+    }
+
+    /// An indented block with a rule on its left
+    fn process_blockquote(
+        source: &mut TypstString,
+        img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        footnotes: &HashMap<&str, &[Element]>,
+        elements: &[Element],
+        attribution: &[Element],
+    ) -> anyhow::Result<()> {
+        let mut body = TypstString::new();
+        for element in elements {
+            process_element(&mut body, img_map, footnotes, element)?;
+        }
+        if attribution.is_empty() {
+            source.push_str(&format!(
+                "#block(inset: (left: 1em), stroke: (left: {QUOTE_RULE}))[\n{body}]\n"
+            ));
+        } else {
+            // Typst sets the attribution below the quote, after a dash
+            let mut source_text = TypstString::new();
+            for element in attribution {
+                process_element(&mut source_text, img_map, footnotes, element)?;
+            }
+            source.push_str(&format!(
+                "#quote(block: true, attribution: [{}])[\n{body}]\n",
+                source_text.trim()
+            ));
+        }
+        Ok(())
+    }
+
+    /// A term list, set with a hanging indent. Further definitions of a term are paragraphs
+    /// of its description, which is indented to continue the item.
+    fn process_definition_list(
+        source: &mut TypstString,
+        img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        footnotes: &HashMap<&str, &[Element]>,
+        items: &[DefinitionItem],
+    ) -> anyhow::Result<()> {
+        for item in items {
+            let mut term = TypstString::new();
+            for element in &item.term {
+                process_element(&mut term, img_map, footnotes, element)?;
+            }
+            let mut term = term.split_whitespace().collect::<Vec<_>>().join(" ");
+            // A colon would end the term early
+            if term.contains(':') {
+                term = format!("#[{term}]");
+            }
+            let mut description = Vec::new();
+            for definition in &item.definitions {
+                let mut body = TypstString::new();
+                process_element(&mut body, img_map, footnotes, definition)?;
+                description.push(body.trim().to_string());
+            }
+            let description = description.join("\n\n").replace('\n', "\n  ");
+            source.push_str(&format!("/ {term}: {description}\n"));
+        }
+        Ok(())
+    }
+
+    /// The note is set where it is referenced, the mark follows the word before it
+    fn process_footnote(
+        source: &mut TypstString,
+        img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        footnotes: &HashMap<&str, &[Element]>,
+        id: &str,
+    ) -> anyhow::Result<()> {
+        let Some(content) = footnotes.get(id) else {
+            warn!("Footnote {} is referenced but not defined", id);
+            return Ok(());
+        };
+        let mut body = TypstString::new();
+        for element in content.iter() {
+            // Notes inside a note are not supported
+            process_element(&mut body, img_map, &HashMap::new(), element)?;
+        }
+        if source.ends_with('\n') && !source.ends_with("\n\n") {
+            source.pop();
+        }
+        source.push_str(&format!("#footnote[{}]\n", body.trim()));
+        Ok(())
+    }
+
+    /// Each style is a function wrapping the content, so the styles nest in any combination
+    fn process_styled(
+        source: &mut TypstString,
+        img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        footnotes: &HashMap<&str, &[Element]>,
+        style: &TextStyle,
+        elements: &[Element],
+    ) -> anyhow::Result<()> {
+        let mut body = TypstString::new();
+        for element in elements {
+            process_element(&mut body, img_map, footnotes, element)?;
+        }
+        let mut styled = body.trim().to_string();
+        for (enabled, function) in [
+            (style.superscript, "super"),
+            (style.subscript, "sub"),
+            (style.strikethrough, "strike"),
+            (style.underline, "underline"),
+            (style.italic, "emph"),
+            (style.bold, "strong"),
+        ] {
+            if enabled {
+                styled = format!("#{function}[{styled}]");
+            }
+        }
+        if let Some(color) = style.color {
+            styled = format!("#text(fill: rgb(\"{color}\"))[{styled}]");
+        }
+        if let Some(color) = style.background {
+            styled = format!("#highlight(fill: rgb(\"{color}\"))[{styled}]");
+        }
+        if let Some(direction) = style.direction {
+            styled = format!("#text(dir: {direction})[{styled}]");
+        }
+        // The highlight is the background of all the other styles
+        if let Some(color) = style.highlight {
+            let fill = match color {
+                HighlightColor::Yellow => "yellow",
+                HighlightColor::Green => "green",
+                HighlightColor::Cyan => "aqua",
+                HighlightColor::Magenta => "fuchsia",
+                HighlightColor::Blue => "blue",
+                HighlightColor::Red => "red",
+                HighlightColor::Gray => "silver",
+            };
+            styled = format!("#highlight(fill: {fill})[{styled}]");
+        }
+        source.push_str(&styled);
+        source.push('\n');
         Ok(())
     }
 
     fn process_element(
         source: &mut TypstString,
         img_map: &mut HashMap<String, typst::foundations::Bytes>,
+        footnotes: &HashMap<&str, &[Element]>,
         element: &Element,
     ) -> anyhow::Result<()> {
         match element {
-            Header { level, text } => process_header(source, *level as usize, text),
+            Header {
+                level,
+                text,
+                anchor,
+            } => process_header(source, *level as usize, text, anchor),
             Paragraph { elements } => {
                 for paragraph_element in elements {
-                    process_element(source, img_map, paragraph_element)?;
+                    process_element(source, img_map, footnotes, paragraph_element)?;
                 }
+                // A blank line ends the paragraph
+                source.push('\n');
 
                 Ok(())
             }
-            Text { text, size } => {
-                process_text(source, *size, text, false)?;
+            Text { text, font } => {
+                process_text(source, font, text, false)?;
                 source.push('\n');
 
                 Ok(())
             }
+            LineBreak => {
+                source.push_str("#linebreak()\n");
+
+                Ok(())
+            }
+            HorizontalRule => {
+                source.push_str("#line(length: 100%)\n");
+                Ok(())
+            }
+            PageBreak => {
+                source.push_str("#pagebreak()\n");
+                Ok(())
+            }
+            // Typst lists the headings itself
+            TableOfContents { max_level } => {
+                source.push_str(&format!("#outline(depth: {})\n", max_level));
+                Ok(())
+            }
+            // A PDF has no player, the poster links to the source
+            Media { .. } => process_element(source, img_map, footnotes, &element.media_fallback()),
+            Ruby { .. } => process_element(source, img_map, footnotes, &element.ruby_fallback()),
+            Custom { .. } => Ok(()),
+            // The headings give the nesting
+            Section { children, .. } => {
+                for child in children {
+                    process_element(source, img_map, footnotes, child)?;
+                }
+                Ok(())
+            }
+            // Drawn as an SVG image, typst has no charts of its own
+            Chart {
+                chart_type,
+                series,
+                categories,
+            } => {
+                let image = ImageData::new(
+                    Bytes::from(crate::chart::svg(*chart_type, series, categories)),
+                    String::new(),
+                    "Chart".to_string(),
+                    "svg".to_string(),
+                    String::new(),
+                    ImageDimension::default(),
+                );
+                process_element(source, img_map, footnotes, &Image(image))
+            }
+            // Comments are editorial notes, only the ones between blocks go in the margin
+            Comment { .. } => Ok(()),
+            CodeBlock { language, code } => process_code_block(source, language, code),
+            Blockquote {
+                elements,
+                attribution,
+            } => process_blockquote(source, img_map, footnotes, elements, attribution),
+            DefinitionList { items } => process_definition_list(source, img_map, footnotes, items),
+            // The content is written at the references
+            Footnote { .. } => Ok(()),
+            FootnoteReference { id } => process_footnote(source, img_map, footnotes, id),
+            Styled { style, elements } => {
+                process_styled(source, img_map, footnotes, style, elements)
+            }
+            // Spaces inside the dollar signs make a display formula
+            Math { tex, display } => {
+                let math = tex_math(tex);
+                if !math.is_empty() {
+                    if *display {
+                        source.push_str(&format!("$ {} $\n", math));
+                    } else {
+                        source.push_str(&format!("${}$\n", math));
+                    }
+                }
+                Ok(())
+            }
             List { elements, numbered } => {
-                process_list(source, img_map, elements, *numbered, 0)?;
+                process_list(source, img_map, footnotes, elements, *numbered, 0)?;
                 Ok(())
             }
             Hyperlink {
                 url,
-                title: _,
+                title,
                 alt: _,
                 size: _,
             } => {
-                process_link(source, url)?;
+                process_link(source, url, title)?;
                 source.push('\n');
 
                 Ok(())
             }
-            Table { headers, rows } => {
-                process_table(source, headers, rows)?;
+            Table {
+                headers,
+                rows,
+                alignments,
+                caption,
+            } if !caption.is_empty() => {
+                let mut table = TypstString::new();
+                process_table(&mut table, img_map, footnotes, headers, rows, alignments)?;
+                let mut body = TypstString::new();
+                for element in caption {
+                    process_element(&mut body, img_map, footnotes, element)?;
+                }
+                // Typst numbers the figure as a table
+                source.push_str(&format!(
+                    "#figure(\n{},\ncaption: [{}])\n",
+                    table.trim_end().trim_start_matches('#'),
+                    body.trim()
+                ));
+                Ok(())
+            }
+            Table {
+                headers,
+                rows,
+                alignments,
+                ..
+            } => {
+                process_table(source, img_map, footnotes, headers, rows, alignments)?;
                 Ok(())
             }
             Image(image) => {
-                let key = format!("{}{}", image.title(), image.image_type());
-                img_map.insert(key, typst::foundations::Bytes::from(image.bytes().to_vec()));
-                process_image(
-                    source,
-                    image.bytes(),
-                    image.title(),
-                    image.alt(),
-                    &image.image_type().to_string(),
-                )?;
-                source.push('\n');
+                if let Some(key) = insert_image(img_map, image) {
+                    process_image(source, &key, image.alt())?;
+                    source.push('\n');
+                }
                 Ok(())
-            } // _ => {
-              //     warn!("Should implement element - {:?}", element);
-              //     Ok(())
-              // }
+            }
+            Figure { image, caption } => process_figure(source, img_map, footnotes, image, caption), // _ => {
+                                                                                                     //     warn!("Should implement element - {:?}", element);
+                                                                                                     //     Ok(())
+                                                                                                     // }
         }
     }
 
@@ -402,37 +891,1156 @@ pub fn generate_document(
     // Converting both headers and footers into a string repr of them in Typst
     let mut header_text = String::new();
     document.get_page_header().iter().for_each(|el| match el {
-        Text { text, size: _ } => {
+        Text { text, font: _ } => {
             header_text.push_str(text);
         }
         _ => {}
     });
     let mut footer_text = String::new();
     document.get_page_footer().iter().for_each(|el| match el {
-        Text { text, size: _ } => {
+        Text { text, font: _ } => {
             footer_text.push_str(text);
         }
         _ => {}
     });
+    let margin_text = match &options.margins {
+        Some(margins) => format!("        margin: {},\n", margins.to_typst()),
+        None => String::new(),
+    };
+    // Typst pages are A4 by default
+    let size_text = match (&document.page_format, &document.orientation) {
+        (PageFormat::A4, PageOrientation::Portrait) => String::new(),
+        (page_format, orientation) => {
+            let dimensions = page_format.dimensions();
+            let (width, height) = match orientation {
+                PageOrientation::Portrait => (dimensions.page_width, dimensions.page_height),
+                PageOrientation::Landscape => (dimensions.page_height, dimensions.page_width),
+            };
+            format!("        width: {width}mm,\n        height: {height}mm,\n")
+        }
+    };
     let footer_header_text = format!(
         "#set page(
-        header: \"{header_text}\",
-        footer: \"{footer_text}\",
-    )\n"
+        header: {},
+        footer: {},
+{size_text}{margin_text}    )\n",
+        string_literal(&header_text),
+        string_literal(&footer_text)
     );
 
     // Converting Document repr to one of typst string
+    // Document properties have to be set before any content
+    if let Some(template) = &options.template {
+        source.push_str(template);
+        source.push('\n');
+    }
+    source.push_str(&document_rules(&document.metadata));
     source.push_str(&footer_header_text);
+    if document.direction != TextDirection::default() {
+        source.push_str(&format!("#set text(dir: {})\n", document.direction));
+    }
+    if let Some(function) = &options.template_function {
+        source.push_str(&format!("#show: {}\n", function));
+    }
+    // Margin notes are placed next to the top level blocks, the headings give the nesting
+    let document = &document.flatten_sections();
+    let footnotes = document.footnotes();
     for element in &document.get_all_elements() {
-        process_element(&mut source, &mut img_map, element)?;
+        if let Some(token) = &options.cancellation {
+            if token.is_cancelled() {
+                return Err(GeneratorError::Cancelled.into());
+            }
+        }
+        match element {
+            Comment { author, text, .. } if options.comments => {
+                process_margin_note(&mut source, author, text)
+            }
+            _ => process_element(&mut source, &mut img_map, &footnotes, element)?,
+        }
     }
 
     Ok((source, img_map))
 }
 
+/// A review comment placed in the right margin next to the text before it
+fn process_margin_note(source: &mut TypstString, author: &Option<String>, text: &str) {
+    let author = author
+        .as_deref()
+        .map(|author| format!("*{}:* ", escape_markup(author)))
+        .unwrap_or_default();
+    source.push_str(&format!(
+        "#place(right, dx: {MARGIN_NOTE_OFFSET}, box(width: {MARGIN_NOTE_WIDTH}, text(size: 7pt, fill: luma(90))[{author}{}]))\n",
+        escape_markup(text)
+    ));
+}
+
+/// Distance of margin notes from the right edge of the text
+const MARGIN_NOTE_OFFSET: &str = "2.3cm";
+
+/// Width of margin notes, they fit in the default page margin
+const MARGIN_NOTE_WIDTH: &str = "2cm";
+
+/// Stroke of the rule on the left of a blockquote
+const QUOTE_RULE: &str = "2pt + luma(180)";
+
+/// Whether an anchor can be written as a typst label, other anchors are left out
+fn is_label(anchor: &str) -> bool {
+    !anchor.is_empty()
+        && anchor
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+/// `#set document` with the title, authors and keywords, `#set text` with the language
+fn document_rules(metadata: &Metadata) -> TypstString {
+    let array = |items: &[String]| {
+        let items: Vec<TypstString> = items.iter().map(|item| string_literal(item)).collect();
+        format!("({},)", items.join(", "))
+    };
+    let mut fields = Vec::new();
+    if let Some(title) = &metadata.title {
+        fields.push(format!("title: {}", string_literal(title)));
+    }
+    if !metadata.authors.is_empty() {
+        fields.push(format!("author: {}", array(&metadata.authors)));
+    }
+    if !metadata.keywords.is_empty() {
+        fields.push(format!("keywords: {}", array(&metadata.keywords)));
+    }
+    let mut rules = TypstString::new();
+    if !fields.is_empty() {
+        rules.push_str(&format!("#set document({})\n", fields.join(", ")));
+    }
+    // Typst knows languages by their ISO 639 code and regions by their ISO 3166 one
+    let mut subtags = metadata.language.as_deref().unwrap_or_default().split(['-', '_']);
+    let language = subtags.next().unwrap_or_default();
+    if (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_alphabetic()) {
+        let mut rule = format!("lang: {}", string_literal(&language.to_ascii_lowercase()));
+        match subtags.next() {
+            Some(region) if region.len() == 2 && region.chars().all(|c| c.is_ascii_alphabetic()) => {
+                rule.push_str(&format!(", region: {}", string_literal(region)));
+            }
+            _ => {}
+        }
+        rules.push_str(&format!("#set text({})\n", rule));
+    }
+    rules
+}
+
+/// A typst string literal
+fn string_literal(text: &str) -> TypstString {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{escaped}\"")
+}
+
+/// Escapes the characters that would start markup or a comment, so text renders as written
+fn escape_markup(text: &str) -> TypstString {
+    // Digits followed by a dot at the start of a line would start a numbered list
+    let enum_marker = text
+        .find(|c: char| !c.is_ascii_digit())
+        .filter(|end| *end > 0 && text[*end..].starts_with('.'));
+    let mut escaped = TypstString::with_capacity(text.len());
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let markup = match c {
+            '\\' | '#' | '*' | '_' | '$' | '<' | '@' | '[' | ']' | '`' | '~' => true,
+            '=' | '-' | '+' => index == 0,
+            '/' => index == 0 || matches!(chars.peek(), Some((_, '/' | '*'))),
+            '.' => enum_marker == Some(index),
+            _ => false,
+        };
+        if markup {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+struct Parser<'a, F> {
+    image_loader: &'a F,
+    page_header: Vec<Element>,
+    page_footer: Vec<Element>,
+    direction: TextDirection,
+    metadata: Metadata,
+    /// Contents of the `footnote` calls, numbered in order and added after the body
+    footnotes: Vec<Element>,
+}
+
+impl<F> Parser<'_, F>
+where
+    F: Fn(&str) -> anyhow::Result<Bytes>,
+{
+    /// Inline content is collected into paragraphs, which end at blank lines and blocks
+    fn blocks(&mut self, markup: ast::Markup) -> anyhow::Result<Vec<Element>> {
+        let mut elements = Vec::new();
+        let mut paragraph = Vec::new();
+        let mut list: Option<(bool, Vec<ListItem>)> = None;
+        for expr in markup.exprs() {
+            // Term items in a row, blank lines aside, make up one definition list
+            if let ast::Expr::Term(item) = expr {
+                end_paragraph(&mut paragraph, &mut elements);
+                end_list(&mut list, &mut elements);
+                let term = self
+                    .blocks(item.term())?
+                    .into_iter()
+                    .flat_map(|element| match element {
+                        Paragraph { elements } => elements,
+                        element => vec![element],
+                    })
+                    .collect();
+                let item = DefinitionItem {
+                    term,
+                    definitions: self.blocks(item.description())?,
+                };
+                match elements.last_mut() {
+                    Some(DefinitionList { items }) => items.push(item),
+                    _ => elements.push(DefinitionList { items: vec![item] }),
+                }
+                continue;
+            }
+            let item = match expr {
+                ast::Expr::List(item) => Some((false, item.body())),
+                ast::Expr::Enum(item) => Some((true, item.body())),
+                _ => None,
+            };
+            if let Some((numbered, body)) = item {
+                end_paragraph(&mut paragraph, &mut elements);
+                if list.as_ref().is_some_and(|(kind, _)| *kind != numbered) {
+                    end_list(&mut list, &mut elements);
+                }
+                let nested = self.blocks(body)?;
+                let (_, items) = list.get_or_insert_with(|| (numbered, Vec::new()));
+                items.extend(nested.into_iter().map(|element| ListItem {
+                    element: unwrap_paragraph(element),
+                    checked: None,
+                }));
+                continue;
+            }
+            // Items of a list may be separated by blank lines
+            if !matches!(expr, ast::Expr::Space(_) | ast::Expr::Parbreak(_)) {
+                end_list(&mut list, &mut elements);
+            }
+            match expr {
+                ast::Expr::Space(_) => push_text(&mut paragraph, " "),
+                ast::Expr::Parbreak(_) => end_paragraph(&mut paragraph, &mut elements),
+                ast::Expr::Linebreak(_) => paragraph.push(LineBreak),
+                ast::Expr::Link(link) => paragraph.push(Hyperlink {
+                    title: link.get().to_string(),
+                    url: link.get().to_string(),
+                    alt: String::new(),
+                    size: 8,
+                }),
+                ast::Expr::Heading(heading) => {
+                    end_paragraph(&mut paragraph, &mut elements);
+                    elements.push(Header {
+                        level: heading.depth().get().min(6) as u8,
+                        text: plain(heading.body()),
+                        anchor: None,
+                    });
+                }
+                // The label of a heading follows it
+                ast::Expr::Label(label) if paragraph.is_empty() => {
+                    if let Some(Header { anchor, .. }) = elements.last_mut() {
+                        *anchor = Some(label.get().to_string());
+                    }
+                }
+                ast::Expr::Raw(raw) if raw.block() => {
+                    end_paragraph(&mut paragraph, &mut elements);
+                    let lines: Vec<&str> = raw.lines().map(|line| line.get().as_str()).collect();
+                    elements.push(CodeBlock {
+                        language: raw.lang().map(|language| language.get().to_string()),
+                        code: lines.join("\n"),
+                    });
+                }
+                ast::Expr::Set(rule) => self.set_rule(rule),
+                ast::Expr::FuncCall(call) => match self.call(call)? {
+                    Some(element @ (Hyperlink { .. } | LineBreak | FootnoteReference { .. })) => {
+                        paragraph.push(element)
+                    }
+                    Some(element) => {
+                        end_paragraph(&mut paragraph, &mut elements);
+                        elements.push(element);
+                    }
+                    None => {}
+                },
+                expr => {
+                    let mut text = String::new();
+                    plain_expr(expr, &mut text);
+                    push_text(&mut paragraph, &text);
+                }
+            }
+        }
+        end_paragraph(&mut paragraph, &mut elements);
+        end_list(&mut list, &mut elements);
+        Ok(elements)
+    }
+
+    /// `#set page(header: ..., footer: ...)` sets the page header and footer,
+    /// `#set text(dir: ...)` the direction of the document
+    fn set_rule(&mut self, rule: ast::SetRule) {
+        if is_function(rule.target(), "text") {
+            if let Some(ast::Expr::Ident(direction)) = named(rule.args(), "dir") {
+                if let Ok(direction) = direction.as_str().parse() {
+                    self.direction = direction;
+                }
+            }
+            if let Some(language) = named(rule.args(), "lang").and_then(text_value) {
+                self.metadata.language = match named(rule.args(), "region").and_then(text_value) {
+                    Some(region) => Some(format!("{}-{}", language, region)),
+                    None => Some(language),
+                };
+            }
+            return;
+        }
+        if is_function(rule.target(), "document") {
+            let texts = |expr: ast::Expr| -> Vec<String> {
+                match expr {
+                    ast::Expr::Array(array) => array
+                        .items()
+                        .filter_map(|item| match item {
+                            ast::ArrayItem::Pos(expr) => text_value(expr),
+                            ast::ArrayItem::Spread(_) => None,
+                        })
+                        .collect(),
+                    expr => text_value(expr).into_iter().collect(),
+                }
+            };
+            let args = rule.args();
+            self.metadata.title = named(args, "title").and_then(text_value);
+            self.metadata.authors = named(args, "author").map(texts).unwrap_or_default();
+            self.metadata.keywords = named(args, "keywords").map(texts).unwrap_or_default();
+            return;
+        }
+        if !is_function(rule.target(), "page") {
+            return;
+        }
+        for (name, band) in [
+            ("header", &mut self.page_header),
+            ("footer", &mut self.page_footer),
+        ] {
+            match named(rule.args(), name).and_then(text_value) {
+                Some(text) if !text.is_empty() => {
+                    *band = vec![Text {
+                        text,
+                        font: FontSpec::from(8),
+                    }]
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Elements of the `image`, `figure`, `table`, `link`, `linebreak`, `pagebreak` and `footnote`
+    /// functions, `quote` and blocks with a stroke are blockquotes
+    fn call(&mut self, call: ast::FuncCall) -> anyhow::Result<Option<Element>> {
+        let ast::Expr::Ident(function) = call.callee() else {
+            return Ok(None);
+        };
+        let args = call.args();
+        let element = match function.as_str() {
+            "image" => self.image(args, String::new())?,
+            "figure" => {
+                let caption_text = named(args, "caption").and_then(text_value);
+                let caption = caption_text.clone().unwrap_or_default();
+                match positional(args).first() {
+                    Some(ast::Expr::FuncCall(body)) if is_function(body.callee(), "image") => {
+                        self.image(body.args(), caption)?
+                    }
+                    // A table keeps its caption
+                    Some(ast::Expr::FuncCall(body)) => match self.call(*body)? {
+                        Some(Table {
+                            headers,
+                            rows,
+                            alignments,
+                            ..
+                        }) => Some(Table {
+                            headers,
+                            rows,
+                            alignments,
+                            caption: caption_text
+                                .map(|text| {
+                                    vec![Text {
+                                        text,
+                                        font: FontSpec::from(8),
+                                    }]
+                                })
+                                .unwrap_or_default(),
+                        }),
+                        element => element,
+                    },
+                    _ => None,
+                }
+            }
+            "table" => Some(table(args)),
+            "link" => {
+                let values: Vec<String> = positional(args)
+                    .into_iter()
+                    .filter_map(|arg| match arg {
+                        // A label is the anchor of a header in the document
+                        ast::Expr::Label(label) => Some(format!("#{}", label.get())),
+                        arg => text_value(arg),
+                    })
+                    .collect();
+                values.first().map(|url| Hyperlink {
+                    title: values.get(1).unwrap_or(url).clone(),
+                    url: url.clone(),
+                    alt: String::new(),
+                    size: 8,
+                })
+            }
+            "linebreak" => Some(LineBreak),
+            "line" => Some(HorizontalRule),
+            "pagebreak" => Some(PageBreak),
+            // Without a depth the outline lists all headings
+            "outline" => Some(TableOfContents {
+                max_level: match named(args, "depth") {
+                    Some(ast::Expr::Int(depth)) => depth.get().clamp(1, 6) as u8,
+                    _ => 6,
+                },
+            }),
+            "footnote" => {
+                let mut content = Vec::new();
+                for body in positional(args).into_iter().filter_map(content_body) {
+                    content.extend(self.blocks(body)?);
+                }
+                let id = (self.footnotes.len() + 1).to_string();
+                self.footnotes.push(Footnote {
+                    id: id.clone(),
+                    content,
+                });
+                Some(FootnoteReference { id })
+            }
+            "quote" => Some(self.blockquote(args)?),
+            // Plain blocks only group content, the rule on the left marks a quote
+            "block" if named(args, "stroke").is_some() => Some(self.blockquote(args)?),
+            _ => None,
+        };
+        Ok(element)
+    }
+
+    fn blockquote(&mut self, args: ast::Args) -> anyhow::Result<Element> {
+        let mut elements = Vec::new();
+        for body in positional(args).into_iter().filter_map(content_body) {
+            elements.extend(self.blocks(body)?);
+        }
+        let attribution = named(args, "attribution")
+            .and_then(text_value)
+            .map(|text| {
+                vec![Text {
+                    text,
+                    font: FontSpec::from(8),
+                }]
+            })
+            .unwrap_or_default();
+        Ok(Blockquote {
+            elements,
+            attribution,
+        })
+    }
+
+    /// The image file is read through the image loader, `title` is the figure caption
+    fn image(&self, args: ast::Args, title: String) -> anyhow::Result<Option<Element>> {
+        let Some(path) = positional(args).into_iter().find_map(text_value) else {
+            return Ok(None);
+        };
+        let bytes = (self.image_loader)(&path)?;
+        let source_text = |expr: ast::Expr| expr.to_untyped().clone().into_text().to_string();
+        let size = ImageDimension {
+            width: named(args, "width").map(source_text),
+            height: named(args, "height").map(source_text),
+        };
+        Ok(Some(Image(ImageData::new(
+            bytes,
+            title,
+            named(args, "alt").and_then(text_value).unwrap_or_default(),
+            path,
+            String::new(),
+            size,
+        ))))
+    }
+}
+
+/// A `table` call, the leading cells are the header when they are all strong
+fn table(args: ast::Args) -> Element {
+    let columns = match named(args, "columns") {
+        Some(ast::Expr::Int(count)) => count.get().max(1) as usize,
+        Some(ast::Expr::Array(array)) => array.items().count().max(1),
+        _ => 1,
+    };
+    let mut header_cells = Vec::new();
+    let mut cells = Vec::new();
+    for arg in positional(args) {
+        match arg {
+            ast::Expr::FuncCall(call) if is_table_header(call) => {
+                header_cells.extend(positional(call.args()).into_iter().filter_map(content_body))
+            }
+            arg => cells.extend(content_body(arg)),
+        }
+    }
+    if header_cells.is_empty()
+        && cells.len() >= columns
+        && cells[..columns].iter().all(|cell| is_strong(*cell))
+    {
+        header_cells = cells.drain(..columns).collect();
+    }
+    let text = |cell: ast::Markup| Text {
+        text: plain(cell),
+        font: FontSpec::from(8),
+    };
+    Table {
+        headers: header_cells
+            .into_iter()
+            .map(|cell| TableHeader {
+                element: text(cell),
+                width: 30.0,
+            })
+            .collect(),
+        rows: cells
+            .chunks(columns)
+            .map(|row| TableRow {
+                cells: row.iter().map(|cell| TableCell::new(text(*cell))).collect(),
+            })
+            .collect(),
+        alignments: vec![],
+        caption: vec![],
+    }
+}
+
+fn is_table_header(call: ast::FuncCall) -> bool {
+    matches!(call.callee(), ast::Expr::FieldAccess(access)
+        if is_function(access.target(), "table") && access.field().as_str() == "header")
+}
+
+fn content_body(expr: ast::Expr) -> Option<ast::Markup> {
+    match expr {
+        ast::Expr::Content(block) => Some(block.body()),
+        _ => None,
+    }
+}
+
+fn is_function(expr: ast::Expr, name: &str) -> bool {
+    matches!(expr, ast::Expr::Ident(ident) if ident.as_str() == name)
+}
+
+fn is_strong(markup: ast::Markup) -> bool {
+    let mut exprs = markup
+        .exprs()
+        .filter(|expr| !matches!(expr, ast::Expr::Space(_)))
+        .peekable();
+    exprs.peek().is_some() && exprs.all(|expr| matches!(expr, ast::Expr::Strong(_)))
+}
+
+fn positional(args: ast::Args) -> Vec<ast::Expr> {
+    args.items()
+        .filter_map(|arg| match arg {
+            ast::Arg::Pos(expr) => Some(expr),
+            _ => None,
+        })
+        .collect()
+}
+
+fn named<'a>(args: ast::Args<'a>, name: &str) -> Option<ast::Expr<'a>> {
+    args.items().find_map(|arg| match arg {
+        ast::Arg::Named(named) if named.name().as_str() == name => Some(named.expr()),
+        _ => None,
+    })
+}
+
+/// The value of a string or the text of a content block
+fn text_value(expr: ast::Expr) -> Option<String> {
+    match expr {
+        ast::Expr::Str(string) => Some(string.get().to_string()),
+        ast::Expr::Content(block) => Some(plain(block.body())),
+        _ => None,
+    }
+}
+
+/// The text of markup with whitespace collapsed
+fn plain(markup: ast::Markup) -> String {
+    let mut text = String::new();
+    markup.exprs().for_each(|expr| plain_expr(expr, &mut text));
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn plain_expr(expr: ast::Expr, text: &mut String) {
+    match expr {
+        ast::Expr::Text(part) => text.push_str(part.get()),
+        ast::Expr::Space(_) | ast::Expr::Linebreak(_) => text.push(' '),
+        ast::Expr::Escape(escape) => text.push(escape.get()),
+        ast::Expr::Shorthand(shorthand) => text.push(shorthand.get()),
+        ast::Expr::SmartQuote(quote) => text.push(if quote.double() { '"' } else { '\'' }),
+        ast::Expr::Link(link) => text.push_str(link.get()),
+        ast::Expr::Raw(raw) => {
+            let lines: Vec<&str> = raw.lines().map(|line| line.get().as_str()).collect();
+            text.push_str(&lines.join(" "));
+        }
+        ast::Expr::Strong(strong) => plain_markup(strong.body(), text),
+        ast::Expr::Emph(emph) => plain_markup(emph.body(), text),
+        ast::Expr::Content(block) => plain_markup(block.body(), text),
+        ast::Expr::Heading(heading) => plain_markup(heading.body(), text),
+        ast::Expr::FuncCall(call) if is_function(call.callee(), "link") => {
+            if let Some(value) = positional(call.args())
+                .into_iter()
+                .filter_map(text_value)
+                .next_back()
+            {
+                text.push_str(&value);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn plain_markup(markup: ast::Markup, text: &mut String) {
+    markup.exprs().for_each(|expr| plain_expr(expr, text));
+}
+
+/// Appends to the trailing text of the paragraph, whitespace runs become a single space
+fn push_text(paragraph: &mut Vec<Element>, text: &str) {
+    if text.trim().is_empty() {
+        match paragraph.last() {
+            None => return,
+            Some(Text { text, .. }) if text.ends_with(' ') => return,
+            _ => {}
+        }
+    }
+    match paragraph.last_mut() {
+        Some(Text { text: last, .. }) => last.push_str(text),
+        _ => paragraph.push(Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        }),
+    }
+}
+
+fn end_paragraph(paragraph: &mut Vec<Element>, elements: &mut Vec<Element>) {
+    if let Some(Text { text, .. }) = paragraph.last_mut() {
+        text.truncate(text.trim_end().len());
+        if text.is_empty() {
+            paragraph.pop();
+        }
+    }
+    if !paragraph.is_empty() {
+        elements.push(Paragraph {
+            elements: std::mem::take(paragraph),
+        });
+    }
+}
+
+fn end_list(list: &mut Option<(bool, Vec<ListItem>)>, elements: &mut Vec<Element>) {
+    if let Some((numbered, items)) = list.take() {
+        elements.push(List {
+            elements: items,
+            numbered,
+        });
+    }
+}
+
+/// Typst math for a TeX formula. Common commands and environments have a Typst counterpart,
+/// other commands are kept as text so that the document still compiles.
+fn tex_math(tex: &str) -> TypstString {
+    let mut converter = TexMath::new(tex);
+    let mut math = TypstString::new();
+    // Unbalanced closing braces end a sequence early, the rest is converted as well
+    loop {
+        let (sequence, end) = converter.sequence(TexContext::default());
+        push_atom(&mut math, &sequence);
+        if end == TexEnd::Input {
+            break;
+        }
+    }
+    math.trim().to_string()
+}
+
+/// Where a sequence of TeX math ended
+#[derive(PartialEq)]
+enum TexEnd {
+    Input,
+    Group,
+    Cell,
+    Row,
+    Environment,
+}
+
+#[derive(Clone, Copy, Default)]
+struct TexContext {
+    /// Inside the arguments of a Typst function, where commas and semicolons separate them
+    arguments: bool,
+    /// Inside an environment, where `&` and `\\` separate cells and rows
+    cells: bool,
+}
+
+const TEX_ARGUMENTS: TexContext = TexContext {
+    arguments: true,
+    cells: false,
+};
+
+struct TexMath {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl TexMath {
+    fn new(tex: &str) -> TexMath {
+        TexMath {
+            chars: tex.chars().collect(),
+            position: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        Some(c)
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn sequence(&mut self, context: TexContext) -> (TypstString, TexEnd) {
+        let mut math = TypstString::new();
+        while let Some(c) = self.next() {
+            let atom = match c {
+                '}' => return (math, TexEnd::Group),
+                '&' if context.cells => return (math, TexEnd::Cell),
+                '{' => {
+                    let group = TexContext {
+                        cells: false,
+                        ..context
+                    };
+                    self.sequence(group).0
+                }
+                '^' | '_' => {
+                    let argument = self.argument(context);
+                    // Typst attaches a single token, anything longer needs parentheses
+                    let atomic = !argument.is_empty()
+                        && argument.chars().all(|c| c.is_alphanumeric() || c == '.');
+                    math.push(c);
+                    if atomic {
+                        math.push_str(&argument);
+                    } else {
+                        math.push_str(&format!("({})", argument));
+                    }
+                    continue;
+                }
+                '\\' => {
+                    if self.peek() == Some('\\') {
+                        self.position += 1;
+                        if context.cells {
+                            return (math, TexEnd::Row);
+                        }
+                        " \\ ".to_string()
+                    } else if self.chars[self.position..].starts_with(&['e', 'n', 'd'])
+                        && !self
+                            .chars
+                            .get(self.position + 3)
+                            .is_some_and(|c| c.is_alphabetic())
+                    {
+                        self.position += 3;
+                        self.raw_argument();
+                        if context.cells {
+                            return (math, TexEnd::Environment);
+                        }
+                        continue;
+                    } else {
+                        self.command()
+                    }
+                }
+                c => character(c, context),
+            };
+            push_atom(&mut math, &atom);
+        }
+        (math, TexEnd::Input)
+    }
+
+    /// A group, a command or a single character
+    fn argument(&mut self, context: TexContext) -> TypstString {
+        self.skip_spaces();
+        let context = TexContext {
+            cells: false,
+            ..context
+        };
+        match self.next() {
+            Some('{') => self.sequence(context).0,
+            Some('\\') => self.command(),
+            Some(c) => character(c, context),
+            None => TypstString::new(),
+        }
+    }
+
+    /// The source of a group or a single character, for text and names
+    fn raw_argument(&mut self) -> String {
+        self.skip_spaces();
+        match self.next() {
+            Some('{') => {
+                let mut depth = 0;
+                let mut raw = String::new();
+                while let Some(c) = self.next() {
+                    match c {
+                        '{' => depth += 1,
+                        '}' if depth == 0 => break,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    raw.push(c);
+                }
+                raw
+            }
+            Some(c) => c.to_string(),
+            None => String::new(),
+        }
+    }
+
+    /// The optional `[...]` argument of a command
+    fn optional_argument(&mut self) -> Option<TypstString> {
+        self.skip_spaces();
+        if self.peek() != Some('[') {
+            return None;
+        }
+        self.position += 1;
+        let mut raw = String::new();
+        while let Some(c) = self.next() {
+            if c == ']' {
+                break;
+            }
+            raw.push(c);
+        }
+        Some(TexMath::new(&raw).sequence(TEX_ARGUMENTS).0)
+    }
+
+    fn call(&mut self, function: &str, count: usize) -> TypstString {
+        let arguments: Vec<TypstString> =
+            (0..count).map(|_| self.argument(TEX_ARGUMENTS)).collect();
+        format!("{}({})", function, arguments.join(", "))
+    }
+
+    fn command(&mut self) -> TypstString {
+        let name: String = if self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            let start = self.position;
+            while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                self.position += 1;
+            }
+            self.chars[start..self.position].iter().collect()
+        } else {
+            self.next().map(String::from).unwrap_or_default()
+        };
+        match name.as_str() {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => self.call("frac", 2),
+            "binom" | "dbinom" | "tbinom" => self.call("binom", 2),
+            "sqrt" => match self.optional_argument() {
+                Some(index) => format!("root({}, {})", index, self.argument(TEX_ARGUMENTS)),
+                None => self.call("sqrt", 1),
+            },
+            "text" | "textrm" | "textnormal" | "textup" | "textit" | "textbf" | "mbox"
+            | "mathrm" => string_literal(&self.raw_argument()),
+            "operatorname" => format!("op({})", string_literal(&self.raw_argument())),
+            "mathbf" | "boldsymbol" | "bm" => self.call("bold", 1),
+            "mathit" => self.call("italic", 1),
+            "mathbb" => self.call("bb", 1),
+            "mathcal" => self.call("cal", 1),
+            "mathfrak" => self.call("frak", 1),
+            "mathsf" => self.call("sans", 1),
+            "mathtt" => self.call("mono", 1),
+            "hat" | "widehat" => self.call("hat", 1),
+            "tilde" | "widetilde" => self.call("tilde", 1),
+            "bar" => self.call("macron", 1),
+            "vec" => self.call("arrow", 1),
+            "dot" => self.call("dot", 1),
+            "ddot" => self.call("dot.double", 1),
+            "check" => self.call("caron", 1),
+            "acute" | "grave" | "breve" | "overline" | "underline" | "overbrace" | "underbrace" => {
+                self.call(&name, 1)
+            }
+            // Typst scales delimiters on its own
+            "left" | "right" | "middle" | "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr"
+            | "Bigl" | "Bigr" | "biggl" | "biggr" | "Biggl" | "Biggr" => {
+                self.skip_spaces();
+                if self.peek() == Some('.') {
+                    self.position += 1;
+                }
+                TypstString::new()
+            }
+            "limits" | "nolimits" | "displaystyle" | "textstyle" | "scriptstyle" | "!" => {
+                TypstString::new()
+            }
+            "begin" => self.environment(),
+            "," => "thin".to_string(),
+            ":" | ">" => "med".to_string(),
+            ";" => "thick".to_string(),
+            " " => "space".to_string(),
+            "|" => "bar.v.double".to_string(),
+            "{" | "}" | "_" | "$" | "#" | "&" => format!("\\{}", name),
+            "%" => "%".to_string(),
+            name => match tex_symbol(name) {
+                Some(symbol) => symbol.to_string(),
+                None => string_literal(&format!("\\{}", name)),
+            },
+        }
+    }
+
+    /// Matrices and cases become calls, other environments keep their alignment
+    fn environment(&mut self) -> TypstString {
+        let name = self.raw_argument();
+        let name = name.trim_end_matches('*');
+        if name == "array" {
+            // The column specification
+            self.raw_argument();
+        }
+        let context = TexContext {
+            arguments: true,
+            cells: true,
+        };
+        let mut rows: Vec<Vec<TypstString>> = vec![Vec::new()];
+        loop {
+            let (cell, end) = self.sequence(context);
+            if let Some(row) = rows.last_mut() {
+                row.push(cell.trim().to_string());
+            }
+            match end {
+                TexEnd::Cell => {}
+                TexEnd::Row => rows.push(Vec::new()),
+                _ => break,
+            }
+        }
+        // A row separator at the end leaves an empty row
+        if rows.len() > 1
+            && rows
+                .last()
+                .is_some_and(|row| row.iter().all(String::is_empty))
+        {
+            rows.pop();
+        }
+        let delimiter = match name {
+            "matrix" | "smallmatrix" => Some("#none"),
+            "pmatrix" => Some("\"(\""),
+            "bmatrix" => Some("\"[\""),
+            "Bmatrix" => Some("\"{\""),
+            "vmatrix" => Some("\"|\""),
+            "Vmatrix" => Some("\"||\""),
+            _ => None,
+        };
+        let rows_with = |separator: &str| -> Vec<TypstString> {
+            rows.iter().map(|row| row.join(separator)).collect()
+        };
+        match (name, delimiter) {
+            (_, Some(delimiter)) => {
+                format!("mat(delim: {}, {})", delimiter, rows_with(", ").join("; "))
+            }
+            ("cases", _) => format!("cases({})", rows_with(" & ").join(", ")),
+            _ => rows_with(" & ").join(" \\ "),
+        }
+    }
+}
+
+/// A character of TeX math, escaped where it means something else in Typst math
+fn character(c: char, context: TexContext) -> TypstString {
+    match c {
+        c if c.is_whitespace() => TypstString::new(),
+        '~' => TypstString::new(),
+        '/' | '"' | '#' | '$' | '@' | '`' => format!("\\{}", c),
+        ',' | ';' if context.arguments => format!("\\{}", c),
+        c => c.to_string(),
+    }
+}
+
+/// Appends a piece of math. Adjacent letters would form a single identifier in Typst and a
+/// name followed by a parenthesis a function call, so such pieces are separated.
+fn push_atom(math: &mut TypstString, atom: &str) {
+    let (Some(last), Some(first)) = (math.chars().next_back(), atom.chars().next()) else {
+        math.push_str(atom);
+        return;
+    };
+    let separate = (last.is_alphanumeric()
+        && first.is_alphanumeric()
+        && !(last.is_ascii_digit() && first.is_ascii_digit()))
+        || (last.is_alphabetic() && matches!(first, '(' | '['));
+    if separate {
+        math.push(' ');
+    }
+    math.push_str(atom);
+}
+
+/// The Typst name of a TeX symbol or operator name
+fn tex_symbol(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "alpha",
+        "beta" => "beta",
+        "gamma" => "gamma",
+        "delta" => "delta",
+        "epsilon" => "epsilon.alt",
+        "varepsilon" => "epsilon",
+        "zeta" => "zeta",
+        "eta" => "eta",
+        "theta" => "theta",
+        "vartheta" => "theta.alt",
+        "iota" => "iota",
+        "kappa" => "kappa",
+        "lambda" => "lambda",
+        "mu" => "mu",
+        "nu" => "nu",
+        "xi" => "xi",
+        "pi" => "pi",
+        "varpi" => "pi.alt",
+        "rho" => "rho",
+        "varrho" => "rho.alt",
+        "sigma" => "sigma",
+        "varsigma" => "sigma.alt",
+        "tau" => "tau",
+        "upsilon" => "upsilon",
+        "phi" => "phi.alt",
+        "varphi" => "phi",
+        "chi" => "chi",
+        "psi" => "psi",
+        "omega" => "omega",
+        "Gamma" => "Gamma",
+        "Delta" => "Delta",
+        "Theta" => "Theta",
+        "Lambda" => "Lambda",
+        "Xi" => "Xi",
+        "Pi" => "Pi",
+        "Sigma" => "Sigma",
+        "Upsilon" => "Upsilon",
+        "Phi" => "Phi",
+        "Psi" => "Psi",
+        "Omega" => "Omega",
+        "cdot" => "dot.op",
+        "times" => "times",
+        "div" => "div",
+        "pm" => "plus.minus",
+        "mp" => "minus.plus",
+        "ast" => "ast",
+        "star" => "star",
+        "circ" => "compose",
+        "bullet" => "bullet",
+        "oplus" => "plus.circle",
+        "otimes" => "times.circle",
+        "cup" => "union",
+        "cap" => "sect",
+        "setminus" => "without",
+        "wedge" | "land" => "and",
+        "vee" | "lor" => "or",
+        "neg" | "lnot" => "not",
+        "le" | "leq" => "lt.eq",
+        "ge" | "geq" => "gt.eq",
+        "ne" | "neq" => "eq.not",
+        "approx" => "approx",
+        "equiv" => "equiv",
+        "sim" => "tilde.op",
+        "simeq" => "tilde.eq",
+        "cong" => "tilde.equiv",
+        "propto" => "prop",
+        "ll" => "lt.double",
+        "gg" => "gt.double",
+        "in" => "in",
+        "notin" => "in.not",
+        "ni" => "in.rev",
+        "subset" => "subset",
+        "subseteq" => "subset.eq",
+        "supset" => "supset",
+        "supseteq" => "supset.eq",
+        "mid" => "divides",
+        "parallel" => "parallel",
+        "perp" => "perp",
+        "to" | "rightarrow" => "arrow.r",
+        "leftarrow" | "gets" => "arrow.l",
+        "leftrightarrow" => "arrow.l.r",
+        "Rightarrow" | "implies" => "arrow.r.double",
+        "Leftarrow" => "arrow.l.double",
+        "Leftrightarrow" | "iff" => "arrow.l.r.double",
+        "mapsto" => "arrow.r.bar",
+        "uparrow" => "arrow.t",
+        "downarrow" => "arrow.b",
+        "sum" => "sum",
+        "prod" => "product",
+        "coprod" => "product.co",
+        "int" => "integral",
+        "iint" => "integral.double",
+        "iiint" => "integral.triple",
+        "oint" => "integral.cont",
+        "bigcup" => "union.big",
+        "bigcap" => "sect.big",
+        "infty" => "infinity",
+        "partial" => "diff",
+        "nabla" => "nabla",
+        "forall" => "forall",
+        "exists" => "exists",
+        "nexists" => "exists.not",
+        "emptyset" | "varnothing" => "nothing",
+        "aleph" => "aleph",
+        "hbar" => "planck.reduce",
+        "ell" => "ell",
+        "angle" => "angle",
+        "prime" => "prime",
+        "ldots" | "dots" => "dots.h",
+        "cdots" => "dots.h.c",
+        "vdots" => "dots.v",
+        "ddots" => "dots.down",
+        "langle" => "angle.l",
+        "rangle" => "angle.r",
+        "lfloor" => "floor.l",
+        "rfloor" => "floor.r",
+        "lceil" => "ceil.l",
+        "rceil" => "ceil.r",
+        "vert" | "lvert" | "rvert" => "bar.v",
+        "Vert" | "lVert" | "rVert" => "bar.v.double",
+        "backslash" => "backslash",
+        "colon" => "colon",
+        "quad" => "quad",
+        "qquad" => "wide",
+        "sin" => "sin",
+        "cos" => "cos",
+        "tan" => "tan",
+        "cot" => "cot",
+        "sec" => "sec",
+        "csc" => "csc",
+        "arcsin" => "arcsin",
+        "arccos" => "arccos",
+        "arctan" => "arctan",
+        "sinh" => "sinh",
+        "cosh" => "cosh",
+        "tanh" => "tanh",
+        "coth" => "coth",
+        "exp" => "exp",
+        "log" => "log",
+        "ln" => "ln",
+        "lg" => "lg",
+        "lim" => "lim",
+        "liminf" => "liminf",
+        "limsup" => "limsup",
+        "max" => "max",
+        "min" => "min",
+        "sup" => "sup",
+        "inf" => "inf",
+        "det" => "det",
+        "dim" => "dim",
+        "gcd" => "gcd",
+        "deg" => "deg",
+        "arg" => "arg",
+        "ker" => "ker",
+        "hom" => "hom",
+        "Pr" => "Pr",
+        "mod" | "bmod" => "mod",
+        _ => return None,
+    })
+}
+
 #[cfg(test)]
 mod test {
-    use crate::core::{disk_image_loader, TransformerWithImageLoaderSaverTrait};
+    use crate::core::{
+        disk_image_loader, Band, ChartSeries, ChartType, Color, MediaKind, MediaSource,
+        TransformerWithImageLoaderSaverTrait,
+    };
     use crate::markdown;
     use bytes::Bytes;
 
@@ -445,18 +2053,807 @@ mod test {
             &documents_bytes,
             disk_image_loader("test/data"),
         )?;
-        let generated_result = crate::typst::Transformer::generate(&parsed)?;
+        let images = std::cell::RefCell::new(Vec::new());
+        let generated_result =
+            crate::typst::Transformer::generate_with_saver(&parsed, |_, name| {
+                images.borrow_mut().push(name.to_string());
+                Ok(())
+            })?;
+        let source = std::str::from_utf8(&generated_result)?;
+        assert!(!images.borrow().is_empty());
+        for name in images.borrow().iter() {
+            assert!(source.contains(&format!("#image(\"{name}\"")));
+        }
         std::fs::write("test/data/document_from_md.typ", generated_result)?;
 
         Ok(())
     }
 
+    #[test]
+    fn test_mirrored_margins() -> anyhow::Result<()> {
+        let document = Document::new(vec![Header {
+            level: 1,
+            text: "Chapter".to_string(),
+            anchor: None,
+        }]);
+        let (source, _) = generate_document(&document)?;
+        assert!(!source.contains("margin:"));
+
+        let options = PdfOptions {
+            margins: Some(Margins::mirrored(30.0, 15.0, 20.0, 20.0)),
+            ..Default::default()
+        };
+        let (source, _) = generate_document_with_options(&document, &options)?;
+        assert!(source.contains("margin: (top: 20mm, bottom: 20mm, inside: 30mm, outside: 15mm),"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_size() -> anyhow::Result<()> {
+        let mut document = Document::new(vec![Header {
+            level: 1,
+            text: "Chapter".to_string(),
+            anchor: None,
+        }]);
+        let (source, _) = generate_document(&document)?;
+        assert!(!source.contains("width:"));
+
+        document.page_format = PageFormat::Letter;
+        document.orientation = PageOrientation::Landscape;
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains("        width: 279mm,\n        height: 216mm,\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_template() -> anyhow::Result<()> {
+        let document = Document::new(vec![Text {
+            text: "Body".to_string(),
+            font: FontSpec::from(8),
+        }]);
+        let options = PdfOptions {
+            template: Some(
+                "#let letter(body) = {\n  set page(paper: \"a5\")\n  [Letterhead\n#body]\n}"
+                    .to_string(),
+            ),
+            template_function: Some("letter".to_string()),
+            ..Default::default()
+        };
+        let (source, _) = generate_document_with_options(&document, &options)?;
+        let page_at = source.find("#set page").unwrap();
+        assert!(source.find("#let letter").unwrap() < page_at);
+        let show_at = source.find("#show: letter\n").unwrap();
+        assert!(page_at < show_at && show_at < source.find("Body").unwrap());
+        let size = compile_document(&document, &options)?.pages[0].frame.size();
+        assert_eq!(size.x.to_mm().round(), 148.0);
+
+        // The page settings of a preamble apply to the document as well
+        let options = PdfOptions {
+            template: Some("#set page(paper: \"a5\")".to_string()),
+            ..Default::default()
+        };
+        let (source, _) = generate_document_with_options(&document, &options)?;
+        assert!(!source.contains("#show:"));
+        let compiled = compile_document(&document, &options)?;
+        let size = compiled.pages[0].frame.size();
+        assert_eq!(size.x.to_mm().round(), 148.0);
+        assert_eq!(size.y.to_mm().round(), 210.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_margin_comments() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            Text {
+                text: "Revenue grew".to_string(),
+                font: FontSpec::from(8),
+            },
+            Comment {
+                author: Some("Ann".to_string()),
+                date: None,
+                text: "Check the totals".to_string(),
+                range: None,
+            },
+        ]);
+        let (source, _) = generate_document(&document)?;
+        assert!(!source.contains("Check the totals"));
+
+        let options = PdfOptions {
+            comments: true,
+            ..Default::default()
+        };
+        let (source, _) = generate_document_with_options(&document, &options)?;
+        assert!(source.contains("#place(right, dx: 2.3cm, box(width: 2cm, text(size: 7pt, fill: luma(90))[*Ann:* Check the totals]))"));
+        compile_document(&document, &options)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let source = r#"#set page(header: "Draft", margin: 2cm)
+#let accent = red
+
+= Report
+Some *strong* text with a #link("https://example.com")[link]
+and a \#hash.
+
+- First
+  - Nested
+- Second
++ One
+
+#table(
+  columns: 2,
+  [*Name*], [*Value*],
+  [a], [1],
+)
+
+#figure(image("chart.png", width: 50%), caption: [Sales])
+"#;
+        let document = Transformer::parse_with_loader(&Bytes::from(source), |path| {
+            assert_eq!(path, "chart.png");
+            Ok(Bytes::from_static(b"\x89PNG"))
+        })?;
+        assert_eq!(
+            document.bands[0],
+            Band::PageHeader(vec![Text {
+                text: "Draft".to_string(),
+                font: FontSpec::from(8)
+            }])
+        );
+        let Band::Detail(elements) = &document.bands[1] else {
+            panic!("expected the detail band, got {:?}", document.bands[1]);
+        };
+        assert_eq!(elements.len(), 6);
+        assert_eq!(
+            elements[0],
+            Header {
+                level: 1,
+                text: "Report".to_string(),
+                anchor: None,
+            }
+        );
+        let Paragraph { elements: inline } = &elements[1] else {
+            panic!("expected a paragraph, got {:?}", elements[1]);
+        };
+        assert!(matches!(&inline[0], Text { text, .. } if text == "Some strong text with a "));
+        assert!(matches!(&inline[1], Hyperlink { url, title, .. }
+            if url == "https://example.com" && title == "link"));
+        assert!(matches!(&inline[2], Text { text, .. } if text == " and a #hash."));
+        let List {
+            elements: items,
+            numbered: false,
+        } = &elements[2]
+        else {
+            panic!("expected a list, got {:?}", elements[2]);
+        };
+        assert_eq!(items.len(), 3);
+        assert!(matches!(&items[1].element, List { elements, .. } if elements.len() == 1));
+        assert!(matches!(elements[3], List { numbered: true, .. }));
+        let Table { headers, rows, .. } = &elements[4] else {
+            panic!("expected a table, got {:?}", elements[4]);
+        };
+        assert_eq!(headers.len(), 2);
+        assert_eq!(rows.len(), 1);
+        assert!(matches!(&headers[1].element, Text { text, .. } if text == "Value"));
+        let Image(image) = &elements[5] else {
+            panic!("expected an image, got {:?}", elements[5]);
+        };
+        assert_eq!(image.title(), "Sales");
+        assert_eq!(image.size().width.as_deref(), Some("50%"));
+
+        assert!(Transformer::parse(&Bytes::from("#table(")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![
+            Header {
+                level: 2,
+                text: "Results".to_string(),
+                anchor: None,
+            },
+            Paragraph {
+                elements: vec![
+                    text("C# uses *stars*, [brackets] and // slashes, see "),
+                    Hyperlink {
+                        title: "the docs".to_string(),
+                        url: "https://example.com/a\"b".to_string(),
+                        alt: String::new(),
+                        size: 8,
+                    },
+                ],
+            },
+            Paragraph {
+                elements: vec![text("- 1. not a list")],
+            },
+            List {
+                elements: vec![
+                    ListItem {
+                        element: text("First"),
+                        checked: None,
+                    },
+                    ListItem {
+                        element: List {
+                            elements: vec![ListItem {
+                                element: text("Nested"),
+                                checked: None,
+                            }],
+                            numbered: true,
+                        },
+                        checked: None,
+                    },
+                ],
+                numbered: false,
+            },
+            Table {
+                headers: vec![TableHeader {
+                    element: text("Name"),
+                    width: 30.0,
+                }],
+                rows: vec![TableRow {
+                    cells: vec![TableCell::new(text("$5"))],
+                }],
+                alignments: vec![],
+                caption: vec![],
+            },
+            CodeBlock {
+                language: Some("rust".to_string()),
+                code: "let s = \"```\";\n    #[derive(Debug)]".to_string(),
+            },
+            Blockquote {
+                elements: vec![Paragraph {
+                    elements: vec![text("Quoted")],
+                }],
+                attribution: vec![],
+            },
+            Image(ImageData::new(
+                Bytes::from_static(b"\x89PNG"),
+                String::new(),
+                "A \"chart\"".to_string(),
+                "png".to_string(),
+                String::new(),
+                ImageDimension::default(),
+            )),
+        ]);
+        let images = std::cell::RefCell::new(HashMap::new());
+        let generated = Transformer::generate_with_saver(&document, |bytes, name| {
+            images.borrow_mut().insert(name.to_string(), bytes.clone());
+            Ok(())
+        })?;
+        assert_eq!(images.borrow().len(), 1);
+        let reparsed = Transformer::parse_with_loader(&generated, |name| {
+            Ok(images.borrow().get(name).cloned().unwrap_or_default())
+        })?;
+        let elements = reparsed.get_all_elements();
+        assert_eq!(elements[..7], document.get_all_elements()[..7]);
+        let Image(image) = elements[7] else {
+            panic!("expected an image, got {:?}", elements[7]);
+        };
+        assert_eq!(image.bytes().as_ref(), b"\x89PNG");
+        assert_eq!(image.alt(), "A \"chart\"");
+        Ok(())
+    }
+
+    #[test]
+    fn test_footnote() -> anyhow::Result<()> {
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![
+            Paragraph {
+                elements: vec![
+                    text("Claim"),
+                    FootnoteReference {
+                        id: "1".to_string(),
+                    },
+                ],
+            },
+            Footnote {
+                id: "1".to_string(),
+                content: vec![Paragraph {
+                    elements: vec![text("Source")],
+                }],
+            },
+        ]);
+        let generated = Transformer::generate_with_saver(&document, |_, _| Ok(()))?;
+        let source = String::from_utf8(generated.to_vec())?;
+        assert!(source.contains("Claim#footnote[Source]"), "{}", source);
+        let reparsed = Transformer::parse(&generated)?;
+        assert_eq!(reparsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merged_cells() -> anyhow::Result<()> {
+        let cell = |text: &str, colspan, rowspan| TableCell {
+            elements: vec![Text {
+                text: text.to_string(),
+                font: FontSpec::from(8),
+            }],
+            colspan,
+            rowspan,
+        };
+        let document = Document::new(vec![Table {
+            headers: vec![],
+            rows: vec![
+                TableRow {
+                    cells: vec![cell("Name", 2, 1), cell("Notes", 1, 2)],
+                },
+                TableRow {
+                    cells: vec![cell("First", 1, 1), cell("Last", 1, 1)],
+                },
+            ],
+            alignments: vec![],
+            caption: vec![],
+        }]);
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains("columns: 3"), "{}", source);
+        assert!(
+            source.contains("table.cell(colspan: 2, rowspan: 1)["),
+            "{}",
+            source
+        );
+        compile_document(&document, &PdfOptions::default())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_rich_cell() -> anyhow::Result<()> {
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let item = |value: &str| ListItem {
+            element: text(value),
+            checked: None,
+        };
+        let document = Document::new(vec![Table {
+            headers: vec![],
+            rows: vec![TableRow {
+                cells: vec![
+                    TableCell::new(text("Steps")),
+                    TableCell::with_elements(vec![
+                        Paragraph {
+                            elements: vec![text("Before")],
+                        },
+                        List {
+                            elements: vec![item("one"), item("two")],
+                            numbered: false,
+                        },
+                    ]),
+                ],
+            }],
+            alignments: vec![],
+            caption: vec![],
+        }]);
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains("[\nBefore\n"), "{}", source);
+        assert!(source.contains("- one\n- two\n],"), "{}", source);
+        compile_document(&document, &PdfOptions::default())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_table() -> anyhow::Result<()> {
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let inner = Table {
+            headers: vec![],
+            rows: vec![TableRow {
+                cells: vec![TableCell::new(text("Widget")), TableCell::new(text("4"))],
+            }],
+            alignments: vec![],
+            caption: vec![],
+        };
+        let document = Document::new(vec![Table {
+            headers: vec![],
+            rows: vec![TableRow {
+                cells: vec![TableCell::new(text("Items")), TableCell::new(inner)],
+            }],
+            alignments: vec![],
+            caption: vec![],
+        }]);
+        let (source, _) = generate_document(&document)?;
+        assert_eq!(source.matches("#table(").count(), 2, "{}", source);
+        compile_document(&document, &PdfOptions::default())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_figure() -> anyhow::Result<()> {
+        let image = ImageData::new(
+            Bytes::from(std::fs::read("test/data/small.png")?),
+            String::new(),
+            "Sales chart".to_string(),
+            "png".to_string(),
+            String::new(),
+            ImageDimension::default(),
+        );
+        let document = Document::new(vec![Figure {
+            image,
+            caption: vec![Text {
+                text: "Sales by quarter".to_string(),
+                font: FontSpec::from(8),
+            }],
+        }]);
+        let (source, _) = generate_document(&document)?;
+        assert!(
+            source.contains("#figure(image(\"image0.png\", alt: \"Sales chart\"), caption: [\n"),
+            "{}",
+            source
+        );
+        assert!(source.contains("Sales by quarter"), "{}", source);
+        compile_document(&document, &PdfOptions::default())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_styled() -> anyhow::Result<()> {
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![Paragraph {
+            elements: vec![
+                text("Plain "),
+                Styled {
+                    style: TextStyle::BOLD.combine(TextStyle::STRIKETHROUGH),
+                    elements: vec![
+                        text("bold "),
+                        Styled {
+                            style: TextStyle::ITALIC,
+                            elements: vec![text("both")],
+                        },
+                    ],
+                },
+            ],
+        }]);
+        let generated = Transformer::generate_with_saver(&document, |_, _| Ok(()))?;
+        let source = String::from_utf8(generated.to_vec())?;
+        // Every text is a line of its own, the line breaks are spaces
+        assert!(
+            source.contains("#strong[#strike[bold \n#emph[both]]]"),
+            "{}",
+            source
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_superscript_subscript() -> anyhow::Result<()> {
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![Paragraph {
+            elements: vec![
+                text("H"),
+                Styled {
+                    style: TextStyle::SUBSCRIPT,
+                    elements: vec![text("2")],
+                },
+                text("O = mc"),
+                Styled {
+                    style: TextStyle::SUPERSCRIPT,
+                    elements: vec![text("2")],
+                },
+            ],
+        }]);
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains("#sub[2]"), "{}", source);
+        assert!(source.contains("#super[2]"), "{}", source);
+        compile_document(&document, &PdfOptions::default())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_highlight() -> anyhow::Result<()> {
+        let document = Document::new(vec![Paragraph {
+            elements: vec![Styled {
+                style: TextStyle::BOLD.combine(TextStyle {
+                    highlight: Some(HighlightColor::Cyan),
+                    ..Default::default()
+                }),
+                elements: vec![Text {
+                    text: "Marked".to_string(),
+                    font: FontSpec::from(8),
+                }],
+            }],
+        }]);
+        let (source, _) = generate_document(&document)?;
+        assert!(
+            source.contains("#highlight(fill: aqua)[#strong[Marked]]"),
+            "{}",
+            source
+        );
+        compile_document(&document, &PdfOptions::default())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_break() -> anyhow::Result<()> {
+        let text = |text: &str| Paragraph {
+            elements: vec![Text {
+                text: text.to_string(),
+                font: FontSpec::from(8),
+            }],
+        };
+        let document = Document::new(vec![text("Cover"), PageBreak, text("Contents")]);
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains("#pagebreak()"), "{}", source);
+        let pages = compile_document(&document, &PdfOptions::default())?.pages;
+        assert_eq!(pages.len(), 2);
+
+        let parsed = Transformer::parse(&Bytes::from(source))?;
+        assert_eq!(parsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_media() -> anyhow::Result<()> {
+        let document = Document::new(vec![Media {
+            kind: MediaKind::Audio,
+            source: MediaSource::Url("https://example.com/talk.mp3".to_string()),
+            poster: None,
+        }]);
+        let (source, _) = generate_document(&document)?;
+        assert!(
+            source.contains("#link(\"https://example.com/talk.mp3\")"),
+            "{}",
+            source
+        );
+        compile_document(&document, &PdfOptions::default())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_chart() -> anyhow::Result<()> {
+        let document = Document::new(vec![Chart {
+            chart_type: ChartType::Bar,
+            series: vec![ChartSeries {
+                name: "Visits".to_string(),
+                values: vec![120.0, 80.0],
+            }],
+            categories: vec!["Mon".to_string(), "Tue".to_string()],
+        }]);
+        let (source, images) = generate_document(&document)?;
+        assert!(source.contains("#image(\"image0.svg\""), "{}", source);
+        assert_eq!(images.len(), 1);
+        compile_document(&document, &PdfOptions::default())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_direction() -> anyhow::Result<()> {
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let mut document = Document::new(vec![
+            Paragraph {
+                elements: vec![text("שלום עולם")],
+            },
+            Paragraph {
+                elements: vec![Styled {
+                    style: TextStyle {
+                        direction: Some(TextDirection::Ltr),
+                        ..Default::default()
+                    },
+                    elements: vec![text("Hello")],
+                }],
+            },
+        ]);
+        document.direction = TextDirection::Rtl;
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains("#set text(dir: rtl)\n"), "{}", source);
+        assert!(source.contains("#text(dir: ltr)[Hello]"), "{}", source);
+        compile_document(&document, &PdfOptions::default())?;
+
+        let parsed = Transformer::parse(&Bytes::from(source))?;
+        assert_eq!(parsed.direction, TextDirection::Rtl);
+        Ok(())
+    }
+
+    #[test]
+    fn test_outline() -> anyhow::Result<()> {
+        let document = Document::new(vec![
+            TableOfContents { max_level: 2 },
+            Header {
+                level: 1,
+                text: "Overview".to_string(),
+                anchor: None,
+            },
+        ]);
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains("#outline(depth: 2)"), "{}", source);
+        let parsed = Transformer::parse(&Bytes::from(source))?;
+        assert_eq!(parsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
+
+    #[test]
+    fn test_anchor() -> anyhow::Result<()> {
+        let header = Header {
+            level: 1,
+            text: "Introduction".to_string(),
+            anchor: Some("intro".to_string()),
+        };
+        let link = Hyperlink {
+            title: "see above".to_string(),
+            url: "#intro".to_string(),
+            alt: String::new(),
+            size: 8,
+        };
+        let document = Document::new(vec![
+            header.clone(),
+            Paragraph {
+                elements: vec![link.clone()],
+            },
+        ]);
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains("= Introduction <intro>"), "{}", source);
+        assert!(source.contains("#link(<intro>)[see above]"), "{}", source);
+        compile_document(&document, &PdfOptions::default())?;
+
+        let parsed = Transformer::parse(&Bytes::from(source))?;
+        assert_eq!(
+            parsed.get_all_elements(),
+            vec![
+                &header,
+                &Paragraph {
+                    elements: vec![link]
+                }
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_color() -> anyhow::Result<()> {
+        let document = Document::new(vec![Paragraph {
+            elements: vec![Styled {
+                style: TextStyle {
+                    color: Some(Color::new(255, 255, 255)),
+                    background: Some(Color::new(0, 0, 128)),
+                    ..Default::default()
+                },
+                elements: vec![Text {
+                    text: "Inverted".to_string(),
+                    font: FontSpec::from(8),
+                }],
+            }],
+        }]);
+        let (source, _) = generate_document(&document)?;
+        assert!(
+            source.contains(
+                "#highlight(fill: rgb(\"#000080\"))[#text(fill: rgb(\"#ffffff\"))[Inverted]]"
+            ),
+            "{}",
+            source
+        );
+        compile_document(&document, &PdfOptions::default())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_font() -> anyhow::Result<()> {
+        let document = Document::new(vec![Paragraph {
+            elements: vec![
+                Text {
+                    text: "Nominal ".to_string(),
+                    font: FontSpec::from(16),
+                },
+                Text {
+                    text: "Quoted".to_string(),
+                    font: FontSpec {
+                        family: Some("Libertinus Serif".to_string()),
+                        size_pt: 13.5,
+                        weight: Some(700),
+                    },
+                },
+            ],
+        }]);
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains("Nominal"), "{}", source);
+        assert!(
+            source.contains("#text(font: \"Libertinus Serif\", size: 13.5pt, weight: 700)[Quoted]"),
+            "{}",
+            source
+        );
+        compile_document(&document, &PdfOptions::default())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_math() -> anyhow::Result<()> {
+        assert_eq!(tex_math("\\frac{a}{b}"), "frac(a, b)");
+        assert_eq!(tex_math("x^{2} + \\alpha_i"), "x^2+alpha_i");
+        assert_eq!(tex_math("\\sqrt[3]{xy}"), "root(3, x y)");
+        assert_eq!(
+            tex_math("\\begin{pmatrix} a & b \\\\ c & d \\end{pmatrix}"),
+            "mat(delim: \"(\", a, b; c, d)"
+        );
+        // Unknown commands are kept as text rather than breaking the document
+        assert_eq!(tex_math("\\unknown x"), "\"\\\\unknown\"x");
+
+        let document = Document::new(vec![
+            Paragraph {
+                elements: vec![
+                    Text {
+                        text: "Area".to_string(),
+                        font: FontSpec::from(8),
+                    },
+                    Math {
+                        tex: "\\pi r^2".to_string(),
+                        display: false,
+                    },
+                ],
+            },
+            Math {
+                tex: "\\sum_{n=1}^{\\infty} \\frac{1}{n^2} = \\frac{\\pi^2}{6}".to_string(),
+                display: true,
+            },
+        ]);
+        let (source, _) = generate_document(&document)?;
+        assert!(source.contains("$pi r^2$"), "{}", source);
+        compile_document(&document, &PdfOptions::default())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_caption() -> anyhow::Result<()> {
+        let text = |text: &str| Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new(vec![
+            Table {
+                headers: vec![TableHeader {
+                    element: text("Quarter"),
+                    width: 30.0,
+                }],
+                rows: vec![TableRow {
+                    cells: vec![TableCell::new(text("Q1"))],
+                }],
+                alignments: vec![],
+                caption: vec![text("Quarterly results")],
+            },
+            Blockquote {
+                elements: vec![Paragraph {
+                    elements: vec![text("Brevity is the soul of wit.")],
+                }],
+                attribution: vec![text("Shakespeare")],
+            },
+        ]);
+        let (source, _) = generate_document(&document)?;
+        assert!(
+            source.contains("caption: [Quarterly results])"),
+            "{}",
+            source
+        );
+        assert!(source.contains("#quote(block: true, attribution: [Shakespeare])"));
+        compile_document(&document, &PdfOptions::default())?;
+        let reparsed = Transformer::parse(&Transformer::generate(&document)?)?;
+        assert_eq!(reparsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
+
     #[test]
     fn test_generate_from_xml() -> anyhow::Result<()> {
         let document = std::fs::read("test/data/document.xml")?;
         let documents_bytes = Bytes::from(document);
         let parsed = crate::xml::Transformer::parse(&documents_bytes)?;
-        let generated_result = crate::typst::Transformer::generate(&parsed)?;
+        let generated_result =
+            crate::typst::Transformer::generate_with_saver(&parsed, |_, _| Ok(()))?;
         std::fs::write("test/data/document_from_xml.typ", generated_result)?;
 
         Ok(())
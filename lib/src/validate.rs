@@ -0,0 +1,348 @@
+//! Checks for content generators write badly or not at all, as links without a target, images
+//! without data and tables with rows of different widths
+//!
+//! [`Document::validate`] reports the issues, [`Document::repair`] fixes those that have an
+//! obvious fix and [`Document::generate_validated`] refuses to generate a document that has any.
+use std::fmt;
+
+use bytes::Bytes;
+
+use crate::core::{
+    child_elements, Document, DocumentType, Element, ElementVisitorMut, FontSpec, GeneratorError,
+    SourceSpan, TableCell, TableHeader,
+};
+
+/// Something wrong with an element of a document, see [`Document::validate`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub kind: IssueKind,
+    /// Index of the band, then of the element in the band and of each nested element down to
+    /// the one with the issue, in the order [`Document::walk`] visits them
+    pub path: Vec<usize>,
+    /// Where the element or the closest one holding it is in the input, for parsed documents
+    pub span: Option<SourceSpan>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IssueKind {
+    /// A hyperlink with an empty url
+    EmptyHyperlinkUrl,
+    /// An image or figure without image data
+    EmptyImage,
+    /// A header more than one level below the header before it
+    SkippedHeaderLevel { previous: u8, level: u8 },
+    /// A table row spanning a different number of columns than the widest row or the header
+    RaggedTableRow {
+        row: usize,
+        columns: usize,
+        expected: usize,
+    },
+    /// An element nested deeper than the limit of [`ValidationOptions`]
+    NestedTooDeep { depth: usize },
+}
+
+/// Limits [`Document::validate_with_options`] checks against
+#[derive(Debug, Clone)]
+pub struct ValidationOptions {
+    /// Elements nested deeper than this are reported, generators recursing through the tree may
+    /// run out of stack on them
+    pub max_depth: usize,
+}
+
+impl Default for ValidationOptions {
+    fn default() -> ValidationOptions {
+        ValidationOptions { max_depth: 32 }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            IssueKind::EmptyHyperlinkUrl => write!(f, "hyperlink without a url")?,
+            IssueKind::EmptyImage => write!(f, "image without data")?,
+            IssueKind::SkippedHeaderLevel { previous, level } => {
+                write!(f, "header of level {} after one of level {}", level, previous)?
+            }
+            IssueKind::RaggedTableRow {
+                row,
+                columns,
+                expected,
+            } => write!(
+                f,
+                "table row {} has {} columns instead of {}",
+                row, columns, expected
+            )?,
+            IssueKind::NestedTooDeep { depth } => write!(f, "element nested {} deep", depth)?,
+        }
+        let path: Vec<String> = self.path.iter().map(usize::to_string).collect();
+        write!(f, " at {}", path.join("."))?;
+        match &self.span {
+            Some(span) => write!(f, " ({})", span),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Document {
+    /// The issues of the document with the default limits, in document order
+    pub fn validate(&self) -> Vec<Issue> {
+        self.validate_with_options(&ValidationOptions::default())
+    }
+
+    /// The issues of the document, in document order
+    pub fn validate_with_options(&self, options: &ValidationOptions) -> Vec<Issue> {
+        let mut check = Check {
+            options,
+            issues: Vec::new(),
+            path: Vec::new(),
+            previous_level: None,
+        };
+        for (index, band) in self.bands.iter().enumerate() {
+            check.path.push(index);
+            check.elements(band.elements().iter().collect(), 1);
+            check.path.pop();
+        }
+        for issue in &mut check.issues {
+            issue.span = self.source_span(&issue.path).cloned();
+        }
+        check.issues
+    }
+
+    /// Fixes the issues [`Document::validate`] reports that have an obvious fix: hyperlinks
+    /// without a url become their title, images without data their alt text and figures their
+    /// caption, skipped header levels are closed up and short table rows are filled with empty
+    /// cells. Deep nesting is left as it is.
+    pub fn repair(&mut self) {
+        struct Repair {
+            previous_level: Option<u8>,
+        }
+        impl ElementVisitorMut for Repair {
+            fn visit_element(&mut self, element: &mut Element) {
+                match element {
+                    Element::Hyperlink { title, url, .. } if url.is_empty() => {
+                        *element = text(title.clone());
+                    }
+                    Element::Image(image) if image.bytes().is_empty() => {
+                        *element = text(image.alt().to_string());
+                    }
+                    Element::Figure { image, caption } if image.bytes().is_empty() => {
+                        *element = Element::Paragraph {
+                            elements: std::mem::take(caption),
+                        };
+                    }
+                    Element::Header { level, .. } => {
+                        if let Some(previous) = self.previous_level {
+                            *level = (*level).min(previous + 1);
+                        }
+                        self.previous_level = Some(*level);
+                    }
+                    Element::Table { headers, rows, .. } => {
+                        let widths = row_widths(rows.iter().map(|row| &row.cells));
+                        let columns = table_columns(headers.len(), &widths);
+                        if !headers.is_empty() {
+                            headers.resize_with(columns, || TableHeader {
+                                element: text(String::new()),
+                                width: 30.0,
+                            });
+                        }
+                        for (row, width) in rows.iter_mut().zip(widths) {
+                            for _ in width..columns {
+                                row.cells.push(TableCell::new(text(String::new())));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        self.walk_mut(&mut Repair {
+            previous_level: None,
+        });
+    }
+
+    /// Generates the document after checking it, failing with [`GeneratorError::Invalid`] on the
+    /// first issue [`Document::validate`] reports
+    pub fn generate_validated(&self, document_type: DocumentType) -> anyhow::Result<Bytes> {
+        if let Some(issue) = self.validate().into_iter().next() {
+            return Err(GeneratorError::Invalid(issue.to_string()).into());
+        }
+        self.generate(document_type)
+    }
+}
+
+struct Check<'a> {
+    options: &'a ValidationOptions,
+    issues: Vec<Issue>,
+    path: Vec<usize>,
+    previous_level: Option<u8>,
+}
+
+impl Check<'_> {
+    fn elements(&mut self, elements: Vec<&Element>, depth: usize) {
+        for (index, element) in elements.into_iter().enumerate() {
+            self.path.push(index);
+            if depth > self.options.max_depth {
+                // Reported once for the outermost element past the limit
+                self.issue(IssueKind::NestedTooDeep { depth });
+            } else {
+                self.element(element);
+                self.elements(child_elements(element), depth + 1);
+            }
+            self.path.pop();
+        }
+    }
+
+    fn element(&mut self, element: &Element) {
+        match element {
+            Element::Hyperlink { url, .. } if url.trim().is_empty() => {
+                self.issue(IssueKind::EmptyHyperlinkUrl);
+            }
+            Element::Image(image) | Element::Figure { image, .. } if image.bytes().is_empty() => {
+                self.issue(IssueKind::EmptyImage);
+            }
+            Element::Header { level, .. } => {
+                if let Some(previous) = self.previous_level {
+                    if *level > previous + 1 {
+                        self.issue(IssueKind::SkippedHeaderLevel {
+                            previous,
+                            level: *level,
+                        });
+                    }
+                }
+                self.previous_level = Some(*level);
+            }
+            Element::Table { headers, rows, .. } => {
+                let widths = row_widths(rows.iter().map(|row| &row.cells));
+                let expected = table_columns(headers.len(), &widths);
+                for (row, columns) in widths.into_iter().enumerate() {
+                    if columns != expected {
+                        self.issue(IssueKind::RaggedTableRow {
+                            row,
+                            columns,
+                            expected,
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn issue(&mut self, kind: IssueKind) {
+        self.issues.push(Issue {
+            kind,
+            path: self.path.clone(),
+            span: None,
+        });
+    }
+}
+
+/// Columns each row spans, with the columns of cells spanning down from the rows above
+fn row_widths<'a>(rows: impl Iterator<Item = &'a Vec<TableCell>>) -> Vec<usize> {
+    let mut widths: Vec<usize> = Vec::new();
+    // Columns covered in the rows below by cells spanning down
+    let mut covered: Vec<usize> = Vec::new();
+    for (index, cells) in rows.enumerate() {
+        let mut width = covered.get(index).copied().unwrap_or(0);
+        for cell in cells {
+            width += cell.colspan.max(1);
+            let below = index + 1..index + cell.rowspan.max(1);
+            if covered.len() < below.end {
+                covered.resize(below.end, 0);
+            }
+            for row in below {
+                covered[row] += cell.colspan.max(1);
+            }
+        }
+        widths.push(width);
+    }
+    widths
+}
+
+/// The width of a table, that of the header when it has one and of the widest row otherwise
+fn table_columns(headers: usize, widths: &[usize]) -> usize {
+    widths.iter().copied().chain([headers]).max().unwrap_or(0)
+}
+
+fn text(text: String) -> Element {
+    Element::Text {
+        text,
+        font: FontSpec::from(8),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{DocumentBuilder, ImageData, ImageDimension};
+
+    fn invalid_document() -> Document {
+        let image = ImageData::new(
+            Bytes::new(),
+            "Chart".to_string(),
+            "Chart".to_string(),
+            "png".to_string(),
+            "center".to_string(),
+            ImageDimension::default(),
+        );
+        DocumentBuilder::new()
+            .header(1, "Report")
+            .header(3, "Details")
+            .paragraph(|p| p.text("See ").link("the appendix", ""))
+            .image(image)
+            .table(["Name", "Size"], [vec!["a.txt", "1 KB"], vec!["b.txt"]])
+            .build()
+    }
+
+    #[test]
+    fn test_validate() {
+        let document = invalid_document();
+        let issues = document.validate();
+        let kinds: Vec<&IssueKind> = issues.iter().map(|issue| &issue.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &IssueKind::SkippedHeaderLevel {
+                    previous: 1,
+                    level: 3
+                },
+                &IssueKind::EmptyHyperlinkUrl,
+                &IssueKind::EmptyImage,
+                &IssueKind::RaggedTableRow {
+                    row: 1,
+                    columns: 1,
+                    expected: 2
+                },
+            ]
+        );
+        assert_eq!(issues[1].path, vec![0, 2, 1]);
+        assert_eq!(issues[1].to_string(), "hyperlink without a url at 0.2.1");
+
+        let mut nested = Element::Paragraph { elements: vec![] };
+        for _ in 0..3 {
+            nested = Element::Blockquote {
+                elements: vec![nested],
+                attribution: vec![],
+            };
+        }
+        let deep = Document::new(vec![nested]);
+        let issues = deep.validate_with_options(&ValidationOptions { max_depth: 2 });
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, IssueKind::NestedTooDeep { depth: 3 });
+        assert!(deep.validate().is_empty());
+    }
+
+    #[test]
+    fn test_repair() {
+        let mut document = invalid_document();
+        assert!(document.generate_validated(DocumentType::Text).is_err());
+
+        document.repair();
+        assert!(document.validate().is_empty());
+        let detail = document.get_detail();
+        assert!(matches!(detail[1], Element::Header { level: 2, .. }));
+        assert!(matches!(detail[3], Element::Text { text, .. } if text == "Chart"));
+        assert!(document.generate_validated(DocumentType::Text).is_ok());
+    }
+}
@@ -33,7 +33,7 @@ impl TransformerTrait for Transformer {
                                 .map(|header| TableHeader {
                                     element: Text {
                                         text: header.to_string(),
-                                        size: 8,
+                                        font: FontSpec::from(8),
                                     },
                                     width: 10.0,
                                 })
@@ -42,11 +42,11 @@ impl TransformerTrait for Transformer {
                         } else {
                             let cells = row
                                 .iter()
-                                .map(|header| TableCell {
-                                    element: Text {
+                                .map(|header| {
+                                    TableCell::new(Text {
                                         text: header.to_string(),
-                                        size: 8,
-                                    },
+                                        font: FontSpec::from(8),
+                                    })
                                 })
                                 .collect();
                             table_rows.push(TableRow { cells });
@@ -55,6 +55,8 @@ impl TransformerTrait for Transformer {
                     data.push(Table {
                         headers: table_headers.clone(),
                         rows: table_rows.clone(),
+                        alignments: vec![],
+                        caption: vec![],
                     });
                 }
                 Err(err) => {
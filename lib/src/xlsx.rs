@@ -33,7 +33,7 @@ impl TransformerTrait for Transformer {
                                 .map(|header| TableHeader {
                                     element: Text {
                                         text: header.to_string(),
-                                        size: 8,
+                                        font: FontSpec::from(8),
                                     },
                                     width: 10.0,
                                 })
@@ -42,11 +42,11 @@ impl TransformerTrait for Transformer {
                         } else {
                             let cells = row
                                 .iter()
-                                .map(|header| TableCell {
-                                    element: Text {
+                                .map(|header| {
+                                    TableCell::new(Text {
                                         text: header.to_string(),
-                                        size: 8,
-                                    },
+                                        font: FontSpec::from(8),
+                                    })
                                 })
                                 .collect();
                             table_rows.push(TableRow { cells });
@@ -55,6 +55,8 @@ impl TransformerTrait for Transformer {
                     data.push(Table {
                         headers: table_headers.clone(),
                         rows: table_rows.clone(),
+                        alignments: vec![],
+                        caption: vec![],
                     });
                 }
                 Err(err) => {
@@ -62,8 +64,12 @@ impl TransformerTrait for Transformer {
                 }
             }
         }
+        // calamine reads the cells only, the charts follow the sheets
+        data.extend(crate::chart::package_charts(document, "xl/charts/")?);
 
-        Ok(Document::new(data))
+        let mut parsed = Document::new(data);
+        parsed.metadata = crate::properties::read_metadata(document)?;
+        Ok(parsed)
     }
 
     fn generate(document: &Document) -> anyhow::Result<Bytes>
@@ -72,7 +78,7 @@ impl TransformerTrait for Transformer {
     {
         let mut workbook = Workbook::new();
         fn generate_element(element: &Element, workbook: &mut Workbook) -> anyhow::Result<()> {
-            if let Table { headers, rows } = element {
+            if let Table { headers, rows, .. } = element {
                 let worksheet = workbook.add_worksheet();
                 let mut row_index = 1;
                 let mut col_index = 0;
@@ -86,7 +92,7 @@ impl TransformerTrait for Transformer {
                 for row in rows {
                     let mut col_index = 0;
                     for cell in row.cells.iter() {
-                        if let Text { text, .. } = cell.element.clone() {
+                        if let Text { text, .. } = cell.element().clone() {
                             worksheet.write_string(row_index, col_index, text)?;
                             col_index += 1;
                         }
@@ -94,12 +100,59 @@ impl TransformerTrait for Transformer {
                     row_index += 1;
                 }
             }
+            // The data goes in a worksheet of its own, the chart plots it next to the data
+            if let Element::Chart {
+                chart_type,
+                series,
+                categories,
+            } = element
+            {
+                let worksheet = workbook.add_worksheet();
+                let name = worksheet.name();
+                for (row, category) in categories.iter().enumerate() {
+                    worksheet.write_string(row as u32 + 1, 0, category)?;
+                }
+                let mut chart = Chart::new(match chart_type {
+                    crate::core::ChartType::Bar => rust_xlsxwriter::ChartType::Column,
+                    crate::core::ChartType::Line => rust_xlsxwriter::ChartType::Line,
+                    crate::core::ChartType::Area => rust_xlsxwriter::ChartType::Area,
+                    crate::core::ChartType::Pie => rust_xlsxwriter::ChartType::Pie,
+                });
+                for (index, item) in series.iter().enumerate() {
+                    let column = index as u16 + 1;
+                    worksheet.write_string(0, column, &item.name)?;
+                    for (row, value) in item.values.iter().enumerate() {
+                        worksheet.write_number(row as u32 + 1, column, *value)?;
+                    }
+                    let last = categories.len().max(item.values.len()) as u32;
+                    chart
+                        .add_series()
+                        .set_name((name.as_str(), 0, column))
+                        .set_categories((name.as_str(), 1, 0, last, 0))
+                        .set_values((name.as_str(), 1, column, last, column));
+                }
+                worksheet.insert_chart(1, series.len() as u16 + 2, &chart)?;
+            }
             Ok(())
         }
-        for element in &document.get_detail() {
+        for element in &document.flatten_sections().get_all_elements() {
             generate_element(element, &mut workbook)?;
         }
-        let xlsx_data = workbook.save_to_buffer()?;
+        let metadata = &document.metadata;
+        let mut properties = DocProperties::new();
+        for (name, value) in &metadata.custom {
+            properties = properties.set_custom_property(name, value.as_str());
+        }
+        workbook.set_properties(&properties);
+        let mut xlsx_data = workbook.save_to_buffer()?;
+        // The writer takes dates as Excel date times, the core properties are written as they are
+        if !metadata.is_empty() {
+            xlsx_data = crate::properties::replace_part(
+                &xlsx_data,
+                crate::properties::CORE_PATH,
+                &crate::properties::core_xml(metadata),
+            )?;
+        }
         Ok(Bytes::from(xlsx_data))
     }
 }
@@ -150,4 +203,20 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_chart() -> anyhow::Result<()> {
+        let chart = Element::Chart {
+            chart_type: crate::core::ChartType::Pie,
+            series: vec![crate::core::ChartSeries {
+                name: "Share".to_string(),
+                values: vec![60.0, 40.0],
+            }],
+            categories: vec!["Rust".to_string(), "Go".to_string()],
+        };
+        let generated = Transformer::generate(&Document::new(vec![chart.clone()]))?;
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_all_elements().last(), Some(&&chart));
+        Ok(())
+    }
 }
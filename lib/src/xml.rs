@@ -8,8 +8,9 @@ use quick_xml::{
 use std::str::from_utf8;
 
 use crate::core::{
-    Document, Element, ImageAlignment, ImageData, ImageDimension, ImageType, ListItem,
-    PageDimensions, PageFormat, TableCell, TableHeader, TableRow, TransformerTrait,
+    ChartSeries, DefinitionItem, Document, Element, FontSpec, ImageAlignment, ImageData,
+    ImageDimension, ImageType, ListItem, MediaSource, Metadata, PageDimensions, PageFormat, TableCell,
+    TableHeader, TableRow, TextDirection, TextStyle, TransformerTrait,
 };
 
 use serde::{Deserialize, Serialize};
@@ -129,6 +130,329 @@ impl TransformerTrait for Transformer {
                             elements: sub_elements,
                         });
                     }
+                    "Blockquote" => {
+                        let (mut sub_elements, mut attribution) = (Vec::new(), Vec::new());
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "elements" => sub_elements = parse_element(child)?,
+                                "attribution" => attribution = parse_element(child)?,
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Blockquote {
+                            elements: sub_elements,
+                            attribution,
+                        });
+                    }
+                    "Footnote" => {
+                        let mut id = "";
+                        let mut content = Vec::new();
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "id" => id = child.text.as_deref().unwrap_or_default(),
+                                "content" => content = parse_element(child)?,
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Footnote {
+                            id: id.to_string(),
+                            content,
+                        });
+                    }
+                    "Styled" => {
+                        let mut style = TextStyle::default();
+                        let mut sub_elements = Vec::new();
+                        for child in element.children.iter() {
+                            let flag = child.text.as_deref() == Some("true");
+                            match child.name.as_str() {
+                                "bold" => style.bold = flag,
+                                "italic" => style.italic = flag,
+                                "underline" => style.underline = flag,
+                                "strikethrough" => style.strikethrough = flag,
+                                "superscript" => style.superscript = flag,
+                                "subscript" => style.subscript = flag,
+                                "highlight" => {
+                                    style.highlight =
+                                        child.text.as_deref().and_then(|color| color.parse().ok())
+                                }
+                                "color" => {
+                                    style.color =
+                                        child.text.as_deref().and_then(|color| color.parse().ok())
+                                }
+                                "background" => {
+                                    style.background =
+                                        child.text.as_deref().and_then(|color| color.parse().ok())
+                                }
+                                "direction" => {
+                                    style.direction = child
+                                        .text
+                                        .as_deref()
+                                        .and_then(|direction| direction.parse().ok())
+                                }
+                                "elements" => sub_elements = parse_element(child)?,
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Styled {
+                            style,
+                            elements: sub_elements,
+                        });
+                    }
+                    "FootnoteReference" => {
+                        let id = element
+                            .children
+                            .iter()
+                            .find(|child| child.name == "id")
+                            .and_then(|child| child.text.as_deref())
+                            .unwrap_or_default();
+                        elements.push(Element::FootnoteReference { id: id.to_string() });
+                    }
+                    "LineBreak" => {
+                        elements.push(Element::LineBreak);
+                    }
+                    "HorizontalRule" => {
+                        elements.push(Element::HorizontalRule);
+                    }
+                    "PageBreak" => {
+                        elements.push(Element::PageBreak);
+                    }
+                    "TableOfContents" => {
+                        let max_level = element
+                            .children
+                            .iter()
+                            .find(|child| child.name == "max_level")
+                            .and_then(|child| child.text.as_deref())
+                            .unwrap_or_default();
+                        elements.push(Element::TableOfContents {
+                            max_level: max_level.parse()?,
+                        });
+                    }
+                    "DefinitionList" => {
+                        let mut items = Vec::new();
+                        let entries = element
+                            .children
+                            .iter()
+                            .filter(|child| child.name == "items");
+                        for item in entries.flat_map(|child| child.children.iter()) {
+                            let mut term = Vec::new();
+                            let mut definitions = Vec::new();
+                            for child in item.children.iter() {
+                                match child.name.as_str() {
+                                    "term" => term = parse_element(child)?,
+                                    "definitions" => definitions = parse_element(child)?,
+                                    _ => {}
+                                }
+                            }
+                            items.push(DefinitionItem { term, definitions });
+                        }
+                        elements.push(Element::DefinitionList { items });
+                    }
+                    "Figure" => {
+                        let mut image = None;
+                        let mut caption = Vec::new();
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "image" => {
+                                    image = parse_element(child)?.into_iter().find_map(|element| {
+                                        match element {
+                                            Element::Image(image) => Some(image),
+                                            _ => None,
+                                        }
+                                    })
+                                }
+                                "caption" => caption = parse_element(child)?,
+                                _ => {}
+                            }
+                        }
+                        if let Some(image) = image {
+                            elements.push(Element::Figure { image, caption });
+                        }
+                    }
+                    "Media" => {
+                        let (mut kind, mut src, mut poster) = (None, None, None);
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "kind" => {
+                                    kind = child.text.as_deref().map(str::parse).transpose()?
+                                }
+                                "src" => src = child.text.as_deref(),
+                                "poster" => {
+                                    poster = parse_element(child)?.into_iter().find_map(|element| {
+                                        match element {
+                                            Element::Image(image) => Some(image),
+                                            _ => None,
+                                        }
+                                    })
+                                }
+                                _ => {}
+                            }
+                        }
+                        if let (Some(kind), Some(src)) = (kind, src) {
+                            elements.push(Element::Media {
+                                kind,
+                                source: MediaSource::from_src(src),
+                                poster,
+                            });
+                        }
+                    }
+                    "Chart" => {
+                        let (mut chart_type, mut series, mut categories) = (None, vec![], vec![]);
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "chart_type" => {
+                                    chart_type =
+                                        child.text.as_deref().map(str::parse).transpose()?
+                                }
+                                "categories" => {
+                                    categories = child
+                                        .children
+                                        .iter()
+                                        .map(|category| category.text.clone().unwrap_or_default())
+                                        .collect()
+                                }
+                                "series" => {
+                                    for item in child.children.iter() {
+                                        let mut chart_series = ChartSeries {
+                                            name: String::new(),
+                                            values: vec![],
+                                        };
+                                        for field in item.children.iter() {
+                                            match field.name.as_str() {
+                                                "name" => {
+                                                    chart_series.name =
+                                                        field.text.clone().unwrap_or_default()
+                                                }
+                                                "values" => {
+                                                    for value in field.children.iter() {
+                                                        chart_series.values.push(
+                                                            value
+                                                                .text
+                                                                .as_deref()
+                                                                .unwrap_or("")
+                                                                .parse()
+                                                                .unwrap_or(f64::NAN),
+                                                        );
+                                                    }
+                                                }
+                                                _ => {}
+                                            }
+                                        }
+                                        series.push(chart_series);
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                        if let Some(chart_type) = chart_type {
+                            elements.push(Element::Chart {
+                                chart_type,
+                                series,
+                                categories,
+                            });
+                        }
+                    }
+                    "Ruby" => {
+                        let (mut base, mut annotation) = (String::new(), String::new());
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "base" => base = child.text.clone().unwrap_or_default(),
+                                "annotation" => annotation = child.text.clone().unwrap_or_default(),
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Ruby { base, annotation });
+                    }
+                    "Section" => {
+                        let mut level = 1;
+                        let mut children = Vec::new();
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "level" => {
+                                    level = child.text.as_deref().unwrap_or("1").parse()?;
+                                }
+                                "children" => children = parse_element(child)?,
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Section { level, children });
+                    }
+                    "Custom" => {
+                        let mut kind = String::new();
+                        let mut data = serde_json::Value::Null;
+                        let mut children = Vec::new();
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "kind" => kind = child.text.clone().unwrap_or_default(),
+                                "data" => {
+                                    if let Some(text) = &child.text {
+                                        data = serde_json::from_str(text)?;
+                                    }
+                                }
+                                "children" => children = parse_element(child)?,
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Custom {
+                            kind,
+                            data,
+                            children,
+                        });
+                    }
+                    "Comment" => {
+                        let mut text = "";
+                        let (mut author, mut date, mut range) = (None, None, None);
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "text" => {
+                                    if let Some(value) = &child.text {
+                                        text = value;
+                                    } else {
+                                        error!("Error: No value");
+                                    }
+                                }
+                                "author" => author = child.text.clone(),
+                                "date" => date = child.text.clone(),
+                                "range" => range = child.text.clone(),
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Comment {
+                            text: text.to_string(),
+                            author,
+                            date,
+                            range,
+                        });
+                    }
+                    "CodeBlock" => {
+                        let mut language = None;
+                        let mut code = "";
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "language" => language = child.text.clone(),
+                                "code" => code = child.text.as_deref().unwrap_or_default(),
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::CodeBlock {
+                            language,
+                            code: code.to_string(),
+                        });
+                    }
+                    "Math" => {
+                        let mut tex = "";
+                        let mut display = false;
+                        for child in element.children.iter() {
+                            match child.name.as_str() {
+                                "tex" => tex = child.text.as_deref().unwrap_or_default(),
+                                "display" => display = child.text.as_deref() == Some("true"),
+                                _ => {}
+                            }
+                        }
+                        elements.push(Element::Math {
+                            tex: tex.to_string(),
+                            display,
+                        });
+                    }
                     "List" => {
                         let mut numbered = false;
                         let mut sub_elements: Vec<ListItem> = vec![];
@@ -157,16 +481,20 @@ impl TransformerTrait for Transformer {
                     }
                     "Text" => {
                         let mut text = "_";
-                        let mut size = 10;
+                        let mut font = FontSpec::from(10);
                         for child in element.children.iter() {
                             match child.name.as_str() {
                                 "size" => {
                                     if let Some(value) = &child.text {
-                                        size = value.parse()?;
+                                        font.size_pt = value.parse()?;
                                     } else {
                                         error!("Error: No value");
                                     }
                                 }
+                                "family" => font.family = child.text.clone(),
+                                "weight" => {
+                                    font.weight = child.text.as_deref().and_then(|w| w.parse().ok())
+                                }
                                 "text" => {
                                     if let Some(value) = &child.text {
                                         text = value;
@@ -179,7 +507,7 @@ impl TransformerTrait for Transformer {
                         }
                         elements.push(Element::Text {
                             text: text.to_string(),
-                            size: size,
+                            font,
                         });
                     }
                     "Image" => {
@@ -301,8 +629,10 @@ impl TransformerTrait for Transformer {
                     "Header" => {
                         let mut text = "_";
                         let mut level = 0;
+                        let mut anchor = None;
                         for child in element.children.iter() {
                             match child.name.as_str() {
+                                "anchor" => anchor = child.text.clone(),
                                 "level" => {
                                     if let Some(value) = &child.text {
                                         level = value.parse()?;
@@ -323,11 +653,13 @@ impl TransformerTrait for Transformer {
                         elements.push(Element::Header {
                             text: text.to_string(),
                             level: level,
+                            anchor,
                         });
                     }
                     "Table" => {
                         let mut headers: Vec<TableHeader> = vec![];
                         let mut rows: Vec<TableRow> = vec![];
+                        let mut caption = vec![];
                         for table_element in element.children.iter() {
                             match table_element.name.as_str() {
                                 "headers" => {
@@ -336,7 +668,7 @@ impl TransformerTrait for Transformer {
                                             TableHeader {
                                                 element: Element::Text {
                                                     text: "_".to_string(),
-                                                    size: 10,
+                                                    font: FontSpec::from(10),
                                                 },
                                                 width: 8.0,
                                             }
@@ -409,7 +741,7 @@ impl TransformerTrait for Transformer {
                                                     element: {
                                                         Element::Text {
                                                             text: text.to_string(),
-                                                            size: size,
+                                                            font: FontSpec::from(size),
                                                         }
                                                     },
                                                     width: width,
@@ -430,12 +762,10 @@ impl TransformerTrait for Transformer {
                                                 match table_cell.name.as_str() {
                                                     "TableCell" => {
                                                         let mut cell_content: TableCell =
-                                                            TableCell {
-                                                                element: Element::Text {
-                                                                    text: "_".to_string(),
-                                                                    size: 10,
-                                                                },
-                                                            };
+                                                            TableCell::new(Element::Text {
+                                                                text: "_".to_string(),
+                                                                font: FontSpec::from(10),
+                                                            });
                                                         for cell in table_cell.children.iter() {
                                                             for cell_element_sub in
                                                                 cell.children.iter()
@@ -486,15 +816,15 @@ impl TransformerTrait for Transformer {
                                                                                 _ => {}
                                                                             }
                                                                         }
-                                                                        cell_content = TableCell {
-                                                                            element:
+                                                                        cell_content =
+                                                                            TableCell::new(
                                                                                 Element::Text {
                                                                                     text: text
                                                                                         .to_string(
                                                                                         ),
-                                                                                    size: size,
+                                                                                    font: FontSpec::from(size),
                                                                                 },
-                                                                        };
+                                                                            );
                                                                     }
                                                                     _ => {}
                                                                 }
@@ -512,12 +842,15 @@ impl TransformerTrait for Transformer {
                                         rows.push(row_content);
                                     }
                                 }
+                                "caption" => caption = parse_element(table_element)?,
                                 _ => {}
                             }
                         }
                         elements.push(Element::Table {
                             headers: headers,
                             rows: rows,
+                            alignments: vec![],
+                            caption,
                         });
                     }
                     "element" => {
@@ -534,8 +867,13 @@ impl TransformerTrait for Transformer {
             for element in element_data.children.iter() {
                 match element.name.as_str() {
                     "ListItem" => {
+                        let start = elements.len();
+                        let mut checked = None;
                         for child in element.children.iter() {
                             match child.name.as_str() {
+                                "checked" => {
+                                    checked = child.text.as_deref().map(|value| value == "true");
+                                }
                                 "elements" => {
                                     for sub_child in child.children.iter() {
                                         match sub_child.name.as_str() {
@@ -563,10 +901,11 @@ impl TransformerTrait for Transformer {
                                                 }
                                                 let sub_element = Element::Text {
                                                     text: text.to_string(),
-                                                    size: size,
+                                                    font: FontSpec::from(size),
                                                 };
                                                 elements.push(ListItem {
                                                     element: sub_element,
+                                                    checked: None,
                                                 });
                                             }
                                             _ => {}
@@ -596,11 +935,15 @@ impl TransformerTrait for Transformer {
                                             elements: sub_elements,
                                             numbered: numbered,
                                         },
+                                        checked: None,
                                     });
                                 }
                                 _ => {}
                             }
                         }
+                        for item in &mut elements[start..] {
+                            item.checked = checked;
+                        }
                     }
                     _ => {}
                 }
@@ -619,6 +962,8 @@ impl TransformerTrait for Transformer {
         } = PageFormat::default().dimensions();
         let mut page_header: Vec<Element> = vec![];
         let mut page_footer: Vec<Element> = vec![];
+        let mut direction = TextDirection::default();
+        let mut metadata = Metadata::default();
 
         for child in element_data.unwrap().children.iter() {
             match child.name.as_str() {
@@ -652,6 +997,35 @@ impl TransformerTrait for Transformer {
                         page_margin_bottom = value.parse()?;
                     }
                 }
+                "direction" => {
+                    if let Some(value) = &child.text {
+                        direction = value.parse()?;
+                    }
+                }
+                "metadata" => {
+                    for property in &child.children {
+                        let value = property.text.clone().unwrap_or_default();
+                        match property.name.as_str() {
+                            "title" => metadata.title = Some(value),
+                            "author" => metadata.authors.push(value),
+                            "subject" => metadata.subject = Some(value),
+                            "keyword" => metadata.keywords.push(value),
+                            "language" => metadata.language = Some(value),
+                            "created" => metadata.created = Some(value),
+                            "modified" => metadata.modified = Some(value),
+                            "property" => {
+                                let name = property
+                                    .attributes
+                                    .iter()
+                                    .find(|attribute| attribute.key == "name");
+                                if let Some(name) = name {
+                                    metadata.custom.insert(name.value.clone(), value);
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
                 "page_header" => {
                     for child_element in child.children.iter() {
                         let mut text = "_";
@@ -677,7 +1051,7 @@ impl TransformerTrait for Transformer {
                         }
                         page_header.push(Element::Text {
                             text: text.to_string(),
-                            size: size,
+                            font: FontSpec::from(size),
                         });
                     }
                 }
@@ -706,7 +1080,7 @@ impl TransformerTrait for Transformer {
                         }
                         page_footer.push(Element::Text {
                             text: text.to_string(),
-                            size: size,
+                            font: FontSpec::from(size),
                         });
                     }
                 }
@@ -722,8 +1096,10 @@ impl TransformerTrait for Transformer {
             page_margin_right,
         });
 
-        let document =
+        let mut document =
             Document::new_with_dimensions(page_header, elements, page_footer, page_custom_format);
+        document.direction = direction;
+        document.metadata = metadata;
         Ok(document)
     }
 
@@ -738,7 +1114,11 @@ impl TransformerTrait for Transformer {
 
         fn serialize_element(element: &Element, writer: &mut Writer<&mut Vec<u8>>) -> Result<()> {
             match element {
-                Element::Header { level, text } => {
+                Element::Header {
+                    level,
+                    text,
+                    anchor,
+                } => {
                     writer.write_event(Event::Start(BytesStart::new("Header")))?;
                     writer.write_event(Event::Start(BytesStart::new("text")))?;
                     writer.write_event(Event::Text(BytesText::new(text)))?;
@@ -746,6 +1126,11 @@ impl TransformerTrait for Transformer {
                     writer.write_event(Event::Start(BytesStart::new("level")))?;
                     writer.write_event(Event::Text(BytesText::new(&level.to_string())))?;
                     writer.write_event(Event::End(BytesEnd::new("level")))?;
+                    if let Some(anchor) = anchor {
+                        writer.write_event(Event::Start(BytesStart::new("anchor")))?;
+                        writer.write_event(Event::Text(BytesText::new(anchor)))?;
+                        writer.write_event(Event::End(BytesEnd::new("anchor")))?;
+                    }
                     writer.write_event(Event::End(BytesEnd::new("Header")))?;
                 }
                 Element::Paragraph { elements } => {
@@ -757,14 +1142,101 @@ impl TransformerTrait for Transformer {
                     writer.write_event(Event::End(BytesEnd::new("elements")))?;
                     writer.write_event(Event::End(BytesEnd::new("Paragraph")))?;
                 }
-                Element::Text { text, size } => {
+                Element::Blockquote {
+                    elements,
+                    attribution,
+                } => {
+                    writer.write_event(Event::Start(BytesStart::new("Blockquote")))?;
+                    writer.write_event(Event::Start(BytesStart::new("elements")))?;
+                    for sub_element in elements {
+                        serialize_element(sub_element, writer)?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("elements")))?;
+                    if !attribution.is_empty() {
+                        writer.write_event(Event::Start(BytesStart::new("attribution")))?;
+                        for sub_element in attribution {
+                            serialize_element(sub_element, writer)?;
+                        }
+                        writer.write_event(Event::End(BytesEnd::new("attribution")))?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("Blockquote")))?;
+                }
+                Element::Footnote { id, content } => {
+                    writer.write_event(Event::Start(BytesStart::new("Footnote")))?;
+                    writer.write_event(Event::Start(BytesStart::new("id")))?;
+                    writer.write_event(Event::Text(BytesText::new(id)))?;
+                    writer.write_event(Event::End(BytesEnd::new("id")))?;
+                    writer.write_event(Event::Start(BytesStart::new("content")))?;
+                    for sub_element in content {
+                        serialize_element(sub_element, writer)?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("content")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Footnote")))?;
+                }
+                Element::FootnoteReference { id } => {
+                    writer.write_event(Event::Start(BytesStart::new("FootnoteReference")))?;
+                    writer.write_event(Event::Start(BytesStart::new("id")))?;
+                    writer.write_event(Event::Text(BytesText::new(id)))?;
+                    writer.write_event(Event::End(BytesEnd::new("id")))?;
+                    writer.write_event(Event::End(BytesEnd::new("FootnoteReference")))?;
+                }
+                Element::Styled { style, elements } => {
+                    writer.write_event(Event::Start(BytesStart::new("Styled")))?;
+                    for (name, value) in [
+                        ("bold", style.bold),
+                        ("italic", style.italic),
+                        ("underline", style.underline),
+                        ("strikethrough", style.strikethrough),
+                        ("superscript", style.superscript),
+                        ("subscript", style.subscript),
+                    ] {
+                        writer.write_event(Event::Start(BytesStart::new(name)))?;
+                        writer.write_event(Event::Text(BytesText::new(&value.to_string())))?;
+                        writer.write_event(Event::End(BytesEnd::new(name)))?;
+                    }
+                    if let Some(color) = style.highlight {
+                        writer.write_event(Event::Start(BytesStart::new("highlight")))?;
+                        writer.write_event(Event::Text(BytesText::new(&color.to_string())))?;
+                        writer.write_event(Event::End(BytesEnd::new("highlight")))?;
+                    }
+                    for (name, color) in [("color", style.color), ("background", style.background)]
+                    {
+                        if let Some(color) = color {
+                            writer.write_event(Event::Start(BytesStart::new(name)))?;
+                            writer.write_event(Event::Text(BytesText::new(&color.to_string())))?;
+                            writer.write_event(Event::End(BytesEnd::new(name)))?;
+                        }
+                    }
+                    if let Some(direction) = style.direction {
+                        writer.write_event(Event::Start(BytesStart::new("direction")))?;
+                        writer.write_event(Event::Text(BytesText::new(&direction.to_string())))?;
+                        writer.write_event(Event::End(BytesEnd::new("direction")))?;
+                    }
+                    writer.write_event(Event::Start(BytesStart::new("elements")))?;
+                    for sub_element in elements {
+                        serialize_element(sub_element, writer)?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("elements")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Styled")))?;
+                }
+                Element::Text { text, font } => {
                     writer.write_event(Event::Start(BytesStart::new("Text")))?;
                     writer.write_event(Event::Start(BytesStart::new("text")))?;
                     writer.write_event(Event::Text(BytesText::new(text)))?;
                     writer.write_event(Event::End(BytesEnd::new("text")))?;
                     writer.write_event(Event::Start(BytesStart::new("size")))?;
-                    writer.write_event(Event::Text(BytesText::new(&size.to_string())))?;
+                    writer.write_event(Event::Text(BytesText::new(&font.size_pt.to_string())))?;
                     writer.write_event(Event::End(BytesEnd::new("size")))?;
+                    if let Some(family) = &font.family {
+                        writer.write_event(Event::Start(BytesStart::new("family")))?;
+                        writer.write_event(Event::Text(BytesText::new(family)))?;
+                        writer.write_event(Event::End(BytesEnd::new("family")))?;
+                    }
+                    if let Some(weight) = font.weight {
+                        writer.write_event(Event::Start(BytesStart::new("weight")))?;
+                        writer.write_event(Event::Text(BytesText::new(&weight.to_string())))?;
+                        writer.write_event(Event::End(BytesEnd::new("weight")))?;
+                    }
                     writer.write_event(Event::End(BytesEnd::new("Text")))?;
                 }
                 Element::Image(image) => {
@@ -810,6 +1282,198 @@ impl TransformerTrait for Transformer {
                     writer.write_event(Event::End(BytesEnd::new("size")))?;
                     writer.write_event(Event::End(BytesEnd::new("Hyperlink")))?;
                 }
+                Element::LineBreak => {
+                    writer.write_event(Event::Start(BytesStart::new("LineBreak")))?;
+                    writer.write_event(Event::End(BytesEnd::new("LineBreak")))?;
+                }
+                Element::HorizontalRule => {
+                    writer.write_event(Event::Start(BytesStart::new("HorizontalRule")))?;
+                    writer.write_event(Event::End(BytesEnd::new("HorizontalRule")))?;
+                }
+                Element::PageBreak => {
+                    writer.write_event(Event::Start(BytesStart::new("PageBreak")))?;
+                    writer.write_event(Event::End(BytesEnd::new("PageBreak")))?;
+                }
+                Element::TableOfContents { max_level } => {
+                    writer.write_event(Event::Start(BytesStart::new("TableOfContents")))?;
+                    writer.write_event(Event::Start(BytesStart::new("max_level")))?;
+                    writer.write_event(Event::Text(BytesText::new(&max_level.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("max_level")))?;
+                    writer.write_event(Event::End(BytesEnd::new("TableOfContents")))?;
+                }
+                Element::Figure { image, caption } => {
+                    writer.write_event(Event::Start(BytesStart::new("Figure")))?;
+                    writer.write_event(Event::Start(BytesStart::new("image")))?;
+                    serialize_element(&Element::Image(image.clone()), writer)?;
+                    writer.write_event(Event::End(BytesEnd::new("image")))?;
+                    writer.write_event(Event::Start(BytesStart::new("caption")))?;
+                    for sub_element in caption {
+                        serialize_element(sub_element, writer)?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("caption")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Figure")))?;
+                }
+                Element::Media {
+                    kind,
+                    source,
+                    poster,
+                } => {
+                    writer.write_event(Event::Start(BytesStart::new("Media")))?;
+                    writer.write_event(Event::Start(BytesStart::new("kind")))?;
+                    writer.write_event(Event::Text(BytesText::new(&kind.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("kind")))?;
+                    writer.write_event(Event::Start(BytesStart::new("src")))?;
+                    writer.write_event(Event::Text(BytesText::new(&source.to_src())))?;
+                    writer.write_event(Event::End(BytesEnd::new("src")))?;
+                    if let Some(poster) = poster {
+                        writer.write_event(Event::Start(BytesStart::new("poster")))?;
+                        serialize_element(&Element::Image(poster.clone()), writer)?;
+                        writer.write_event(Event::End(BytesEnd::new("poster")))?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("Media")))?;
+                }
+                Element::Chart {
+                    chart_type,
+                    series,
+                    categories,
+                } => {
+                    writer.write_event(Event::Start(BytesStart::new("Chart")))?;
+                    writer.write_event(Event::Start(BytesStart::new("chart_type")))?;
+                    writer.write_event(Event::Text(BytesText::new(&chart_type.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("chart_type")))?;
+                    writer.write_event(Event::Start(BytesStart::new("categories")))?;
+                    for category in categories {
+                        writer.write_event(Event::Start(BytesStart::new("category")))?;
+                        writer.write_event(Event::Text(BytesText::new(category)))?;
+                        writer.write_event(Event::End(BytesEnd::new("category")))?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("categories")))?;
+                    writer.write_event(Event::Start(BytesStart::new("series")))?;
+                    for item in series {
+                        writer.write_event(Event::Start(BytesStart::new("ChartSeries")))?;
+                        writer.write_event(Event::Start(BytesStart::new("name")))?;
+                        writer.write_event(Event::Text(BytesText::new(&item.name)))?;
+                        writer.write_event(Event::End(BytesEnd::new("name")))?;
+                        writer.write_event(Event::Start(BytesStart::new("values")))?;
+                        for value in &item.values {
+                            writer.write_event(Event::Start(BytesStart::new("value")))?;
+                            writer.write_event(Event::Text(BytesText::new(&value.to_string())))?;
+                            writer.write_event(Event::End(BytesEnd::new("value")))?;
+                        }
+                        writer.write_event(Event::End(BytesEnd::new("values")))?;
+                        writer.write_event(Event::End(BytesEnd::new("ChartSeries")))?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("series")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Chart")))?;
+                }
+                Element::Ruby { base, annotation } => {
+                    writer.write_event(Event::Start(BytesStart::new("Ruby")))?;
+                    writer.write_event(Event::Start(BytesStart::new("base")))?;
+                    writer.write_event(Event::Text(BytesText::new(base)))?;
+                    writer.write_event(Event::End(BytesEnd::new("base")))?;
+                    writer.write_event(Event::Start(BytesStart::new("annotation")))?;
+                    writer.write_event(Event::Text(BytesText::new(annotation)))?;
+                    writer.write_event(Event::End(BytesEnd::new("annotation")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Ruby")))?;
+                }
+                Element::Section { level, children } => {
+                    writer.write_event(Event::Start(BytesStart::new("Section")))?;
+                    writer.write_event(Event::Start(BytesStart::new("level")))?;
+                    writer.write_event(Event::Text(BytesText::new(&level.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("level")))?;
+                    writer.write_event(Event::Start(BytesStart::new("children")))?;
+                    for child in children {
+                        serialize_element(child, writer)?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("children")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Section")))?;
+                }
+                // The data is kept as JSON text
+                Element::Custom {
+                    kind,
+                    data,
+                    children,
+                } => {
+                    writer.write_event(Event::Start(BytesStart::new("Custom")))?;
+                    writer.write_event(Event::Start(BytesStart::new("kind")))?;
+                    writer.write_event(Event::Text(BytesText::new(kind)))?;
+                    writer.write_event(Event::End(BytesEnd::new("kind")))?;
+                    writer.write_event(Event::Start(BytesStart::new("data")))?;
+                    writer.write_event(Event::Text(BytesText::new(&data.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("data")))?;
+                    writer.write_event(Event::Start(BytesStart::new("children")))?;
+                    for child in children {
+                        serialize_element(child, writer)?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("children")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Custom")))?;
+                }
+                Element::DefinitionList { items } => {
+                    writer.write_event(Event::Start(BytesStart::new("DefinitionList")))?;
+                    writer.write_event(Event::Start(BytesStart::new("items")))?;
+                    for item in items {
+                        writer.write_event(Event::Start(BytesStart::new("DefinitionItem")))?;
+                        writer.write_event(Event::Start(BytesStart::new("term")))?;
+                        for sub_element in &item.term {
+                            serialize_element(sub_element, writer)?;
+                        }
+                        writer.write_event(Event::End(BytesEnd::new("term")))?;
+                        writer.write_event(Event::Start(BytesStart::new("definitions")))?;
+                        for sub_element in &item.definitions {
+                            serialize_element(sub_element, writer)?;
+                        }
+                        writer.write_event(Event::End(BytesEnd::new("definitions")))?;
+                        writer.write_event(Event::End(BytesEnd::new("DefinitionItem")))?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("items")))?;
+                    writer.write_event(Event::End(BytesEnd::new("DefinitionList")))?;
+                }
+                Element::Comment {
+                    author,
+                    date,
+                    text,
+                    range,
+                } => {
+                    writer.write_event(Event::Start(BytesStart::new("Comment")))?;
+                    for (name, value) in [("author", author), ("date", date)] {
+                        if let Some(value) = value {
+                            writer.write_event(Event::Start(BytesStart::new(name)))?;
+                            writer.write_event(Event::Text(BytesText::new(value)))?;
+                            writer.write_event(Event::End(BytesEnd::new(name)))?;
+                        }
+                    }
+                    writer.write_event(Event::Start(BytesStart::new("text")))?;
+                    writer.write_event(Event::Text(BytesText::new(text)))?;
+                    writer.write_event(Event::End(BytesEnd::new("text")))?;
+                    if let Some(range) = range {
+                        writer.write_event(Event::Start(BytesStart::new("range")))?;
+                        writer.write_event(Event::Text(BytesText::new(range)))?;
+                        writer.write_event(Event::End(BytesEnd::new("range")))?;
+                    }
+                    writer.write_event(Event::End(BytesEnd::new("Comment")))?;
+                }
+                Element::CodeBlock { language, code } => {
+                    writer.write_event(Event::Start(BytesStart::new("CodeBlock")))?;
+                    if let Some(language) = language {
+                        writer.write_event(Event::Start(BytesStart::new("language")))?;
+                        writer.write_event(Event::Text(BytesText::new(language)))?;
+                        writer.write_event(Event::End(BytesEnd::new("language")))?;
+                    }
+                    writer.write_event(Event::Start(BytesStart::new("code")))?;
+                    writer.write_event(Event::Text(BytesText::new(code)))?;
+                    writer.write_event(Event::End(BytesEnd::new("code")))?;
+                    writer.write_event(Event::End(BytesEnd::new("CodeBlock")))?;
+                }
+                Element::Math { tex, display } => {
+                    writer.write_event(Event::Start(BytesStart::new("Math")))?;
+                    writer.write_event(Event::Start(BytesStart::new("tex")))?;
+                    writer.write_event(Event::Text(BytesText::new(tex)))?;
+                    writer.write_event(Event::End(BytesEnd::new("tex")))?;
+                    writer.write_event(Event::Start(BytesStart::new("display")))?;
+                    writer.write_event(Event::Text(BytesText::new(&display.to_string())))?;
+                    writer.write_event(Event::End(BytesEnd::new("display")))?;
+                    writer.write_event(Event::End(BytesEnd::new("Math")))?;
+                }
                 Element::List { elements, numbered } => {
                     writer.write_event(Event::Start(BytesStart::new("List")))?;
                     writer.write_event(Event::Start(BytesStart::new("elements")))?;
@@ -822,20 +1486,26 @@ impl TransformerTrait for Transformer {
                     writer.write_event(Event::End(BytesEnd::new("numbered")))?;
                     writer.write_event(Event::End(BytesEnd::new("List")))?;
                 }
-                Element::Table { headers, rows } => {
+                Element::Table {
+                    headers,
+                    rows,
+                    caption,
+                    ..
+                } => {
                     writer.write_event(Event::Start(BytesStart::new("Table")))?;
                     writer.write_event(Event::Start(BytesStart::new("headers")))?;
                     for header in headers {
                         writer.write_event(Event::Start(BytesStart::new("TableHeader")))?;
                         writer.write_event(Event::Start(BytesStart::new("element")))?;
                         match &header.element {
-                            Element::Text { text, size } => {
+                            Element::Text { text, font } => {
                                 writer.write_event(Event::Start(BytesStart::new("Text")))?;
                                 writer.write_event(Event::Text(BytesText::new(&text)))?;
                                 writer.write_event(Event::End(BytesEnd::new("Text")))?;
                                 writer.write_event(Event::Start(BytesStart::new("Text")))?;
-                                writer
-                                    .write_event(Event::Text(BytesText::new(&size.to_string())))?;
+                                writer.write_event(Event::Text(BytesText::new(
+                                    &font.size_pt.to_string(),
+                                )))?;
                                 writer.write_event(Event::End(BytesEnd::new("Text")))?;
                             }
                             _ => {}
@@ -854,12 +1524,12 @@ impl TransformerTrait for Transformer {
                         writer.write_event(Event::Start(BytesStart::new("cells")))?;
                         for cell in &row.cells {
                             match cell {
-                                TableCell { element } => {
+                                TableCell { elements, .. } => {
                                     writer
                                         .write_event(Event::Start(BytesStart::new("TableCell")))?;
                                     writer.write_event(Event::Start(BytesStart::new("element")))?;
-                                    match &element {
-                                        Element::Text { text, size } => {
+                                    match elements.first() {
+                                        Some(Element::Text { text, font }) => {
                                             writer.write_event(Event::Start(BytesStart::new(
                                                 "Text",
                                             )))?;
@@ -871,7 +1541,7 @@ impl TransformerTrait for Transformer {
                                                 "Text",
                                             )))?;
                                             writer.write_event(Event::Text(BytesText::new(
-                                                &size.to_string(),
+                                                &font.size_pt.to_string(),
                                             )))?;
                                             writer
                                                 .write_event(Event::End(BytesEnd::new("Text")))?;
@@ -887,6 +1557,13 @@ impl TransformerTrait for Transformer {
                         writer.write_event(Event::End(BytesEnd::new("TableRow")))?;
                     }
                     writer.write_event(Event::End(BytesEnd::new("rows")))?;
+                    if !caption.is_empty() {
+                        writer.write_event(Event::Start(BytesStart::new("caption")))?;
+                        for sub_element in caption {
+                            serialize_element(sub_element, writer)?;
+                        }
+                        writer.write_event(Event::End(BytesEnd::new("caption")))?;
+                    }
                     writer.write_event(Event::End(BytesEnd::new("Table")))?;
                 }
             }
@@ -898,11 +1575,16 @@ impl TransformerTrait for Transformer {
             writer: &mut Writer<&mut Vec<u8>>,
         ) -> Result<()> {
             match element {
-                ListItem { element } => {
+                ListItem { element, checked } => {
                     writer.write_event(Event::Start(BytesStart::new("ListItem")))?;
                     writer.write_event(Event::Start(BytesStart::new("element")))?;
                     serialize_element(element, writer)?;
                     writer.write_event(Event::End(BytesEnd::new("element")))?;
+                    if let Some(checked) = checked {
+                        writer.write_event(Event::Start(BytesStart::new("checked")))?;
+                        writer.write_event(Event::Text(BytesText::new(&checked.to_string())))?;
+                        writer.write_event(Event::End(BytesEnd::new("checked")))?;
+                    }
                     writer.write_event(Event::End(BytesEnd::new("ListItem")))?;
                 }
             }
@@ -961,16 +1643,50 @@ impl TransformerTrait for Transformer {
         )))?;
         writer.write_event(Event::End(BytesEnd::new("bottom_page_indent")))?;
 
+        // Written only for right to left documents
+        if document.direction != TextDirection::default() {
+            writer.write_event(Event::Start(BytesStart::new("direction")))?;
+            writer.write_event(Event::Text(BytesText::new(&document.direction.to_string())))?;
+            writer.write_event(Event::End(BytesEnd::new("direction")))?;
+        }
+
+        if !document.metadata.is_empty() {
+            let metadata = &document.metadata;
+            let properties = metadata
+                .title
+                .iter()
+                .map(|title| ("title", title))
+                .chain(metadata.authors.iter().map(|author| ("author", author)))
+                .chain(metadata.subject.iter().map(|subject| ("subject", subject)))
+                .chain(metadata.keywords.iter().map(|keyword| ("keyword", keyword)))
+                .chain(metadata.language.iter().map(|language| ("language", language)))
+                .chain(metadata.created.iter().map(|created| ("created", created)))
+                .chain(metadata.modified.iter().map(|modified| ("modified", modified)));
+            writer.write_event(Event::Start(BytesStart::new("metadata")))?;
+            for (name, value) in properties {
+                writer.write_event(Event::Start(BytesStart::new(name)))?;
+                writer.write_event(Event::Text(BytesText::new(value)))?;
+                writer.write_event(Event::End(BytesEnd::new(name)))?;
+            }
+            for (name, value) in &metadata.custom {
+                let start = BytesStart::new("property").with_attributes([("name", name.as_str())]);
+                writer.write_event(Event::Start(start))?;
+                writer.write_event(Event::Text(BytesText::new(value)))?;
+                writer.write_event(Event::End(BytesEnd::new("property")))?;
+            }
+            writer.write_event(Event::End(BytesEnd::new("metadata")))?;
+        }
+
         writer.write_event(Event::Start(BytesStart::new("page_header")))?;
         for page_header_element in document.get_page_header().iter() {
             match page_header_element {
-                Element::Text { text, size } => {
+                Element::Text { text, font } => {
                     writer.write_event(Event::Start(BytesStart::new("Text")))?;
                     writer.write_event(Event::Start(BytesStart::new("text")))?;
                     writer.write_event(Event::Text(BytesText::new(text)))?;
                     writer.write_event(Event::End(BytesEnd::new("text")))?;
                     writer.write_event(Event::Start(BytesStart::new("size")))?;
-                    writer.write_event(Event::Text(BytesText::new(&size.to_string())))?;
+                    writer.write_event(Event::Text(BytesText::new(&font.size_pt.to_string())))?;
                     writer.write_event(Event::End(BytesEnd::new("size")))?;
                     writer.write_event(Event::End(BytesEnd::new("Text")))?;
                 }
@@ -982,13 +1698,13 @@ impl TransformerTrait for Transformer {
         writer.write_event(Event::Start(BytesStart::new("page_footer")))?;
         for page_footer_element in document.get_page_footer().iter() {
             match page_footer_element {
-                Element::Text { text, size } => {
+                Element::Text { text, font } => {
                     writer.write_event(Event::Start(BytesStart::new("Text")))?;
                     writer.write_event(Event::Start(BytesStart::new("text")))?;
                     writer.write_event(Event::Text(BytesText::new(text)))?;
                     writer.write_event(Event::End(BytesEnd::new("text")))?;
                     writer.write_event(Event::Start(BytesStart::new("size")))?;
-                    writer.write_event(Event::Text(BytesText::new(&size.to_string())))?;
+                    writer.write_event(Event::Text(BytesText::new(&font.size_pt.to_string())))?;
                     writer.write_event(Event::End(BytesEnd::new("size")))?;
                     writer.write_event(Event::End(BytesEnd::new("Text")))?;
                 }
@@ -1047,4 +1763,20 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_custom() -> anyhow::Result<()> {
+        let document = Document::new(vec![Element::Custom {
+            kind: "callout".to_string(),
+            data: serde_json::json!({ "tone": "warning", "icon": "<!>" }),
+            children: vec![Element::Text {
+                text: "Mind the gap".to_string(),
+                font: FontSpec::from(8),
+            }],
+        }]);
+        let generated = Transformer::generate(&document)?;
+        let parsed = Transformer::parse(&generated)?;
+        assert_eq!(parsed.get_all_elements(), document.get_all_elements());
+        Ok(())
+    }
 }
@@ -0,0 +1,90 @@
+use crate::core::*;
+use bytes::Bytes;
+
+pub struct Transformer;
+
+impl TransformerTrait for Transformer {
+    /// Reads a document written by `generate`, or edited by hand.
+    fn parse(document: &Bytes) -> anyhow::Result<Document> {
+        Ok(serde_yaml::from_slice(document)?)
+    }
+
+    /// Serializes the whole document: every band, the page format and the orientation, with the
+    /// schema version and images as base64, see [`crate::schema`].
+    fn generate(document: &Document) -> anyhow::Result<Bytes> {
+        Ok(Bytes::from(serde_yaml::to_string(document)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::yaml::*;
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        let text = |text: &str| Element::Text {
+            text: text.to_string(),
+            font: FontSpec::from(8),
+        };
+        let document = Document::new_with_dimensions(
+            vec![text("Header")],
+            vec![
+                Element::Header {
+                    level: 1,
+                    text: "Title".to_string(),
+                    anchor: None,
+                },
+                Element::List {
+                    elements: vec![ListItem {
+                        element: text("Item"),
+                        checked: None,
+                    }],
+                    numbered: true,
+                },
+                Element::Hyperlink {
+                    title: "Docs".to_string(),
+                    url: "https://example.com".to_string(),
+                    alt: String::new(),
+                    size: 8,
+                },
+            ],
+            vec![text("Footer")],
+            PageFormat::Letter,
+        );
+        let generated = Transformer::generate(&document)?;
+        let yaml = std::str::from_utf8(&generated)?;
+        assert!(yaml.contains("!PageHeader"));
+        assert!(yaml.contains("page_format: Letter"));
+        assert_eq!(Transformer::parse(&generated)?, document);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse() -> anyhow::Result<()> {
+        let yaml = r#"
+bands:
+- !Detail
+  - !Header
+    level: 2
+    text: Edited
+  - !Paragraph
+    elements:
+    - !Text
+      text: by hand
+      size: 8
+page_format: A4
+orientation: Landscape
+"#;
+        let document = Transformer::parse(&Bytes::from(yaml))?;
+        assert_eq!(document.orientation, PageOrientation::Landscape);
+        assert_eq!(
+            document.get_all_elements()[0],
+            &Element::Header {
+                level: 2,
+                text: "Edited".to_string(),
+                anchor: None,
+            }
+        );
+        Ok(())
+    }
+}
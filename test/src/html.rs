@@ -3,8 +3,8 @@ mod tests {
     use bytes::Bytes;
     use shiva::core::Element::{Header, Hyperlink, Image, List, Paragraph, Table, Text};
     use shiva::core::{
-        Document, Element, ImageAlignment, ImageData, ImageDimension, ImageType, ListItem,
-        TableCell, TableHeader, TableRow, TransformerTrait,
+        Document, Element, FontSpec, ImageAlignment, ImageData, ImageDimension, ImageType,
+        ListItem, TableCell, TableHeader, TableRow, TransformerTrait,
     };
     use shiva::html::Transformer;
 
@@ -28,7 +28,7 @@ mod tests {
         assert_eq!(parsed.get_all_elements().len(), 6);
         let elements: Vec<&Element> = parsed.get_all_elements();
         match &elements[0] {
-            Header { level: _, text } => {
+            Header { level: _, text, .. } => {
                 assert_eq!(text, "First header");
             }
             _ => panic!("Expected header"),
@@ -54,26 +54,32 @@ mod tests {
             Header {
                 level: 1,
                 text: "First header".to_string(),
+                anchor: None,
             },
             Header {
                 level: 2,
                 text: "Second header".to_string(),
+                anchor: None,
             },
             Header {
                 level: 3,
                 text: "Third header".to_string(),
+                anchor: None,
             },
             Header {
                 level: 4,
                 text: "Fourth header".to_string(),
+                anchor: None,
             },
             Header {
                 level: 5,
                 text: "Fifth header".to_string(),
+                anchor: None,
             },
             Header {
                 level: 6,
                 text: "Sixth header".to_string(),
+                anchor: None,
             },
         ]
         .to_vec();
@@ -108,7 +114,7 @@ mod tests {
         let elements: Vec<&Element> = parsed.get_all_elements();
         match &elements[0] {
             Paragraph { elements } => match &elements[0] {
-                Text { text, size: _ } => {
+                Text { text, font: _ } => {
                     assert_eq!(text, "First Paragraph");
                 }
                 _ => panic!("Expected Paragraph"),
@@ -135,42 +141,42 @@ mod tests {
             Paragraph {
                 elements: [Text {
                     text: "First Paragraph".to_string(),
-                    size: 8,
+                    font: FontSpec::from(8),
                 }]
                 .to_vec(),
             },
             Paragraph {
                 elements: [Text {
                     text: "Second Paragraph".to_string(),
-                    size: 8,
+                    font: FontSpec::from(8),
                 }]
                 .to_vec(),
             },
             Paragraph {
                 elements: [Text {
                     text: "Third Paragraph".to_string(),
-                    size: 8,
+                    font: FontSpec::from(8),
                 }]
                 .to_vec(),
             },
             Paragraph {
                 elements: [Text {
                     text: "Fourth Paragraph".to_string(),
-                    size: 8,
+                    font: FontSpec::from(8),
                 }]
                 .to_vec(),
             },
             Paragraph {
                 elements: [Text {
                     text: "Fifth Paragraph".to_string(),
-                    size: 8,
+                    font: FontSpec::from(8),
                 }]
                 .to_vec(),
             },
             Paragraph {
                 elements: [Text {
                     text: "Sixth Paragraph".to_string(),
-                    size: 8,
+                    font: FontSpec::from(8),
                 }]
                 .to_vec(),
             },
@@ -213,8 +219,8 @@ mod tests {
                 elements,
                 numbered: _,
             } => match &elements[0] {
-                ListItem { element } => match element {
-                    Text { text, size: _ } => {
+                ListItem { element, .. } => match element {
+                    Text { text, font: _ } => {
                         assert_eq!(text, "List item 1");
                     }
                     _ => panic!("Expected Paragraph"),
@@ -245,10 +251,11 @@ mod tests {
                     ListItem {
                         element: {
                             Text {
-                                size: 8,
+                                font: FontSpec::from(8),
                                 text: "List item 1".to_string(),
                             }
                         },
+                        checked: None,
                     }
                 }],
                 numbered: true,
@@ -258,10 +265,11 @@ mod tests {
                     ListItem {
                         element: {
                             Text {
-                                size: 8,
+                                font: FontSpec::from(8),
                                 text: "List item one".to_string(),
                             }
                         },
+                        checked: None,
                     }
                 }],
                 numbered: false,
@@ -303,10 +311,10 @@ mod tests {
         let parsed: Document = Transformer::parse(&html_document.as_bytes().into())?;
         let elements: Vec<&Element> = parsed.get_all_elements();
         match &elements[0] {
-            Table { headers, rows } => {
+            Table { headers, rows, .. } => {
                 match &headers[0] {
                     TableHeader { element, width: _ } => match element {
-                        Text { text, size: _ } => {
+                        Text { text, font: _ } => {
                             assert_eq!(text, "Syntax");
                         }
                         _ => panic!("Expected Paragraph"),
@@ -314,8 +322,8 @@ mod tests {
                 }
                 match &rows[0] {
                     TableRow { cells } => match &cells[0] {
-                        TableCell { element } => match element {
-                            Text { text, size: _ } => {
+                        TableCell { elements, .. } => match &elements[..] {
+                            [Text { text, font: _ }] => {
                                 assert_eq!(text, "Header");
                             }
                             _ => panic!("Expected Paragraph"),
@@ -355,7 +363,7 @@ mod tests {
                     TableHeader {
                         element: {
                             Text {
-                                size: 8,
+                                font: FontSpec::from(8),
                                 text: "Syntax".to_string(),
                             }
                         },
@@ -366,7 +374,7 @@ mod tests {
                     TableHeader {
                         element: {
                             Text {
-                                size: 8,
+                                font: FontSpec::from(8),
                                 text: "Description".to_string(),
                             }
                         },
@@ -378,52 +386,38 @@ mod tests {
                 TableRow {
                     cells: vec![
                         {
-                            TableCell {
-                                element: {
-                                    Text {
-                                        size: 8,
-                                        text: "Header".to_string(),
-                                    }
-                                },
-                            }
+                            TableCell::new(Text {
+                                font: FontSpec::from(8),
+                                text: "Header".to_string(),
+                            })
                         },
                         {
-                            TableCell {
-                                element: {
-                                    Text {
-                                        size: 8,
-                                        text: "Title".to_string(),
-                                    }
-                                },
-                            }
+                            TableCell::new(Text {
+                                font: FontSpec::from(8),
+                                text: "Title".to_string(),
+                            })
                         },
                     ],
                 },
                 TableRow {
                     cells: vec![
                         {
-                            TableCell {
-                                element: {
-                                    Text {
-                                        size: 8,
-                                        text: "Paragraph".to_string(),
-                                    }
-                                },
-                            }
+                            TableCell::new(Text {
+                                font: FontSpec::from(8),
+                                text: "Paragraph".to_string(),
+                            })
                         },
                         {
-                            TableCell {
-                                element: {
-                                    Text {
-                                        size: 8,
-                                        text: "Text".to_string(),
-                                    }
-                                },
-                            }
+                            TableCell::new(Text {
+                                font: FontSpec::from(8),
+                                text: "Text".to_string(),
+                            })
                         },
                     ],
                 },
             ],
+            alignments: vec![],
+            caption: vec![],
         }]
         .to_vec();
         let html_document: Document = Document::new(elements);
@@ -480,7 +474,7 @@ mod tests {
             elements: vec![
                 {
                     Text {
-                        size: 8,
+                        font: FontSpec::from(8),
                         text: "bla".to_string(),
                     }
                 },
@@ -496,7 +490,7 @@ mod tests {
                 },
                 {
                     Text {
-                        size: 8,
+                        font: FontSpec::from(8),
                         text: "bla bla".to_string(),
                     }
                 },
@@ -563,7 +557,7 @@ mod tests {
                     alt: "http://example.com".to_string(),
                 },
                 Text {
-                    size: 8,
+                    font: FontSpec::from(8),
                     text: "  ".to_string(),
                 },
                 Hyperlink {
@@ -608,7 +602,7 @@ mod tests {
 
         let mut header_elements: Vec<Element> = Vec::new();
         let header: Element = Text {
-            size: 10,
+            font: FontSpec::from(10),
             text: std::string::String::from("This is page header text"),
         };
 
@@ -641,7 +635,7 @@ mod tests {
 
         let mut header_elements: Vec<Element> = Vec::new();
         let header: Element = Text {
-            size: 10,
+            font: FontSpec::from(10),
             text: std::string::String::from("This is page header text"),
         };
 
@@ -673,7 +667,7 @@ mod tests {
 
         let mut footer_elements: Vec<Element> = Vec::new();
         let footer: Element = Text {
-            size: 10,
+            font: FontSpec::from(10),
             text: std::string::String::from("This is page footer text"),
         };
 
@@ -706,7 +700,7 @@ mod tests {
 
         let mut footer_elements: Vec<Element> = Vec::new();
         let footer: Element = Text {
-            size: 10,
+            font: FontSpec::from(10),
             text: std::string::String::from("This is page footer text"),
         };
 